@@ -0,0 +1,445 @@
+//! # B-tree cursors
+//!
+//!  A cursor walks the entries of one complete b-tree, identified by its root
+//! page, in key order. Table b-trees are ordered by rowid and keep their
+//! content in the leaves; index b-trees are ordered by their record keys and
+//! also hold entries on interior pages, between the subtrees of their left and
+//! right neighbours.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+#[cfg(test)]
+mod tests;
+
+use std::{borrow::Cow, cmp::Ordering};
+
+use crate::{
+  pager::{
+    page::btree::{cell::BtreeCell, header::BtreePageType, BtreePage},
+    RetrievedPage, SqlitePager,
+  },
+  result::{SqliteError, SqliteResult},
+};
+
+/// Size of the database file header, which precedes the b-tree page header on
+/// page 1.
+const PAGE_ONE_HEADER_OFFSET: usize = 100;
+
+#[derive(Debug)]
+struct CursorFrame {
+  page_number: u32,
+  retrieved: RetrievedPage,
+  page: BtreePage,
+  /// On leaf pages, the cell the cursor points to. On interior pages, the
+  /// child being visited, where `number_of_cells` means the right-most
+  /// pointer. When an interior frame of an index b-tree is at the top of the
+  /// stack, the cursor points to its cell at this position.
+  index: usize,
+}
+
+impl CursorFrame {
+  fn page_type(&self) -> BtreePageType {
+    *self.page.header().page_type()
+  }
+
+  fn number_of_cells(&self) -> usize {
+    self.page.number_of_cells()
+  }
+
+  fn cell(&self, index: usize, usable_size: usize) -> SqliteResult<BtreeCell<'_>> {
+    self.page.cell(self.retrieved.data(), index, usable_size)
+  }
+
+  /// Page number of the child at position `index`, where `number_of_cells`
+  /// means the right-most pointer.
+  fn child(&self, index: usize, usable_size: usize) -> SqliteResult<u32> {
+    let child = if index < self.number_of_cells() {
+      self.cell(index, usable_size)?.left_child()
+    } else {
+      self.page.header().right_most_pointer()
+    };
+    child.ok_or_else(|| {
+      SqliteError::Custom(format!(
+        "Page [{}] has no child at [{index}]",
+        self.page_number
+      ))
+    })
+  }
+}
+
+/// A cursor over the entries of a single b-tree.
+#[derive(Debug)]
+pub struct BtreeCursor<'a> {
+  pager: &'a SqlitePager,
+  root_page: u32,
+  stack: Vec<CursorFrame>,
+}
+
+impl<'a> BtreeCursor<'a> {
+  pub fn new(pager: &'a SqlitePager, root_page: u32) -> Self {
+    Self {
+      pager,
+      root_page,
+      stack: vec![],
+    }
+  }
+
+  pub fn root_page(&self) -> u32 {
+    self.root_page
+  }
+
+  /// Returns `true` while the cursor points to an entry.
+  pub fn is_valid(&self) -> bool {
+    !self.stack.is_empty()
+  }
+
+  /// Moves to the first entry, returning `false` if the b-tree is empty.
+  pub fn rewind(&mut self) -> SqliteResult<bool> {
+    self.stack.clear();
+    self.descend_leftmost(self.root_page)
+  }
+
+  /// Moves to the next entry, returning `false` past the last one.
+  pub fn advance(&mut self) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    let Some(top) = self.stack.last_mut() else {
+      return Ok(false);
+    };
+    top.index += 1;
+    if top.page_type().is_leaf() {
+      if top.index < top.number_of_cells() {
+        return Ok(true);
+      }
+      self.stack.pop();
+      self.finish_child()
+    } else {
+      // The cursor was on a cell of an interior index page: its right
+      // neighbour subtree comes next.
+      let child = top.child(top.index, usable_size)?;
+      self.descend_leftmost(child)
+    }
+  }
+
+  /// Moves to the last entry, returning `false` if the b-tree is empty.
+  pub fn last(&mut self) -> SqliteResult<bool> {
+    self.stack.clear();
+    self.descend_rightmost(self.root_page)
+  }
+
+  /// Moves to the previous entry, returning `false` before the first one.
+  pub fn retreat(&mut self) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    let Some(top) = self.stack.last_mut() else {
+      return Ok(false);
+    };
+    if top.page_type().is_leaf() {
+      if top.index > 0 {
+        top.index -= 1;
+        return Ok(true);
+      }
+      self.stack.pop();
+      self.finish_child_backward()
+    } else {
+      // The cursor was on a cell of an interior index page: its left child
+      // subtree comes before it.
+      let child = top.child(top.index, usable_size)?;
+      self.descend_rightmost(child)
+    }
+  }
+
+  /// Positions the cursor on the first row whose rowid is greater than or
+  /// equal to `rowid`. Returns `false` if there is no such row.
+  pub fn seek_rowid_ge(&mut self, rowid: i64) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    self.stack.clear();
+    let mut page_number = self.root_page;
+    loop {
+      let mut frame = self.load_frame(page_number)?;
+      if !frame.page_type().is_table() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{page_number}] is not a table b-tree page"
+        )));
+      }
+
+      // Binary search for the first cell whose key is >= rowid.
+      let (mut low, mut high) = (0, frame.number_of_cells());
+      while low < high {
+        let middle = (low + high) / 2;
+        let key = frame.cell(middle, usable_size)?.rowid().unwrap_or_default();
+        if key < rowid {
+          low = middle + 1;
+        } else {
+          high = middle;
+        }
+      }
+      frame.index = low;
+
+      if frame.page_type().is_leaf() {
+        if low >= frame.number_of_cells() {
+          return self.finish_child();
+        }
+        self.stack.push(frame);
+        return Ok(true);
+      }
+      page_number = frame.child(low, usable_size)?;
+      self.stack.push(frame);
+    }
+  }
+
+  /// Positions the cursor on the last row whose rowid is less than or equal
+  /// to `rowid`. Returns `false` if there is no such row.
+  pub fn seek_rowid_le(&mut self, rowid: i64) -> SqliteResult<bool> {
+    match rowid.checked_add(1) {
+      Some(next) if self.seek_rowid_ge(next)? => self.retreat(),
+      _ => self.last(),
+    }
+  }
+
+  /// Positions the cursor on the row with the given `rowid`, returning
+  /// whether it exists.
+  pub fn seek_rowid(&mut self, rowid: i64) -> SqliteResult<bool> {
+    Ok(self.seek_rowid_ge(rowid)? && self.rowid()? == Some(rowid))
+  }
+
+  /// The rowid of the current entry, for table b-trees.
+  pub fn rowid(&self) -> SqliteResult<Option<i64>> {
+    match self.stack.last() {
+      Some(top) => Ok(top.cell(top.index, self.pager.usable_size())?.rowid()),
+      None => Ok(None),
+    }
+  }
+
+  /// Positions the cursor on the first entry of an index b-tree that is
+  /// greater than or equal to a search key. `compare` receives the payload of
+  /// an entry and orders that entry against the key. Returns `false` if every
+  /// entry is smaller than the key.
+  pub fn seek_index_ge<F>(&mut self, mut compare: F) -> SqliteResult<bool>
+  where
+    F: FnMut(&[u8]) -> SqliteResult<Ordering>,
+  {
+    let usable_size = self.pager.usable_size();
+    self.stack.clear();
+    let mut page_number = self.root_page;
+    loop {
+      let mut frame = self.load_frame(page_number)?;
+      if !frame.page_type().is_index() {
+        return Err(SqliteError::Custom(format!(
+          "Page [{page_number}] is not an index b-tree page"
+        )));
+      }
+
+      let (mut low, mut high) = (0, frame.number_of_cells());
+      while low < high {
+        let middle = (low + high) / 2;
+        let cell = frame.cell(middle, usable_size)?;
+        let payload = self.read_payload(&cell)?;
+        if compare(&payload)? == Ordering::Less {
+          low = middle + 1;
+        } else {
+          high = middle;
+        }
+      }
+      frame.index = low;
+
+      if frame.page_type().is_leaf() {
+        if low >= frame.number_of_cells() {
+          // Nothing in this leaf is large enough: the answer is the next
+          // entry of a parent, if any.
+          return self.finish_child();
+        }
+        self.stack.push(frame);
+        return Ok(true);
+      }
+      page_number = frame.child(low, usable_size)?;
+      self.stack.push(frame);
+    }
+  }
+
+  /// Positions the cursor on the last entry of an index b-tree that is less
+  /// than or equal to a search key, with `compare` as for
+  /// [`Self::seek_index_ge`]. Returns `false` if every entry is greater than
+  /// the key.
+  pub fn seek_index_le<F>(&mut self, mut compare: F) -> SqliteResult<bool>
+  where
+    F: FnMut(&[u8]) -> SqliteResult<Ordering>,
+  {
+    // Find the first entry greater than the key, then step back from it.
+    let found = self.seek_index_ge(|payload| {
+      Ok(match compare(payload)? {
+        Ordering::Equal => Ordering::Less,
+        ordering => ordering,
+      })
+    })?;
+    if found {
+      self.retreat()
+    } else {
+      self.last()
+    }
+  }
+
+  /// Positions the cursor on the last entry of an index b-tree that is less
+  /// than a search key, with `compare` as for [`Self::seek_index_ge`].
+  /// Returns `false` if every entry is greater than or equal to the key.
+  pub fn seek_index_lt<F>(&mut self, compare: F) -> SqliteResult<bool>
+  where
+    F: FnMut(&[u8]) -> SqliteResult<Ordering>,
+  {
+    if self.seek_index_ge(compare)? {
+      self.retreat()
+    } else {
+      self.last()
+    }
+  }
+
+  /// Estimates the number of entries of the b-tree from the path to its
+  /// left-most leaf, as if every page on a level held as many cells as the
+  /// one on that path. It is exact for a b-tree of a single page.
+  pub fn estimate_entries(&self) -> SqliteResult<u64> {
+    let usable_size = self.pager.usable_size();
+    let mut estimate: u64 = 1;
+    let mut page_number = self.root_page;
+    for _ in 0..64 {
+      let frame = self.load_frame(page_number)?;
+      let cells = frame.number_of_cells() as u64;
+      if frame.page_type().is_leaf() {
+        return Ok(estimate.saturating_mul(cells));
+      }
+      estimate = estimate.saturating_mul(cells + 1);
+      page_number = frame.child(0, usable_size)?;
+    }
+    Err(SqliteError::Custom("B-tree is too deep".into()))
+  }
+
+  /// The full payload of the current entry, following any overflow pages.
+  pub fn payload(&self) -> SqliteResult<Cow<'_, [u8]>> {
+    let top = self.stack.last().ok_or(SqliteError::Custom(
+      "Cursor is not pointing to an entry".into(),
+    ))?;
+    let cell = top.cell(top.index, self.pager.usable_size())?;
+    self.read_payload(&cell)
+  }
+
+  fn read_payload<'c>(&self, cell: &BtreeCell<'c>) -> SqliteResult<Cow<'c, [u8]>> {
+    let Some(mut overflow_page) = cell.overflow_page() else {
+      return Ok(Cow::Borrowed(cell.local_payload()));
+    };
+
+    let usable_size = self.pager.usable_size();
+    let mut payload = Vec::with_capacity(cell.payload_size());
+    payload.extend_from_slice(cell.local_payload());
+    while payload.len() < cell.payload_size() {
+      if overflow_page == 0 {
+        return Err(SqliteError::Custom("Overflow chain ended early".into()));
+      }
+      let page = self.pager.read_page(overflow_page)?;
+      let data = page.data();
+      let remaining = cell.payload_size() - payload.len();
+      let chunk = remaining.min(usable_size - 4);
+      payload.extend_from_slice(&data[4..4 + chunk]);
+      overflow_page = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    }
+    Ok(Cow::Owned(payload))
+  }
+
+  fn load_frame(&self, page_number: u32) -> SqliteResult<CursorFrame> {
+    let retrieved = self.pager.read_page(page_number)?;
+    let header_offset = if page_number == 1 {
+      PAGE_ONE_HEADER_OFFSET
+    } else {
+      0
+    };
+    let page = BtreePage::parse(retrieved.data(), header_offset)?;
+    Ok(CursorFrame {
+      page_number,
+      retrieved,
+      page,
+      index: 0,
+    })
+  }
+
+  /// Descends from `page_number` to the left-most entry of that subtree.
+  fn descend_leftmost(&mut self, page_number: u32) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    let mut page_number = page_number;
+    loop {
+      if self.stack.len() > 64 {
+        return Err(SqliteError::Custom("B-tree is too deep".into()));
+      }
+      let frame = self.load_frame(page_number)?;
+      if frame.page_type().is_leaf() {
+        let is_empty = frame.number_of_cells() == 0;
+        if is_empty {
+          return self.finish_child();
+        }
+        self.stack.push(frame);
+        return Ok(true);
+      }
+      page_number = frame.child(0, usable_size)?;
+      self.stack.push(frame);
+    }
+  }
+
+  /// Called once the subtree below the top frame has been fully visited.
+  fn finish_child(&mut self) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    while let Some(parent) = self.stack.last_mut() {
+      let number_of_cells = parent.number_of_cells();
+      if parent.page_type().is_index() && parent.index < number_of_cells {
+        // Interior index cells are entries themselves.
+        return Ok(true);
+      }
+      parent.index += 1;
+      if parent.index <= number_of_cells {
+        let child = parent.child(parent.index, usable_size)?;
+        return self.descend_leftmost(child);
+      }
+      self.stack.pop();
+    }
+    Ok(false)
+  }
+
+  /// Descends from `page_number` to the right-most entry of that subtree.
+  fn descend_rightmost(&mut self, page_number: u32) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    let mut page_number = page_number;
+    loop {
+      if self.stack.len() > 64 {
+        return Err(SqliteError::Custom("B-tree is too deep".into()));
+      }
+      let mut frame = self.load_frame(page_number)?;
+      let number_of_cells = frame.number_of_cells();
+      if frame.page_type().is_leaf() {
+        if number_of_cells == 0 {
+          return self.finish_child_backward();
+        }
+        frame.index = number_of_cells - 1;
+        self.stack.push(frame);
+        return Ok(true);
+      }
+      frame.index = number_of_cells;
+      page_number = frame.child(number_of_cells, usable_size)?;
+      self.stack.push(frame);
+    }
+  }
+
+  /// Called once the subtree below the top frame has been fully visited
+  /// backwards.
+  fn finish_child_backward(&mut self) -> SqliteResult<bool> {
+    let usable_size = self.pager.usable_size();
+    while let Some(parent) = self.stack.last_mut() {
+      if parent.index == 0 {
+        self.stack.pop();
+        continue;
+      }
+      parent.index -= 1;
+      if parent.page_type().is_index() {
+        // The cell between the finished child and its left neighbour is an
+        // entry itself.
+        return Ok(true);
+      }
+      let child = parent.child(parent.index, usable_size)?;
+      return self.descend_rightmost(child);
+    }
+    Ok(false)
+  }
+}
@@ -0,0 +1,129 @@
+//! Tests for b-tree cursors
+//!
+//! To run: `cargo test btree`
+
+use super::BtreeCursor;
+use crate::{record::Record, value::Value, SqliteConnection};
+
+/// Root page of the `Observation` table in the flights sample databases.
+const OBSERVATION_ROOT_PAGE: u32 = 2;
+
+#[test]
+fn ok_on_full_table_scan() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let pager = conn.runtime.pager();
+  let mut cursor = BtreeCursor::new(pager, OBSERVATION_ROOT_PAGE);
+
+  let mut count = 0;
+  let mut last_rowid = 0;
+  let mut has_row = cursor.rewind().unwrap();
+  while has_row {
+    let rowid = cursor.rowid().unwrap().unwrap();
+    assert!(rowid > last_rowid);
+    last_rowid = rowid;
+    count += 1;
+    has_row = cursor.advance().unwrap();
+  }
+  assert_eq!(count, 21748);
+  assert_eq!(last_rowid, 21748);
+  assert!(!cursor.is_valid());
+}
+
+#[test]
+fn ok_on_seek_rowid() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let encoding = conn.file_header().database_text_encoding();
+  let mut cursor = BtreeCursor::new(conn.runtime.pager(), OBSERVATION_ROOT_PAGE);
+
+  assert!(cursor.seek_rowid(50).unwrap());
+  let record = Record::decode(&cursor.payload().unwrap(), encoding).unwrap();
+  assert_eq!(
+    record.values(),
+    [Value::Integer(1953), Value::Integer(196), Value::Integer(1)]
+  );
+
+  assert!(cursor.seek_rowid(10000).unwrap());
+  assert!(cursor.advance().unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(10001));
+
+  assert!(!cursor.seek_rowid(0).unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(1));
+  assert!(!cursor.seek_rowid_ge(21749).unwrap());
+}
+
+#[test]
+fn ok_on_single_leaf_table() {
+  let conn = SqliteConnection::open("sqlite://./data/small.sqlite3").unwrap();
+  // Root page of the `stock` table.
+  let mut cursor = BtreeCursor::new(conn.runtime.pager(), 2);
+  assert!(cursor.rewind().unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(1));
+  assert!(!cursor.advance().unwrap());
+  assert!(!cursor.seek_rowid_ge(2).unwrap());
+  assert_eq!(cursor.estimate_entries().unwrap(), 1);
+}
+
+#[test]
+fn ok_on_estimate_entries() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let cursor = BtreeCursor::new(conn.runtime.pager(), OBSERVATION_ROOT_PAGE);
+  // 21748 rows, within a factor of two.
+  let estimate = cursor.estimate_entries().unwrap();
+  assert!((10874..=43496).contains(&estimate), "{estimate}");
+}
+
+#[test]
+fn ok_on_backward_scan() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let mut cursor = BtreeCursor::new(conn.runtime.pager(), OBSERVATION_ROOT_PAGE);
+  let mut expected = 21748;
+  let mut has_row = cursor.last().unwrap();
+  while has_row {
+    assert_eq!(cursor.rowid().unwrap(), Some(expected));
+    expected -= 1;
+    has_row = cursor.retreat().unwrap();
+  }
+  assert_eq!(expected, 0);
+
+  assert!(cursor.seek_rowid_le(10000).unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(10000));
+  assert!(cursor.seek_rowid_le(i64::MAX).unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(21748));
+  assert!(!cursor.seek_rowid_le(0).unwrap());
+}
+
+#[test]
+fn ok_on_backward_index_scan() {
+  let conn = SqliteConnection::open("sqlite://./data/planner.db").unwrap();
+  let encoding = conn.file_header().database_text_encoding();
+  // Root page of the `item_category` index, whose interior pages hold
+  // entries too.
+  let mut cursor = BtreeCursor::new(conn.runtime.pager(), 3);
+  let mut forward = vec![];
+  let mut has_entry = cursor.rewind().unwrap();
+  while has_entry {
+    forward.push(cursor.payload().unwrap().into_owned());
+    has_entry = cursor.advance().unwrap();
+  }
+  let mut backward = vec![];
+  let mut has_entry = cursor.last().unwrap();
+  while has_entry {
+    backward.push(cursor.payload().unwrap().into_owned());
+    has_entry = cursor.retreat().unwrap();
+  }
+  backward.reverse();
+  assert_eq!(forward.len(), 2000);
+  assert_eq!(forward, backward);
+
+  // The last entry of category 'cat1', followed by the first of 'cat10'.
+  let category = |payload: &[u8]| match Record::decode(payload, encoding).unwrap().values() {
+    [Value::Text(category), ..] => category.clone(),
+    values => panic!("{values:?}"),
+  };
+  assert!(cursor
+    .seek_index_le(|payload| Ok(category(payload).as_str().cmp("cat1")))
+    .unwrap());
+  assert_eq!(category(&cursor.payload().unwrap()), "cat1");
+  assert!(cursor.advance().unwrap());
+  assert_eq!(category(&cursor.payload().unwrap()), "cat10");
+}
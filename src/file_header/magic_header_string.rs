@@ -17,6 +17,12 @@ impl Default for MagicHeaderString {
     Self(SQLITE3_FILE_FORMAT_MAGIC_STRING)
   }
 }
+impl MagicHeaderString {
+  pub fn as_bytes(&self) -> &[u8; 16] {
+    &self.0
+  }
+}
+
 impl_name! {MagicHeaderString}
 
 impl Debug for MagicHeaderString {
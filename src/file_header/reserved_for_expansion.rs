@@ -12,6 +12,12 @@ impl Debug for ReservedForExpansion {
   }
 }
 
+impl ReservedForExpansion {
+  pub fn as_bytes(&self) -> &[u8; 20] {
+    &self.0
+  }
+}
+
 impl_name! {ReservedForExpansion}
 
 impl ParseBytes for ReservedForExpansion {
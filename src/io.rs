@@ -2,20 +2,23 @@ use crate::result::{SqliteError, SqliteResult};
 use crate::traits::SqliteRawIo;
 use crate::{error, trace, IN_MEMORY_URI};
 use std::fmt::{Debug, Display};
-use std::fs::{File, Metadata};
+use std::fs::{File, Metadata, OpenOptions};
 use std::io::Seek;
 use std::io::SeekFrom;
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Mutex;
 
 // #[cfg(test)]
 // mod tests;
 
 pub struct SqliteIo {
   mode: SqliteIoMode,
-  raw_io: Box<dyn SqliteRawIo>,
+  raw_io: Mutex<Box<dyn SqliteRawIo>>,
   file_metadata: Option<Metadata>,
+  /// The absolute path of the database file, if it is one.
+  path: Option<PathBuf>,
 }
 
 impl Debug for SqliteIo {
@@ -74,27 +77,31 @@ impl SqliteIo {
         let raw_io = cursor as Box<dyn SqliteRawIo>;
         Ok(Self {
           mode,
-          raw_io,
+          raw_io: Mutex::new(raw_io),
           file_metadata: None,
+          path: None,
         })
       }
 
       SqliteIoMode::File => {
         let uri = conn_str.parse::<SqliteUri>()?;
+        // The file is only read, and opened again for each write.
         let file = File::open(uri.path())?;
         let metadata = file.metadata()?;
         let raw_io = Box::new(file) as Box<dyn SqliteRawIo>;
+        let path = std::fs::canonicalize(uri.path()).unwrap_or_else(|_| uri.path().clone());
         Ok(Self {
           mode,
-          raw_io,
+          raw_io: Mutex::new(raw_io),
           file_metadata: Some(metadata),
+          path: Some(path),
         })
       }
     }
   }
 
   pub fn is_empty(&mut self) -> SqliteResult<bool> {
-    if self.raw_io.read(&mut [0u8; 1])? == 0 {
+    if self.raw_io_mut().read(&mut [0u8; 1])? == 0 {
       Ok(true)
     } else {
       Ok(false)
@@ -102,7 +109,7 @@ impl SqliteIo {
   }
 
   pub fn read(&mut self, buf: &mut [u8]) -> SqliteResult<usize> {
-    let bytes_read = self.raw_io.read(buf)?;
+    let bytes_read = self.raw_io_mut().read(buf)?;
 
     let msg = format!(
       "[{bytes_read}] Bytes read. File: {} at line {}",
@@ -114,15 +121,69 @@ impl SqliteIo {
     Ok(bytes_read)
   }
 
+  /// Reads up to `buf.len()` bytes starting at the absolute offset `pos`.
+  ///
+  ///  Unlike [`SqliteIo::read`] this only needs a shared reference, so several
+  /// b-tree cursors can fetch pages through the same connection at once.
+  /// Returns the number of bytes read, which is only smaller than the buffer
+  /// when the end of the file is reached.
+  pub fn read_at(&self, pos: u64, buf: &mut [u8]) -> SqliteResult<usize> {
+    let mut raw_io = self
+      .raw_io
+      .lock()
+      .map_err(|_| SqliteError::Custom("SqliteIo lock poisoned".into()))?;
+    raw_io.seek(SeekFrom::Start(pos))?;
+    let mut bytes_read = 0;
+    while bytes_read < buf.len() {
+      match raw_io.read(&mut buf[bytes_read..])? {
+        0 => break,
+        n => bytes_read += n,
+      }
+    }
+    trace!("[{bytes_read}] Bytes read at offset [{pos}].");
+
+    Ok(bytes_read)
+  }
+
+  /// Writes all of `buf` starting at the absolute offset `pos`, and flushes
+  /// it to the file. The file is opened for writing only for the time of
+  /// the write: one that cannot be, on a read-only filesystem or without
+  /// permission, is a read-only database.
+  pub fn write_at(&self, pos: u64, buf: &[u8]) -> SqliteResult<()> {
+    match &self.path {
+      Some(path) => {
+        let mut file = OpenOptions::new()
+          .write(true)
+          .open(path)
+          .map_err(|_| SqliteError::Custom("attempt to write a readonly database".into()))?;
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(buf)?;
+        file.sync_data()?;
+      }
+      None => {
+        let mut raw_io = self
+          .raw_io
+          .lock()
+          .map_err(|_| SqliteError::Custom("SqliteIo lock poisoned".into()))?;
+        raw_io.seek(SeekFrom::Start(pos))?;
+        raw_io.write_all(buf)?;
+        raw_io.flush()?;
+      }
+    }
+    trace!("[{}] Bytes written at offset [{pos}].", buf.len());
+
+    Ok(())
+  }
+
   pub fn seek(&mut self, pos: u64) -> SqliteResult<u64> {
-    Ok(self.raw_io.seek(SeekFrom::Start(pos))?)
+    Ok(self.raw_io_mut().seek(SeekFrom::Start(pos))?)
   }
 
   pub fn rewind(&mut self) -> SqliteResult<()> {
-    Ok(self.raw_io.rewind()?)
+    Ok(self.raw_io_mut().rewind()?)
   }
   pub fn stream_position(&mut self) -> SqliteResult<u64> {
-    Ok(self.raw_io.stream_position()?)
+    Ok(self.raw_io_mut().stream_position()?)
   }
 
   pub fn close() -> SqliteResult<()> {
@@ -136,6 +197,17 @@ impl SqliteIo {
   pub fn file_metadata(&self) -> Option<&Metadata> {
     self.file_metadata.as_ref()
   }
+
+  pub fn path(&self) -> Option<&PathBuf> {
+    self.path.as_ref()
+  }
+
+  fn raw_io_mut(&mut self) -> &mut Box<dyn SqliteRawIo> {
+    self
+      .raw_io
+      .get_mut()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
 }
 
 #[derive(Debug)]
@@ -146,9 +218,17 @@ pub struct SqliteUri {
 }
 
 impl SqliteUri {
+  pub fn uri(&self) -> &str {
+    &self.uri
+  }
+
   pub fn path(&self) -> &PathBuf {
     &self.path
   }
+
+  pub fn mode(&self) -> &SqliteUriFileMode {
+    &self.mode
+  }
 }
 impl FromStr for SqliteUri {
   type Err = SqliteError;
@@ -3,6 +3,7 @@
 //! # SQLite arquitecture
 //! *Reference:* https://www.sqlite.org/arch.html
 
+pub mod btree;
 pub mod file_header;
 pub mod io;
 #[cfg(feature = "log")]
@@ -10,19 +11,30 @@ pub(crate) mod log;
 #[macro_use]
 pub(crate) mod log_macros;
 pub mod pager;
+pub mod record;
 pub mod result;
 pub mod runtime;
+pub mod sql;
 pub mod traits;
+pub mod value;
+pub(crate) mod varint;
 #[macro_use]
 pub mod macros;
 
 #[cfg(test)]
 mod tests;
 
+use core::cmp::Ordering;
 use std::{fs::Metadata, sync::OnceLock};
 
 use crate::{
-  file_header::SqliteHeader, io::SqliteIoMode, result::SqliteResult, runtime::SqliteRuntime,
+  file_header::SqliteHeader,
+  io::SqliteIoMode,
+  result::SqliteResult,
+  runtime::{
+    IndexCursor, IndexEntries, InterruptHandle, QueryPlan, QueryResult, SqliteRuntime,
+    SqliteSchema, SqliteSequence, SqliteStat1, Statement, TableCursor,
+  },
 };
 
 static VERSION_NUMBER: OnceLock<u32> = OnceLock::new();
@@ -56,7 +68,99 @@ impl SqliteConnection {
   }
 
   pub fn file_header(&self) -> &SqliteHeader {
-    &self.runtime.file_header()
+    self.runtime.file_header()
+  }
+
+  pub fn schema(&self) -> &SqliteSchema {
+    self.runtime.schema()
+  }
+
+  /// The last assigned sequence of every AUTOINCREMENT table.
+  pub fn sqlite_sequence(&self) -> &SqliteSequence {
+    self.runtime.sqlite_sequence()
+  }
+
+  /// The statistics ANALYZE has gathered about tables and indexes.
+  pub fn sqlite_stat1(&self) -> &SqliteStat1 {
+    self.runtime.sqlite_stat1()
+  }
+
+  /// Reparses the schema if another connection has changed it since it was
+  /// last read. Returns whether it was reloaded.
+  pub fn refresh_schema(&self) -> SqliteResult<bool> {
+    self.runtime.begin_read()
+  }
+
+  /// Opens a cursor over the rows of a table, first picking up any schema
+  /// change made by another connection.
+  pub fn table_cursor(&self, table_name: &str) -> SqliteResult<TableCursor<'_>> {
+    self.runtime.begin_read()?;
+    self.runtime.table_cursor(table_name)
+  }
+
+  /// Opens a cursor over the entries of an index, first picking up any
+  /// schema change made by another connection.
+  pub fn index_cursor(&self, index_name: &str) -> SqliteResult<IndexCursor<'_>> {
+    self.runtime.begin_read()?;
+    self.runtime.index_cursor(index_name)
+  }
+
+  /// Builds the entries of an index from the rows of its table, sorted in
+  /// index order, as CREATE INDEX does, first picking up any schema change
+  /// made by another connection.
+  pub fn index_entries(&self, index_name: &str) -> SqliteResult<IndexEntries> {
+    self.runtime.begin_read()?;
+    self.runtime.index_entries(index_name)
+  }
+
+  /// Runs a query and collects all of its rows, first picking up any schema
+  /// change made by another connection.
+  pub fn query(&self, sql: &str) -> SqliteResult<QueryResult> {
+    self.runtime.begin_read()?;
+    self.runtime.query(sql)
+  }
+
+  /// Parses and compiles a statement once, to run it many times with other
+  /// values bound to its parameters, first picking up any schema change
+  /// made by another connection.
+  pub fn prepare(&self, sql: &str) -> SqliteResult<Statement<'_>> {
+    self.runtime.begin_read()?;
+    self.runtime.prepare(sql)
+  }
+
+  /// Plans a query without running it, first picking up any schema change
+  /// made by another connection. The query may start with `EXPLAIN QUERY
+  /// PLAN`.
+  pub fn query_plan(&self, sql: &str) -> SqliteResult<QueryPlan> {
+    self.runtime.begin_read()?;
+    self.runtime.query_plan(sql)
+  }
+
+  /// A handle that interrupts the running query from any thread, making it
+  /// fail.
+  pub fn interrupt_handle(&self) -> InterruptHandle {
+    self.runtime.interrupt_handle()
+  }
+
+  /// The memory, in bytes, a sort may use before it spills sorted runs to
+  /// temporary files. Sorts serve ORDER BY, DISTINCT and GROUP BY.
+  pub fn sort_memory_budget(&self) -> usize {
+    self.runtime.sort_memory_budget()
+  }
+
+  pub fn set_sort_memory_budget(&mut self, bytes: usize) {
+    self.runtime.set_sort_memory_budget(bytes);
+  }
+
+  /// Registers a collation for `COLLATE name`, columns and indexes to use,
+  /// given how it orders two texts. It replaces any other of the same name,
+  /// ignoring ASCII case, built-in ones included.
+  pub fn create_collation(
+    &mut self,
+    name: &str,
+    compare: impl Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+  ) {
+    self.runtime.create_collation(name, compare);
   }
 
   pub fn io_mode(&self) -> &SqliteIoMode {
@@ -1,4 +1,4 @@
-mod page;
+pub mod page;
 
 #[cfg(test)]
 mod tests;
@@ -8,9 +8,9 @@ use std::fmt::Debug;
 use page::PageKind;
 
 use crate::{
-  file_header::{MagicHeaderString, PageSize},
+  file_header::{FileFormatVersionNumbers, MagicHeaderString, PageSize, ReservedBytesPerPage},
   io::SqliteIo,
-  result::SqliteResult,
+  result::{SqliteError, SqliteResult},
   traits::ParseBytes,
 };
 
@@ -22,7 +22,7 @@ use self::page::Page;
 pub struct SqlitePager {
   io: SqliteIo,
   page_size: PageSize,
-  // reserved_bytes_per_page: ReservedBytesPerPage,
+  reserved_bytes_per_page: ReservedBytesPerPage,
 }
 
 #[derive(Debug)]
@@ -60,7 +60,10 @@ impl<const N: usize> From<Page<N>> for RetrievedPage {
 impl SqlitePager {
   pub fn connect(mut io: SqliteIo) -> SqliteResult<Self> {
     io.rewind()?;
-    const BYTES_TO_READ: usize = MagicHeaderString::LENGTH_BYTES + PageSize::LENGTH_BYTES;
+    const BYTES_TO_READ: usize = MagicHeaderString::LENGTH_BYTES
+      + PageSize::LENGTH_BYTES
+      + FileFormatVersionNumbers::LENGTH_BYTES
+      + ReservedBytesPerPage::LENGTH_BYTES;
     let mut buf = [0u8; BYTES_TO_READ];
     let bytes_read = io.read(&mut buf)?;
 
@@ -78,162 +81,63 @@ impl SqlitePager {
       Self {
         io,
         page_size,
-        // reserved_bytes_per_page: ReservedBytesPerPage::parse_bytes(&[buf[20]])?,
+        reserved_bytes_per_page: ReservedBytesPerPage::parse_bytes(&[buf[20]])?,
       }
     } else {
       Self {
         io,
         page_size: PageSize::default(),
-        // reserved_bytes_per_page: ReservedBytesPerPage::default(),
+        reserved_bytes_per_page: ReservedBytesPerPage::default(),
       }
     };
     Ok(pager)
   }
 
   pub fn get_first_page(&mut self) -> SqliteResult<RetrievedPage> {
-    self.io.rewind()?;
-    // TODO
-    let page_number: u32 = 1;
-    {
-      match self.page_size {
-        PageSize::L512 => {
-          const PAGE_SIZE: usize = 512;
-
-          let mut bytes = [0u8; PAGE_SIZE];
-          // TODO page_number
-          let bytes_read = self.io.read(&mut bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: Box::new(bytes),
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-
-        PageSize::L1024 => {
-          const PAGE_SIZE: usize = 1024;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L2048 => {
-          const PAGE_SIZE: usize = 2048;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L4096 => {
-          const PAGE_SIZE: usize = 4096;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L8192 => {
-          const PAGE_SIZE: usize = 8192;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L16384 => {
-          const PAGE_SIZE: usize = 16384;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L32768 => {
-          const PAGE_SIZE: usize = 32768;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-        PageSize::L65536 => {
-          const PAGE_SIZE: usize = 65536;
-          let mut bytes = Box::new([0u8; PAGE_SIZE]);
-          // TODO page_number
-          let bytes_read = self.io.read(&mut *bytes)?;
-          dbg!(bytes_read);
-
-          return Ok(
-            Page {
-              size: bytes.len(),
-              data: bytes,
-              kind: PageKind::_Todo,
-            }
-            .into(),
-          );
-        }
-      }
+    self.read_page(1)
+  }
+
+  /// Reads the page `page_number` (1-based) from the database file.
+  ///
+  ///  Pages are numbered beginning with 1, so page `N` starts at the byte
+  /// offset `(N - 1) * page_size`.
+  pub fn read_page(&self, page_number: u32) -> SqliteResult<RetrievedPage> {
+    if page_number == 0 {
+      return Err(SqliteError::Custom("Page number `0` is invalid".into()));
     }
+    let size = u32::from(&self.page_size) as usize;
+    let offset = u64::from(page_number - 1) * size as u64;
+    let mut data = vec![0u8; size];
+    let bytes_read = self.io.read_at(offset, &mut data)?;
+    if bytes_read < size {
+      error!("Page [{page_number}] is beyond the end of the database file.");
+      return Err(SqliteError::Custom(format!(
+        "Page [{page_number}] is out of range"
+      )));
+    }
+    trace!("Page [{page_number}] retrieved.");
+
+    Ok(RetrievedPage {
+      size,
+      data,
+      kind: PageKind::_Todo,
+    })
   }
 
   pub fn page_size(&self) -> &PageSize {
     &self.page_size
   }
 
+  pub fn reserved_bytes_per_page(&self) -> &ReservedBytesPerPage {
+    &self.reserved_bytes_per_page
+  }
+
+  /// The "usable size" of a database page is the page size minus the number
+  /// of reserved bytes at the end of each page.
+  pub fn usable_size(&self) -> usize {
+    u32::from(&self.page_size) as usize - usize::from(*self.reserved_bytes_per_page)
+  }
+
   pub fn io(&self) -> &SqliteIo {
     &self.io
   }
@@ -55,11 +55,11 @@
 //!
 //! *Reference:* https://www.sqlite.org/fileformat2.html#pages
 
-mod btree;
-mod freelist;
-mod lock_byte;
-mod payload_overflow;
-mod pointer_map;
+pub mod btree;
+pub mod freelist;
+pub mod lock_byte;
+pub mod payload_overflow;
+pub mod pointer_map;
 
 #[cfg(test)]
 mod tests;
@@ -83,9 +83,9 @@ impl<const N: usize> Page<N> {
       data: Box::new(data),
       kind: PageKind::_Todo,
     })?;
-    dbg!(&page);
-    let btree_page = BtreePage::parse(*page.data)?;
-    dbg!(btree_page);
+    trace!("{page:?}");
+    let btree_page = BtreePage::parse(&page.data[..], 0)?;
+    trace!("{btree_page:?}");
     Ok(page)
   }
 
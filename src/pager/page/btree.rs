@@ -92,15 +92,16 @@
 //! overflow pages.
 //!
 
-pub(super) mod freeblock;
-pub(super) mod header;
+pub mod cell;
+pub mod freeblock;
+pub mod header;
 
 use crate::{
-  impl_name,
-  traits::{Name, ParseBytes},
+  field_parsing_error,
+  result::{SqliteError, SqliteResult},
 };
 
-use self::header::BtreePageHeader;
+use self::{cell::BtreeCell, freeblock::Freeblock, header::BtreePageHeader};
 
 /// ## BtreePage
 ///
@@ -123,25 +124,89 @@ use self::header::BtreePageHeader;
 /// unsigned integer found at an offset of 20 into the database file header. The
 /// size of the reserved region is usually zero.
 #[derive(Debug)]
-pub struct BtreePage<const N: usize> {
-  size: usize,
+pub struct BtreePage {
+  /// Offset of the b-tree page header: 100 on page 1 and 0 elsewhere.
+  header_offset: usize,
   /// The 8 or 12 byte b-tree page header
   header: BtreePageHeader,
-  // /// The cell pointer array
-  // cell_pointer_array: CellPointerArray,
-  // /// Unallocated space
-  // unallocated_space: UnallocatedSpace,
-  // // The cell content area
-  // cell_content_area: CellContentRegion,
-  // // The reserved region.
-  // reserved_region: (),
+  /// The cell pointer array
+  cell_pointer_array: CellPointerArray,
 }
 
-impl<const N: usize> BtreePage<N> {
-  pub fn parse(bytes: [u8; N]) -> crate::result::SqliteResult<Self> {
-    let header = BtreePageHeader::parse(&bytes[0..12])?;
-    dbg!(&header);
-    Ok(Self { size: N, header })
+impl BtreePage {
+  /// Parses the b-tree page stored in `bytes`, whose page header starts at
+  /// `header_offset`.
+  pub fn parse(bytes: &[u8], header_offset: usize) -> SqliteResult<Self> {
+    let header_bytes = bytes
+      .get(header_offset..)
+      .ok_or(field_parsing_error!("BtreePage".into()))?;
+    let header = BtreePageHeader::parse(header_bytes)?;
+    let cell_pointer_array = CellPointerArray::parse(
+      bytes,
+      header_offset + header.size(),
+      header.number_of_cells(),
+    )?;
+
+    Ok(Self {
+      header_offset,
+      header,
+      cell_pointer_array,
+    })
+  }
+
+  pub fn header(&self) -> &BtreePageHeader {
+    &self.header
+  }
+
+  pub fn header_offset(&self) -> usize {
+    self.header_offset
+  }
+
+  pub fn number_of_cells(&self) -> usize {
+    self.cell_pointer_array.0.len()
+  }
+
+  pub fn cell_pointer_array(&self) -> &CellPointerArray {
+    &self.cell_pointer_array
+  }
+
+  /// Decodes the cell at position `index` of the cell pointer array.
+  pub fn cell<'a>(
+    &self,
+    bytes: &'a [u8],
+    index: usize,
+    usable_size: usize,
+  ) -> SqliteResult<BtreeCell<'a>> {
+    let offset = *self
+      .cell_pointer_array
+      .0
+      .get(index)
+      .ok_or_else(|| SqliteError::Custom(format!("Cell index [{index}] is out of range")))?;
+    BtreeCell::parse(
+      *self.header.page_type(),
+      bytes,
+      usize::from(offset),
+      usable_size,
+    )
+  }
+
+  pub fn unallocated_space(&self) -> UnallocatedSpace {
+    UnallocatedSpace {
+      start: self.header_offset + self.header.size() + 2 * self.number_of_cells(),
+      end: self.header.start_of_cell_content_area() as usize,
+    }
+  }
+
+  /// Walks the freeblock chain of this page.
+  pub fn freeblocks(&self, bytes: &[u8]) -> SqliteResult<Vec<Freeblock>> {
+    let mut freeblocks = vec![];
+    let mut next = self.header.first_freeblock();
+    while let Some(offset) = next {
+      let freeblock = Freeblock::parse(bytes, offset)?;
+      next = freeblock.next();
+      freeblocks.push(freeblock);
+    }
+    Ok(freeblocks)
   }
 }
 
@@ -152,7 +217,24 @@ impl<const N: usize> BtreePage<N> {
 /// are arranged in key order with left-most cell (the cell with the smallest
 /// key) first and the right-most cell (the cell with the largest key) last.
 #[derive(Debug)]
-pub struct CellPointerArray;
+pub struct CellPointerArray(Vec<u16>);
+
+impl CellPointerArray {
+  fn parse(bytes: &[u8], start: usize, number_of_cells: u16) -> SqliteResult<Self> {
+    let end = start + 2 * usize::from(number_of_cells);
+    let pointers = bytes
+      .get(start..end)
+      .ok_or(field_parsing_error!("CellPointerArray".into()))?
+      .chunks_exact(2)
+      .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+      .collect();
+    Ok(Self(pointers))
+  }
+
+  pub fn offsets(&self) -> &[u16] {
+    &self.0
+  }
+}
 
 /// ### UnallocatedSpace
 ///  If a page contains no cells (which is only possible for a root page of a
@@ -163,7 +245,28 @@ pub struct CellPointerArray;
 /// 65536. However, that integer is too large to be stored in a 2-byte unsigned
 /// integer, so a value of 0 is used in its place.
 #[derive(Debug)]
-pub struct UnallocatedSpace;
+pub struct UnallocatedSpace {
+  start: usize,
+  end: usize,
+}
+
+impl UnallocatedSpace {
+  pub fn start(&self) -> usize {
+    self.start
+  }
+
+  pub fn end(&self) -> usize {
+    self.end
+  }
+
+  pub fn len(&self) -> usize {
+    self.end.saturating_sub(self.start)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
 
 /// ### CellContentRegion
 ///  Cell content is stored in the cell content region of the b-tree page.
@@ -0,0 +1,183 @@
+//! # B-tree Cell Format
+//!
+//!  The format of a cell depends on which kind of b-tree page the cell
+//! appears on. The following table shows the elements of a cell, in order of
+//! appearance, for the various b-tree page types.
+//!
+//! - Table B-Tree Leaf Cell (header 0x0d):
+//!     - A varint which is the total number of bytes of payload, including any
+//!       overflow
+//!     - A varint which is the integer key, a.k.a. "rowid"
+//!     - The initial portion of the payload that does not spill to overflow
+//!       pages.
+//!     - A 4-byte big-endian integer page number for the first page of the
+//!       overflow page list - omitted if all payload fits on the b-tree page.
+//! - Table B-Tree Interior Cell (header 0x05):
+//!     - A 4-byte big-endian page number which is the left child pointer.
+//!     - A varint which is the integer key
+//! - Index B-Tree Leaf Cell (header 0x0a):
+//!     - A varint which is the total number of bytes of key payload, including
+//!       any overflow
+//!     - The initial portion of the payload that does not spill to overflow
+//!       pages.
+//!     - A 4-byte big-endian integer page number for the first page of the
+//!       overflow page list - omitted if all payload fits on the b-tree page.
+//! - Index B-Tree Interior Cell (header 0x02):
+//!     - A 4-byte big-endian page number which is the left child pointer.
+//!     - A varint which is the total number of bytes of key payload, including
+//!       any overflow
+//!     - The initial portion of the payload that does not spill to overflow
+//!       pages.
+//!     - A 4-byte big-endian integer page number for the first page of the
+//!       overflow page list - omitted if all payload fits on the b-tree page.
+//!
+//!  The amount of payload that spills onto overflow pages also depends on the
+//! page type. For the following computations, let U be the usable size of a
+//! database page, the total page size less the reserved space at the end of
+//! each page. And let P be the payload size. In the following, symbol X
+//! represents the maximum amount of payload that can be stored directly on the
+//! b-tree page without spilling onto an overflow page and symbol M represents
+//! the minimum amount of payload that must be stored on the btree page before
+//! spilling is allowed.
+//!
+//! - Table B-Tree Leaf Cell: Let X be U-35. If the payload size P is less than
+//!   or equal to X then the entire payload is stored on the b-tree leaf page.
+//!   Let M be ((U-12)*32/255)-23 and let K be M+((P-M)%(U-4)). If P is greater
+//!   than X then the number of bytes stored on the table b-tree leaf page is K
+//!   if K is less or equal to X or M otherwise. The number of bytes stored on
+//!   the leaf page is never less than M.
+//! - Table B-Tree Interior Cell: Interior pages of table b-trees have no
+//!   payload and so there is never any payload to spill.
+//! - Index B-Tree Leaf Or Interior Cell: Let X be ((U-12)*64/255)-23. If the
+//!   payload size P is less than or equal to X then the entire payload is
+//!   stored on the b-tree page. Let M be ((U-12)*32/255)-23 and let K be
+//!   M+((P-M)%(U-4)). If P is greater than X then the number of bytes stored on
+//!   the index b-tree page is K if K is less than or equal to X or M otherwise.
+//!   The number of bytes stored on the index page is never less than M.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#b_tree_pages
+
+use super::header::BtreePageType;
+use crate::{field_parsing_error, result::SqliteResult, varint::read_varint};
+
+#[derive(Debug)]
+pub struct BtreeCell<'a> {
+  left_child: Option<u32>,
+  rowid: Option<i64>,
+  payload_size: usize,
+  local_payload: &'a [u8],
+  overflow_page: Option<u32>,
+}
+
+impl<'a> BtreeCell<'a> {
+  pub fn parse(
+    page_type: BtreePageType,
+    bytes: &'a [u8],
+    offset: usize,
+    usable_size: usize,
+  ) -> SqliteResult<Self> {
+    let err = || field_parsing_error!("BtreeCell".into());
+    let mut cursor = offset;
+
+    let left_child = if page_type.is_interior() {
+      let pointer = bytes.get(cursor..cursor + 4).ok_or_else(err)?;
+      cursor += 4;
+      Some(u32::from_be_bytes([
+        pointer[0], pointer[1], pointer[2], pointer[3],
+      ]))
+    } else {
+      None
+    };
+
+    if page_type == BtreePageType::InteriorTable {
+      let (rowid, _) = read_varint(bytes.get(cursor..).ok_or_else(err)?)?;
+      return Ok(Self {
+        left_child,
+        rowid: Some(rowid),
+        payload_size: 0,
+        local_payload: &[],
+        overflow_page: None,
+      });
+    }
+
+    let (payload_size, len) = read_varint(bytes.get(cursor..).ok_or_else(err)?)?;
+    cursor += len;
+    let payload_size = usize::try_from(payload_size).map_err(|_| err())?;
+
+    let rowid = if page_type == BtreePageType::LeafTable {
+      let (rowid, len) = read_varint(bytes.get(cursor..).ok_or_else(err)?)?;
+      cursor += len;
+      Some(rowid)
+    } else {
+      None
+    };
+
+    let local_size = Self::local_payload_size(page_type, payload_size, usable_size);
+    let local_payload = bytes.get(cursor..cursor + local_size).ok_or_else(err)?;
+    cursor += local_size;
+
+    let overflow_page = if local_size < payload_size {
+      let pointer = bytes.get(cursor..cursor + 4).ok_or_else(err)?;
+      Some(u32::from_be_bytes([
+        pointer[0], pointer[1], pointer[2], pointer[3],
+      ]))
+    } else {
+      None
+    };
+
+    Ok(Self {
+      left_child,
+      rowid,
+      payload_size,
+      local_payload,
+      overflow_page,
+    })
+  }
+
+  /// Number of payload bytes stored on the b-tree page itself.
+  pub fn local_payload_size(
+    page_type: BtreePageType,
+    payload_size: usize,
+    usable_size: usize,
+  ) -> usize {
+    let max_local = if page_type.is_table() {
+      usable_size - 35
+    } else {
+      ((usable_size - 12) * 64 / 255) - 23
+    };
+    if payload_size <= max_local {
+      return payload_size;
+    }
+    let min_local = ((usable_size - 12) * 32 / 255) - 23;
+    let k = min_local + ((payload_size - min_local) % (usable_size - 4));
+    if k <= max_local {
+      k
+    } else {
+      min_local
+    }
+  }
+
+  /// The left child pointer, present on interior pages only.
+  pub fn left_child(&self) -> Option<u32> {
+    self.left_child
+  }
+
+  /// The integer key, present on table b-tree pages only.
+  pub fn rowid(&self) -> Option<i64> {
+    self.rowid
+  }
+
+  /// Total number of bytes of payload, including any overflow.
+  pub fn payload_size(&self) -> usize {
+    self.payload_size
+  }
+
+  pub fn local_payload(&self) -> &'a [u8] {
+    self.local_payload
+  }
+
+  /// First page of the overflow page list, if the payload spilled.
+  pub fn overflow_page(&self) -> Option<u32> {
+    self.overflow_page
+  }
+}
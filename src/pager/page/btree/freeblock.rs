@@ -24,5 +24,41 @@
 //! contained in the unallocated space region, and all cells are packed tightly
 //! at the end of the page. This is called "defragmenting" the b-tree page.
 
+use crate::{field_parsing_error, result::SqliteResult};
+
 #[derive(Debug)]
-pub struct Freeblock;
+pub struct Freeblock {
+  offset: u16,
+  next: Option<u16>,
+  size: u16,
+}
+
+impl Freeblock {
+  pub fn parse(bytes: &[u8], offset: u16) -> SqliteResult<Self> {
+    let start = usize::from(offset);
+    let header = bytes
+      .get(start..start + 4)
+      .ok_or(field_parsing_error!("Freeblock".into()))?;
+    let next = match u16::from_be_bytes([header[0], header[1]]) {
+      0 => None,
+      next if next <= offset => return Err(field_parsing_error!("Freeblock".into())),
+      next => Some(next),
+    };
+    let size = u16::from_be_bytes([header[2], header[3]]);
+    Ok(Self { offset, next, size })
+  }
+
+  pub fn offset(&self) -> u16 {
+    self.offset
+  }
+
+  /// Offset of the next freeblock in the chain, if any.
+  pub fn next(&self) -> Option<u16> {
+    self.next
+  }
+
+  /// Size of the freeblock in bytes, including its 4-byte header.
+  pub fn size(&self) -> u16 {
+    self.size
+  }
+}
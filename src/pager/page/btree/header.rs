@@ -34,22 +34,79 @@ use crate::{
 #[derive(Debug)]
 pub struct BtreePageHeader {
   page_type: BtreePageType,
-  // first_freeblock: Option<FirstFreeBlock>,
-  // number_of_cells: NumberOfCells,
-  // start_of_cell_content_area: StartOfContentArea,
-  // number_of_fragmented: NumberOfFragmented,
-  // TODO: ParseBytes was implemented for handling fixed length data structures  // right_most_pointer: Option<RightMostPointer>,
+  first_freeblock: Option<FirstFreeBlock>,
+  number_of_cells: NumberOfCells,
+  start_of_cell_content_area: StartOfContentArea,
+  number_of_fragmented: NumberOfFragmented,
+  right_most_pointer: Option<RightMostPointer>,
 }
 
 impl BtreePageHeader {
   pub fn parse(bytes: &[u8]) -> SqliteResult<Self> {
-    dbg!(bytes);
-    let page_type = BtreePageType::parse_bytes(&bytes[0..=0])?;
-    dbg!(&page_type);
-    Ok(Self { page_type })
+    let page_type = BtreePageType::parse_bytes(bytes)?;
+    let header_len = page_type.header_len();
+    if bytes.len() < header_len {
+      return Err(field_parsing_error!(Self::NAME.into()));
+    }
+    let first_freeblock =
+      NonZeroU16::new(u16::from_be_bytes([bytes[1], bytes[2]])).map(FirstFreeBlock);
+    let number_of_cells = NumberOfCells(u16::from_be_bytes([bytes[3], bytes[4]]));
+    let start_of_cell_content_area = match u16::from_be_bytes([bytes[5], bytes[6]]) {
+      0 => StartOfContentArea(65536),
+      offset => StartOfContentArea(offset.into()),
+    };
+    let number_of_fragmented = NumberOfFragmented(bytes[7]);
+    let right_most_pointer = if page_type.is_interior() {
+      Some(RightMostPointer(u32::from_be_bytes([
+        bytes[8], bytes[9], bytes[10], bytes[11],
+      ])))
+    } else {
+      None
+    };
+
+    Ok(Self {
+      page_type,
+      first_freeblock,
+      number_of_cells,
+      start_of_cell_content_area,
+      number_of_fragmented,
+      right_most_pointer,
+    })
+  }
+
+  pub fn page_type(&self) -> &BtreePageType {
+    &self.page_type
+  }
+
+  pub fn first_freeblock(&self) -> Option<u16> {
+    self.first_freeblock.as_ref().map(|block| block.0.get())
+  }
+
+  pub fn number_of_cells(&self) -> u16 {
+    self.number_of_cells.0
+  }
+
+  pub fn start_of_cell_content_area(&self) -> u32 {
+    self.start_of_cell_content_area.0
+  }
+
+  pub fn number_of_fragmented(&self) -> u8 {
+    self.number_of_fragmented.0
+  }
+
+  pub fn right_most_pointer(&self) -> Option<u32> {
+    self.right_most_pointer.as_ref().map(|pointer| pointer.0)
+  }
+
+  /// The size in bytes of this header: 12 bytes for interior pages and 8 for
+  /// leaf pages.
+  pub fn size(&self) -> usize {
+    self.page_type.header_len()
   }
 }
 
+impl_name!(BtreePageHeader);
+
 /// ### BtreePageType (1 Byte)
 ///
 /// The one-byte flag at offset 0 indicating the b-tree page type.
@@ -60,7 +117,7 @@ impl BtreePageHeader {
 /// - A value of 13 (0x0d) means the page is a **leaf table** b-tree page.
 ///
 /// Any other value for the b-tree page type is an error.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BtreePageType {
   InteriorIndex,
   InteriorTable,
@@ -68,13 +125,39 @@ pub enum BtreePageType {
   LeafTable,
 }
 
+impl BtreePageType {
+  pub fn is_interior(&self) -> bool {
+    matches!(self, Self::InteriorIndex | Self::InteriorTable)
+  }
+
+  pub fn is_leaf(&self) -> bool {
+    !self.is_interior()
+  }
+
+  pub fn is_table(&self) -> bool {
+    matches!(self, Self::InteriorTable | Self::LeafTable)
+  }
+
+  pub fn is_index(&self) -> bool {
+    !self.is_table()
+  }
+
+  fn header_len(&self) -> usize {
+    if self.is_interior() {
+      12
+    } else {
+      8
+    }
+  }
+}
+
 impl_name!(BtreePageType);
 
 impl ParseBytes for BtreePageType {
   const LENGTH_BYTES: usize = 1;
 
   fn parsing_handler(bytes: &[u8]) -> SqliteResult<Self> {
-    let maybe_byte = bytes.get(0);
+    let maybe_byte = bytes.first();
     let outcome = maybe_byte
       .and_then(|byte| match *byte {
         2 => Some(Self::InteriorIndex),
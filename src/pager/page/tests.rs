@@ -2,10 +2,8 @@
 //!
 //! To run: `cargo test pager::page`
 
-use std::any::Any;
-
 use super::Page;
-use crate::{file_header::PageSize, pager::ValidPage};
+use crate::file_header::PageSize;
 
 // #[test]
 // fn ok_on_parse_valid_pagesize() {
@@ -34,7 +32,7 @@ fn ok_on_parse_btree_page() {
   assert_eq!(PageSize::L512, BUF_SIZE);
 
   let buf: [u8; BUF_SIZE] = [0; BUF_SIZE];
-  let page = Page::<BUF_SIZE>::parse(&buf).unwrap();
+  let _page = Page::<BUF_SIZE>::parse(&buf).unwrap();
 
   // let page_any = Box::new(page) as Box<dyn Any>;
   // let maybe_valid_pagesize =
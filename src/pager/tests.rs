@@ -5,7 +5,6 @@
 #[test]
 
 fn ok_on_check_page_size() {
-  use crate::runtime::SqliteRuntime;
   use crate::SqliteConnection;
   let res = SqliteConnection::open("sqlite://./data/flights-initial.db");
 
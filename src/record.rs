@@ -0,0 +1,232 @@
+//! # Record Format
+//!
+//!  The data for a table b-tree leaf page and the key of an index b-tree page
+//! was characterized above as an arbitrary sequence of bytes. The prior
+//! discussion mentioned one key being less than another, but did not define
+//! what "less than" meant. The current section will address these omissions.
+//!
+//!  Payload, either table b-tree data or index b-tree keys, is always in the
+//! "record format". The record format defines a sequence of values
+//! corresponding to columns in a table or index. The record format specifies
+//! the number of columns, the datatype of each column, and the content of each
+//! column.
+//!
+//!  A record contains a header and a body, in that order. The header begins
+//! with a single varint which determines the total number of bytes in the
+//! header. The varint value is the size of the header in bytes including the
+//! size varint itself. Following the size varint are one or more additional
+//! varints, one per column. These additional varints are called "serial type"
+//! numbers and determine the datatype of each column, according to the
+//! following chart:
+//!
+//! | Serial Type  | Content Size  | Meaning |
+//! |--------------|---------------|---------|
+//! | 0            | 0             | Value is a NULL. |
+//! | 1            | 1             | Value is an 8-bit twos-complement integer. |
+//! | 2            | 2             | Value is a big-endian 16-bit twos-complement integer. |
+//! | 3            | 3             | Value is a big-endian 24-bit twos-complement integer. |
+//! | 4            | 4             | Value is a big-endian 32-bit twos-complement integer. |
+//! | 5            | 6             | Value is a big-endian 48-bit twos-complement integer. |
+//! | 6            | 8             | Value is a big-endian 64-bit twos-complement integer. |
+//! | 7            | 8             | Value is a big-endian IEEE 754-2008 64-bit floating point number. |
+//! | 8            | 0             | Value is the integer 0. (Only available for schema format 4 and higher.) |
+//! | 9            | 0             | Value is the integer 1. (Only available for schema format 4 and higher.) |
+//! | 10,11        | variable      | Reserved for internal use. |
+//! | N≥12 & even  | (N-12)/2      | Value is a BLOB that is (N-12)/2 bytes in length. |
+//! | N≥13 & odd   | (N-13)/2      | Value is a string in the text encoding and (N-13)/2 bytes in length. |
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#record_format
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  result::{SqliteError, SqliteResult},
+  value::Value,
+  varint::{read_varint, write_varint},
+};
+
+/// A decoded record: the column values of a table row or an index key.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Record(Vec<Value>);
+
+impl Record {
+  pub fn new(values: Vec<Value>) -> Self {
+    Self(values)
+  }
+
+  /// Decodes a record payload, reading text in the database `encoding`.
+  pub fn decode(payload: &[u8], encoding: &DatabaseTextEncoding) -> SqliteResult<Self> {
+    let err = || SqliteError::Custom("Malformed record".into());
+
+    let (header_size, mut header_cursor) = read_varint(payload)?;
+    let header_size = usize::try_from(header_size).map_err(|_| err())?;
+    if header_size > payload.len() || header_size < header_cursor {
+      return Err(err());
+    }
+
+    let mut body_cursor = header_size;
+    let mut values = vec![];
+    while header_cursor < header_size {
+      let (serial_type, len) = read_varint(&payload[header_cursor..header_size])?;
+      header_cursor += len;
+      let content_size = serial_type_size(serial_type).ok_or_else(err)?;
+      let content = payload
+        .get(body_cursor..body_cursor + content_size)
+        .ok_or_else(err)?;
+      body_cursor += content_size;
+      values.push(decode_value(serial_type, content, encoding)?);
+    }
+
+    Ok(Self(values))
+  }
+
+  /// Encodes the record, writing text in the database `encoding`.
+  pub fn encode(&self, encoding: &DatabaseTextEncoding) -> Vec<u8> {
+    let mut serial_types = vec![];
+    let mut body = vec![];
+    for value in &self.0 {
+      let (serial_type, content) = encode_value(value, encoding);
+      serial_types.extend(write_varint(serial_type));
+      body.extend(content);
+    }
+
+    // The header size includes the size varint itself, whose length depends
+    // on the header size.
+    let mut header_size = serial_types.len() + 1;
+    while write_varint(header_size as i64).len() + serial_types.len() != header_size {
+      header_size += 1;
+    }
+
+    let mut payload = write_varint(header_size as i64);
+    payload.extend(serial_types);
+    payload.extend(body);
+    payload
+  }
+
+  pub fn values(&self) -> &[Value] {
+    &self.0
+  }
+
+  pub fn into_values(self) -> Vec<Value> {
+    self.0
+  }
+
+  pub fn get(&self, index: usize) -> Option<&Value> {
+    self.0.get(index)
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+/// The size in bytes of the content of a given serial type.
+fn serial_type_size(serial_type: i64) -> Option<usize> {
+  let size = match serial_type {
+    0 | 8 | 9 => 0,
+    1 => 1,
+    2 => 2,
+    3 => 3,
+    4 => 4,
+    5 => 6,
+    6 | 7 => 8,
+    10 | 11 => return None,
+    n if n >= 12 => ((n - 12) / 2) as usize,
+    _ => return None,
+  };
+  Some(size)
+}
+
+fn decode_value(
+  serial_type: i64,
+  content: &[u8],
+  encoding: &DatabaseTextEncoding,
+) -> SqliteResult<Value> {
+  let value = match serial_type {
+    0 => Value::Null,
+    1..=6 => {
+      // Sign-extend the big-endian twos-complement integer.
+      let fill = if content[0] & 0x80 != 0 { 0xff } else { 0x00 };
+      let mut buf = [fill; 8];
+      buf[8 - content.len()..].copy_from_slice(content);
+      Value::Integer(i64::from_be_bytes(buf))
+    }
+    7 => {
+      let buf: [u8; 8] = content.try_into()?;
+      Value::Real(f64::from_be_bytes(buf))
+    }
+    8 => Value::Integer(0),
+    9 => Value::Integer(1),
+    n if n >= 12 && n % 2 == 0 => Value::Blob(content.to_vec()),
+    n if n >= 13 => Value::Text(decode_text(content, encoding)),
+    _ => return Err(SqliteError::Custom("Invalid serial type".into())),
+  };
+  Ok(value)
+}
+
+fn encode_value(value: &Value, encoding: &DatabaseTextEncoding) -> (i64, Vec<u8>) {
+  match value {
+    Value::Null => (0, vec![]),
+    Value::Integer(0) => (8, vec![]),
+    Value::Integer(1) => (9, vec![]),
+    Value::Integer(int) => {
+      let int = *int;
+      let (serial_type, size) = if (-128..=127).contains(&int) {
+        (1, 1)
+      } else if (-32768..=32767).contains(&int) {
+        (2, 2)
+      } else if (-8388608..=8388607).contains(&int) {
+        (3, 3)
+      } else if (-2147483648..=2147483647).contains(&int) {
+        (4, 4)
+      } else if (-140737488355328..=140737488355327).contains(&int) {
+        (5, 6)
+      } else {
+        (6, 8)
+      };
+      (serial_type, int.to_be_bytes()[8 - size..].to_vec())
+    }
+    Value::Real(real) => (7, real.to_be_bytes().to_vec()),
+    Value::Text(text) => {
+      let content = encode_text(text, encoding);
+      (content.len() as i64 * 2 + 13, content)
+    }
+    Value::Blob(blob) => (blob.len() as i64 * 2 + 12, blob.clone()),
+  }
+}
+
+/// Decodes text stored in the database `encoding`.
+pub(crate) fn decode_text(content: &[u8], encoding: &DatabaseTextEncoding) -> String {
+  match encoding {
+    DatabaseTextEncoding::Utf8 => String::from_utf8_lossy(content).into_owned(),
+    DatabaseTextEncoding::Utf16Le => {
+      let units: Vec<u16> = content
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+      String::from_utf16_lossy(&units)
+    }
+    DatabaseTextEncoding::Utf16Be => {
+      let units: Vec<u16> = content
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+      String::from_utf16_lossy(&units)
+    }
+  }
+}
+
+/// Encodes text in the database `encoding`.
+pub(crate) fn encode_text(text: &str, encoding: &DatabaseTextEncoding) -> Vec<u8> {
+  match encoding {
+    DatabaseTextEncoding::Utf8 => text.as_bytes().to_vec(),
+    DatabaseTextEncoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+    DatabaseTextEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+  }
+}
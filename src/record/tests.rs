@@ -0,0 +1,48 @@
+//! Tests for the record format
+//!
+//! To run: `cargo test record`
+
+use super::Record;
+use crate::{file_header::DatabaseTextEncoding, value::Value};
+
+#[test]
+fn ok_on_record_roundtrip() {
+  let record = Record::new(vec![
+    Value::Null,
+    Value::Integer(0),
+    Value::Integer(1),
+    Value::Integer(-2),
+    Value::Integer(300),
+    Value::Integer(-8_388_608),
+    Value::Integer(2_147_483_648),
+    Value::Integer(i64::MIN),
+    Value::Real(3.25),
+    Value::Text("héllo".into()),
+    Value::Blob(vec![0xde, 0xad, 0xbe, 0xef]),
+  ]);
+  for encoding in [
+    DatabaseTextEncoding::Utf8,
+    DatabaseTextEncoding::Utf16Le,
+    DatabaseTextEncoding::Utf16Be,
+  ] {
+    let payload = record.encode(&encoding);
+    assert_eq!(Record::decode(&payload, &encoding).unwrap(), record);
+  }
+}
+
+#[test]
+fn ok_on_decode_known_payload() {
+  // CREATE TABLE t(a, b, c); INSERT INTO t VALUES (NULL, 7, 'ab');
+  let payload = [0x04, 0x00, 0x01, 0x11, 0x07, b'a', b'b'];
+  let record = Record::decode(&payload, &DatabaseTextEncoding::Utf8).unwrap();
+  assert_eq!(
+    record.values(),
+    [Value::Null, Value::Integer(7), Value::Text("ab".into())]
+  );
+}
+
+#[test]
+fn err_on_truncated_record() {
+  let payload = [0x03, 0x01, 0x11];
+  assert!(Record::decode(&payload, &DatabaseTextEncoding::Utf8).is_err());
+}
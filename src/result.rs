@@ -15,6 +15,7 @@ pub enum SqliteError {
   Custom(String),
   ParsingField(FieldParsingError),
   InvalidPayloadSize(InvalidPayloadSizeError),
+  SqlParsing(SqlParsingError),
 }
 
 #[derive(Debug)]
@@ -29,10 +30,73 @@ pub struct InvalidPayloadSizeError {
   pub ty: String,
 }
 
+#[derive(Debug)]
+pub struct SqlParsingError {
+  pub error: String,
+  /// Byte offset of the offending text in the SQL input.
+  pub offset: usize,
+  /// 1-based line of the offending text.
+  pub line: usize,
+  /// 1-based column (in characters) of the offending text.
+  pub column: usize,
+  /// What the parser was expecting at that point, if known.
+  pub expected: Option<String>,
+}
+
+impl SqlParsingError {
+  pub fn new(error: impl Into<String>, sql: &str, offset: usize) -> Self {
+    let offset = offset.min(sql.len());
+    let before = sql.get(..offset).unwrap_or(sql);
+    let line = before.matches('\n').count() + 1;
+    let column = before
+      .rsplit('\n')
+      .next()
+      .map(|s| s.chars().count())
+      .unwrap_or_default()
+      + 1;
+    Self {
+      error: error.into(),
+      offset,
+      line,
+      column,
+      expected: None,
+    }
+  }
+
+  pub fn with_expected(mut self, expected: impl Into<String>) -> Self {
+    self.expected = Some(expected.into());
+    self
+  }
+}
+
+impl Display for SqlParsingError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(
+      f,
+      "{} at line {}, column {}",
+      self.error, self.line, self.column
+    )?;
+    match &self.expected {
+      Some(expected) => write!(f, ", expected {expected}"),
+      None => Ok(()),
+    }
+  }
+}
+
+impl From<SqlParsingError> for SqliteError {
+  fn from(error: SqlParsingError) -> Self {
+    Self::SqlParsing(error)
+  }
+}
+
 impl Display for SqliteError {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-    // TODO
-    write!(f, "{:?}", self)
+    match self {
+      Self::Custom(message) => write!(f, "{message}"),
+      Self::SqlParsing(error) => write!(f, "{error}"),
+      // TODO
+      other => write!(f, "{:?}", other),
+    }
   }
 }
 
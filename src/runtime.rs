@@ -1,28 +1,86 @@
+mod collation;
+mod eval;
+mod functions;
+mod index_cursor;
 mod internal_tables;
-mod schema;
+mod pragma;
+mod query;
+pub mod schema;
+mod sorter;
+mod statement;
+mod table_cursor;
+mod vdbe;
 
-use std::{fmt::Debug, fs::Metadata};
+use core::cmp::Ordering;
+use std::{cell::OnceCell, fmt::Debug, fs::Metadata, rc::Rc};
 
 use crate::{
   file_header::SqliteHeader,
   io::{SqliteIo, SqliteIoMode},
   pager::SqlitePager,
-  result::SqliteResult,
+  result::{SqliteError, SqliteResult},
+  sql::{ast, parse_statement},
   traits::ParseBytes,
 };
 
-pub use self::schema::SqliteSchema;
+pub use self::{
+  collation::Collation,
+  functions::{functions, Arity, FunctionDef, FunctionKind},
+  index_cursor::IndexCursor,
+  internal_tables::{
+    sqlite_master::{SchemaObject, SchemaObjectType},
+    sqlite_sequence::SqliteSequence,
+    sqlite_stat1::SqliteStat1,
+  },
+  query::{PlanStep, QueryPlan, QueryResult},
+  schema::SqliteSchema,
+  sorter::{IndexEntries, DEFAULT_SORT_MEMORY_BUDGET},
+  statement::Statement,
+  table_cursor::TableCursor,
+  vdbe::InterruptHandle,
+};
+
+use self::schema::IndexSchema;
+
+/// What the connection has read of the database file: its header, and the
+/// schema and internal tables it parsed.
+///
+///  Statements borrow the schema they were compiled for, and may outlive a
+/// change another connection makes to it. So a snapshot is never replaced:
+/// the one read after it is chained to it, and the last one is current.
+#[derive(Debug, Default)]
+struct Snapshot {
+  file_header: SqliteHeader,
+  schema: Rc<SqliteSchema>,
+  sequence: SqliteSequence,
+  stat1: SqliteStat1,
+  next: OnceCell<Box<Snapshot>>,
+}
 
 pub struct SqliteRuntime {
   pager: SqlitePager,
-  file_header: SqliteHeader,
+  /// The first snapshot read, which leads to the current one.
+  snapshots: Snapshot,
+  /// The memory a sort may use before it spills to temporary files.
+  sort_memory_budget: usize,
+  /// The collations registered on the connection.
+  collations: Vec<Collation>,
+  /// Interrupts the running program.
+  interrupt: InterruptHandle,
 }
 
 impl Debug for SqliteRuntime {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let snapshot = self.snapshot();
     f.debug_struct("SqliteRuntime")
       .field("pager", &"SqlitePager")
-      .field("header", &self.file_header)
+      .field("header", &snapshot.file_header)
+      .field("schema", &snapshot.schema)
+      .field("sequence", &snapshot.sequence)
+      .field("stat1", &snapshot.stat1)
+      .field("sort_memory_budget", &self.sort_memory_budget)
+      .field("collations", &self.collations)
+      .field("interrupt", &self.interrupt)
       .finish()
   }
 }
@@ -40,7 +98,10 @@ impl SqliteRuntime {
     match pager.io().mode() {
       &SqliteIoMode::InMemory => Ok(Self {
         pager,
-        file_header: Default::default(),
+        snapshots: Default::default(),
+        sort_memory_budget: DEFAULT_SORT_MEMORY_BUDGET,
+        collations: vec![],
+        interrupt: InterruptHandle::default(),
       }),
       _ => {
         let page = pager.get_first_page()?;
@@ -51,13 +112,216 @@ impl SqliteRuntime {
           error!("{err}");
           err
         })?;
-        Ok(Self { pager, file_header })
+        let schema = SqliteSchema::load(&pager, file_header.database_text_encoding())?;
+        trace!("SqliteSchema loaded: [{schema:?}].");
+        let sequence = SqliteSequence::read(&pager, &schema, file_header.database_text_encoding())?;
+        let stat1 = SqliteStat1::read(&pager, &schema, file_header.database_text_encoding())?;
+        Ok(Self {
+          pager,
+          snapshots: Snapshot {
+            file_header,
+            schema: Rc::new(schema),
+            sequence,
+            stat1,
+            next: OnceCell::new(),
+          },
+          sort_memory_budget: DEFAULT_SORT_MEMORY_BUDGET,
+          collations: vec![],
+          interrupt: InterruptHandle::default(),
+        })
+      }
+    }
+  }
+
+  /// Starts a read by checking the header on page 1 against the cached one.
+  ///
+  ///  The schema cookie is incremented whenever the database schema changes,
+  /// so a different cookie means another connection has changed the schema
+  /// and sqlite_schema is parsed again. sqlite_sequence and sqlite_stat1 are
+  /// read again whenever the file change counter moves. What is read again
+  /// is a new snapshot, after the current one. Returns whether the schema
+  /// was reloaded.
+  pub fn begin_read(&self) -> SqliteResult<bool> {
+    if *self.pager.io().mode() == SqliteIoMode::InMemory {
+      return Ok(false);
+    }
+    let page = self.pager.read_page(1)?;
+    let file_header = SqliteHeader::parse_bytes(page.data())?;
+
+    let current = self.snapshot();
+    let is_schema_changed = **file_header.schema_cookie() != **current.file_header.schema_cookie();
+    let is_file_changed =
+      **file_header.file_change_counter() != **current.file_header.file_change_counter();
+    if !is_schema_changed && !is_file_changed {
+      return Ok(false);
+    }
+    let encoding = file_header.database_text_encoding();
+    let schema = match is_schema_changed {
+      true => {
+        trace!(
+          "Schema cookie changed from [{}] to [{}].",
+          **current.file_header.schema_cookie(),
+          **file_header.schema_cookie()
+        );
+        Rc::new(SqliteSchema::load(&self.pager, encoding)?)
       }
+      false => Rc::clone(&current.schema),
+    };
+    let sequence = SqliteSequence::read(&self.pager, &schema, encoding)?;
+    let stat1 = SqliteStat1::read(&self.pager, &schema, encoding)?;
+    let _ = current.next.set(Box::new(Snapshot {
+      file_header,
+      schema,
+      sequence,
+      stat1,
+      next: OnceCell::new(),
+    }));
+    Ok(is_schema_changed)
+  }
+
+  /// The snapshot read last.
+  fn snapshot(&self) -> &Snapshot {
+    let mut snapshot = &self.snapshots;
+    while let Some(next) = snapshot.next.get() {
+      snapshot = next;
     }
+    snapshot
   }
 
   pub fn file_header(&self) -> &SqliteHeader {
-    &self.file_header
+    &self.snapshot().file_header
+  }
+
+  pub fn schema(&self) -> &SqliteSchema {
+    &self.snapshot().schema
+  }
+
+  /// The last assigned sequence of every AUTOINCREMENT table.
+  pub fn sqlite_sequence(&self) -> &SqliteSequence {
+    &self.snapshot().sequence
+  }
+
+  /// The statistics ANALYZE has gathered about tables and indexes.
+  pub fn sqlite_stat1(&self) -> &SqliteStat1 {
+    &self.snapshot().stat1
+  }
+
+  /// Opens a cursor over the rows of a table. The PRIMARY KEY of a WITHOUT
+  /// ROWID table is its index, so its collations must be registered.
+  pub fn table_cursor(&self, table_name: &str) -> SqliteResult<TableCursor<'_>> {
+    let table = self
+      .schema()
+      .table(table_name)
+      .ok_or_else(|| SqliteError::Custom(format!("no such table: {table_name}")))?;
+    let collations = match table.is_without_rowid() {
+      true => table
+        .primary_key()
+        .iter()
+        .map(|(column, _)| self.collation(table.columns()[*column].collation().unwrap_or("BINARY")))
+        .collect::<SqliteResult<_>>()?,
+      false => vec![],
+    };
+    TableCursor::new(
+      &self.pager,
+      table,
+      *self.file_header().database_text_encoding(),
+      collations,
+    )
+  }
+
+  /// Opens a cursor over the entries of an index, whose collations must be
+  /// registered. A key without a collation of its own has that of its
+  /// column.
+  pub fn index_cursor(&self, index_name: &str) -> SqliteResult<IndexCursor<'_>> {
+    let index = self
+      .schema()
+      .index(index_name)
+      .ok_or_else(|| SqliteError::Custom(format!("no such index: {index_name}")))?;
+    Ok(IndexCursor::new(
+      &self.pager,
+      index,
+      *self.file_header().database_text_encoding(),
+      self.key_collations(index)?,
+    ))
+  }
+
+  /// The collation of each key of an index: its own, or that of its
+  /// column.
+  fn key_collations(&self, index: &IndexSchema) -> SqliteResult<Vec<Collation>> {
+    let table = self.schema().table(index.table_name());
+    index
+      .columns()
+      .iter()
+      .map(|key| {
+        let column = key
+          .column_name()
+          .zip(table)
+          .and_then(|(name, table)| table.column(name));
+        let name = key
+          .collation()
+          .or_else(|| column.and_then(|(_, column)| column.collation()));
+        self.collation(name.unwrap_or("BINARY"))
+      })
+      .collect()
+  }
+
+  /// Registers a collation, which replaces any other of the same name,
+  /// ignoring ASCII case, built-in ones included.
+  pub fn create_collation(
+    &mut self,
+    name: &str,
+    compare: impl Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+  ) {
+    self
+      .collations
+      .retain(|collation| !collation.name().eq_ignore_ascii_case(name));
+    self.collations.push(Collation::new(name, compare));
+  }
+
+  /// Finds a registered or built-in collation, ignoring ASCII case.
+  pub fn collation(&self, name: &str) -> SqliteResult<Collation> {
+    match self
+      .collations
+      .iter()
+      .find(|collation| collation.name().eq_ignore_ascii_case(name))
+    {
+      Some(collation) => Ok(collation.clone()),
+      None => Collation::find(name),
+    }
+  }
+
+  /// Runs a query and collects all of its rows. Those of `EXPLAIN` are the
+  /// instructions of the program of its query, and those of `EXPLAIN QUERY
+  /// PLAN` the steps of its plan.
+  pub fn query(&self, sql: &str) -> SqliteResult<QueryResult> {
+    self.execute(&parse_statement(sql)?)
+  }
+
+  /// Plans a query without running it. The query may start with `EXPLAIN
+  /// QUERY PLAN`.
+  pub fn query_plan(&self, sql: &str) -> SqliteResult<QueryPlan> {
+    match parse_statement(sql)? {
+      ast::Statement::Select(select)
+      | ast::Statement::Explain(select)
+      | ast::Statement::ExplainQueryPlan(select) => self.explain_select(&select),
+      ast::Statement::Pragma(_) => Ok(QueryPlan::default()),
+    }
+  }
+
+  /// A handle that interrupts the running query from any thread, making it
+  /// fail.
+  pub fn interrupt_handle(&self) -> InterruptHandle {
+    self.interrupt.clone()
+  }
+
+  /// The memory, in bytes, a sort may use before it spills sorted runs to
+  /// temporary files.
+  pub fn sort_memory_budget(&self) -> usize {
+    self.sort_memory_budget
+  }
+
+  pub fn set_sort_memory_budget(&mut self, bytes: usize) {
+    self.sort_memory_budget = bytes;
   }
 
   pub fn pager(&self) -> &SqlitePager {
@@ -0,0 +1,98 @@
+//! # Collating sequences
+//!
+//!  A collation decides how two texts compare when they are sorted or
+//! grouped. Only texts are compared with it: values of other storage
+//! classes compare as they always do.
+//!
+//! - **BINARY** compares the bytes of the texts, and is the default.
+//! - **NOCASE** is like BINARY, but folds the 26 upper case ASCII letters to
+//!   lower case first.
+//! - **RTRIM** is like BINARY, but ignores trailing spaces.
+//!
+//!  Others are registered on the connection, with a function that compares
+//! two texts. A comparison, a sort or an index that names a collation which
+//! is neither built in nor registered is an error.
+//!
+//! *Reference:* https://www.sqlite.org/datatype3.html#collating_sequences
+
+use core::{cmp::Ordering, fmt::Debug};
+use std::sync::Arc;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  value::Value,
+};
+
+/// Compares two texts.
+pub(crate) type Comparator = dyn Fn(&str, &str) -> Ordering + Send + Sync;
+
+/// A named collating sequence.
+#[derive(Clone)]
+pub struct Collation {
+  name: Arc<str>,
+  compare: Arc<Comparator>,
+}
+
+impl Collation {
+  pub fn binary() -> Self {
+    Self::new("BINARY", |a, b| a.as_bytes().cmp(b.as_bytes()))
+  }
+
+  pub fn nocase() -> Self {
+    Self::new("NOCASE", |a, b| {
+      a.bytes()
+        .map(|byte| byte.to_ascii_lowercase())
+        .cmp(b.bytes().map(|byte| byte.to_ascii_lowercase()))
+    })
+  }
+
+  pub fn rtrim() -> Self {
+    Self::new("RTRIM", |a, b| {
+      a.trim_end_matches(' ')
+        .as_bytes()
+        .cmp(b.trim_end_matches(' ').as_bytes())
+    })
+  }
+
+  pub(crate) fn new(
+    name: &str,
+    compare: impl Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+  ) -> Self {
+    Self {
+      name: name.into(),
+      compare: Arc::new(compare),
+    }
+  }
+
+  /// Finds a built-in collation, ignoring ASCII case.
+  pub fn find(name: &str) -> SqliteResult<Self> {
+    [Self::binary(), Self::nocase(), Self::rtrim()]
+      .into_iter()
+      .find(|collation| collation.name.eq_ignore_ascii_case(name))
+      .ok_or_else(|| SqliteError::Custom(format!("no such collation sequence: {name}")))
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Compares two values, texts with this collation.
+  pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+      (Value::Text(a), Value::Text(b)) => (self.compare)(a, b),
+      (a, b) => a.compare(b),
+    }
+  }
+}
+
+impl Default for Collation {
+  fn default() -> Self {
+    Self::binary()
+  }
+}
+
+impl Debug for Collation {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_tuple("Collation").field(&self.name).finish()
+  }
+}
@@ -0,0 +1,756 @@
+//! # Expression evaluation
+//!
+//!  Expressions are evaluated against an environment that supplies the values
+//! of the column references they contain. SQL uses three-valued logic: a
+//! comparison with NULL is NULL, and a WHERE clause only keeps the rows for
+//! which its expression is true.
+//!
+//!  Before two values are compared, the affinity of each operand may be
+//! applied to the other, so that a column of numbers can be compared with a
+//! number written as text:
+//!
+//! - If one operand has INTEGER, REAL or NUMERIC affinity and the other has
+//!   TEXT or BLOB or no affinity then NUMERIC affinity is applied to other
+//!   operand.
+//! - If one operand has TEXT affinity and the other has no affinity, then
+//!   TEXT affinity is applied to the other operand.
+//! - Otherwise, no affinity is applied and both operands are compared as is.
+//!
+//!  Only column references, `CAST` expressions and their `COLLATE`d forms
+//! have an affinity. In particular `+column` has none.
+//!
+//! *Reference:* https://www.sqlite.org/datatype3.html#comparison_expressions
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  result::{SqliteError, SqliteResult},
+  sql::ast::{BinaryOperator, Expr, FunctionArgs, LikeOperator, Literal, UnaryOperator},
+  value::{Affinity, Value},
+};
+use core::cmp::Ordering;
+
+use super::{
+  collation::Collation,
+  functions::{
+    current_date_time, extract_arrow, is_json, resolve_call, to_jsonb, FunctionDef, Implementation,
+  },
+};
+
+/// Supplies the values that an expression refers to.
+pub(crate) trait Environment {
+  /// The value of the column `[[schema.]table.]name`.
+  fn column(&self, schema: Option<&str>, table: Option<&str>, name: &str) -> SqliteResult<Value>;
+
+  /// The affinity of the column `[[schema.]table.]name`.
+  fn column_affinity(&self, _schema: Option<&str>, _table: Option<&str>, _name: &str) -> Affinity {
+    Affinity::Blob
+  }
+
+  /// The name of the collation of the column `[[schema.]table.]name`.
+  fn column_collation(
+    &self,
+    _schema: Option<&str>,
+    _table: Option<&str>,
+    _name: &str,
+  ) -> Option<String> {
+    None
+  }
+
+  /// Finds a collation by name, ignoring ASCII case.
+  fn collation(&self, name: &str) -> SqliteResult<Collation> {
+    Collation::find(name)
+  }
+
+  /// Whether the column `[[schema.]table.]name` holds JSON, as the `value`
+  /// of `json_each()` may.
+  fn is_json_column(&self, _schema: Option<&str>, _table: Option<&str>, _name: &str) -> bool {
+    false
+  }
+
+  /// The text encoding of the database, which CAST reads blobs in.
+  fn encoding(&self) -> DatabaseTextEncoding {
+    DatabaseTextEncoding::Utf8
+  }
+
+  /// The value bound to a parameter. Unbound parameters are NULL.
+  fn variable(&self, _name: &str) -> SqliteResult<Value> {
+    Ok(Value::Null)
+  }
+
+  /// The rowid of the last row inserted through the connection, or 0.
+  fn last_insert_rowid(&self) -> i64 {
+    0
+  }
+
+  /// The time the current statement started, in milliseconds since the
+  /// Unix epoch, which `now` refers to.
+  fn statement_time(&self) -> i64 {
+    unix_time_millis()
+  }
+}
+
+/// The environment of constant expressions, which have no columns.
+pub(crate) struct Constant;
+
+impl Environment for Constant {
+  fn column(&self, _: Option<&str>, table: Option<&str>, name: &str) -> SqliteResult<Value> {
+    Err(no_such_column(table, name))
+  }
+}
+
+/// The current time, in milliseconds since the Unix epoch.
+pub(crate) fn unix_time_millis() -> i64 {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_millis() as i64)
+}
+
+/// The value of TRUE or FALSE, which are names of the constants 1 and 0
+/// where no column has them.
+///
+/// *Reference:* https://www.sqlite.org/lang_expr.html#boolean_expressions
+pub(crate) fn truth_value(name: &str) -> Option<bool> {
+  match name.to_ascii_lowercase().as_str() {
+    "true" => Some(true),
+    "false" => Some(false),
+    _ => None,
+  }
+}
+
+pub(crate) fn no_such_column(table: Option<&str>, name: &str) -> SqliteError {
+  match table {
+    Some(table) => SqliteError::Custom(format!("no such column: {table}.{name}")),
+    None => SqliteError::Custom(format!("no such column: {name}")),
+  }
+}
+
+pub(crate) fn evaluate(expr: &Expr, env: &dyn Environment) -> SqliteResult<Value> {
+  match expr {
+    Expr::Literal(literal) => evaluate_literal(literal, env),
+    Expr::Variable(name) => env.variable(name),
+    Expr::Column {
+      schema,
+      table,
+      name,
+    } => env
+      .column(schema.as_deref(), table.as_deref(), name)
+      .or_else(|err| match (schema, table, truth_value(name)) {
+        (None, None, Some(value)) => Ok(Value::Integer(value.into())),
+        _ => Err(err),
+      }),
+    Expr::Unary { operator, expr } => {
+      let value = evaluate(expr, env)?;
+      Ok(match operator {
+        UnaryOperator::Plus => value,
+        UnaryOperator::Negate => negate(&value),
+        UnaryOperator::Not => boolean(is_true(&value).map(|is_true| !is_true)),
+        UnaryOperator::BitNot => match to_integer(&value) {
+          Some(integer) => Value::Integer(!integer),
+          None => Value::Null,
+        },
+      })
+    }
+    Expr::Binary {
+      left,
+      operator: BinaryOperator::And,
+      right,
+    } => {
+      // FALSE AND NULL is FALSE, TRUE AND NULL is NULL.
+      let left = is_true(&evaluate(left, env)?);
+      if left == Some(false) {
+        return Ok(boolean(Some(false)));
+      }
+      Ok(match (left, is_true(&evaluate(right, env)?)) {
+        (_, Some(false)) => boolean(Some(false)),
+        (Some(true), Some(true)) => boolean(Some(true)),
+        _ => Value::Null,
+      })
+    }
+    Expr::Binary {
+      left,
+      operator: BinaryOperator::Or,
+      right,
+    } => {
+      // TRUE OR NULL is TRUE, FALSE OR NULL is NULL.
+      let left = is_true(&evaluate(left, env)?);
+      if left == Some(true) {
+        return Ok(boolean(Some(true)));
+      }
+      Ok(match (left, is_true(&evaluate(right, env)?)) {
+        (_, Some(true)) => boolean(Some(true)),
+        (Some(false), Some(false)) => boolean(Some(false)),
+        _ => Value::Null,
+      })
+    }
+    Expr::Binary {
+      left,
+      operator,
+      right,
+    } => match comparison(*operator) {
+      Some(is_match) => {
+        let ordering = compare_exprs(left, right, *operator, env)?;
+        Ok(boolean(ordering.map(is_match)))
+      }
+      None => binary(*operator, &evaluate(left, env)?, &evaluate(right, env)?),
+    },
+    Expr::IsNull { expr, not } => Ok(boolean(Some(evaluate(expr, env)?.is_null() != *not))),
+    // The collation only matters to the comparison the expression is in.
+    Expr::Collate { expr, .. } => evaluate(expr, env),
+    Expr::Cast { expr, type_name } => {
+      let affinity = Affinity::from_declared_type(Some(type_name));
+      Ok(evaluate(expr, env)?.cast_in(affinity, env.encoding()))
+    }
+    Expr::Between {
+      expr,
+      not,
+      low,
+      high,
+    } => {
+      let low = compare_exprs(expr, low, BinaryOperator::Ge, env)?.map(Ordering::is_ge);
+      let high = compare_exprs(expr, high, BinaryOperator::Le, env)?.map(Ordering::is_le);
+      let is_between = match (low, high) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+      };
+      Ok(boolean(is_between.map(|is_between| is_between != *not)))
+    }
+    Expr::InList { expr, not, list } => {
+      // `x IN (y, z)` is `x = +y OR x = +z`: only the left operand can lend
+      // its affinity.
+      if list.is_empty() {
+        return Ok(boolean(Some(*not)));
+      }
+      let affinity = expr_affinity(expr, env);
+      let value = evaluate(expr, env)?;
+      if value.is_null() {
+        return Ok(Value::Null);
+      }
+      let mut has_null = false;
+      for item in list {
+        let collation = comparison_collation(expr, item, env)?;
+        let item = evaluate(item, env)?;
+        match compare_with_affinity(value.clone(), affinity, item, Affinity::Blob, &collation) {
+          Some(Ordering::Equal) => return Ok(boolean(Some(!*not))),
+          Some(_) => {}
+          None => has_null = true,
+        }
+      }
+      Ok(match has_null {
+        true => Value::Null,
+        false => boolean(Some(*not)),
+      })
+    }
+    Expr::Case {
+      operand,
+      when_then,
+      else_expr,
+    } => {
+      for (when, then) in when_then {
+        let is_match = match operand {
+          Some(operand) => {
+            compare_exprs(operand, when, BinaryOperator::Eq, env)? == Some(Ordering::Equal)
+          }
+          None => is_true(&evaluate(when, env)?) == Some(true),
+        };
+        if is_match {
+          return evaluate(then, env);
+        }
+      }
+      match else_expr {
+        Some(else_expr) => evaluate(else_expr, env),
+        None => Ok(Value::Null),
+      }
+    }
+    Expr::Like {
+      expr,
+      not,
+      operator,
+      pattern,
+      escape,
+    } => {
+      let value = evaluate(expr, env)?;
+      let pattern = evaluate(pattern, env)?;
+      let escape = match escape {
+        Some(escape) => Some(evaluate(escape, env)?),
+        None => None,
+      };
+      let is_match = match_pattern(*operator, &value, &pattern, escape.as_ref())?;
+      Ok(boolean(is_match.map(|is_match| is_match != *not)))
+    }
+    Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    } => {
+      let function = resolve_call(
+        name,
+        *distinct,
+        args,
+        order_by,
+        filter.as_deref(),
+        over.is_some(),
+      )?;
+      let implementation = match function.implementation {
+        _ if over.is_some() => {
+          return Err(SqliteError::Custom(format!(
+            "misuse of window function {name}()"
+          )))
+        }
+        Implementation::Scalar(implementation) => implementation,
+        Implementation::Aggregate(_) | Implementation::Window(_) => {
+          return Err(SqliteError::Custom(format!(
+            "misuse of aggregate function {name}()"
+          )))
+        }
+      };
+      let args = match args {
+        FunctionArgs::Star => vec![],
+        FunctionArgs::List(args) => evaluate_args(function, args, env)?,
+      };
+      implementation(env, &args)
+    }
+    Expr::Row(_) => Err(SqliteError::Custom("row value misused".into())),
+    // Subqueries only run in compiled queries.
+    Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSelect { .. } => Err(SqliteError::Custom(
+      "subqueries are not supported here".into(),
+    )),
+    Expr::InTable { .. } => Err(SqliteError::Custom(
+      "IN with a table is not supported yet".into(),
+    )),
+  }
+}
+
+/// Whether a value matches a `LIKE` or `GLOB` pattern, or `None` when
+/// either of them or the escape character is NULL.
+pub(crate) fn match_pattern(
+  operator: LikeOperator,
+  value: &Value,
+  pattern: &Value,
+  escape: Option<&Value>,
+) -> SqliteResult<Option<bool>> {
+  let escape = match escape {
+    Some(Value::Null) => return Ok(None),
+    Some(escape) => {
+      let escape = escape.to_string();
+      let mut chars = escape.chars();
+      match (chars.next(), chars.next()) {
+        (Some(escape), None) => Some(escape),
+        _ => {
+          return Err(SqliteError::Custom(
+            "ESCAPE expression must be a single character".into(),
+          ))
+        }
+      }
+    }
+    None => None,
+  };
+  if value.is_null() || pattern.is_null() {
+    return Ok(None);
+  }
+  let text: Vec<char> = value.to_string().chars().collect();
+  let pattern: Vec<char> = pattern.to_string().chars().collect();
+  match operator {
+    LikeOperator::Like => Ok(Some(like(&pattern, &text, escape))),
+    LikeOperator::Glob => Ok(Some(glob(&pattern, &text))),
+    LikeOperator::Regexp => Err(SqliteError::Custom("no such function: REGEXP".into())),
+    LikeOperator::Match => Err(SqliteError::Custom(
+      "unable to use function MATCH in the requested context".into(),
+    )),
+  }
+}
+
+/// Whether a value is true in a boolean context, or `None` for NULL.
+///
+///  Numbers are true when they are not zero. Text and blobs are converted to
+/// numbers first, so `'1abc'` is true and `'abc'` is false.
+pub(crate) fn is_true(value: &Value) -> Option<bool> {
+  match value.to_numeric() {
+    Value::Integer(integer) => Some(integer != 0),
+    Value::Real(real) => Some(real != 0.0),
+    _ => None,
+  }
+}
+
+/// The affinity an expression lends to comparisons.
+pub(crate) fn expr_affinity(expr: &Expr, env: &dyn Environment) -> Affinity {
+  match expr {
+    Expr::Column {
+      schema,
+      table,
+      name,
+    } => env.column_affinity(schema.as_deref(), table.as_deref(), name),
+    Expr::Cast { type_name, .. } => Affinity::from_declared_type(Some(type_name)),
+    Expr::Collate { expr, .. } => expr_affinity(expr, env),
+    _ => Affinity::Blob,
+  }
+}
+
+pub(crate) fn boolean(value: Option<bool>) -> Value {
+  match value {
+    Some(value) => Value::Integer(value.into()),
+    None => Value::Null,
+  }
+}
+
+fn evaluate_literal(literal: &Literal, env: &dyn Environment) -> SqliteResult<Value> {
+  Ok(match literal {
+    Literal::Null => Value::Null,
+    Literal::Integer(integer) => Value::Integer(*integer),
+    Literal::Float(real) => Value::Real(*real),
+    Literal::String(text) => Value::Text(text.clone()),
+    Literal::Blob(blob) => Value::Blob(blob.clone()),
+    Literal::CurrentTime | Literal::CurrentDate | Literal::CurrentTimestamp => {
+      return current_date_time(env, literal)
+    }
+  })
+}
+
+/// The orderings for which a comparison operator is true.
+pub(crate) fn comparison(operator: BinaryOperator) -> Option<fn(Ordering) -> bool> {
+  Some(match operator {
+    BinaryOperator::Eq | BinaryOperator::Is => Ordering::is_eq,
+    BinaryOperator::Ne | BinaryOperator::IsNot => Ordering::is_ne,
+    BinaryOperator::Lt => Ordering::is_lt,
+    BinaryOperator::Le => Ordering::is_le,
+    BinaryOperator::Gt => Ordering::is_gt,
+    BinaryOperator::Ge => Ordering::is_ge,
+    _ => return None,
+  })
+}
+
+/// Compares two expressions with their affinities and collation, as
+/// [`compare_values`] does.
+fn compare_exprs(
+  left: &Expr,
+  right: &Expr,
+  operator: BinaryOperator,
+  env: &dyn Environment,
+) -> SqliteResult<Option<Ordering>> {
+  let left_affinity = expr_affinity(left, env);
+  let right_affinity = expr_affinity(right, env);
+  let collation = comparison_collation(left, right, env)?;
+  let left = evaluate(left, env)?;
+  let right = evaluate(right, env)?;
+  Ok(compare_values(
+    left,
+    left_affinity,
+    right,
+    right_affinity,
+    &collation,
+    operator,
+  ))
+}
+
+/// Compares the values of the operands of a comparison operator, given
+/// their affinities, or `None` when either is NULL. For `IS` and `IS NOT`,
+/// NULL compares equal to NULL and less than anything else.
+pub(crate) fn compare_values(
+  left: Value,
+  left_affinity: Affinity,
+  right: Value,
+  right_affinity: Affinity,
+  collation: &Collation,
+  operator: BinaryOperator,
+) -> Option<Ordering> {
+  if matches!(operator, BinaryOperator::Is | BinaryOperator::IsNot)
+    && (left.is_null() || right.is_null())
+  {
+    return Some(left.is_null().cmp(&right.is_null()).reverse());
+  }
+  compare_with_affinity(left, left_affinity, right, right_affinity, collation)
+}
+
+/// The collation a comparison uses.
+fn comparison_collation(
+  left: &Expr,
+  right: &Expr,
+  env: &dyn Environment,
+) -> SqliteResult<Collation> {
+  env.collation(&comparison_collation_name(left, right, env))
+}
+
+/// The name of the collation a comparison uses: that of a `COLLATE` on
+/// either operand, the left one first, or else that of a column operand,
+/// BINARY by default.
+pub(crate) fn comparison_collation_name(
+  left: &Expr,
+  right: &Expr,
+  env: &dyn Environment,
+) -> String {
+  let explicit = |expr: &Expr| match expr {
+    Expr::Collate { collation, .. } => Some(collation.clone()),
+    _ => None,
+  };
+  explicit(left)
+    .or_else(|| explicit(right))
+    .or_else(|| collation_name(left, env))
+    .or_else(|| collation_name(right, env))
+    .unwrap_or_else(|| "BINARY".into())
+}
+
+/// The name of the collation of an expression: that of its `COLLATE`, or
+/// of the column it is, through unary `+` and `CAST`.
+pub(crate) fn collation_name(expr: &Expr, env: &dyn Environment) -> Option<String> {
+  match expr {
+    Expr::Collate { collation, .. } => Some(collation.clone()),
+    Expr::Column {
+      schema,
+      table,
+      name,
+    } => env.column_collation(schema.as_deref(), table.as_deref(), name),
+    Expr::Unary {
+      operator: UnaryOperator::Plus,
+      expr,
+    }
+    | Expr::Cast { expr, .. } => collation_name(expr, env),
+    _ => None,
+  }
+}
+
+pub(crate) fn compare_with_affinity(
+  left: Value,
+  left_affinity: Affinity,
+  right: Value,
+  right_affinity: Affinity,
+  collation: &Collation,
+) -> Option<Ordering> {
+  if left.is_null() || right.is_null() {
+    return None;
+  }
+  let is_numeric = |affinity| {
+    matches!(
+      affinity,
+      Affinity::Integer | Affinity::Real | Affinity::Numeric
+    )
+  };
+  let (left, right) = match (left_affinity, right_affinity) {
+    (l, r) if is_numeric(l) && !is_numeric(r) => (left, right.apply_affinity(Affinity::Numeric)),
+    (l, r) if !is_numeric(l) && is_numeric(r) => (left.apply_affinity(Affinity::Numeric), right),
+    (Affinity::Text, Affinity::Blob) => (left, right.apply_affinity(Affinity::Text)),
+    (Affinity::Blob, Affinity::Text) => (left.apply_affinity(Affinity::Text), right),
+    _ => (left, right),
+  };
+  Some(collation.compare(&left, &right))
+}
+
+/// Evaluates the arguments of a call. Those that are JSON are passed as
+/// such to the functions that take JSON.
+pub(crate) fn evaluate_args(
+  function: &FunctionDef,
+  args: &[Expr],
+  env: &dyn Environment,
+) -> SqliteResult<Vec<Value>> {
+  args
+    .iter()
+    .map(|arg| {
+      let value = evaluate(arg, env)?;
+      Ok(
+        match function.is_taking_json() && is_json(arg, &value, env) {
+          true => to_jsonb(value),
+          false => value,
+        },
+      )
+    })
+    .collect()
+}
+
+pub(crate) fn binary(operator: BinaryOperator, left: &Value, right: &Value) -> SqliteResult<Value> {
+  if left.is_null() || right.is_null() {
+    return Ok(Value::Null);
+  }
+  Ok(match operator {
+    BinaryOperator::Add
+    | BinaryOperator::Subtract
+    | BinaryOperator::Multiply
+    | BinaryOperator::Divide
+    | BinaryOperator::Remainder => arithmetic(operator, &left.to_numeric(), &right.to_numeric()),
+    BinaryOperator::Concat => Value::Text(format!("{left}{right}")),
+    BinaryOperator::BitAnd
+    | BinaryOperator::BitOr
+    | BinaryOperator::ShiftLeft
+    | BinaryOperator::ShiftRight => {
+      let (Some(left), Some(right)) = (to_integer(left), to_integer(right)) else {
+        return Ok(Value::Null);
+      };
+      Value::Integer(match operator {
+        BinaryOperator::BitAnd => left & right,
+        BinaryOperator::BitOr => left | right,
+        BinaryOperator::ShiftLeft => shift_left(left, right),
+        _ => shift_left(left, right.checked_neg().unwrap_or(i64::MAX)),
+      })
+    }
+    BinaryOperator::Extract => extract_arrow(left, right, false)?,
+    BinaryOperator::ExtractText => extract_arrow(left, right, true)?,
+    _ => {
+      return Err(SqliteError::Custom(format!(
+        "unsupported operator: {operator:?}"
+      )))
+    }
+  })
+}
+
+/// Shifts left, or right for a negative `shift`. Bits shifted past either
+/// end are lost, and right shifts copy the sign bit.
+fn shift_left(value: i64, shift: i64) -> i64 {
+  match shift {
+    64.. => 0,
+    0..=63 => ((value as u64) << shift) as i64,
+    ..=-64 => match value < 0 {
+      true => -1,
+      false => 0,
+    },
+    _ => value >> -shift,
+  }
+}
+
+/// Integer arithmetic falls back to REAL when it overflows. Division and
+/// remainder by zero are NULL. The remainder of REAL operands is that of
+/// their integer parts.
+fn arithmetic(operator: BinaryOperator, left: &Value, right: &Value) -> Value {
+  if let (Value::Integer(a), Value::Integer(b)) = (left, right) {
+    let result = match operator {
+      BinaryOperator::Add => a.checked_add(*b),
+      BinaryOperator::Subtract => a.checked_sub(*b),
+      BinaryOperator::Multiply => a.checked_mul(*b),
+      BinaryOperator::Divide if *b == 0 => return Value::Null,
+      BinaryOperator::Divide => a.checked_div(*b),
+      BinaryOperator::Remainder if *b == 0 => return Value::Null,
+      BinaryOperator::Remainder => Some(a.checked_rem(*b).unwrap_or(0)),
+      _ => None,
+    };
+    if let Some(result) = result {
+      return Value::Integer(result);
+    }
+  }
+
+  let (Some(a), Some(b)) = (as_f64(left), as_f64(right)) else {
+    return Value::Null;
+  };
+  let result = match operator {
+    BinaryOperator::Add => a + b,
+    BinaryOperator::Subtract => a - b,
+    BinaryOperator::Multiply => a * b,
+    BinaryOperator::Divide if b == 0.0 => return Value::Null,
+    BinaryOperator::Divide => a / b,
+    BinaryOperator::Remainder => {
+      let (a, b) = (a as i64, b as i64);
+      if b == 0 {
+        return Value::Null;
+      }
+      return Value::Real(a.checked_rem(b).unwrap_or(0) as f64);
+    }
+    _ => return Value::Null,
+  };
+  if result.is_nan() {
+    Value::Null
+  } else {
+    Value::Real(result)
+  }
+}
+
+pub(crate) fn negate(value: &Value) -> Value {
+  match value.to_numeric() {
+    Value::Integer(integer) => integer
+      .checked_neg()
+      .map(Value::Integer)
+      .unwrap_or(Value::Real(-(integer as f64))),
+    Value::Real(real) => Value::Real(-real),
+    other => other,
+  }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+  match value {
+    Value::Integer(integer) => Some(*integer as f64),
+    Value::Real(real) => Some(*real),
+    _ => None,
+  }
+}
+
+/// The integer operand of a bitwise operator, or `None` for NULL.
+pub(crate) fn to_integer(value: &Value) -> Option<i64> {
+  match value.cast(Affinity::Integer) {
+    Value::Integer(integer) => Some(integer),
+    _ => None,
+  }
+}
+
+/// `LIKE` matching: `%` matches any sequence of characters and `_` any one
+/// character. Letters match regardless of ASCII case.
+fn like(pattern: &[char], text: &[char], escape: Option<char>) -> bool {
+  let Some((&first, rest)) = pattern.split_first() else {
+    return text.is_empty();
+  };
+  if Some(first) == escape {
+    return match (rest.split_first(), text.split_first()) {
+      (Some((&literal, rest)), Some((&c, text))) if literal.eq_ignore_ascii_case(&c) => {
+        like(rest, text, escape)
+      }
+      _ => false,
+    };
+  }
+  match first {
+    '%' => (0..=text.len()).any(|skip| like(rest, &text[skip..], escape)),
+    '_' => !text.is_empty() && like(rest, &text[1..], escape),
+    _ => match text.split_first() {
+      Some((c, text)) if c.eq_ignore_ascii_case(&first) => like(rest, text, escape),
+      _ => false,
+    },
+  }
+}
+
+/// `GLOB` matching, which is case sensitive: `*` matches any sequence of
+/// characters, `?` any one character and `[...]` any character of a set,
+/// or not in it when the set starts with `^`.
+fn glob(pattern: &[char], text: &[char]) -> bool {
+  let Some((&first, rest)) = pattern.split_first() else {
+    return text.is_empty();
+  };
+  match first {
+    '*' => (0..=text.len()).any(|skip| glob(rest, &text[skip..])),
+    '?' => !text.is_empty() && glob(rest, &text[1..]),
+    '[' => {
+      let Some((&c, text)) = text.split_first() else {
+        return false;
+      };
+      let mut set = rest;
+      let is_negated = set.first() == Some(&'^');
+      if is_negated {
+        set = &set[1..];
+      }
+      // A `]` right after the opening bracket is part of the set.
+      let Some(end) = set
+        .iter()
+        .skip(1)
+        .position(|c| *c == ']')
+        .map(|end| end + 1)
+      else {
+        return false;
+      };
+      let (members, rest) = (&set[..end], &set[end + 1..]);
+      let mut is_member = false;
+      let mut i = 0;
+      while i < members.len() {
+        if i + 2 < members.len() && members[i + 1] == '-' {
+          is_member |= (members[i]..=members[i + 2]).contains(&c);
+          i += 3;
+        } else {
+          is_member |= members[i] == c;
+          i += 1;
+        }
+      }
+      is_member != is_negated && glob(rest, text)
+    }
+    _ => match text.split_first() {
+      Some((c, text)) if *c == first => glob(rest, text),
+      _ => false,
+    },
+  }
+}
@@ -0,0 +1,129 @@
+//! Tests for expression evaluation
+//!
+//! To run: `cargo test runtime::eval`
+
+use super::{evaluate, Constant};
+use crate::{sql::parse_expr, value::Value, SqliteConnection};
+
+fn eval(sql: &str) -> Value {
+  evaluate(&parse_expr(sql).unwrap(), &Constant).unwrap()
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same expressions.
+#[test]
+fn ok_on_constant_expressions() {
+  let null = Value::Null;
+  let cases: [(&str, Value); 34] = [
+    ("9223372036854775807 + 1", 9223372036854775808.0.into()),
+    ("-9223372036854775808 / -1", 9223372036854775808.0.into()),
+    ("7 / 2", 3.into()),
+    ("-7 / 2", (-3).into()),
+    ("7.0 / 2", 3.5.into()),
+    ("1 / 0", null.clone()),
+    ("5 % 0.5", null.clone()),
+    ("-7 % 3", (-1).into()),
+    ("5.5 % 2", 1.0.into()),
+    ("'3' + '4'", 7.into()),
+    ("'1e3' + 0", 1000.0.into()),
+    ("'0x10' + 0", 0.into()),
+    ("-'5'", (-5).into()),
+    ("'abc' < x'00'", 1.into()),
+    ("1 < 'a'", 1.into()),
+    ("'10' < 9", 0.into()),
+    ("NULL = NULL", null.clone()),
+    ("NULL IS NULL", 1.into()),
+    ("1 IS NOT NULL", 1.into()),
+    ("3 BETWEEN 1 AND NULL", null.clone()),
+    ("0 BETWEEN 1 AND NULL", 0.into()),
+    ("1 IN (2, NULL)", null.clone()),
+    ("2 IN (2, NULL)", 1.into()),
+    ("NULL IN ()", 0.into()),
+    ("CASE 2 WHEN 1 THEN 'a' WHEN 2 THEN 'b' END", "b".into()),
+    ("CASE WHEN 0 THEN 1 END", null.clone()),
+    ("6 & 3 | 8", 10.into()),
+    ("~5", (-6).into()),
+    ("1 << 62 << 1", i64::MIN.into()),
+    ("-16 >> 2", (-4).into()),
+    ("1 << 64", 0.into()),
+    ("'a' || 1.5 || x'41' || 2", "a1.5A2".into()),
+    ("NULL AND 0", 0.into()),
+    ("NOT '1abc'", 0.into()),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_cast_and_pattern_matching() {
+  let cases: [(&str, Value); 21] = [
+    ("CAST('1e3' AS INTEGER)", 1.into()),
+    ("CAST('0x10' AS INTEGER)", 0.into()),
+    ("CAST(' 12abc' AS NUMERIC)", 12.into()),
+    ("CAST('1e400' AS REAL)", f64::INFINITY.into()),
+    ("CAST('-0.0' AS NUMERIC)", 0.into()),
+    ("CAST('1e18' AS NUMERIC)", 1e18.into()),
+    ("CAST(' 12.5x' AS REAL)", 12.5.into()),
+    ("CAST('3.0' AS NUMERIC)", 3.into()),
+    ("CAST(3.0 AS NUMERIC)", 3.0.into()),
+    ("CAST('9223372036854775808' AS INTEGER)", i64::MAX.into()),
+    ("CAST(-9e30 AS INTEGER)", i64::MIN.into()),
+    ("CAST(x'3132' AS INTEGER)", 12.into()),
+    ("CAST(1e20 AS TEXT)", "1.0e+20".into()),
+    ("CAST(3 AS BLOB)", Value::Blob(b"3".to_vec())),
+    ("'abc' LIKE 'A%'", 1.into()),
+    ("'a_c' LIKE 'a\\_c' ESCAPE '\\'", 1.into()),
+    ("'abc' NOT LIKE '_b_'", 0.into()),
+    ("'abc' GLOB 'A*'", 0.into()),
+    ("'b' GLOB '[a-c]'", 1.into()),
+    ("'b' GLOB '[^a-c]'", 0.into()),
+    ("'x]' GLOB '?[]]'", 1.into()),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_cast_in_utf16_database() {
+  let conn = SqliteConnection::open("sqlite://./data/utf16.db").unwrap();
+  let sql = "SELECT CAST(b AS TEXT), CAST(b AS INTEGER), CAST(s AS BLOB), CAST(1.5 AS BLOB) FROM t";
+  assert_eq!(
+    conn.query(sql).unwrap().rows(),
+    [[
+      Value::from("12"),
+      Value::from(12),
+      Value::from(vec![b'3', 0, b'.', 0, b'5', 0]),
+      Value::from(vec![b'1', 0, b'.', 0, b'5', 0]),
+    ]]
+  );
+}
+
+#[test]
+fn ok_on_column_affinity_in_comparisons() {
+  let conn = SqliteConnection::open("sqlite://./data/added-columns.db").unwrap();
+  let ids = |sql: &str| -> Vec<Value> {
+    conn
+      .query(sql)
+      .unwrap()
+      .into_rows()
+      .into_iter()
+      .map(|mut row| row.remove(0))
+      .collect()
+  };
+
+  // `age` has INTEGER affinity, which '30' takes on. `+age` has none.
+  assert_eq!(
+    ids("SELECT id FROM person WHERE age = '30'"),
+    [1.into(), 2.into(), 3.into()]
+  );
+  assert!(ids("SELECT id FROM person WHERE +age = '30'").is_empty());
+  assert_eq!(
+    ids("SELECT id FROM person WHERE id IN ('2', 4.0)"),
+    [2.into(), 4.into()]
+  );
+  assert_eq!(
+    ids("SELECT name FROM person WHERE age BETWEEN '31' AND '50' OR name LIKE 'A%'"),
+    ["ana".into(), "dora".into()]
+  );
+}
@@ -0,0 +1,387 @@
+//! # Built-in SQL functions
+//!
+//!  Every function the engine knows is listed once in a registry, along with
+//! the number of arguments it takes. Expressions look their functions up in
+//! it, and so can anything that needs to enumerate them, such as the query
+//! planner or `EXPLAIN`.
+//!
+//!  A name may be registered more than once with different arities: the
+//! entry whose arity matches the call is used.
+//!
+//! *Reference:* https://www.sqlite.org/lang_corefunc.html
+
+#[cfg(test)]
+mod tests;
+
+mod aggregate;
+mod datetime;
+mod json;
+mod scalar;
+mod window;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  sql::ast::{Expr, FunctionArgs, OrderingTerm},
+  value::Value,
+};
+
+use super::{eval::Environment, pragma::PRAGMA_FUNCTIONS, SqliteRuntime};
+
+pub(crate) use self::{
+  aggregate::Accumulator,
+  datetime::current_date_time,
+  json::{extract_arrow, is_json, to_jsonb},
+  window::WindowFrame,
+};
+
+/// Computes a scalar function from the values of its arguments.
+pub(crate) type ScalarFunction = fn(&dyn Environment, &[Value]) -> SqliteResult<Value>;
+
+/// Starts a new accumulator of an aggregate function.
+pub(crate) type AggregateFunction = fn() -> Box<dyn Accumulator>;
+
+/// Computes a window function for the current row of a frame.
+pub(crate) type WindowFunction = fn(&WindowFrame) -> SqliteResult<Value>;
+
+/// Computes the rows of a table-valued function from the values of its
+/// arguments, reading the database through the runtime if it needs to.
+pub(crate) type TableFunction = fn(&SqliteRuntime, &[Value]) -> SqliteResult<Vec<Vec<Value>>>;
+
+/// The number of arguments a function takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+  Exactly(u8),
+  AtLeast(u8),
+}
+
+impl Arity {
+  pub fn accepts(&self, count: usize) -> bool {
+    match *self {
+      Self::Exactly(arity) => count == usize::from(arity),
+      Self::AtLeast(arity) => count >= usize::from(arity),
+    }
+  }
+
+  /// The number of arguments as SQLite reports it, -1 meaning any number.
+  pub fn narg(&self) -> i32 {
+    match *self {
+      Self::Exactly(arity) => i32::from(arity),
+      Self::AtLeast(_) => -1,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+  /// Computes one value from the arguments of each call.
+  Scalar,
+  /// Computes one value from the arguments of every row of a group.
+  Aggregate,
+  /// Computes one value for each row from the rows of its window.
+  Window,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Implementation {
+  Scalar(ScalarFunction),
+  Aggregate(AggregateFunction),
+  Window(WindowFunction),
+}
+
+/// A registered function.
+#[derive(Clone, Copy)]
+pub struct FunctionDef {
+  name: &'static str,
+  arity: Arity,
+  deterministic: bool,
+  takes_json: bool,
+  pub(crate) implementation: Implementation,
+}
+
+impl FunctionDef {
+  const fn scalar(name: &'static str, arity: Arity, implementation: ScalarFunction) -> Self {
+    Self {
+      name,
+      arity,
+      deterministic: true,
+      takes_json: false,
+      implementation: Implementation::Scalar(implementation),
+    }
+  }
+
+  const fn aggregate(name: &'static str, arity: Arity, implementation: AggregateFunction) -> Self {
+    Self {
+      name,
+      arity,
+      deterministic: true,
+      takes_json: false,
+      implementation: Implementation::Aggregate(implementation),
+    }
+  }
+
+  const fn window(name: &'static str, arity: Arity, implementation: WindowFunction) -> Self {
+    Self {
+      name,
+      arity,
+      deterministic: true,
+      takes_json: false,
+      implementation: Implementation::Window(implementation),
+    }
+  }
+
+  /// Marks a function whose result may differ between calls with the same
+  /// arguments.
+  const fn non_deterministic(mut self) -> Self {
+    self.deterministic = false;
+    self
+  }
+
+  /// Marks a function whose arguments may be JSON, rather than text to put
+  /// into JSON, when they come from another JSON function.
+  const fn takes_json(mut self) -> Self {
+    self.takes_json = true;
+    self
+  }
+
+  pub fn name(&self) -> &'static str {
+    self.name
+  }
+
+  pub fn arity(&self) -> Arity {
+    self.arity
+  }
+
+  pub fn kind(&self) -> FunctionKind {
+    match self.implementation {
+      Implementation::Scalar(_) => FunctionKind::Scalar,
+      Implementation::Aggregate(_) => FunctionKind::Aggregate,
+      Implementation::Window(_) => FunctionKind::Window,
+    }
+  }
+
+  pub fn is_deterministic(&self) -> bool {
+    self.deterministic
+  }
+
+  pub(crate) fn is_taking_json(&self) -> bool {
+    self.takes_json
+  }
+}
+
+impl core::fmt::Debug for FunctionDef {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("FunctionDef")
+      .field("name", &self.name)
+      .field("arity", &self.arity)
+      .field("kind", &self.kind())
+      .field("deterministic", &self.deterministic)
+      .finish()
+  }
+}
+
+const FUNCTIONS: &[FunctionDef] = {
+  use self::{
+    datetime::*,
+    json::*,
+    scalar::*,
+    window::*,
+    Arity::{AtLeast, Exactly},
+  };
+  &[
+    FunctionDef::aggregate("avg", Exactly(1), aggregate::avg),
+    FunctionDef::aggregate("count", Exactly(0), aggregate::count),
+    FunctionDef::aggregate("count", Exactly(1), aggregate::count),
+    FunctionDef::aggregate("group_concat", Exactly(1), aggregate::group_concat),
+    FunctionDef::aggregate("group_concat", Exactly(2), aggregate::group_concat),
+    FunctionDef::aggregate("json_group_array", Exactly(1), json_group_array).takes_json(),
+    FunctionDef::aggregate("json_group_object", Exactly(2), json_group_object).takes_json(),
+    FunctionDef::aggregate("max", Exactly(1), aggregate::max),
+    FunctionDef::aggregate("min", Exactly(1), aggregate::min),
+    FunctionDef::aggregate("string_agg", Exactly(2), aggregate::group_concat),
+    FunctionDef::aggregate("sum", Exactly(1), aggregate::sum),
+    FunctionDef::aggregate("total", Exactly(1), aggregate::total),
+    FunctionDef::scalar("abs", Exactly(1), abs),
+    FunctionDef::scalar("char", AtLeast(0), char),
+    FunctionDef::scalar("coalesce", AtLeast(2), coalesce),
+    FunctionDef::scalar("date", AtLeast(0), date),
+    FunctionDef::scalar("datetime", AtLeast(0), datetime),
+    FunctionDef::scalar("hex", Exactly(1), hex),
+    FunctionDef::scalar("if", AtLeast(2), iif),
+    FunctionDef::scalar("ifnull", Exactly(2), coalesce),
+    FunctionDef::scalar("iif", AtLeast(2), iif),
+    FunctionDef::scalar("instr", Exactly(2), instr),
+    FunctionDef::scalar("json", Exactly(1), json),
+    FunctionDef::scalar("json_array", AtLeast(0), json_array).takes_json(),
+    FunctionDef::scalar("json_extract", AtLeast(0), json_extract),
+    FunctionDef::scalar("json_insert", AtLeast(0), json_insert).takes_json(),
+    FunctionDef::scalar("json_object", AtLeast(0), json_object).takes_json(),
+    FunctionDef::scalar("json_patch", Exactly(2), json_patch),
+    FunctionDef::scalar("json_remove", AtLeast(0), json_remove),
+    FunctionDef::scalar("json_replace", AtLeast(0), json_replace).takes_json(),
+    FunctionDef::scalar("json_set", AtLeast(0), json_set).takes_json(),
+    FunctionDef::scalar("json_type", Exactly(1), json_type),
+    FunctionDef::scalar("json_type", Exactly(2), json_type),
+    FunctionDef::scalar("json_valid", Exactly(1), json_valid),
+    FunctionDef::scalar("json_valid", Exactly(2), json_valid),
+    FunctionDef::scalar("julianday", AtLeast(0), julianday),
+    FunctionDef::scalar("last_insert_rowid", Exactly(0), last_insert_rowid).non_deterministic(),
+    FunctionDef::scalar("length", Exactly(1), length),
+    FunctionDef::scalar("lower", Exactly(1), lower),
+    FunctionDef::scalar("ltrim", Exactly(1), ltrim),
+    FunctionDef::scalar("ltrim", Exactly(2), ltrim),
+    FunctionDef::scalar("max", AtLeast(2), max),
+    FunctionDef::scalar("min", AtLeast(2), min),
+    FunctionDef::scalar("nullif", Exactly(2), nullif),
+    FunctionDef::scalar("quote", Exactly(1), quote),
+    FunctionDef::scalar("random", Exactly(0), random).non_deterministic(),
+    FunctionDef::scalar("randomblob", Exactly(1), randomblob).non_deterministic(),
+    FunctionDef::scalar("replace", Exactly(3), replace),
+    FunctionDef::scalar("round", Exactly(1), round),
+    FunctionDef::scalar("round", Exactly(2), round),
+    FunctionDef::scalar("rtrim", Exactly(1), rtrim),
+    FunctionDef::scalar("rtrim", Exactly(2), rtrim),
+    FunctionDef::scalar("sign", Exactly(1), sign),
+    FunctionDef::scalar("strftime", AtLeast(1), strftime),
+    FunctionDef::scalar("substr", Exactly(2), substr),
+    FunctionDef::scalar("substr", Exactly(3), substr),
+    FunctionDef::scalar("substring", Exactly(2), substr),
+    FunctionDef::scalar("substring", Exactly(3), substr),
+    FunctionDef::scalar("time", AtLeast(0), time),
+    FunctionDef::scalar("timediff", Exactly(2), timediff),
+    FunctionDef::scalar("trim", Exactly(1), trim),
+    FunctionDef::scalar("trim", Exactly(2), trim),
+    FunctionDef::scalar("typeof", Exactly(1), type_of),
+    FunctionDef::scalar("unhex", Exactly(1), unhex),
+    FunctionDef::scalar("unhex", Exactly(2), unhex),
+    FunctionDef::scalar("unicode", Exactly(1), unicode),
+    FunctionDef::scalar("unixepoch", AtLeast(0), unixepoch),
+    FunctionDef::scalar("upper", Exactly(1), upper),
+    FunctionDef::scalar("zeroblob", Exactly(1), zeroblob),
+    FunctionDef::window("cume_dist", Exactly(0), cume_dist),
+    FunctionDef::window("dense_rank", Exactly(0), dense_rank),
+    FunctionDef::window("first_value", Exactly(1), first_value),
+    FunctionDef::window("lag", Exactly(1), lag),
+    FunctionDef::window("lag", Exactly(2), lag),
+    FunctionDef::window("lag", Exactly(3), lag),
+    FunctionDef::window("last_value", Exactly(1), last_value),
+    FunctionDef::window("lead", Exactly(1), lead),
+    FunctionDef::window("lead", Exactly(2), lead),
+    FunctionDef::window("lead", Exactly(3), lead),
+    FunctionDef::window("nth_value", Exactly(2), nth_value),
+    FunctionDef::window("ntile", Exactly(1), ntile),
+    FunctionDef::window("percent_rank", Exactly(0), percent_rank),
+    FunctionDef::window("rank", Exactly(0), rank),
+    FunctionDef::window("row_number", Exactly(0), row_number),
+  ]
+};
+
+/// A table-valued function, which a `FROM` clause calls like a table.
+#[derive(Debug)]
+pub(crate) struct TableFunctionDef {
+  pub(crate) name: &'static str,
+  /// Its columns, the last `arguments` of which are hidden and hold its
+  /// arguments. A function whose rows are shorter leaves them to the scan,
+  /// which appends the values of the arguments, NULL for those not given.
+  pub(crate) columns: &'static [&'static str],
+  pub(crate) arguments: usize,
+  pub(crate) implementation: TableFunction,
+  /// Whether a column of a row holds JSON, given the row.
+  pub(crate) is_json: fn(usize, &[Value]) -> bool,
+}
+
+const TABLE_FUNCTIONS: &[TableFunctionDef] = &[
+  TableFunctionDef {
+    name: "json_each",
+    columns: &json::EACH_COLUMNS,
+    arguments: 2,
+    implementation: json::json_each,
+    is_json: json::is_each_json,
+  },
+  TableFunctionDef {
+    name: "json_tree",
+    columns: &json::EACH_COLUMNS,
+    arguments: 2,
+    implementation: json::json_tree,
+    is_json: json::is_each_json,
+  },
+];
+
+/// Every built-in function, one entry per name and arity.
+pub fn functions() -> &'static [FunctionDef] {
+  FUNCTIONS
+}
+
+/// Finds the function a name and a number of arguments refer to, ignoring
+/// ASCII case.
+pub(crate) fn find_function(name: &str, args: &FunctionArgs) -> SqliteResult<&'static FunctionDef> {
+  let count = match args {
+    FunctionArgs::Star => 0,
+    FunctionArgs::List(args) => args.len(),
+  };
+  let mut candidates = FUNCTIONS
+    .iter()
+    .filter(|function| function.name.eq_ignore_ascii_case(name))
+    .peekable();
+  if candidates.peek().is_none() {
+    return Err(SqliteError::Custom(format!("no such function: {name}")));
+  }
+  candidates
+    .find(|function| function.arity.accepts(count))
+    .ok_or_else(|| SqliteError::Custom(format!("wrong number of arguments to function {name}()")))
+}
+
+/// Finds a table-valued function, ignoring ASCII case.
+pub(crate) fn find_table_function(name: &str) -> Option<&'static TableFunctionDef> {
+  TABLE_FUNCTIONS
+    .iter()
+    .chain(PRAGMA_FUNCTIONS)
+    .find(|function| function.name.eq_ignore_ascii_case(name))
+}
+
+/// Finds the function of a call, checking that only aggregates are given
+/// `FILTER` or `ORDER BY`, and `DISTINCT` only with a single argument. A
+/// call with an `OVER` clause, `is_window`, must be of an aggregate or a
+/// window function, and a window function must have one.
+pub(crate) fn resolve_call(
+  name: &str,
+  distinct: bool,
+  args: &FunctionArgs,
+  order_by: &[OrderingTerm],
+  filter: Option<&Expr>,
+  is_window: bool,
+) -> SqliteResult<&'static FunctionDef> {
+  let function = find_function(name, args)?;
+  if is_window {
+    let message = match function.kind() {
+      FunctionKind::Scalar => format!("{name}() may not be used as a window function"),
+      _ if distinct => "DISTINCT is not supported for window functions".into(),
+      _ if !order_by.is_empty() => format!("ORDER BY may not be used with non-aggregate {name}()"),
+      FunctionKind::Window if filter.is_some() => {
+        "FILTER clause may only be used with aggregate window functions".into()
+      }
+      _ => return Ok(function),
+    };
+    return Err(SqliteError::Custom(message));
+  }
+  if function.kind() == FunctionKind::Window {
+    return Err(SqliteError::Custom(format!(
+      "misuse of window function {name}()"
+    )));
+  }
+  if function.kind() == FunctionKind::Scalar {
+    let clause = match (filter, order_by.is_empty()) {
+      (Some(_), _) => "FILTER",
+      (None, false) => "ORDER BY",
+      (None, true) => return Ok(function),
+    };
+    return Err(SqliteError::Custom(format!(
+      "{clause} may not be used with non-aggregate {name}()"
+    )));
+  }
+  if distinct && !matches!(args, FunctionArgs::List(args) if args.len() == 1) {
+    return Err(SqliteError::Custom(
+      "DISTINCT aggregates must have exactly one argument".into(),
+    ));
+  }
+  Ok(function)
+}
@@ -0,0 +1,262 @@
+//! # Aggregate functions
+//!
+//!  An aggregate function folds the arguments of every row of a group into
+//! one value. Except for `count(*)`, rows whose argument is NULL are
+//! skipped, and over no rows at all the result is NULL, or 0 for `count()`
+//! and `total()`.
+//!
+//! *Reference:* https://www.sqlite.org/lang_aggfunc.html
+
+use core::cmp::Ordering;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  value::{Affinity, Value},
+};
+
+/// The running state of one aggregate function over one group.
+pub(crate) trait Accumulator {
+  /// Adds the arguments of a row.
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()>;
+
+  /// The result over the rows added so far.
+  fn value(&self) -> SqliteResult<Value>;
+
+  /// Whether the last step replaced the result with its argument. Only
+  /// `min()` and `max()` do, and the bare columns of their query then come
+  /// from that row.
+  fn replaced(&self) -> bool {
+    false
+  }
+}
+
+/// `count(*)` counts rows and `count(X)` the rows where `X` is not NULL.
+#[derive(Default)]
+struct Count(i64);
+
+impl Accumulator for Count {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    if args.first().map_or(true, |arg| !arg.is_null()) {
+      self.0 += 1;
+    }
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(Value::Integer(self.0))
+  }
+}
+
+pub(super) fn count() -> Box<dyn Accumulator> {
+  Box::<Count>::default()
+}
+
+/// Which result `Sum` gives.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SumKind {
+  Sum,
+  Total,
+  Average,
+}
+
+/// The sum of integers, exact until it overflows or a non-integer comes in,
+/// after which reals are added with Kahan-Babuska-Neumaier compensation.
+struct Sum {
+  kind: SumKind,
+  count: i64,
+  integer: i64,
+  real: f64,
+  error: f64,
+  is_approximate: bool,
+  has_overflowed: bool,
+}
+
+impl Sum {
+  fn new(kind: SumKind) -> Self {
+    Self {
+      kind,
+      count: 0,
+      integer: 0,
+      real: 0.0,
+      error: 0.0,
+      is_approximate: false,
+      has_overflowed: false,
+    }
+  }
+
+  fn add_real(&mut self, value: f64) {
+    let sum = self.real;
+    let total = sum + value;
+    if sum.abs() > value.abs() {
+      self.error += (sum - total) + value;
+    } else {
+      self.error += (value - total) + sum;
+    }
+    self.real = total;
+  }
+
+  /// Adds an integer to the real sum, in two parts when it is too large to
+  /// convert exactly.
+  fn add_integer(&mut self, value: i64) {
+    const EXACT_BOUND: i64 = 4_503_599_627_370_496;
+    if value <= -EXACT_BOUND || value >= EXACT_BOUND {
+      let small = value % 16384;
+      self.add_real((value - small) as f64);
+      self.add_real(small as f64);
+    } else {
+      self.add_real(value as f64);
+    }
+  }
+
+  /// Moves the exact integer sum so far into the real sum.
+  fn switch_to_real(&mut self) {
+    if !self.is_approximate {
+      self.is_approximate = true;
+      self.add_integer(self.integer);
+    }
+  }
+
+  fn real_value(&self) -> f64 {
+    match self.is_approximate {
+      true if self.error.is_finite() => self.real + self.error,
+      true => self.real,
+      false => self.integer as f64,
+    }
+  }
+}
+
+impl Accumulator for Sum {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    let number = match &args[0] {
+      Value::Null => return Ok(()),
+      Value::Text(_) => args[0].clone().apply_affinity(Affinity::Numeric),
+      value => value.clone(),
+    };
+    self.count += 1;
+    match number {
+      Value::Integer(integer) if !self.is_approximate => match self.integer.checked_add(integer) {
+        Some(sum) => self.integer = sum,
+        None => {
+          self.has_overflowed = true;
+          self.switch_to_real();
+          self.add_integer(integer);
+        }
+      },
+      Value::Integer(integer) => self.add_integer(integer),
+      Value::Real(real) => {
+        self.switch_to_real();
+        self.add_real(real);
+      }
+      other => {
+        self.switch_to_real();
+        match other.to_numeric() {
+          Value::Integer(integer) => self.add_integer(integer),
+          Value::Real(real) => self.add_real(real),
+          _ => {}
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(match self.kind {
+      SumKind::Total => Value::Real(self.real_value()),
+      _ if self.count == 0 => Value::Null,
+      SumKind::Average => Value::Real(self.real_value() / self.count as f64),
+      SumKind::Sum if self.has_overflowed => {
+        return Err(SqliteError::Custom("integer overflow".into()))
+      }
+      SumKind::Sum if self.is_approximate => Value::Real(self.real_value()),
+      SumKind::Sum => Value::Integer(self.integer),
+    })
+  }
+}
+
+pub(super) fn sum() -> Box<dyn Accumulator> {
+  Box::new(Sum::new(SumKind::Sum))
+}
+
+pub(super) fn total() -> Box<dyn Accumulator> {
+  Box::new(Sum::new(SumKind::Total))
+}
+
+pub(super) fn avg() -> Box<dyn Accumulator> {
+  Box::new(Sum::new(SumKind::Average))
+}
+
+/// `min(X)` and `max(X)`. The first row holding the smallest or largest
+/// value wins.
+struct Extremum {
+  ordering: Ordering,
+  best: Value,
+  replaced: bool,
+}
+
+impl Accumulator for Extremum {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    self.replaced =
+      !args[0].is_null() && (self.best.is_null() || args[0].compare(&self.best) == self.ordering);
+    if self.replaced {
+      self.best = args[0].clone();
+    }
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(self.best.clone())
+  }
+
+  fn replaced(&self) -> bool {
+    self.replaced
+  }
+}
+
+pub(super) fn min() -> Box<dyn Accumulator> {
+  Box::new(Extremum {
+    ordering: Ordering::Less,
+    best: Value::Null,
+    replaced: false,
+  })
+}
+
+pub(super) fn max() -> Box<dyn Accumulator> {
+  Box::new(Extremum {
+    ordering: Ordering::Greater,
+    best: Value::Null,
+    replaced: false,
+  })
+}
+
+/// `group_concat(X, [Y])` and `string_agg(X, Y)`: the text of every `X`,
+/// each after the first preceded by the `Y` of its own row, `,` by default.
+#[derive(Default)]
+struct Concat(Option<String>);
+
+impl Accumulator for Concat {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    if args[0].is_null() {
+      return Ok(());
+    }
+    match &mut self.0 {
+      None => self.0 = Some(args[0].to_string()),
+      Some(text) => {
+        match args.get(1) {
+          None => text.push(','),
+          Some(Value::Null) => {}
+          Some(separator) => text.push_str(&separator.to_string()),
+        }
+        text.push_str(&args[0].to_string());
+      }
+    }
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(self.0.clone().map_or(Value::Null, Value::Text))
+  }
+}
+
+pub(super) fn group_concat() -> Box<dyn Accumulator> {
+  Box::<Concat>::default()
+}
@@ -0,0 +1,1024 @@
+//! # Date and time functions
+//!
+//!  Every function reads a time value, then applies its modifiers from left
+//! to right. A time value is one of:
+//!
+//! - **Text** such as `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS[.SSS]]` with a `T`
+//!   allowed in place of the space, or `HH:MM[:SS[.SSS]]` alone on
+//!   2000-01-01, each optionally followed by a time zone `[+-]HH:MM` or `Z`.
+//! - **`now`**, the time the statement started, the same for every call.
+//! - **A number**, a Julian day number unless a modifier says otherwise.
+//!
+//!  Times are held as the milliseconds since noon in Greenwich on November
+//! 24, 4714 B.C., with the calendar date and the time of day worked out from
+//! them on demand, and the other way round. Out of range times, unknown
+//! modifiers and malformed text give NULL.
+//!
+//!  `localtime` and `utc` convert with the offsets of the local time zone
+//! (see [`zone`]). Like SQLite, times outside of 1970 to 2037 use the
+//! offset of the same day of a year between 1997 and 2003.
+//!
+//! *Reference:* https://www.sqlite.org/lang_datefunc.html
+
+mod zone;
+
+use crate::{
+  result::SqliteResult,
+  runtime::eval::Environment,
+  sql::ast::Literal,
+  value::{parse_number, Value},
+};
+
+/// Milliseconds in a day.
+const DAY_MS: i64 = 86_400_000;
+
+/// The Julian day of the Unix epoch, in milliseconds.
+const UNIX_EPOCH_MS: i64 = 210_866_760_000_000;
+
+/// The last millisecond of 9999-12-31.
+const MAX_JULIAN_DAY_MS: i64 = 464_269_060_799_999;
+
+/// A time value being worked on. Either of the Julian day and the calendar
+/// date and time of day may be out of date, as their flags tell.
+#[derive(Clone, Copy, Default)]
+struct DateTime {
+  /// Milliseconds since the start of the Julian day 0.
+  jd: i64,
+  year: i32,
+  month: i32,
+  day: i32,
+  hour: i32,
+  minute: i32,
+  second: f64,
+  /// The offset of the time zone of the text, in minutes.
+  tz: i32,
+  valid_jd: bool,
+  valid_ymd: bool,
+  valid_hms: bool,
+  /// Whether `second` holds a number read as the time value, which is not
+  /// known to be a Julian day or a Unix time yet.
+  raw_s: bool,
+  is_error: bool,
+  use_subsec: bool,
+  is_utc: bool,
+  is_local: bool,
+  /// The days the `floor` modifier takes back when a day past the end of a
+  /// month rolled over into the next.
+  floor: i32,
+}
+
+impl DateTime {
+  fn error() -> Self {
+    Self {
+      is_error: true,
+      ..Self::default()
+    }
+  }
+
+  fn now(env: &dyn Environment) -> Self {
+    Self {
+      jd: env.statement_time() + UNIX_EPOCH_MS,
+      valid_jd: true,
+      is_utc: true,
+      ..Self::default()
+    }
+  }
+
+  /// A number read as the time value.
+  fn set_raw_number(&mut self, number: f64) {
+    self.second = number;
+    self.raw_s = true;
+    if (0.0..5_373_484.5).contains(&number) {
+      self.jd = (number * DAY_MS as f64 + 0.5) as i64;
+      self.valid_jd = true;
+    }
+  }
+
+  fn compute_jd(&mut self) {
+    if self.valid_jd {
+      return;
+    }
+    let (mut year, mut month, day) = match self.valid_ymd {
+      true => (self.year, self.month, self.day),
+      false => (2000, 1, 1),
+    };
+    if !(-4713..=9999).contains(&year) || self.raw_s {
+      *self = Self::error();
+      return;
+    }
+    if month <= 2 {
+      year -= 1;
+      month += 12;
+    }
+    let a = year / 100;
+    let b = 2 - a + a / 4;
+    let x1 = 36525 * (year + 4716) / 100;
+    let x2 = 306_001 * (month + 1) / 10000;
+    self.jd = ((f64::from(x1 + x2 + day + b) - 1524.5) * DAY_MS as f64) as i64;
+    self.valid_jd = true;
+    if self.valid_hms {
+      self.jd += i64::from(self.hour) * 3_600_000
+        + i64::from(self.minute) * 60_000
+        + (self.second * 1000.0 + 0.5) as i64;
+      if self.tz != 0 {
+        self.jd -= i64::from(self.tz) * 60_000;
+        self.valid_ymd = false;
+        self.valid_hms = false;
+        self.tz = 0;
+        self.is_utc = true;
+        self.is_local = false;
+      }
+    }
+  }
+
+  fn compute_ymd(&mut self) {
+    if self.valid_ymd {
+      return;
+    }
+    if !self.valid_jd {
+      self.year = 2000;
+      self.month = 1;
+      self.day = 1;
+    } else if !is_valid_julian_day(self.jd) {
+      *self = Self::error();
+      return;
+    } else {
+      let z = ((self.jd + DAY_MS / 2) / DAY_MS) as i32;
+      let alpha = ((f64::from(z) + 32044.75) / 36524.25) as i32 - 52;
+      let a = z + 1 + alpha - (alpha + 100) / 4 + 25;
+      let b = a + 1524;
+      let c = ((f64::from(b) - 122.1) / 365.25) as i32;
+      let d = (36525 * (c & 32767)) / 100;
+      let e = (f64::from(b - d) / 30.6001) as i32;
+      let x1 = (30.6001 * f64::from(e)) as i32;
+      self.day = b - d - x1;
+      self.month = if e < 14 { e - 1 } else { e - 13 };
+      self.year = if self.month > 2 { c - 4716 } else { c - 4715 };
+    }
+    self.valid_ymd = true;
+  }
+
+  fn compute_hms(&mut self) {
+    if self.valid_hms {
+      return;
+    }
+    self.compute_jd();
+    let day_ms = ((self.jd + DAY_MS / 2) % DAY_MS) as i32;
+    self.second = f64::from(day_ms % 60000) / 1000.0;
+    let day_minute = day_ms / 60000;
+    self.minute = day_minute % 60;
+    self.hour = day_minute / 60;
+    self.raw_s = false;
+    self.valid_hms = true;
+  }
+
+  fn compute_ymd_hms(&mut self) {
+    self.compute_ymd();
+    self.compute_hms();
+  }
+
+  fn clear_ymd_hms_tz(&mut self) {
+    self.valid_ymd = false;
+    self.valid_hms = false;
+    self.tz = 0;
+  }
+
+  /// Counts the days past the end of the month, such as 2 for February 30
+  /// of a leap year.
+  fn compute_floor(&mut self) {
+    self.floor = if self.day <= 28 || (1 << self.month) & 0x15aa != 0 {
+      0
+    } else if self.month != 2 {
+      i32::from(self.day == 31)
+    } else if self.year % 4 != 0 || (self.year % 100 == 0 && self.year % 400 != 0) {
+      self.day - 28
+    } else {
+      self.day - 29
+    };
+  }
+
+  /// Converts a time in UTC to local time.
+  fn convert_to_localtime(&mut self) {
+    self.compute_jd();
+    // Outside of what 32-bit time zone data covers, the offset is that of
+    // the same day of a year between 1997 and 2003, which starts on the
+    // same day of the week.
+    let (seconds, year_diff) = if !(UNIX_EPOCH_MS..=213_014_145_600_000).contains(&self.jd) {
+      let mut mapped = *self;
+      mapped.compute_ymd_hms();
+      let year_diff = 2000 + mapped.year % 4 - mapped.year;
+      mapped.year += year_diff;
+      mapped.valid_jd = false;
+      mapped.compute_jd();
+      ((mapped.jd - UNIX_EPOCH_MS) / 1000, year_diff)
+    } else {
+      ((self.jd - UNIX_EPOCH_MS) / 1000, 0)
+    };
+    let mut local = Self {
+      jd: (seconds + zone::utc_offset(seconds)) * 1000 + UNIX_EPOCH_MS,
+      valid_jd: true,
+      ..Self::default()
+    };
+    local.compute_ymd_hms();
+    *self = Self {
+      year: local.year - year_diff,
+      month: local.month,
+      day: local.day,
+      hour: local.hour,
+      minute: local.minute,
+      second: local.second.trunc() + (self.jd % 1000) as f64 * 0.001,
+      valid_ymd: true,
+      valid_hms: true,
+      valid_jd: false,
+      raw_s: false,
+      tz: 0,
+      is_error: false,
+      ..*self
+    };
+  }
+
+  /// Converts a time in local time to UTC, by guessing the UTC time and
+  /// correcting the guess with the error of its local time a few times.
+  fn convert_to_utc(&mut self) {
+    self.compute_jd();
+    let original = self.jd;
+    let mut guess = original;
+    let mut error = 0;
+    for _ in 0..4 {
+      guess -= error;
+      let mut local = Self {
+        jd: guess,
+        valid_jd: true,
+        ..Self::default()
+      };
+      local.convert_to_localtime();
+      local.compute_jd();
+      error = local.jd - original;
+      if error == 0 {
+        break;
+      }
+    }
+    *self = Self {
+      jd: guess,
+      valid_jd: true,
+      is_utc: true,
+      ..Self::default()
+    };
+  }
+
+  /// The days since the Monday on or before the date.
+  fn days_after_monday(&self) -> i32 {
+    (((self.jd + DAY_MS / 2) / DAY_MS) % 7) as i32
+  }
+
+  /// The days since the Sunday on or before the date.
+  fn days_after_sunday(&self) -> i32 {
+    (((self.jd + 3 * DAY_MS / 2) / DAY_MS) % 7) as i32
+  }
+
+  /// The days since January 1 of the year.
+  fn days_after_jan01(&self) -> i32 {
+    let mut jan01 = Self {
+      valid_jd: false,
+      month: 1,
+      day: 1,
+      ..*self
+    };
+    jan01.compute_jd();
+    ((self.jd - jan01.jd + DAY_MS / 2) / DAY_MS) as i32
+  }
+
+  /// The Thursday of the ISO 8601 week of the date, which is in the year
+  /// of that week.
+  fn iso_week_thursday(&self) -> Self {
+    let mut thursday = Self {
+      jd: self.jd + i64::from(3 - self.days_after_monday()) * DAY_MS,
+      valid_ymd: false,
+      ..*self
+    };
+    thursday.compute_ymd();
+    thursday
+  }
+}
+
+fn is_valid_julian_day(jd: i64) -> bool {
+  (0..=MAX_JULIAN_DAY_MS).contains(&jd)
+}
+
+fn is_space(byte: u8) -> bool {
+  matches!(byte, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+/// The byte at `index`, or 0 past the end, as in a C string.
+fn at(text: &[u8], index: usize) -> u8 {
+  text.get(index).copied().unwrap_or(0)
+}
+
+/// Reads fixed-width numbers separated by single characters. Each field of
+/// the format is 4 characters: the number of digits, the minimum value, the
+/// maximum value as a letter of `a` to `f`, and the separator that must
+/// follow, or nothing for the last field. Returns the values read before
+/// the first field that does not match.
+fn get_digits(text: &[u8], format: &str) -> Vec<i32> {
+  const MAX: [i32; 6] = [12, 14, 24, 31, 59, 14712];
+  let mut values = vec![];
+  let mut position = 0;
+  for field in format.as_bytes().chunks(4) {
+    let digits = usize::from(field[0] - b'0');
+    let min = i32::from(field[1] - b'0');
+    let max = MAX[usize::from(field[2] - b'a')];
+    let mut value = 0;
+    for _ in 0..digits {
+      let byte = at(text, position);
+      if !byte.is_ascii_digit() {
+        return values;
+      }
+      value = value * 10 + i32::from(byte - b'0');
+      position += 1;
+    }
+    if value < min || value > max {
+      return values;
+    }
+    if let Some(separator) = field.get(3) {
+      if at(text, position) != *separator {
+        return values;
+      }
+    }
+    values.push(value);
+    position += 1;
+  }
+  values
+}
+
+/// Parses `[+-]HH:MM` or `Z`, with whitespace around.
+fn parse_timezone(text: &[u8], time: &mut DateTime) -> Option<()> {
+  let mut position = text.iter().take_while(|byte| is_space(**byte)).count();
+  time.tz = 0;
+  let sign = match at(text, position) {
+    b'-' => -1,
+    b'+' => 1,
+    b'Z' | b'z' => {
+      time.is_local = false;
+      time.is_utc = true;
+      0
+    }
+    0 => return Some(()),
+    _ => return None,
+  };
+  position += 1;
+  if sign != 0 {
+    let [hours, minutes] = get_digits(&text[position..], "20b:20e")[..] else {
+      return None;
+    };
+    position += 5;
+    time.tz = sign * (minutes + hours * 60);
+  }
+  text[position.min(text.len())..]
+    .iter()
+    .all(|byte| is_space(*byte))
+    .then_some(())
+}
+
+/// Parses `HH:MM[:SS[.SSS]]` and a time zone.
+fn parse_hh_mm_ss(text: &[u8], time: &mut DateTime) -> Option<()> {
+  let [hour, minute] = get_digits(text, "20c:20e")[..] else {
+    return None;
+  };
+  let mut position = 5;
+  let mut second = 0.0;
+  if at(text, position) == b':' {
+    position += 1;
+    let [whole] = get_digits(&text[position..], "20e")[..] else {
+      return None;
+    };
+    position += 2;
+    second = f64::from(whole);
+    if at(text, position) == b'.' && at(text, position + 1).is_ascii_digit() {
+      position += 1;
+      let mut fraction = 0.0;
+      let mut scale = 1.0;
+      while at(text, position).is_ascii_digit() {
+        fraction = fraction * 10.0 + f64::from(at(text, position) - b'0');
+        scale *= 10.0;
+        position += 1;
+      }
+      // Truncated rather than rounded up to the next second.
+      second += (fraction / scale).min(0.999);
+    }
+  }
+  time.valid_jd = false;
+  time.raw_s = false;
+  time.valid_hms = true;
+  time.hour = hour;
+  time.minute = minute;
+  time.second = second;
+  parse_timezone(&text[position.min(text.len())..], time)
+}
+
+/// Parses `[-]YYYY-MM-DD`, optionally followed by a time.
+fn parse_yyyy_mm_dd(text: &[u8], time: &mut DateTime) -> Option<()> {
+  let (is_negative, text) = match text.first() {
+    Some(b'-') => (true, &text[1..]),
+    _ => (false, text),
+  };
+  let [year, month, day] = get_digits(text, "40f-21a-21d")[..] else {
+    return None;
+  };
+  let rest = &text[10.min(text.len())..];
+  let rest = &rest[rest
+    .iter()
+    .take_while(|byte| is_space(**byte) || **byte == b'T')
+    .count()..];
+  if parse_hh_mm_ss(rest, time).is_none() {
+    if !rest.is_empty() {
+      return None;
+    }
+    time.valid_hms = false;
+  }
+  time.valid_jd = false;
+  time.valid_ymd = true;
+  time.year = if is_negative { -year } else { year };
+  time.month = month;
+  time.day = day;
+  time.compute_floor();
+  if time.tz != 0 {
+    time.compute_jd();
+  }
+  Some(())
+}
+
+fn parse_date_or_time(env: &dyn Environment, text: &str, time: &mut DateTime) -> Option<()> {
+  let bytes = text.as_bytes();
+  if parse_yyyy_mm_dd(bytes, time).is_some() || parse_hh_mm_ss(bytes, time).is_some() {
+    return Some(());
+  }
+  if text.eq_ignore_ascii_case("now") {
+    *time = DateTime::now(env);
+  } else if let Some(number) = parse_number(text) {
+    time.set_raw_number(number);
+  } else if text.eq_ignore_ascii_case("subsec") || text.eq_ignore_ascii_case("subsecond") {
+    *time = DateTime {
+      use_subsec: true,
+      ..DateTime::now(env)
+    };
+  } else {
+    return None;
+  }
+  Some(())
+}
+
+/// The units of `+NNN units` modifiers: their name, the largest number of
+/// them that is accepted, and their length in seconds.
+const UNITS: [(&str, f32, f64); 6] = [
+  ("second", 4.6427e14, 1.0),
+  ("minute", 7.7379e12, 60.0),
+  ("hour", 1.2897e11, 3600.0),
+  ("day", 5_373_485.0, 86400.0),
+  ("month", 176_546.0, 2_592_000.0),
+  ("year", 14713.0, 31_536_000.0),
+];
+
+/// Applies the modifier at `index` of the arguments, starting at 1.
+fn apply_modifier(time: &mut DateTime, modifier: &str, index: usize) -> Option<()> {
+  let text = modifier.as_bytes();
+  match at(text, 0).to_ascii_lowercase() {
+    b'a' if modifier.eq_ignore_ascii_case("auto") && index == 1 => {
+      if !time.raw_s || time.valid_jd {
+        time.raw_s = false;
+      } else if (-210_866_760_000.0..=253_402_300_799.0).contains(&time.second) {
+        let jd = time.second * 1000.0 + UNIX_EPOCH_MS as f64;
+        time.clear_ymd_hms_tz();
+        time.jd = (jd + 0.5) as i64;
+        time.valid_jd = true;
+        time.raw_s = false;
+      }
+      Some(())
+    }
+    b'c' if modifier.eq_ignore_ascii_case("ceiling") => {
+      time.compute_jd();
+      time.clear_ymd_hms_tz();
+      time.floor = 0;
+      Some(())
+    }
+    b'f' if modifier.eq_ignore_ascii_case("floor") => {
+      time.compute_jd();
+      time.jd -= i64::from(time.floor) * DAY_MS;
+      time.clear_ymd_hms_tz();
+      Some(())
+    }
+    b'j' if modifier.eq_ignore_ascii_case("julianday") && index == 1 => {
+      (time.valid_jd && time.raw_s).then(|| time.raw_s = false)
+    }
+    b'l' if modifier.eq_ignore_ascii_case("localtime") => {
+      if !time.is_local {
+        time.convert_to_localtime();
+      }
+      time.is_utc = false;
+      time.is_local = true;
+      Some(())
+    }
+    b'u' if modifier.eq_ignore_ascii_case("unixepoch") => {
+      if !time.raw_s || index > 1 {
+        return None;
+      }
+      let jd = time.second * 1000.0 + UNIX_EPOCH_MS as f64;
+      if !(0.0..464_269_060_800_000.0).contains(&jd) {
+        return None;
+      }
+      time.clear_ymd_hms_tz();
+      time.jd = (jd + 0.5) as i64;
+      time.valid_jd = true;
+      time.raw_s = false;
+      Some(())
+    }
+    b'u' if modifier.eq_ignore_ascii_case("utc") => {
+      if !time.is_utc {
+        time.convert_to_utc();
+      }
+      Some(())
+    }
+    b'w' => {
+      let weekday = modifier
+        .get(..8)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("weekday "))
+        .and_then(|_| parse_number(&modifier[8..]))
+        .filter(|weekday| (0.0..7.0).contains(weekday) && weekday.fract() == 0.0)?
+        as i64;
+      time.compute_ymd_hms();
+      time.tz = 0;
+      time.valid_jd = false;
+      time.compute_jd();
+      let mut day = ((time.jd + 3 * DAY_MS / 2) / DAY_MS) % 7;
+      if day > weekday {
+        day -= 7;
+      }
+      time.jd += (weekday - day) * DAY_MS;
+      time.clear_ymd_hms_tz();
+      Some(())
+    }
+    b's'
+      if modifier.eq_ignore_ascii_case("subsec") || modifier.eq_ignore_ascii_case("subsecond") =>
+    {
+      time.use_subsec = true;
+      Some(())
+    }
+    b's' => {
+      let unit = modifier
+        .get(..9)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("start of "))
+        .map(|_| &modifier[9..])?;
+      if !time.valid_jd && !time.valid_ymd && !time.valid_hms {
+        return None;
+      }
+      time.compute_ymd();
+      time.valid_hms = true;
+      time.hour = 0;
+      time.minute = 0;
+      time.second = 0.0;
+      time.raw_s = false;
+      time.tz = 0;
+      time.valid_jd = false;
+      if unit.eq_ignore_ascii_case("month") {
+        time.day = 1;
+      } else if unit.eq_ignore_ascii_case("year") {
+        time.month = 1;
+        time.day = 1;
+      } else if !unit.eq_ignore_ascii_case("day") {
+        return None;
+      }
+      Some(())
+    }
+    b'+' | b'-' | b'0'..=b'9' => apply_offset(time, modifier),
+    _ => None,
+  }
+}
+
+/// Applies `+NNN units`, `±HH:MM[:SS[.SSS]]` or `±YYYY-MM-DD[ HH:MM...]`.
+fn apply_offset(time: &mut DateTime, modifier: &str) -> Option<()> {
+  let text = modifier.as_bytes();
+  let sign = text[0];
+  let mut length = 1;
+  while let Some(&byte) = text.get(length) {
+    if byte == b':' || is_space(byte) {
+      break;
+    }
+    if byte == b'-'
+      && ((length == 5 && get_digits(&text[1..], "40f").len() == 1)
+        || (length == 6 && get_digits(&text[1..], "50f").len() == 1))
+    {
+      break;
+    }
+    length += 1;
+  }
+  let number = parse_number(modifier.get(..length)?)?;
+
+  let mut clock = text;
+  let mut clock_at = length;
+  if at(text, length) == b'-' {
+    // `±YYYY-MM-DD` adds or subtracts years, months of 0 to 11 and days of
+    // 0 to 30.
+    if sign != b'+' && sign != b'-' {
+      return None;
+    }
+    let format = match length {
+      5 => "40f-20a-20d",
+      _ => "50f-20a-20d",
+    };
+    let [years, months, mut days] = get_digits(&text[1..], format)[..] else {
+      return None;
+    };
+    // Past the year, a 5-digit one reads as if it had 4.
+    let text = &text[length - 5..];
+    if months >= 12 || days >= 31 {
+      return None;
+    }
+    time.compute_ymd_hms();
+    time.valid_jd = false;
+    if sign == b'-' {
+      time.year -= years;
+      time.month -= months;
+      days = -days;
+    } else {
+      time.year += years;
+      time.month += months;
+    }
+    normalize_month(time);
+    time.compute_floor();
+    time.compute_jd();
+    time.valid_hms = false;
+    time.valid_ymd = false;
+    time.jd += i64::from(days) * DAY_MS;
+    match at(text, 11) {
+      0 => return Some(()),
+      byte if is_space(byte) && get_digits(&text[12..], "20c:20e").len() == 2 => {
+        clock = &text[12..];
+        clock_at = 2;
+      }
+      _ => return None,
+    }
+  }
+
+  if at(clock, clock_at) == b':' {
+    // `±HH:MM[:SS[.SSS]]` adds or subtracts a time of day.
+    let clock = match clock[0].is_ascii_digit() {
+      true => clock,
+      false => &clock[1..],
+    };
+    let mut offset = DateTime::default();
+    parse_hh_mm_ss(clock, &mut offset)?;
+    offset.compute_jd();
+    offset.jd -= DAY_MS / 2;
+    offset.jd -= offset.jd / DAY_MS * DAY_MS;
+    if sign == b'-' {
+      offset.jd = -offset.jd;
+    }
+    time.compute_jd();
+    time.clear_ymd_hms_tz();
+    time.jd += offset.jd;
+    return Some(());
+  }
+
+  let unit = modifier[length..].trim_start_matches(|c: char| c.is_ascii() && is_space(c as u8));
+  if unit.len() < 3 || unit.len() > 10 {
+    return None;
+  }
+  let unit = match unit.as_bytes()[unit.len() - 1].to_ascii_lowercase() {
+    b's' => &unit[..unit.len() - 1],
+    _ => unit,
+  };
+  time.compute_jd();
+  let rounder = if number < 0.0 { -0.5 } else { 0.5 };
+  time.floor = 0;
+  let found = UNITS.iter().find(|(name, limit, _)| {
+    name.eq_ignore_ascii_case(unit) && number > -f64::from(*limit) && number < f64::from(*limit)
+  });
+  let result = found.map(|(name, _, seconds)| {
+    let mut number = number;
+    if *name == "month" || *name == "year" {
+      // Whole months and years move the calendar date, and what is left
+      // counts as 30 or 365 days.
+      time.compute_ymd_hms();
+      match *name {
+        "month" => time.month += number as i32,
+        _ => time.year += number as i32,
+      }
+      normalize_month(time);
+      time.compute_floor();
+      time.valid_jd = false;
+      number -= f64::from(number as i32);
+    }
+    time.compute_jd();
+    time.jd += (number * 1000.0 * seconds + rounder) as i64;
+  });
+  time.clear_ymd_hms_tz();
+  result
+}
+
+/// Brings the month back to 1 to 12, carrying into the year.
+fn normalize_month(time: &mut DateTime) {
+  let years = match time.month > 0 {
+    true => (time.month - 1) / 12,
+    false => (time.month - 12) / 12,
+  };
+  time.year += years;
+  time.month -= years * 12;
+}
+
+/// The text of an argument, up to any NUL, or `None` for NULL.
+fn text_of(value: &Value) -> Option<String> {
+  let text = match value {
+    Value::Null => return None,
+    Value::Text(text) => text.clone(),
+    Value::Blob(blob) => String::from_utf8_lossy(blob).into_owned(),
+    value => value.to_string(),
+  };
+  Some(match text.find('\0') {
+    Some(end) => text[..end].to_string(),
+    None => text,
+  })
+}
+
+/// Reads a time value and applies its modifiers, or `None` when the result
+/// is NULL. Without arguments, the time value is `now`.
+fn read_time(env: &dyn Environment, args: &[Value]) -> Option<DateTime> {
+  let mut time = DateTime::default();
+  match args.first() {
+    None => time = DateTime::now(env),
+    Some(Value::Integer(integer)) => time.set_raw_number(*integer as f64),
+    Some(Value::Real(real)) => time.set_raw_number(*real),
+    Some(value) => parse_date_or_time(env, &text_of(value)?, &mut time)?,
+  }
+  for (index, modifier) in args.iter().enumerate().skip(1) {
+    apply_modifier(&mut time, &text_of(modifier)?, index)?;
+  }
+  time.compute_jd();
+  if time.is_error || !is_valid_julian_day(time.jd) {
+    return None;
+  }
+  if args.len() == 1 && time.valid_ymd && time.day > 28 {
+    // A day past the end of its month rolls over into the next.
+    time.valid_ymd = false;
+  }
+  Some(time)
+}
+
+fn format_date(time: &DateTime) -> String {
+  let sign = if time.year < 0 { "-" } else { "" };
+  format!(
+    "{sign}{:04}-{:02}-{:02}",
+    time.year.abs() % 10000,
+    time.month,
+    time.day
+  )
+}
+
+fn format_time(time: &DateTime) -> String {
+  match time.use_subsec {
+    true => {
+      let millis = (1000.0 * time.second + 0.5) as i32;
+      format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        time.hour,
+        time.minute,
+        millis / 1000 % 100,
+        millis % 1000
+      )
+    }
+    false => format!(
+      "{:02}:{:02}:{:02}",
+      time.hour, time.minute, time.second as i32
+    ),
+  }
+}
+
+/// `date(time-value, modifier, ...)`: `YYYY-MM-DD`.
+pub(super) fn date(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::from(read_time(env, args).map(|mut time| {
+    time.compute_ymd();
+    format_date(&time)
+  })))
+}
+
+/// `time(time-value, modifier, ...)`: `HH:MM:SS`, or `HH:MM:SS.SSS` with
+/// `subsec`.
+pub(super) fn time(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::from(read_time(env, args).map(|mut time| {
+    time.compute_hms();
+    format_time(&time)
+  })))
+}
+
+/// `datetime(time-value, modifier, ...)`: `YYYY-MM-DD HH:MM:SS`.
+pub(super) fn datetime(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::from(read_time(env, args).map(|mut time| {
+    time.compute_ymd_hms();
+    format!("{} {}", format_date(&time), format_time(&time))
+  })))
+}
+
+/// `julianday(time-value, modifier, ...)`: the fractional Julian day.
+pub(super) fn julianday(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::from(
+    read_time(env, args).map(|time| time.jd as f64 / DAY_MS as f64),
+  ))
+}
+
+/// `unixepoch(time-value, modifier, ...)`: the seconds since 1970-01-01, an
+/// INTEGER, or a REAL with `subsec`.
+pub(super) fn unixepoch(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match read_time(env, args) {
+    Some(time) if time.use_subsec => Value::Real((time.jd - UNIX_EPOCH_MS) as f64 / 1000.0),
+    Some(time) => Value::Integer(time.jd / 1000 - UNIX_EPOCH_MS / 1000),
+    None => Value::Null,
+  })
+}
+
+/// `strftime(format, time-value, modifier, ...)`: the time formatted with
+/// the conversions `%d %e %f %F %G %g %H %I %j %J %k %l %m %M %p %P %R %s
+/// %S %T %u %U %V %w %W %Y %%`. Any other conversion gives NULL.
+pub(super) fn strftime(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let Some(format) = args.first().and_then(text_of) else {
+    return Ok(Value::Null);
+  };
+  let Some(mut time) = read_time(env, &args[1..]) else {
+    return Ok(Value::Null);
+  };
+  time.compute_ymd_hms();
+  let mut result = String::new();
+  let mut chars = format.chars();
+  while let Some(c) = chars.next() {
+    if c != '%' {
+      result.push(c);
+      continue;
+    }
+    let hour12 = match time.hour {
+      0 => 12,
+      hour if hour > 12 => hour - 12,
+      hour => hour,
+    };
+    let conversion = match chars.next() {
+      Some('d') => format!("{:02}", time.day),
+      Some('e') => format!("{:2}", time.day),
+      Some('f') => format!("{:06.3}", time.second.min(59.999)),
+      Some('F') => format!(
+        "{}-{:02}-{:02}",
+        c_padded(time.year, 4),
+        time.month,
+        time.day
+      ),
+      Some('G') => c_padded(time.iso_week_thursday().year, 4),
+      Some('g') => c_padded(time.iso_week_thursday().year % 100, 2),
+      Some('H') => format!("{:02}", time.hour),
+      Some('k') => format!("{:2}", time.hour),
+      Some('I') => format!("{hour12:02}"),
+      Some('l') => format!("{hour12:2}"),
+      Some('j') => format!("{:03}", time.days_after_jan01() + 1),
+      Some('J') => format_g16(time.jd as f64 / DAY_MS as f64),
+      Some('m') => format!("{:02}", time.month),
+      Some('M') => format!("{:02}", time.minute),
+      Some('p') => (if time.hour >= 12 { "PM" } else { "AM" }).into(),
+      Some('P') => (if time.hour >= 12 { "pm" } else { "am" }).into(),
+      Some('R') => format!("{:02}:{:02}", time.hour, time.minute),
+      Some('s') if time.use_subsec => {
+        format!("{:.3}", (time.jd - UNIX_EPOCH_MS) as f64 / 1000.0)
+      }
+      Some('s') => (time.jd / 1000 - UNIX_EPOCH_MS / 1000).to_string(),
+      Some('S') => format!("{:02}", time.second as i32),
+      Some('T') => format!(
+        "{:02}:{:02}:{:02}",
+        time.hour, time.minute, time.second as i32
+      ),
+      Some('u') => match time.days_after_sunday() {
+        0 => "7".into(),
+        day => day.to_string(),
+      },
+      Some('w') => time.days_after_sunday().to_string(),
+      Some('U') => format!(
+        "{:02}",
+        (time.days_after_jan01() - time.days_after_sunday() + 7) / 7
+      ),
+      Some('V') => format!("{:02}", time.iso_week_thursday().days_after_jan01() / 7 + 1),
+      Some('W') => format!(
+        "{:02}",
+        (time.days_after_jan01() - time.days_after_monday() + 7) / 7
+      ),
+      Some('Y') => c_padded(time.year, 4),
+      Some('%') => "%".into(),
+      _ => return Ok(Value::Null),
+    };
+    result.push_str(&conversion);
+  }
+  Ok(Value::Text(result))
+}
+
+/// `timediff(A, B)`: the time to add to `B` to reach `A`, as
+/// `±YYYY-MM-DD HH:MM:SS.SSS`.
+pub(super) fn timediff(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let (Some(mut from), Some(mut to)) = (read_time(env, &args[..1]), read_time(env, &args[1..]))
+  else {
+    return Ok(Value::Null);
+  };
+  from.compute_ymd_hms();
+  to.compute_ymd_hms();
+  let is_forward = from.jd >= to.jd;
+  let mut years = match is_forward {
+    true => from.year - to.year,
+    false => to.year - from.year,
+  };
+  if years != 0 {
+    to.year = from.year;
+    to.valid_jd = false;
+    to.compute_jd();
+  }
+  let mut months = match is_forward {
+    true => from.month - to.month,
+    false => to.month - from.month,
+  };
+  if months < 0 {
+    years -= 1;
+    months += 12;
+  }
+  if months != 0 {
+    to.month = from.month;
+    to.valid_jd = false;
+    to.compute_jd();
+  }
+  // Step `to` back, or forward, a month at a time until it is no longer
+  // past `from`.
+  while (is_forward && from.jd < to.jd) || (!is_forward && from.jd > to.jd) {
+    months -= 1;
+    if months < 0 {
+      months = 11;
+      years -= 1;
+    }
+    if is_forward {
+      to.month -= 1;
+      if to.month < 1 {
+        to.month = 12;
+        to.year -= 1;
+      }
+    } else {
+      to.month += 1;
+      if to.month > 12 {
+        to.month = 1;
+        to.year += 1;
+      }
+    }
+    to.valid_jd = false;
+    to.compute_jd();
+  }
+  let mut rest = DateTime {
+    jd: (from.jd - to.jd).abs() + 148_699_540_800_000,
+    valid_jd: true,
+    ..DateTime::default()
+  };
+  rest.compute_ymd_hms();
+  Ok(Value::Text(format!(
+    "{}{}-{:02}-{:02} {:02}:{:02}:{:06.3}",
+    if is_forward { '+' } else { '-' },
+    c_padded(years, 4),
+    months,
+    rest.day - 1,
+    rest.hour,
+    rest.minute,
+    rest.second
+  )))
+}
+
+/// The value of `CURRENT_DATE`, `CURRENT_TIME` or `CURRENT_TIMESTAMP`.
+pub(crate) fn current_date_time(env: &dyn Environment, literal: &Literal) -> SqliteResult<Value> {
+  match literal {
+    Literal::CurrentDate => date(env, &[]),
+    Literal::CurrentTime => time(env, &[]),
+    _ => datetime(env, &[]),
+  }
+}
+
+/// Formats an integer like the C format `%0Nd`, where the sign counts
+/// towards the width.
+fn c_padded(value: i32, width: usize) -> String {
+  match value < 0 {
+    true => format!("-{:0width$}", -value, width = width.saturating_sub(1)),
+    false => format!("{value:0width$}"),
+  }
+}
+
+/// Formats a REAL like the C format `%.16g`.
+fn format_g16(value: f64) -> String {
+  let scientific = format!("{value:.15e}");
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+  let exponent: i32 = exponent.parse().unwrap_or_default();
+  let trim = |number: String| match number.contains('.') {
+    true => number
+      .trim_end_matches('0')
+      .trim_end_matches('.')
+      .to_string(),
+    false => number,
+  };
+  if !(-4..16).contains(&exponent) {
+    let sign = if exponent < 0 { '-' } else { '+' };
+    return format!("{}e{sign}{:02}", trim(mantissa.to_string()), exponent.abs());
+  }
+  let precision = (15 - exponent) as usize;
+  trim(format!("{value:.precision$}"))
+}
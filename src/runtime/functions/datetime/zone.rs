@@ -0,0 +1,374 @@
+//! # Local time zone
+//!
+//!  The local time zone is the one the `TZ` environment variable names, as
+//! a file of the time zone database, such as `Europe/Lisbon`, or otherwise
+//! as a POSIX rule, such as `EST5EDT,M3.2.0,M11.1.0`. Without `TZ`, it is
+//! read from `/etc/localtime`. A zone that cannot be read falls back to the
+//! fixed offset of its rule, or to UTC.
+//!
+//! *Reference:* https://www.rfc-editor.org/rfc/rfc8536
+//! and https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/V1_chap08.html
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::OnceLock;
+
+/// Where the time zone database lives on most systems.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// The offset of local time from UTC, in seconds east, at a Unix time.
+pub(super) fn utc_offset(time: i64) -> i64 {
+  static LOCAL: OnceLock<Zone> = OnceLock::new();
+  LOCAL.get_or_init(Zone::local).offset_at(time)
+}
+
+/// The offsets of a time zone over time.
+#[derive(Default)]
+struct Zone {
+  /// The Unix times at which the offset changes, in order.
+  transitions: Vec<i64>,
+  /// The offset from each transition on.
+  offsets: Vec<i64>,
+  /// The offset before the first transition.
+  initial: i64,
+  /// The rule for the times past the last transition.
+  rule: Option<Rule>,
+}
+
+impl Zone {
+  fn local() -> Self {
+    Self::named(std::env::var("TZ").ok().as_deref())
+  }
+
+  /// The zone a value of `TZ` names, or that of the system without one.
+  fn named(tz: Option<&str>) -> Self {
+    let name = match tz {
+      None => return Self::read("/etc/localtime").unwrap_or_default(),
+      Some(name) => name.strip_prefix(':').unwrap_or(name),
+    };
+    if name.is_empty() {
+      return Self::default();
+    }
+    let path = match name.starts_with('/') {
+      true => name.to_string(),
+      false => format!("{ZONEINFO}/{name}"),
+    };
+    if !name.contains("..") {
+      if let Some(zone) = Self::read(&path) {
+        return zone;
+      }
+    }
+    Self {
+      rule: Rule::parse(name),
+      ..Self::default()
+    }
+  }
+
+  fn read(path: &str) -> Option<Self> {
+    Self::parse(&std::fs::read(path).ok()?)
+  }
+
+  /// Parses a TZif file, from its 64-bit data when it has some.
+  fn parse(bytes: &[u8]) -> Option<Self> {
+    let header = |at: usize| -> Option<(u8, [usize; 6])> {
+      if bytes.get(at..at + 4)? != b"TZif" {
+        return None;
+      }
+      let mut counts = [0; 6];
+      for (index, count) in counts.iter_mut().enumerate() {
+        let start = at + 20 + index * 4;
+        *count = u32::from_be_bytes(bytes.get(start..start + 4)?.try_into().ok()?) as usize;
+      }
+      Some((*bytes.get(at + 4)?, counts))
+    };
+    // The data is `timecnt` transition times, `timecnt` type indexes,
+    // `typecnt` types, `charcnt` bytes of abbreviations, `leapcnt` leap
+    // seconds and `isstdcnt` and `isutcnt` indicators.
+    let length = |[isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt]: [usize; 6],
+                  time_size: usize| {
+      timecnt * (time_size + 1)
+        + typecnt * 6
+        + charcnt
+        + leapcnt * (time_size + 4)
+        + isstdcnt
+        + isutcnt
+    };
+
+    let (version, mut counts) = header(0)?;
+    let mut at = 44;
+    let mut time_size = 4;
+    if version >= b'2' {
+      at += length(counts, 4);
+      counts = header(at)?.1;
+      at += 44;
+      time_size = 8;
+    }
+    let [_, _, _, timecnt, typecnt, _] = counts;
+    let data = bytes.get(at..at + length(counts, time_size))?;
+    let offsets_of_types: Vec<i64> = data[timecnt * (time_size + 1)..]
+      .chunks(6)
+      .take(typecnt)
+      .map(|ttinfo| {
+        i64::from(i32::from_be_bytes([
+          ttinfo[0], ttinfo[1], ttinfo[2], ttinfo[3],
+        ]))
+      })
+      .collect();
+    let transitions = data[..timecnt * time_size]
+      .chunks(time_size)
+      .map(|time| match time_size {
+        4 => i64::from(i32::from_be_bytes([time[0], time[1], time[2], time[3]])),
+        _ => i64::from_be_bytes([
+          time[0], time[1], time[2], time[3], time[4], time[5], time[6], time[7],
+        ]),
+      })
+      .collect();
+    let offsets = data[timecnt * time_size..timecnt * (time_size + 1)]
+      .iter()
+      .map(|index| offsets_of_types.get(usize::from(*index)).copied())
+      .collect::<Option<_>>()?;
+    // A version 2 file ends with the rule for later times between newlines.
+    let footer = bytes.get(at + data.len()..).unwrap_or_default();
+    let rule = match footer.split(|byte| *byte == b'\n').nth(1) {
+      Some(rule) if version >= b'2' => Rule::parse(core::str::from_utf8(rule).ok()?),
+      _ => None,
+    };
+    Some(Self {
+      transitions,
+      offsets,
+      initial: offsets_of_types.first().copied().unwrap_or_default(),
+      rule,
+    })
+  }
+
+  fn offset_at(&self, time: i64) -> i64 {
+    let count = self
+      .transitions
+      .partition_point(|transition| *transition <= time);
+    match (count.checked_sub(1), &self.rule) {
+      (Some(last), Some(rule)) if last + 1 == self.transitions.len() => rule.offset_at(time),
+      (Some(last), _) => self.offsets[last],
+      (None, Some(rule)) if self.transitions.is_empty() => rule.offset_at(time),
+      (None, _) => self.initial,
+    }
+  }
+}
+
+/// A POSIX time zone rule: a standard offset, and optionally a daylight
+/// saving offset with the dates it starts and ends on.
+struct Rule {
+  /// Seconds east of UTC.
+  standard: i64,
+  daylight: Option<Daylight>,
+}
+
+struct Daylight {
+  offset: i64,
+  /// The date and the local time, in standard time, it starts at.
+  start: (RuleDate, i64),
+  /// The date and the local time, in daylight saving time, it ends at.
+  end: (RuleDate, i64),
+}
+
+#[derive(Clone, Copy)]
+enum RuleDate {
+  /// `Jn`: the day of the year from 1, never counting February 29.
+  Julian(i64),
+  /// `n`: the day of the year from 0, counting February 29.
+  Day(i64),
+  /// `Mm.w.d`: the day `d` of the week, 0 for Sunday, of the week `w` of
+  /// the month `m`, 5 meaning the last one.
+  MonthWeekDay(i64, i64, i64),
+}
+
+impl Rule {
+  fn parse(text: &str) -> Option<Self> {
+    let mut parser = RuleParser { text, at: 0 };
+    parser.name()?;
+    let standard = -parser.offset()?;
+    if parser.at == text.len() {
+      return Some(Self {
+        standard,
+        daylight: None,
+      });
+    }
+    parser.name()?;
+    let offset = match parser.peek() {
+      Some(b',') | None => standard + 3600,
+      Some(_) => -parser.offset()?,
+    };
+    let (start, end) = match parser.peek() {
+      // The rule of the United States when none is given.
+      None => (
+        (RuleDate::MonthWeekDay(3, 2, 0), 7200),
+        (RuleDate::MonthWeekDay(11, 1, 0), 7200),
+      ),
+      Some(_) => {
+        parser.expect(b',')?;
+        let start = parser.transition()?;
+        parser.expect(b',')?;
+        (start, parser.transition()?)
+      }
+    };
+    (parser.at == text.len()).then_some(Self {
+      standard,
+      daylight: Some(Daylight { offset, start, end }),
+    })
+  }
+
+  fn offset_at(&self, time: i64) -> i64 {
+    let Some(daylight) = &self.daylight else {
+      return self.standard;
+    };
+    let (year, ..) = civil_from_days((time + self.standard).div_euclid(86400));
+    let start = daylight.start.0.day_of(year) * 86400 + daylight.start.1 - self.standard;
+    let end = daylight.end.0.day_of(year) * 86400 + daylight.end.1 - daylight.offset;
+    let is_daylight = match start < end {
+      true => start <= time && time < end,
+      // In the southern hemisphere, daylight saving time spans new year.
+      false => !(end <= time && time < start),
+    };
+    match is_daylight {
+      true => daylight.offset,
+      false => self.standard,
+    }
+  }
+}
+
+impl RuleDate {
+  /// The day of the date in a year, as days since the Unix epoch.
+  fn day_of(self, year: i64) -> i64 {
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let january_1 = days_from_civil(year, 1, 1);
+    match self {
+      Self::Julian(day) => january_1 + day - 1 + i64::from(is_leap && day >= 60),
+      Self::Day(day) => january_1 + day,
+      Self::MonthWeekDay(month, week, weekday) => {
+        let first = days_from_civil(year, month, 1);
+        let first_weekday = (first + 4).rem_euclid(7);
+        let mut day = first + (weekday - first_weekday).rem_euclid(7) + (week - 1) * 7;
+        let next_month = match month {
+          12 => days_from_civil(year + 1, 1, 1),
+          _ => days_from_civil(year, month + 1, 1),
+        };
+        while day >= next_month {
+          day -= 7;
+        }
+        day
+      }
+    }
+  }
+}
+
+struct RuleParser<'a> {
+  text: &'a str,
+  at: usize,
+}
+
+impl RuleParser<'_> {
+  fn peek(&self) -> Option<u8> {
+    self.text.as_bytes().get(self.at).copied()
+  }
+
+  fn expect(&mut self, byte: u8) -> Option<()> {
+    (self.peek() == Some(byte)).then(|| self.at += 1)
+  }
+
+  /// A zone name of 3 letters or more, or any name between `<` and `>`.
+  fn name(&mut self) -> Option<()> {
+    let start = self.at;
+    if self.expect(b'<').is_some() {
+      while self.peek()? != b'>' {
+        self.at += 1;
+      }
+      self.at += 1;
+      return Some(());
+    }
+    while self.peek().is_some_and(|byte| byte.is_ascii_alphabetic()) {
+      self.at += 1;
+    }
+    (self.at - start >= 3).then_some(())
+  }
+
+  fn number(&mut self) -> Option<i64> {
+    let start = self.at;
+    while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+      self.at += 1;
+    }
+    self.text.get(start..self.at)?.parse().ok()
+  }
+
+  /// `[+-]hh[:mm[:ss]]`, in seconds.
+  fn offset(&mut self) -> Option<i64> {
+    let sign = match self.peek() {
+      Some(b'-') => -1,
+      Some(b'+') => 1,
+      _ => 1,
+    };
+    if matches!(self.peek(), Some(b'+' | b'-')) {
+      self.at += 1;
+    }
+    let mut seconds = self.number()? * 3600;
+    for unit in [60, 1] {
+      if self.expect(b':').is_none() {
+        break;
+      }
+      seconds += self.number()? * unit;
+    }
+    Some(sign * seconds)
+  }
+
+  /// `date[/time]`, the time being 02:00 by default.
+  fn transition(&mut self) -> Option<(RuleDate, i64)> {
+    let date = match self.peek()? {
+      b'J' => {
+        self.at += 1;
+        RuleDate::Julian(self.number()?)
+      }
+      b'M' => {
+        self.at += 1;
+        let month = self.number()?;
+        self.expect(b'.')?;
+        let week = self.number()?;
+        self.expect(b'.')?;
+        RuleDate::MonthWeekDay(month, week, self.number()?)
+      }
+      _ => RuleDate::Day(self.number()?),
+    };
+    let time = match self.expect(b'/') {
+      Some(()) => self.offset()?,
+      None => 7200,
+    };
+    Some((date, time))
+  }
+}
+
+/// The days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+/// The date of a number of days since 1970-01-01, as year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days - era * 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+  let month = if month_index < 10 {
+    month_index + 3
+  } else {
+    month_index - 9
+  };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
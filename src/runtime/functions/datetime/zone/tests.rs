@@ -0,0 +1,181 @@
+//! Tests for the local time zone
+//!
+//! To run: `cargo test datetime::zone`
+
+use super::{days_from_civil, Rule, RuleDate, Zone};
+
+/// A TZif file of `version` with the given transitions, each to the offset
+/// of a type, and a footer rule for a version 2 file.
+fn tzif(version: u8, transitions: &[(i64, u8)], offsets: &[i32], footer: &str) -> Vec<u8> {
+  let header = |time_size: usize, bytes: &mut Vec<u8>| {
+    bytes.extend(b"TZif");
+    bytes.push(version);
+    bytes.extend([0; 15]);
+    let (timecnt, typecnt) = match time_size {
+      0 => (0, 1),
+      _ => (transitions.len(), offsets.len()),
+    };
+    for count in [0, 0, 0, timecnt, typecnt, 4 * typecnt] {
+      bytes.extend((count as u32).to_be_bytes());
+    }
+  };
+  let data = |time_size: usize, bytes: &mut Vec<u8>| {
+    for (time, _) in transitions {
+      match time_size {
+        4 => bytes.extend((*time as i32).to_be_bytes()),
+        _ => bytes.extend(time.to_be_bytes()),
+      }
+    }
+    bytes.extend(transitions.iter().map(|(_, index)| index));
+    for (index, offset) in offsets.iter().enumerate() {
+      bytes.extend(offset.to_be_bytes());
+      bytes.extend([u8::from(index > 0), 4 * index as u8]);
+    }
+    for _ in offsets {
+      bytes.extend(b"ZZZ\0");
+    }
+  };
+
+  let mut bytes = vec![];
+  match version {
+    0 => {
+      header(4, &mut bytes);
+      data(4, &mut bytes);
+    }
+    _ => {
+      // The 32-bit data a reader of version 1 would use is left empty.
+      header(0, &mut bytes);
+      bytes.extend(0i32.to_be_bytes());
+      bytes.extend([0, 0]);
+      bytes.extend(b"ZZZ\0");
+      header(8, &mut bytes);
+      data(8, &mut bytes);
+      bytes.extend(format!("\n{footer}\n").as_bytes());
+    }
+  }
+  bytes
+}
+
+#[test]
+fn ok_on_rule_with_daylight_saving_time() {
+  let rule = Rule::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+  assert_eq!(rule.standard, -5 * 3600);
+  // 2024-01-15 and 2024-07-01.
+  assert_eq!(rule.offset_at(1_705_320_000), -5 * 3600);
+  assert_eq!(rule.offset_at(1_719_792_000), -4 * 3600);
+  // At 02:00 local time on the second Sunday of March and the first of
+  // November 2024.
+  assert_eq!(rule.offset_at(1_710_053_999), -5 * 3600);
+  assert_eq!(rule.offset_at(1_710_054_000), -4 * 3600);
+  assert_eq!(rule.offset_at(1_730_613_599), -4 * 3600);
+  assert_eq!(rule.offset_at(1_730_613_600), -5 * 3600);
+
+  // Without dates, daylight saving time follows the rule of the United
+  // States, an hour ahead.
+  let rule = Rule::parse("EST5EDT").unwrap();
+  assert_eq!(rule.offset_at(1_710_054_000), -4 * 3600);
+
+  // In the southern hemisphere, daylight saving time spans new year.
+  let rule = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+  assert_eq!(rule.offset_at(1_705_320_000), 11 * 3600);
+  assert_eq!(rule.offset_at(1_712_419_199), 11 * 3600);
+  assert_eq!(rule.offset_at(1_712_419_200), 10 * 3600);
+  assert_eq!(rule.offset_at(1_728_143_999), 10 * 3600);
+  assert_eq!(rule.offset_at(1_728_144_000), 11 * 3600);
+}
+
+#[test]
+fn ok_on_rule_with_fixed_offset() {
+  let rule = Rule::parse("<+0530>-5:30").unwrap();
+  assert_eq!(rule.standard, 5 * 3600 + 30 * 60);
+  assert!(rule.daylight.is_none());
+  assert_eq!(rule.offset_at(1_719_792_000), 19800);
+
+  assert_eq!(Rule::parse("UTC0").unwrap().standard, 0);
+  assert_eq!(Rule::parse("ABC+1:02:03").unwrap().standard, -3723);
+}
+
+#[test]
+fn err_on_bad_rules() {
+  for rule in [
+    "",
+    "EST",
+    "ES5",
+    "<+05",
+    "EST5EDT,M3.2.0",
+    "EST5EDT,M3.2",
+    "EST5 EDT",
+  ] {
+    assert!(Rule::parse(rule).is_none(), "{rule}");
+  }
+}
+
+#[test]
+fn ok_on_rule_dates() {
+  // `J60` is March 1 even in a leap year; day 59 is February 29 then.
+  assert_eq!(
+    RuleDate::Julian(60).day_of(2024),
+    days_from_civil(2024, 3, 1)
+  );
+  assert_eq!(
+    RuleDate::Julian(60).day_of(2023),
+    days_from_civil(2023, 3, 1)
+  );
+  assert_eq!(RuleDate::Day(59).day_of(2024), days_from_civil(2024, 2, 29));
+  // The last Sunday of October 2024, and of December 2023.
+  assert_eq!(
+    RuleDate::MonthWeekDay(10, 5, 0).day_of(2024),
+    days_from_civil(2024, 10, 27)
+  );
+  assert_eq!(
+    RuleDate::MonthWeekDay(12, 5, 0).day_of(2023),
+    days_from_civil(2023, 12, 31)
+  );
+}
+
+#[test]
+fn ok_on_tzif_files() {
+  // A version 2 file follows its rule past the last transition.
+  let zone = Zone::parse(&tzif(
+    b'2',
+    &[(1000, 1), (2000, 0)],
+    &[3600, 7200],
+    "ABC-1XYZ",
+  ))
+  .unwrap();
+  assert_eq!(zone.transitions, [1000, 2000]);
+  assert_eq!(zone.offset_at(999), 3600);
+  assert_eq!(zone.offset_at(1000), 7200);
+  assert_eq!(zone.offset_at(1999), 7200);
+  assert_eq!(zone.offset_at(2000), 3600);
+  assert_eq!(zone.offset_at(1_719_792_000), 7200);
+
+  // A version 1 file keeps the offset of its last transition.
+  let zone = Zone::parse(&tzif(0, &[(-1000, 1), (1000, 0)], &[-3600, 0], "")).unwrap();
+  assert_eq!(zone.transitions, [-1000, 1000]);
+  assert_eq!(zone.offset_at(-2000), -3600);
+  assert_eq!(zone.offset_at(0), 0);
+  assert_eq!(zone.offset_at(1_719_792_000), -3600);
+
+  // A zone without transitions follows its rule throughout.
+  let zone = Zone::parse(&tzif(b'3', &[], &[0], "<+0530>-5:30")).unwrap();
+  assert_eq!(zone.offset_at(0), 19800);
+
+  let bytes = tzif(b'2', &[(1000, 1)], &[3600, 7200], "ABC-1");
+  assert!(Zone::parse(&bytes[..bytes.len() - 20]).is_none());
+  assert!(Zone::parse(b"TZjf").is_none());
+  // A transition to a type the file does not have.
+  assert!(Zone::parse(&tzif(0, &[(1000, 2)], &[3600, 7200], "")).is_none());
+}
+
+#[test]
+fn ok_on_fixed_offset_fallback() {
+  // A name no file of the database has is read as a rule.
+  assert_eq!(Zone::named(Some("ABC-3:30")).offset_at(0), 12600);
+  assert_eq!(Zone::named(Some(":XYZ+2")).offset_at(0), -7200);
+  // And UTC when it is not one, nor may name a file outside the database.
+  assert_eq!(Zone::named(Some("No/Such_Zone")).offset_at(0), 0);
+  assert_eq!(Zone::named(Some("../../etc/passwd")).offset_at(0), 0);
+  assert_eq!(Zone::named(Some("")).offset_at(1_719_792_000), 0);
+  assert_eq!(Zone::default().offset_at(0), 0);
+}
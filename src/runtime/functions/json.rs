@@ -0,0 +1,568 @@
+//! # JSON functions
+//!
+//!  JSON arguments are text, parsed along with the extensions of JSON5 (see
+//! [`parse`]), or JSONB blobs. Results are minified JSON text. Other values
+//! go into JSON as `null`, numbers and strings: the text `'[1]'` is the
+//! string `"[1]"`, not an array.
+//!
+//!  Except for the result of another JSON function, which SQLite tells
+//! apart by its subtype. Here, the arguments of the functions that build
+//! JSON out of values are checked for a call of a JSON function, an `->`
+//! operator or the `value` of `json_each()`, and passed as JSONB if they
+//! are one. The result of `json_extract()` counts when it is an array or
+//! an object. Like in SQLite, the arguments of window calls never do.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html
+
+mod node;
+mod parse;
+mod path;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{eval::Environment, SqliteRuntime},
+  sql::ast::{BinaryOperator, Expr},
+  value::Value,
+};
+
+use self::{
+  node::{header_size, Json},
+  parse::parse,
+  path::{edit, find, locate, Edit},
+};
+
+use super::Accumulator;
+
+fn malformed() -> SqliteError {
+  SqliteError::Custom("malformed JSON".into())
+}
+
+fn bad_path(path: &str) -> SqliteError {
+  SqliteError::Custom(format!("bad JSON path: '{}'", path.replace('\'', "''")))
+}
+
+/// Reads a JSON argument: JSONB, or else JSON text. NULL gives `None`.
+fn read_json(value: &Value) -> SqliteResult<Option<Json>> {
+  let text = match value {
+    Value::Null => return Ok(None),
+    Value::Blob(blob) => match Json::decode(blob) {
+      Some(json) => return Ok(Some(json)),
+      None => String::from_utf8_lossy(blob).into_owned(),
+    },
+    value => value.to_string(),
+  };
+  parse(&text)
+    .map(|parsed| Some(parsed.json))
+    .ok_or_else(malformed)
+}
+
+/// Reads a value to put into JSON.
+fn json_value(value: &Value) -> SqliteResult<Json> {
+  Json::from_value(value).ok_or_else(|| SqliteError::Custom("JSON cannot hold BLOB values".into()))
+}
+
+fn text(json: &Json) -> Value {
+  Value::Text(json.to_text())
+}
+
+/// Whether an argument is JSON for the functions that put values into JSON,
+/// rather than text.
+pub(crate) fn is_json(expr: &Expr, value: &Value, env: &dyn Environment) -> bool {
+  let Value::Text(text) = value else {
+    return false;
+  };
+  match expr {
+    Expr::Binary {
+      operator: BinaryOperator::Extract,
+      ..
+    } => true,
+    Expr::Function { name, .. } => match name.to_ascii_lowercase().as_str() {
+      "json" | "json_array" | "json_group_array" | "json_group_object" | "json_insert"
+      | "json_object" | "json_patch" | "json_remove" | "json_replace" | "json_set" => true,
+      "json_extract" => text.starts_with(['[', '{']),
+      _ => false,
+    },
+    Expr::Column {
+      schema,
+      table,
+      name,
+    } => env.is_json_column(schema.as_deref(), table.as_deref(), name),
+    _ => false,
+  }
+}
+
+/// Passes JSON text as JSONB, which the JSON functions read as JSON.
+pub(crate) fn to_jsonb(value: Value) -> Value {
+  let Value::Text(text) = &value else {
+    return value;
+  };
+  match parse(text) {
+    Some(parsed) => {
+      let mut blob = vec![];
+      parsed.json.encode(&mut blob);
+      Value::Blob(blob)
+    }
+    None => value,
+  }
+}
+
+/// `json(X)`: `X` minified, with the extensions of JSON5 rendered as JSON.
+pub(super) fn json(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(read_json(&args[0])?.map_or(Value::Null, |json| text(&json)))
+}
+
+/// `json_array(...)`: an array of the arguments.
+pub(super) fn json_array(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let items = args.iter().map(json_value).collect::<SqliteResult<_>>()?;
+  Ok(text(&Json::Array(items)))
+}
+
+/// `json_object(label, value, ...)`: an object of the pairs of arguments.
+pub(super) fn json_object(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if args.len() % 2 != 0 {
+    return Err(SqliteError::Custom(
+      "json_object() requires an even number of arguments".into(),
+    ));
+  }
+  let mut members = vec![];
+  for pair in args.chunks(2) {
+    let Value::Text(label) = &pair[0] else {
+      return Err(SqliteError::Custom(
+        "json_object() labels must be TEXT".into(),
+      ));
+    };
+    members.push((Json::TextRaw(label.clone()), json_value(&pair[1])?));
+  }
+  Ok(text(&Json::Object(members)))
+}
+
+/// `json_extract(X, P, ...)`: with one path, the SQL value of the element
+/// it leads to, and with more, an array of the elements, `null` for those
+/// missing.
+pub(super) fn json_extract(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if args.len() < 2 {
+    return Ok(Value::Null);
+  }
+  let Some(json) = read_json(&args[0])? else {
+    return Ok(Value::Null);
+  };
+  let mut items = vec![];
+  for path in &args[1..] {
+    if path.is_null() {
+      return Ok(Value::Null);
+    }
+    let path = path.to_string();
+    let found = find(&json, &path).map_err(|_| bad_path(&path))?;
+    if args.len() == 2 {
+      return Ok(found.map_or(Value::Null, Json::to_value));
+    }
+    items.push(found.cloned().unwrap_or(Json::Null));
+  }
+  Ok(text(&Json::Array(items)))
+}
+
+/// `X -> P` and, with `as_value`, `X ->> P`: the element as JSON, or as an
+/// SQL value. An integer `P` is an index in an array, counting back from
+/// the end when negative, and a text not starting with `$` a label.
+pub(crate) fn extract_arrow(json: &Value, path: &Value, as_value: bool) -> SqliteResult<Value> {
+  let Some(json) = read_json(json)? else {
+    return Ok(Value::Null);
+  };
+  let path = match path {
+    Value::Integer(index) if *index >= 0 => format!("$[{index}]"),
+    Value::Integer(index) => format!("$[#{index}]"),
+    path => {
+      let path = path.to_string();
+      let is_word = path
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+      match path.as_bytes() {
+        [b'$', ..] => path,
+        _ if is_word => format!("$.{path}"),
+        [b'[', .., b']'] if path.len() >= 3 => format!("${path}"),
+        _ => format!("$.\"{path}\""),
+      }
+    }
+  };
+  let found = find(&json, &path).map_err(|_| bad_path(&path))?;
+  Ok(match (found, as_value) {
+    (None, _) => Value::Null,
+    (Some(found), true) => found.to_value(),
+    (Some(found), false) => text(found),
+  })
+}
+
+/// Runs the edits of `json_insert()`, `json_replace()` and `json_set()`
+/// over the pairs of paths and values.
+fn edit_pairs(name: &str, args: &[Value], make_edit: fn(&Json) -> Edit) -> SqliteResult<Value> {
+  if args.is_empty() {
+    return Ok(Value::Null);
+  }
+  if args.len() % 2 == 0 {
+    return Err(SqliteError::Custom(format!(
+      "{name}() needs an odd number of arguments"
+    )));
+  }
+  let Some(mut json) = read_json(&args[0])? else {
+    return Ok(Value::Null);
+  };
+  for pair in args[1..].chunks(2) {
+    if pair[0].is_null() {
+      continue;
+    }
+    let path = pair[0].to_string();
+    let value = json_value(&pair[1])?;
+    match (path.as_str(), make_edit(&value)) {
+      ("$", Edit::Insert(_)) => {}
+      ("$", _) => json = value.clone(),
+      (_, edit_kind) => {
+        edit(&mut json, &path, edit_kind).map_err(|_| bad_path(&path))?;
+      }
+    }
+  }
+  Ok(text(&json))
+}
+
+/// `json_insert(X, P, V, ...)`: adds each `V` where its path leads nowhere.
+pub(super) fn json_insert(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  edit_pairs("json_insert", args, |value| Edit::Insert(value))
+}
+
+/// `json_replace(X, P, V, ...)`: changes the elements the paths lead to.
+pub(super) fn json_replace(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  edit_pairs("json_replace", args, |value| Edit::Replace(value))
+}
+
+/// `json_set(X, P, V, ...)`: adds or changes the elements.
+pub(super) fn json_set(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  edit_pairs("json_set", args, |value| Edit::Set(value))
+}
+
+/// `json_remove(X, P, ...)`: removes the elements, in turn. Removing the
+/// whole value gives NULL.
+pub(super) fn json_remove(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let Some(mut json) = args.first().map(read_json).transpose()?.flatten() else {
+    return Ok(Value::Null);
+  };
+  for path in &args[1..] {
+    if path.is_null() {
+      return Ok(Value::Null);
+    }
+    let path = path.to_string();
+    if path == "$" {
+      return Ok(Value::Null);
+    }
+    edit(&mut json, &path, Edit::Remove).map_err(|_| bad_path(&path))?;
+  }
+  Ok(text(&json))
+}
+
+/// `json_patch(T, P)`: `T` with the MergePatch `P` of RFC 7396 applied.
+pub(super) fn json_patch(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let (Some(target), Some(patch)) = (read_json(&args[0])?, read_json(&args[1])?) else {
+    return Ok(Value::Null);
+  };
+  Ok(text(&merge_patch(target, patch)))
+}
+
+fn merge_patch(target: Json, patch: Json) -> Json {
+  let Json::Object(patch) = patch else {
+    return patch;
+  };
+  let mut members = match target {
+    Json::Object(members) => members,
+    _ => vec![],
+  };
+  for (label, value) in patch {
+    let name = label.text();
+    let position = members.iter().position(|(other, _)| other.text() == name);
+    match (position, value) {
+      (Some(position), Json::Null) => {
+        members.remove(position);
+      }
+      (None, Json::Null) => {}
+      (Some(position), value) => {
+        let target = core::mem::replace(&mut members[position].1, Json::Null);
+        members[position].1 = merge_patch(target, value);
+      }
+      (None, value) => members.push((label, merge_patch(Json::Null, value))),
+    }
+  }
+  Json::Object(members)
+}
+
+/// `json_type(X, [P])`: the type of the element, as `null`, `true`,
+/// `false`, `integer`, `real`, `text`, `array` or `object`.
+pub(super) fn json_type(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let Some(json) = read_json(&args[0])? else {
+    return Ok(Value::Null);
+  };
+  let found = match args.get(1) {
+    None => Some(&json),
+    Some(Value::Null) => None,
+    Some(path) => {
+      let path = path.to_string();
+      find(&json, &path).map_err(|_| bad_path(&path))?
+    }
+  };
+  Ok(found.map_or(Value::Null, |found| Value::Text(found.type_name().into())))
+}
+
+/// `json_valid(X, [F])`: whether `X` is well formed, as the bits of `F`
+/// accept it: 1 for JSON, 2 for JSON5, 4 and 8 for JSONB.
+pub(super) fn json_valid(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let flags = match args.get(1) {
+    None => 1,
+    Some(Value::Integer(flags @ 1..=15)) => *flags,
+    Some(_) => {
+      return Err(SqliteError::Custom(
+        "FLAGS parameter to json_valid() must be between 1 and 15".into(),
+      ))
+    }
+  };
+  let text = match &args[0] {
+    Value::Null => return Ok(Value::Null),
+    Value::Blob(blob) if Json::decode(blob).is_some() => {
+      return Ok(Value::Integer((flags & 0x0c != 0).into()))
+    }
+    Value::Blob(blob) => String::from_utf8_lossy(blob).into_owned(),
+    value => value.to_string(),
+  };
+  let is_valid =
+    flags & 0x03 != 0 && parse(&text).is_some_and(|parsed| flags & 0x02 != 0 || !parsed.is_json5);
+  Ok(Value::Integer(is_valid.into()))
+}
+
+/// `json_group_array(X)`: an array of `X` over the rows.
+#[derive(Default)]
+struct GroupArray(Vec<Json>);
+
+impl Accumulator for GroupArray {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    self.0.push(json_value(&args[0])?);
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(text(&Json::Array(self.0.clone())))
+  }
+}
+
+pub(super) fn json_group_array() -> Box<dyn Accumulator> {
+  Box::<GroupArray>::default()
+}
+
+/// `json_group_object(N, V)`: an object of the pairs over the rows, but
+/// those whose `N` is NULL.
+#[derive(Default)]
+struct GroupObject(Vec<(Json, Json)>);
+
+impl Accumulator for GroupObject {
+  fn step(&mut self, args: &[Value]) -> SqliteResult<()> {
+    if args[0].is_null() {
+      return Ok(());
+    }
+    let label = Json::TextRaw(args[0].to_string());
+    self.0.push((label, json_value(&args[1])?));
+    Ok(())
+  }
+
+  fn value(&self) -> SqliteResult<Value> {
+    Ok(text(&Json::Object(self.0.clone())))
+  }
+}
+
+pub(super) fn json_group_object() -> Box<dyn Accumulator> {
+  Box::<GroupObject>::default()
+}
+
+/// The columns of `json_each()` and `json_tree()`, the last two of which
+/// are their arguments.
+pub(super) const EACH_COLUMNS: [&str; 10] = [
+  "key", "value", "type", "atom", "id", "parent", "fullkey", "path", "json", "root",
+];
+
+/// Whether a column of a row of `json_each()` or `json_tree()` holds JSON:
+/// its `value` does for an array or an object.
+pub(super) fn is_each_json(column: usize, row: &[Value]) -> bool {
+  column == 1
+    && matches!(row.get(2), Some(Value::Text(kind)) if kind == "array" || kind == "object")
+}
+
+/// `json_each(X, [P])`: a row for each element of the array or object `P`
+/// leads to in `X`, or for the element itself if it is neither.
+pub(super) fn json_each(_: &SqliteRuntime, args: &[Value]) -> SqliteResult<Vec<Vec<Value>>> {
+  Walk::rows(args, false)
+}
+
+/// `json_tree(X, [P])`: a row for the element `P` leads to in `X`, and
+/// for every element it contains, depth first.
+pub(super) fn json_tree(_: &SqliteRuntime, args: &[Value]) -> SqliteResult<Vec<Vec<Value>>> {
+  Walk::rows(args, true)
+}
+
+/// The rows of `json_each()` or `json_tree()`.
+struct Walk {
+  is_tree: bool,
+  json: Value,
+  root: Value,
+  rows: Vec<Vec<Value>>,
+}
+
+impl Walk {
+  fn rows(args: &[Value], is_tree: bool) -> SqliteResult<Vec<Vec<Value>>> {
+    let Some(arg) = args.first() else {
+      return Ok(vec![]);
+    };
+    let root = match args.get(1) {
+      None => "$".to_string(),
+      Some(Value::Null) => return Ok(vec![]),
+      Some(path) => path.to_string(),
+    };
+    let Some(json) = read_json(arg)? else {
+      return Ok(vec![]);
+    };
+    let Some(found) = locate(&json, &root, true).map_err(|_| bad_path(&root))? else {
+      return Ok(vec![]);
+    };
+    let mut walk = Self {
+      is_tree,
+      json: arg.clone(),
+      root: Value::Text(root.clone()),
+      rows: vec![],
+    };
+    match (is_tree, found.json.is_container()) {
+      (false, true) => walk.children(found.json, found.offset, None, &root),
+      (false, false) => walk.row(found.json, Value::Null, found.id, None, &root, &root),
+      (true, _) => {
+        let parent = parent_length(&json, &root, found.id);
+        let step = &root[parent..];
+        let key = match step.as_bytes() {
+          [] | [b'$'] => Value::Null,
+          [b'[', ..] => {
+            let digits = step[1..].bytes().take_while(u8::is_ascii_digit).count();
+            Value::Integer(step[1..1 + digits].parse().unwrap_or_default())
+          }
+          [_, b'"', ..] => Value::Text(step[2..step.len() - 1].into()),
+          _ => Value::Text(step[1..].into()),
+        };
+        let path = &root[..parent];
+        walk.row(found.json, key, found.id, None, &root, path);
+        walk.children(found.json, found.offset, Some(found.id), &root);
+      }
+    }
+    Ok(walk.rows)
+  }
+
+  fn row(
+    &mut self,
+    json: &Json,
+    key: Value,
+    id: usize,
+    parent: Option<usize>,
+    fullkey: &str,
+    path: &str,
+  ) {
+    let atom = match json.is_container() {
+      true => Value::Null,
+      false => json.to_value(),
+    };
+    self.rows.push(vec![
+      key,
+      json.to_value(),
+      Value::Text(json.type_name().into()),
+      atom,
+      Value::Integer(id as i64),
+      parent.map_or(Value::Null, |parent| Value::Integer(parent as i64)),
+      Value::Text(fullkey.into()),
+      Value::Text(path.into()),
+      self.json.clone(),
+      self.root.clone(),
+    ]);
+  }
+
+  /// Adds the rows of the elements of an array or an object at `offset`,
+  /// and for a tree, of their own elements.
+  fn children(&mut self, json: &Json, offset: usize, parent: Option<usize>, fullkey: &str) {
+    let mut child = offset + header_size(json.payload_size());
+    let parent = parent.filter(|_| self.is_tree);
+    match json {
+      Json::Array(items) => {
+        for (index, item) in items.iter().enumerate() {
+          let key = format!("{fullkey}[{index}]");
+          self.element(
+            item,
+            Value::Integer(index as i64),
+            child,
+            child,
+            parent,
+            &key,
+            fullkey,
+          );
+          child += item.size();
+        }
+      }
+      Json::Object(members) => {
+        for (label, value) in members {
+          let key = format!("{fullkey}{}", path_label(label));
+          let offset = child + label.size();
+          let name = label.text().map_or(Value::Null, Value::Text);
+          self.element(value, name, child, offset, parent, &key, fullkey);
+          child = offset + value.size();
+        }
+      }
+      _ => {}
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn element(
+    &mut self,
+    json: &Json,
+    key: Value,
+    id: usize,
+    offset: usize,
+    parent: Option<usize>,
+    fullkey: &str,
+    path: &str,
+  ) {
+    self.row(json, key, id, parent, fullkey, path);
+    if self.is_tree && json.is_container() {
+      self.children(json, offset, Some(id), fullkey);
+    }
+  }
+}
+
+/// The length of the path to the parent of the root of `json_tree()`,
+/// whose last step gives the key of the root: that of the longest prefix
+/// of the path to an array or an object which the root is the first
+/// element of, or else `$`. Like in SQLite, the root may be another of its
+/// elements, and the key and path of its row then are those of the first.
+fn parent_length(json: &Json, root: &str, id: usize) -> usize {
+  if root.len() < 2 {
+    return root.len();
+  }
+  (1..root.len())
+    .rev()
+    .filter(|&length| matches!(root.as_bytes()[length], b'[' | b'.'))
+    .find(|&length| {
+      let prefix = root.get(..length).unwrap_or_default();
+      matches!(
+        locate(json, prefix, true),
+        Ok(Some(found)) if found.offset + header_size(found.json.payload_size()) == id
+      )
+    })
+    .unwrap_or(1)
+}
+
+/// The step of a path to a label, quoted unless it is a letter followed by
+/// letters and digits.
+fn path_label(label: &Json) -> String {
+  let text = label.payload_text().unwrap_or_default();
+  let is_word = text.starts_with(|c: char| c.is_ascii_alphabetic())
+    && text.chars().all(|c| c.is_ascii_alphanumeric());
+  match is_word {
+    true => format!(".{text}"),
+    false => format!(".\"{text}\""),
+  }
+}
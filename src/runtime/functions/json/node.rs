@@ -0,0 +1,496 @@
+//! # JSON values
+//!
+//!  A JSON value is held as a tree whose nodes are the elements of JSONB,
+//! the binary format of SQLite. Numbers and strings keep the text they were
+//! written with, which is what they are rendered as, unless that text uses
+//! the extensions of JSON5. Strings from SQL keep theirs unescaped.
+//!
+//!  In JSONB, each element is a header, with its type and the size of its
+//! payload, followed by the payload: the text of a number or a string, or
+//! the elements of an array, or the labels and values of an object. The
+//! offsets of elements in it identify them in `json_each()` and
+//! `json_tree()`.
+//!
+//! *Reference:* https://www.sqlite.org/jsonb.html
+
+use crate::value::{format_real, Value};
+
+/// A JSON element, by its JSONB type.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Json {
+  Null,
+  True,
+  False,
+  /// An integer, as written.
+  Int(String),
+  /// A hexadecimal integer of JSON5, such as `0x1F`.
+  Int5(String),
+  /// A real number, as written.
+  Float(String),
+  /// A real number of JSON5 with no digit on one side of its point.
+  Float5(String),
+  /// A string without escapes.
+  Text(String),
+  /// A string with the escapes of JSON.
+  TextJ(String),
+  /// A string with the escapes of JSON5, or control characters.
+  Text5(String),
+  /// A string from SQL, escaped when it is rendered.
+  TextRaw(String),
+  Array(Vec<Json>),
+  /// The labels and values of an object. Labels are strings.
+  Object(Vec<(Json, Json)>),
+}
+
+impl Json {
+  fn type_code(&self) -> u8 {
+    match self {
+      Self::Null => 0,
+      Self::True => 1,
+      Self::False => 2,
+      Self::Int(_) => 3,
+      Self::Int5(_) => 4,
+      Self::Float(_) => 5,
+      Self::Float5(_) => 6,
+      Self::Text(_) => 7,
+      Self::TextJ(_) => 8,
+      Self::Text5(_) => 9,
+      Self::TextRaw(_) => 10,
+      Self::Array(_) => 11,
+      Self::Object(_) => 12,
+    }
+  }
+
+  /// The name `json_type()` gives the element.
+  pub(super) fn type_name(&self) -> &'static str {
+    match self {
+      Self::Null => "null",
+      Self::True => "true",
+      Self::False => "false",
+      Self::Int(_) | Self::Int5(_) => "integer",
+      Self::Float(_) | Self::Float5(_) => "real",
+      Self::Text(_) | Self::TextJ(_) | Self::Text5(_) | Self::TextRaw(_) => "text",
+      Self::Array(_) => "array",
+      Self::Object(_) => "object",
+    }
+  }
+
+  pub(super) fn is_container(&self) -> bool {
+    matches!(self, Self::Array(_) | Self::Object(_))
+  }
+
+  /// The text of a number or a string, as held.
+  pub(super) fn payload_text(&self) -> Option<&str> {
+    match self {
+      Self::Int(text)
+      | Self::Int5(text)
+      | Self::Float(text)
+      | Self::Float5(text)
+      | Self::Text(text)
+      | Self::TextJ(text)
+      | Self::Text5(text)
+      | Self::TextRaw(text) => Some(text),
+      _ => None,
+    }
+  }
+
+  /// The size of the payload in JSONB.
+  pub(super) fn payload_size(&self) -> usize {
+    match self {
+      Self::Null | Self::True | Self::False => 0,
+      Self::Array(items) => items.iter().map(Self::size).sum(),
+      Self::Object(members) => members
+        .iter()
+        .map(|(label, value)| label.size() + value.size())
+        .sum(),
+      _ => self.payload_text().map_or(0, str::len),
+    }
+  }
+
+  /// The size of the element in JSONB, header included.
+  pub(super) fn size(&self) -> usize {
+    let payload = self.payload_size();
+    header_size(payload) + payload
+  }
+
+  /// Encodes the element as JSONB.
+  pub(super) fn encode(&self, out: &mut Vec<u8>) {
+    let payload = self.payload_size();
+    let code = self.type_code();
+    match payload {
+      0..=11 => out.push(((payload as u8) << 4) | code),
+      12..=0xff => out.extend([0xc0 | code, payload as u8]),
+      0x100..=0xffff => {
+        out.push(0xd0 | code);
+        out.extend((payload as u16).to_be_bytes());
+      }
+      _ => match u32::try_from(payload) {
+        Ok(payload) => {
+          out.push(0xe0 | code);
+          out.extend(payload.to_be_bytes());
+        }
+        Err(_) => {
+          out.push(0xf0 | code);
+          out.extend((payload as u64).to_be_bytes());
+        }
+      },
+    }
+    match self {
+      Self::Array(items) => items.iter().for_each(|item| item.encode(out)),
+      Self::Object(members) => {
+        for (label, value) in members {
+          label.encode(out);
+          value.encode(out);
+        }
+      }
+      _ => out.extend(self.payload_text().unwrap_or_default().bytes()),
+    }
+  }
+
+  /// Decodes a JSONB blob, or `None` if it is not one.
+  pub(super) fn decode(bytes: &[u8]) -> Option<Self> {
+    match decode_element(bytes)? {
+      (json, size) if size == bytes.len() => Some(json),
+      _ => None,
+    }
+  }
+
+  /// Renders the element as minified JSON text.
+  pub(super) fn render(&self, out: &mut String) {
+    match self {
+      Self::Null => out.push_str("null"),
+      Self::True => out.push_str("true"),
+      Self::False => out.push_str("false"),
+      Self::Int(text) | Self::Float(text) => out.push_str(text),
+      Self::Int5(text) => render_hex(text, out),
+      Self::Float5(text) => render_float5(text, out),
+      Self::Text(text) | Self::TextJ(text) => {
+        out.push('"');
+        out.push_str(text);
+        out.push('"');
+      }
+      Self::Text5(text) => render_text5(text, out),
+      Self::TextRaw(text) => {
+        out.push('"');
+        for c in text.chars() {
+          match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => push_control(c, out),
+            c => out.push(c),
+          }
+        }
+        out.push('"');
+      }
+      Self::Array(items) => {
+        out.push('[');
+        for (index, item) in items.iter().enumerate() {
+          if index > 0 {
+            out.push(',');
+          }
+          item.render(out);
+        }
+        out.push(']');
+      }
+      Self::Object(members) => {
+        out.push('{');
+        for (index, (label, value)) in members.iter().enumerate() {
+          if index > 0 {
+            out.push(',');
+          }
+          label.render(out);
+          out.push(':');
+          value.render(out);
+        }
+        out.push('}');
+      }
+    }
+  }
+
+  pub(super) fn to_text(&self) -> String {
+    let mut out = String::new();
+    self.render(&mut out);
+    out
+  }
+
+  /// The text of a string, with its escapes undone.
+  pub(super) fn text(&self) -> Option<String> {
+    match self {
+      Self::Text(text) | Self::TextRaw(text) => Some(text.clone()),
+      Self::TextJ(text) | Self::Text5(text) => Some(unescape(text)),
+      _ => None,
+    }
+  }
+
+  /// The SQL value of the element: numbers and strings as such, `true` and
+  /// `false` as 1 and 0, and arrays and objects as their JSON text.
+  pub(super) fn to_value(&self) -> Value {
+    match self {
+      Self::Null => Value::Null,
+      Self::True => Value::Integer(1),
+      Self::False => Value::Integer(0),
+      Self::Int(text) => match text.parse() {
+        Ok(integer) => Value::Integer(integer),
+        Err(_) => Value::Real(text.parse().unwrap_or_default()),
+      },
+      Self::Int5(text) => {
+        let (is_negative, digits) = match text.strip_prefix('-') {
+          Some(digits) => (true, digits),
+          None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let magnitude = u64::from_str_radix(&digits[2.min(digits.len())..], 16).ok();
+        match magnitude.and_then(|magnitude| i64::try_from(magnitude).ok()) {
+          Some(integer) if is_negative => Value::Integer(-integer),
+          Some(integer) => Value::Integer(integer),
+          None => Value::Real(match is_negative {
+            true => f64::NEG_INFINITY,
+            false => f64::INFINITY,
+          }),
+        }
+      }
+      Self::Float(text) | Self::Float5(text) => Value::Real(text.parse().unwrap_or_default()),
+      Self::Array(_) | Self::Object(_) => Value::Text(self.to_text()),
+      _ => Value::Text(self.text().unwrap_or_default()),
+    }
+  }
+
+  /// The element for an SQL value: NULL as `null`, numbers as such, and
+  /// text as a string. A blob must be JSONB.
+  pub(super) fn from_value(value: &Value) -> Option<Self> {
+    Some(match value {
+      Value::Null => Self::Null,
+      Value::Integer(integer) => Self::Int(integer.to_string()),
+      Value::Real(real) if real.is_nan() => Self::Null,
+      Value::Real(real) if real.is_infinite() => Self::Float(
+        match real.is_sign_negative() {
+          true => "-9.0e+999",
+          false => "9.0e+999",
+        }
+        .into(),
+      ),
+      Value::Real(real) => Self::Float(format_real(*real)),
+      Value::Text(text) => Self::TextRaw(text.clone()),
+      Value::Blob(blob) => return Self::decode(blob),
+    })
+  }
+}
+
+/// The size of the header of an element with a payload of `size` bytes.
+pub(super) fn header_size(size: usize) -> usize {
+  match size {
+    0..=11 => 1,
+    12..=0xff => 2,
+    0x100..=0xffff => 3,
+    _ if u32::try_from(size).is_ok() => 5,
+    _ => 9,
+  }
+}
+
+/// Decodes the element at the start of `bytes`, with its size.
+fn decode_element(bytes: &[u8]) -> Option<(Json, usize)> {
+  let header = *bytes.first()?;
+  let (header_size, size) = match header >> 4 {
+    size @ 0..=11 => (1, usize::from(size)),
+    12 => (2, usize::from(*bytes.get(1)?)),
+    13 => (
+      3,
+      usize::from(u16::from_be_bytes(bytes.get(1..3)?.try_into().ok()?)),
+    ),
+    14 => (
+      5,
+      u32::from_be_bytes(bytes.get(1..5)?.try_into().ok()?) as usize,
+    ),
+    _ => (
+      9,
+      usize::try_from(u64::from_be_bytes(bytes.get(1..9)?.try_into().ok()?)).ok()?,
+    ),
+  };
+  let end = header_size + size;
+  let payload = bytes.get(header_size..end)?;
+  let text = || core::str::from_utf8(payload).ok().map(String::from);
+  let json = match header & 0x0f {
+    0..=2 if size > 0 => return None,
+    0 => Json::Null,
+    1 => Json::True,
+    2 => Json::False,
+    3 => Json::Int(text()?),
+    4 => Json::Int5(text()?),
+    5 => Json::Float(text()?),
+    6 => Json::Float5(text()?),
+    7 => Json::Text(text()?),
+    8 => Json::TextJ(text()?),
+    9 => Json::Text5(text()?),
+    10 => Json::TextRaw(text()?),
+    11 => {
+      let mut items = vec![];
+      let mut offset = 0;
+      while offset < size {
+        let (item, item_size) = decode_element(&payload[offset..])?;
+        items.push(item);
+        offset += item_size;
+      }
+      Json::Array(items)
+    }
+    12 => {
+      let mut members = vec![];
+      let mut offset = 0;
+      while offset < size {
+        let (label, label_size) = decode_element(&payload[offset..])?;
+        label.text()?;
+        let (value, value_size) = decode_element(payload.get(offset + label_size..)?)?;
+        members.push((label, value));
+        offset += label_size + value_size;
+      }
+      Json::Object(members)
+    }
+    _ => return None,
+  };
+  Some((json, end))
+}
+
+/// Renders a hexadecimal integer in decimal, or as infinity when it does
+/// not fit in 64 bits.
+fn render_hex(text: &str, out: &mut String) {
+  let digits = match text.strip_prefix('-') {
+    Some(digits) => {
+      out.push('-');
+      digits
+    }
+    None => text.strip_prefix('+').unwrap_or(text),
+  };
+  let mut value: u64 = 0;
+  for digit in digits.chars().skip(2) {
+    if value >> 60 != 0 {
+      out.push_str("9.0e999");
+      return;
+    }
+    value = value * 16 + u64::from(digit.to_digit(16).unwrap_or_default());
+  }
+  out.push_str(&value.to_string());
+}
+
+/// Renders a real number with a zero where a digit is missing next to its
+/// point.
+fn render_float5(text: &str, out: &mut String) {
+  let digits = match text.strip_prefix('-') {
+    Some(digits) => {
+      out.push('-');
+      digits
+    }
+    None => text,
+  };
+  if digits.starts_with('.') {
+    out.push('0');
+  }
+  let mut chars = digits.chars().peekable();
+  while let Some(c) = chars.next() {
+    out.push(c);
+    if c == '.' && !chars.peek().is_some_and(char::is_ascii_digit) {
+      out.push('0');
+    }
+  }
+}
+
+/// Renders a string of JSON5 with the escapes of JSON.
+fn render_text5(text: &str, out: &mut String) {
+  out.push('"');
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => match chars.next() {
+        Some('\'') => out.push('\''),
+        Some('v') => out.push_str("\\u000b"),
+        Some('0') => out.push_str("\\u0000"),
+        Some('x') => {
+          out.push_str("\\u00");
+          out.extend(chars.by_ref().take(2));
+        }
+        Some('\r') => {
+          chars.next_if_eq(&'\n');
+        }
+        Some('\n' | '\u{2028}' | '\u{2029}') => {}
+        Some(c) => {
+          out.push('\\');
+          out.push(c);
+        }
+        None => {}
+      },
+      c if u32::from(c) < 0x20 => push_control(c, out),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+/// Escapes a control character.
+fn push_control(c: char, out: &mut String) {
+  match c {
+    '\u{8}' => out.push_str("\\b"),
+    '\u{c}' => out.push_str("\\f"),
+    '\n' => out.push_str("\\n"),
+    '\r' => out.push_str("\\r"),
+    '\t' => out.push_str("\\t"),
+    c => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+  }
+}
+
+/// Undoes the escapes of JSON and JSON5 in a string. Unpaired surrogates
+/// become U+FFFD.
+pub(super) fn unescape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('b') => out.push('\u{8}'),
+      Some('f') => out.push('\u{c}'),
+      Some('n') => out.push('\n'),
+      Some('r') => out.push('\r'),
+      Some('t') => out.push('\t'),
+      Some('v') => out.push('\u{b}'),
+      Some('0') => out.push('\0'),
+      Some('x') => out.push(char::from_u32(hex(&mut chars, 2)).unwrap_or('\u{fffd}')),
+      Some('u') => {
+        let high = hex(&mut chars, 4);
+        let code = match high {
+          0xd800..=0xdbff => {
+            let mut rest = chars.clone();
+            match (rest.next(), rest.next()) {
+              (Some('\\'), Some('u')) => {
+                chars = rest;
+                let low = hex(&mut chars, 4);
+                match low {
+                  0xdc00..=0xdfff => 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                  _ => 0xfffd,
+                }
+              }
+              _ => 0xfffd,
+            }
+          }
+          code => code,
+        };
+        out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+      }
+      Some('\r') => {
+        chars.next_if_eq(&'\n');
+      }
+      Some('\n' | '\u{2028}' | '\u{2029}') => {}
+      Some(c) => out.push(c),
+      None => {}
+    }
+  }
+  out
+}
+
+/// Reads up to `count` hexadecimal digits.
+fn hex(chars: &mut core::iter::Peekable<core::str::Chars>, count: usize) -> u32 {
+  (0..count)
+    .filter_map(|_| chars.next_if(char::is_ascii_hexdigit))
+    .fold(0, |value, digit| {
+      value * 16 + digit.to_digit(16).unwrap_or_default()
+    })
+}
@@ -0,0 +1,345 @@
+//! # JSON text
+//!
+//!  Besides JSON itself, the parser reads the extensions of JSON5 the way
+//! SQLite does: comments, trailing commas, unquoted labels, strings in
+//! single quotes with more escapes, hexadecimal integers, numbers with a
+//! leading `+` or with no digit on one side of their point, `Infinity` and
+//! `NaN`, and more whitespace. They are rendered back as JSON.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html#json5_extensions
+
+use super::node::Json;
+
+/// How deeply arrays and objects may nest.
+const MAX_DEPTH: usize = 1000;
+
+/// The result of parsing a JSON text.
+pub(super) struct Parsed {
+  pub(super) json: Json,
+  /// Whether the text uses extensions of JSON5.
+  pub(super) is_json5: bool,
+}
+
+/// Parses a JSON text, or returns `None` if it is malformed.
+pub(super) fn parse(text: &str) -> Option<Parsed> {
+  let mut parser = Parser {
+    text: text.as_bytes(),
+    position: 0,
+    is_json5: false,
+  };
+  let json = parser.value(0)?;
+  parser.skip_whitespace();
+  match parser.position == parser.text.len() {
+    true => Some(Parsed {
+      json,
+      is_json5: parser.is_json5,
+    }),
+    false => None,
+  }
+}
+
+struct Parser<'a> {
+  text: &'a [u8],
+  position: usize,
+  is_json5: bool,
+}
+
+impl Parser<'_> {
+  fn peek(&self) -> Option<u8> {
+    self.text.get(self.position).copied()
+  }
+
+  fn at(&self, offset: usize) -> u8 {
+    self.text.get(self.position + offset).copied().unwrap_or(0)
+  }
+
+  fn slice(&self, start: usize) -> String {
+    String::from_utf8_lossy(&self.text[start..self.position]).into_owned()
+  }
+
+  /// Skips whitespace and comments.
+  fn skip_whitespace(&mut self) {
+    loop {
+      let length = match (self.at(0), self.at(1), self.at(2)) {
+        (b' ' | b'\t' | b'\n' | b'\r', ..) => {
+          self.position += 1;
+          continue;
+        }
+        (0x0b | 0x0c, ..) => 1,
+        (0xc2, 0xa0, _) => 2,
+        (0xe1, 0x9a, 0x80) | (0xe2, 0x80, 0x80..=0x8a | 0xa8 | 0xa9 | 0xaf) => 3,
+        (0xe2, 0x81, 0x9f) | (0xe3, 0x80, 0x80) | (0xef, 0xbb, 0xbf) => 3,
+        (b'/', b'*', _) => {
+          let rest = &self.text[self.position + 2..];
+          match rest.windows(2).position(|pair| pair == b"*/") {
+            Some(end) => end + 4,
+            None => return,
+          }
+        }
+        (b'/', b'/', _) => {
+          let rest = &self.text[self.position + 2..];
+          let end = rest
+            .iter()
+            .position(|byte| matches!(byte, b'\n' | b'\r'))
+            .map_or(rest.len(), |end| end + 1);
+          end + 2
+        }
+        _ => return,
+      };
+      self.is_json5 = true;
+      self.position += length;
+    }
+  }
+
+  fn value(&mut self, depth: usize) -> Option<Json> {
+    if depth > MAX_DEPTH {
+      return None;
+    }
+    self.skip_whitespace();
+    match self.peek()? {
+      b'{' => self.object(depth),
+      b'[' => self.array(depth),
+      quote @ (b'"' | b'\'') => self.string(quote),
+      b't' if self.keyword("true") => Some(Json::True),
+      b'f' if self.keyword("false") => Some(Json::False),
+      b'n' if self.keyword("null") => Some(Json::Null),
+      b'-' | b'+' | b'.' | b'0'..=b'9' => self.number(),
+      _ => self.non_finite(false),
+    }
+  }
+
+  /// Reads a word that does not go on with letters or digits.
+  fn keyword(&mut self, word: &str) -> bool {
+    let end = self.position + word.len();
+    let matches = self.text[self.position..].starts_with(word.as_bytes())
+      && !self.text.get(end).is_some_and(u8::is_ascii_alphanumeric);
+    if matches {
+      self.position = end;
+    }
+    matches
+  }
+
+  /// Reads `Infinity` or `NaN` and their variants, ignoring case.
+  fn non_finite(&mut self, is_negative: bool) -> Option<Json> {
+    const WORDS: [(&str, bool); 5] = [
+      ("infinity", true),
+      ("inf", true),
+      ("nan", false),
+      ("qnan", false),
+      ("snan", false),
+    ];
+    let rest = &self.text[self.position..];
+    let (word, is_infinity) = WORDS.into_iter().find(|(word, _)| {
+      rest.len() >= word.len()
+        && rest[..word.len()].eq_ignore_ascii_case(word.as_bytes())
+        && !rest.get(word.len()).is_some_and(u8::is_ascii_alphanumeric)
+    })?;
+    if is_negative && !is_infinity {
+      return None;
+    }
+    self.position += word.len();
+    self.is_json5 = true;
+    Some(match (is_infinity, is_negative) {
+      (true, false) => Json::Float("9e999".into()),
+      (true, true) => Json::Float("-9e999".into()),
+      (false, _) => Json::Null,
+    })
+  }
+
+  fn number(&mut self) -> Option<Json> {
+    let mut start = self.position;
+    let mut is_json5 = false;
+    match self.at(0) {
+      b'+' => {
+        is_json5 = true;
+        self.position += 1;
+        start += 1;
+      }
+      b'-' => self.position += 1,
+      _ => {}
+    }
+    if !matches!(self.at(0), b'0'..=b'9' | b'.') {
+      return self.non_finite(self.position > start);
+    }
+    if self.at(0) == b'0' && matches!(self.at(1), b'x' | b'X') && self.at(2).is_ascii_hexdigit() {
+      self.position += 2;
+      while self.at(0).is_ascii_hexdigit() {
+        self.position += 1;
+      }
+      self.is_json5 = true;
+      return Some(Json::Int5(self.slice(start)));
+    }
+    if self.at(0) == b'0' && self.at(1).is_ascii_digit() {
+      return None;
+    }
+    let digits = self.digits();
+    let mut is_real = false;
+    let mut is_real5 = false;
+    if self.at(0) == b'.' {
+      self.position += 1;
+      let fraction = self.digits();
+      match (digits, fraction) {
+        (0, 0) => return None,
+        (0, _) | (_, 0) => is_real5 = true,
+        _ => is_real = true,
+      }
+    } else if digits == 0 {
+      return None;
+    }
+    if matches!(self.at(0), b'e' | b'E') {
+      self.position += 1;
+      if matches!(self.at(0), b'+' | b'-') {
+        self.position += 1;
+      }
+      if self.digits() == 0 {
+        return None;
+      }
+      is_real = true;
+    }
+    self.is_json5 |= is_json5 || is_real5;
+    let text = self.slice(start);
+    Some(match (is_real5, is_real) {
+      (true, _) => Json::Float5(text),
+      (false, true) => Json::Float(text),
+      (false, false) => Json::Int(text),
+    })
+  }
+
+  fn digits(&mut self) -> usize {
+    let start = self.position;
+    while self.at(0).is_ascii_digit() {
+      self.position += 1;
+    }
+    self.position - start
+  }
+
+  /// Reads a string between `quote`s, which tells how it is escaped.
+  fn string(&mut self, quote: u8) -> Option<Json> {
+    self.position += 1;
+    let start = self.position;
+    let mut is_json = false;
+    let mut is_json5 = false;
+    loop {
+      let byte = self.peek()?;
+      match byte {
+        _ if byte == quote => break,
+        b'\\' => {
+          self.position += 1;
+          match (self.at(0), self.at(1), self.at(2)) {
+            (b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't', ..) => is_json = true,
+            (b'u', ..)
+              if self.text.len() >= self.position + 5
+                && self.text[self.position + 1..self.position + 5]
+                  .iter()
+                  .all(u8::is_ascii_hexdigit) =>
+            {
+              is_json = true
+            }
+            (b'\'' | b'v' | b'\n', ..) => is_json5 = true,
+            (b'0', next, _) if !next.is_ascii_digit() => is_json5 = true,
+            (b'x', high, low) if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+              is_json5 = true
+            }
+            (0xe2, 0x80, 0xa8 | 0xa9) => {
+              is_json5 = true;
+              self.position += 2;
+            }
+            (b'\r', next, _) => {
+              if next == b'\n' {
+                self.position += 1;
+              }
+              is_json5 = true;
+            }
+            _ => return None,
+          }
+        }
+        0 => return None,
+        0x01..=0x1f => is_json5 = true,
+        b'"' => is_json5 = true,
+        _ => {}
+      }
+      self.position += 1;
+    }
+    let text = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+    let text = text.to_string();
+    self.position += 1;
+    self.is_json5 |= is_json5 || quote == b'\'';
+    Some(match (is_json5, is_json) {
+      (true, _) => Json::Text5(text),
+      (false, true) => Json::TextJ(text),
+      (false, false) => Json::Text(text),
+    })
+  }
+
+  fn array(&mut self, depth: usize) -> Option<Json> {
+    self.position += 1;
+    let mut items = vec![];
+    loop {
+      self.skip_whitespace();
+      if self.peek()? == b']' {
+        if !items.is_empty() {
+          self.is_json5 = true;
+        }
+        break;
+      }
+      items.push(self.value(depth + 1)?);
+      self.skip_whitespace();
+      match self.peek()? {
+        b',' => self.position += 1,
+        b']' => break,
+        _ => return None,
+      }
+    }
+    self.position += 1;
+    Some(Json::Array(items))
+  }
+
+  fn object(&mut self, depth: usize) -> Option<Json> {
+    self.position += 1;
+    let mut members = vec![];
+    loop {
+      self.skip_whitespace();
+      let label = match self.peek()? {
+        b'}' => {
+          if !members.is_empty() {
+            self.is_json5 = true;
+          }
+          break;
+        }
+        quote @ (b'"' | b'\'') => self.string(quote)?,
+        _ => self.identifier()?,
+      };
+      self.skip_whitespace();
+      if self.peek()? != b':' {
+        return None;
+      }
+      self.position += 1;
+      let value = self.value(depth + 1)?;
+      members.push((label, value));
+      self.skip_whitespace();
+      match self.peek()? {
+        b',' => self.position += 1,
+        b'}' => break,
+        _ => return None,
+      }
+    }
+    self.position += 1;
+    Some(Json::Object(members))
+  }
+
+  /// Reads an unquoted label of JSON5.
+  fn identifier(&mut self) -> Option<Json> {
+    let start = self.position;
+    let is_start =
+      |byte: u8| byte.is_ascii_alphabetic() || matches!(byte, b'_' | b'$') || byte >= 0x80;
+    if !is_start(self.at(0)) {
+      return None;
+    }
+    while is_start(self.at(0)) || self.at(0).is_ascii_digit() {
+      self.position += 1;
+    }
+    self.is_json5 = true;
+    let text = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+    Some(Json::Text(text.into()))
+  }
+}
@@ -0,0 +1,306 @@
+//! # JSON paths
+//!
+//!  A path starts with `$`, the whole value, followed by steps: `.label` or
+//! `."label"` for a member of an object, and `[N]` for an element of an
+//! array, counting from 0, or `[#-N]` counting back from its end. `[#]` is
+//! just past the last element, where `json_insert()` and `json_set()`
+//! append.
+//!
+//!  Like in SQLite, a step is only read once the value it applies to is
+//! reached, so a step that cannot apply to it makes the path lead nowhere
+//! rather than be malformed.
+//!
+//! *Reference:* https://www.sqlite.org/json1.html#path_arguments
+
+use super::node::{header_size, unescape, Json};
+
+/// A path that is not well formed.
+#[derive(Debug)]
+pub(super) struct BadPath;
+
+/// A step of a path.
+enum Step<'p> {
+  /// A label, as written between its quotes if it has some, and whether
+  /// it has no escapes.
+  Label(&'p str, bool),
+  /// An index, or `None` if it is malformed.
+  Index(Option<Index>),
+}
+
+#[derive(Clone, Copy)]
+enum Index {
+  At(usize),
+  /// `[#-N]`, and `[#]` for 0.
+  FromEnd(usize),
+}
+
+impl Index {
+  /// The position the index refers to in an array of `length` elements,
+  /// which may be `length` itself, or `None` if it is past that.
+  fn resolve(self, length: usize) -> Option<usize> {
+    match self {
+      Self::At(index) => (index <= length).then_some(index),
+      Self::FromEnd(back) => length.checked_sub(back),
+    }
+  }
+}
+
+/// Reads the first step of a path, along with the rest of the path.
+fn step(path: &str) -> Result<(Step<'_>, &str), BadPath> {
+  let bytes = path.as_bytes();
+  match bytes.first() {
+    Some(b'.') if bytes.get(1) == Some(&b'"') => {
+      let mut end = 2;
+      while end < bytes.len() && bytes[end] != b'"' {
+        if bytes[end] == b'\\' && end + 1 < bytes.len() {
+          end += 1;
+        }
+        end += 1;
+      }
+      if end >= bytes.len() {
+        return Err(BadPath);
+      }
+      let label = &path[2..end];
+      Ok((Step::Label(label, !label.contains('\\')), &path[end + 1..]))
+    }
+    Some(b'.') => {
+      let end = bytes[1..]
+        .iter()
+        .position(|byte| matches!(byte, b'.' | b'['))
+        .map_or(bytes.len(), |end| end + 1);
+      match end {
+        1 => Err(BadPath),
+        _ => Ok((Step::Label(&path[1..end], true), &path[end..])),
+      }
+    }
+    Some(b'[') => {
+      let digits = bytes[1..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+      if digits > 0 && bytes.get(1 + digits) == Some(&b']') {
+        let index = path[1..1 + digits].parse().unwrap_or(usize::MAX);
+        return Ok((Step::Index(Some(Index::At(index))), &path[digits + 2..]));
+      }
+      if bytes.get(1) != Some(&b'#') {
+        return Ok((Step::Index(None), ""));
+      }
+      let mut end = 2;
+      let mut back = 0;
+      if bytes.get(2) == Some(&b'-') && bytes.get(3).is_some_and(u8::is_ascii_digit) {
+        let digits = bytes[3..]
+          .iter()
+          .take_while(|byte| byte.is_ascii_digit())
+          .count();
+        back = path[3..3 + digits].parse().unwrap_or(usize::MAX);
+        end = 3 + digits;
+      }
+      match bytes.get(end) {
+        Some(b']') => Ok((Step::Index(Some(Index::FromEnd(back))), &path[end + 1..])),
+        _ => Ok((Step::Index(None), "")),
+      }
+    }
+    _ => Err(BadPath),
+  }
+}
+
+/// Whether a label of a path names a label of an object.
+fn is_label(key: &str, is_raw: bool, label: &Json) -> bool {
+  match (label, is_raw) {
+    (Json::Text(text) | Json::TextRaw(text), true) => text == key,
+    _ => label.text().is_some_and(|text| text == unescape(key)),
+  }
+}
+
+/// The label of a member inserted by a path.
+fn new_label(key: &str, is_raw: bool) -> Json {
+  match is_raw {
+    true => Json::TextRaw(key.into()),
+    false => Json::Text5(key.into()),
+  }
+}
+
+/// Strips the leading `$` of a path.
+fn steps(path: &str) -> Result<&str, BadPath> {
+  path.strip_prefix('$').ok_or(BadPath)
+}
+
+/// An element found by a path.
+pub(super) struct Found<'a> {
+  pub(super) json: &'a Json,
+  /// Its offset in the JSONB of the whole value, or that of its label for
+  /// a member of an object.
+  pub(super) id: usize,
+  /// The offset of the element itself.
+  pub(super) offset: usize,
+}
+
+/// Finds the element a path leads to, if any.
+pub(super) fn find<'a>(root: &'a Json, path: &str) -> Result<Option<&'a Json>, BadPath> {
+  Ok(locate(root, path, false)?.map(|found| found.json))
+}
+
+/// Finds the element a path leads to, along with its offset in JSONB when
+/// `with_offsets`.
+pub(super) fn locate<'a>(
+  root: &'a Json,
+  path: &str,
+  with_offsets: bool,
+) -> Result<Option<Found<'a>>, BadPath> {
+  let mut rest = steps(path)?;
+  let mut found = Found {
+    json: root,
+    id: 0,
+    offset: 0,
+  };
+  while !rest.is_empty() {
+    let (step, next) = step(rest)?;
+    let mut child = match with_offsets {
+      true => found.offset + header_size(found.json.payload_size()),
+      false => 0,
+    };
+    match (step, found.json) {
+      (Step::Label(key, is_raw), Json::Object(members)) => {
+        let Some((label, value)) = members.iter().find(|(label, value)| {
+          let is_match = is_label(key, is_raw, label);
+          if with_offsets && !is_match {
+            child += label.size() + value.size();
+          }
+          is_match
+        }) else {
+          return Ok(None);
+        };
+        found.id = child;
+        if with_offsets {
+          found.offset = child + label.size();
+        }
+        found.json = value;
+      }
+      (Step::Index(index), Json::Array(items)) => {
+        let index = index.ok_or(BadPath)?;
+        let Some(item) = index
+          .resolve(items.len())
+          .and_then(|index| items.get(index))
+        else {
+          return Ok(None);
+        };
+        if with_offsets {
+          child += items
+            .iter()
+            .take_while(|other| !core::ptr::eq(*other, item))
+            .map(Json::size)
+            .sum::<usize>();
+        }
+        found.id = child;
+        found.offset = child;
+        found.json = item;
+      }
+      _ => return Ok(None),
+    }
+    rest = next;
+  }
+  Ok(Some(found))
+}
+
+/// How `edit()` changes the element a path leads to.
+#[derive(Clone, Copy)]
+pub(super) enum Edit<'v> {
+  Remove,
+  /// Adds the value if there is no element, like `json_insert()`.
+  Insert(&'v Json),
+  /// Changes the element if there is one, like `json_replace()`.
+  Replace(&'v Json),
+  /// Either of the above, like `json_set()`.
+  Set(&'v Json),
+}
+
+impl Edit<'_> {
+  fn inserts(self) -> bool {
+    matches!(self, Self::Insert(_) | Self::Set(_))
+  }
+}
+
+/// Applies an edit at a path below the root. Returns whether the element
+/// was there or has been added.
+pub(super) fn edit(root: &mut Json, path: &str, edit: Edit) -> Result<bool, BadPath> {
+  edit_at(root, steps(path)?, edit)
+}
+
+fn edit_at(json: &mut Json, path: &str, edit: Edit) -> Result<bool, BadPath> {
+  let (step, rest) = step(path)?;
+  match (step, json) {
+    (Step::Label(key, is_raw), Json::Object(members)) => {
+      match members
+        .iter()
+        .position(|(label, _)| is_label(key, is_raw, label))
+      {
+        Some(position) if rest.is_empty() => {
+          match edit {
+            Edit::Remove => {
+              members.remove(position);
+            }
+            Edit::Insert(_) => {}
+            Edit::Replace(value) | Edit::Set(value) => members[position].1 = value.clone(),
+          }
+          Ok(true)
+        }
+        Some(position) => edit_at(&mut members[position].1, rest, edit),
+        None if edit.inserts() => match create(rest, edit)? {
+          Some(value) => {
+            members.push((new_label(key, is_raw), value));
+            Ok(true)
+          }
+          None => Ok(false),
+        },
+        None => Ok(false),
+      }
+    }
+    (Step::Index(index), Json::Array(items)) => {
+      let Some(position) = index.ok_or(BadPath)?.resolve(items.len()) else {
+        return Ok(false);
+      };
+      if position == items.len() {
+        if !edit.inserts() {
+          return Ok(false);
+        }
+        return match create(rest, edit)? {
+          Some(value) => {
+            items.push(value);
+            Ok(true)
+          }
+          None => Ok(false),
+        };
+      }
+      if !rest.is_empty() {
+        return edit_at(&mut items[position], rest, edit);
+      }
+      match edit {
+        Edit::Remove => {
+          items.remove(position);
+        }
+        Edit::Insert(_) => {}
+        Edit::Replace(value) | Edit::Set(value) => items[position] = value.clone(),
+      }
+      Ok(true)
+    }
+    _ => Ok(false),
+  }
+}
+
+/// The value inserted where the rest of a path leads nowhere: the new
+/// value itself, or the objects and arrays the rest of the path goes
+/// through, if it can add them.
+fn create(rest: &str, edit: Edit) -> Result<Option<Json>, BadPath> {
+  let value = match edit {
+    Edit::Insert(value) | Edit::Set(value) => value,
+    Edit::Replace(_) | Edit::Remove => return Ok(None),
+  };
+  if rest.is_empty() {
+    return Ok(Some(value.clone()));
+  }
+  let mut json = match rest.starts_with('.') {
+    true => Json::Object(vec![]),
+    false => Json::Array(vec![]),
+  };
+  Ok(edit_at(&mut json, rest, edit)?.then_some(json))
+}
@@ -0,0 +1,525 @@
+//! # Core scalar functions
+//!
+//!  Unless noted otherwise, a function returns NULL when any of its
+//! arguments is NULL. Functions that work on text read numbers as their
+//! text form and blobs as UTF-8.
+//!
+//! *Reference:* https://www.sqlite.org/lang_corefunc.html
+
+use core::cmp::Ordering;
+use std::{
+  collections::hash_map::RandomState,
+  hash::{BuildHasher, Hasher},
+  sync::{
+    atomic::{AtomicU64, Ordering as AtomicOrdering},
+    OnceLock,
+  },
+};
+
+use crate::{
+  record::encode_text,
+  result::{SqliteError, SqliteResult},
+  runtime::eval::{is_true, Environment},
+  value::{format_real, Affinity, Value},
+};
+
+/// The largest string or blob a function may build, `SQLITE_MAX_LENGTH`.
+const MAX_LENGTH: i64 = 1_000_000_000;
+
+/// The most digits `round()` keeps after the decimal point.
+const MAX_ROUND_DIGITS: i64 = 30;
+
+fn has_null(args: &[Value]) -> bool {
+  args.iter().any(Value::is_null)
+}
+
+fn integer(value: &Value) -> i64 {
+  value
+    .cast(Affinity::Integer)
+    .as_integer()
+    .unwrap_or_default()
+}
+
+fn real(value: &Value) -> f64 {
+  match value.to_numeric() {
+    Value::Integer(integer) => integer as f64,
+    Value::Real(real) => real,
+    _ => 0.0,
+  }
+}
+
+fn too_big() -> SqliteError {
+  SqliteError::Custom("string or blob too big".into())
+}
+
+/// `abs(X)`: integers stay integers, anything else is read as a REAL.
+pub(super) fn abs(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match &args[0] {
+    Value::Null => Value::Null,
+    Value::Integer(integer) => Value::Integer(
+      integer
+        .checked_abs()
+        .ok_or_else(|| SqliteError::Custom("integer overflow".into()))?,
+    ),
+    value => Value::Real(real(value).abs()),
+  })
+}
+
+/// `char(X1, X2, ...)`: the text made of the given code points. Invalid code
+/// points become U+FFFD.
+pub(super) fn char(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::Text(
+    args
+      .iter()
+      .map(|arg| {
+        u32::try_from(integer(arg))
+          .ok()
+          .and_then(core::char::from_u32)
+          .unwrap_or(core::char::REPLACEMENT_CHARACTER)
+      })
+      .collect(),
+  ))
+}
+
+/// `coalesce(X, Y, ...)` and `ifnull(X, Y)`: the first argument that is not
+/// NULL.
+pub(super) fn coalesce(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(
+    args
+      .iter()
+      .find(|arg| !arg.is_null())
+      .cloned()
+      .unwrap_or_default(),
+  )
+}
+
+/// `hex(X)`: the bytes of a blob, of text in the database encoding, or of
+/// the UTF-8 text form of a number, as upper case hexadecimal. NULL gives an
+/// empty text.
+pub(super) fn hex(env: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let bytes = match &args[0] {
+    Value::Null => vec![],
+    Value::Blob(blob) => blob.clone(),
+    Value::Text(text) => encode_text(text, &env.encoding()),
+    value => value.to_string().into_bytes(),
+  };
+  Ok(Value::Text(
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect(),
+  ))
+}
+
+/// `iif(B1, V1, B2, V2, ..., [E])`: the value after the first true
+/// condition, or the odd last argument, or NULL.
+pub(super) fn iif(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  for pair in args.chunks(2) {
+    match pair {
+      [condition, value] if is_true(condition) == Some(true) => return Ok(value.clone()),
+      [otherwise] => return Ok(otherwise.clone()),
+      _ => {}
+    }
+  }
+  Ok(Value::Null)
+}
+
+/// `instr(X, Y)`: the 1-based position of the first `Y` in `X`, in
+/// characters, or in bytes when both are blobs. 0 when there is none.
+pub(super) fn instr(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  if let (Value::Blob(haystack), Value::Blob(needle)) = (&args[0], &args[1]) {
+    let position = match needle.is_empty() {
+      true => Some(0),
+      false => haystack
+        .windows(needle.len())
+        .position(|window| window == needle.as_slice()),
+    };
+    return Ok(Value::Integer(
+      position.map_or(0, |position| position as i64 + 1),
+    ));
+  }
+  let haystack = args[0].to_string();
+  let needle = args[1].to_string();
+  Ok(Value::Integer(haystack.find(&needle).map_or(0, |index| {
+    haystack[..index].chars().count() as i64 + 1
+  })))
+}
+
+/// `last_insert_rowid()`: the rowid of the last row inserted through the
+/// connection.
+pub(super) fn last_insert_rowid(env: &dyn Environment, _: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::Integer(env.last_insert_rowid()))
+}
+
+/// `length(X)`: the number of characters of a text before the first NUL,
+/// or the number of bytes of a blob.
+pub(super) fn length(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match &args[0] {
+    Value::Null => Value::Null,
+    Value::Blob(blob) => Value::Integer(blob.len() as i64),
+    value => Value::Integer(
+      value
+        .to_string()
+        .chars()
+        .take_while(|char| *char != '\0')
+        .count() as i64,
+    ),
+  })
+}
+
+/// `lower(X)`: only ASCII letters are converted.
+pub(super) fn lower(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match &args[0] {
+    Value::Null => Value::Null,
+    value => Value::Text(value.to_string().to_ascii_lowercase()),
+  })
+}
+
+/// `upper(X)`: only ASCII letters are converted.
+pub(super) fn upper(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match &args[0] {
+    Value::Null => Value::Null,
+    value => Value::Text(value.to_string().to_ascii_uppercase()),
+  })
+}
+
+/// Removes the characters of `Y`, spaces by default, from the start and/or
+/// the end of `X`.
+fn trim_with(args: &[Value], start: bool, end: bool) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let text = args[0].to_string();
+  let characters: Vec<char> = match args.get(1) {
+    Some(characters) => characters.to_string().chars().collect(),
+    None => vec![' '],
+  };
+  let mut trimmed = text.as_str();
+  if start {
+    trimmed = trimmed.trim_start_matches(characters.as_slice());
+  }
+  if end {
+    trimmed = trimmed.trim_end_matches(characters.as_slice());
+  }
+  Ok(Value::Text(trimmed.into()))
+}
+
+pub(super) fn trim(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  trim_with(args, true, true)
+}
+
+pub(super) fn ltrim(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  trim_with(args, true, false)
+}
+
+pub(super) fn rtrim(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  trim_with(args, false, true)
+}
+
+/// Picks the argument that sorts first (`min`) or last (`max`). On ties,
+/// `min` keeps the later argument and `max` the earlier one.
+fn extremum(args: &[Value], ordering: Ordering) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let mut best = &args[0];
+  for arg in &args[1..] {
+    let comparison = best.compare(arg);
+    if comparison == ordering.reverse() || (ordering == Ordering::Less && comparison.is_eq()) {
+      best = arg;
+    }
+  }
+  Ok(best.clone())
+}
+
+/// `max(X, Y, ...)`: the largest argument, or NULL if any is NULL.
+pub(super) fn max(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  extremum(args, Ordering::Greater)
+}
+
+/// `min(X, Y, ...)`: the smallest argument, or NULL if any is NULL.
+pub(super) fn min(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  extremum(args, Ordering::Less)
+}
+
+/// `nullif(X, Y)`: NULL when the arguments are equal, `X` otherwise. No
+/// affinity is applied.
+pub(super) fn nullif(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match args[0].compare(&args[1]) {
+    Ordering::Equal => Value::Null,
+    _ => args[0].clone(),
+  })
+}
+
+/// `quote(X)`: an SQL literal for the value. Reals that 15 significant
+/// digits do not round trip are written with more.
+pub(super) fn quote(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::Text(match &args[0] {
+    Value::Null => "NULL".into(),
+    Value::Integer(integer) => integer.to_string(),
+    Value::Real(real) if real.is_infinite() => match *real > 0.0 {
+      true => "9.0e+999".into(),
+      false => "-9.0e+999".into(),
+    },
+    Value::Real(real) => {
+      let text = format_real(*real);
+      match text.parse::<f64>() == Ok(*real) {
+        true => text,
+        false => exact_real(*real),
+      }
+    }
+    Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+    Value::Blob(blob) => format!(
+      "X'{}'",
+      blob
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<String>()
+    ),
+  }))
+}
+
+/// Writes a REAL with 19 significant digits in exponential notation, such
+/// as `3.333333333333333148e-01`.
+fn exact_real(real: f64) -> String {
+  let scientific = format!("{real:.18e}");
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+  let mantissa = mantissa.trim_end_matches('0');
+  let mantissa = match mantissa.ends_with('.') {
+    true => format!("{mantissa}0"),
+    false => mantissa.to_string(),
+  };
+  let exponent: i32 = exponent.parse().unwrap_or_default();
+  let sign = if exponent < 0 { '-' } else { '+' };
+  format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+/// A pseudo-random 64-bit number. The sequence is seeded once per process
+/// from the standard library's per-process random keys.
+fn random_u64() -> u64 {
+  static SEED: OnceLock<u64> = OnceLock::new();
+  static COUNTER: AtomicU64 = AtomicU64::new(0);
+  let seed = *SEED.get_or_init(|| RandomState::new().build_hasher().finish());
+  // SplitMix64
+  let mut z = seed.wrapping_add(
+    COUNTER
+      .fetch_add(1, AtomicOrdering::Relaxed)
+      .wrapping_mul(0x9e37_79b9_7f4a_7c15),
+  );
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+/// `random()`: a pseudo-random INTEGER.
+pub(super) fn random(_: &dyn Environment, _: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::Integer(random_u64() as i64))
+}
+
+/// `randomblob(N)`: `N` pseudo-random bytes, at least one.
+pub(super) fn randomblob(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let length = integer(&args[0]).max(1);
+  if length > MAX_LENGTH {
+    return Err(too_big());
+  }
+  let mut blob = Vec::with_capacity(length as usize);
+  while blob.len() < length as usize {
+    blob.extend(random_u64().to_le_bytes());
+  }
+  blob.truncate(length as usize);
+  Ok(Value::Blob(blob))
+}
+
+/// `replace(X, Y, Z)`: `X` with every `Y` replaced by `Z`. `X` is returned
+/// as is when `Y` is empty.
+pub(super) fn replace(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let pattern = args[1].to_string();
+  if pattern.is_empty() {
+    return Ok(args[0].clone());
+  }
+  Ok(Value::Text(
+    args[0].to_string().replace(&pattern, &args[2].to_string()),
+  ))
+}
+
+/// `round(X, [Y])`: `X` rounded to `Y` digits after the decimal point, half
+/// away from zero, always as a REAL. `Y` is clamped to 0..=30.
+pub(super) fn round(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let value = real(&args[0]);
+  let digits = args
+    .get(1)
+    .map_or(0, |digits| integer(digits).clamp(0, MAX_ROUND_DIGITS)) as usize;
+
+  // Beyond 2^52 a REAL has no fractional part left to round.
+  const NO_FRACTION: f64 = 4_503_599_627_370_496.0;
+  if !(-NO_FRACTION..=NO_FRACTION).contains(&value) {
+    return Ok(Value::Real(value));
+  }
+  if digits == 0 {
+    let rounded = (value.abs() + 0.5) as i64 as f64;
+    return Ok(Value::Real(rounded.copysign(value)));
+  }
+
+  // Formatting rounds the exact binary value correctly, but breaks exact
+  // ties to even where SQLite rounds them away from zero. Ties can only
+  // show as a trailing 5 one digit further, and are then rounded by hand
+  // on the exact decimal expansion.
+  let longer = format!("{value:.*}", digits + 1);
+  if !longer.ends_with('5') {
+    return Ok(Value::Real(
+      format!("{value:.digits$}").parse().unwrap_or(value),
+    ));
+  }
+  let exact = format!("{:.1074}", value.abs());
+  let end = exact.find('.').unwrap_or(exact.len()) + 1 + digits;
+  let mut kept = exact.as_bytes()[..end].to_vec();
+  if exact.as_bytes()[end] >= b'5' {
+    let mut index = end;
+    loop {
+      if index == 0 {
+        kept.insert(0, b'1');
+        break;
+      }
+      index -= 1;
+      match kept[index] {
+        b'.' => {}
+        b'9' => kept[index] = b'0',
+        _ => {
+          kept[index] += 1;
+          break;
+        }
+      }
+    }
+  }
+  let rounded: f64 = String::from_utf8_lossy(&kept).parse().unwrap_or(value);
+  Ok(Value::Real(rounded.copysign(value)))
+}
+
+/// `sign(X)`: -1, 0 or 1 for a number, NULL for anything that is not one.
+pub(super) fn sign(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let number = match &args[0] {
+    Value::Text(_) => args[0].clone().apply_affinity(Affinity::Numeric),
+    value => value.clone(),
+  };
+  Ok(match number {
+    Value::Integer(integer) => Value::Integer(integer.signum()),
+    Value::Real(real) if real > 0.0 => Value::Integer(1),
+    Value::Real(real) if real < 0.0 => Value::Integer(-1),
+    Value::Real(_) => Value::Integer(0),
+    _ => Value::Null,
+  })
+}
+
+/// `substr(X, Y, [Z])`: `Z` characters of `X` starting at the 1-based
+/// position `Y`, or bytes for a blob. A negative `Y` counts from the end and
+/// a negative `Z` takes the characters before `Y`.
+pub(super) fn substr(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let chars: Vec<char> = match &args[0] {
+    Value::Blob(_) => vec![],
+    value => value.to_string().chars().collect(),
+  };
+  let length = match &args[0] {
+    Value::Blob(blob) => blob.len(),
+    _ => chars.len(),
+  } as i64;
+
+  // This follows the arithmetic of SQLite's own implementation, including
+  // its treatment of a start position of 0.
+  let mut start = integer(&args[1]);
+  let (mut count, is_negative_count) = match args.get(2) {
+    Some(count) => {
+      let count = integer(count);
+      (count.saturating_abs(), count < 0)
+    }
+    None => (MAX_LENGTH, false),
+  };
+  if start < 0 {
+    start = start.saturating_add(length);
+    if start < 0 {
+      count = count.saturating_add(start).max(0);
+      start = 0;
+    }
+  } else if start > 0 {
+    start -= 1;
+  } else if count > 0 {
+    count -= 1;
+  }
+  if is_negative_count {
+    start -= count;
+    if start < 0 {
+      count += start;
+      start = 0;
+    }
+  }
+  let from = start.min(length) as usize;
+  let to = start.saturating_add(count).min(length) as usize;
+
+  Ok(match &args[0] {
+    Value::Blob(blob) => Value::Blob(blob[from..to].to_vec()),
+    _ => Value::Text(chars[from..to].iter().collect()),
+  })
+}
+
+/// `typeof(X)`: the storage class of the value.
+pub(super) fn type_of(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(Value::Text(args[0].type_name().into()))
+}
+
+/// `unhex(X, [Y])`: the blob written in hexadecimal by `X`. Characters of
+/// `Y` may appear between bytes and are skipped. NULL if anything else is
+/// not a pair of hexadecimal digits.
+pub(super) fn unhex(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  if has_null(args) {
+    return Ok(Value::Null);
+  }
+  let ignored: Vec<char> = args
+    .get(1)
+    .map(|ignored| ignored.to_string().chars().collect())
+    .unwrap_or_default();
+  let text = args[0].to_string();
+  let mut chars = text.chars();
+  let mut blob = vec![];
+  while let Some(char) = chars.next() {
+    let Some(high) = char.to_digit(16) else {
+      if ignored.contains(&char) {
+        continue;
+      }
+      return Ok(Value::Null);
+    };
+    let Some(low) = chars.next().and_then(|char| char.to_digit(16)) else {
+      return Ok(Value::Null);
+    };
+    blob.push((high * 16 + low) as u8);
+  }
+  Ok(Value::Blob(blob))
+}
+
+/// `unicode(X)`: the code point of the first character of `X`.
+pub(super) fn unicode(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  Ok(match &args[0] {
+    Value::Null => Value::Null,
+    value => value
+      .to_string()
+      .chars()
+      .next()
+      .map_or(Value::Null, |char| Value::Integer(u32::from(char).into())),
+  })
+}
+
+/// `zeroblob(N)`: a blob of `N` zero bytes.
+pub(super) fn zeroblob(_: &dyn Environment, args: &[Value]) -> SqliteResult<Value> {
+  let length = integer(&args[0]).max(0);
+  if length > MAX_LENGTH {
+    return Err(too_big());
+  }
+  Ok(Value::Blob(vec![0; length as usize]))
+}
@@ -0,0 +1,417 @@
+//! Tests for built-in SQL functions
+//!
+//! To run: `cargo test runtime::functions`
+
+use super::{find_function, functions, Arity, FunctionKind};
+use crate::{
+  runtime::eval::{evaluate, Constant},
+  sql::{ast::FunctionArgs, parse_expr},
+  value::Value,
+  SqliteConnection,
+};
+
+fn eval(sql: &str) -> Value {
+  evaluate(&parse_expr(sql).unwrap(), &Constant).unwrap()
+}
+
+fn blob(bytes: &[u8]) -> Value {
+  Value::Blob(bytes.to_vec())
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same expressions.
+#[test]
+fn ok_on_text_functions() {
+  let null = Value::Null;
+  let cases: [(&str, Value); 34] = [
+    ("length('a' || char(0) || 'b')", 1.into()),
+    ("length(x'000102')", 3.into()),
+    ("length(12.50)", 4.into()),
+    ("length(NULL)", null.clone()),
+    ("substr('hello', 0)", "hello".into()),
+    ("substr('hello', 0, 2)", "h".into()),
+    ("substr('hello', -3, 2)", "ll".into()),
+    ("substr('hello', 2, -1)", "h".into()),
+    ("substr('hello', -1, -2)", "ll".into()),
+    ("substr('hello', -10)", "hello".into()),
+    ("substring('héllo', 2, 3)", "éll".into()),
+    ("substr(x'00ff00ff', -2)", blob(&[0x00, 0xff])),
+    ("substr('abc', NULL)", null.clone()),
+    ("upper('ção')", "çãO".into()),
+    ("lower(x'4142')", "ab".into()),
+    ("trim('xxhixx', 'x')", "hi".into()),
+    ("ltrim('  a ')", "a ".into()),
+    ("rtrim('ab;;;', ';')", "ab".into()),
+    ("replace('aaa', 'a', 'bb')", "bbbbbb".into()),
+    ("replace(1.5, '.', ',')", "1,5".into()),
+    ("replace(2, '', 'x')", 2.into()),
+    ("instr('abcabc', 'ca')", 3.into()),
+    ("instr('héllo', 'l')", 3.into()),
+    ("instr(x'010203', x'03')", 3.into()),
+    ("instr('abc', '')", 1.into()),
+    ("char(65, 8364, -1)", "A€\u{fffd}".into()),
+    ("unicode('€')", 8364.into()),
+    ("unicode('')", null.clone()),
+    ("hex('é')", "C3A9".into()),
+    ("hex(NULL)", "".into()),
+    ("unhex('41 42', ' ')", blob(b"AB")),
+    ("unhex('4 142', ' ')", null.clone()),
+    ("unhex('414')", null.clone()),
+    (
+      "quote('it''s') || quote(x'0a') || quote(NULL)",
+      "'it''s'X'0A'NULL".into(),
+    ),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same query.
+#[test]
+fn ok_on_hex_in_utf16_database() {
+  let conn = SqliteConnection::open("sqlite://./data/utf16.db").unwrap();
+  let sql = "SELECT hex('é'), hex(CAST(12 AS TEXT)), hex(12), hex(x'00'), hex(NULL)";
+  assert_eq!(
+    conn.query(sql).unwrap().rows(),
+    [[
+      Value::from("E900"),
+      Value::from("31003200"),
+      Value::from("3132"),
+      Value::from("00"),
+      Value::from("")
+    ]]
+  );
+}
+
+#[test]
+fn ok_on_numeric_and_null_functions() {
+  let null = Value::Null;
+  let cases: [(&str, Value); 32] = [
+    ("abs(-3)", 3.into()),
+    ("abs('-5')", 5.0.into()),
+    ("abs(x'2D35')", 5.0.into()),
+    ("round(2.5)", 3.0.into()),
+    ("round(-2.5)", (-3.0).into()),
+    ("round(2.675, 2)", 2.67.into()),
+    ("round(0.125, 2)", 0.13.into()),
+    ("round(-0.125, 2)", (-0.13).into()),
+    ("round(1.005, 2)", 1.0.into()),
+    ("round(123.456, -1)", 123.0.into()),
+    ("round('3.7')", 4.0.into()),
+    ("round(1, NULL)", null.clone()),
+    ("sign(' 5 ')", 1.into()),
+    ("sign('5x')", null.clone()),
+    ("sign(-2.5)", (-1).into()),
+    ("sign(x'35')", null.clone()),
+    ("quote(0.1)", "0.1".into()),
+    ("quote(1 / 3.0)", "3.333333333333333148e-01".into()),
+    (
+      "quote(123456789012345678.0)",
+      "1.2345678901234568e+17".into(),
+    ),
+    ("coalesce(NULL, NULL, 3)", 3.into()),
+    ("ifnull(NULL, 'a')", "a".into()),
+    ("nullif(1, 1)", null.clone()),
+    ("nullif(1, '1')", 1.into()),
+    ("iif(0, 'a', 'b')", "b".into()),
+    ("iif(0, 'a')", null.clone()),
+    ("iif(0, 1, 0, 2, 3)", 3.into()),
+    ("max(1, 'a', x'00')", blob(&[0])),
+    ("min(1, NULL, 3)", null.clone()),
+    ("max(2, 2.5)", 2.5.into()),
+    (
+      "typeof(zeroblob(-1)) || length(zeroblob(3))",
+      "blob3".into(),
+    ),
+    ("length(randomblob(0))", 1.into()),
+    ("typeof(random()) || last_insert_rowid()", "integer0".into()),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_registry() {
+  let substr: Vec<_> = functions()
+    .iter()
+    .filter(|function| function.name() == "substr")
+    .map(|function| function.arity())
+    .collect();
+  assert_eq!(substr, [Arity::Exactly(2), Arity::Exactly(3)]);
+
+  let random = find_function("RANDOM", &FunctionArgs::List(vec![])).unwrap();
+  assert_eq!(random.kind(), FunctionKind::Scalar);
+  assert!(!random.is_deterministic());
+  assert_eq!(
+    find_function("coalesce", &FunctionArgs::Star)
+      .unwrap_err()
+      .to_string(),
+    "wrong number of arguments to function coalesce()"
+  );
+}
+
+#[test]
+fn err_on_bad_calls() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT foo(1) FROM Month WHERE 0"),
+    "no such function: foo"
+  );
+  assert_eq!(
+    error("SELECT month FROM Month WHERE substr(month) = 'J'"),
+    "wrong number of arguments to function substr()"
+  );
+  assert_eq!(
+    error("SELECT upper('a') FILTER (WHERE 1)"),
+    "FILTER may not be used with non-aggregate upper()"
+  );
+  assert_eq!(
+    error("SELECT abs(-9223372036854775808)"),
+    "integer overflow"
+  );
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same queries.
+#[test]
+fn ok_on_aggregate_functions() {
+  let conn = SqliteConnection::open("sqlite://./data/added-columns.db").unwrap();
+  let row = |sql: &str| conn.query(sql).unwrap().into_rows().remove(0);
+
+  assert_eq!(
+    row("SELECT sum(name), sum(age), sum(score), total(note), avg(age) FROM person"),
+    [
+      Value::from(0.0),
+      Value::from(131),
+      Value::from(5.0),
+      Value::from(0.0),
+      Value::from(32.75)
+    ]
+  );
+  assert_eq!(
+    row("SELECT sum(' 7 '), typeof(sum('7x')), count(note), count(*), count() FROM person"),
+    [
+      Value::from(28),
+      Value::from("real"),
+      Value::from(1),
+      Value::from(4),
+      Value::from(4)
+    ]
+  );
+  assert_eq!(
+    row("SELECT min(city), max(city), max(note), group_concat(DISTINCT city) FROM person"),
+    [
+      Value::from("Lisbon"),
+      Value::from("Porto"),
+      Value::from("new"),
+      Value::from("Lisbon,Porto")
+    ]
+  );
+  assert_eq!(
+    row("SELECT string_agg(name, NULL ORDER BY name DESC), group_concat(age, id) FROM person"),
+    [Value::from("doracarlabrunoana"), Value::from("30230330441")]
+  );
+  assert_eq!(
+    conn
+      .query("SELECT sum(9223372036854775807) FROM person")
+      .unwrap_err()
+      .to_string(),
+    "integer overflow"
+  );
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same expressions,
+/// with the local time zone left out.
+#[test]
+fn ok_on_date_and_time_functions() {
+  let null = Value::Null;
+  let cases: [(&str, Value); 26] = [
+    ("date('2023-02-31')", "2023-03-03".into()),
+    (
+      "datetime('2024-01-15 13:45:30+05:30')",
+      "2024-01-15 08:15:30".into(),
+    ),
+    ("datetime('2024-01-01 24:00')", "2024-01-01 24:00:00".into()),
+    ("time('13:45:61')", null.clone()),
+    ("time(0.5)", "00:00:00".into()),
+    (
+      "unixepoch('2000-01-01 00:00:00.25', 'subsec')",
+      946684800.25.into(),
+    ),
+    (
+      "datetime(1700000000.5, 'unixepoch', 'subsec')",
+      "2023-11-14 22:13:20.500".into(),
+    ),
+    ("datetime(1700000000000, 'auto')", null.clone()),
+    ("datetime(5373484.5)", null.clone()),
+    ("date('2024-01-31', '+1 month')", "2024-03-02".into()),
+    (
+      "date('2024-01-31', '+1 month', 'floor')",
+      "2024-02-29".into(),
+    ),
+    (
+      "date('2024-02-29', '+1 year', 'floor')",
+      "2025-02-28".into(),
+    ),
+    (
+      "datetime('2024-01-01', '+1.5 days')",
+      "2024-01-02 12:00:00".into(),
+    ),
+    (
+      "datetime('2024-01-01', '-01:30:15.5')",
+      "2023-12-31 22:29:44".into(),
+    ),
+    (
+      "datetime('2024-01-01 10:00', '-0001-02-03 04:05:06')",
+      "2022-10-29 05:54:54".into(),
+    ),
+    ("date('2024-01-17', 'weekday 0')", "2024-01-21".into()),
+    ("date('2024-05-17', 'start of year')", "2024-01-01".into()),
+    ("date('2024-01-01', 'bogus')", null.clone()),
+    (
+      "strftime('%j %w %u %W %U %V %G %g', '2021-01-03')",
+      "003 0 7 00 01 53 2020 20".into(),
+    ),
+    (
+      "strftime('%s %J %e %k %l %I %p %R', '2024-07-04 20:05:09')",
+      "1720123509 2460496.336909722  4 20  8 08 PM 20:05".into(),
+    ),
+    (
+      "strftime('%f', '2024-01-01 00:00:59.9999')",
+      "59.999".into(),
+    ),
+    ("strftime('%F', '-0044-03-15')", "-044-03-15".into()),
+    ("strftime('%y', '2024-07-04')", null.clone()),
+    (
+      "timediff('2024-01-15 12:00:00.5', '2023-02-28 00:00')",
+      "+0000-10-18 12:00:00.500".into(),
+    ),
+    (
+      "timediff('2024-01-15', '2024-03-01')",
+      "-0000-01-17 00:00:00.000".into(),
+    ),
+    ("typeof(julianday('now'))", "real".into()),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+
+  // `now` is the same for the whole statement.
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let rows = conn
+    .query("SELECT datetime('now') = CURRENT_TIMESTAMP, date() = CURRENT_DATE")
+    .unwrap()
+    .into_rows();
+  assert_eq!(rows, [[Value::from(1), Value::from(1)]]);
+}
+
+/// Expected values are the output of sqlite3 3.51 for the same expressions.
+#[test]
+fn ok_on_json_functions() {
+  let null = Value::Null;
+  let cases: [(&str, Value); 24] = [
+    (
+      r#"json(' { "a" : [1, 2.50, true] } ')"#,
+      r#"{"a":[1,2.50,true]}"#.into(),
+    ),
+    (
+      r#"json('{a:1,''b'':0x1F,c:.5,d:+1,e:Infinity,f:NaN,g:[1,2,],}')"#,
+      r#"{"a":1,"b":31,"c":0.5,"d":1,"e":9e999,"f":null,"g":[1,2]}"#.into(),
+    ),
+    (
+      "json_array(1, 2.5, 'a', NULL, json('[1]'), '[1]', 1e300)",
+      r#"[1,2.5,"a",null,[1],"[1]",1.0e+300]"#.into(),
+    ),
+    (
+      r#"json_object('a', 1, 'b', json_array(1, 2), 'c', 'x"y')"#,
+      r#"{"a":1,"b":[1,2],"c":"x\"y"}"#.into(),
+    ),
+    (
+      r#"json_extract('{"a":{"b":[1,2,3]}}', '$.a.b[#-1]')"#,
+      3.into(),
+    ),
+    (
+      r#"json_extract('{"a":1}', '$.a', '$.x')"#,
+      "[1,null]".into(),
+    ),
+    (
+      "json_extract('[99999999999999999999]', '$[0]')",
+      1e20.into(),
+    ),
+    (r#"'{"a":{"b":2}}' -> 'a'"#, r#"{"b":2}"#.into()),
+    (r#"'{"a":{"b":"x"}}' ->> '$.a.b'"#, "x".into()),
+    ("'[1,2,3]' ->> -1", 3.into()),
+    (
+      r#"json_set('{"a":1}', '$.a', 2, '$.b.c', 3)"#,
+      r#"{"a":2,"b":{"c":3}}"#.into(),
+    ),
+    (
+      r#"json_insert('{"a":1}', '$.a', 2, '$.b', json('[1]'))"#,
+      r#"{"a":1,"b":[1]}"#.into(),
+    ),
+    (
+      "json_replace('[1,2]', '$[1]', 3, '$[#]', 4)",
+      "[1,3]".into(),
+    ),
+    ("json_set('[1]', NULL, 2, '$[0]', 3)", "[3]".into()),
+    ("json_remove('[1,2,3]', '$[1]', '$[0]')", "[3]".into()),
+    ("json_remove('[1]', '$')", null.clone()),
+    (
+      r#"json_patch('{"a":1,"b":{"c":2}}', '{"b":{"c":null,"d":3},"e":{"f":null}}')"#,
+      r#"{"a":1,"b":{"d":3},"e":{}}"#.into(),
+    ),
+    (r#"json_type('{"a":[1,2.5]}', '$.a[1]')"#, "real".into()),
+    ("json_type('[1]', '$[9]')", null.clone()),
+    ("json_valid('{a:1}')", 0.into()),
+    ("json_valid('{a:1}', 2)", 1.into()),
+    ("json_valid(5)", 1.into()),
+    (
+      r#"json('[''a\x41\v"'', -0x10, 5.]')"#,
+      r#"["a\u0041\u000b\"",-16,5.0]"#.into(),
+    ),
+    ("json(NULL)", null),
+  ];
+  for (sql, expected) in cases {
+    assert_eq!(eval(sql), expected, "{sql}");
+  }
+
+  let conn = SqliteConnection::open("sqlite://./data/added-columns.db").unwrap();
+  let row = |sql: &str| conn.query(sql).unwrap().into_rows().remove(0);
+  assert_eq!(
+    row("SELECT json_group_array(age), json_group_object(name, json_array(id)) FROM person"),
+    [
+      Value::from("[30,30,30,41]"),
+      Value::from(r#"{"ana":[1],"bruno":[2],"carla":[3],"dora":[4]}"#)
+    ]
+  );
+  assert_eq!(
+    row("SELECT json_group_array(age), json_group_object(name, age) FROM person WHERE 0"),
+    [Value::from("[]"), Value::from("{}")]
+  );
+}
+
+#[test]
+fn err_on_bad_json() {
+  let conn = SqliteConnection::open("sqlite://./data/flights-populated.db").unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(error("SELECT json('[1,')"), "malformed JSON");
+  assert_eq!(
+    error("SELECT json_extract('[1]', 'a')"),
+    "bad JSON path: 'a'"
+  );
+  assert_eq!(
+    error("SELECT json_object('a')"),
+    "json_object() requires an even number of arguments"
+  );
+  assert_eq!(
+    error("SELECT json_set('[1]', '$[0]')"),
+    "json_set() needs an odd number of arguments"
+  );
+  assert_eq!(
+    error("SELECT json_array(x'ff')"),
+    "JSON cannot hold BLOB values"
+  );
+}
@@ -0,0 +1,169 @@
+//! # Window functions
+//!
+//!  A window function computes a value for each row of a partition from the
+//! rows around it. The ranking functions look at where the row falls in the
+//! ORDER BY of its window, counting its peers, the rows with the same ORDER
+//! BY values, as one. The offset functions read the arguments of another
+//! row: `lag()` and `lead()` one of the partition, and `first_value()`,
+//! `last_value()` and `nth_value()` one of the frame.
+//!
+//! *Reference:* https://www.sqlite.org/windowfunctions.html#builtins
+
+use core::ops::Range;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  value::{Affinity, Value},
+};
+
+/// The rows a window function sees when it computes the value of one row.
+pub(crate) struct WindowFrame<'a> {
+  /// The arguments of the call on every row of the partition, in the order
+  /// of the window.
+  pub(crate) args: &'a [Vec<Value>],
+  /// The current row.
+  pub(crate) row: usize,
+  /// The current row and its peers.
+  pub(crate) peers: Range<usize>,
+  /// How many groups of peers come before that of the current row.
+  pub(crate) group: usize,
+  /// The rows of the frame, before the exclusions.
+  pub(crate) frame: Range<usize>,
+  /// The rows the frame leaves out, by its `EXCLUDE` clause.
+  pub(crate) excluded: [Range<usize>; 2],
+}
+
+impl WindowFrame<'_> {
+  /// The rows of the frame, in order.
+  pub(crate) fn rows(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    self
+      .frame
+      .clone()
+      .filter(|row| !self.excluded.iter().any(|range| range.contains(row)))
+  }
+
+  fn arg(&self, row: usize, index: usize) -> Value {
+    self.args[row].get(index).cloned().unwrap_or(Value::Null)
+  }
+}
+
+pub(super) fn row_number(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(Value::Integer(frame.row as i64 + 1))
+}
+
+/// The row number of the first peer.
+pub(super) fn rank(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(Value::Integer(frame.peers.start as i64 + 1))
+}
+
+/// The number of the group of peers.
+pub(super) fn dense_rank(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(Value::Integer(frame.group as i64 + 1))
+}
+
+/// `(rank - 1) / (rows - 1)`, or 0 for a single row.
+pub(super) fn percent_rank(frame: &WindowFrame) -> SqliteResult<Value> {
+  let count = frame.args.len();
+  Ok(Value::Real(match count {
+    0 | 1 => 0.0,
+    _ => frame.peers.start as f64 / (count - 1) as f64,
+  }))
+}
+
+/// The share of rows up to the last peer.
+pub(super) fn cume_dist(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(Value::Real(
+    frame.peers.end as f64 / frame.args.len() as f64,
+  ))
+}
+
+/// `ntile(N)` splits the partition in `N` buckets as even as possible, the
+/// larger ones first, and numbers them from 1.
+pub(super) fn ntile(frame: &WindowFrame) -> SqliteResult<Value> {
+  let buckets = match frame.arg(frame.row, 0) {
+    Value::Integer(buckets) if buckets > 0 => buckets as usize,
+    _ => {
+      return Err(SqliteError::Custom(
+        "argument of ntile must be a positive integer".into(),
+      ))
+    }
+  };
+  let count = frame.args.len();
+  let size = count / buckets;
+  let larger = count % buckets;
+  // The first `larger` buckets hold one more row.
+  let bucket = match frame.row < larger * (size + 1) {
+    true => frame.row / (size + 1),
+    false => larger + (frame.row - larger * (size + 1)) / size.max(1),
+  };
+  Ok(Value::Integer(bucket as i64 + 1))
+}
+
+/// `lag(X, [offset, [default]])`: `X` of the row `offset` rows before, 1 by
+/// default, or `default` when there is none.
+pub(super) fn lag(frame: &WindowFrame) -> SqliteResult<Value> {
+  offset_row(frame, -1)
+}
+
+/// `lead(X, [offset, [default]])`: `X` of the row `offset` rows after.
+pub(super) fn lead(frame: &WindowFrame) -> SqliteResult<Value> {
+  offset_row(frame, 1)
+}
+
+fn offset_row(frame: &WindowFrame, direction: i64) -> SqliteResult<Value> {
+  let offset = match frame.args[frame.row].get(1) {
+    None => 1,
+    Some(Value::Integer(offset)) => *offset,
+    Some(Value::Null) => return Ok(Value::Null),
+    Some(value) => value
+      .cast(Affinity::Integer)
+      .as_integer()
+      .unwrap_or_default(),
+  };
+  let row = offset
+    .checked_mul(direction)
+    .and_then(|offset| (frame.row as i64).checked_add(offset))
+    .and_then(|row| usize::try_from(row).ok())
+    .filter(|row| *row < frame.args.len());
+  Ok(match row {
+    Some(row) => frame.arg(row, 0),
+    None => frame.arg(frame.row, 2),
+  })
+}
+
+pub(super) fn first_value(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(
+    frame
+      .rows()
+      .next()
+      .map_or(Value::Null, |row| frame.arg(row, 0)),
+  )
+}
+
+pub(super) fn last_value(frame: &WindowFrame) -> SqliteResult<Value> {
+  Ok(
+    frame
+      .rows()
+      .next_back()
+      .map_or(Value::Null, |row| frame.arg(row, 0)),
+  )
+}
+
+/// `nth_value(X, N)`: `X` of the `N`-th row of the frame, counting from 1.
+pub(super) fn nth_value(frame: &WindowFrame) -> SqliteResult<Value> {
+  let n = match frame.arg(frame.row, 1) {
+    Value::Integer(n) if n > 0 => n as usize,
+    Value::Real(n) if n >= 1.0 && n.fract() == 0.0 => n as usize,
+    _ => {
+      return Err(SqliteError::Custom(
+        "second argument to nth_value must be a positive integer".into(),
+      ))
+    }
+  };
+  Ok(
+    frame
+      .rows()
+      .nth(n - 1)
+      .map_or(Value::Null, |row| frame.arg(row, 0)),
+  )
+}
@@ -0,0 +1,143 @@
+//! # Index cursors
+//!
+//!  An index cursor reads the entries of an index b-tree in key order. Each
+//! entry holds the indexed values of a row, followed by its rowid, or by its
+//! PRIMARY KEY for a WITHOUT ROWID table.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#index_btree_pages
+
+#[cfg(test)]
+mod tests;
+
+use core::cmp::Ordering;
+
+use crate::{
+  btree::BtreeCursor, file_header::DatabaseTextEncoding, pager::SqlitePager, record::Record,
+  result::SqliteResult, value::Value,
+};
+
+use super::{
+  collation::Collation,
+  schema::{IndexSchema, SortOrder},
+  table_cursor::compare_key,
+};
+
+#[derive(Debug)]
+pub struct IndexCursor<'a> {
+  cursor: BtreeCursor<'a>,
+  index: &'a IndexSchema,
+  encoding: DatabaseTextEncoding,
+  /// The order and collation of each key column.
+  orders: Vec<(SortOrder, Collation)>,
+}
+
+impl<'a> IndexCursor<'a> {
+  /// Opens a cursor over an index, given the collation of each of its keys.
+  pub fn new(
+    pager: &'a SqlitePager,
+    index: &'a IndexSchema,
+    encoding: DatabaseTextEncoding,
+    collations: Vec<Collation>,
+  ) -> Self {
+    Self {
+      cursor: BtreeCursor::new(pager, index.root_page()),
+      index,
+      encoding,
+      orders: index
+        .columns()
+        .iter()
+        .map(|key| key.order().unwrap_or_default())
+        .zip(collations)
+        .collect(),
+    }
+  }
+
+  pub fn index(&self) -> &IndexSchema {
+    self.index
+  }
+
+  /// Returns `true` while the cursor points to an entry.
+  pub fn is_valid(&self) -> bool {
+    self.cursor.is_valid()
+  }
+
+  /// Moves to the first entry, returning `false` if the index is empty.
+  pub fn rewind(&mut self) -> SqliteResult<bool> {
+    self.cursor.rewind()
+  }
+
+  /// Moves to the next entry, returning `false` past the last one.
+  pub fn advance(&mut self) -> SqliteResult<bool> {
+    self.cursor.advance()
+  }
+
+  /// Moves to the last entry, returning `false` if the index is empty.
+  pub fn last(&mut self) -> SqliteResult<bool> {
+    self.cursor.last()
+  }
+
+  /// Moves to the previous entry, returning `false` before the first one.
+  pub fn retreat(&mut self) -> SqliteResult<bool> {
+    self.cursor.retreat()
+  }
+
+  /// The values of the current entry.
+  pub fn entry(&self) -> SqliteResult<Vec<Value>> {
+    Ok(Record::decode(&self.cursor.payload()?, &self.encoding)?.into_values())
+  }
+
+  /// The rowid the current entry points to, which is its last value.
+  pub fn rowid(&self) -> SqliteResult<Option<i64>> {
+    Ok(match self.entry()?.pop() {
+      Some(Value::Integer(rowid)) => Some(rowid),
+      _ => None,
+    })
+  }
+
+  /// Positions the cursor on the first entry whose leading values are
+  /// greater than or equal to `key`, in index order, returning whether
+  /// there is one.
+  pub fn seek_ge(&mut self, key: &[Value]) -> SqliteResult<bool> {
+    let orders = &self.orders;
+    let encoding = self.encoding;
+    self.cursor.seek_index_ge(|payload| {
+      let record = Record::decode(payload, &encoding)?;
+      Ok(compare_key(record.values(), key, orders))
+    })
+  }
+
+  /// Positions the cursor on the last entry whose leading values are less
+  /// than `key`, or equal to it when `is_inclusive`, in index order,
+  /// returning whether there is one.
+  pub fn seek_le(&mut self, key: &[Value], is_inclusive: bool) -> SqliteResult<bool> {
+    let orders = &self.orders;
+    let encoding = self.encoding;
+    let compare = |payload: &[u8]| {
+      let record = Record::decode(payload, &encoding)?;
+      Ok(compare_key(record.values(), key, orders))
+    };
+    match is_inclusive {
+      true => self.cursor.seek_index_le(compare),
+      false => self.cursor.seek_index_lt(compare),
+    }
+  }
+
+  /// Positions the cursor on the first entry whose leading values equal
+  /// `key`, returning whether there is one.
+  pub fn seek_eq(&mut self, key: &[Value]) -> SqliteResult<bool> {
+    Ok(self.seek_ge(key)? && self.compare_entry(key)? == Ordering::Equal)
+  }
+
+  /// Moves to the next entry, returning whether its leading values still
+  /// equal `key`.
+  pub fn advance_eq(&mut self, key: &[Value]) -> SqliteResult<bool> {
+    Ok(self.advance()? && self.compare_entry(key)? == Ordering::Equal)
+  }
+
+  /// Orders the leading values of the current entry against `key`, in index
+  /// order.
+  pub fn compare_entry(&self, key: &[Value]) -> SqliteResult<Ordering> {
+    let record = Record::decode(&self.cursor.payload()?, &self.encoding)?;
+    Ok(compare_key(record.values(), key, &self.orders))
+  }
+}
@@ -0,0 +1,47 @@
+//! Tests for index cursors
+//!
+//! To run: `cargo test runtime::index_cursor`
+
+use crate::{value::Value, SqliteConnection};
+
+const JOINS_DB: &str = "sqlite://./data/joins.db";
+
+#[test]
+fn ok_on_index_scan_and_seek() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let mut cursor = conn.index_cursor("assignment_project").unwrap();
+
+  let mut entries = vec![];
+  let mut has_entry = cursor.rewind().unwrap();
+  while has_entry {
+    entries.push(cursor.entry().unwrap());
+    has_entry = cursor.advance().unwrap();
+  }
+  // Spread over several pages, ordered by project, then by employee
+  // descending.
+  assert_eq!(entries.len(), 306);
+  for pair in entries.windows(2) {
+    let (a, b) = (&pair[0], &pair[1]);
+    assert!(a[0].compare(&b[0]).then(b[1].compare(&a[1])).is_lt());
+  }
+
+  let key = [Value::from("apollo")];
+  let mut rowids = vec![];
+  let mut found = cursor.seek_eq(&key).unwrap();
+  while found {
+    rowids.push(cursor.rowid().unwrap());
+    found = cursor.advance_eq(&key).unwrap();
+  }
+  assert_eq!(rowids, [Some(2), Some(1), Some(6)]);
+
+  let key = [Value::from("bulk3"), Value::from(103)];
+  assert!(cursor.seek_eq(&key).unwrap());
+  assert_eq!(
+    cursor.entry().unwrap(),
+    [Value::from("bulk3"), Value::from(103), Value::from(9)]
+  );
+  assert!(!cursor.advance_eq(&key).unwrap());
+
+  assert!(!cursor.seek_eq(&[Value::from("atlantis")]).unwrap());
+  assert!(!cursor.seek_eq(&[Value::from("zzz")]).unwrap());
+}
@@ -1,2 +1,166 @@
+//! # The Schema Table
+//!
+//!  Every SQLite database contains a single "schema table" that stores the
+//! schema for that database. The schema for a database is a description of
+//! all of the other tables, indexes, triggers, and views that are contained
+//! within the database. The schema table looks like this:
+//!
+//! ```sql
+//! CREATE TABLE sqlite_schema(
+//!   type text,
+//!   name text,
+//!   tbl_name text,
+//!   rootpage integer,
+//!   sql text
+//! );
+//! ```
+//!
+//!  The sqlite_schema table contains one row for each table, index, view, and
+//! trigger (collectively "objects") in the schema, except there is no entry
+//! for the sqlite_schema table itself. Its b-tree always has its root on
+//! page 1. The sqlite_schema table is also accessible as sqlite_master.
+//!
+//! *Reference:* https://www.sqlite.org/schematab.html
+
+use crate::{
+  btree::BtreeCursor,
+  file_header::DatabaseTextEncoding,
+  pager::SqlitePager,
+  record::Record,
+  result::{SqliteError, SqliteResult},
+  value::Value,
+};
+
+/// Root page of the sqlite_schema table.
+pub(crate) const SQLITE_SCHEMA_ROOT_PAGE: u32 = 1;
+
 #[derive(Debug, Default)]
-pub(super) struct SqliteMaster(());
+pub(crate) struct SqliteMaster(Vec<SchemaObject>);
+
+impl SqliteMaster {
+  /// Reads every row of the sqlite_schema table.
+  pub(crate) fn read(pager: &SqlitePager, encoding: &DatabaseTextEncoding) -> SqliteResult<Self> {
+    let mut objects = vec![];
+    let mut cursor = BtreeCursor::new(pager, SQLITE_SCHEMA_ROOT_PAGE);
+    let mut has_row = cursor.rewind()?;
+    while has_row {
+      let record = Record::decode(&cursor.payload()?, encoding)?;
+      objects.push(SchemaObject::try_from(record)?);
+      has_row = cursor.advance()?;
+    }
+    trace!("[{}] schema objects read.", objects.len());
+    Ok(Self(objects))
+  }
+
+  pub(crate) fn into_objects(self) -> Vec<SchemaObject> {
+    self.0
+  }
+}
+
+/// A row of the sqlite_schema table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaObject {
+  object_type: SchemaObjectType,
+  name: String,
+  tbl_name: String,
+  root_page: u32,
+  sql: Option<String>,
+}
+
+impl SchemaObject {
+  pub fn object_type(&self) -> SchemaObjectType {
+    self.object_type
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The name of the table or view that the object is associated with.
+  pub fn tbl_name(&self) -> &str {
+    &self.tbl_name
+  }
+
+  /// Page number of the root b-tree page, or `0` for views, triggers and
+  /// virtual tables.
+  pub fn root_page(&self) -> u32 {
+    self.root_page
+  }
+
+  /// The original CREATE statement, or `None` for automatically created
+  /// indexes.
+  pub fn sql(&self) -> Option<&str> {
+    self.sql.as_deref()
+  }
+}
+
+impl TryFrom<Record> for SchemaObject {
+  type Error = SqliteError;
+
+  fn try_from(record: Record) -> Result<Self, Self::Error> {
+    let err = || SqliteError::Custom("Malformed sqlite_schema row".into());
+    let mut values = record.into_values().into_iter();
+    let mut next_text = || match values.next() {
+      Some(Value::Text(text)) => Ok(Some(text)),
+      Some(Value::Null) => Ok(None),
+      _ => Err(err()),
+    };
+    let object_type = next_text()?.ok_or_else(err)?.parse()?;
+    let name = next_text()?.ok_or_else(err)?;
+    let tbl_name = next_text()?.ok_or_else(err)?;
+    let root_page = match values.next() {
+      Some(Value::Integer(root_page)) => u32::try_from(root_page).map_err(|_| err())?,
+      Some(Value::Null) | None => 0,
+      _ => return Err(err()),
+    };
+    let sql = match values.next() {
+      Some(Value::Text(sql)) => Some(sql),
+      Some(Value::Null) | None => None,
+      _ => return Err(err()),
+    };
+    Ok(Self {
+      object_type,
+      name,
+      tbl_name,
+      root_page,
+      sql,
+    })
+  }
+}
+
+/// The `type` column of sqlite_schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaObjectType {
+  Table,
+  Index,
+  View,
+  Trigger,
+}
+
+impl std::str::FromStr for SchemaObjectType {
+  type Err = SqliteError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "table" => Ok(Self::Table),
+      "index" => Ok(Self::Index),
+      "view" => Ok(Self::View),
+      "trigger" => Ok(Self::Trigger),
+      _ => Err(SqliteError::Custom(format!(
+        "Unknown sqlite_schema object type [{s}]"
+      ))),
+    }
+  }
+}
+
+impl core::fmt::Display for SchemaObjectType {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let name = match self {
+      Self::Table => "table",
+      Self::Index => "index",
+      Self::View => "view",
+      Self::Trigger => "trigger",
+    };
+    write!(f, "{name}")
+  }
+}
@@ -1,2 +1,118 @@
-#[derive(Debug, Default)]
-pub(super) struct SqliteSequence(());
+//! # The sqlite_sequence Table
+//!
+//!  SQLite keeps track of the largest ROWID that a table has ever held using
+//! an internal table named "sqlite_sequence". The sqlite_sequence table is
+//! created and initialized automatically whenever a normal table that
+//! contains an AUTOINCREMENT column is created:
+//!
+//! ```sql
+//! CREATE TABLE sqlite_sequence(name,seq);
+//! ```
+//!
+//!  The content of the table is modified by INSERTs and UPDATEs to keep the
+//! largest sequence of each AUTOINCREMENT table, so that ROWIDs are never
+//! reused, even after the rows that held them are deleted.
+//!
+//! *Reference:* https://www.sqlite.org/autoinc.html
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use crate::{
+  btree::BtreeCursor,
+  file_header::DatabaseTextEncoding,
+  pager::SqlitePager,
+  record::Record,
+  result::{SqliteError, SqliteResult},
+  runtime::SqliteSchema,
+  value::{Affinity, Value},
+};
+
+/// Name of the table holding the AUTOINCREMENT sequences.
+pub(crate) const SQLITE_SEQUENCE_TABLE_NAME: &str = "sqlite_sequence";
+
+/// The last assigned sequence of every AUTOINCREMENT table, by table name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SqliteSequence(BTreeMap<String, i64>);
+
+impl SqliteSequence {
+  /// Reads the sqlite_sequence table. A database without AUTOINCREMENT tables
+  /// has no such table and no sequences.
+  pub(crate) fn read(
+    pager: &SqlitePager,
+    schema: &SqliteSchema,
+    encoding: &DatabaseTextEncoding,
+  ) -> SqliteResult<Self> {
+    let mut sequences = BTreeMap::new();
+    let Some(table) = schema.table(SQLITE_SEQUENCE_TABLE_NAME) else {
+      return Ok(Self(sequences));
+    };
+
+    let mut cursor = BtreeCursor::new(pager, table.root_page());
+    let mut has_row = cursor.rewind()?;
+    while has_row {
+      let record = Record::decode(&cursor.payload()?, encoding)?;
+      // The table has no column types, so `seq` is read as an integer the
+      // way SQLite reads it: '5x' is 5 and NULL is 0. A row without the
+      // name of a table is no table's sequence.
+      if let [Value::Text(name), seq, ..] = record.values() {
+        let seq = seq.cast_in(Affinity::Integer, *encoding);
+        sequences.insert(name.clone(), seq.as_integer().unwrap_or_default());
+      }
+      has_row = cursor.advance()?;
+    }
+    trace!("[{}] sequences read.", sequences.len());
+    Ok(Self(sequences))
+  }
+
+  /// The last sequence assigned to `table_name`, ignoring ASCII case.
+  pub fn get(&self, table_name: &str) -> Option<i64> {
+    self
+      .0
+      .iter()
+      .find(|(name, _)| name.eq_ignore_ascii_case(table_name))
+      .map(|(_, seq)| *seq)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, i64)> {
+    self.0.iter().map(|(name, seq)| (name.as_str(), *seq))
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// The ROWID for a new row of an AUTOINCREMENT table whose largest current
+  /// ROWID is `max_rowid`: one more than the largest ROWID the table has ever
+  /// held. Fails once the largest possible ROWID has been used.
+  pub fn next_rowid(&self, table_name: &str, max_rowid: Option<i64>) -> SqliteResult<i64> {
+    let largest = self
+      .get(table_name)
+      .unwrap_or_default()
+      .max(max_rowid.unwrap_or_default());
+    largest.checked_add(1).ok_or_else(|| {
+      SqliteError::Custom(format!(
+        "database or disk is full: no ROWID left for [{table_name}]"
+      ))
+    })
+  }
+
+  /// Records that `rowid` was inserted into `table_name`. The sequence only
+  /// ever grows.
+  pub fn update(&mut self, table_name: &str, rowid: i64) {
+    let name = self
+      .0
+      .keys()
+      .find(|name| name.eq_ignore_ascii_case(table_name))
+      .cloned()
+      .unwrap_or_else(|| table_name.to_string());
+    let seq = self.0.entry(name).or_default();
+    *seq = (*seq).max(rowid);
+  }
+}
@@ -0,0 +1,50 @@
+//! Tests for the sqlite_sequence table
+//!
+//! To run: `cargo test sqlite_sequence`
+
+use super::SqliteSequence;
+use crate::SqliteConnection;
+
+#[test]
+fn ok_on_read_sqlite_sequence() {
+  let conn = SqliteConnection::open("sqlite://./data/mydatabase.db").unwrap();
+  let sequence = conn.sqlite_sequence();
+  assert_eq!(sequence.len(), 1);
+  assert_eq!(sequence.get("users"), Some(2));
+  assert_eq!(sequence.get("USERS"), Some(2));
+  assert_eq!(sequence.get("Observation"), None);
+
+  let conn = SqliteConnection::open("sqlite://./data/flights-initial.db").unwrap();
+  assert!(conn.sqlite_sequence().is_empty());
+}
+
+#[test]
+fn ok_on_autoincrement_rules() {
+  let mut sequence = SqliteSequence::default();
+  assert_eq!(sequence.next_rowid("t", None).unwrap(), 1);
+  assert_eq!(sequence.next_rowid("t", Some(5)).unwrap(), 6);
+
+  sequence.update("t", 10);
+  // Deleted rows keep their ROWIDs reserved.
+  assert_eq!(sequence.next_rowid("t", Some(3)).unwrap(), 11);
+  assert_eq!(sequence.next_rowid("T", Some(20)).unwrap(), 21);
+
+  sequence.update("T", 4);
+  assert_eq!(sequence.get("t"), Some(10));
+  assert_eq!(sequence.len(), 1);
+
+  sequence.update("t", i64::MAX);
+  assert!(sequence.next_rowid("t", None).is_err());
+}
+
+#[test]
+fn ok_on_read_untyped_sequences() {
+  let conn = SqliteConnection::open("sqlite://./data/sequence.db").unwrap();
+  let sequence = conn.sqlite_sequence();
+  assert_eq!(
+    sequence.iter().collect::<Vec<_>>(),
+    [("a", 5), ("b", 7), ("c", 0)]
+  );
+  assert_eq!(sequence.next_rowid("a", Some(1)).unwrap(), 6);
+  assert_eq!(sequence.next_rowid("c", Some(1)).unwrap(), 2);
+}
@@ -0,0 +1,118 @@
+//! # The sqlite_stat1 Table
+//!
+//!  ANALYZE gathers statistics about the tables and indexes of a database
+//! into an internal table named "sqlite_stat1", which the query planner
+//! reads to estimate how many rows each way of reading a table visits:
+//!
+//! ```sql
+//! CREATE TABLE sqlite_stat1(tbl,idx,stat);
+//! ```
+//!
+//!  The `stat` of an index is a list of integers: the number of rows of the
+//! table, followed by the average number of rows that share the same values
+//! in the first column of the index, in its first two columns, and so on.
+//! The PRIMARY KEY of a WITHOUT ROWID table goes by the name of the table,
+//! and a table without indexes has a row with a NULL `idx` holding only its
+//! number of rows. Words after the integers, such as `unordered`, are
+//! ignored, and so are rows that cannot be read.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#stat1tab
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+  btree::BtreeCursor, file_header::DatabaseTextEncoding, pager::SqlitePager, record::Record,
+  result::SqliteResult, runtime::SqliteSchema, value::Value,
+};
+
+/// Name of the table holding the statistics of ANALYZE.
+pub(crate) const SQLITE_STAT1_TABLE_NAME: &str = "sqlite_stat1";
+
+/// A row of sqlite_stat1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stat {
+  table: String,
+  index: Option<String>,
+  values: Vec<u64>,
+}
+
+/// The statistics ANALYZE has gathered, if any.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SqliteStat1(Vec<Stat>);
+
+impl SqliteStat1 {
+  /// Reads the sqlite_stat1 table. A database that was never analyzed has
+  /// no such table and no statistics.
+  pub(crate) fn read(
+    pager: &SqlitePager,
+    schema: &SqliteSchema,
+    encoding: &DatabaseTextEncoding,
+  ) -> SqliteResult<Self> {
+    let mut stats = vec![];
+    let Some(table) = schema.table(SQLITE_STAT1_TABLE_NAME) else {
+      return Ok(Self(stats));
+    };
+
+    let mut cursor = BtreeCursor::new(pager, table.root_page());
+    let mut has_row = cursor.rewind()?;
+    while has_row {
+      let record = Record::decode(&cursor.payload()?, encoding)?;
+      if let [Value::Text(table), index, Value::Text(stat), ..] = record.values() {
+        let values: Vec<u64> = stat
+          .split(' ')
+          .map_while(|word| word.parse().ok())
+          .collect();
+        let index = match index {
+          Value::Text(index) => Some(index.clone()),
+          _ => None,
+        };
+        if !values.is_empty() {
+          stats.push(Stat {
+            table: table.clone(),
+            index,
+            values,
+          });
+        }
+      }
+      has_row = cursor.advance()?;
+    }
+    trace!("[{}] statistics read.", stats.len());
+    Ok(Self(stats))
+  }
+
+  /// The number of rows of a table when it was analyzed, ignoring ASCII
+  /// case.
+  pub fn table_rows(&self, table_name: &str) -> Option<u64> {
+    self
+      .0
+      .iter()
+      .find(|stat| stat.table.eq_ignore_ascii_case(table_name))
+      .map(|stat| stat.values[0])
+  }
+
+  /// The statistics of an index, or of the PRIMARY KEY of a WITHOUT ROWID
+  /// table by its name: the number of rows of the table, then the average
+  /// number of rows per value of each prefix of its columns.
+  pub fn index(&self, table_name: &str, index_name: &str) -> Option<&[u64]> {
+    self
+      .0
+      .iter()
+      .find(|stat| {
+        stat.table.eq_ignore_ascii_case(table_name)
+          && stat
+            .index
+            .as_deref()
+            .is_some_and(|index| index.eq_ignore_ascii_case(index_name))
+      })
+      .map(|stat| &stat.values[..])
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
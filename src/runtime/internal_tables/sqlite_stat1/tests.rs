@@ -0,0 +1,25 @@
+//! Tests for the sqlite_stat1 table
+//!
+//! To run: `cargo test sqlite_stat1`
+
+use crate::SqliteConnection;
+
+#[test]
+fn ok_on_read_sqlite_stat1() {
+  let conn = SqliteConnection::open("sqlite://./data/planner.db").unwrap();
+  let stat1 = conn.sqlite_stat1();
+  assert_eq!(stat1.len(), 5);
+  assert_eq!(stat1.table_rows("item"), Some(2000));
+  assert_eq!(stat1.table_rows("NOTE"), Some(3));
+  assert_eq!(
+    stat1.index("item", "item_category"),
+    Some(&[2000, 100, 20][..])
+  );
+  // The PRIMARY KEY of a WITHOUT ROWID table goes by the table's name.
+  assert_eq!(stat1.index("tag", "tag"), Some(&[2200, 1, 1][..]));
+  assert_eq!(stat1.index("note", "note"), None);
+
+  let conn = SqliteConnection::open("sqlite://./data/indexes.db").unwrap();
+  assert!(conn.sqlite_stat1().is_empty());
+  assert_eq!(conn.sqlite_stat1().table_rows("a"), None);
+}
@@ -0,0 +1,780 @@
+//! # PRAGMAs
+//!
+//!  A pragma reads a field of the database header, or describes the schema:
+//! the columns of a table, the keys of an index, its foreign keys. Each one is
+//! a table-valued function named `pragma_` and the name of the pragma, whose
+//! hidden columns hold its argument and the schema it reads, so that a query
+//! may join it like a table. The `PRAGMA` statement runs the same function.
+//!
+//!  `user_version`, `application_id` and `schema_version` may be set: the
+//! field is written to the header on page 1, and the file change counter
+//! moves on as it does for any change to the database. A pragma that only
+//! takes effect when a database is created is ignored. Only the main
+//! database has tables, `temp` is always empty, and unknown pragmas do
+//! nothing, as in SQLite.
+//!
+//!  The `PRAGMA` statement compiles to a program: one that reads the rows of
+//! the function when it runs, or that writes the field of the header.
+//!
+//! *Reference:* https://www.sqlite.org/pragma.html
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+  file_header::{DatabaseTextEncoding, FileFormatWriteVersion},
+  io::SqliteIoMode,
+  result::{SqliteError, SqliteResult},
+  sql::{ast, parser::MAX_VARIABLE_NUMBER},
+  value::Value,
+};
+
+use super::{
+  functions::{functions, FunctionKind, TableFunction, TableFunctionDef},
+  schema::{
+    ColumnDef, ForeignKeyAction, ForeignKeyClause, GeneratedColumnKind, IndexKeyKind, IndexOrigin,
+    SortOrder, TableConstraintKind,
+  },
+  SqliteRuntime,
+};
+
+/// The rows of a pragma, without their hidden columns.
+type Rows = SqliteResult<Vec<Vec<Value>>>;
+
+/// What a `PRAGMA` statement runs.
+pub(crate) enum PragmaAction {
+  /// Reads the rows of the function of the pragma, with these arguments.
+  Read(&'static TableFunctionDef, Vec<Value>),
+  /// Writes a value to the 4-byte field of the header at an offset.
+  Set(usize, i32),
+  /// Returns no rows, with these columns.
+  Empty(Vec<String>),
+}
+
+const fn pragma(
+  name: &'static str,
+  columns: &'static [&'static str],
+  arguments: usize,
+  implementation: TableFunction,
+) -> TableFunctionDef {
+  TableFunctionDef {
+    name,
+    columns,
+    arguments,
+    implementation,
+    is_json: no_json,
+  }
+}
+
+fn no_json(_: usize, _: &[Value]) -> bool {
+  false
+}
+
+pub(crate) const PRAGMA_FUNCTIONS: &[TableFunctionDef] = &[
+  pragma(
+    "pragma_application_id",
+    &["application_id"],
+    0,
+    application_id,
+  ),
+  pragma(
+    "pragma_auto_vacuum",
+    &["auto_vacuum", "schema"],
+    1,
+    auto_vacuum,
+  ),
+  pragma(
+    "pragma_compile_options",
+    &["compile_options"],
+    0,
+    compile_options,
+  ),
+  pragma(
+    "pragma_database_list",
+    &["seq", "name", "file"],
+    0,
+    database_list,
+  ),
+  pragma("pragma_encoding", &["encoding"], 0, encoding),
+  pragma(
+    "pragma_foreign_key_list",
+    &[
+      "id",
+      "seq",
+      "table",
+      "from",
+      "to",
+      "on_update",
+      "on_delete",
+      "match",
+      "arg",
+      "schema",
+    ],
+    2,
+    foreign_key_list,
+  ),
+  pragma(
+    "pragma_freelist_count",
+    &["freelist_count"],
+    0,
+    freelist_count,
+  ),
+  pragma(
+    "pragma_function_list",
+    &["name", "builtin", "type", "enc", "narg", "flags"],
+    0,
+    function_list,
+  ),
+  pragma(
+    "pragma_index_info",
+    &["seqno", "cid", "name", "arg", "schema"],
+    2,
+    index_info,
+  ),
+  pragma(
+    "pragma_index_list",
+    &[
+      "seq", "name", "unique", "origin", "partial", "arg", "schema",
+    ],
+    2,
+    index_list,
+  ),
+  pragma(
+    "pragma_index_xinfo",
+    &[
+      "seqno", "cid", "name", "desc", "coll", "key", "arg", "schema",
+    ],
+    2,
+    index_xinfo,
+  ),
+  pragma(
+    "pragma_journal_mode",
+    &["journal_mode", "schema"],
+    1,
+    journal_mode,
+  ),
+  pragma(
+    "pragma_page_count",
+    &["page_count", "schema"],
+    1,
+    page_count,
+  ),
+  pragma("pragma_page_size", &["page_size", "schema"], 1, page_size),
+  pragma(
+    "pragma_schema_version",
+    &["schema_version"],
+    0,
+    schema_version,
+  ),
+  pragma(
+    "pragma_table_info",
+    &[
+      "cid",
+      "name",
+      "type",
+      "notnull",
+      "dflt_value",
+      "pk",
+      "arg",
+      "schema",
+    ],
+    2,
+    table_info,
+  ),
+  pragma(
+    "pragma_table_list",
+    &[
+      "schema", "name", "type", "ncol", "wr", "strict", "arg", "schema",
+    ],
+    2,
+    table_list,
+  ),
+  pragma(
+    "pragma_table_xinfo",
+    &[
+      "cid",
+      "name",
+      "type",
+      "notnull",
+      "dflt_value",
+      "pk",
+      "hidden",
+      "arg",
+      "schema",
+    ],
+    2,
+    table_xinfo,
+  ),
+  pragma("pragma_user_version", &["user_version"], 0, user_version),
+];
+
+impl SqliteRuntime {
+  /// What a `PRAGMA` statement runs: it reads like the function of the
+  /// pragma, with the value as its argument if it takes one. A value given
+  /// to any other pragma sets it.
+  pub(crate) fn pragma(&self, pragma: &ast::Pragma) -> SqliteResult<PragmaAction> {
+    let Some(function) = PRAGMA_FUNCTIONS
+      .iter()
+      .find(|function| name(function).eq_ignore_ascii_case(&pragma.name.name))
+    else {
+      return Ok(PragmaAction::Empty(vec![]));
+    };
+    let database = match &pragma.name.schema {
+      Some(name) if name.eq_ignore_ascii_case("main") => Value::from(name.as_str()),
+      Some(name) if name.eq_ignore_ascii_case("temp") => {
+        let columns = function.columns[..function.columns.len() - function.arguments]
+          .iter()
+          .map(|&column| column.to_string())
+          .collect();
+        return Ok(PragmaAction::Empty(columns));
+      }
+      Some(name) => return Err(SqliteError::Custom(format!("unknown database {name}"))),
+      None => Value::Null,
+    };
+    let args = match function.arguments {
+      2 => vec![Value::from(pragma.value.as_deref()), database],
+      1 => vec![database],
+      _ => vec![],
+    };
+    if function.arguments < 2 && pragma.value.is_some() {
+      match name(function) {
+        "application_id" | "schema_version" | "user_version" => {
+          let offset = match name(function) {
+            "application_id" => APPLICATION_ID,
+            "schema_version" => SCHEMA_COOKIE,
+            _ => USER_VERSION,
+          };
+          let value = int32_prefix(pragma.value.as_deref().unwrap_or_default());
+          return Ok(PragmaAction::Set(offset, value));
+        }
+        // These only take effect on a database yet to be created.
+        "auto_vacuum" | "encoding" | "page_size" => return Ok(PragmaAction::Empty(vec![])),
+        // The others ignore their value, and journal_mode keeps its mode.
+        _ => {}
+      }
+    }
+    Ok(PragmaAction::Read(function, args))
+  }
+
+  /// Sets a 4-byte field of the header on page 1, moving the file change
+  /// counter on. The version-valid-for number follows it when it was
+  /// valid, so that the size of the database in the header still is. Only
+  /// those fields are written, not the rest of the page.
+  ///
+  ///  A connection of SQLite writes through a rollback journal or a
+  /// write-ahead log, which this one does not keep: a database in WAL mode,
+  /// or with a journal or log that may hold changes, is not written.
+  pub(crate) fn write_header_field(&self, offset: usize, value: u32) -> SqliteResult<()> {
+    let page = self.pager.read_page(1)?;
+    let data = page.data();
+    let versions = (
+      data.get(FILE_FORMAT_WRITE_VERSION),
+      data.get(FILE_FORMAT_READ_VERSION),
+    );
+    if versions.0 == Some(&WAL_VERSION) || versions.1 == Some(&WAL_VERSION) {
+      return Err(SqliteError::Custom(
+        "cannot write the header of a database in WAL mode".into(),
+      ));
+    }
+    if let Some(path) = self.pager.io().path() {
+      for suffix in ["-journal", "-wal"] {
+        let mut name = path.clone().into_os_string();
+        name.push(suffix);
+        if std::path::Path::new(&name).exists() {
+          return Err(SqliteError::Custom("database is locked".into()));
+        }
+      }
+    }
+    let counter = field(data, FILE_CHANGE_COUNTER)?;
+    let mut fields = vec![
+      (offset, value),
+      (FILE_CHANGE_COUNTER, counter.wrapping_add(1)),
+    ];
+    if field(data, VERSION_VALID_FOR)? == counter {
+      fields.push((VERSION_VALID_FOR, counter.wrapping_add(1)));
+    }
+    for (offset, value) in fields {
+      self
+        .pager
+        .io()
+        .write_at(offset as u64, &value.to_be_bytes())?;
+    }
+    Ok(())
+  }
+}
+
+/// The offsets of the fields of the header the pragmas write.
+const FILE_CHANGE_COUNTER: usize = 24;
+const SCHEMA_COOKIE: usize = 40;
+const USER_VERSION: usize = 60;
+const APPLICATION_ID: usize = 68;
+const VERSION_VALID_FOR: usize = 92;
+
+/// The offsets of the file format versions, which are 2 in WAL mode.
+const FILE_FORMAT_WRITE_VERSION: usize = 18;
+const FILE_FORMAT_READ_VERSION: usize = 19;
+const WAL_VERSION: u8 = 2;
+
+/// The big-endian 4-byte field at `offset` of a header.
+fn field(header: &[u8], offset: usize) -> SqliteResult<u32> {
+  header
+    .get(offset..offset + 4)
+    .and_then(|bytes| bytes.try_into().ok())
+    .map(u32::from_be_bytes)
+    .ok_or_else(|| SqliteError::Custom("the database header is truncated".into()))
+}
+
+/// A field of the header as it is on page 1, where this connection may
+/// have just set it. An in-memory database has none.
+fn current_field(runtime: &SqliteRuntime, offset: usize) -> SqliteResult<u32> {
+  match runtime.pager.io().mode() {
+    SqliteIoMode::InMemory => Ok(0),
+    _ => field(runtime.pager.read_page(1)?.data(), offset),
+  }
+}
+
+/// The 32-bit integer a pragma value starts with, as SQLite reads it: an
+/// optional sign and digits. One that does not fit is 0.
+fn int32_prefix(text: &str) -> i32 {
+  let (is_negative, digits) = match text.as_bytes().first() {
+    Some(b'-') => (true, &text[1..]),
+    Some(b'+') => (false, &text[1..]),
+    _ => (false, text),
+  };
+  let digits: String = digits.chars().take_while(char::is_ascii_digit).collect();
+  let value = match digits.trim_start_matches('0') {
+    digits if digits.len() > 10 => return 0,
+    "" => 0,
+    digits => digits.parse::<i64>().unwrap_or_default(),
+  };
+  let value = if is_negative { -value } else { value };
+  i32::try_from(value).unwrap_or_default()
+}
+
+/// The name of the pragma of a function.
+fn name(function: &TableFunctionDef) -> &'static str {
+  &function.name["pragma_".len()..]
+}
+
+/// The text of an argument, `None` when it is NULL or not given.
+fn text(args: &[Value], index: usize) -> Option<String> {
+  match args.get(index)? {
+    Value::Null => None,
+    value => Some(value.to_string()),
+  }
+}
+
+/// Whether the schema argument at `index` names the main database, which it
+/// does when it is not given. `temp` is the empty temporary database.
+fn is_main(args: &[Value], index: usize) -> SqliteResult<bool> {
+  match text(args, index) {
+    None => Ok(true),
+    Some(name) if name.eq_ignore_ascii_case("main") => Ok(true),
+    Some(name) if name.eq_ignore_ascii_case("temp") => Ok(false),
+    Some(name) => Err(SqliteError::Custom(format!("unknown database '{name}'"))),
+  }
+}
+
+/// A single row of one value, in the main database.
+fn header_value(args: &[Value], value: impl Into<Value>) -> Rows {
+  Ok(match is_main(args, 0)? {
+    true => vec![vec![value.into()]],
+    false => vec![],
+  })
+}
+
+fn application_id(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  let value = current_field(runtime, APPLICATION_ID)?;
+  header_value(&[], i64::from(value as i32))
+}
+
+fn user_version(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  let value = current_field(runtime, USER_VERSION)?;
+  header_value(&[], i64::from(value as i32))
+}
+
+fn schema_version(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  let value = current_field(runtime, SCHEMA_COOKIE)?;
+  header_value(&[], i64::from(value))
+}
+
+fn freelist_count(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  header_value(
+    &[],
+    i64::from(**runtime.file_header().freelist_pages().total()),
+  )
+}
+
+fn page_size(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  header_value(
+    args,
+    i64::from(u32::from(runtime.file_header().page_size())),
+  )
+}
+
+/// The size of the database in pages: that in the header when it is valid,
+/// and that of the file otherwise.
+fn page_count(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let header = &runtime.file_header();
+  let size = **header.db_filesize_in_pages();
+  let count = match size > 0 && **header.version_valid_for() == **header.file_change_counter() {
+    true => u64::from(size),
+    false => runtime.file_metadata().map_or(0, |metadata| {
+      metadata.len() / u64::from(u32::from(header.page_size()))
+    }),
+  };
+  header_value(args, i64::try_from(count).unwrap_or(i64::MAX))
+}
+
+fn encoding(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  let encoding = match runtime.file_header().database_text_encoding() {
+    DatabaseTextEncoding::Utf8 => "UTF-8",
+    DatabaseTextEncoding::Utf16Le => "UTF-16le",
+    DatabaseTextEncoding::Utf16Be => "UTF-16be",
+  };
+  header_value(&[], encoding)
+}
+
+/// 0 for none, 1 for full and 2 for incremental, which databases with a
+/// largest root page use.
+fn auto_vacuum(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let settings = runtime.file_header().incremental_vacuum_settings();
+  let mode = match (
+    **settings.largest_root_btree_page(),
+    bool::from(settings.incremental_vacuum_mode()),
+  ) {
+    (0, _) => 0i64,
+    (_, false) => 1,
+    (_, true) => 2,
+  };
+  header_value(args, mode)
+}
+
+fn journal_mode(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let mode = match (
+    runtime.pager.io().mode(),
+    runtime
+      .file_header()
+      .file_format_version_numbers()
+      .write_version(),
+  ) {
+    (SqliteIoMode::InMemory, _) => "memory",
+    (_, FileFormatWriteVersion::WAL) => "wal",
+    (_, FileFormatWriteVersion::Legacy) => "delete",
+  };
+  header_value(args, mode)
+}
+
+/// The options the engine was built with that SQLite reports.
+fn compile_options(_: &SqliteRuntime, _: &[Value]) -> Rows {
+  let options = [
+    "DEFAULT_FILE_FORMAT=4".to_string(),
+    "DEFAULT_PAGE_SIZE=4096".to_string(),
+    "ENABLE_EXPLAIN_COMMENTS".to_string(),
+    format!("MAX_VARIABLE_NUMBER={MAX_VARIABLE_NUMBER}"),
+  ];
+  Ok(
+    options
+      .into_iter()
+      .map(|option| vec![option.into()])
+      .collect(),
+  )
+}
+
+fn database_list(runtime: &SqliteRuntime, _: &[Value]) -> Rows {
+  let file = runtime
+    .pager
+    .io()
+    .path()
+    .map(|path| path.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  Ok(vec![vec![0i64.into(), "main".into(), file.into()]])
+}
+
+/// Every built-in function once per arity. Aggregates may all run as
+/// window functions.
+fn function_list(_: &SqliteRuntime, _: &[Value]) -> Rows {
+  Ok(
+    functions()
+      .iter()
+      .map(|function| {
+        let is_scalar = function.kind() == FunctionKind::Scalar;
+        let flags = match is_scalar && function.is_deterministic() {
+          true => 0x200800i64,
+          false => 0x200000,
+        };
+        vec![
+          function.name().into(),
+          1i64.into(),
+          if is_scalar { "s" } else { "w" }.into(),
+          "utf8".into(),
+          i64::from(function.arity().narg()).into(),
+          flags.into(),
+        ]
+      })
+      .collect(),
+  )
+}
+
+/// The tables of the main database in schema order, its schema table, and
+/// that of the temporary database, those named by the argument only.
+fn table_list(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let schema = text(args, 1);
+  is_main(args, 1)?;
+  let tables = runtime.schema().tables().iter().map(|table| {
+    (
+      "main",
+      table.name(),
+      table.columns().len(),
+      table.is_without_rowid(),
+      table.is_strict(),
+    )
+  });
+  let schema_tables = [
+    ("main", "sqlite_schema", 5, false, false),
+    ("temp", "sqlite_temp_schema", 5, false, false),
+  ];
+  let name = text(args, 0);
+  Ok(
+    tables
+      .chain(schema_tables)
+      .filter(|(database, table, ..)| {
+        schema
+          .as_deref()
+          .map_or(true, |schema| schema.eq_ignore_ascii_case(database))
+          && name
+            .as_deref()
+            .map_or(true, |name| name.eq_ignore_ascii_case(table))
+      })
+      .map(|(database, table, columns, is_without_rowid, is_strict)| {
+        vec![
+          database.into(),
+          table.into(),
+          "table".into(),
+          (columns as i64).into(),
+          i64::from(is_without_rowid).into(),
+          i64::from(is_strict).into(),
+        ]
+      })
+      .collect(),
+  )
+}
+
+fn table_info(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  columns(runtime, args, false)
+}
+
+fn table_xinfo(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  columns(runtime, args, true)
+}
+
+/// The columns of a table. Generated columns are hidden, and only the
+/// extended form lists them, with whether they are: 2 for virtual ones and
+/// 3 for stored ones. `pk` is the position of a column in the primary key,
+/// whose columns are never NULL in a WITHOUT ROWID or STRICT table, but for
+/// an INTEGER PRIMARY KEY, which is the rowid.
+fn columns(runtime: &SqliteRuntime, args: &[Value], is_extended: bool) -> Rows {
+  let name = match is_main(args, 1)? {
+    true => text(args, 0),
+    false => None,
+  };
+  let Some(name) = name else {
+    return Ok(vec![]);
+  };
+  let Some(table) = runtime.schema().table(&name) else {
+    return Ok(vec![]);
+  };
+  let primary_key = table.primary_key();
+  let mut rows = vec![];
+  for (index, column) in table.columns().iter().enumerate() {
+    let hidden: i64 = match column.generated().map(|generated| generated.kind()) {
+      None => 0,
+      Some(GeneratedColumnKind::Virtual) => 2,
+      Some(GeneratedColumnKind::Stored) => 3,
+    };
+    if hidden != 0 && !is_extended {
+      continue;
+    }
+    let pk = primary_key
+      .iter()
+      .position(|(key, _)| *key == index)
+      .map_or(0, |position| position as i64 + 1);
+    let is_not_null = column.is_not_null()
+      || (pk > 0
+        && (table.is_without_rowid() || (table.is_strict() && table.rowid_alias() != Some(index))));
+    let mut row = vec![
+      (rows.len() as i64).into(),
+      column.name().into(),
+      column.declared_type().unwrap_or_default().into(),
+      i64::from(is_not_null).into(),
+      column.default().map(|default| default.sql()).into(),
+      pk.into(),
+    ];
+    if is_extended {
+      row.push(hidden.into());
+    }
+    rows.push(row);
+  }
+  Ok(rows)
+}
+
+/// The indexes of a table, the newest first.
+fn index_list(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let (true, Some(table)) = (is_main(args, 1)?, text(args, 0)) else {
+    return Ok(vec![]);
+  };
+  Ok(
+    runtime
+      .schema()
+      .index_list(&table)
+      .into_iter()
+      .enumerate()
+      .map(|(seq, index)| {
+        vec![
+          (seq as i64).into(),
+          index.name().into(),
+          i64::from(index.is_unique()).into(),
+          index.origin().to_string().into(),
+          i64::from(index.is_partial()).into(),
+        ]
+      })
+      .collect(),
+  )
+}
+
+fn index_info(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  keys(runtime, args, false)
+}
+
+fn index_xinfo(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  keys(runtime, args, true)
+}
+
+/// The keys of an index, by the number of the column each reads: -2 for an
+/// expression. The extended form adds their order and collation, and the
+/// columns that follow the keys in each entry: the rowid, -1, or the
+/// primary key of a WITHOUT ROWID table. Its own primary key index holds
+/// every other column instead.
+fn keys(runtime: &SqliteRuntime, args: &[Value], is_extended: bool) -> Rows {
+  let index = match is_main(args, 1)? {
+    true => text(args, 0).and_then(|name| runtime.schema().index(&name)),
+    false => None,
+  };
+  let Some(index) = index else {
+    return Ok(vec![]);
+  };
+  let table = runtime.schema().table(index.table_name());
+  let collation = |column: Option<&ColumnDef>| {
+    column
+      .and_then(ColumnDef::collation)
+      .unwrap_or("BINARY")
+      .to_string()
+  };
+  let mut rows = vec![];
+  let mut key_columns = vec![];
+  for key in index.columns() {
+    let column = match key.kind() {
+      IndexKeyKind::Column(name) => table.and_then(|table| table.column(name)),
+      IndexKeyKind::Expression(_) => None,
+    };
+    key_columns.extend(column.map(|(cid, _)| cid));
+    let mut row = vec![
+      (rows.len() as i64).into(),
+      column.map_or(-2, |(cid, _)| cid as i64).into(),
+      column.map(|(_, column)| column.name()).into(),
+    ];
+    if is_extended {
+      row.extend([
+        i64::from(key.order() == Some(SortOrder::Desc)).into(),
+        key
+          .collation()
+          .map_or_else(|| collation(column.map(|(_, column)| column)), String::from)
+          .into(),
+        1i64.into(),
+      ]);
+    }
+    rows.push(row);
+  }
+  let Some(table) = table.filter(|_| is_extended) else {
+    return Ok(rows);
+  };
+  let others: Vec<(i64, Option<&ColumnDef>, SortOrder)> =
+    match (table.is_without_rowid(), index.origin()) {
+      (false, _) => vec![(-1, None, SortOrder::Asc)],
+      (true, IndexOrigin::PrimaryKey) => (0..table.columns().len())
+        .map(|cid| (cid, SortOrder::Asc))
+        .filter(|(cid, _)| !key_columns.contains(cid))
+        .map(|(cid, order)| (cid as i64, table.columns().get(cid), order))
+        .collect(),
+      (true, _) => table
+        .primary_key()
+        .into_iter()
+        .filter(|(cid, _)| !key_columns.contains(cid))
+        .map(|(cid, order)| (cid as i64, table.columns().get(cid), order))
+        .collect(),
+    };
+  for (cid, column, order) in others {
+    rows.push(vec![
+      (rows.len() as i64).into(),
+      cid.into(),
+      column.map(ColumnDef::name).into(),
+      i64::from(order == SortOrder::Desc).into(),
+      collation(column).into(),
+      0i64.into(),
+    ]);
+  }
+  Ok(rows)
+}
+
+/// The foreign keys of a table, declared on a column or as a constraint,
+/// numbered from the last declared, with a row for each of their columns.
+fn foreign_key_list(runtime: &SqliteRuntime, args: &[Value]) -> Rows {
+  let table = match is_main(args, 1)? {
+    true => text(args, 0).and_then(|name| runtime.schema().table(&name)),
+    false => None,
+  };
+  let Some(table) = table else {
+    return Ok(vec![]);
+  };
+  let on_columns = table.columns().iter().filter_map(|column| {
+    column
+      .references()
+      .map(|clause| (vec![column.name()], clause))
+  });
+  let on_table = table
+    .constraints()
+    .iter()
+    .filter_map(|constraint| match constraint.kind() {
+      TableConstraintKind::ForeignKey { columns, clause } => {
+        Some((columns.iter().map(String::as_str).collect(), clause))
+      }
+      _ => None,
+    });
+  let keys: Vec<(Vec<&str>, &ForeignKeyClause)> = on_columns.chain(on_table).collect();
+  let mut rows = vec![];
+  for (id, (columns, clause)) in keys.into_iter().rev().enumerate() {
+    for (seq, column) in columns.into_iter().enumerate() {
+      rows.push(vec![
+        (id as i64).into(),
+        (seq as i64).into(),
+        clause.foreign_table().into(),
+        column.into(),
+        clause.columns().get(seq).map(String::as_str).into(),
+        action(clause.on_update()).into(),
+        action(clause.on_delete()).into(),
+        "NONE".into(),
+      ]);
+    }
+  }
+  Ok(rows)
+}
+
+fn action(action: Option<ForeignKeyAction>) -> &'static str {
+  match action {
+    Some(ForeignKeyAction::SetNull) => "SET NULL",
+    Some(ForeignKeyAction::SetDefault) => "SET DEFAULT",
+    Some(ForeignKeyAction::Cascade) => "CASCADE",
+    Some(ForeignKeyAction::Restrict) => "RESTRICT",
+    Some(ForeignKeyAction::NoAction) | None => "NO ACTION",
+  }
+}
@@ -0,0 +1,291 @@
+//! Tests for PRAGMAs
+//!
+//! To run: `cargo test runtime::pragma`
+
+use crate::{value::Value, SqliteConnection};
+
+const PRAGMAS_DB: &str = "sqlite://./data/pragmas.db";
+const PLANNER_DB: &str = "sqlite://./data/planner.db";
+
+/// The rows of a query, as the sqlite3 shell prints them.
+fn lines(conn: &mut SqliteConnection, sql: &str) -> Vec<String> {
+  conn
+    .query(sql)
+    .unwrap()
+    .rows()
+    .iter()
+    .map(|row| {
+      row
+        .iter()
+        .map(|value| match value {
+          Value::Null => String::new(),
+          value => value.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+    })
+    .collect()
+}
+
+#[test]
+fn ok_on_header_pragmas() {
+  let mut conn = SqliteConnection::open(PRAGMAS_DB).unwrap();
+  for (sql, expected) in [
+    ("PRAGMA user_version", "7"),
+    ("PRAGMA application_id", "-2"),
+    ("PRAGMA main.schema_version", "5"),
+    ("PRAGMA page_size", "4096"),
+    ("PRAGMA page_count", "7"),
+    ("PRAGMA freelist_count", "0"),
+    ("PRAGMA encoding", "UTF-8"),
+    ("PRAGMA auto_vacuum", "0"),
+    ("PRAGMA journal_mode", "delete"),
+    ("PRAGMA journal_mode = wal", "delete"),
+    ("SELECT * FROM pragma_page_size", "4096"),
+    (
+      "SELECT page_count * page_size FROM pragma_page_count('main'), pragma_page_size",
+      "28672",
+    ),
+  ] {
+    assert_eq!(lines(&mut conn, sql), [expected], "{sql}");
+  }
+  let result = conn.query("PRAGMA user_version").unwrap();
+  assert_eq!(result.columns(), ["user_version"]);
+
+  // Setting a pragma that only matters to a new database does nothing.
+  assert!(lines(&mut conn, "PRAGMA page_size = 1024").is_empty());
+  assert!(lines(&mut conn, "PRAGMA temp.user_version").is_empty());
+  assert!(lines(&mut conn, "PRAGMA no_such_pragma").is_empty());
+}
+
+#[test]
+fn ok_on_header_writes() {
+  let path = std::env::temp_dir().join(format!("sqlite-rs-pragmas-{}.db", std::process::id()));
+  std::fs::copy("./data/pragmas.db", &path).unwrap();
+  let mut conn = SqliteConnection::open(format!("sqlite://{}", path.display())).unwrap();
+  for (sql, expected) in [
+    ("PRAGMA user_version = 42", None),
+    ("PRAGMA user_version", Some("42")),
+    ("PRAGMA main.user_version = -5", None),
+    ("PRAGMA user_version", Some("-5")),
+    // The value is read as SQLite reads it, 0 when it does not fit.
+    ("PRAGMA user_version = '12abc'", None),
+    ("PRAGMA user_version", Some("12")),
+    ("PRAGMA user_version = 4294967297", None),
+    ("PRAGMA user_version", Some("0")),
+    ("PRAGMA application_id = 1234", None),
+    ("SELECT * FROM pragma_application_id", Some("1234")),
+    ("PRAGMA schema_version = 9", None),
+    ("PRAGMA schema_version", Some("9")),
+  ] {
+    assert_eq!(lines(&mut conn, sql), expected.as_slice(), "{sql}");
+  }
+  // Each write is a change to the database, which the next read picks up.
+  assert_eq!(**conn.file_header().file_change_counter(), 7 + 6);
+  assert_eq!(**conn.file_header().version_valid_for(), 7 + 6);
+  assert_eq!(**conn.file_header().user_version(), 0);
+  assert_eq!(**conn.file_header().application_id(), 1234);
+  assert_eq!(**conn.file_header().schema_cookie(), 9);
+  assert!(conn.schema().table("child").is_some());
+  drop(conn);
+
+  // The header is the one another connection then reads.
+  let mut conn = SqliteConnection::open(format!("sqlite://{}", path.display())).unwrap();
+  assert_eq!(lines(&mut conn, "PRAGMA application_id"), ["1234"]);
+  assert_eq!(lines(&mut conn, "PRAGMA page_count"), ["7"]);
+  drop(conn);
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn err_on_unsafe_header_writes() {
+  let path = std::env::temp_dir().join(format!("sqlite-rs-locked-{}.db", std::process::id()));
+  let uri = format!("sqlite://{}", path.display());
+  std::fs::copy("./data/pragmas.db", &path).unwrap();
+
+  // A journal beside the database may hold changes of another connection.
+  let journal = format!("{}-journal", path.display());
+  std::fs::write(&journal, b"").unwrap();
+  let conn = SqliteConnection::open(&uri).unwrap();
+  let err = conn.query("PRAGMA user_version = 1").unwrap_err();
+  assert_eq!(err.to_string(), "database is locked");
+  std::fs::remove_file(&journal).unwrap();
+  drop(conn);
+
+  // A database in WAL mode is only written through its log.
+  let mut data = std::fs::read(&path).unwrap();
+  data[18] = 2;
+  data[19] = 2;
+  std::fs::write(&path, &data).unwrap();
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  assert!(conn.query("PRAGMA user_version = 1").is_err());
+  assert_eq!(lines(&mut conn, "PRAGMA user_version"), ["7"]);
+  drop(conn);
+
+  // A file that cannot be opened for writing is still read.
+  data[18] = 1;
+  data[19] = 1;
+  std::fs::write(&path, &data).unwrap();
+  let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+  permissions.set_readonly(true);
+  std::fs::set_permissions(&path, permissions).unwrap();
+  let mut conn = SqliteConnection::open(&uri).unwrap();
+  assert_eq!(lines(&mut conn, "PRAGMA user_version"), ["7"]);
+  // Root may write any file, so only a failed write is checked.
+  if let Err(err) = conn.query("PRAGMA user_version = 1") {
+    assert_eq!(err.to_string(), "attempt to write a readonly database");
+  }
+  drop(conn);
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn ok_on_table_pragmas() {
+  let mut conn = SqliteConnection::open(PRAGMAS_DB).unwrap();
+  assert_eq!(
+    lines(&mut conn, "PRAGMA table_info(child)"),
+    [
+      "0|id|INTEGER|0||1",
+      "1|x|INTEGER|1|0|0",
+      "2|y|TEXT|0|'none'|0",
+      "3|z|TEXT|0||0",
+    ]
+  );
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT name, hidden FROM pragma_table_xinfo('child') WHERE hidden"
+    ),
+    ["w|2", "v|3"]
+  );
+  assert_eq!(
+    lines(&mut conn, "PRAGMA foreign_key_list('child')"),
+    [
+      "0|0|parent|y|b|SET NULL|NO ACTION|NONE",
+      "0|1|parent|z|c|SET NULL|NO ACTION|NONE",
+      "1|0|parent|x||NO ACTION|CASCADE|NONE",
+    ]
+  );
+  // SQLite lists the tables in the order of its hash table, and this in
+  // that of the schema.
+  assert_eq!(
+    lines(&mut conn, "PRAGMA table_list"),
+    [
+      "main|parent|table|3|0|0",
+      "main|child|table|6|0|0",
+      "main|pair|table|3|1|0",
+      "main|sqlite_schema|table|5|0|0",
+      "temp|sqlite_temp_schema|table|5|0|0",
+    ]
+  );
+  assert_eq!(
+    lines(&mut conn, "PRAGMA table_list(pair)"),
+    ["main|pair|table|3|1|0"]
+  );
+  // A table-valued pragma joins like a table.
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT t.name, count(*) FROM pragma_table_list t, pragma_table_info(t.name) c
+       WHERE t.schema = 'main' GROUP BY 1 ORDER BY 1"
+    ),
+    ["child|4", "pair|3", "parent|3", "sqlite_schema|5"]
+  );
+}
+
+/// The expected rows are the output of sqlite3 for `data/strict.db`.
+#[test]
+fn ok_on_primary_keys_of_strict_tables() {
+  let mut conn = SqliteConnection::open("sqlite://./data/strict.db").unwrap();
+  for (table, expected) in [
+    ("named", &["0|a|TEXT|1||1", "1|b|INT|0||0"][..]),
+    ("pair", &["0|a|TEXT|1||1", "1|b|INT|1||2", "2|c|ANY|0||0"]),
+    ("alias", &["0|a|INTEGER|0||1", "1|b|INT|1||0"]),
+    ("plain", &["0|a|INT|1||1", "1|b|TEXT|0||0"]),
+    ("loose", &["0|a|TEXT|0||1", "1|b||0||0"]),
+  ] {
+    let sql = format!("PRAGMA table_info({table})");
+    assert_eq!(lines(&mut conn, &sql), expected, "{table}");
+  }
+}
+
+#[test]
+fn ok_on_index_pragmas() {
+  let mut conn = SqliteConnection::open(PRAGMAS_DB).unwrap();
+  assert_eq!(
+    lines(&mut conn, "PRAGMA index_list(pair)"),
+    ["0|pair_d|0|c|0", "1|sqlite_autoindex_pair_1|1|pk|0"]
+  );
+  assert_eq!(
+    lines(&mut conn, "PRAGMA index_list(child)"),
+    ["0|child_expr|0|c|1"]
+  );
+  assert_eq!(
+    lines(&mut conn, "PRAGMA index_info(child_expr)"),
+    ["0|-2|", "1|1|x"]
+  );
+  for (index, expected) in [
+    (
+      "pair_d",
+      ["0|2|d|0|BINARY|1", "1|0|k|0|NOCASE|1", "2|1|n|1|BINARY|0"],
+    ),
+    (
+      "sqlite_autoindex_pair_1",
+      ["0|1|n|1|BINARY|1", "1|0|k|0|NOCASE|1", "2|2|d|0|BINARY|0"],
+    ),
+    (
+      "child_expr",
+      ["0|-2||0|BINARY|1", "1|1|x|1|BINARY|1", "2|-1||0|BINARY|0"],
+    ),
+  ] {
+    assert_eq!(
+      lines(&mut conn, &format!("PRAGMA index_xinfo({index})")),
+      expected
+    );
+  }
+
+  let mut conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  assert_eq!(
+    lines(&mut conn, "PRAGMA index_xinfo('item_category')"),
+    [
+      "0|1|category|0|BINARY|1",
+      "1|2|price|0|BINARY|1",
+      "2|-1||0|BINARY|0"
+    ]
+  );
+}
+
+#[test]
+fn ok_on_connection_pragmas() {
+  let mut conn = SqliteConnection::open(PRAGMAS_DB).unwrap();
+  let database = lines(&mut conn, "PRAGMA database_list");
+  assert!(database[0].starts_with("0|main|/"));
+  assert!(database[0].ends_with("/data/pragmas.db"));
+  assert_eq!(
+    lines(
+      &mut conn,
+      "SELECT type, narg, flags FROM pragma_function_list WHERE name IN ('abs', 'count')
+       ORDER BY name, narg"
+    ),
+    ["s|1|2099200", "w|0|2097152", "w|1|2097152"]
+  );
+  assert!(lines(&mut conn, "PRAGMA compile_options").contains(&"MAX_VARIABLE_NUMBER=32766".into()));
+}
+
+#[test]
+fn err_on_bad_pragmas() {
+  let conn = SqliteConnection::open(PRAGMAS_DB).unwrap();
+  for (sql, message) in [
+    ("PRAGMA aux.page_size", "unknown database aux"),
+    (
+      "SELECT * FROM pragma_table_info('child', 'aux')",
+      "unknown database 'aux'",
+    ),
+    (
+      "SELECT * FROM pragma_page_size('main', 1)",
+      "too many arguments on pragma_page_size() - max 1",
+    ),
+  ] {
+    assert_eq!(conn.query(sql).unwrap_err().to_string(), message, "{sql}");
+  }
+}
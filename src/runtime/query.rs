@@ -0,0 +1,1416 @@
+//! # Queries
+//!
+//!  A SELECT is compiled to a program of the [virtual database
+//! engine](super::vdbe), which reads the rows of its tables through
+//! cursors, joined as nested loops (see [`join`]), keeps those for which the
+//! WHERE clause is true and computes the result columns of each. Column
+//! references are resolved by name, ignoring ASCII case, as the query is
+//! compiled, so a misspelled column is reported even on an empty table. See
+//! [`compile`].
+//!
+//!  A query with a GROUP BY clause or an aggregate function is an aggregate
+//! query: its rows are grouped, and the result columns and the HAVING clause
+//! are computed once per group. See [`group`].
+//!
+//!  Window calls, those with an OVER clause, are computed once the rows of
+//! the query are known, after the WHERE clause and any grouping. See
+//! [`window`].
+//!
+//!  The WHERE, GROUP BY and HAVING clauses may refer to a result column by
+//! its alias, when no column of the tables has that name.
+//!
+//!  A subquery in an expression runs with the current row of its query as
+//! its outer row, which its correlated columns read. A subquery that reads
+//! none runs only once per statement. A subquery in FROM or a common table
+//! expression that reads a single table is flattened into its query when
+//! that keeps its meaning (see [`flatten`]); any other fills an ephemeral
+//! table before the query reads it. See [`cte`].
+//!
+//!  The selects of a compound select run in turn, and their rows are
+//! combined before its ORDER BY and LIMIT apply. See [`compound`].
+//!
+//!  `EXPLAIN QUERY PLAN` plans a query the same way, and returns the steps
+//! it would take instead of its rows. See [`explain`]. `EXPLAIN` returns the
+//! instructions of its program.
+//!
+//!  DISTINCT keeps a set of the rows so far, and ORDER BY and GROUP BY sort
+//! rows with a sorter, which keeps them in memory up to the sort memory
+//! budget of the runtime and spills them to temporary files past it. Rows
+//! stream out of a query without ORDER BY, and stop being read once LIMIT
+//! is reached.
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html
+
+#[cfg(test)]
+mod tests;
+
+mod compile;
+mod compound;
+mod cte;
+mod explain;
+mod flatten;
+mod group;
+mod join;
+mod window;
+
+use core::cell::{Cell, RefCell};
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  result::{SqliteError, SqliteResult},
+  sql::ast::{Expr, Limit, Literal, OrderingTerm, ResultColumn, Select},
+  value::{Affinity, Value},
+};
+
+pub use self::explain::{PlanStep, QueryPlan};
+
+pub(crate) use self::{
+  compile::CompiledStatement,
+  compound::Distinct,
+  cte::Queue,
+  group::Aggregate,
+  join::entry_columns,
+  window::{compute_windows, WindowSpec},
+};
+
+use self::{
+  compile::{compile_expr, compile_position, Builder, Sink, Slot},
+  compound::compile_compound,
+  cte::{CteFrame, Ctes},
+  explain::{explain_subqueries, Explainer},
+  flatten::flatten,
+  group::{aggregate_calls, compile_groups, compile_single_group, AggregateCall},
+  join::Joins,
+  window::{has_window_call, window_calls, WindowCall},
+};
+use super::{
+  collation::Collation,
+  eval::{evaluate, expr_affinity, no_such_column, truth_value, Constant, Environment},
+  functions::{resolve_call, FunctionKind, TableFunctionDef},
+  sorter::KeyOrder,
+  vdbe::Op,
+  SqliteRuntime,
+};
+
+/// The names that refer to the rowid of a table, unless a column of the
+/// table has the same name.
+const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+/// The columns and rows returned by a query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+  columns: Vec<String>,
+  rows: Vec<Vec<Value>>,
+  /// The affinity of each column, for subqueries.
+  affinities: Vec<Affinity>,
+  /// The collation of each column, for subqueries and compound selects.
+  /// `None` for a column that has none, and so sorts with BINARY.
+  collations: Vec<Option<String>>,
+  /// The declared type of each column, for prepared statements.
+  declared_types: Vec<Option<String>>,
+}
+
+impl QueryResult {
+  /// A result whose columns have no affinity, collation or declared type.
+  pub(crate) fn new(columns: Vec<String>, rows: Vec<Vec<Value>>) -> Self {
+    Self {
+      affinities: vec![Affinity::Blob; columns.len()],
+      collations: vec![None; columns.len()],
+      declared_types: vec![None; columns.len()],
+      columns,
+      rows,
+    }
+  }
+
+  /// The result column names.
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  pub fn rows(&self) -> &[Vec<Value>] {
+    &self.rows
+  }
+
+  pub fn into_rows(self) -> Vec<Vec<Value>> {
+    self.rows
+  }
+
+  /// The declared type of each result column: that of the table column it
+  /// reads, when it is only a column, and `None` otherwise.
+  pub fn declared_types(&self) -> &[Option<String>] {
+    &self.declared_types
+  }
+}
+
+/// What a query sees of the queries it is nested in.
+#[derive(Clone, Copy, Default)]
+struct Context<'a> {
+  /// The scope of the query an expression subquery belongs to, whose
+  /// current row its correlated columns read.
+  outer: Option<&'a Scope<'a>>,
+  /// The common table expressions in scope.
+  ctes: Option<Ctes<'a>>,
+  /// Where the steps of the plan go, when the query is only explained.
+  explainer: Option<&'a Explainer>,
+}
+
+/// The tables visible to the expressions of a query. Each row is laid out as
+/// the columns of every table in turn, each table followed by its rowid.
+pub(crate) struct Scope<'a> {
+  runtime: &'a SqliteRuntime,
+  context: Context<'a>,
+  sources: Vec<Source>,
+  /// The aliased result columns.
+  aliases: Vec<(String, Expr)>,
+  /// The columns joined by USING or NATURAL, as the position of the column
+  /// an unqualified name refers to and of the column that stands in for it
+  /// when it is NULL.
+  coalesced: Vec<(usize, usize)>,
+  /// Whether a subquery has read a column of the current row.
+  is_referenced: Cell<bool>,
+  /// How the program reads each position of the row, at the point being
+  /// compiled.
+  slots: RefCell<Vec<Slot>>,
+  /// The aggregate and window calls whose results are known at the point
+  /// being compiled, and where they are read.
+  computed: RefCell<Vec<(Expr, Slot)>>,
+}
+
+/// The clause an expression belongs to, which decides whether it may use
+/// aliases and aggregate functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Clause {
+  Result,
+  Where,
+  GroupBy,
+  Having,
+  OrderBy,
+}
+
+#[derive(Debug)]
+struct Source {
+  /// The alias of the table, or its name.
+  name: String,
+  columns: Vec<String>,
+  affinities: Vec<Affinity>,
+  /// The declared collation of each column.
+  collations: Vec<Option<String>>,
+  declared_types: Vec<Option<String>>,
+  has_rowid: bool,
+  /// Position of the first column in the row.
+  offset: usize,
+  /// The columns joined by USING or NATURAL to a column of a table on the
+  /// left, which unqualified names and `*` leave out.
+  hidden: Vec<usize>,
+  /// The table-valued function whose rows the table holds, if it is one.
+  function: Option<&'static TableFunctionDef>,
+}
+
+impl Source {
+  /// Whether a column holds an argument of a table-valued function, which
+  /// `*` leaves out.
+  fn is_argument(&self, index: usize) -> bool {
+    self
+      .function
+      .is_some_and(|function| index + function.arguments >= self.columns.len())
+  }
+}
+
+impl<'a> Scope<'a> {
+  fn new(runtime: &'a SqliteRuntime, context: Context<'a>) -> Self {
+    Self {
+      runtime,
+      context,
+      sources: vec![],
+      aliases: vec![],
+      coalesced: vec![],
+      is_referenced: Cell::new(false),
+      slots: RefCell::new(vec![]),
+      computed: RefCell::new(vec![]),
+    }
+  }
+
+  fn push(
+    &mut self,
+    name: &str,
+    columns: Vec<String>,
+    affinities: Vec<Affinity>,
+    collations: Vec<Option<String>>,
+    declared_types: Vec<Option<String>>,
+    has_rowid: bool,
+  ) {
+    let offset = self.width();
+    self.sources.push(Source {
+      name: name.into(),
+      columns,
+      affinities,
+      collations,
+      declared_types,
+      has_rowid,
+      offset,
+      hidden: vec![],
+      function: None,
+    });
+  }
+
+  /// Finds the position of a column in the row, along with its declared
+  /// name and affinity. The rowid has INTEGER affinity.
+  fn resolve(&self, table: Option<&str>, name: &str) -> SqliteResult<(usize, String, Affinity)> {
+    self
+      .find(table, name)?
+      .ok_or_else(|| no_such_column(table, name))
+  }
+
+  /// Like [`Self::resolve`], but `None` when no table of the query has the
+  /// column, which may then be a column of an outer query.
+  fn find(
+    &self,
+    table: Option<&str>,
+    name: &str,
+  ) -> SqliteResult<Option<(usize, String, Affinity)>> {
+    let sources: Vec<&Source> = match table {
+      Some(table) => {
+        let sources: Vec<&Source> = self
+          .sources
+          .iter()
+          .filter(|source| source.name.eq_ignore_ascii_case(table))
+          .collect();
+        if sources.is_empty() {
+          return Ok(None);
+        }
+        sources
+      }
+      None => self.sources.iter().collect(),
+    };
+
+    let mut found: Vec<(usize, String, Affinity, bool)> = sources
+      .iter()
+      .filter_map(|source| {
+        let index = source
+          .columns
+          .iter()
+          .position(|column| column.eq_ignore_ascii_case(name))?;
+        Some((
+          source.offset + index,
+          source.columns[index].clone(),
+          source.affinities[index],
+          source.hidden.contains(&index),
+        ))
+      })
+      .collect();
+    // Unqualified names leave out the columns joined by USING or NATURAL,
+    // and so do qualified ones when two tables have the same name.
+    if table.is_none() || found.len() > 1 {
+      found.retain(|(.., is_hidden)| !is_hidden);
+    }
+    let mut found = found
+      .into_iter()
+      .map(|(position, name, affinity, _)| (position, name, affinity));
+    if let Some(column) = found.next() {
+      if found.next().is_some() {
+        return Err(SqliteError::Custom(format!(
+          "ambiguous column name: {name}"
+        )));
+      }
+      return Ok(Some(column));
+    }
+
+    let is_rowid_name = ROWID_NAMES
+      .iter()
+      .any(|rowid| rowid.eq_ignore_ascii_case(name));
+    let mut rowids = sources
+      .iter()
+      .filter(|source| is_rowid_name && source.has_rowid)
+      .map(|source| {
+        (
+          source.offset + source.columns.len(),
+          name.to_string(),
+          Affinity::Integer,
+        )
+      });
+    match (rowids.next(), rowids.next()) {
+      (Some(rowid), None) => Ok(Some(rowid)),
+      (Some(_), Some(_)) => Err(SqliteError::Custom(format!(
+        "ambiguous column name: {name}"
+      ))),
+      (None, _) => Ok(None),
+    }
+  }
+
+  /// Checks that a column exists in the query or in an outer one.
+  fn check_column(&self, table: Option<&str>, name: &str) -> SqliteResult<()> {
+    match (self.find(table, name)?, self.context.outer) {
+      (Some(_), _) => Ok(()),
+      (None, Some(outer)) => {
+        outer.is_referenced.set(true);
+        outer.check_column(table, name)
+      }
+      (None, None) => Err(no_such_column(table, name)),
+    }
+  }
+
+  /// Whether a name is a column of an outer query rather than of this one.
+  fn is_outer_column(&self, table: Option<&str>, name: &str) -> bool {
+    matches!(self.find(table, name), Ok(None)) && self.check_column(table, name).is_ok()
+  }
+
+  /// The position of the leftmost column with this name among the tables
+  /// before a position of the row, for USING and NATURAL.
+  fn resolve_left(&self, before: usize, name: &str) -> Option<usize> {
+    self
+      .sources
+      .iter()
+      .filter(|source| source.offset < before)
+      .find_map(|source| {
+        let index = source
+          .columns
+          .iter()
+          .enumerate()
+          .position(|(index, column)| {
+            column.eq_ignore_ascii_case(name) && !source.hidden.contains(&index)
+          })?;
+        Some(source.offset + index)
+      })
+  }
+
+  /// The index of the table a position of the row belongs to.
+  fn source_at(&self, position: usize) -> usize {
+    self
+      .sources
+      .iter()
+      .rposition(|source| source.offset <= position)
+      .unwrap_or_default()
+  }
+
+  /// The affinity of the value at a position of the row.
+  fn affinity_at(&self, position: usize) -> Affinity {
+    let source = &self.sources[self.source_at(position)];
+    source
+      .affinities
+      .get(position - source.offset)
+      .copied()
+      .unwrap_or(Affinity::Integer)
+  }
+
+  /// The collation of the value at a position of the row.
+  fn collation_at(&self, position: usize) -> String {
+    let source = &self.sources[self.source_at(position)];
+    source
+      .collations
+      .get(position - source.offset)
+      .cloned()
+      .flatten()
+      .unwrap_or_else(|| "BINARY".into())
+  }
+
+  /// The declared type of an expression that is a column of a table of the
+  /// query. `None` for other expressions and the rowid.
+  fn declared_type(&self, expr: &Expr) -> Option<String> {
+    let Expr::Column { table, name, .. } = expr else {
+      return None;
+    };
+    let (position, ..) = self.find(table.as_deref(), name).ok()??;
+    let source = &self.sources[self.source_at(position)];
+    source
+      .declared_types
+      .get(position - source.offset)
+      .cloned()
+      .flatten()
+  }
+
+  /// The name of the collation an expression has: that of its outermost
+  /// `COLLATE`, or the declared collation of the column it is, BINARY by
+  /// default. `None` for other expressions.
+  fn collation_name(&self, expr: &Expr) -> Option<String> {
+    match expr {
+      Expr::Collate { collation, .. } => Some(collation.clone()),
+      Expr::Column { table, name, .. } => self.reference_collation(table.as_deref(), name),
+      _ => None,
+    }
+  }
+
+  /// The name of the collation of a column reference, which may be an
+  /// alias.
+  fn reference_collation(&self, table: Option<&str>, name: &str) -> Option<String> {
+    match self.alias(table, name) {
+      Some(Expr::Collate { collation, .. }) => Some(collation.clone()),
+      // An alias of a column is not looked up as an alias again, so that
+      // aliases of one another do not loop.
+      Some(Expr::Column { table, name, .. }) => self.column_collation(table.as_deref(), name),
+      Some(_) => None,
+      None => self.column_collation(table, name),
+    }
+  }
+
+  fn column_collation(&self, table: Option<&str>, name: &str) -> Option<String> {
+    match self.find(table, name) {
+      Ok(Some((position, ..))) => Some(self.collation_at(position)),
+      Ok(None) => self.context.outer?.column_collation(table, name),
+      Err(_) => None,
+    }
+  }
+
+  /// The collation DISTINCT, GROUP BY and ORDER BY compare an expression
+  /// with.
+  fn collation(&self, expr: &Expr) -> SqliteResult<Collation> {
+    self
+      .runtime
+      .collation(self.collation_name(expr).as_deref().unwrap_or("BINARY"))
+  }
+
+  /// The order of an ORDER BY term, with the collation of its expression.
+  fn term_order(&self, term: &OrderingTerm) -> SqliteResult<KeyOrder> {
+    Ok(KeyOrder::of_term(term, self.collation(&term.expr)?))
+  }
+
+  /// The number of values in a row.
+  fn width(&self) -> usize {
+    self
+      .sources
+      .last()
+      .map(|source| source.offset + source.columns.len() + 1)
+      .unwrap_or_default()
+  }
+
+  /// Where the result of an aggregate or window call is read, once known.
+  fn computed(&self, expr: &Expr) -> Option<Slot> {
+    self
+      .computed
+      .borrow()
+      .iter()
+      .find(|(computed, _)| computed == expr)
+      .map(|(_, slot)| *slot)
+  }
+
+  /// Makes the positions of the row read from the registers from `start`.
+  fn read_registers(&self, start: usize) {
+    *self.slots.borrow_mut() = (0..self.width())
+      .map(|position| Slot::Register(start + position))
+      .collect();
+  }
+
+  /// Compiles the values of every position of the row into the registers
+  /// from `dest`.
+  fn compile_row(&self, builder: &mut Builder, dest: usize) {
+    for position in 0..self.width() {
+      compile_position(builder, self, position, dest + position);
+    }
+  }
+
+  /// The expression of the result column with this alias, unless a column
+  /// of the tables has this name.
+  fn alias(&self, table: Option<&str>, name: &str) -> Option<&Expr> {
+    if table.is_some() || self.resolve(None, name).is_ok() {
+      return None;
+    }
+    self
+      .aliases
+      .iter()
+      .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+      .map(|(_, expr)| expr)
+  }
+
+  /// Checks that every column and function an expression refers to exists,
+  /// and that aggregate functions are only used where they are allowed.
+  fn check(&self, expr: &Expr, clause: Clause) -> SqliteResult<()> {
+    let mut result = Ok(());
+    expr.walk(&mut |expr| {
+      if result.is_err() {
+        return;
+      }
+      result = self.check_node(expr, clause);
+    });
+    result
+  }
+
+  fn check_node(&self, expr: &Expr, clause: Clause) -> SqliteResult<()> {
+    match expr {
+      Expr::Column { table, name, .. } => {
+        let alias = match clause {
+          Clause::Result => None,
+          _ => self.alias(table.as_deref(), name),
+        };
+        match alias {
+          // TRUE and FALSE are constants where no column has their name.
+          None => match self.check_column(table.as_deref(), name) {
+            Err(_) if table.is_none() && truth_value(name).is_some() => Ok(()),
+            result => result,
+          },
+          Some(alias) => {
+            self.check(alias, Clause::Result)?;
+            if clause != Clause::OrderBy && has_window_call(alias) {
+              return Err(SqliteError::Custom(format!(
+                "misuse of aliased window function {name}"
+              )));
+            }
+            let calls = aggregate_calls(alias)?;
+            match (calls.first(), clause) {
+              (Some(call), Clause::Where) => Err(SqliteError::Custom(format!(
+                "misuse of aggregate: {}()",
+                call.name()
+              ))),
+              (Some(_), Clause::GroupBy) => Err(SqliteError::Custom(
+                "aggregate functions are not allowed in the GROUP BY clause".into(),
+              )),
+              _ => Ok(()),
+            }
+          }
+        }
+      }
+      Expr::Function {
+        name,
+        distinct,
+        args,
+        order_by,
+        filter,
+        over,
+      } => {
+        let function = resolve_call(
+          name,
+          *distinct,
+          args,
+          order_by,
+          filter.as_deref(),
+          over.is_some(),
+        )?;
+        if over.is_some() && !matches!(clause, Clause::Result | Clause::OrderBy) {
+          return Err(SqliteError::Custom(format!(
+            "misuse of window function {name}()"
+          )));
+        }
+        match (function.kind(), clause) {
+          (FunctionKind::Aggregate, Clause::Where) => Err(SqliteError::Custom(format!(
+            "misuse of aggregate function {name}()"
+          ))),
+          (FunctionKind::Aggregate, Clause::GroupBy) => Err(SqliteError::Custom(
+            "aggregate functions are not allowed in the GROUP BY clause".into(),
+          )),
+          _ => Ok(()),
+        }
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// The names of a query, as the environment in which the affinity and the
+/// collation of its expressions are found.
+#[derive(Clone, Copy)]
+struct Names<'a> {
+  scope: &'a Scope<'a>,
+  /// Whether column names may refer to aliased result columns.
+  aliases: bool,
+}
+
+impl<'a> Names<'a> {
+  fn alias(&self, table: Option<&str>, name: &str) -> Option<&'a Expr> {
+    match self.aliases {
+      true => self.scope.alias(table, name),
+      false => None,
+    }
+  }
+}
+
+impl Environment for Names<'_> {
+  fn column(&self, _: Option<&str>, table: Option<&str>, name: &str) -> SqliteResult<Value> {
+    Err(no_such_column(table, name))
+  }
+
+  fn column_affinity(&self, _: Option<&str>, table: Option<&str>, name: &str) -> Affinity {
+    if let Some(alias) = self.alias(table, name) {
+      return expr_affinity(
+        alias,
+        &Names {
+          aliases: false,
+          ..*self
+        },
+      );
+    }
+    match (self.scope.find(table, name), self.scope.context.outer) {
+      (Ok(Some((.., affinity))), _) => affinity,
+      (Ok(None), Some(outer)) => Names {
+        scope: outer,
+        aliases: false,
+      }
+      .column_affinity(None, table, name),
+      _ => Affinity::default(),
+    }
+  }
+
+  fn column_collation(&self, _: Option<&str>, table: Option<&str>, name: &str) -> Option<String> {
+    match self.aliases {
+      true => self.scope.reference_collation(table, name),
+      false => self.scope.column_collation(table, name),
+    }
+  }
+
+  fn collation(&self, name: &str) -> SqliteResult<Collation> {
+    self.scope.runtime.collation(name)
+  }
+
+  fn encoding(&self) -> DatabaseTextEncoding {
+    *self.scope.runtime.file_header().database_text_encoding()
+  }
+}
+
+/// The registers a query counts LIMIT and OFFSET down in.
+#[derive(Clone, Copy)]
+struct Limits {
+  limit: usize,
+  offset: Option<usize>,
+  /// How many rows a sorter needs to keep, when LIMIT and OFFSET are
+  /// constants.
+  keep: Option<usize>,
+  /// Where the query ends once LIMIT is reached.
+  done: usize,
+}
+
+/// Compiles the LIMIT and OFFSET of a query, which may read the parameters
+/// of a prepared statement. A negative limit means no limit, and a negative
+/// offset is zero. A query whose limit is 0 ends at once.
+fn compile_limits(
+  builder: &mut Builder,
+  limit: Option<&Limit>,
+  done: usize,
+) -> SqliteResult<Option<Limits>> {
+  let Some(limit) = limit else {
+    return Ok(None);
+  };
+  let (register, count) = compile_limit(builder, &limit.limit)?;
+  let (offset, skipped) = match &limit.offset {
+    None => (None, Some(0)),
+    Some(offset) => match evaluate_integer(offset, &Constant) {
+      Ok(value) if value <= 0 => (None, Some(0)),
+      _ => {
+        let (register, value) = compile_limit(builder, offset)?;
+        (Some(register), value)
+      }
+    },
+  };
+  builder.emit(Op::IfNot {
+    register,
+    target: done,
+    if_null: true,
+  });
+  // Only the first rows are needed, when a sorter orders them.
+  let keep = match (count, skipped) {
+    (Some(count), Some(skipped)) if count >= 0 => {
+      usize::try_from(count.saturating_add(skipped)).ok()
+    }
+    _ => None,
+  };
+  Ok(Some(Limits {
+    limit: register,
+    offset,
+    keep,
+    done,
+  }))
+}
+
+/// Compiles the value of LIMIT or OFFSET into a register, along with the
+/// value when it is a constant.
+fn compile_limit(builder: &mut Builder, expr: &Expr) -> SqliteResult<(usize, Option<i64>)> {
+  let register = builder.register();
+  if let Ok(value) = evaluate_integer(expr, &Constant) {
+    builder.constant(Op::Integer {
+      value,
+      dest: register,
+    });
+    return Ok((register, Some(value)));
+  }
+  let scope = Scope::new(builder.runtime, Context::default());
+  compile_expr(builder, &scope, expr, false, register)?;
+  builder.emit(Op::MustBeInt { register });
+  Ok((register, None))
+}
+
+/// How a query sends its result rows on: without duplicates for DISTINCT,
+/// through a sorter for ORDER BY, and counted against LIMIT and OFFSET.
+struct Output {
+  /// The set of the rows so far, for DISTINCT.
+  distinct: Option<usize>,
+  /// The sorter, with the address of the instruction that opens it.
+  sorter: Option<(usize, usize)>,
+  key_count: usize,
+  limits: Option<Limits>,
+}
+
+impl Output {
+  /// Opens the set and the sorter of a query, when it needs them.
+  fn open(
+    builder: &mut Builder,
+    distinct: Option<Vec<KeyOrder>>,
+    order: Vec<KeyOrder>,
+    limits: Option<Limits>,
+  ) -> Self {
+    let distinct = distinct.map(|order| {
+      let cursor = builder.cursor();
+      builder.emit(Op::SetOpen { cursor, order });
+      cursor
+    });
+    let key_count = order.len();
+    let sorter = (key_count > 0).then(|| {
+      let cursor = builder.cursor();
+      let address = builder.address();
+      builder.emit(Op::SorterOpen {
+        cursor,
+        order,
+        limit: limits.and_then(|limits| limits.keep),
+        combine: None,
+      });
+      (cursor, address)
+    });
+    Self {
+      distinct,
+      sorter,
+      key_count,
+      limits,
+    }
+  }
+
+  /// Sets how the sorter orders rows, once the columns it sorts by are
+  /// known.
+  fn set_order(&self, builder: &mut Builder, key_orders: Vec<KeyOrder>) {
+    let Some((_, address)) = self.sorter else {
+      return;
+    };
+    if let Some(Op::SorterOpen { order, .. }) = builder.op_mut(address) {
+      *order = key_orders;
+    }
+  }
+
+  /// Compiles a result row held in the `count` registers from `start`.
+  /// `keys` compiles the values of its ORDER BY terms into the registers
+  /// from the one it is given.
+  fn compile_row<'r>(
+    &self,
+    builder: &mut Builder<'r>,
+    start: usize,
+    count: usize,
+    keys: &mut dyn FnMut(&mut Builder<'r>, usize) -> SqliteResult<()>,
+    emit: &mut Sink<'_, 'r>,
+  ) -> SqliteResult<()> {
+    let skip = builder.label();
+    if let Some(cursor) = self.distinct {
+      builder.emit(Op::SetInsert {
+        cursor,
+        key: start,
+        count,
+        target: skip,
+      });
+    }
+    match self.sorter {
+      Some((cursor, _)) => {
+        let key = builder.registers(self.key_count);
+        keys(builder, key)?;
+        builder.emit(Op::SorterInsert {
+          cursor,
+          key,
+          values: start,
+          count,
+        });
+      }
+      None => emit_limited(builder, self.limits, start, count, emit)?,
+    }
+    builder.place(skip);
+    Ok(())
+  }
+
+  /// Compiles the loop over the rows of the sorter, once every row is in.
+  fn finish<'r>(
+    &self,
+    builder: &mut Builder<'r>,
+    count: usize,
+    emit: &mut Sink<'_, 'r>,
+  ) -> SqliteResult<()> {
+    let Some((cursor, _)) = self.sorter else {
+      return Ok(());
+    };
+    let top = builder.label();
+    let end = builder.label();
+    let row = builder.registers(count);
+    builder.emit(Op::SorterSort {
+      cursor,
+      target: end,
+    });
+    builder.place(top);
+    builder.emit(Op::SorterData {
+      cursor,
+      dest: row,
+      count,
+    });
+    emit_limited(builder, self.limits, row, count, emit)?;
+    builder.emit(Op::SorterNext {
+      cursor,
+      target: top,
+    });
+    builder.place(end);
+    Ok(())
+  }
+}
+
+/// Sends a row on once OFFSET rows are skipped, and ends the query once
+/// LIMIT rows are sent.
+fn emit_limited<'r>(
+  builder: &mut Builder<'r>,
+  limits: Option<Limits>,
+  start: usize,
+  count: usize,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<()> {
+  let skip = builder.label();
+  if let Some(offset) = limits.and_then(|limits| limits.offset) {
+    builder.emit(Op::IfPos {
+      register: offset,
+      target: skip,
+      decrement: 1,
+    });
+  }
+  emit(builder, start, count)?;
+  if let Some(limits) = limits {
+    builder.emit(Op::DecrJumpZero {
+      register: limits.limit,
+      target: limits.done,
+    });
+  }
+  builder.place(skip);
+  Ok(())
+}
+
+/// Compiles a query in the context of the queries it is nested in, with
+/// `emit` compiled for each of its rows.
+fn compile_query<'r>(
+  builder: &mut Builder<'r>,
+  select: &Select,
+  context: Context,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<QueryResult> {
+  let frame;
+  let context = match &select.with {
+    Some(with) => {
+      frame = CteFrame::new(builder, with, context)?;
+      Context {
+        ctes: Some(frame.ctes()),
+        ..context
+      }
+    }
+    None => context,
+  };
+  let done = builder.label();
+  // A plan reads no rows.
+  let limits = match context.explainer {
+    Some(_) => None,
+    None => compile_limits(builder, select.limit.as_ref(), done)?,
+  };
+  builder.depth += 1;
+  let result = match select.compound.is_empty() {
+    true => compile_core(builder, select, context, &select.order_by, limits, emit),
+    false => compile_compound(builder, select, context, limits, emit),
+  };
+  builder.depth -= 1;
+  builder.place(done);
+  result
+}
+
+/// Compiles a query whose rows fill an ephemeral table, opened anew each
+/// time the program reaches it. Returns the cursor of the table.
+fn materialize(
+  builder: &mut Builder,
+  select: &Select,
+  context: Context,
+) -> SqliteResult<(usize, QueryResult)> {
+  let cursor = builder.cursor();
+  let address = builder.address();
+  builder.emit(Op::OpenEphemeral { cursor, columns: 0 });
+  let result = compile_query(builder, select, context, &mut |builder, start, count| {
+    builder.emit(Op::Insert {
+      cursor,
+      start,
+      count,
+    });
+    Ok(())
+  })?;
+  if let Some(Op::OpenEphemeral { columns, .. }) = builder.op_mut(address) {
+    *columns = result.columns.len();
+  }
+  Ok((cursor, result))
+}
+
+/// Compiles a select that is not compound, or one of the selects of a
+/// compound, with the ORDER BY and LIMIT that apply to it.
+fn compile_core<'r>(
+  builder: &mut Builder<'r>,
+  select: &Select,
+  context: Context,
+  order_by: &[OrderingTerm],
+  limits: Option<Limits>,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<QueryResult> {
+  let flattened = flatten(builder.runtime, select, order_by, context);
+  let (select, order_by) = match &flattened {
+    Some((select, order_by)) => (select, &order_by[..]),
+    None => (select, order_by),
+  };
+  let mut scope = Scope::new(builder.runtime, context);
+  let star = [ResultColumn::Star];
+  let (mut joins, columns) = match select.values.is_empty() {
+    true => (
+      Joins::new(builder, &mut scope, select.from.as_ref())?,
+      &select.columns[..],
+    ),
+    false => (
+      Joins::values(builder, &mut scope, &select.values)?,
+      &star[..],
+    ),
+  };
+
+  let (names, exprs) = expand_result_columns(&scope, columns)?;
+  scope.aliases = result_aliases(select);
+  if let Some(where_clause) = &select.where_clause {
+    scope.check(where_clause, Clause::Where)?;
+  }
+  joins.plan(&scope, select.from.as_ref(), select.where_clause.as_ref())?;
+  let group_by = group_by_terms(&scope, &select.group_by, &exprs)?;
+  if let Some(having) = &select.having {
+    scope.check(having, Clause::Having)?;
+  }
+  let written_order_by = order_by;
+  let order_by = order_by_terms(&scope, order_by, &exprs)?;
+  let order_keys: Vec<Expr> = order_by.iter().map(|term| term.expr.clone()).collect();
+  let mut windows: Vec<WindowCall> = vec![];
+  for expr in exprs.iter().chain(&order_keys) {
+    for call in window_calls(expr, &select.windows)? {
+      if !windows.iter().any(|window| window.expr() == call.expr()) {
+        windows.push(call);
+      }
+    }
+  }
+  for call in &windows {
+    for expr in call.window_exprs() {
+      scope.check(expr, Clause::Result)?;
+    }
+  }
+  let mut aggregates = vec![];
+  let window_exprs = windows.iter().flat_map(WindowCall::window_exprs);
+  for expr in exprs.iter().chain(&select.having).chain(window_exprs) {
+    for call in aggregate_calls(expr)? {
+      if !aggregates
+        .iter()
+        .any(|aggregate: &AggregateCall| aggregate.expr() == call.expr())
+      {
+        aggregates.push(call);
+      }
+    }
+  }
+  let is_aggregate = !group_by.is_empty() || !aggregates.is_empty();
+  if select.having.is_some() && !is_aggregate {
+    return Err(SqliteError::Custom(
+      "HAVING clause on a non-aggregate query".into(),
+    ));
+  }
+  // ORDER BY may use aggregate functions only in an aggregate query.
+  for expr in &order_keys {
+    for call in aggregate_calls(expr)? {
+      if !is_aggregate {
+        return Err(SqliteError::Custom(format!(
+          "misuse of aggregate: {}()",
+          call.name()
+        )));
+      }
+      if !aggregates
+        .iter()
+        .any(|aggregate: &AggregateCall| aggregate.expr() == call.expr())
+      {
+        aggregates.push(call);
+      }
+    }
+  }
+
+  let env = Names {
+    scope: &scope,
+    aliases: false,
+  };
+  let affinities = exprs.iter().map(|expr| expr_affinity(expr, &env)).collect();
+  let collations = exprs
+    .iter()
+    .map(|expr| scope.collation_name(expr))
+    .collect();
+  let declared_types: Vec<Option<String>> =
+    exprs.iter().map(|expr| scope.declared_type(expr)).collect();
+  let distinct = match select.distinct {
+    true => Some(
+      exprs
+        .iter()
+        .map(|expr| scope.collation(expr).map(KeyOrder::ascending))
+        .collect::<SqliteResult<_>>()?,
+    ),
+    false => None,
+  };
+  // The rows of a query that neither groups nor removes duplicates need
+  // no sort when its tables are read in the order of ORDER BY.
+  let is_sortable = !is_aggregate && windows.is_empty() && !select.distinct;
+  let used: Vec<&Expr> = exprs
+    .iter()
+    .chain(&order_keys)
+    .chain(&group_by)
+    .chain(&select.having)
+    .chain(windows.iter().flat_map(WindowCall::window_exprs))
+    .collect();
+  let is_ordered = joins.pick_access(
+    builder.runtime,
+    &scope,
+    &used,
+    (is_sortable && !order_by.is_empty()).then_some(&order_by[..]),
+  )?;
+  let key_orders = match is_ordered {
+    true => vec![],
+    false => order_by
+      .iter()
+      .map(|term| scope.term_order(term))
+      .collect::<SqliteResult<_>>()?,
+  };
+  let result = QueryResult {
+    columns: names,
+    rows: vec![],
+    affinities,
+    collations,
+    declared_types,
+  };
+  if let Some(explainer) = context.explainer {
+    match (select.values.len(), joins.is_empty()) {
+      (0, true) => {
+        explainer.add("SCAN CONSTANT ROW");
+      }
+      (0, false) => joins.explain(explainer),
+      (rows, _) => {
+        explainer.add(format!("SCAN {rows}-ROW VALUES CLAUSE"));
+      }
+    }
+    explain_subqueries(builder, explainer, &scope, select, written_order_by)?;
+    if !group_by.is_empty() {
+      explainer.add("USE TEMP B-TREE FOR GROUP BY");
+    }
+    if select.distinct {
+      explainer.add("USE TEMP B-TREE FOR DISTINCT");
+    }
+    if !key_orders.is_empty() {
+      explainer.add("USE TEMP B-TREE FOR ORDER BY");
+    }
+    return Ok(result);
+  }
+
+  let output = Output::open(builder, distinct, key_orders, limits);
+  // With window calls, the rows are held in an ephemeral table until all
+  // are known: the values of the row, the results of its aggregate calls,
+  // then the values of each window call.
+  let width = scope.width();
+  let windowed = match windows.is_empty() {
+    true => None,
+    false => {
+      let cursor = builder.cursor();
+      let mut offset = width + aggregates.len();
+      let mut specs = vec![];
+      for call in &windows {
+        specs.push(call.spec(&scope, offset)?);
+        offset += call.width();
+      }
+      builder.emit(Op::OpenEphemeral {
+        cursor,
+        columns: offset,
+      });
+      Some((cursor, specs, offset))
+    }
+  };
+  let mut stage = |builder: &mut Builder<'r>| -> SqliteResult<()> {
+    let Some((cursor, _, row_width)) = &windowed else {
+      return compile_result(builder, &scope, &output, &exprs, &order_keys, emit);
+    };
+    let row = builder.registers(*row_width);
+    scope.compile_row(builder, row);
+    for (index, call) in aggregates.iter().enumerate() {
+      compile_expr(builder, &scope, call.expr(), true, row + width + index)?;
+    }
+    let mut register = row + width + aggregates.len();
+    for call in &windows {
+      call.compile_values(builder, &scope, register)?;
+      register += call.width();
+    }
+    builder.emit(Op::Insert {
+      cursor: *cursor,
+      start: row,
+      count: *row_width,
+    });
+    Ok(())
+  };
+  let having = select.having.as_ref();
+  match (is_aggregate, group_by.is_empty()) {
+    (false, _) => joins.compile(builder, &scope, &mut stage)?,
+    (true, true) => compile_single_group(builder, &scope, &joins, &aggregates, having, &mut stage)?,
+    (true, false) => compile_groups(
+      builder,
+      &scope,
+      &joins,
+      &group_by,
+      &aggregates,
+      having,
+      &mut stage,
+    )?,
+  }
+
+  if let Some((cursor, specs, row_width)) = windowed {
+    builder.emit(Op::Window { cursor, specs });
+    let top = builder.label();
+    let end = builder.label();
+    builder.emit(Op::Rewind {
+      cursor,
+      target: end,
+    });
+    builder.place(top);
+    let column = |column| Slot::Column {
+      cursor,
+      column,
+      is_real: false,
+    };
+    *scope.slots.borrow_mut() = (0..width).map(column).collect();
+    *scope.computed.borrow_mut() = aggregates
+      .iter()
+      .map(AggregateCall::expr)
+      .enumerate()
+      .map(|(index, expr)| (expr.clone(), column(width + index)))
+      .chain(
+        windows
+          .iter()
+          .enumerate()
+          .map(|(index, call)| (call.expr().clone(), column(row_width + index))),
+      )
+      .collect();
+    compile_result(builder, &scope, &output, &exprs, &order_keys, emit)?;
+    builder.emit(Op::Next {
+      cursor,
+      target: top,
+    });
+    builder.place(end);
+  }
+  output.finish(builder, exprs.len(), emit)?;
+  Ok(result)
+}
+
+/// Compiles the result columns of the current row and sends them on, with
+/// the values of its ORDER BY terms.
+fn compile_result<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  output: &Output,
+  exprs: &[Expr],
+  order_keys: &[Expr],
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<()> {
+  let row = builder.registers(exprs.len());
+  for (index, expr) in exprs.iter().enumerate() {
+    compile_expr(builder, scope, expr, true, row + index)?;
+  }
+  output.compile_row(
+    builder,
+    row,
+    exprs.len(),
+    &mut |builder, key| {
+      for (index, expr) in order_keys.iter().enumerate() {
+        compile_expr(builder, scope, expr, true, key + index)?;
+      }
+      Ok(())
+    },
+    emit,
+  )
+}
+
+/// Expands `*` and `table.*`, and names every result column.
+fn expand_result_columns(
+  scope: &Scope,
+  columns: &[ResultColumn],
+) -> SqliteResult<(Vec<String>, Vec<Expr>)> {
+  let mut names = vec![];
+  let mut exprs = vec![];
+  for column in columns {
+    let sources: Vec<&Source> = match column {
+      ResultColumn::Star if scope.sources.is_empty() => {
+        return Err(SqliteError::Custom("no tables specified".into()))
+      }
+      ResultColumn::Star => scope.sources.iter().collect(),
+      ResultColumn::TableStar(table) => {
+        let sources: Vec<&Source> = scope
+          .sources
+          .iter()
+          .filter(|source| source.name.eq_ignore_ascii_case(table))
+          .collect();
+        if sources.is_empty() {
+          return Err(SqliteError::Custom(format!("no such table: {table}")));
+        }
+        sources
+      }
+      ResultColumn::Expr { expr, alias, text } => {
+        scope.check(expr, Clause::Result)?;
+        let name = match (alias, expr) {
+          (Some(alias), _) => alias.clone(),
+          (None, Expr::Column { table, name, .. }) => scope
+            .find(table.as_deref(), name)?
+            .map_or_else(|| name.clone(), |(_, name, _)| name),
+          (None, _) => text.clone(),
+        };
+        names.push(name);
+        exprs.push(expr.clone());
+        continue;
+      }
+    };
+    let is_star = matches!(column, ResultColumn::Star);
+    for source in sources {
+      for (index, name) in source.columns.iter().enumerate() {
+        if (is_star && source.hidden.contains(&index)) || source.is_argument(index) {
+          continue;
+        }
+        // A column joined by USING or NATURAL reads as the first of the
+        // joined columns that is not NULL.
+        let position = source.offset + index;
+        let is_coalesced = is_star
+          && scope.coalesced.iter().any(|(left, _)| *left == position)
+          && scope
+            .resolve(None, name)
+            .is_ok_and(|(resolved, ..)| resolved == position);
+        names.push(name.clone());
+        exprs.push(Expr::Column {
+          schema: None,
+          table: (!is_coalesced).then(|| source.name.clone()),
+          name: name.clone(),
+        });
+      }
+    }
+  }
+  Ok((names, exprs))
+}
+
+/// The aliased result columns of a select, which its clauses may refer to.
+fn result_aliases(select: &Select) -> Vec<(String, Expr)> {
+  select
+    .columns
+    .iter()
+    .filter_map(|column| match column {
+      ResultColumn::Expr {
+        expr,
+        alias: Some(alias),
+        ..
+      } => Some((alias.clone(), expr.clone())),
+      _ => None,
+    })
+    .collect()
+}
+
+/// Resolves the terms of a GROUP BY clause. A constant integer `k` stands
+/// for the `k`-th result column.
+fn group_by_terms(scope: &Scope, terms: &[Expr], exprs: &[Expr]) -> SqliteResult<Vec<Expr>> {
+  terms
+    .iter()
+    .enumerate()
+    .map(|(index, term)| {
+      let term = match term {
+        Expr::Literal(Literal::Integer(position)) => {
+          match usize::try_from(*position)
+            .ok()
+            .and_then(|position| exprs.get(position.checked_sub(1)?))
+          {
+            Some(expr) => expr,
+            None => {
+              return Err(SqliteError::Custom(format!(
+                "{} GROUP BY term out of range - should be between 1 and {}",
+                ordinal(index + 1),
+                exprs.len()
+              )))
+            }
+          }
+        }
+        term => term,
+      };
+      scope.check(term, Clause::GroupBy)?;
+      Ok(term.clone())
+    })
+    .collect()
+}
+
+/// Resolves the terms of an ORDER BY clause. A constant integer `k` stands
+/// for the `k`-th result column, and a name for the result column with that
+/// alias, even if a column of the tables has the same name. A `COLLATE` on
+/// the term applies to the result column.
+fn order_by_terms(
+  scope: &Scope,
+  terms: &[OrderingTerm],
+  exprs: &[Expr],
+) -> SqliteResult<Vec<OrderingTerm>> {
+  terms
+    .iter()
+    .enumerate()
+    .map(|(index, term)| {
+      let (inner, collation) = match &term.expr {
+        Expr::Collate { expr, collation } => (&**expr, Some(collation)),
+        expr => (expr, None),
+      };
+      let resolved = match inner {
+        Expr::Literal(Literal::Integer(position)) => {
+          match usize::try_from(*position)
+            .ok()
+            .and_then(|position| exprs.get(position.checked_sub(1)?))
+          {
+            Some(expr) => expr,
+            None => {
+              return Err(SqliteError::Custom(format!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(index + 1),
+                exprs.len()
+              )))
+            }
+          }
+        }
+        Expr::Column {
+          table: None, name, ..
+        } => scope
+          .aliases
+          .iter()
+          .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+          .map_or(inner, |(_, expr)| expr),
+        expr => expr,
+      };
+      scope.check(resolved, Clause::OrderBy)?;
+      let expr = match collation {
+        Some(collation) => Expr::Collate {
+          expr: Box::new(resolved.clone()),
+          collation: collation.clone(),
+        },
+        None => resolved.clone(),
+      };
+      Ok(OrderingTerm {
+        expr,
+        order: term.order,
+        nulls: term.nulls,
+      })
+    })
+    .collect()
+}
+
+/// `1st`, `2nd`, `3rd`, `4th`...
+fn ordinal(number: usize) -> String {
+  let suffix = match (number % 10, number % 100) {
+    (_, 11..=13) => "th",
+    (1, _) => "st",
+    (2, _) => "nd",
+    (3, _) => "rd",
+    _ => "th",
+  };
+  format!("{number}{suffix}")
+}
+
+fn evaluate_integer(expr: &Expr, env: &dyn Environment) -> SqliteResult<i64> {
+  match evaluate(expr, env)? {
+    Value::Integer(integer) => Ok(integer),
+    Value::Text(text) if text.trim().parse::<i64>().is_ok() => {
+      Ok(text.trim().parse().unwrap_or_default())
+    }
+    _ => Err(SqliteError::Custom("datatype mismatch".into())),
+  }
+}
@@ -0,0 +1,1397 @@
+//! # Compiled queries
+//!
+//!  Every query runs as a program of the [virtual database
+//! engine](crate::runtime::vdbe). The program opens a cursor on every table
+//! and runs the nested loops of the joins (see [`join`](super::join)),
+//! where each condition is a jump to the next row. A row that leaves the
+//! loops has its result columns computed into registers and returned, or
+//! added to a sorter for ORDER BY, which returns the rows once the loops
+//! are over. LIMIT and OFFSET are counted down in registers.
+//!
+//!  Where the query reads a position of its row depends on the stage of
+//! the program: a column of a cursor inside the loops, a register once rows
+//! are grouped, a column of an ephemeral table for window calls. The scope
+//! of the query notes it for every position, and for the aggregate and
+//! window calls computed so far.
+//!
+//!  A subquery in an expression is compiled where it is used. One that
+//! reads no column of its outer queries runs only the first time, and
+//! keeps its result in a register or an ephemeral table for the next ones.
+//!
+//!  Every other statement is a program too. `EXPLAIN` steps through the
+//! program of its query one instruction at a time, listing each rather than
+//! running it. `EXPLAIN QUERY PLAN` returns the steps of the plan, which
+//! are known once the query is compiled, and a `PRAGMA` reads the rows of
+//! its function when it runs.
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+use std::rc::Rc;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    collation::Collation,
+    eval::{
+      collation_name, comparison, comparison_collation_name, expr_affinity, no_such_column,
+      truth_value,
+    },
+    functions::{find_function, resolve_call, FunctionDef, Implementation},
+    pragma::PragmaAction,
+    vdbe::{JsonCheck, Op, Program, Vdbe, EXPLAIN_COLUMNS},
+    SqliteRuntime,
+  },
+  sql::ast::{
+    BinaryOperator, Expr, FunctionArgs, Literal, Pragma, Select, Statement, UnaryOperator,
+  },
+  value::{Affinity, Value},
+};
+
+use super::{compile_query, Context, Names, QueryResult, Scope};
+
+/// A statement compiled to a program, with the columns of its result.
+pub(crate) struct CompiledStatement<'a> {
+  pub(crate) program: Program<'a>,
+  pub(crate) columns: Vec<String>,
+  affinities: Vec<Affinity>,
+  collations: Vec<Option<String>>,
+  pub(crate) declared_types: Vec<Option<String>>,
+  /// Whether the program is listed rather than run, for `EXPLAIN`.
+  pub(crate) is_listing: bool,
+}
+
+impl<'a> CompiledStatement<'a> {
+  /// A program whose result columns have no affinity, collation or
+  /// declared type.
+  fn untyped(program: Program<'a>, columns: Vec<String>) -> Self {
+    let count = columns.len();
+    Self {
+      program,
+      columns,
+      affinities: vec![Affinity::Blob; count],
+      collations: vec![None; count],
+      declared_types: vec![None; count],
+      is_listing: false,
+    }
+  }
+}
+
+/// How a position of the row of a query is read.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Slot {
+  /// A column of a table, of an ephemeral table or of an index entry, which
+  /// is made a real for a REAL column.
+  Column {
+    cursor: usize,
+    column: usize,
+    is_real: bool,
+  },
+  Rowid {
+    cursor: usize,
+  },
+  /// A value held in a register, once rows are grouped.
+  Register(usize),
+  /// A position no cursor reads, such as the rowid of a WITHOUT ROWID
+  /// table.
+  Null,
+}
+
+/// Where a query sends its result rows: the instructions compiled for each
+/// row, given the first of the registers that hold it and their number.
+pub(super) type Sink<'s, 'r> = dyn FnMut(&mut Builder<'r>, usize, usize) -> SqliteResult<()> + 's;
+
+/// Emits the instructions of a program, allocates its registers and
+/// cursors, and places the labels its jumps go to.
+pub(crate) struct Builder<'r> {
+  /// The runtime whose schema the program reads, which it may outlive the
+  /// query.
+  pub(super) runtime: &'r SqliteRuntime,
+  ops: Vec<Op<'r>>,
+  /// The address of each label, once placed. Jumps go to labels until the
+  /// program is finished.
+  labels: Vec<Option<usize>>,
+  registers: usize,
+  cursors: usize,
+  aggregates: usize,
+  /// The instructions that set the program up, which load the constant
+  /// LIMIT and OFFSET of the outermost query once.
+  setup: Vec<Op<'r>>,
+  /// How deep the query being compiled is nested in the outermost one.
+  pub(super) depth: usize,
+}
+
+impl<'r> Builder<'r> {
+  pub(super) fn new(runtime: &'r SqliteRuntime) -> Self {
+    Self {
+      runtime,
+      ops: vec![],
+      labels: vec![],
+      registers: 0,
+      cursors: 0,
+      aggregates: 0,
+      setup: vec![],
+      depth: 0,
+    }
+  }
+
+  pub(super) fn emit(&mut self, op: Op<'r>) {
+    self.ops.push(op);
+  }
+
+  /// Emits an instruction that loads a constant, with those that set the
+  /// program up when the outermost query needs it.
+  pub(super) fn constant(&mut self, op: Op<'r>) {
+    match self.depth {
+      0 => self.setup.push(op),
+      _ => self.emit(op),
+    }
+  }
+
+  /// The address of the next instruction.
+  pub(super) fn address(&self) -> usize {
+    self.ops.len()
+  }
+
+  /// The instruction at an address, to be completed once more of the query
+  /// is known.
+  pub(super) fn op_mut(&mut self, address: usize) -> Option<&mut Op<'r>> {
+    self.ops.get_mut(address)
+  }
+
+  pub(super) fn label(&mut self) -> usize {
+    self.labels.push(None);
+    self.labels.len() - 1
+  }
+
+  /// Makes a label refer to the next instruction.
+  pub(super) fn place(&mut self, label: usize) {
+    self.labels[label] = Some(self.ops.len());
+  }
+
+  pub(super) fn register(&mut self) -> usize {
+    self.registers(1)
+  }
+
+  /// Allocates `count` consecutive registers, and returns the first.
+  pub(super) fn registers(&mut self, count: usize) -> usize {
+    let first = self.registers + 1;
+    self.registers += count;
+    first
+  }
+
+  pub(super) fn cursor(&mut self) -> usize {
+    self.cursors += 1;
+    self.cursors - 1
+  }
+
+  /// Allocates `count` consecutive aggregate slots, and returns the first.
+  pub(super) fn aggregates(&mut self, count: usize) -> usize {
+    self.aggregates += count;
+    self.aggregates - count
+  }
+
+  /// Copies `count` registers from `source` to `dest`.
+  pub(super) fn copy(&mut self, source: usize, dest: usize, count: usize) {
+    for offset in 0..count {
+      self.emit(Op::Copy {
+        source: source + offset,
+        dest: dest + offset,
+      });
+    }
+  }
+
+  /// The program, with its jumps going to the addresses of their labels.
+  fn finish(mut self) -> SqliteResult<Program<'r>> {
+    for op in &mut self.ops {
+      if let Some(target) = op.target_mut() {
+        *target = self
+          .labels
+          .get(*target)
+          .copied()
+          .flatten()
+          .ok_or_else(|| SqliteError::Custom(format!("unplaced label: {target}")))?;
+      }
+    }
+    Ok(Program::new(self.ops, self.registers, self.cursors))
+  }
+}
+
+impl SqliteRuntime {
+  /// Compiles a statement to a program, which returns its rows.
+  pub(crate) fn compile_statement(
+    &self,
+    statement: &Statement,
+  ) -> SqliteResult<CompiledStatement<'_>> {
+    match statement {
+      Statement::Select(select) => self.compile_select(select),
+      Statement::Explain(select) => {
+        let columns = EXPLAIN_COLUMNS
+          .iter()
+          .map(|column| column.to_string())
+          .collect();
+        Ok(CompiledStatement {
+          is_listing: true,
+          ..CompiledStatement::untyped(self.compile_select(select)?.program, columns)
+        })
+      }
+      Statement::ExplainQueryPlan(select) => {
+        let plan = self.explain_select(select)?.into_result();
+        let mut builder = Builder::new(self);
+        let start = builder.label();
+        builder.emit(Op::Init { target: start });
+        builder.place(start);
+        let row = builder.registers(plan.columns.len());
+        for values in &plan.rows {
+          for (offset, value) in values.iter().enumerate() {
+            builder.emit(load_value(value, row + offset));
+          }
+          builder.emit(Op::ResultRow {
+            start: row,
+            count: values.len(),
+          });
+        }
+        builder.emit(Op::Halt);
+        Ok(CompiledStatement::untyped(builder.finish()?, plan.columns))
+      }
+      Statement::Pragma(pragma) => self.compile_pragma(pragma),
+    }
+  }
+
+  /// Compiles a select to a program, which returns its rows.
+  pub(crate) fn compile_select(&self, select: &Select) -> SqliteResult<CompiledStatement<'_>> {
+    let mut builder = Builder::new(self);
+    let start = builder.label();
+    let setup = builder.label();
+    builder.emit(Op::Init { target: setup });
+    builder.place(start);
+    let result = compile_query(
+      &mut builder,
+      select,
+      Context::default(),
+      &mut |builder, start, count| {
+        builder.emit(Op::ResultRow { start, count });
+        Ok(())
+      },
+    )?;
+    builder.emit(Op::Halt);
+    builder.place(setup);
+    for op in core::mem::take(&mut builder.setup) {
+      builder.emit(op);
+    }
+    builder.emit(Op::Goto { target: start });
+    Ok(CompiledStatement {
+      program: builder.finish()?,
+      columns: result.columns,
+      affinities: result.affinities,
+      collations: result.collations,
+      declared_types: result.declared_types,
+      is_listing: false,
+    })
+  }
+
+  /// Compiles a `PRAGMA` statement: a program that reads the rows of the
+  /// function of the pragma into an ephemeral table and returns them, or
+  /// that writes a field of the header.
+  fn compile_pragma(&self, pragma: &Pragma) -> SqliteResult<CompiledStatement<'_>> {
+    let mut builder = Builder::new(self);
+    let start = builder.label();
+    builder.emit(Op::Init { target: start });
+    builder.place(start);
+    let columns = match self.pragma(pragma)? {
+      PragmaAction::Read(function, args) => {
+        let columns = &function.columns[..function.columns.len() - function.arguments];
+        let first = builder.registers(args.len());
+        for (offset, arg) in args.iter().enumerate() {
+          builder.emit(load_value(arg, first + offset));
+        }
+        let cursor = builder.cursor();
+        builder.emit(Op::FunctionRows {
+          cursor,
+          function,
+          args: first,
+          count: args.len(),
+        });
+        let top = builder.label();
+        let end = builder.label();
+        builder.emit(Op::Rewind {
+          cursor,
+          target: end,
+        });
+        builder.place(top);
+        let row = builder.registers(columns.len());
+        for column in 0..columns.len() {
+          builder.emit(Op::Column {
+            cursor,
+            column,
+            dest: row + column,
+          });
+        }
+        builder.emit(Op::ResultRow {
+          start: row,
+          count: columns.len(),
+        });
+        builder.emit(Op::Next {
+          cursor,
+          target: top,
+        });
+        builder.place(end);
+        columns.iter().map(|column| column.to_string()).collect()
+      }
+      PragmaAction::Set(offset, value) => {
+        builder.emit(Op::SetCookie { offset, value });
+        vec![]
+      }
+      PragmaAction::Empty(columns) => columns,
+    };
+    builder.emit(Op::Halt);
+    Ok(CompiledStatement::untyped(builder.finish()?, columns))
+  }
+
+  /// Runs a statement to its end.
+  pub(crate) fn execute(&self, statement: &Statement) -> SqliteResult<QueryResult> {
+    let compiled = self.compile_statement(statement)?;
+    let mut vdbe = Vdbe::new(self, Rc::new(compiled.program));
+    if compiled.is_listing {
+      vdbe = vdbe.listing();
+    }
+    let mut rows = vec![];
+    while let Some(row) = vdbe.step()? {
+      rows.push(row);
+    }
+    Ok(QueryResult {
+      columns: compiled.columns,
+      rows,
+      affinities: compiled.affinities,
+      collations: compiled.collations,
+      declared_types: compiled.declared_types,
+    })
+  }
+}
+
+/// The instruction that loads a value into a register.
+fn load_value<'a>(value: &Value, dest: usize) -> Op<'a> {
+  match value {
+    Value::Null => Op::Null { dest },
+    Value::Integer(value) => Op::Integer {
+      value: *value,
+      dest,
+    },
+    Value::Real(value) => Op::Real {
+      value: *value,
+      dest,
+    },
+    Value::Text(value) => Op::String8 {
+      value: value.clone(),
+      dest,
+    },
+    Value::Blob(value) => Op::Blob {
+      value: value.clone(),
+      dest,
+    },
+  }
+}
+
+/// Compiles an expression of a query into instructions that store its value
+/// in `dest`. Column names may refer to aliased result columns with
+/// `aliases`.
+pub(super) fn compile_expr(
+  builder: &mut Builder,
+  scope: &Scope,
+  expr: &Expr,
+  aliases: bool,
+  dest: usize,
+) -> SqliteResult<()> {
+  if let Some(slot) = scope.computed(expr) {
+    compile_slot(builder, slot, dest);
+    return Ok(());
+  }
+  let env = Names { scope, aliases };
+  match expr {
+    Expr::Literal(literal) => {
+      let op = match literal {
+        Literal::Null => Op::Null { dest },
+        Literal::Integer(value) => Op::Integer {
+          value: *value,
+          dest,
+        },
+        Literal::Float(value) => Op::Real {
+          value: *value,
+          dest,
+        },
+        Literal::String(value) => Op::String8 {
+          value: value.clone(),
+          dest,
+        },
+        Literal::Blob(value) => Op::Blob {
+          value: value.clone(),
+          dest,
+        },
+        // The time the statement started, as `date()`, `time()` and
+        // `datetime()` without arguments return it.
+        Literal::CurrentTime | Literal::CurrentDate | Literal::CurrentTimestamp => {
+          let name = match literal {
+            Literal::CurrentDate => "date",
+            Literal::CurrentTime => "time",
+            _ => "datetime",
+          };
+          Op::Function {
+            function: find_function(name, &FunctionArgs::List(vec![]))?,
+            args: dest,
+            count: 0,
+            dest,
+          }
+        }
+      };
+      builder.emit(op);
+    }
+    Expr::Variable(name) => builder.emit(Op::Variable {
+      name: name.clone(),
+      dest,
+    }),
+    Expr::Column { table, name, .. } => {
+      if let Some(alias) = env.alias(table.as_deref(), name) {
+        return compile_expr(builder, scope, alias, false, dest);
+      }
+      match constant_truth(scope, expr)? {
+        Some(value) => builder.emit(Op::Integer {
+          value: value.into(),
+          dest,
+        }),
+        None => compile_column(builder, scope, table.as_deref(), name, dest)?,
+      }
+    }
+    Expr::Unary { operator, expr } => {
+      compile_expr(builder, scope, expr, aliases, dest)?;
+      let source = dest;
+      match operator {
+        UnaryOperator::Plus => {}
+        UnaryOperator::Negate => builder.emit(Op::Negate { source, dest }),
+        UnaryOperator::Not => builder.emit(Op::Not { source, dest }),
+        UnaryOperator::BitNot => builder.emit(Op::BitNot { source, dest }),
+      }
+    }
+    Expr::Binary {
+      left,
+      operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+      right,
+    } => {
+      // The right operand is skipped when the left one decides: FALSE for
+      // AND, TRUE for OR.
+      let is_and = *operator == BinaryOperator::And;
+      let decided = builder.label();
+      let done = builder.label();
+      compile_expr(builder, scope, left, aliases, dest)?;
+      builder.emit(match is_and {
+        true => Op::IfNot {
+          register: dest,
+          target: decided,
+          if_null: false,
+        },
+        false => Op::If {
+          register: dest,
+          target: decided,
+          if_null: false,
+        },
+      });
+      let other = builder.register();
+      compile_expr(builder, scope, right, aliases, other)?;
+      builder.emit(match is_and {
+        true => Op::And {
+          left: dest,
+          right: other,
+          dest,
+        },
+        false => Op::Or {
+          left: dest,
+          right: other,
+          dest,
+        },
+      });
+      builder.emit(Op::Goto { target: done });
+      builder.place(decided);
+      builder.emit(Op::Integer {
+        value: (!is_and).into(),
+        dest,
+      });
+      builder.place(done);
+    }
+    Expr::Binary {
+      left,
+      operator,
+      right,
+    } if comparison(*operator).is_some() => {
+      compile_comparison(builder, scope, aliases, *operator, left, right, dest)?;
+    }
+    Expr::Binary {
+      left,
+      operator,
+      right,
+    } => {
+      let (left_register, right_register) = (builder.register(), builder.register());
+      compile_expr(builder, scope, left, aliases, left_register)?;
+      compile_expr(builder, scope, right, aliases, right_register)?;
+      builder.emit(Op::Arithmetic {
+        operator: *operator,
+        left: left_register,
+        right: right_register,
+        dest,
+      });
+    }
+    Expr::IsNull { expr, not } => {
+      let value = builder.register();
+      let done = builder.label();
+      compile_expr(builder, scope, expr, aliases, value)?;
+      builder.emit(Op::Integer { value: 1, dest });
+      builder.emit(match not {
+        false => Op::IsNull {
+          register: value,
+          target: done,
+        },
+        true => Op::NotNull {
+          register: value,
+          target: done,
+        },
+      });
+      builder.emit(Op::Integer { value: 0, dest });
+      builder.place(done);
+    }
+    // The collation only matters to the comparison the expression is in.
+    Expr::Collate { expr, .. } => compile_expr(builder, scope, expr, aliases, dest)?,
+    Expr::Cast { expr, type_name } => {
+      compile_expr(builder, scope, expr, aliases, dest)?;
+      builder.emit(Op::Cast {
+        register: dest,
+        affinity: Affinity::from_declared_type(Some(type_name)),
+      });
+    }
+    Expr::Between {
+      expr,
+      not,
+      low,
+      high,
+    } => {
+      let is_low = builder.register();
+      compile_comparison(
+        builder,
+        scope,
+        aliases,
+        BinaryOperator::Ge,
+        expr,
+        low,
+        is_low,
+      )?;
+      compile_comparison(
+        builder,
+        scope,
+        aliases,
+        BinaryOperator::Le,
+        expr,
+        high,
+        dest,
+      )?;
+      builder.emit(Op::And {
+        left: is_low,
+        right: dest,
+        dest,
+      });
+      if *not {
+        builder.emit(Op::Not { source: dest, dest });
+      }
+    }
+    // `(x, y) IN ((a, b), (c, d))` compares the rows in turn.
+    Expr::InList { expr, not, list } if matches!(**expr, Expr::Row(_)) => {
+      let is_equal = builder.register();
+      let done = builder.label();
+      builder.emit(Op::Integer { value: 0, dest });
+      for item in list {
+        compile_comparison(
+          builder,
+          scope,
+          aliases,
+          BinaryOperator::Eq,
+          expr,
+          item,
+          is_equal,
+        )?;
+        builder.emit(Op::Or {
+          left: dest,
+          right: is_equal,
+          dest,
+        });
+        builder.emit(Op::If {
+          register: dest,
+          target: done,
+          if_null: false,
+        });
+      }
+      builder.place(done);
+      if *not {
+        builder.emit(Op::Not { source: dest, dest });
+      }
+    }
+    Expr::InList { expr, not, list } => {
+      if list.is_empty() {
+        builder.emit(Op::Integer {
+          value: (*not).into(),
+          dest,
+        });
+        return Ok(());
+      }
+      // `x IN (y, z)` is `x = +y OR x = +z`: only the left operand can lend
+      // its affinity.
+      let value = builder.register();
+      let item = builder.register();
+      let is_equal = builder.register();
+      let done = builder.label();
+      compile_expr(builder, scope, expr, aliases, value)?;
+      builder.emit(Op::Null { dest });
+      builder.emit(Op::IsNull {
+        register: value,
+        target: done,
+      });
+      builder.emit(Op::Integer { value: 0, dest });
+      let affinity = expr_affinity(expr, &env);
+      for item_expr in list {
+        compile_expr(builder, scope, item_expr, aliases, item)?;
+        let collation = comparison_collation(scope, &env, expr, item_expr)?;
+        builder.emit(Op::Compare {
+          operator: BinaryOperator::Eq,
+          left: value,
+          right: item,
+          dest: is_equal,
+          affinities: [affinity, Affinity::Blob],
+          collation,
+        });
+        builder.emit(Op::Or {
+          left: dest,
+          right: is_equal,
+          dest,
+        });
+        builder.emit(Op::If {
+          register: dest,
+          target: done,
+          if_null: false,
+        });
+      }
+      builder.place(done);
+      if *not {
+        builder.emit(Op::Not { source: dest, dest });
+      }
+    }
+    Expr::Case {
+      operand,
+      when_then,
+      else_expr,
+    } => {
+      let done = builder.label();
+      let is_match = builder.register();
+      for (when, then) in when_then {
+        let next = builder.label();
+        match operand {
+          Some(operand) => compile_comparison(
+            builder,
+            scope,
+            aliases,
+            BinaryOperator::Eq,
+            operand,
+            when,
+            is_match,
+          )?,
+          None => compile_expr(builder, scope, when, aliases, is_match)?,
+        }
+        builder.emit(Op::IfNot {
+          register: is_match,
+          target: next,
+          if_null: true,
+        });
+        compile_expr(builder, scope, then, aliases, dest)?;
+        builder.emit(Op::Goto { target: done });
+        builder.place(next);
+      }
+      match else_expr {
+        Some(else_expr) => compile_expr(builder, scope, else_expr, aliases, dest)?,
+        None => builder.emit(Op::Null { dest }),
+      }
+      builder.place(done);
+    }
+    Expr::Like {
+      expr,
+      not,
+      operator,
+      pattern,
+      escape,
+    } => {
+      let operands: Vec<&Expr> = [&**expr, &**pattern]
+        .into_iter()
+        .chain(escape.as_deref())
+        .collect();
+      let args = compile_args(builder, scope, aliases, &operands)?;
+      builder.emit(Op::Like {
+        operator: *operator,
+        args,
+        count: operands.len(),
+        dest,
+      });
+      if *not {
+        builder.emit(Op::Not { source: dest, dest });
+      }
+    }
+    Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    } => {
+      let function = resolve_call(
+        name,
+        *distinct,
+        args,
+        order_by,
+        filter.as_deref(),
+        over.is_some(),
+      )?;
+      // Aggregate and window calls are computed by their query, and found
+      // above where it allows them.
+      match function.implementation {
+        _ if over.is_some() => {
+          return Err(SqliteError::Custom(format!(
+            "misuse of window function {name}()"
+          )))
+        }
+        Implementation::Scalar(_) => {}
+        Implementation::Aggregate(_) | Implementation::Window(_) => {
+          return Err(SqliteError::Custom(format!(
+            "misuse of aggregate function {name}()"
+          )))
+        }
+      }
+      let operands: Vec<&Expr> = match args {
+        FunctionArgs::Star => vec![],
+        FunctionArgs::List(args) => args.iter().collect(),
+      };
+      let args = builder.registers(operands.len());
+      compile_call_args(builder, scope, function, &operands, aliases, args)?;
+      builder.emit(Op::Function {
+        function,
+        args,
+        count: operands.len(),
+        dest,
+      });
+    }
+    Expr::Row(_) => return Err(SqliteError::Custom("row value misused".into())),
+    Expr::Subquery(select) => compile_scalar_subquery(builder, scope, select, dest)?,
+    Expr::Exists { not, select } => compile_exists(builder, scope, select, *not, dest)?,
+    Expr::InSelect { expr, not, select } => {
+      compile_in_select(builder, scope, aliases, expr, select, *not, dest)?
+    }
+    Expr::InTable { .. } => {
+      return Err(SqliteError::Custom(
+        "IN with a table is not supported yet".into(),
+      ))
+    }
+  }
+  Ok(())
+}
+
+/// Compiles a condition of a query, which jumps to `target` unless it is
+/// true.
+pub(super) fn compile_condition(
+  builder: &mut Builder,
+  scope: &Scope,
+  expr: &Expr,
+  target: usize,
+) -> SqliteResult<()> {
+  let register = builder.register();
+  compile_expr(builder, scope, expr, true, register)?;
+  builder.emit(Op::IfNot {
+    register,
+    target,
+    if_null: true,
+  });
+  Ok(())
+}
+
+/// Loads the value at a position of the row.
+pub(super) fn compile_position(builder: &mut Builder, scope: &Scope, position: usize, dest: usize) {
+  let slot = scope
+    .slots
+    .borrow()
+    .get(position)
+    .copied()
+    .unwrap_or(Slot::Null);
+  compile_slot(builder, slot, dest);
+}
+
+fn compile_slot(builder: &mut Builder, slot: Slot, dest: usize) {
+  match slot {
+    Slot::Column {
+      cursor,
+      column,
+      is_real,
+    } => {
+      builder.emit(Op::Column {
+        cursor,
+        column,
+        dest,
+      });
+      if is_real {
+        builder.emit(Op::RealAffinity { register: dest });
+      }
+    }
+    Slot::Rowid { cursor } => builder.emit(Op::Rowid { cursor, dest }),
+    Slot::Register(source) => builder.emit(Op::Copy { source, dest }),
+    Slot::Null => builder.emit(Op::Null { dest }),
+  }
+}
+
+/// The value of a name that is TRUE or FALSE, when no column of the query
+/// or of an outer query has the name.
+fn constant_truth(scope: &Scope, expr: &Expr) -> SqliteResult<Option<bool>> {
+  let Expr::Column {
+    schema: None,
+    table: None,
+    name,
+  } = expr
+  else {
+    return Ok(None);
+  };
+  let Some(value) = truth_value(name) else {
+    return Ok(None);
+  };
+  let mut scope = Some(scope);
+  while let Some(current) = scope {
+    if current.find(None, name)?.is_some() {
+      return Ok(None);
+    }
+    scope = current.context.outer;
+  }
+  Ok(Some(value))
+}
+
+/// Loads the value of a column of the query, or of an outer query, whose
+/// scope then notes that a subquery reads its row. An unqualified name of
+/// a column joined by USING or NATURAL reads the first of the joined
+/// columns that is not NULL.
+fn compile_column(
+  builder: &mut Builder,
+  scope: &Scope,
+  table: Option<&str>,
+  name: &str,
+  dest: usize,
+) -> SqliteResult<()> {
+  let Some((position, ..)) = scope.find(table, name)? else {
+    let outer = scope
+      .context
+      .outer
+      .ok_or_else(|| no_such_column(table, name))?;
+    outer.is_referenced.set(true);
+    return compile_column(builder, outer, table, name, dest);
+  };
+  compile_position(builder, scope, position, dest);
+  if table.is_none() {
+    let done = builder.label();
+    for (_, other) in scope.coalesced.iter().filter(|(left, _)| *left == position) {
+      builder.emit(Op::NotNull {
+        register: dest,
+        target: done,
+      });
+      compile_position(builder, scope, *other, dest);
+    }
+    builder.place(done);
+  }
+  Ok(())
+}
+
+/// Compiles expressions into consecutive registers, and returns the first.
+fn compile_args(
+  builder: &mut Builder,
+  scope: &Scope,
+  aliases: bool,
+  exprs: &[&Expr],
+) -> SqliteResult<usize> {
+  let first = builder.registers(exprs.len());
+  for (index, expr) in exprs.iter().enumerate() {
+    compile_expr(builder, scope, expr, aliases, first + index)?;
+  }
+  Ok(first)
+}
+
+/// Compiles the arguments of a call into the registers from `first`. Those
+/// that are JSON are passed as such to the functions that take JSON.
+pub(super) fn compile_call_args(
+  builder: &mut Builder,
+  scope: &Scope,
+  function: &FunctionDef,
+  args: &[&Expr],
+  aliases: bool,
+  first: usize,
+) -> SqliteResult<()> {
+  for (index, arg) in args.iter().enumerate() {
+    let register = first + index;
+    compile_expr(builder, scope, arg, aliases, register)?;
+    if !function.is_taking_json() {
+      continue;
+    }
+    if let Some(check) = json_check(scope, arg, aliases) {
+      builder.emit(Op::ToJson { register, check });
+    }
+  }
+  Ok(())
+}
+
+/// How to tell that the value of an expression holds JSON, when it may.
+fn json_check(scope: &Scope, expr: &Expr, aliases: bool) -> Option<JsonCheck> {
+  match expr {
+    Expr::Binary {
+      operator: BinaryOperator::Extract,
+      ..
+    } => Some(JsonCheck::Always),
+    Expr::Function { name, .. } => match name.to_ascii_lowercase().as_str() {
+      "json" | "json_array" | "json_group_array" | "json_group_object" | "json_insert"
+      | "json_object" | "json_patch" | "json_remove" | "json_replace" | "json_set" => {
+        Some(JsonCheck::Always)
+      }
+      "json_extract" => Some(JsonCheck::Container),
+      _ => None,
+    },
+    Expr::Column { table, name, .. } => {
+      let is_alias = aliases && scope.alias(table.as_deref(), name).is_some();
+      (!is_alias)
+        .then(|| column_json_check(scope, table.as_deref(), name))
+        .flatten()
+    }
+    _ => None,
+  }
+}
+
+/// How to tell that a column of a table-valued function holds JSON, which
+/// the function decides from the row the column is in.
+fn column_json_check(scope: &Scope, table: Option<&str>, name: &str) -> Option<JsonCheck> {
+  let Some((position, ..)) = scope.find(table, name).ok()? else {
+    return column_json_check(scope.context.outer?, table, name);
+  };
+  let source = &scope.sources[scope.source_at(position)];
+  let function = source.function?;
+  let column = position - source.offset;
+  if column >= source.columns.len() {
+    return None;
+  }
+  match scope.slots.borrow().get(source.offset)? {
+    Slot::Column {
+      cursor,
+      column: offset,
+      ..
+    } => Some(JsonCheck::Column {
+      cursor: *cursor,
+      offset: *offset,
+      column,
+      is_json: function.is_json,
+    }),
+    Slot::Register(start) => Some(JsonCheck::Registers {
+      start: *start,
+      column,
+      is_json: function.is_json,
+    }),
+    Slot::Rowid { .. } | Slot::Null => None,
+  }
+}
+
+/// Compiles a comparison between two expressions, with their affinities
+/// and collation. `x IS TRUE` and `x IS FALSE` test the truth of `x`, and
+/// rows of the same size are compared column by column.
+fn compile_comparison(
+  builder: &mut Builder,
+  scope: &Scope,
+  aliases: bool,
+  operator: BinaryOperator,
+  left: &Expr,
+  right: &Expr,
+  dest: usize,
+) -> SqliteResult<()> {
+  if matches!(operator, BinaryOperator::Is | BinaryOperator::IsNot) {
+    if let Some(truth) = constant_truth(scope, right)? {
+      return compile_truth_test(builder, scope, aliases, operator, left, truth, dest);
+    }
+  }
+  match (left, right) {
+    (Expr::Row(left), Expr::Row(right)) if left.len() == right.len() => {
+      return compile_row_comparison(builder, scope, aliases, operator, left, right, dest)
+    }
+    (Expr::Row(_), _) | (_, Expr::Row(_)) => {
+      return Err(SqliteError::Custom("row value misused".into()))
+    }
+    _ => {}
+  }
+  let env = Names { scope, aliases };
+  let (left_register, right_register) = (builder.register(), builder.register());
+  compile_expr(builder, scope, left, aliases, left_register)?;
+  compile_expr(builder, scope, right, aliases, right_register)?;
+  let collation = comparison_collation(scope, &env, left, right)?;
+  builder.emit(Op::Compare {
+    operator,
+    left: left_register,
+    right: right_register,
+    dest,
+    affinities: [expr_affinity(left, &env), expr_affinity(right, &env)],
+    collation,
+  });
+  Ok(())
+}
+
+/// Compiles `x IS [NOT] TRUE` or `x IS [NOT] FALSE`, which is never NULL:
+/// `x IS TRUE` is whether `x` is true, not whether it is 1.
+fn compile_truth_test(
+  builder: &mut Builder,
+  scope: &Scope,
+  aliases: bool,
+  operator: BinaryOperator,
+  expr: &Expr,
+  truth: bool,
+  dest: usize,
+) -> SqliteResult<()> {
+  let value = builder.register();
+  let done = builder.label();
+  compile_expr(builder, scope, expr, aliases, value)?;
+  builder.emit(Op::Integer { value: 1, dest });
+  builder.emit(match truth {
+    true => Op::If {
+      register: value,
+      target: done,
+      if_null: false,
+    },
+    false => Op::IfNot {
+      register: value,
+      target: done,
+      if_null: false,
+    },
+  });
+  builder.emit(Op::Integer { value: 0, dest });
+  builder.place(done);
+  if operator == BinaryOperator::IsNot {
+    builder.emit(Op::Not { source: dest, dest });
+  }
+  Ok(())
+}
+
+/// Compiles a comparison of two rows of the same size. They are equal when
+/// all of their columns are, and are otherwise ordered by the first columns
+/// that are not equal, NULL when one of those is.
+///
+/// *Reference:* https://www.sqlite.org/rowvalue.html#row_value_comparisons
+fn compile_row_comparison(
+  builder: &mut Builder,
+  scope: &Scope,
+  aliases: bool,
+  operator: BinaryOperator,
+  left: &[Expr],
+  right: &[Expr],
+  dest: usize,
+) -> SqliteResult<()> {
+  let pair = builder.register();
+  let done = builder.label();
+  match operator {
+    BinaryOperator::Eq | BinaryOperator::Ne | BinaryOperator::Is | BinaryOperator::IsNot => {
+      let pair_operator = match operator {
+        BinaryOperator::Is | BinaryOperator::IsNot => BinaryOperator::Is,
+        _ => BinaryOperator::Eq,
+      };
+      builder.emit(Op::Integer { value: 1, dest });
+      for (left, right) in left.iter().zip(right) {
+        compile_comparison(builder, scope, aliases, pair_operator, left, right, pair)?;
+        builder.emit(Op::And {
+          left: dest,
+          right: pair,
+          dest,
+        });
+        builder.emit(Op::IfNot {
+          register: dest,
+          target: done,
+          if_null: false,
+        });
+      }
+      builder.place(done);
+      if matches!(operator, BinaryOperator::Ne | BinaryOperator::IsNot) {
+        builder.emit(Op::Not { source: dest, dest });
+      }
+    }
+    _ => {
+      // Only the last columns decide by the operator itself: the others
+      // that differ decide by its strict form.
+      let strict = match operator {
+        BinaryOperator::Le => BinaryOperator::Lt,
+        BinaryOperator::Ge => BinaryOperator::Gt,
+        operator => operator,
+      };
+      let count = left.len();
+      for (index, (left, right)) in left.iter().zip(right).enumerate() {
+        if index + 1 == count {
+          compile_comparison(builder, scope, aliases, operator, left, right, dest)?;
+          break;
+        }
+        let (differ, next) = (builder.label(), builder.label());
+        compile_comparison(
+          builder,
+          scope,
+          aliases,
+          BinaryOperator::Eq,
+          left,
+          right,
+          pair,
+        )?;
+        builder.emit(Op::IfNot {
+          register: pair,
+          target: differ,
+          if_null: true,
+        });
+        builder.emit(Op::Goto { target: next });
+        builder.place(differ);
+        compile_comparison(builder, scope, aliases, strict, left, right, dest)?;
+        builder.emit(Op::Goto { target: done });
+        builder.place(next);
+      }
+      builder.place(done);
+    }
+  }
+  Ok(())
+}
+
+/// The collation a comparison uses.
+fn comparison_collation(
+  scope: &Scope,
+  env: &Names,
+  left: &Expr,
+  right: &Expr,
+) -> SqliteResult<Collation> {
+  scope
+    .runtime
+    .collation(&comparison_collation_name(left, right, env))
+}
+
+/// Compiles a subquery run with the current row of its query as its outer
+/// row, with `emit` compiled for each of its rows. The instruction at
+/// `once`, which skips the subquery once it has run, is made a no-op when
+/// the subquery reads a column of the row, and so must run for every row.
+fn compile_subquery<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  select: &Select,
+  once: usize,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<QueryResult> {
+  let context = Context {
+    outer: Some(scope),
+    ..scope.context
+  };
+  let was_referenced = scope.is_referenced.replace(false);
+  let result = compile_query(builder, select, context, emit)?;
+  let is_correlated = scope.is_referenced.get();
+  scope.is_referenced.set(was_referenced || is_correlated);
+  if is_correlated {
+    if let Some(op) = builder.op_mut(once) {
+      *op = Op::Noop;
+    }
+  }
+  Ok(result)
+}
+
+/// Compiles a subquery used as a value: the first column of its first row,
+/// or NULL without rows.
+fn compile_scalar_subquery(
+  builder: &mut Builder,
+  scope: &Scope,
+  select: &Select,
+  dest: usize,
+) -> SqliteResult<()> {
+  let value = builder.register();
+  let done = builder.label();
+  let once = builder.address();
+  builder.emit(Op::Once { target: done });
+  builder.emit(Op::Null { dest: value });
+  let result = compile_subquery(builder, scope, select, once, &mut |builder, start, _| {
+    builder.emit(Op::Copy {
+      source: start,
+      dest: value,
+    });
+    builder.emit(Op::Goto { target: done });
+    Ok(())
+  })?;
+  if result.columns.len() != 1 {
+    return Err(SqliteError::Custom(format!(
+      "sub-select returns {} columns - expected 1",
+      result.columns.len()
+    )));
+  }
+  builder.place(done);
+  builder.emit(Op::Copy {
+    source: value,
+    dest,
+  });
+  Ok(())
+}
+
+/// Compiles `[NOT] EXISTS (subquery)`, which only needs its first row.
+fn compile_exists(
+  builder: &mut Builder,
+  scope: &Scope,
+  select: &Select,
+  not: bool,
+  dest: usize,
+) -> SqliteResult<()> {
+  let value = builder.register();
+  let done = builder.label();
+  let once = builder.address();
+  builder.emit(Op::Once { target: done });
+  builder.emit(Op::Integer {
+    value: not.into(),
+    dest: value,
+  });
+  compile_subquery(builder, scope, select, once, &mut |builder, _, _| {
+    builder.emit(Op::Integer {
+      value: (!not).into(),
+      dest: value,
+    });
+    builder.emit(Op::Goto { target: done });
+    Ok(())
+  })?;
+  builder.place(done);
+  builder.emit(Op::Copy {
+    source: value,
+    dest,
+  });
+  Ok(())
+}
+
+/// Compiles `x [NOT] IN (subquery)`, whose rows are kept in an ephemeral
+/// table. Both operands lend their affinity, as in a comparison, and the
+/// left one lends its collation first, then the column of the subquery. A
+/// row `(x, y)` is in the subquery when a row of it has all its columns
+/// equal.
+fn compile_in_select(
+  builder: &mut Builder,
+  scope: &Scope,
+  aliases: bool,
+  expr: &Expr,
+  select: &Select,
+  not: bool,
+  dest: usize,
+) -> SqliteResult<()> {
+  let exprs = match expr {
+    Expr::Row(exprs) => exprs.iter().collect(),
+    expr => vec![expr],
+  };
+  let width = exprs.len();
+  let cursor = builder.cursor();
+  let filled = builder.label();
+  let once = builder.address();
+  builder.emit(Op::Once { target: filled });
+  builder.emit(Op::OpenEphemeral {
+    cursor,
+    columns: width,
+  });
+  let result = compile_subquery(builder, scope, select, once, &mut |builder, start, _| {
+    builder.emit(Op::Insert {
+      cursor,
+      start,
+      count: width,
+    });
+    Ok(())
+  })?;
+  if result.columns.len() != width {
+    return Err(SqliteError::Custom(format!(
+      "sub-select returns {} columns - expected {width}",
+      result.columns.len()
+    )));
+  }
+  builder.place(filled);
+
+  let env = Names { scope, aliases };
+  let comparisons = exprs
+    .iter()
+    .enumerate()
+    .map(|(column, expr)| {
+      let affinities = [expr_affinity(expr, &env), result.affinities[column]];
+      let collation = match collation_name(expr, &env).or_else(|| result.collations[column].clone())
+      {
+        Some(name) => scope.runtime.collation(&name)?,
+        None => Collation::binary(),
+      };
+      Ok((affinities, collation))
+    })
+    .collect::<SqliteResult<Vec<_>>>()?;
+  let values = compile_args(builder, scope, aliases, &exprs)?;
+  let item = builder.register();
+  let is_equal = builder.register();
+  let is_column_equal = builder.register();
+  let (empty, decided, done, top) = (
+    builder.label(),
+    builder.label(),
+    builder.label(),
+    builder.label(),
+  );
+  builder.emit(Op::Rewind {
+    cursor,
+    target: empty,
+  });
+  builder.emit(Op::Null { dest });
+  if width == 1 {
+    builder.emit(Op::IsNull {
+      register: values,
+      target: decided,
+    });
+  }
+  // A NULL in the rows makes the result NULL rather than false.
+  builder.emit(Op::Integer { value: 0, dest });
+  builder.place(top);
+  builder.emit(Op::Integer {
+    value: 1,
+    dest: is_equal,
+  });
+  for (column, (affinities, collation)) in comparisons.into_iter().enumerate() {
+    builder.emit(Op::Column {
+      cursor,
+      column,
+      dest: item,
+    });
+    builder.emit(Op::Compare {
+      operator: BinaryOperator::Eq,
+      left: values + column,
+      right: item,
+      dest: is_column_equal,
+      affinities,
+      collation,
+    });
+    builder.emit(Op::And {
+      left: is_equal,
+      right: is_column_equal,
+      dest: is_equal,
+    });
+  }
+  builder.emit(Op::Or {
+    left: dest,
+    right: is_equal,
+    dest,
+  });
+  builder.emit(Op::If {
+    register: dest,
+    target: decided,
+    if_null: false,
+  });
+  builder.emit(Op::Next {
+    cursor,
+    target: top,
+  });
+  builder.place(decided);
+  if not {
+    builder.emit(Op::Not { source: dest, dest });
+  }
+  builder.emit(Op::Goto { target: done });
+  builder.place(empty);
+  builder.emit(Op::Integer {
+    value: not.into(),
+    dest,
+  });
+  builder.place(done);
+  Ok(())
+}
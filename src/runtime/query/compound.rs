@@ -0,0 +1,402 @@
+//! # Compound selects
+//!
+//!  The selects of a compound run in turn, and their rows are combined from
+//! left to right:
+//!
+//! - **UNION ALL** keeps the rows of both sides.
+//! - **UNION** keeps the distinct rows of both sides.
+//! - **INTERSECT** keeps the distinct rows of the left side that are also on
+//!   the right side.
+//! - **EXCEPT** keeps the distinct rows of the left side that are not on the
+//!   right side.
+//!
+//!  Rows are compared column by column with the collation of the leftmost
+//! select whose column has one, from a `COLLATE` or the declared collation
+//! of a table column. The last of equal rows is kept, and the distinct rows
+//! come out sorted: the rows of both sides go through a sorter, which
+//! spills them to temporary files past its memory budget, each with the
+//! side it comes from, and each run of equal rows is then read at once.
+//!
+//!  The ORDER BY and LIMIT of a compound apply to the combined rows. An
+//! ORDER BY term is the number of a result column, or the name or the
+//! expression of a result column of any of the selects, the leftmost first.
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html#compound_select_statements
+
+use core::iter::Peekable;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    sorter::{compare_keys, KeyOrder, Sorted},
+    vdbe::Op,
+  },
+  sql::ast::{CompoundOperator, Expr, Literal, ResultColumn, Select},
+  value::Value,
+};
+
+use super::{
+  compile::{Builder, Sink},
+  compile_core, ordinal, Context, Limits, Output, QueryResult,
+};
+
+/// The selects of a compound, with the operator before each.
+pub(super) fn members(
+  select: &Select,
+) -> impl Iterator<Item = (Option<CompoundOperator>, &Select)> {
+  core::iter::once((None, select)).chain(
+    select
+      .compound
+      .iter()
+      .map(|(operator, select)| (Some(*operator), select)),
+  )
+}
+
+fn operator_name(operator: CompoundOperator) -> &'static str {
+  match operator {
+    CompoundOperator::Union => "UNION",
+    CompoundOperator::UnionAll => "UNION ALL",
+    CompoundOperator::Intersect => "INTERSECT",
+    CompoundOperator::Except => "EXCEPT",
+  }
+}
+
+/// Compiles a compound select, with its ORDER BY and LIMIT. The combined
+/// rows go through a subroutine, compiled once the columns the ORDER BY
+/// terms refer to are known.
+pub(super) fn compile_compound<'r>(
+  builder: &mut Builder<'r>,
+  select: &Select,
+  context: Context,
+  limits: Option<Limits>,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<QueryResult> {
+  let members: Vec<_> = members(select).collect();
+  let placeholder = vec![KeyOrder::default(); select.order_by.len()];
+  let output = Output::open(builder, None, placeholder, limits);
+  let ret = builder.register();
+  let subroutine = builder.label();
+  let mut row = None;
+  let (result, names) =
+    compile_members(builder, &members, context, &mut |builder, start, count| {
+      let row = *row.get_or_insert_with(|| builder.registers(count));
+      builder.copy(start, row, count);
+      builder.emit(Op::Gosub {
+        register: ret,
+        target: subroutine,
+      });
+      Ok(())
+    })?;
+
+  let width = result.columns.len();
+  let mut columns = vec![];
+  let mut key_orders = vec![];
+  for (index, term) in select.order_by.iter().enumerate() {
+    let (inner, collation) = match &term.expr {
+      Expr::Collate { expr, collation } => (&**expr, Some(collation)),
+      expr => (expr, None),
+    };
+    let column = match inner {
+      Expr::Literal(Literal::Integer(position)) => usize::try_from(*position)
+        .ok()
+        .and_then(|position| position.checked_sub(1))
+        .filter(|column| *column < width)
+        .ok_or_else(|| {
+          SqliteError::Custom(format!(
+            "{} ORDER BY term out of range - should be between 1 and {width}",
+            ordinal(index + 1)
+          ))
+        })?,
+      _ => members
+        .iter()
+        .zip(&names)
+        .find_map(|((_, member), names)| result_column(member, names, inner))
+        .ok_or_else(|| {
+          SqliteError::Custom(format!(
+            "{} ORDER BY term does not match any column in the result set",
+            ordinal(index + 1)
+          ))
+        })?,
+    };
+    let collation = match collation {
+      Some(collation) => collation,
+      None => result.collations[column].as_deref().unwrap_or("BINARY"),
+    };
+    columns.push(column);
+    key_orders.push(KeyOrder::of_term(
+      term,
+      builder.runtime.collation(collation)?,
+    ));
+  }
+  if let Some(explainer) = context.explainer.filter(|_| !key_orders.is_empty()) {
+    explainer.add("USE TEMP B-TREE FOR ORDER BY");
+  }
+  output.set_order(builder, key_orders);
+
+  let row = row.unwrap_or_else(|| builder.registers(width));
+  let after = builder.label();
+  builder.emit(Op::Goto { target: after });
+  builder.place(subroutine);
+  output.compile_row(
+    builder,
+    row,
+    width,
+    &mut |builder, key| {
+      for (index, column) in columns.iter().enumerate() {
+        builder.copy(row + column, key + index, 1);
+      }
+      Ok(())
+    },
+    emit,
+  )?;
+  builder.emit(Op::Return { register: ret });
+  builder.place(after);
+  output.finish(builder, width, emit)?;
+  Ok(result)
+}
+
+/// Compiles the selects of a compound and combines their rows, leaving out
+/// its ORDER BY and LIMIT, with `emit` compiled for each combined row. The
+/// result columns are named after those of the first select, and the names
+/// of the result columns of every select are returned along.
+///
+///  The rows of both sides of a UNION, INTERSECT or EXCEPT go to a sorter,
+/// each with the side it comes from, and the distinct rows it combines them
+/// into go on as the left side of the next operator, or to `emit` for the
+/// last one. The sorters compare rows with the collations of the columns,
+/// which are known once every select is compiled.
+pub(super) fn compile_members<'r>(
+  builder: &mut Builder<'r>,
+  members: &[(Option<CompoundOperator>, &Select)],
+  context: Context,
+  emit: &mut Sink<'_, 'r>,
+) -> SqliteResult<(QueryResult, Vec<Vec<String>>)> {
+  let explainer = context.explainer.filter(|_| members.len() > 1);
+  let _compound = explainer.map(|explainer| explainer.open("COMPOUND QUERY"));
+  // The sorter of each select after a UNION, INTERSECT or EXCEPT, with the
+  // address of the instruction that opens it.
+  let sorters: Vec<Option<(usize, usize)>> = members
+    .iter()
+    .map(|(operator, _)| match operator {
+      Some(CompoundOperator::UnionAll) | None => None,
+      Some(operator) => {
+        let cursor = builder.cursor();
+        let address = builder.address();
+        builder.emit(Op::SorterOpen {
+          cursor,
+          order: vec![],
+          limit: None,
+          combine: Some(*operator),
+        });
+        Some((cursor, address))
+      }
+    })
+    .collect();
+  // Where the rows up to a select go: the sorter of the next operator that
+  // combines them, or `emit`.
+  let next_sorter = |index: usize| {
+    sorters[index + 1..]
+      .iter()
+      .flatten()
+      .next()
+      .map(|(cursor, _)| *cursor)
+  };
+  let side = builder.register();
+  let mut send = |builder: &mut Builder<'r>, target: Option<(usize, i64)>, start, count| {
+    let Some((cursor, value)) = target else {
+      return emit(builder, start, count);
+    };
+    builder.emit(Op::Integer { value, dest: side });
+    builder.emit(Op::SorterInsert {
+      cursor,
+      key: start,
+      values: side,
+      count: 1,
+    });
+    Ok(())
+  };
+
+  let mut result = QueryResult::default();
+  let mut names = vec![];
+  for (index, (operator, member)) in members.iter().enumerate() {
+    let step = explainer.map(|explainer| {
+      explainer.open(match operator {
+        None => "LEFT-MOST SUBQUERY".to_string(),
+        Some(CompoundOperator::UnionAll) => "UNION ALL".to_string(),
+        Some(operator) => format!("{} USING TEMP B-TREE", operator_name(*operator)),
+      })
+    });
+    let target = match sorters[index] {
+      Some((cursor, _)) => Some((cursor, 1)),
+      None => next_sorter(index).map(|cursor| (cursor, 0)),
+    };
+    let right = compile_core(
+      builder,
+      member,
+      context,
+      &[],
+      None,
+      &mut |builder, start, count| send(builder, target, start, count),
+    )?;
+    drop(step);
+    names.push(right.columns.clone());
+    match operator {
+      None => result = right,
+      Some(operator) => {
+        if right.columns.len() != result.columns.len() {
+          return Err(SqliteError::Custom(format!(
+            "SELECTs to the left and right of {} do not have the same number of result columns",
+            operator_name(*operator)
+          )));
+        }
+        for (collation, other) in result.collations.iter_mut().zip(right.collations) {
+          if collation.is_none() {
+            *collation = other;
+          }
+        }
+      }
+    }
+    let Some((cursor, _)) = sorters[index] else {
+      continue;
+    };
+    let width = result.columns.len();
+    let row = builder.registers(width);
+    let (top, end) = (builder.label(), builder.label());
+    builder.emit(Op::SorterSort {
+      cursor,
+      target: end,
+    });
+    builder.place(top);
+    builder.emit(Op::SorterData {
+      cursor,
+      dest: row,
+      count: width,
+    });
+    send(
+      builder,
+      next_sorter(index).map(|cursor| (cursor, 0)),
+      row,
+      width,
+    )?;
+    builder.emit(Op::SorterNext {
+      cursor,
+      target: top,
+    });
+    builder.place(end);
+  }
+
+  let key_orders: Vec<KeyOrder> = result
+    .collations
+    .iter()
+    .map(|collation| {
+      builder
+        .runtime
+        .collation(collation.as_deref().unwrap_or("BINARY"))
+        .map(KeyOrder::ascending)
+    })
+    .collect::<SqliteResult<_>>()?;
+  for (_, address) in sorters.into_iter().flatten() {
+    if let Some(Op::SorterOpen { order, .. }) = builder.op_mut(address) {
+      order.clone_from(&key_orders);
+    }
+  }
+  Ok((result, names))
+}
+
+/// The distinct rows of both sides of a UNION, INTERSECT or EXCEPT, out of
+/// a sorter where each row has the side it comes from, the left one first.
+pub(crate) struct Distinct {
+  records: Peekable<Sorted>,
+  key_orders: Vec<KeyOrder>,
+  operator: CompoundOperator,
+}
+
+impl Distinct {
+  pub(crate) fn new(
+    records: Sorted,
+    key_orders: Vec<KeyOrder>,
+    operator: CompoundOperator,
+  ) -> Self {
+    Self {
+      records: records.peekable(),
+      key_orders,
+      operator,
+    }
+  }
+
+  /// The next row that is kept, as the last of the rows equal to it, or the
+  /// last of those from the left side.
+  fn next_row(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    while let Some(record) = self.records.next() {
+      let (mut last, side) = record?;
+      let mut has_right = is_right(&side);
+      let mut last_left = (!has_right).then(|| last.clone());
+      while let Some(Ok((next, _))) = self.records.peek() {
+        if compare_keys(next, &last, &self.key_orders).is_ne() {
+          break;
+        }
+        let Some(record) = self.records.next() else {
+          break;
+        };
+        let (row, side) = record?;
+        match is_right(&side) {
+          true => has_right = true,
+          false => last_left = Some(row.clone()),
+        }
+        last = row;
+      }
+      if let Some(Err(_)) = self.records.peek() {
+        if let Some(Err(err)) = self.records.next() {
+          return Err(err);
+        }
+      }
+      let kept = match self.operator {
+        CompoundOperator::Union | CompoundOperator::UnionAll => Some(last),
+        CompoundOperator::Intersect => last_left.filter(|_| has_right),
+        CompoundOperator::Except => last_left.filter(|_| !has_right),
+      };
+      if kept.is_some() {
+        return Ok(kept);
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Whether a row in the sorter comes from the right side.
+fn is_right(side: &[Value]) -> bool {
+  side.first() == Some(&Value::Integer(1))
+}
+
+impl Iterator for Distinct {
+  type Item = SqliteResult<Vec<Value>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_row().transpose()
+  }
+}
+
+/// The index of the result column of a select an ORDER BY term refers to,
+/// by name or by expression.
+fn result_column(select: &Select, names: &[String], term: &Expr) -> Option<usize> {
+  if let Expr::Column { name, .. } = term {
+    if let Some(index) = names
+      .iter()
+      .position(|column| column.eq_ignore_ascii_case(name))
+    {
+      return Some(index);
+    }
+  }
+  // Result columns are only matched by expression when none is a `*`, so
+  // that their index is that of the expression.
+  select
+    .columns
+    .iter()
+    .map(|column| match column {
+      ResultColumn::Expr { expr, .. } => Some(expr),
+      ResultColumn::Star | ResultColumn::TableStar(_) => None,
+    })
+    .collect::<Option<Vec<_>>>()?
+    .iter()
+    .position(|expr| *expr == term)
+}
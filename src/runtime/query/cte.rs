@@ -0,0 +1,615 @@
+//! # Common table expressions
+//!
+//!  The common table expressions of a WITH clause are visible to its query,
+//! to the queries nested in it and to one another. One that is neither
+//! recursive nor `MATERIALIZED` is flattened into the query reading it when
+//! it can be (see [`flatten`](super::flatten)). Any other is run when a
+//! FROM clause first reads it, and keeps its rows for the other references
+//! of the query, unless it is `NOT MATERIALIZED`, when it runs again for
+//! each of them.
+//!
+//!  A recursive common table expression is a compound select whose last
+//! selects read the table being defined. The rows of the first selects go to
+//! a queue. Then, until the queue is empty, a row is taken out of it and
+//! added to the result, and the recursive selects run with that row as the
+//! only one of the table, their rows going to the queue. With UNION, a row
+//! already queued once is left out. The queue is first in, first out, unless
+//! the compound has an ORDER BY, which takes out the lowest row first. Its
+//! LIMIT stops the recursion once the result has that many rows.
+//!
+//!  A row is only taken out of the queue when the query reading the table
+//! asks for the next one, so a LIMIT or a condition of that query can stop a
+//! recursion that would never end on its own. Each reference to the table
+//! runs the recursion anew.
+//!
+//!  In the program, a materialized common table expression is a subroutine
+//! that fills an ephemeral table, which each reference calls unless a flag
+//! notes that it has run, and then reads through a cursor of its own. A
+//! recursive one is a coroutine per reference, which adds a row to its
+//! ephemeral table each time the reference resumes it.
+//!
+//! *Reference:* https://www.sqlite.org/lang_with.html
+
+use core::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    sorter::{compare_keys, KeyOrder, KeySet},
+    vdbe::Op,
+  },
+  sql::ast::{
+    CommonTableExpr, CompoundOperator, Expr, QualifiedName, Select, TableOrSubquery, With,
+  },
+  value::Value,
+};
+
+use super::{
+  compile::{compile_expr, Builder},
+  compile_core, compile_limits,
+  compound::{compile_members, members},
+  materialize, order_by_terms, Context, QueryResult, Scope,
+};
+
+/// The common table expressions of a WITH clause.
+pub(super) struct CteFrame<'a> {
+  tables: Vec<CteTable<'a>>,
+  /// The context of the query the WITH clause belongs to.
+  context: Context<'a>,
+}
+
+struct CteTable<'a> {
+  cte: &'a CommonTableExpr,
+  is_recursive: bool,
+  /// The register that notes whether a materialized one has run.
+  flag: usize,
+  state: RefCell<State>,
+}
+
+enum State {
+  /// Not compiled, or to be compiled again for each reference.
+  Pending,
+  Running,
+  /// Compiled as the subroutine that fills the ephemeral table at
+  /// `cursor`, which returns to the address in `ret`.
+  Compiled {
+    cursor: usize,
+    subroutine: usize,
+    ret: usize,
+    result: QueryResult,
+  },
+  /// The ephemeral table holding the current row of a recursion, while its
+  /// recursive selects are compiled.
+  Current {
+    cursor: usize,
+    result: QueryResult,
+  },
+}
+
+/// The common table expressions a query sees: those of a frame, then those
+/// its parent sees.
+#[derive(Clone, Copy)]
+pub(super) struct Ctes<'a> {
+  frame: &'a CteFrame<'a>,
+}
+
+impl<'a> CteFrame<'a> {
+  /// Starts the common table expressions of a WITH clause, none of which
+  /// has run.
+  pub(super) fn new(
+    builder: &mut Builder,
+    with: &'a With,
+    context: Context<'a>,
+  ) -> SqliteResult<Self> {
+    let mut tables: Vec<CteTable> = vec![];
+    for cte in &with.ctes {
+      if tables
+        .iter()
+        .any(|table| table.cte.name.eq_ignore_ascii_case(&cte.name))
+      {
+        return Err(SqliteError::Custom(format!(
+          "duplicate WITH table name: {}",
+          cte.name
+        )));
+      }
+      let flag = builder.register();
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: flag,
+      });
+      tables.push(CteTable {
+        cte,
+        is_recursive: with.recursive
+          && members(&cte.select).any(|(_, select)| reads_table(select, &cte.name)),
+        flag,
+        state: RefCell::new(State::Pending),
+      });
+    }
+    Ok(Self { tables, context })
+  }
+
+  pub(super) fn ctes(&'a self) -> Ctes<'a> {
+    Ctes { frame: self }
+  }
+}
+
+impl<'a> Ctes<'a> {
+  /// Finds a common table expression by name, as the frame it belongs to
+  /// and its index there.
+  pub(super) fn find(self, name: &str) -> Option<(Ctes<'a>, usize)> {
+    match self
+      .frame
+      .tables
+      .iter()
+      .position(|table| table.cte.name.eq_ignore_ascii_case(name))
+    {
+      Some(index) => Some((self, index)),
+      None => self.frame.context.ctes?.find(name),
+    }
+  }
+
+  /// The common table expression at `index`, unless it is recursive or
+  /// MATERIALIZED, which the query reading it may then flatten.
+  pub(super) fn flattenable(self, index: usize) -> Option<&'a CommonTableExpr> {
+    let table = &self.frame.tables[index];
+    (!table.is_recursive && table.cte.materialized != Some(true)).then_some(table.cte)
+  }
+
+  /// Compiles a reference to a common table expression in a FROM clause,
+  /// and the common table expression itself unless it already is.
+  pub(super) fn compile(self, builder: &mut Builder, index: usize) -> SqliteResult<CteRows> {
+    let table = &self.frame.tables[index];
+    let name = &table.cte.name;
+    let state = table.state.replace(State::Running);
+    match state {
+      State::Pending => {}
+      State::Running => return Err(SqliteError::Custom(format!("circular reference: {name}"))),
+      State::Compiled {
+        cursor,
+        subroutine,
+        ret,
+        ref result,
+      } => {
+        let rows = reference(builder, table.flag, cursor, subroutine, ret, result.clone());
+        *table.state.borrow_mut() = state;
+        return Ok(rows);
+      }
+      State::Current { cursor, ref result } => {
+        let copy = builder.cursor();
+        builder.emit(Op::OpenDup {
+          cursor: copy,
+          source: cursor,
+        });
+        let rows = CteRows::Table {
+          cursor: copy,
+          result: result.clone(),
+        };
+        *table.state.borrow_mut() = state;
+        return Ok(rows);
+      }
+    }
+
+    let explainer = self.frame.context.explainer;
+    let _step = explainer.map(|explainer| explainer.open(format!("MATERIALIZE {name}")));
+    if table.is_recursive {
+      let rows = self.compile_recursion(builder, index);
+      // A recursion runs anew for every reference to it.
+      *table.state.borrow_mut() = State::Pending;
+      return rows;
+    }
+    let context = Context {
+      ctes: Some(self),
+      ..self.frame.context
+    };
+    if table.cte.materialized == Some(false) {
+      let (cursor, mut result) = materialize(builder, &table.cte.select, context)?;
+      rename_columns(table.cte, &mut result)?;
+      *table.state.borrow_mut() = State::Pending;
+      return Ok(CteRows::Table { cursor, result });
+    }
+    let ret = builder.register();
+    let subroutine = builder.label();
+    let after = builder.label();
+    builder.emit(Op::Goto { target: after });
+    builder.place(subroutine);
+    let (cursor, mut result) = materialize(builder, &table.cte.select, context)?;
+    rename_columns(table.cte, &mut result)?;
+    builder.emit(Op::Return { register: ret });
+    builder.place(after);
+    let rows = reference(builder, table.flag, cursor, subroutine, ret, result.clone());
+    *table.state.borrow_mut() = State::Compiled {
+      cursor,
+      subroutine,
+      ret,
+      result,
+    };
+    Ok(rows)
+  }
+
+  /// Compiles a recursive common table expression as a coroutine. Its first
+  /// selects fill an ephemeral table, whose rows go to the queue once the
+  /// columns, and so the ORDER BY terms, are known. Then each row taken out
+  /// of the queue is added to the table the reference reads, and the
+  /// coroutine yields before the recursive selects run with it.
+  fn compile_recursion(self, builder: &mut Builder, index: usize) -> SqliteResult<CteRows> {
+    let table = &self.frame.tables[index];
+    let name = &table.cte.name;
+    let select = &*table.cte.select;
+    let members: Vec<_> = members(select).collect();
+    let first = members
+      .iter()
+      .position(|(_, select)| reads_table(select, name))
+      .unwrap_or_default();
+    let (initial, recursive) = members.split_at(first);
+    let is_circular = initial.is_empty()
+      || recursive.iter().any(|(operator, _)| {
+        matches!(
+          operator,
+          Some(CompoundOperator::Intersect | CompoundOperator::Except)
+        )
+      });
+    if is_circular {
+      return Err(SqliteError::Custom(format!("circular reference: {name}")));
+    }
+    let is_distinct = recursive
+      .iter()
+      .any(|(operator, _)| *operator == Some(CompoundOperator::Union));
+
+    let cursor = builder.cursor();
+    let coroutine = builder.register();
+    let done = builder.register();
+    let after = builder.label();
+    let opened = builder.address();
+    builder.emit(Op::OpenEphemeral { cursor, columns: 0 });
+    builder.emit(Op::Integer {
+      value: 0,
+      dest: done,
+    });
+    builder.emit(Op::InitCoroutine {
+      register: coroutine,
+      target: after,
+    });
+
+    let frame = match &select.with {
+      Some(with) => Some(CteFrame::new(
+        builder,
+        with,
+        Context {
+          ctes: Some(self),
+          ..self.frame.context
+        },
+      )?),
+      None => None,
+    };
+    let context = Context {
+      ctes: Some(frame.as_ref().map_or(self, CteFrame::ctes)),
+      ..self.frame.context
+    };
+    let finished = builder.label();
+    let limits = match context.explainer {
+      Some(_) => None,
+      None => compile_limits(builder, select.limit.as_ref(), finished)?,
+    };
+    let first_rows = builder.cursor();
+    builder.emit(Op::OpenEphemeral {
+      cursor: first_rows,
+      columns: 0,
+    });
+    let step = context.explainer.map(|explainer| explainer.open("SETUP"));
+    let (mut template, _) =
+      compile_members(builder, initial, context, &mut |builder, start, count| {
+        builder.emit(Op::Insert {
+          cursor: first_rows,
+          start,
+          count,
+        });
+        Ok(())
+      })?;
+    drop(step);
+    rename_columns(table.cte, &mut template)?;
+    let width = template.columns.len();
+    if let Some(Op::OpenEphemeral { columns, .. }) = builder.op_mut(opened) {
+      *columns = width;
+    }
+
+    // The ORDER BY terms refer to the columns of the table.
+    let runtime = builder.runtime;
+    let mut scope = Scope::new(runtime, context);
+    scope.push(
+      name,
+      template.columns.clone(),
+      template.affinities.clone(),
+      template.collations.clone(),
+      template.declared_types.clone(),
+      false,
+    );
+    let columns: Vec<Expr> = template
+      .columns
+      .iter()
+      .map(|column| Expr::Column {
+        schema: None,
+        table: None,
+        name: column.clone(),
+      })
+      .collect();
+    let terms = order_by_terms(&scope, &select.order_by, &columns)?;
+    let order = terms
+      .iter()
+      .map(|term| scope.term_order(term))
+      .collect::<SqliteResult<_>>()?;
+    // UNION compares rows with the collations of their columns.
+    let distinct = match is_distinct {
+      true => Some(
+        template
+          .collations
+          .iter()
+          .map(|collation| {
+            runtime
+              .collation(collation.as_deref().unwrap_or("BINARY"))
+              .map(KeyOrder::ascending)
+          })
+          .collect::<SqliteResult<_>>()?,
+      ),
+      false => None,
+    };
+    let queue = builder.cursor();
+    builder.emit(Op::QueueOpen {
+      cursor: queue,
+      order,
+      distinct,
+    });
+    let order_keys: Vec<Expr> = terms.into_iter().map(|term| term.expr).collect();
+    let push = |builder: &mut Builder, start: usize| -> SqliteResult<()> {
+      scope.read_registers(start);
+      let key = builder.registers(order_keys.len());
+      for (index, expr) in order_keys.iter().enumerate() {
+        compile_expr(builder, &scope, expr, false, key + index)?;
+      }
+      builder.emit(Op::QueuePush {
+        cursor: queue,
+        key,
+        values: start,
+        count: width,
+      });
+      Ok(())
+    };
+
+    let row = builder.registers(width);
+    let (top, end) = (builder.label(), builder.label());
+    builder.emit(Op::Rewind {
+      cursor: first_rows,
+      target: end,
+    });
+    builder.place(top);
+    for column in 0..width {
+      builder.emit(Op::Column {
+        cursor: first_rows,
+        column,
+        dest: row + column,
+      });
+    }
+    push(builder, row)?;
+    builder.emit(Op::Next {
+      cursor: first_rows,
+      target: top,
+    });
+    builder.place(end);
+
+    let current = builder.registers(width);
+    let (next, recurse) = (builder.label(), builder.label());
+    builder.place(next);
+    builder.emit(Op::QueuePop {
+      cursor: queue,
+      dest: current,
+      count: width,
+      target: finished,
+    });
+    if let Some(offset) = limits.and_then(|limits| limits.offset) {
+      builder.emit(Op::IfPos {
+        register: offset,
+        target: recurse,
+        decrement: 1,
+      });
+    }
+    builder.emit(Op::Insert {
+      cursor,
+      start: current,
+      count: width,
+    });
+    builder.emit(Op::Yield {
+      register: coroutine,
+    });
+    if let Some(limits) = limits {
+      builder.emit(Op::DecrJumpZero {
+        register: limits.limit,
+        target: finished,
+      });
+    }
+    builder.place(recurse);
+    let current_row = builder.cursor();
+    builder.emit(Op::OpenEphemeral {
+      cursor: current_row,
+      columns: width,
+    });
+    builder.emit(Op::Insert {
+      cursor: current_row,
+      start: current,
+      count: width,
+    });
+    *table.state.borrow_mut() = State::Current {
+      cursor: current_row,
+      result: template.clone(),
+    };
+    let step = context
+      .explainer
+      .map(|explainer| explainer.open("RECURSIVE STEP"));
+    for (_, member) in recursive {
+      let result = compile_core(
+        builder,
+        member,
+        context,
+        &[],
+        None,
+        &mut |builder, start, _| push(builder, start),
+      )?;
+      if result.columns.len() != width {
+        return Err(SqliteError::Custom(
+          "SELECTs to the left and right of UNION do not have the same number of result columns"
+            .into(),
+        ));
+      }
+    }
+    drop(step);
+    builder.emit(Op::Goto { target: next });
+
+    // The reference asks no more once `done` is set.
+    builder.place(finished);
+    builder.emit(Op::Integer {
+      value: 1,
+      dest: done,
+    });
+    let park = builder.label();
+    builder.place(park);
+    builder.emit(Op::Yield {
+      register: coroutine,
+    });
+    builder.emit(Op::Goto { target: park });
+    builder.place(after);
+    Ok(CteRows::Recursion {
+      cursor,
+      coroutine,
+      done,
+      result: template,
+    })
+  }
+}
+
+/// Compiles a reference to a materialized common table expression, which
+/// calls its subroutine unless it has run.
+fn reference(
+  builder: &mut Builder,
+  flag: usize,
+  source: usize,
+  subroutine: usize,
+  ret: usize,
+  result: QueryResult,
+) -> CteRows {
+  let ready = builder.label();
+  builder.emit(Op::If {
+    register: flag,
+    target: ready,
+    if_null: false,
+  });
+  builder.emit(Op::Integer {
+    value: 1,
+    dest: flag,
+  });
+  builder.emit(Op::Gosub {
+    register: ret,
+    target: subroutine,
+  });
+  builder.place(ready);
+  let cursor = builder.cursor();
+  builder.emit(Op::OpenDup { cursor, source });
+  CteRows::Table { cursor, result }
+}
+
+/// Whether the FROM clause of a select reads a table by this name.
+fn reads_table(select: &Select, name: &str) -> bool {
+  let Some(from) = &select.from else {
+    return false;
+  };
+  core::iter::once(&from.first)
+    .chain(from.joins.iter().map(|join| &join.table))
+    .any(|table| {
+      matches!(
+        table,
+        TableOrSubquery::Table {
+          name: QualifiedName { schema: None, name: table },
+          ..
+        } if table.eq_ignore_ascii_case(name)
+      )
+    })
+}
+
+/// The rows of a common table expression, as a FROM clause reads them.
+pub(super) enum CteRows {
+  /// An ephemeral table with every row.
+  Table { cursor: usize, result: QueryResult },
+  /// The ephemeral table of a recursive one, whose coroutine adds its rows
+  /// as they are read, and sets `done` once it has no more.
+  Recursion {
+    cursor: usize,
+    coroutine: usize,
+    done: usize,
+    result: QueryResult,
+  },
+}
+
+/// Names the columns of the result of a common table expression after
+/// those it declares, if it does.
+fn rename_columns(cte: &CommonTableExpr, result: &mut QueryResult) -> SqliteResult<()> {
+  let columns = &cte.columns;
+  if columns.is_empty() {
+    return Ok(());
+  }
+  if columns.len() != result.columns.len() {
+    return Err(SqliteError::Custom(format!(
+      "table {} has {} values for {} columns",
+      cte.name,
+      result.columns.len(),
+      columns.len()
+    )));
+  }
+  result.columns.clone_from(columns);
+  Ok(())
+}
+
+/// The rows of a recursion waiting to be added to its table.
+pub(crate) struct Queue {
+  /// The rows with the values of their ORDER BY terms, lowest first.
+  rows: VecDeque<(Vec<Value>, Vec<Value>)>,
+  /// Every row queued so far, for UNION.
+  seen: Option<KeySet>,
+  key_orders: Vec<KeyOrder>,
+}
+
+impl Queue {
+  /// An empty queue, whose rows come out in `order` of their keys, and
+  /// first in, first out without keys. `distinct` compares the rows of a
+  /// UNION, which leaves out a row already queued once.
+  pub(crate) fn new(order: Vec<KeyOrder>, distinct: Option<Vec<KeyOrder>>, budget: usize) -> Self {
+    Self {
+      rows: VecDeque::new(),
+      seen: distinct.map(|key_orders| KeySet::new(key_orders, budget)),
+      key_orders: order,
+    }
+  }
+
+  /// The number of values of ORDER BY terms a row comes with.
+  pub(crate) fn key_count(&self) -> usize {
+    self.key_orders.len()
+  }
+
+  pub(crate) fn push(&mut self, keys: Vec<Value>, row: Vec<Value>) -> SqliteResult<()> {
+    if let Some(seen) = &mut self.seen {
+      if !seen.insert(row.clone())? {
+        return Ok(());
+      }
+    }
+    // Rows with equal keys come out in the order they came in.
+    let position = match self.key_orders.is_empty() {
+      true => self.rows.len(),
+      false => self
+        .rows
+        .partition_point(|(other, ..)| compare_keys(other, &keys, &self.key_orders).is_le()),
+    };
+    self.rows.insert(position, (keys, row));
+    Ok(())
+  }
+
+  pub(crate) fn pop(&mut self) -> Option<Vec<Value>> {
+    self.rows.pop_front().map(|(.., row)| row)
+  }
+}
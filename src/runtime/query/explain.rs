@@ -0,0 +1,267 @@
+//! # Query plans
+//!
+//!  `EXPLAIN QUERY PLAN` plans a query without running it, and tells how
+//! each of its tables would be read, in SQLite's words. A subquery in FROM
+//! and a common table expression are planned as they would run, before the
+//! query that reads them, and return no rows. The steps of the plan form a
+//! tree: those of a subquery or of the selects of a compound hang from the
+//! step that runs them.
+//!
+//! *Reference:* https://www.sqlite.org/eqp.html
+
+use core::{
+  cell::{Cell, RefCell},
+  fmt::{self, Display},
+};
+
+use crate::{
+  result::SqliteResult,
+  sql::ast::{
+    Expr, JoinClause, JoinConstraint, OrderingTerm, ResultColumn, Select, TableOrSubquery,
+  },
+  value::{Affinity, Value},
+};
+
+use super::{compile::Builder, compile_query, Context, QueryResult, Scope, SqliteRuntime};
+
+/// A step of a query plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+  id: usize,
+  parent: usize,
+  detail: String,
+}
+
+impl PlanStep {
+  pub fn id(&self) -> usize {
+    self.id
+  }
+
+  /// The step this one belongs to, or 0 at the top of the plan.
+  pub fn parent(&self) -> usize {
+    self.parent
+  }
+
+  pub fn detail(&self) -> &str {
+    &self.detail
+  }
+}
+
+/// The steps of a query plan, each after its parent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryPlan {
+  steps: Vec<PlanStep>,
+}
+
+impl QueryPlan {
+  pub fn steps(&self) -> &[PlanStep] {
+    &self.steps
+  }
+
+  /// The plan as the rows of `EXPLAIN QUERY PLAN`: the id, the parent, an
+  /// unused column and the detail of each step.
+  pub(crate) fn into_result(self) -> QueryResult {
+    let integer = |value: usize| Value::Integer(i64::try_from(value).unwrap_or(i64::MAX));
+    QueryResult {
+      columns: ["id", "parent", "notused", "detail"]
+        .map(String::from)
+        .to_vec(),
+      rows: self
+        .steps
+        .into_iter()
+        .map(|step| {
+          vec![
+            integer(step.id),
+            integer(step.parent),
+            Value::Integer(0),
+            Value::Text(step.detail),
+          ]
+        })
+        .collect(),
+      affinities: vec![Affinity::Blob; 4],
+      collations: vec![None; 4],
+      declared_types: vec![None; 4],
+    }
+  }
+
+  fn fmt_children(&self, f: &mut fmt::Formatter<'_>, parent: usize, prefix: &str) -> fmt::Result {
+    let children: Vec<&PlanStep> = self
+      .steps
+      .iter()
+      .filter(|step| step.parent == parent)
+      .collect();
+    for (index, step) in children.iter().enumerate() {
+      let is_last = index + 1 == children.len();
+      let (branch, indent) = if is_last {
+        ("`--", "   ")
+      } else {
+        ("|--", "|  ")
+      };
+      write!(f, "\n{prefix}{branch}{}", step.detail)?;
+      self.fmt_children(f, step.id, &format!("{prefix}{indent}"))?;
+    }
+    Ok(())
+  }
+}
+
+/// The plan as a tree, as the sqlite3 shell prints it.
+impl Display for QueryPlan {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "QUERY PLAN")?;
+    self.fmt_children(f, 0, "")
+  }
+}
+
+/// Collects the steps of a plan as the query is planned.
+#[derive(Debug, Default)]
+pub(super) struct Explainer {
+  steps: RefCell<Vec<PlanStep>>,
+  /// The step new steps belong to.
+  parent: Cell<usize>,
+  /// The number of subqueries met so far.
+  subqueries: Cell<usize>,
+}
+
+/// A step the steps added while it is alive belong to.
+pub(super) struct OpenStep<'a> {
+  explainer: &'a Explainer,
+  id: usize,
+  parent: usize,
+}
+
+impl Explainer {
+  /// Adds a step under the current one, returning its id.
+  pub(super) fn add(&self, detail: impl Into<String>) -> usize {
+    let mut steps = self.steps.borrow_mut();
+    let id = steps.len() + 1;
+    steps.push(PlanStep {
+      id,
+      parent: self.parent.get(),
+      detail: detail.into(),
+    });
+    id
+  }
+
+  /// Adds a step under the current one, and makes it the current one until
+  /// the returned guard is dropped.
+  pub(super) fn open(&self, detail: impl Into<String>) -> OpenStep<'_> {
+    let id = self.add(detail);
+    OpenStep {
+      explainer: self,
+      id,
+      parent: self.parent.replace(id),
+    }
+  }
+
+  /// The number of the next subquery, counting from 1.
+  pub(super) fn next_subquery(&self) -> usize {
+    let number = self.subqueries.get() + 1;
+    self.subqueries.set(number);
+    number
+  }
+
+  pub(super) fn into_plan(self) -> QueryPlan {
+    QueryPlan {
+      steps: self.steps.into_inner(),
+    }
+  }
+}
+
+impl OpenStep<'_> {
+  /// Replaces the detail of the step, once what it runs is known.
+  pub(super) fn set_detail(&self, detail: impl Into<String>) {
+    if let Some(step) = self.explainer.steps.borrow_mut().get_mut(self.id - 1) {
+      step.detail = detail.into();
+    }
+  }
+}
+
+impl Drop for OpenStep<'_> {
+  fn drop(&mut self) {
+    self.explainer.parent.set(self.parent);
+  }
+}
+
+impl SqliteRuntime {
+  /// Plans a query without running it.
+  pub(crate) fn explain_select(&self, select: &Select) -> SqliteResult<QueryPlan> {
+    let explainer = Explainer::default();
+    let context = Context {
+      explainer: Some(&explainer),
+      ..Context::default()
+    };
+    compile_query(&mut Builder::new(self), select, context, &mut |_, _, _| {
+      Ok(())
+    })?;
+    Ok(explainer.into_plan())
+  }
+}
+
+/// Adds the steps of the subqueries in the expressions of a select, which
+/// run with a row of `scope` as their outer row. Those that read a column of
+/// it are correlated, and run again for every row.
+pub(super) fn explain_subqueries(
+  builder: &mut Builder,
+  explainer: &Explainer,
+  scope: &Scope,
+  select: &Select,
+  order_by: &[OrderingTerm],
+) -> SqliteResult<()> {
+  let mut exprs = from_exprs(select.from.as_ref());
+  exprs.extend(&select.where_clause);
+  exprs.extend(select.columns.iter().filter_map(|column| match column {
+    ResultColumn::Expr { expr, .. } => Some(expr),
+    ResultColumn::Star | ResultColumn::TableStar(_) => None,
+  }));
+  exprs.extend(select.values.iter().flatten());
+  exprs.extend(&select.group_by);
+  exprs.extend(&select.having);
+  exprs.extend(order_by.iter().map(|term| &term.expr));
+  let mut subqueries = vec![];
+  for expr in exprs {
+    expr.walk(&mut |expr| match expr {
+      Expr::Subquery(select) | Expr::Exists { select, .. } => {
+        subqueries.push(("SCALAR SUBQUERY", &**select))
+      }
+      Expr::InSelect { select, .. } => subqueries.push(("LIST SUBQUERY", &**select)),
+      _ => {}
+    });
+  }
+
+  for (kind, subquery) in subqueries {
+    let number = explainer.next_subquery();
+    let step = explainer.open(format!("{kind} {number}"));
+    let context = Context {
+      outer: Some(scope),
+      ..scope.context
+    };
+    let was_referenced = scope.is_referenced.replace(false);
+    compile_query(builder, subquery, context, &mut |_, _, _| Ok(()))?;
+    let is_correlated = scope.is_referenced.get();
+    scope.is_referenced.set(was_referenced || is_correlated);
+    if is_correlated {
+      step.set_detail(format!("CORRELATED {kind} {number}"));
+    }
+  }
+  Ok(())
+}
+
+/// The ON constraints and the table-valued function arguments of a FROM
+/// clause.
+fn from_exprs(from: Option<&JoinClause>) -> Vec<&Expr> {
+  let Some(from) = from else {
+    return vec![];
+  };
+  let mut exprs = vec![];
+  for table in core::iter::once(&from.first).chain(from.joins.iter().map(|join| &join.table)) {
+    if let TableOrSubquery::TableFunction { args, .. } = table {
+      exprs.extend(args);
+    }
+  }
+  for join in &from.joins {
+    if let Some(JoinConstraint::On(on)) = &join.constraint {
+      exprs.push(on);
+    }
+  }
+  exprs
+}
@@ -0,0 +1,577 @@
+//! # Flattening
+//!
+//!  A subquery in FROM, or a common table expression, that only picks rows
+//! of one table and computes columns from them is flattened into the query
+//! that reads it, instead of filling an ephemeral table that the query then
+//! scans. The table takes its place in the FROM clause, its WHERE clause is
+//! added to that of the query, and each of its columns is replaced by the
+//! expression it stands for. The planner then sees the conditions of the
+//! query on the table itself, and may search the table with them.
+//!
+//!  A common table expression is flattened unless it is recursive or
+//! `MATERIALIZED`. A subquery is only flattened when that keeps the meaning
+//! of the query: it has no DISTINCT, GROUP BY, aggregate or window
+//! function, ORDER BY, LIMIT, compound select or subquery of its own, and
+//! on the right of a LEFT JOIN only plain columns and no WHERE clause; the
+//! query has no RIGHT or FULL JOIN, USING, NATURAL, window function or
+//! subquery in its expressions; and every name of the query still refers to
+//! the same column once the subquery is gone. Any other is materialized.
+//!
+//! *Reference:* https://www.sqlite.org/optoverview.html#flattening
+
+use core::iter::once;
+
+use crate::{
+  runtime::{schema::TableSchema, SqliteRuntime},
+  sql::ast::{
+    BinaryOperator, Expr, JoinConstraint, JoinKind, OrderingTerm, QualifiedName, ResultColumn,
+    Select, TableOrSubquery,
+  },
+};
+
+use super::{aggregate_calls, has_window_call, Context, ROWID_NAMES};
+
+/// Flattens every subquery of the FROM clause of a select that can be, and
+/// returns the select with the ORDER BY that applies to it, or `None` when
+/// none can.
+pub(super) fn flatten(
+  runtime: &SqliteRuntime,
+  select: &Select,
+  order_by: &[OrderingTerm],
+  context: Context,
+) -> Option<(Select, Vec<OrderingTerm>)> {
+  let mut flattened: Option<(Select, Vec<OrderingTerm>)> = None;
+  loop {
+    let (select, order_by) = match &flattened {
+      Some((select, order_by)) => (select, &order_by[..]),
+      None => (select, order_by),
+    };
+    match flatten_source(runtime, select, order_by, context) {
+      Some(next) => flattened = Some(next),
+      None => return flattened,
+    }
+  }
+}
+
+/// Flattens the first subquery of the FROM clause that can be.
+fn flatten_source(
+  runtime: &SqliteRuntime,
+  select: &Select,
+  order_by: &[OrderingTerm],
+  context: Context,
+) -> Option<(Select, Vec<OrderingTerm>)> {
+  let from = select.from.as_ref()?;
+  let is_plain_join = from.joins.iter().all(|join| {
+    !join.operator.natural
+      && !matches!(join.operator.kind, JoinKind::Right | JoinKind::Full)
+      && !matches!(join.constraint, Some(JoinConstraint::Using(_)))
+  });
+  let sources: Vec<&TableOrSubquery> = once(&from.first)
+    .chain(from.joins.iter().map(|join| &join.table))
+    .collect();
+  if !select.values.is_empty()
+    || !is_plain_join
+    || sources
+      .iter()
+      .any(|source| matches!(source, TableOrSubquery::Join(_)))
+    || outer_exprs(select, order_by).any(|expr| has_subquery(expr) || has_window_call(expr))
+  {
+    return None;
+  }
+
+  (0..sources.len()).find_map(|index| {
+    let others: Vec<Option<&str>> = sources
+      .iter()
+      .enumerate()
+      .filter(|(other, _)| *other != index)
+      .map(|(_, source)| source_name(source))
+      .collect();
+    let view = View::new(runtime, sources[index], context, &others)?;
+    // On the right of a LEFT JOIN, the columns of the subquery must be null
+    // when no row matches, as those of its table are, and its conditions
+    // would belong to the ON clause rather than to the WHERE clause.
+    let is_left = index > 0 && from.joins[index - 1].operator.kind == JoinKind::Left;
+    let is_null_row = view.where_clause.is_none()
+      && view
+        .columns
+        .iter()
+        .all(|(_, expr)| matches!(expr, Expr::Column { .. }));
+    if is_left && !is_null_row {
+      return None;
+    }
+    view.inline(runtime, context, select, order_by, index)
+  })
+}
+
+/// A subquery of the FROM clause, as the query reads it once flattened.
+struct View<'a> {
+  /// The name the query reads the subquery by, if it has one.
+  name: Option<String>,
+  /// The table the subquery reads.
+  table: &'a TableSchema,
+  /// The name of that table in the flattened query.
+  table_name: String,
+  /// The table as a source of the flattened query.
+  source: TableOrSubquery,
+  /// The name of each column of the subquery, and the expression it stands
+  /// for in the flattened query.
+  columns: Vec<(String, Expr)>,
+  where_clause: Option<Expr>,
+}
+
+impl<'a> View<'a> {
+  /// Reads a subquery, or a reference to a common table expression, of the
+  /// FROM clause whose other sources have the names in `others`, unless it
+  /// cannot be flattened.
+  fn new(
+    runtime: &'a SqliteRuntime,
+    source: &TableOrSubquery,
+    context: Context,
+    others: &[Option<&str>],
+  ) -> Option<Self> {
+    let (select, name, declared) = match source {
+      TableOrSubquery::Subquery { select, alias } => (&**select, alias.clone(), &[][..]),
+      TableOrSubquery::Table {
+        name: QualifiedName { schema: None, name },
+        alias,
+        indexed: None,
+      } => {
+        let (ctes, index) = context.ctes?.find(name)?;
+        let cte = ctes.flattenable(index)?;
+        let name = alias.as_deref().unwrap_or(name);
+        (&*cte.select, Some(name.to_string()), &cte.columns[..])
+      }
+      _ => return None,
+    };
+
+    let Some(from) = &select.from else {
+      return None;
+    };
+    let TableOrSubquery::Table {
+      name: QualifiedName {
+        schema: None,
+        name: inner,
+      },
+      alias: inner_alias,
+      indexed,
+    } = &from.first
+    else {
+      return None;
+    };
+    let is_simple = select.with.is_none()
+      && !select.distinct
+      && select.group_by.is_empty()
+      && select.having.is_none()
+      && select.order_by.is_empty()
+      && select.limit.is_none()
+      && select.values.is_empty()
+      && select.windows.is_empty()
+      && select.compound.is_empty()
+      && from.joins.is_empty();
+    let is_cte = context.ctes.is_some_and(|ctes| ctes.find(inner).is_some());
+    if !is_simple || is_cte {
+      return None;
+    }
+    let table = runtime.schema().table(inner)?;
+
+    // The table keeps the name the subquery reads it by, unless another
+    // source of the query has that name.
+    let inner_name = inner_alias.as_deref().unwrap_or(inner);
+    let is_taken = |candidate: &str| {
+      others
+        .iter()
+        .flatten()
+        .any(|other| other.eq_ignore_ascii_case(candidate))
+    };
+    let table_name = match name.as_deref() {
+      _ if !is_taken(inner_name) => inner_name.to_string(),
+      Some(name) if !is_taken(name) => name.to_string(),
+      _ => return None,
+    };
+
+    let qualify = |expr: &Expr| -> Option<Expr> {
+      if has_subquery(expr)
+        || has_window_call(expr)
+        || !aggregate_calls(expr).is_ok_and(|calls| calls.is_empty())
+      {
+        return None;
+      }
+      let mut expr = expr.clone();
+      let mut is_resolved = true;
+      expr.walk_mut(&mut |expr| {
+        if let Expr::Column {
+          schema,
+          table: column_table,
+          name,
+        } = expr
+        {
+          is_resolved &= match (&schema, &column_table) {
+            (None, Some(column_table)) => column_table.eq_ignore_ascii_case(inner_name),
+            (None, None) => has_column(table, name),
+            _ => false,
+          };
+          *column_table = Some(table_name.clone());
+        }
+      });
+      is_resolved.then_some(expr)
+    };
+    let column = |name: &str| Expr::Column {
+      schema: None,
+      table: Some(table_name.clone()),
+      name: name.to_string(),
+    };
+
+    let mut columns = vec![];
+    for result in &select.columns {
+      match result {
+        ResultColumn::Star => columns.extend(
+          table
+            .columns()
+            .iter()
+            .map(|def| (def.name().to_string(), column(def.name()))),
+        ),
+        ResultColumn::TableStar(star) if star.eq_ignore_ascii_case(inner_name) => columns.extend(
+          table
+            .columns()
+            .iter()
+            .map(|def| (def.name().to_string(), column(def.name()))),
+        ),
+        ResultColumn::TableStar(_) => return None,
+        ResultColumn::Expr { expr, alias, text } => {
+          let name = match (alias, expr) {
+            (Some(alias), _) => alias.clone(),
+            (None, Expr::Column { name, .. }) => table
+              .column(name)
+              .map_or_else(|| name.clone(), |(_, def)| def.name().to_string()),
+            (None, _) => text.clone(),
+          };
+          columns.push((name, qualify(expr)?));
+        }
+      }
+    }
+    if !declared.is_empty() {
+      if declared.len() != columns.len() {
+        return None;
+      }
+      for ((name, _), declared) in columns.iter_mut().zip(declared) {
+        name.clone_from(declared);
+      }
+    }
+    let is_duplicate = columns.iter().enumerate().any(|(index, (name, _))| {
+      columns[..index]
+        .iter()
+        .any(|(other, _)| other.eq_ignore_ascii_case(name))
+    });
+    if is_duplicate {
+      return None;
+    }
+    let where_clause = match &select.where_clause {
+      Some(where_clause) => Some(qualify(where_clause)?),
+      None => None,
+    };
+
+    let source = TableOrSubquery::Table {
+      name: QualifiedName {
+        schema: None,
+        name: inner.clone(),
+      },
+      alias: (table_name != *inner).then(|| table_name.clone()),
+      indexed: indexed.clone(),
+    };
+    Some(Self {
+      name,
+      table,
+      table_name,
+      source,
+      columns,
+      where_clause,
+    })
+  }
+
+  /// The column of the subquery called `name`.
+  fn column(&self, name: &str) -> Option<&(String, Expr)> {
+    self
+      .columns
+      .iter()
+      .find(|(column, _)| column.eq_ignore_ascii_case(name))
+  }
+
+  /// Whether `table` is the name the query reads the subquery by.
+  fn is_named(&self, table: &str) -> bool {
+    self
+      .name
+      .as_deref()
+      .is_some_and(|name| name.eq_ignore_ascii_case(table))
+  }
+
+  /// The select with the subquery at `index` of its FROM clause flattened,
+  /// unless a name of the select would then refer to another column.
+  fn inline(
+    &self,
+    runtime: &SqliteRuntime,
+    context: Context,
+    select: &Select,
+    order_by: &[OrderingTerm],
+    index: usize,
+  ) -> Option<(Select, Vec<OrderingTerm>)> {
+    let mut select = select.clone();
+    let mut order_by = order_by.to_vec();
+    let from = select.from.as_mut()?;
+    let sources: Vec<&TableOrSubquery> = once(&from.first)
+      .chain(from.joins.iter().map(|join| &join.table))
+      .collect();
+    // The other sources, with their tables when they are tables, whose
+    // columns an unqualified name might otherwise refer to.
+    let others: Vec<Option<&TableSchema>> = sources
+      .iter()
+      .enumerate()
+      .filter(|(other, _)| *other != index)
+      .map(|(_, source)| plain_table(runtime, context, source))
+      .collect();
+
+    let is_aliased = select.columns.iter().any(|result| {
+      matches!(result, ResultColumn::Expr { alias: Some(alias), .. } if self.column(alias).is_some())
+    });
+    if is_aliased {
+      return None;
+    }
+
+    let mut is_resolved = true;
+    let mut substitute = |expr: &mut Expr| {
+      let Expr::Column {
+        schema: None,
+        table,
+        name,
+      } = expr
+      else {
+        return;
+      };
+      let column = self.column(name).map(|(_, expr)| expr);
+      let replacement = match table.as_deref() {
+        Some(table) if self.is_named(table) => column,
+        Some(table) => {
+          is_resolved &= !table.eq_ignore_ascii_case(&self.table_name);
+          return;
+        }
+        None => {
+          let is_other = others
+            .iter()
+            .any(|table| table.map_or(true, |table| has_column(table, name)));
+          match column {
+            Some(_) if is_other => None,
+            None => {
+              is_resolved &= !has_column(self.table, name);
+              return;
+            }
+            column => column,
+          }
+        }
+      };
+      match replacement {
+        Some(replacement) => *expr = replacement.clone(),
+        None => is_resolved = false,
+      }
+    };
+
+    let mut columns = vec![];
+    for result in &select.columns {
+      match result {
+        ResultColumn::Star => {
+          for (position, source) in sources.iter().enumerate() {
+            if position == index {
+              columns.extend(self.star());
+              continue;
+            }
+            let is_table = match source {
+              TableOrSubquery::Table { name, .. } => {
+                name.schema.is_some()
+                  || context
+                    .ctes
+                    .is_some_and(|ctes| ctes.find(&name.name).is_some())
+                  || runtime.schema().table(&name.name).is_some()
+              }
+              TableOrSubquery::Subquery { .. } => true,
+              _ => false,
+            };
+            match source_name(source) {
+              Some(name) if is_table => columns.push(ResultColumn::TableStar(name.to_string())),
+              _ => return None,
+            }
+          }
+        }
+        ResultColumn::TableStar(star) if self.is_named(star) => columns.extend(self.star()),
+        ResultColumn::TableStar(star) if star.eq_ignore_ascii_case(&self.table_name) => {
+          return None
+        }
+        ResultColumn::Expr { expr, alias, text } => {
+          // A column of the subquery keeps its name as a result column.
+          let alias = match (alias, expr) {
+            (
+              None,
+              Expr::Column {
+                schema: None,
+                table,
+                name,
+              },
+            ) if table.as_deref().map_or(true, |table| self.is_named(table)) => {
+              self.column(name).map(|(name, _)| name.clone())
+            }
+            (alias, _) => alias.clone(),
+          };
+          let mut expr = expr.clone();
+          expr.walk_mut(&mut substitute);
+          columns.push(ResultColumn::Expr {
+            expr,
+            alias,
+            text: text.clone(),
+          });
+        }
+        result => columns.push(result.clone()),
+      }
+    }
+    select.columns = columns;
+
+    for join in &mut from.joins {
+      if let Some(JoinConstraint::On(expr)) = &mut join.constraint {
+        expr.walk_mut(&mut substitute);
+      }
+    }
+    for source in once(&mut from.first).chain(from.joins.iter_mut().map(|join| &mut join.table)) {
+      if let TableOrSubquery::TableFunction { args, .. } = source {
+        args
+          .iter_mut()
+          .for_each(|arg| arg.walk_mut(&mut substitute));
+      }
+    }
+    let clauses = select
+      .where_clause
+      .iter_mut()
+      .chain(&mut select.group_by)
+      .chain(&mut select.having)
+      .chain(order_by.iter_mut().map(|term| &mut term.expr));
+    for expr in clauses {
+      expr.walk_mut(&mut substitute);
+    }
+    for (_, window) in &mut select.windows {
+      window.walk_mut(&mut substitute);
+    }
+    if !is_resolved {
+      return None;
+    }
+
+    let from = select.from.as_mut()?;
+    match index {
+      0 => from.first = self.source.clone(),
+      _ => from.joins[index - 1].table = self.source.clone(),
+    }
+    select.where_clause = match (self.where_clause.clone(), select.where_clause) {
+      (Some(inner), Some(outer)) => Some(Expr::Binary {
+        left: Box::new(inner),
+        operator: BinaryOperator::And,
+        right: Box::new(outer),
+      }),
+      (inner, outer) => inner.or(outer),
+    };
+    Some((select, order_by))
+  }
+
+  /// The result columns `*` stands for on the subquery.
+  fn star(&self) -> impl Iterator<Item = ResultColumn> + '_ {
+    self.columns.iter().map(|(name, expr)| ResultColumn::Expr {
+      expr: expr.clone(),
+      alias: Some(name.clone()),
+      text: name.clone(),
+    })
+  }
+}
+
+/// The expressions of a select outside its FROM clause and its subqueries,
+/// with those of the ORDER BY that applies to it.
+fn outer_exprs<'a>(
+  select: &'a Select,
+  order_by: &'a [OrderingTerm],
+) -> impl Iterator<Item = &'a Expr> {
+  let from = select.from.iter().flat_map(|from| {
+    let constraints = from.joins.iter().filter_map(|join| match &join.constraint {
+      Some(JoinConstraint::On(expr)) => Some(expr),
+      _ => None,
+    });
+    let args = once(&from.first)
+      .chain(from.joins.iter().map(|join| &join.table))
+      .flat_map(|source| match source {
+        TableOrSubquery::TableFunction { args, .. } => &args[..],
+        _ => &[],
+      });
+    constraints.chain(args)
+  });
+  let windows = select.windows.iter().flat_map(|(_, window)| {
+    window
+      .partition_by
+      .iter()
+      .chain(window.order_by.iter().map(|term| &term.expr))
+  });
+  select
+    .columns
+    .iter()
+    .filter_map(|result| match result {
+      ResultColumn::Expr { expr, .. } => Some(expr),
+      _ => None,
+    })
+    .chain(&select.where_clause)
+    .chain(&select.group_by)
+    .chain(&select.having)
+    .chain(order_by.iter().map(|term| &term.expr))
+    .chain(windows)
+    .chain(from)
+}
+
+/// Whether an expression has a subquery in it.
+fn has_subquery(expr: &Expr) -> bool {
+  let mut found = false;
+  expr.walk(&mut |expr| {
+    found |= matches!(
+      expr,
+      Expr::Exists { .. } | Expr::Subquery(_) | Expr::InSelect { .. } | Expr::InTable { .. }
+    );
+  });
+  found
+}
+
+/// The name a source of a FROM clause is read by, if it has one.
+fn source_name(source: &TableOrSubquery) -> Option<&str> {
+  match source {
+    TableOrSubquery::Table { name, alias, .. } => Some(alias.as_deref().unwrap_or(&name.name)),
+    TableOrSubquery::TableFunction { name, alias, .. } => {
+      Some(alias.as_deref().unwrap_or(&name.name))
+    }
+    TableOrSubquery::Subquery { alias, .. } => alias.as_deref(),
+    TableOrSubquery::Join(_) => None,
+  }
+}
+
+/// The table of the schema a source of a FROM clause reads, when it is
+/// one rather than a common table expression or a subquery.
+fn plain_table<'a>(
+  runtime: &'a SqliteRuntime,
+  context: Context,
+  source: &TableOrSubquery,
+) -> Option<&'a TableSchema> {
+  match source {
+    TableOrSubquery::Table {
+      name: QualifiedName { schema: None, name },
+      ..
+    } if context.ctes.map_or(true, |ctes| ctes.find(name).is_none()) => {
+      runtime.schema().table(name)
+    }
+    _ => None,
+  }
+}
+
+/// Whether a table has a column called `name`, or `name` is its rowid.
+fn has_column(table: &TableSchema, name: &str) -> bool {
+  table.column(name).is_some()
+    || (!table.is_without_rowid()
+      && ROWID_NAMES
+        .iter()
+        .any(|rowid| rowid.eq_ignore_ascii_case(name)))
+}
@@ -0,0 +1,508 @@
+//! # Aggregate queries
+//!
+//!  The rows of an aggregate query are grouped by the values of their GROUP
+//! BY terms, and every aggregate call of the query keeps an accumulator per
+//! group. The rows are sorted by those values first, so that each group is
+//! a run of consecutive rows, and groups come out in the order of their
+//! terms.
+//!
+//!  Each aggregate call keeps its state in a slot of the program, which
+//! `AggReset` starts, `AggStep` feeds a row and `AggFinal` turns into the
+//! result. The results of a group are then read from registers by the
+//! HAVING clause and the result columns.
+//!
+//!  A column used outside of an aggregate call, a bare column, takes its
+//! value from the first row of the group. If the query uses `min()` or
+//! `max()`, it takes it instead from the row on which one of them last found
+//! a new minimum or maximum.
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html#bare_columns_in_an_aggregate_query
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    functions::{resolve_call, Accumulator, FunctionDef, Implementation},
+    sorter::{compare_keys, KeyOrder, Sorter},
+    vdbe::Op,
+  },
+  sql::ast::{Expr, FunctionArgs, OrderingTerm},
+  value::Value,
+};
+
+use super::{
+  compile::{compile_call_args, compile_condition, compile_expr, Builder, Slot},
+  join::Joins,
+  Scope,
+};
+
+/// A call of an aggregate function in a query.
+pub(crate) struct AggregateCall<'a> {
+  expr: &'a Expr,
+  name: &'a str,
+  function: &'static FunctionDef,
+  distinct: bool,
+  args: &'a FunctionArgs,
+  order_by: &'a [OrderingTerm],
+  filter: Option<&'a Expr>,
+}
+
+/// The aggregate calls of an expression, outermost first. An aggregate call
+/// inside the arguments of another is an error.
+pub(crate) fn aggregate_calls(expr: &Expr) -> SqliteResult<Vec<AggregateCall<'_>>> {
+  let mut calls: Vec<AggregateCall> = vec![];
+  let mut result = Ok(());
+  expr.walk(&mut |expr| {
+    if result.is_err() {
+      return;
+    }
+    let Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    } = expr
+    else {
+      return;
+    };
+    // A window call is not an aggregate call, though its arguments may be.
+    if over.is_some() {
+      return;
+    }
+    let function = match resolve_call(name, *distinct, args, order_by, filter.as_deref(), false) {
+      Ok(function) => match function.implementation {
+        Implementation::Aggregate(_) => function,
+        Implementation::Scalar(_) | Implementation::Window(_) => return,
+      },
+      Err(err) => {
+        result = Err(err);
+        return;
+      }
+    };
+    // Calls are visited outermost first, so a nested aggregate is always
+    // inside one that was already collected.
+    if calls.iter().any(|call| contains(call.expr, expr)) {
+      result = Err(SqliteError::Custom(format!(
+        "misuse of aggregate function {name}()"
+      )));
+      return;
+    }
+    calls.push(AggregateCall {
+      expr,
+      name,
+      function,
+      distinct: *distinct,
+      args,
+      order_by,
+      filter: filter.as_deref(),
+    });
+  });
+  result.map(|_| calls)
+}
+
+/// Whether `inner` is a strict sub-expression of `outer`.
+pub(super) fn contains(outer: &Expr, inner: &Expr) -> bool {
+  let mut found = false;
+  outer.walk(&mut |expr| {
+    found |= !core::ptr::eq(expr, outer) && core::ptr::eq(expr, inner);
+  });
+  found
+}
+
+impl<'a> AggregateCall<'a> {
+  pub(crate) fn expr(&self) -> &'a Expr {
+    self.expr
+  }
+
+  /// The function name, as written.
+  pub(crate) fn name(&self) -> &'a str {
+    self.name
+  }
+
+  /// Compiles the start of the aggregate of the call in `slot`, with no
+  /// rows. The ORDER BY of a call sorts with the collations of its terms in
+  /// the scope of the query, and DISTINCT compares arguments with theirs.
+  /// `min()` and `max()` ignore DISTINCT, which would not change their
+  /// result, so that their bare columns still come from their row.
+  pub(super) fn compile_reset(
+    &self,
+    builder: &mut Builder,
+    scope: &Scope,
+    slot: usize,
+  ) -> SqliteResult<()> {
+    let is_distinct = self.distinct && !self.is_min_or_max();
+    let distinct = match (is_distinct, self.args) {
+      (true, FunctionArgs::List(args)) => Some(
+        args
+          .iter()
+          .map(|arg| scope.collation(arg).map(KeyOrder::ascending))
+          .collect::<SqliteResult<Vec<_>>>()?,
+      ),
+      _ => None,
+    };
+    let order = match self.order_by.is_empty() && distinct.is_none() {
+      true => None,
+      false => Some(
+        self
+          .order_by
+          .iter()
+          .map(|term| scope.term_order(term))
+          .collect::<SqliteResult<Vec<_>>>()?,
+      ),
+    };
+    builder.emit(Op::AggReset {
+      slot,
+      function: self.function,
+      distinct,
+      order,
+    });
+    Ok(())
+  }
+
+  /// Compiles the step of the aggregate in `slot` for the current row,
+  /// unless its FILTER leaves the row out. `on_replaced` is compiled for a
+  /// row that is now that of a `min()` or `max()`.
+  pub(super) fn compile_step<'r>(
+    &self,
+    builder: &mut Builder<'r>,
+    scope: &Scope,
+    slot: usize,
+    on_replaced: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+  ) -> SqliteResult<()> {
+    let skip = builder.label();
+    if let Some(filter) = self.filter {
+      compile_condition(builder, scope, filter, skip)?;
+    }
+    let args: Vec<&Expr> = match self.args {
+      FunctionArgs::Star => vec![],
+      FunctionArgs::List(args) => args.iter().collect(),
+    };
+    // The values of the ORDER BY terms follow the arguments.
+    let first = builder.registers(args.len() + self.order_by.len());
+    compile_call_args(builder, scope, self.function, &args, true, first)?;
+    for (index, term) in self.order_by.iter().enumerate() {
+      compile_expr(builder, scope, &term.expr, true, first + args.len() + index)?;
+    }
+    let replaced = (self.is_min_or_max() && self.order_by.is_empty()).then(|| builder.register());
+    builder.emit(Op::AggStep {
+      slot,
+      args: first,
+      count: args.len(),
+      replaced,
+    });
+    if let Some(replaced) = replaced {
+      builder.emit(Op::IfNot {
+        register: replaced,
+        target: skip,
+        if_null: true,
+      });
+      on_replaced(builder)?;
+    }
+    builder.place(skip);
+    Ok(())
+  }
+
+  fn is_min_or_max(&self) -> bool {
+    matches!(self.function.name(), "min" | "max")
+  }
+}
+
+/// The state of one aggregate call over one group, as a program keeps it.
+pub(crate) struct Aggregate {
+  accumulator: Box<dyn Accumulator>,
+  /// The arguments of every row, each with its position and the values of
+  /// the ORDER BY terms, for `DISTINCT`, and how arguments are compared.
+  distinct: Option<(Sorter, Vec<KeyOrder>)>,
+  /// The sort keys and arguments of every row, for `ORDER BY` and
+  /// `DISTINCT`. The position of the row is the last key.
+  pending: Option<Sorter>,
+  /// The number of ORDER BY terms.
+  key_count: usize,
+  /// The number of rows added.
+  count: i64,
+}
+
+impl Aggregate {
+  /// Starts an aggregate with no rows. `distinct` compares the arguments of
+  /// a DISTINCT call, and `order` sorts the rows of a call with an ORDER BY
+  /// or DISTINCT by the values of its terms.
+  pub(crate) fn new(
+    function: &FunctionDef,
+    distinct: Option<Vec<KeyOrder>>,
+    order: Option<Vec<KeyOrder>>,
+    budget: usize,
+  ) -> SqliteResult<Self> {
+    let Implementation::Aggregate(start) = function.implementation else {
+      return Err(SqliteError::Custom(format!(
+        "{}() is not an aggregate function",
+        function.name()
+      )));
+    };
+    let key_count = order.as_ref().map_or(0, Vec::len);
+    let pending = order.map(|mut key_orders| {
+      key_orders.push(KeyOrder::default());
+      Sorter::new(key_orders, budget)
+    });
+    Ok(Self {
+      accumulator: start(),
+      distinct: distinct.map(|key_orders| (Sorter::new(key_orders.clone(), budget), key_orders)),
+      pending,
+      key_count,
+      count: 0,
+    })
+  }
+
+  /// The number of values of ORDER BY terms a row comes with.
+  pub(crate) fn key_count(&self) -> usize {
+    self.key_count
+  }
+
+  /// Adds the arguments of a row and the values of the ORDER BY terms of
+  /// the call, returning whether it is now the row of a `min()` or `max()`.
+  pub(crate) fn step(&mut self, args: Vec<Value>, mut keys: Vec<Value>) -> SqliteResult<bool> {
+    if let Some(pending) = &mut self.pending {
+      keys.push(Value::Integer(self.count));
+      self.count += 1;
+      match &mut self.distinct {
+        Some((sorter, _)) => sorter.push(args, keys)?,
+        None => pending.push(keys, args)?,
+      }
+      return Ok(false);
+    }
+    self.accumulator.step(&args)?;
+    Ok(self.accumulator.replaced())
+  }
+
+  /// The result of the aggregate over its rows.
+  pub(crate) fn finish(mut self) -> SqliteResult<Value> {
+    // The first of equal arguments is kept, in the order of the rows.
+    if let (Some((sorter, key_orders)), Some(pending)) = (self.distinct, &mut self.pending) {
+      let mut last: Option<Vec<Value>> = None;
+      for record in sorter.finish()? {
+        let (args, keys) = record?;
+        if last
+          .as_ref()
+          .is_some_and(|last| compare_keys(last, &args, &key_orders).is_eq())
+        {
+          continue;
+        }
+        last = Some(args.clone());
+        pending.push(keys, args)?;
+      }
+    }
+    if let Some(pending) = self.pending {
+      for record in pending.finish()? {
+        let (_, args) = record?;
+        self.accumulator.step(&args)?;
+      }
+    }
+    self.accumulator.value()
+  }
+}
+
+/// Compiles an aggregate query without GROUP BY, a single group which has
+/// a row even if no row matches, where bare columns are NULL. `stage` is
+/// compiled for the group, unless HAVING leaves it out.
+pub(super) fn compile_single_group<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  joins: &Joins,
+  calls: &[AggregateCall],
+  having: Option<&Expr>,
+  stage: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+) -> SqliteResult<()> {
+  let width = scope.width();
+  let has_row = builder.register();
+  let bare = builder.registers(width);
+  let slot = builder.aggregates(calls.len());
+  builder.emit(Op::Integer {
+    value: 0,
+    dest: has_row,
+  });
+  for position in 0..width {
+    builder.emit(Op::Null {
+      dest: bare + position,
+    });
+  }
+  for (index, call) in calls.iter().enumerate() {
+    call.compile_reset(builder, scope, slot + index)?;
+  }
+  joins.compile(builder, scope, &mut |builder| {
+    // The bare columns of the first row.
+    let stepped = builder.label();
+    builder.emit(Op::IfPos {
+      register: has_row,
+      target: stepped,
+      decrement: 0,
+    });
+    builder.emit(Op::Integer {
+      value: 1,
+      dest: has_row,
+    });
+    scope.compile_row(builder, bare);
+    builder.place(stepped);
+    compile_steps(builder, scope, calls, slot, bare)
+  })?;
+  compile_group_end(builder, scope, calls, slot, bare, having, stage)
+}
+
+/// Compiles an aggregate query with GROUP BY. The rows are sorted by the
+/// values of the terms, each with the values of the row, and `stage` is
+/// compiled once per group, as a subroutine called when the next group
+/// starts and after the last row.
+pub(super) fn compile_groups<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  joins: &Joins,
+  group_by: &[Expr],
+  calls: &[AggregateCall],
+  having: Option<&Expr>,
+  stage: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+) -> SqliteResult<()> {
+  let width = scope.width();
+  let terms = group_by.len();
+  let order: Vec<KeyOrder> = group_by
+    .iter()
+    .map(|term| scope.collation(term).map(KeyOrder::ascending))
+    .collect::<SqliteResult<_>>()?;
+  let sorter = builder.cursor();
+  builder.emit(Op::SorterOpen {
+    cursor: sorter,
+    order: order.clone(),
+    limit: None,
+    combine: None,
+  });
+  // A record is the values of the terms, then those of the row.
+  let record = builder.registers(terms + width);
+  joins.compile(builder, scope, &mut |builder| {
+    for (index, term) in group_by.iter().enumerate() {
+      compile_expr(builder, scope, term, true, record + index)?;
+    }
+    scope.compile_row(builder, record + terms);
+    builder.emit(Op::SorterInsert {
+      cursor: sorter,
+      key: record,
+      values: record,
+      count: terms + width,
+    });
+    Ok(())
+  })?;
+
+  let has_group = builder.register();
+  let previous = builder.registers(terms);
+  let bare = builder.registers(width);
+  let slot = builder.aggregates(calls.len());
+  let ret = builder.register();
+  let (top, new_group, same_group) = (builder.label(), builder.label(), builder.label());
+  let (end, output, after) = (builder.label(), builder.label(), builder.label());
+  builder.emit(Op::Integer {
+    value: 0,
+    dest: has_group,
+  });
+  builder.emit(Op::SorterSort {
+    cursor: sorter,
+    target: end,
+  });
+  builder.place(top);
+  builder.emit(Op::SorterData {
+    cursor: sorter,
+    dest: record,
+    count: terms + width,
+  });
+  builder.emit(Op::IfNot {
+    register: has_group,
+    target: new_group,
+    if_null: true,
+  });
+  builder.emit(Op::CompareKeys {
+    left: record,
+    right: previous,
+    count: terms,
+    order,
+    target: same_group,
+  });
+  builder.emit(Op::Gosub {
+    register: ret,
+    target: output,
+  });
+  builder.place(new_group);
+  builder.copy(record, previous, terms);
+  builder.emit(Op::Integer {
+    value: 1,
+    dest: has_group,
+  });
+  for (index, call) in calls.iter().enumerate() {
+    call.compile_reset(builder, scope, slot + index)?;
+  }
+  builder.copy(record + terms, bare, width);
+  builder.place(same_group);
+  scope.read_registers(record + terms);
+  compile_steps(builder, scope, calls, slot, bare)?;
+  builder.emit(Op::SorterNext {
+    cursor: sorter,
+    target: top,
+  });
+  builder.emit(Op::Gosub {
+    register: ret,
+    target: output,
+  });
+  builder.place(end);
+  builder.emit(Op::Goto { target: after });
+  builder.place(output);
+  compile_group_end(builder, scope, calls, slot, bare, having, stage)?;
+  builder.emit(Op::Return { register: ret });
+  builder.place(after);
+  Ok(())
+}
+
+/// Compiles the steps of every aggregate call for the current row. The row
+/// of a new minimum or maximum becomes that of the bare columns.
+fn compile_steps<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  calls: &[AggregateCall],
+  slot: usize,
+  bare: usize,
+) -> SqliteResult<()> {
+  for (index, call) in calls.iter().enumerate() {
+    call.compile_step(builder, scope, slot + index, &mut |builder| {
+      scope.compile_row(builder, bare);
+      Ok(())
+    })?;
+  }
+  Ok(())
+}
+
+/// Compiles the results of the aggregate calls of a group, then `stage`
+/// with the row of its bare columns, unless HAVING leaves it out.
+fn compile_group_end<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  calls: &[AggregateCall],
+  slot: usize,
+  bare: usize,
+  having: Option<&Expr>,
+  stage: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+) -> SqliteResult<()> {
+  let results = builder.registers(calls.len());
+  for index in 0..calls.len() {
+    builder.emit(Op::AggFinal {
+      slot: slot + index,
+      dest: results + index,
+    });
+  }
+  scope.read_registers(bare);
+  *scope.computed.borrow_mut() = calls
+    .iter()
+    .enumerate()
+    .map(|(index, call)| (call.expr().clone(), Slot::Register(results + index)))
+    .collect();
+  let skip = builder.label();
+  if let Some(having) = having {
+    compile_condition(builder, scope, having, skip)?;
+  }
+  stage(builder)?;
+  builder.place(skip);
+  Ok(())
+}
@@ -0,0 +1,969 @@
+//! # Joins
+//!
+//!  The tables of a FROM clause are joined as nested loops: the rows of
+//! each table are read once for every row the levels before it have
+//! produced. Each table is a level of the loops, and every condition is
+//! checked at the first level where all of its tables have a row.
+//!
+//!  A subquery in FROM, VALUES and a common table expression fill an
+//! ephemeral table before the loops, whose rows are read as those of a
+//! table without a rowid. So are the rows of a table-valued function,
+//! computed anew each time its level starts, since its arguments may refer
+//! to the levels before it. A recursive common table expression is a
+//! coroutine, which the level resumes whenever it has read every row
+//! produced so far.
+//!
+//!  When a level's conditions compare columns of its table with values
+//! known from the levels before it, the matching rows may be sought by
+//! rowid, by PRIMARY KEY or through an index, rather than read in full. The
+//! planner picks the cheapest way for each level, and for inner joins of
+//! tables, the order of the levels that costs the least. Outer joins, CROSS
+//! JOIN and the other sources of rows keep the order of the FROM clause.
+//!
+//!  A LEFT or FULL join pads the row with NULLs when no row of its table
+//! matches. A RIGHT or FULL join keeps a set of the ordinals of its rows
+//! that matched, and once the loops are over, reads its table again for the
+//! others, padded with NULLs for the tables on its left. See [`compile`].
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html#determination_of_input_data_from_clause_processing_
+
+mod compile;
+mod plan;
+
+use core::iter::once;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    eval::expr_affinity,
+    functions::{find_table_function, resolve_call, TableFunctionDef},
+    schema::TableSchema,
+    vdbe::Op,
+    SqliteRuntime,
+  },
+  sql::ast::{
+    BinaryOperator, Expr, JoinClause, JoinConstraint, JoinKind, OrderingTerm, QualifiedName,
+    ResultColumn, Select, TableOrSubquery,
+  },
+  value::Affinity,
+};
+
+pub(crate) use self::plan::entry_columns;
+
+use self::plan::{Access, Choice, KeyRange, KeyTerm, Operand};
+use super::{
+  compile::{compile_expr, Builder},
+  compound::members,
+  cte::CteRows,
+  explain::Explainer,
+  materialize, Clause, Names, QueryResult, Scope, ROWID_NAMES,
+};
+
+/// Which rows of a join are kept without a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outer {
+  None,
+  Left,
+  Right,
+  Full,
+}
+
+impl Outer {
+  /// Whether rows on the left are kept with NULLs for this table.
+  fn keeps_left(self) -> bool {
+    matches!(self, Self::Left | Self::Full)
+  }
+
+  /// Whether rows of this table are kept with NULLs for the tables on the
+  /// left.
+  fn keeps_right(self) -> bool {
+    matches!(self, Self::Right | Self::Full)
+  }
+}
+
+/// A condition of a join.
+#[derive(Debug, Clone)]
+enum Condition {
+  Expr(Expr),
+  /// Two positions of the row hold equal values, for USING and NATURAL.
+  Equal(usize, usize),
+}
+
+/// What a level reads its rows from.
+#[derive(Debug)]
+enum Rows<'a> {
+  Table(&'a TableSchema),
+  /// The rows of a subquery, of VALUES or of a common table expression, in
+  /// an ephemeral table.
+  Ephemeral {
+    cursor: usize,
+    result: QueryResult,
+  },
+  /// The rows of a recursive common table expression, which its coroutine
+  /// adds to an ephemeral table as they are read, and sets `done` once it
+  /// has no more.
+  Recursion {
+    cursor: usize,
+    coroutine: usize,
+    done: usize,
+    result: QueryResult,
+  },
+  /// A table-valued function and its arguments.
+  Function(&'static TableFunctionDef, &'a [Expr]),
+}
+
+/// One table of the nested loops.
+#[derive(Debug)]
+struct Level<'a> {
+  rows: Rows<'a>,
+  /// The name of the table in the query plan.
+  name: String,
+  outer: Outer,
+  /// The source of the scope its table is, by its place in the FROM clause.
+  source: usize,
+  /// The position of its first column in the row.
+  offset: usize,
+  /// The conditions a row of the table must meet to match.
+  on: Vec<Condition>,
+  /// The conditions every row leaving the level must meet, including rows
+  /// padded with NULLs.
+  filters: Vec<Expr>,
+  access: Access<'a>,
+  /// Whether its rows are read from the last to the first.
+  is_backward: bool,
+}
+
+impl Rows<'_> {
+  /// The columns of the rows of an ephemeral table.
+  fn result(&self) -> Option<&QueryResult> {
+    match self {
+      Self::Ephemeral { result, .. } | Self::Recursion { result, .. } => Some(result),
+      Self::Table(_) | Self::Function(..) => None,
+    }
+  }
+}
+
+impl From<CteRows> for Rows<'_> {
+  fn from(rows: CteRows) -> Self {
+    match rows {
+      CteRows::Table { cursor, result } => Self::Ephemeral { cursor, result },
+      CteRows::Recursion {
+        cursor,
+        coroutine,
+        done,
+        result,
+      } => Self::Recursion {
+        cursor,
+        coroutine,
+        done,
+        result,
+      },
+    }
+  }
+}
+
+impl Level<'_> {
+  fn width(&self) -> usize {
+    match &self.rows {
+      Rows::Table(table) => table.columns().len() + 1,
+      Rows::Function(function, _) => function.columns.len() + 1,
+      rows => rows.result().map_or(0, |result| result.columns().len()) + 1,
+    }
+  }
+}
+
+/// The first levels of an order of the joins, with the way picked for each,
+/// and what they cost.
+#[derive(Debug, Clone)]
+struct Path<'a> {
+  /// The index of each level, in the order they are read.
+  levels: Vec<(usize, Choice<'a>)>,
+  cost: f64,
+  /// The rows the levels are expected to produce together.
+  rows: f64,
+}
+
+impl Default for Path<'_> {
+  fn default() -> Self {
+    Self {
+      levels: vec![],
+      cost: 0.0,
+      rows: 1.0,
+    }
+  }
+}
+
+impl<'a> Path<'a> {
+  /// The path with one more level, read once for each row of the others.
+  fn extend(&self, index: usize, choice: Choice<'a>) -> Self {
+    let mut levels = self.levels.clone();
+    let cost = self.cost + self.rows * choice.cost;
+    let rows = self.rows * choice.rows;
+    levels.push((index, choice));
+    Self { levels, cost, rows }
+  }
+
+  /// Its cost, with that of sorting its rows when ORDER BY `is_sorted` and
+  /// the rows of its first level do not come out in that order.
+  fn cost_with_sort(&self, is_sorted: bool) -> f64 {
+    let is_ordered = self
+      .levels
+      .first()
+      .is_some_and(|(_, choice)| choice.is_ordered);
+    match is_sorted && !is_ordered {
+      true => self.cost + self.rows * self.rows.log2().max(1.0),
+      false => self.cost,
+    }
+  }
+}
+
+/// The tables of a FROM clause, pushed to the scope of their query, and how
+/// they join.
+#[derive(Debug, Default)]
+pub(super) struct Joins<'a> {
+  levels: Vec<Level<'a>>,
+  /// The conditions of a query without tables.
+  filters: Vec<Expr>,
+  /// Whether the tables are read in the order of the FROM clause, as for
+  /// outer joins, CROSS JOIN and rows that are not in a b-tree.
+  is_fixed: bool,
+}
+
+impl<'a> Joins<'a> {
+  /// Pushes the tables of a FROM clause to the scope, and resolves their
+  /// USING and NATURAL columns. The subqueries and common table
+  /// expressions it reads are compiled to fill their ephemeral tables.
+  pub(super) fn new(
+    builder: &mut Builder,
+    scope: &mut Scope<'a>,
+    from: Option<&'a JoinClause>,
+  ) -> SqliteResult<Self> {
+    let Some(from) = from else {
+      return Ok(Self::default());
+    };
+    let runtime = scope.runtime;
+    let mut levels = vec![];
+    let mut is_fixed = false;
+    let tables = core::iter::once((&from.first, None))
+      .chain(from.joins.iter().map(|join| (&join.table, Some(join))));
+    for (table, join) in tables {
+      let context = scope.context;
+      let mut plan_name = None;
+      let (rows, name) = match table {
+        TableOrSubquery::Table { name, alias, .. } => {
+          let cte = match &name.schema {
+            Some(_) => None,
+            None => context.ctes.and_then(|ctes| ctes.find(&name.name)),
+          };
+          match cte {
+            Some((ctes, index)) => (
+              ctes.compile(builder, index)?.into(),
+              alias.as_deref().unwrap_or(&name.name),
+            ),
+            // A table-valued function may be named like a table, without
+            // its arguments.
+            None => match (
+              runtime.schema().table(&name.name),
+              find_table_function(&name.name),
+            ) {
+              (Some(table), _) => (Rows::Table(table), alias.as_deref().unwrap_or(&name.name)),
+              (None, Some(function)) => (
+                Rows::Function(function, &[]),
+                alias.as_deref().unwrap_or(&name.name),
+              ),
+              (None, None) => {
+                return Err(SqliteError::Custom(format!("no such table: {}", name.name)))
+              }
+            },
+          }
+        }
+        TableOrSubquery::Subquery { select, alias } => {
+          // Without an alias, the plan numbers the subquery.
+          plan_name = match (alias, context.explainer) {
+            (None, Some(explainer)) => Some(format!("(subquery-{})", explainer.next_subquery())),
+            _ => None,
+          };
+          let name = plan_name
+            .as_deref()
+            .or(alias.as_deref())
+            .unwrap_or_default();
+          let _step = context
+            .explainer
+            .map(|explainer| explainer.open(format!("MATERIALIZE {name}")));
+          let (cursor, result) = materialize(builder, select, context)?;
+          (
+            Rows::Ephemeral { cursor, result },
+            alias.as_deref().unwrap_or_default(),
+          )
+        }
+        TableOrSubquery::TableFunction { name, args, alias } => {
+          let function = find_table_function(&name.name)
+            .ok_or_else(|| SqliteError::Custom(format!("no such table: {}", name.name)))?;
+          if args.len() > function.arguments {
+            return Err(SqliteError::Custom(format!(
+              "too many arguments on {}() - max {}",
+              function.name, function.arguments
+            )));
+          }
+          (
+            Rows::Function(function, args),
+            alias.as_deref().unwrap_or(&name.name),
+          )
+        }
+        _ => {
+          return Err(SqliteError::Custom(
+            "only tables and subqueries are supported in FROM yet".into(),
+          ))
+        }
+      };
+      let offset = scope.width();
+      let columns: Vec<String> = match &rows {
+        Rows::Table(table) => table
+          .columns()
+          .iter()
+          .map(|column| column.name().to_string())
+          .collect(),
+        Rows::Function(function, _) => function.columns.iter().map(|&name| name.into()).collect(),
+        rows => rows
+          .result()
+          .map(|result| result.columns().to_vec())
+          .unwrap_or_default(),
+      };
+      match &rows {
+        Rows::Table(table) => scope.push(
+          name,
+          columns.clone(),
+          table
+            .columns()
+            .iter()
+            .map(|column| column.affinity())
+            .collect(),
+          table
+            .columns()
+            .iter()
+            .map(|column| column.collation().map(String::from))
+            .collect(),
+          table
+            .columns()
+            .iter()
+            .map(|column| column.declared_type().map(String::from))
+            .collect(),
+          !table.is_without_rowid(),
+        ),
+        Rows::Ephemeral { .. } | Rows::Recursion { .. } => {
+          let result = rows.result().cloned().unwrap_or_default();
+          scope.push(
+            name,
+            columns.clone(),
+            result.affinities,
+            result.collations,
+            result.declared_types,
+            false,
+          )
+        }
+        Rows::Function(function, _) => {
+          scope.push(
+            name,
+            columns.clone(),
+            vec![Affinity::Blob; columns.len()],
+            vec![None; columns.len()],
+            vec![None; columns.len()],
+            false,
+          );
+          if let Some(source) = scope.sources.last_mut() {
+            source.function = Some(function);
+          }
+        }
+      }
+      is_fixed |= !matches!(rows, Rows::Table(_));
+      let mut level = Level {
+        rows,
+        name: plan_name.unwrap_or_else(|| name.to_string()),
+        outer: Outer::None,
+        source: levels.len(),
+        offset,
+        on: vec![],
+        filters: vec![],
+        access: Access::Scan,
+        is_backward: false,
+      };
+      if let Some(join) = join {
+        level.outer = match join.operator.kind {
+          JoinKind::Left => Outer::Left,
+          JoinKind::Right => Outer::Right,
+          JoinKind::Full => Outer::Full,
+          JoinKind::Comma | JoinKind::Inner | JoinKind::Cross => Outer::None,
+        };
+        is_fixed |= !matches!(join.operator.kind, JoinKind::Comma | JoinKind::Inner);
+        let using: Vec<&str> = match (&join.constraint, join.operator.natural) {
+          (Some(_), true) => {
+            return Err(SqliteError::Custom(
+              "a NATURAL join may not have an ON or USING clause".into(),
+            ))
+          }
+          (Some(JoinConstraint::Using(names)), false) => names.iter().map(String::as_str).collect(),
+          (_, true) => columns
+            .iter()
+            .map(String::as_str)
+            .filter(|name| scope.resolve_left(offset, name).is_some())
+            .collect(),
+          (_, false) => vec![],
+        };
+        for name in using {
+          let right = columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name));
+          let (Some(left), Some(right)) = (scope.resolve_left(offset, name), right) else {
+            return Err(SqliteError::Custom(format!(
+              "cannot join using column {name} - column not present in both tables"
+            )));
+          };
+          if let Some(source) = scope.sources.last_mut() {
+            source.hidden.push(right);
+          }
+          scope.coalesced.push((left, offset + right));
+          level.on.push(Condition::Equal(left, offset + right));
+        }
+      }
+      levels.push(level);
+    }
+    Ok(Self {
+      levels,
+      filters: vec![],
+      is_fixed,
+    })
+  }
+
+  /// Compiles the rows of VALUES into an ephemeral table, pushed to the
+  /// scope as its only table. A plan leaves them out.
+  pub(super) fn values(
+    builder: &mut Builder,
+    scope: &mut Scope,
+    values: &[Vec<Expr>],
+  ) -> SqliteResult<Self> {
+    let width = values.first().map_or(0, Vec::len);
+    let cursor = builder.cursor();
+    builder.emit(Op::OpenEphemeral {
+      cursor,
+      columns: width,
+    });
+    if scope.context.explainer.is_none() {
+      for row in values {
+        let start = builder.registers(row.len());
+        for (index, expr) in row.iter().enumerate() {
+          compile_expr(builder, scope, expr, false, start + index)?;
+        }
+        builder.emit(Op::Insert {
+          cursor,
+          start,
+          count: row.len(),
+        });
+      }
+    }
+    let result = QueryResult::new(
+      (1..=width).map(|index| format!("column{index}")).collect(),
+      vec![],
+    );
+    scope.push(
+      "",
+      result.columns.clone(),
+      result.affinities.clone(),
+      result.collations.clone(),
+      result.declared_types.clone(),
+      false,
+    );
+    Ok(Self {
+      levels: vec![Level {
+        rows: Rows::Ephemeral { cursor, result },
+        name: String::new(),
+        outer: Outer::None,
+        source: 0,
+        offset: 0,
+        on: vec![],
+        filters: vec![],
+        access: Access::Scan,
+        is_backward: false,
+      }],
+      filters: vec![],
+      is_fixed: true,
+    })
+  }
+
+  /// Whether there are no tables, for a query without FROM.
+  pub(super) fn is_empty(&self) -> bool {
+    self.levels.is_empty()
+  }
+
+  /// Adds the steps that read the tables to a query plan. The rows of a
+  /// RIGHT join without a match are read once the loops are over.
+  pub(super) fn explain(&self, explainer: &Explainer) {
+    for level in &self.levels {
+      let mut detail = match &level.rows {
+        Rows::Table(table) => level.access.explain(&level.name, table),
+        Rows::Ephemeral { .. } | Rows::Recursion { .. } => format!("SCAN {}", level.name),
+        Rows::Function(..) => format!("SCAN {} VIRTUAL TABLE", level.name),
+      };
+      if level.outer.keeps_left() {
+        detail.push_str(" LEFT-JOIN");
+      }
+      explainer.add(detail);
+    }
+    for level in &self.levels {
+      if level.outer.keeps_right() {
+        let _step = explainer.open(format!("RIGHT-JOIN {}", level.name));
+        explainer.add(format!("SCAN {}", level.name));
+      }
+    }
+  }
+
+  /// Places the ON and WHERE conditions at the levels where they are
+  /// checked.
+  pub(super) fn plan(
+    &mut self,
+    scope: &Scope,
+    from: Option<&JoinClause>,
+    where_clause: Option<&Expr>,
+  ) -> SqliteResult<()> {
+    let Some(last) = self.levels.len().checked_sub(1) else {
+      self.filters.extend(where_clause.cloned());
+      return Ok(());
+    };
+    let has_right_join = self.levels.iter().any(|level| level.outer.keeps_right());
+
+    let ons = from
+      .into_iter()
+      .flat_map(|from| &from.joins)
+      .enumerate()
+      .filter_map(|(index, join)| match &join.constraint {
+        Some(JoinConstraint::On(on)) => Some((index + 1, on)),
+        _ => None,
+      });
+    for (index, on) in ons {
+      scope.check(on, Clause::Where)?;
+      let outer = self.levels[index].outer;
+      for term in conjuncts(on) {
+        let level = term_level(scope, term);
+        if outer != Outer::None {
+          if level.is_some_and(|level| level > index) {
+            return Err(SqliteError::Custom(
+              "ON clause references tables to its right".into(),
+            ));
+          }
+          self.levels[index].on.push(Condition::Expr(term.clone()));
+          continue;
+        }
+        match level.unwrap_or(last) {
+          level if level <= index => self.levels[index].on.push(Condition::Expr(term.clone())),
+          level => self.push_condition(level, term),
+        }
+      }
+    }
+    for term in where_clause.into_iter().flat_map(conjuncts) {
+      match has_right_join {
+        // A row of a RIGHT join padded with NULLs must still meet every
+        // condition, so none may be checked early.
+        true => self.levels[last].filters.push(term.clone()),
+        false => self.push_condition(term_level(scope, term).unwrap_or(last), term),
+      }
+    }
+    Ok(())
+  }
+
+  /// Picks the order the levels are read in, and how each reads its table,
+  /// given the expressions the query computes from the rows besides the
+  /// conditions of the joins. Returns whether the rows come out in the
+  /// order of `order_by`, when given.
+  ///
+  ///  The order is searched the way SQLite does: level by level, extending
+  /// the few cheapest orders so far with each level that may come next.
+  /// The cost of a level counts once for each row of the levels before it,
+  /// and a sort for ORDER BY counts once the rows are all known.
+  pub(super) fn pick_access(
+    &mut self,
+    runtime: &'a SqliteRuntime,
+    scope: &Scope,
+    exprs: &[&Expr],
+    order_by: Option<&[OrderingTerm]>,
+  ) -> SqliteResult<bool> {
+    let needed = self.needed_columns(scope, exprs);
+    // The rows of a RIGHT join without a match come out last.
+    let has_right_join = self.levels.iter().any(|level| level.outer.keeps_right());
+    let order_by = order_by.filter(|_| !has_right_join);
+    let breadth = match self.levels.len() {
+      0 | 1 => 1,
+      2 => 5,
+      _ => 10,
+    };
+    // Any level may come first, so each is offered every condition, and
+    // those are placed again once the order is known.
+    let conditions: Vec<Condition> = match self.is_fixed {
+      true => vec![],
+      false => self
+        .levels
+        .iter_mut()
+        .flat_map(|level| core::mem::take(&mut level.on))
+        .collect(),
+    };
+    if !self.is_fixed {
+      for level in &mut self.levels {
+        level.on.clone_from(&conditions);
+      }
+    }
+    let mut paths = vec![Path::default()];
+    for depth in 0..self.levels.len() {
+      let mut extended = vec![];
+      for path in &paths {
+        let mut known = vec![false; scope.sources.len()];
+        for (index, _) in &path.levels {
+          known[self.levels[*index].source] = true;
+        }
+        for (index, level) in self.levels.iter().enumerate() {
+          let is_next = match self.is_fixed {
+            true => index == depth,
+            false => !path.levels.iter().any(|(picked, _)| *picked == index),
+          };
+          if !is_next {
+            continue;
+          }
+          let choices = match (&level.rows, level.outer.keeps_right()) {
+            (Rows::Table(table), false) => {
+              let columns = level.offset..level.offset + level.width();
+              level.pick_access(
+                runtime,
+                scope,
+                &known,
+                table,
+                needed.as_ref().map(|needed| &needed[columns]),
+                order_by.filter(|_| depth == 0),
+              )?
+            }
+            _ => vec![Choice::scan()],
+          };
+          for choice in choices {
+            extended.push(path.extend(index, choice));
+          }
+        }
+      }
+      extended.sort_by(|left, right| {
+        let cost = |path: &Path| path.cost_with_sort(order_by.is_some());
+        cost(left).total_cmp(&cost(right))
+      });
+      extended.truncate(breadth);
+      paths = extended;
+    }
+    let Some(path) = paths.into_iter().next() else {
+      return Ok(false);
+    };
+    let is_ordered = path
+      .levels
+      .first()
+      .is_some_and(|(_, choice)| choice.is_ordered);
+    let order: Vec<usize> = path.levels.iter().map(|(index, _)| *index).collect();
+    for (index, choice) in path.levels {
+      self.levels[index].access = choice.access;
+      self.levels[index].is_backward = choice.is_backward;
+    }
+    if !self.is_fixed {
+      self.reorder(scope, &order, conditions);
+    }
+    Ok(is_ordered)
+  }
+
+  /// Puts the levels of an inner join in a new order, where `order` holds
+  /// the index of each, and places each condition at the first level where
+  /// all of its tables have a row.
+  fn reorder(&mut self, scope: &Scope, order: &[usize], conditions: Vec<Condition>) {
+    let mut levels: Vec<Option<Level>> = core::mem::take(&mut self.levels)
+      .into_iter()
+      .map(Some)
+      .collect();
+    self.levels = order
+      .iter()
+      .filter_map(|index| levels.get_mut(*index).and_then(Option::take))
+      .collect();
+    let mut places = vec![0; scope.sources.len()];
+    for (place, level) in self.levels.iter().enumerate() {
+      places[level.source] = place;
+    }
+    let Some(last) = self.levels.len().checked_sub(1) else {
+      return;
+    };
+    for level in &mut self.levels {
+      level.on.clear();
+    }
+    for condition in conditions {
+      let place = match &condition {
+        Condition::Expr(expr) => term_sources(scope, expr).map_or(last, |sources| {
+          sources
+            .iter()
+            .map(|source| places[*source])
+            .max()
+            .unwrap_or(0)
+        }),
+        Condition::Equal(left, right) => {
+          places[scope.source_at(*left)].max(places[scope.source_at(*right)])
+        }
+      };
+      self.levels[place].on.push(condition);
+    }
+  }
+
+  /// Which positions of the row the query reads, or `None` when it may read
+  /// any of them, through a subquery that depends on the row.
+  fn needed_columns(&self, scope: &Scope, exprs: &[&Expr]) -> Option<Vec<bool>> {
+    let mut needed = vec![false; scope.width()];
+    let mut has_subquery = false;
+    let conditions = self.levels.iter().flat_map(|level| {
+      let on = level.on.iter().filter_map(|condition| match condition {
+        Condition::Expr(expr) => Some(expr),
+        Condition::Equal(..) => None,
+      });
+      let args = match &level.rows {
+        Rows::Function(_, args) => &args[..],
+        _ => &[],
+      };
+      on.chain(&level.filters).chain(args)
+    });
+    for expr in exprs.iter().copied().chain(conditions).chain(&self.filters) {
+      expr.walk(&mut |expr| match expr {
+        Expr::Column { table, name, .. } => {
+          if let Ok(Some((position, ..))) = scope.find(table.as_deref(), name) {
+            needed[position] = true;
+            // An unqualified name of a USING column reads both columns.
+            for (_, other) in scope.coalesced.iter().filter(|(left, _)| *left == position) {
+              needed[*other] |= table.is_none();
+            }
+          }
+        }
+        Expr::InSelect { select, .. } if list_affinity(scope, select).is_some() => {}
+        Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSelect { .. } => has_subquery = true,
+        _ => {}
+      });
+    }
+    for level in &self.levels {
+      for condition in &level.on {
+        if let Condition::Equal(left, right) = condition {
+          needed[*left] = true;
+          needed[*right] = true;
+        }
+      }
+    }
+    (!has_subquery).then_some(needed)
+  }
+
+  /// Adds a condition to a level: one that decides which rows match for an
+  /// inner join, one that is checked after rows are padded with NULLs for an
+  /// outer join.
+  fn push_condition(&mut self, level: usize, term: &Expr) {
+    let level = &mut self.levels[level];
+    match level.outer {
+      Outer::None => level.on.push(Condition::Expr(term.clone())),
+      _ => level.filters.push(term.clone()),
+    }
+  }
+}
+
+/// The terms of a chain of ANDs.
+fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+  match expr {
+    Expr::Binary {
+      left,
+      operator: BinaryOperator::And,
+      right,
+    } => {
+      let mut terms = conjuncts(left);
+      terms.extend(conjuncts(right));
+      terms
+    }
+    expr => vec![expr],
+  }
+}
+
+/// The sources of the tables an expression refers to, or `None` if it
+/// refers to an alias or holds a subquery, and so can only be checked once
+/// every table has a row.
+fn term_sources(scope: &Scope, expr: &Expr) -> Option<Vec<usize>> {
+  let mut sources = Some(vec![]);
+  expr.walk(&mut |expr| match expr {
+    Expr::Column { table, name, .. } => match scope.find(table.as_deref(), name) {
+      Ok(Some((position, ..))) => {
+        if let Some(sources) = &mut sources {
+          sources.push(scope.source_at(position));
+          if table.is_none() {
+            for (_, other) in scope.coalesced.iter().filter(|(left, _)| *left == position) {
+              sources.push(scope.source_at(*other));
+            }
+          }
+        }
+      }
+      // A column of an outer query has the same value at every level.
+      Ok(None) if scope.is_outer_column(table.as_deref(), name) => {}
+      _ => sources = None,
+    },
+    // A subquery that does not depend on the row has the same value at
+    // every level.
+    Expr::InSelect { select, .. } if list_affinity(scope, select).is_some() => {}
+    Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSelect { .. } => sources = None,
+    _ => {}
+  });
+  sources
+}
+
+/// The last level whose table an expression refers to, by the order of the
+/// FROM clause, or `None` as for [`term_sources`].
+fn term_level(scope: &Scope, expr: &Expr) -> Option<usize> {
+  term_sources(scope, expr).map(|sources| sources.into_iter().max().unwrap_or(0))
+}
+
+/// Whether an expression only refers to the tables of the `known` sources,
+/// and gives the same value every time it is evaluated with the same row.
+fn is_known(scope: &Scope, expr: &Expr, known: &[bool]) -> bool {
+  is_deterministic(expr)
+    && term_sources(scope, expr).is_some_and(|sources| {
+      sources
+        .iter()
+        .all(|source| known.get(*source) == Some(&true))
+    })
+}
+
+/// Whether every function an expression calls is deterministic.
+fn is_deterministic(expr: &Expr) -> bool {
+  let mut is_deterministic = true;
+  expr.walk(&mut |expr| {
+    if let Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    } = expr
+    {
+      is_deterministic &= resolve_call(
+        name,
+        *distinct,
+        args,
+        order_by,
+        filter.as_deref(),
+        over.is_some(),
+      )
+      .is_ok_and(|function| function.is_deterministic());
+    }
+  });
+  is_deterministic
+}
+
+/// The affinity of the values of a subquery on the right of IN, if they
+/// are the same for every row of the query: it returns a single column,
+/// reads only tables of the schema, none of the columns of the query, and
+/// calls deterministic functions only, without subqueries of its own.
+fn list_affinity(scope: &Scope, select: &Select) -> Option<Affinity> {
+  let mut affinity = None;
+  for (_, member) in members(select) {
+    let [ResultColumn::Expr { expr, .. }] = &member.columns[..] else {
+      return None;
+    };
+    if member.with.is_some() || !member.values.is_empty() {
+      return None;
+    }
+    let mut tables: Vec<(&str, &TableSchema)> = vec![];
+    for source in member
+      .from
+      .iter()
+      .flat_map(|from| once(&from.first).chain(from.joins.iter().map(|join| &join.table)))
+    {
+      let TableOrSubquery::Table {
+        name: QualifiedName { schema: None, name },
+        alias,
+        ..
+      } = source
+      else {
+        return None;
+      };
+      if scope
+        .context
+        .ctes
+        .is_some_and(|ctes| ctes.find(name).is_some())
+      {
+        return None;
+      }
+      let table = scope.runtime.schema().table(name)?;
+      tables.push((alias.as_deref().unwrap_or(name), table));
+    }
+    // The affinity of a column of the tables, if one has it.
+    let column_affinity = |table: Option<&str>, name: &str| {
+      tables
+        .iter()
+        .filter(|(source, _)| table.map_or(true, |table| table.eq_ignore_ascii_case(source)))
+        .find_map(|(_, table)| match table.column(name) {
+          Some((_, column)) => Some(column.affinity()),
+          None => (!table.is_without_rowid()
+            && ROWID_NAMES
+              .iter()
+              .any(|rowid| rowid.eq_ignore_ascii_case(name)))
+          .then_some(Affinity::Integer),
+        })
+    };
+
+    let constraints =
+      member
+        .from
+        .iter()
+        .flat_map(|from| &from.joins)
+        .filter_map(|join| match &join.constraint {
+          Some(JoinConstraint::On(on)) => Some(on),
+          _ => None,
+        });
+    let windows = member.windows.iter().flat_map(|(_, window)| {
+      window
+        .partition_by
+        .iter()
+        .chain(window.order_by.iter().map(|term| &term.expr))
+    });
+    let order_by = match member.compound.is_empty() {
+      true => &member.order_by[..],
+      false => &[],
+    };
+    let mut exprs = once(expr)
+      .chain(&member.where_clause)
+      .chain(&member.group_by)
+      .chain(&member.having)
+      .chain(order_by.iter().map(|term| &term.expr))
+      .chain(constraints)
+      .chain(windows);
+    let is_local = exprs.all(|expr| {
+      let mut is_local = is_deterministic(expr);
+      expr.walk(&mut |expr| match expr {
+        Expr::Column {
+          schema,
+          table,
+          name,
+        } => is_local &= schema.is_none() && column_affinity(table.as_deref(), name).is_some(),
+        Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSelect { .. } => is_local = false,
+        _ => {}
+      });
+      is_local
+    });
+    if !is_local {
+      return None;
+    }
+
+    if affinity.is_none() {
+      let mut expr = expr;
+      while let Expr::Collate { expr: inner, .. } = expr {
+        expr = inner;
+      }
+      affinity = Some(match expr {
+        Expr::Column { table, name, .. } => column_affinity(table.as_deref(), name)?,
+        expr => expr_affinity(
+          expr,
+          &Names {
+            scope,
+            aliases: false,
+          },
+        ),
+      });
+    }
+  }
+  affinity
+}
@@ -0,0 +1,949 @@
+//! # Compiled joins
+//!
+//!  The nested loops of a join as instructions. Each level moves the cursor
+//! of its table to the first row that may match, by a rewind or by a seek
+//! with keys computed from the levels before it, and goes on with `Next`
+//! until the end of its range. A level read backward starts from the last
+//! row with `Last` or a seek at the upper end, and goes on with `Prev`. A
+//! condition that does not hold jumps to the next row.
+//!
+//!  The values of an IN list or IN subquery a level is sought by are sorted
+//! without duplicates, and read in a loop around that of the level: the
+//! level starts over for each.
+//!
+//!  A LEFT join notes in a register whether a row of its table has matched.
+//! When none has, its cursors are moved to a row of NULLs, and the levels
+//! after it run once more.
+//!
+//!  A RIGHT join counts the rows of its table in a register, and adds the
+//! ordinal of each that matches to a set. Once the loops are over, the
+//! cursors of every level are moved to a row of NULLs, and its table is
+//! read again: the rows whose ordinal is not in the set go on to the levels
+//! after it.
+//!
+//!  The level of a recursive common table expression reads the rows its
+//! coroutine has added to an ephemeral table by position. Past the last, it
+//! resumes the coroutine for one more, until the coroutine is done.
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    collation::Collation,
+    schema::{IndexSchema, SortOrder, TableSchema},
+    sorter::KeyOrder,
+    vdbe::{Op, Tree},
+    SqliteRuntime,
+  },
+  sql::ast::{BinaryOperator, CompoundOperator},
+  value::Affinity,
+};
+
+use super::{
+  super::{
+    compile::{compile_condition, compile_expr, compile_position, Builder, Slot},
+    compile_query, Context,
+  },
+  entry_columns, Access, Condition, Joins, KeyRange, KeyTerm, Level, Operand, Rows, Scope,
+};
+
+/// The cursors a level reads the rows of its table with.
+struct Cursors<'r> {
+  /// The table, for a level that reads one.
+  table: Option<&'r TableSchema>,
+  /// The cursor on the table or the ephemeral table, unless it is read from
+  /// a covering index.
+  cursor: Option<usize>,
+  index: Option<(usize, &'r IndexSchema)>,
+  /// For a RIGHT or FULL join, the set of the ordinals of the rows that
+  /// matched, and the register that counts them.
+  matched: Option<(usize, usize)>,
+}
+
+impl Cursors<'_> {
+  /// Moves every cursor of the level to a row of NULLs.
+  fn null_row(&self, builder: &mut Builder) {
+    for cursor in self
+      .cursor
+      .into_iter()
+      .chain(self.index.map(|(cursor, _)| cursor))
+    {
+      builder.emit(Op::NullRow { cursor });
+    }
+  }
+}
+
+/// How a level goes on to its next row.
+enum Looping {
+  /// The level has a single row.
+  Once,
+  /// The next row of a cursor.
+  Next(usize),
+  /// The previous row of a cursor, for a level read backward.
+  Prev(usize),
+  /// The next row of a recursion, by position, which its coroutine
+  /// produces when it is not yet. `missing` is where a position past the
+  /// last row goes.
+  Recursion {
+    position: usize,
+    coroutine: usize,
+    done: usize,
+    missing: usize,
+  },
+}
+
+/// The loops over the values of the IN lists a level is sought by, around
+/// the loop of the level.
+struct Lists {
+  /// For each list, the sorter of its values, where its next value is read,
+  /// and where it goes on once the rows its value seeks are over.
+  loops: Vec<(usize, usize, usize)>,
+  /// Where the level goes once the rows sought by the current values are
+  /// over.
+  end: usize,
+}
+
+impl Lists {
+  fn new(end: usize) -> Self {
+    Self { loops: vec![], end }
+  }
+
+  /// Sorts the distinct values of a list, or of the rows of a subquery, and
+  /// starts a loop that reads each into `dest`. NULLs match no row, and are
+  /// left out.
+  fn open(
+    &mut self,
+    builder: &mut Builder,
+    scope: &Scope,
+    term: &KeyTerm,
+    dest: usize,
+  ) -> SqliteResult<()> {
+    let cursor = builder.cursor();
+    builder.emit(Op::SorterOpen {
+      cursor,
+      order: vec![KeyOrder::ascending(
+        scope.runtime.collation(&term.collation)?,
+      )],
+      limit: None,
+      combine: Some(CompoundOperator::Union),
+    });
+    let side = builder.register();
+    builder.emit(Op::Integer {
+      value: 0,
+      dest: side,
+    });
+    let insert = |builder: &mut Builder, value: usize| {
+      let skip = builder.label();
+      if let Some(affinity) = term.affinity {
+        builder.emit(Op::Affinity {
+          start: value,
+          affinities: vec![affinity],
+        });
+      }
+      builder.emit(Op::IsNull {
+        register: value,
+        target: skip,
+      });
+      builder.emit(Op::SorterInsert {
+        cursor,
+        key: value,
+        values: side,
+        count: 1,
+      });
+      builder.place(skip);
+    };
+    match &term.value {
+      Operand::List(list) => {
+        let value = builder.register();
+        for item in list {
+          compile_expr(builder, scope, item, false, value)?;
+          insert(builder, value);
+        }
+      }
+      // The subquery reads none of the columns of the query, nor is it a
+      // step of its plan of its own.
+      Operand::Select(select) => {
+        let context = Context {
+          outer: Some(scope),
+          explainer: None,
+          ..scope.context
+        };
+        compile_query(builder, select, context, &mut |builder, start, _| {
+          insert(builder, start);
+          Ok(())
+        })?;
+      }
+      Operand::Expr(_) | Operand::Position(_) => {}
+    }
+    builder.emit(Op::SorterSort {
+      cursor,
+      target: self.end,
+    });
+    let top = builder.label();
+    let exhausted = builder.label();
+    builder.place(top);
+    builder.emit(Op::SorterData {
+      cursor,
+      dest,
+      count: 1,
+    });
+    self.loops.push((cursor, top, exhausted));
+    self.end = exhausted;
+    Ok(())
+  }
+}
+
+impl Joins<'_> {
+  /// Compiles the nested loops of the joins, with `body` compiled for each
+  /// row that leaves the last level. The cursors of every table are opened
+  /// first. A query without tables has a single empty row.
+  pub(crate) fn compile<'r>(
+    &self,
+    builder: &mut Builder<'r>,
+    scope: &Scope,
+    body: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+  ) -> SqliteResult<()> {
+    if self.levels.is_empty() {
+      let skip = builder.label();
+      for filter in &self.filters {
+        compile_condition(builder, scope, filter, skip)?;
+      }
+      body(builder)?;
+      builder.place(skip);
+      return Ok(());
+    }
+    *scope.slots.borrow_mut() = vec![Slot::Null; scope.width()];
+    let mut cursors = vec![];
+    for level in &self.levels {
+      cursors.push(open_level(builder, scope, level)?);
+    }
+    self.compile_level(builder, scope, &cursors, 0, body)?;
+
+    for (index, level) in self.levels.iter().enumerate() {
+      let opened = &cursors[index];
+      let Some((set, ordinal)) = opened.matched else {
+        continue;
+      };
+      for level_cursors in &cursors {
+        level_cursors.null_row(builder);
+      }
+      let top = builder.label();
+      let next = builder.label();
+      let end = builder.label();
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: ordinal,
+      });
+      let mut lists = Lists::new(end);
+      let looping = compile_access(builder, scope, level, opened, top, next, &mut lists)?;
+      builder.emit(Op::AddImm {
+        register: ordinal,
+        value: 1,
+      });
+      builder.emit(Op::SetInsert {
+        cursor: set,
+        key: ordinal,
+        count: 1,
+        target: next,
+      });
+      for filter in &level.filters {
+        compile_condition(builder, scope, filter, next)?;
+      }
+      self.compile_level(builder, scope, &cursors, index + 1, body)?;
+      compile_next(builder, looping, &lists, top, next, end);
+    }
+    Ok(())
+  }
+
+  /// Compiles the loop of a level around those of the levels after it.
+  fn compile_level<'r>(
+    &self,
+    builder: &mut Builder<'r>,
+    scope: &Scope,
+    cursors: &[Cursors<'r>],
+    index: usize,
+    body: &mut dyn FnMut(&mut Builder<'r>) -> SqliteResult<()>,
+  ) -> SqliteResult<()> {
+    let (Some(level), Some(opened)) = (self.levels.get(index), cursors.get(index)) else {
+      return body(builder);
+    };
+    let top = builder.label();
+    let next = builder.label();
+    let end = builder.label();
+    let matched = level.outer.keeps_left().then(|| builder.register());
+    if let Some(matched) = matched {
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: matched,
+      });
+    }
+    if let Some((_, ordinal)) = opened.matched {
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: ordinal,
+      });
+    }
+    let mut lists = Lists::new(end);
+    let looping = compile_access(builder, scope, level, opened, top, next, &mut lists)?;
+    if let Some((_, ordinal)) = opened.matched {
+      builder.emit(Op::AddImm {
+        register: ordinal,
+        value: 1,
+      });
+    }
+    for condition in &level.on {
+      match condition {
+        Condition::Expr(expr) => compile_condition(builder, scope, expr, next)?,
+        // Like `left = right`, with the collation of the left column.
+        Condition::Equal(left, right) => {
+          let (left_value, right_value) = (builder.register(), builder.register());
+          let is_equal = builder.register();
+          compile_position(builder, scope, *left, left_value);
+          compile_position(builder, scope, *right, right_value);
+          builder.emit(Op::Compare {
+            operator: BinaryOperator::Eq,
+            left: left_value,
+            right: right_value,
+            dest: is_equal,
+            affinities: [scope.affinity_at(*left), scope.affinity_at(*right)],
+            collation: scope.runtime.collation(&scope.collation_at(*left))?,
+          });
+          builder.emit(Op::IfNot {
+            register: is_equal,
+            target: next,
+            if_null: true,
+          });
+        }
+      }
+    }
+    if let Some((set, ordinal)) = opened.matched {
+      let noted = builder.label();
+      builder.emit(Op::SetInsert {
+        cursor: set,
+        key: ordinal,
+        count: 1,
+        target: noted,
+      });
+      builder.place(noted);
+    }
+    let matching = builder.label();
+    builder.place(matching);
+    if let Some(matched) = matched {
+      builder.emit(Op::Integer {
+        value: 1,
+        dest: matched,
+      });
+    }
+    for filter in &level.filters {
+      compile_condition(builder, scope, filter, next)?;
+    }
+    self.compile_level(builder, scope, cursors, index + 1, body)?;
+    compile_next(builder, looping, &lists, top, next, end);
+    if let Some(matched) = matched {
+      let done = builder.label();
+      builder.emit(Op::IfPos {
+        register: matched,
+        target: done,
+        decrement: 0,
+      });
+      opened.null_row(builder);
+      builder.emit(Op::Goto { target: matching });
+      builder.place(done);
+    }
+    Ok(())
+  }
+}
+
+/// Compiles the end of the loop of a level, and of those over its IN lists,
+/// placing `next` where it goes on to its next row and `end` past its last.
+fn compile_next(
+  builder: &mut Builder,
+  looping: Looping,
+  lists: &Lists,
+  top: usize,
+  next: usize,
+  end: usize,
+) {
+  builder.place(next);
+  match looping {
+    Looping::Once => {}
+    Looping::Next(cursor) => builder.emit(Op::Next {
+      cursor,
+      target: top,
+    }),
+    Looping::Prev(cursor) => builder.emit(Op::Prev {
+      cursor,
+      target: top,
+    }),
+    Looping::Recursion {
+      position,
+      coroutine,
+      done,
+      missing,
+    } => {
+      builder.emit(Op::AddImm {
+        register: position,
+        value: 1,
+      });
+      builder.emit(Op::Goto { target: top });
+      builder.place(missing);
+      builder.emit(Op::If {
+        register: done,
+        target: end,
+        if_null: false,
+      });
+      builder.emit(Op::Yield {
+        register: coroutine,
+      });
+      builder.emit(Op::Goto { target: top });
+    }
+  }
+  for (cursor, top, exhausted) in lists.loops.iter().rev() {
+    builder.place(*exhausted);
+    builder.emit(Op::SorterNext {
+      cursor: *cursor,
+      target: *top,
+    });
+  }
+  builder.place(end);
+}
+
+/// Opens the cursors of a level, and notes how each position of its table
+/// in the row is read.
+fn open_level<'r>(
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  level: &Level,
+) -> SqliteResult<Cursors<'r>> {
+  let matched = level.outer.keeps_right().then(|| {
+    let set = builder.cursor();
+    builder.emit(Op::SetOpen {
+      cursor: set,
+      order: vec![KeyOrder::default()],
+    });
+    (set, builder.register())
+  });
+  let ephemeral = match &level.rows {
+    Rows::Table(table) => {
+      // The schema of the runtime, which the program may outlive the query.
+      let runtime = builder.runtime;
+      let table = runtime
+        .schema()
+        .table(table.name())
+        .ok_or_else(|| SqliteError::Custom(format!("no such table: {}", table.name())))?;
+      return open_table(runtime, builder, scope, level, table, matched);
+    }
+    Rows::Ephemeral { cursor, .. } | Rows::Recursion { cursor, .. } => *cursor,
+    // Its rows are only computed once the level starts.
+    Rows::Function(..) => {
+      let cursor = builder.cursor();
+      builder.emit(Op::OpenEphemeral {
+        cursor,
+        columns: level.width() - 1,
+      });
+      cursor
+    }
+  };
+  let mut slots = scope.slots.borrow_mut();
+  for column in 0..level.width() - 1 {
+    slots[level.offset + column] = Slot::Column {
+      cursor: ephemeral,
+      column,
+      is_real: false,
+    };
+  }
+  Ok(Cursors {
+    table: None,
+    cursor: Some(ephemeral),
+    index: None,
+    matched,
+  })
+}
+
+/// Opens the cursors of a level that reads a table.
+fn open_table<'r>(
+  runtime: &'r SqliteRuntime,
+  builder: &mut Builder<'r>,
+  scope: &Scope,
+  level: &Level,
+  table: &'r TableSchema,
+  matched: Option<(usize, usize)>,
+) -> SqliteResult<Cursors<'r>> {
+  let index = match &level.access {
+    Access::Index {
+      index, is_covering, ..
+    } => {
+      let index = runtime
+        .schema()
+        .index(index.name())
+        .ok_or_else(|| SqliteError::Custom(format!("no such index: {}", index.name())))?;
+      Some((index, *is_covering))
+    }
+    _ => None,
+  };
+  let cursor = match index {
+    Some((_, true)) => None,
+    _ => Some(builder.cursor()),
+  };
+  if let Some(cursor) = cursor {
+    builder.emit(Op::OpenRead {
+      cursor,
+      tree: Tree::Table(table),
+    });
+  }
+  let index = index.map(|(index, _)| {
+    let cursor = builder.cursor();
+    builder.emit(Op::OpenRead {
+      cursor,
+      tree: Tree::Index(index),
+    });
+    (cursor, index)
+  });
+
+  let rowid = table.columns().len();
+  let held = index.map(|(_, index)| entry_columns(table, index));
+  let mut slots = scope.slots.borrow_mut();
+  for column in 0..=rowid {
+    let is_rowid =
+      !table.is_without_rowid() && (column == rowid || table.rowid_alias() == Some(column));
+    let slot = match (cursor, index, &held) {
+      (Some(cursor), ..) if is_rowid => Slot::Rowid { cursor },
+      (Some(cursor), ..) if column < rowid => Slot::Column {
+        cursor,
+        column,
+        is_real: false,
+      },
+      // A covering index holds the rowid past its key columns.
+      (None, Some((cursor, _)), Some(held)) => {
+        let target = match is_rowid {
+          true => rowid,
+          false => column,
+        };
+        match held.iter().position(|held| *held == Some(target)) {
+          Some(position) => Slot::Column {
+            cursor,
+            column: position,
+            is_real: table
+              .columns()
+              .get(target)
+              .is_some_and(|definition| definition.affinity() == Affinity::Real),
+          },
+          None => Slot::Null,
+        }
+      }
+      _ => Slot::Null,
+    };
+    slots[level.offset + column] = slot;
+  }
+  Ok(Cursors {
+    table: Some(table),
+    cursor,
+    index,
+    matched,
+  })
+}
+
+/// Moves the cursors of a level to its first row, placing `top` where each
+/// of its rows is checked to be within range and read.
+fn compile_access(
+  builder: &mut Builder,
+  scope: &Scope,
+  level: &Level,
+  opened: &Cursors,
+  top: usize,
+  next: usize,
+  lists: &mut Lists,
+) -> SqliteResult<Looping> {
+  let table = match (&level.rows, opened.table, opened.cursor) {
+    (Rows::Table(_), Some(table), _) => table,
+    (Rows::Ephemeral { .. }, _, Some(cursor)) => {
+      return Ok(rewind(builder, cursor, false, top, lists.end))
+    }
+    (Rows::Function(function, args), _, Some(cursor)) => {
+      let start = builder.registers(args.len());
+      for (index, arg) in args.iter().enumerate() {
+        compile_expr(builder, scope, arg, false, start + index)?;
+      }
+      builder.emit(Op::FunctionRows {
+        cursor,
+        function,
+        args: start,
+        count: args.len(),
+      });
+      return Ok(rewind(builder, cursor, false, top, lists.end));
+    }
+    (
+      Rows::Recursion {
+        cursor,
+        coroutine,
+        done,
+        ..
+      },
+      ..,
+    ) => {
+      let position = builder.register();
+      let missing = builder.label();
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: position,
+      });
+      builder.place(top);
+      builder.emit(Op::SeekPosition {
+        cursor: *cursor,
+        register: position,
+        target: missing,
+      });
+      return Ok(Looping::Recursion {
+        position,
+        coroutine: *coroutine,
+        done: *done,
+        missing,
+      });
+    }
+    _ => return Err(no_cursor(&level.name)),
+  };
+  let is_backward = level.is_backward;
+  match (&level.access, opened.cursor, opened.index) {
+    (Access::Scan, Some(cursor), _) => Ok(rewind(builder, cursor, is_backward, top, lists.end)),
+    (Access::Rowid(range), Some(cursor), _) => {
+      if let Some(term) = range.equal.first() {
+        let key = builder.register();
+        compile_key_term(builder, scope, term, key, lists)?;
+        builder.emit(Op::SeekRowid {
+          cursor,
+          key,
+          target: lists.end,
+        });
+        builder.place(top);
+        return Ok(Looping::Once);
+      }
+      // Read backward, the range starts at its upper bound.
+      let (first, last) = match is_backward {
+        false => (&range.lower, &range.upper),
+        true => (&range.upper, &range.lower),
+      };
+      match first {
+        Some(bound) => {
+          let key = builder.register();
+          compile_key_term(builder, scope, &bound.term, key, lists)?;
+          builder.emit(seek(
+            is_backward,
+            bound.is_inclusive,
+            cursor,
+            key,
+            1,
+            lists.end,
+          ));
+        }
+        None => builder.emit(match is_backward {
+          false => Op::Rewind {
+            cursor,
+            target: lists.end,
+          },
+          true => Op::Last {
+            cursor,
+            target: lists.end,
+          },
+        }),
+      }
+      let stop = match last {
+        Some(bound) => {
+          let stop = builder.register();
+          compile_key_term(builder, scope, &bound.term, stop, lists)?;
+          Some(stop)
+        }
+        None => None,
+      };
+      builder.place(top);
+      if let Some(stop) = stop {
+        let rowid = builder.register();
+        let is_past = builder.register();
+        builder.emit(Op::Rowid {
+          cursor,
+          dest: rowid,
+        });
+        builder.emit(Op::Compare {
+          operator: match is_backward {
+            false => BinaryOperator::Gt,
+            true => BinaryOperator::Lt,
+          },
+          left: rowid,
+          right: stop,
+          dest: is_past,
+          affinities: [Affinity::Blob, Affinity::Blob],
+          collation: Collation::binary(),
+        });
+        builder.emit(Op::If {
+          register: is_past,
+          target: lists.end,
+          if_null: false,
+        });
+      }
+      Ok(step(cursor, is_backward))
+    }
+    (Access::PrimaryKey(range), Some(cursor), _) => {
+      let primary_key = table.primary_key();
+      let is_descending = primary_key
+        .get(range.equal.len())
+        .is_some_and(|(_, order)| *order == SortOrder::Desc);
+      let ((start, count), stop) = compile_range(builder, scope, range, is_descending, lists)?;
+      if range.equal.len() == primary_key.len() {
+        builder.emit(Op::NotFound {
+          cursor,
+          key: start,
+          count,
+          target: lists.end,
+        });
+        builder.place(top);
+        return Ok(Looping::Once);
+      }
+      seek_range(
+        builder,
+        cursor,
+        is_backward,
+        (start, count),
+        stop,
+        top,
+        lists.end,
+      );
+      Ok(step(cursor, is_backward))
+    }
+    (Access::Index { range, .. }, _, Some((cursor, index))) => {
+      let is_descending = index
+        .columns()
+        .get(range.equal.len())
+        .is_some_and(|key| key.order() == Some(SortOrder::Desc));
+      let (start, stop) = compile_range(builder, scope, range, is_descending, lists)?;
+      seek_range(builder, cursor, is_backward, start, stop, top, lists.end);
+      // The row the entry points to, unless the index covers the query.
+      if let Some(table_cursor) = opened.cursor {
+        match table.is_without_rowid() {
+          false => {
+            let rowid = builder.register();
+            builder.emit(Op::IdxRowid {
+              cursor,
+              dest: rowid,
+            });
+            builder.emit(Op::SeekRowid {
+              cursor: table_cursor,
+              key: rowid,
+              target: next,
+            });
+          }
+          true => {
+            // The entries of an index of a WITHOUT ROWID table hold its
+            // PRIMARY KEY.
+            let held = entry_columns(table, index);
+            let primary_key = table.primary_key();
+            let key = builder.registers(primary_key.len());
+            for (offset, (column, _)) in primary_key.iter().enumerate() {
+              let position = held
+                .iter()
+                .position(|held| *held == Some(*column))
+                .ok_or_else(|| no_cursor(&level.name))?;
+              builder.emit(Op::Column {
+                cursor,
+                column: position,
+                dest: key + offset,
+              });
+            }
+            builder.emit(Op::NotFound {
+              cursor: table_cursor,
+              key,
+              count: primary_key.len(),
+              target: next,
+            });
+          }
+        }
+      }
+      Ok(step(cursor, is_backward))
+    }
+    _ => Err(no_cursor(&level.name)),
+  }
+}
+
+/// Rewinds a cursor to its first row, or to its last to read it backward.
+fn rewind(
+  builder: &mut Builder,
+  cursor: usize,
+  is_backward: bool,
+  top: usize,
+  end: usize,
+) -> Looping {
+  builder.emit(match is_backward {
+    false => Op::Rewind {
+      cursor,
+      target: end,
+    },
+    true => Op::Last {
+      cursor,
+      target: end,
+    },
+  });
+  builder.place(top);
+  step(cursor, is_backward)
+}
+
+/// Goes on to the next row of a cursor, or to the previous to read it
+/// backward.
+fn step(cursor: usize, is_backward: bool) -> Looping {
+  match is_backward {
+    false => Looping::Next(cursor),
+    true => Looping::Prev(cursor),
+  }
+}
+
+/// Seeks the first row at or past a key in the direction a cursor is read.
+fn seek<'a>(
+  is_backward: bool,
+  is_inclusive: bool,
+  cursor: usize,
+  key: usize,
+  count: usize,
+  target: usize,
+) -> Op<'a> {
+  match (is_backward, is_inclusive) {
+    (false, true) => Op::SeekGE {
+      cursor,
+      key,
+      count,
+      target,
+    },
+    (false, false) => Op::SeekGT {
+      cursor,
+      key,
+      count,
+      target,
+    },
+    (true, true) => Op::SeekLE {
+      cursor,
+      key,
+      count,
+      target,
+    },
+    (true, false) => Op::SeekLT {
+      cursor,
+      key,
+      count,
+      target,
+    },
+  }
+}
+
+/// The error of a level whose cursors cannot read it the way it was
+/// planned.
+fn no_cursor(name: &str) -> SqliteError {
+  SqliteError::Custom(format!("no cursor to read {name} with"))
+}
+
+/// Compiles the first and the last keys of a range, in key order, given
+/// whether the column after the equal ones is in descending order. Returns
+/// the first register and the number of values of each.
+fn compile_range(
+  builder: &mut Builder,
+  scope: &Scope,
+  range: &KeyRange,
+  is_descending: bool,
+  lists: &mut Lists,
+) -> SqliteResult<((usize, usize), (usize, usize))> {
+  let (first, last) = match is_descending {
+    false => (&range.lower, &range.upper),
+    true => (&range.upper, &range.lower),
+  };
+  let equal = range.equal.len();
+  let start_count = equal + usize::from(first.is_some());
+  let start = builder.registers(start_count);
+  for (offset, term) in range.equal.iter().enumerate() {
+    compile_key_term(builder, scope, term, start + offset, lists)?;
+  }
+  if let Some(bound) = first {
+    compile_key_term(builder, scope, &bound.term, start + equal, lists)?;
+  }
+  let stop_count = equal + usize::from(last.is_some());
+  let stop = builder.registers(stop_count);
+  for offset in 0..equal {
+    builder.emit(Op::Copy {
+      source: start + offset,
+      dest: stop + offset,
+    });
+  }
+  if let Some(bound) = last {
+    compile_key_term(builder, scope, &bound.term, stop + equal, lists)?;
+  }
+  Ok(((start, start_count), (stop, stop_count)))
+}
+
+/// Seeks the first entry of a range, or rewinds without a start key, and
+/// checks each entry against the end key. Read backward, the range is
+/// sought from its end key, and each entry checked against its start key.
+fn seek_range(
+  builder: &mut Builder,
+  cursor: usize,
+  is_backward: bool,
+  first: (usize, usize),
+  last: (usize, usize),
+  top: usize,
+  end: usize,
+) {
+  let ((start, start_count), (stop, stop_count)) = match is_backward {
+    false => (first, last),
+    true => (last, first),
+  };
+  builder.emit(match start_count {
+    0 => match is_backward {
+      false => Op::Rewind {
+        cursor,
+        target: end,
+      },
+      true => Op::Last {
+        cursor,
+        target: end,
+      },
+    },
+    count => seek(is_backward, true, cursor, start, count, end),
+  });
+  builder.place(top);
+  if stop_count > 0 {
+    builder.emit(match is_backward {
+      false => Op::IdxGT {
+        cursor,
+        key: stop,
+        count: stop_count,
+        target: end,
+      },
+      true => Op::IdxLT {
+        cursor,
+        key: stop,
+        count: stop_count,
+        target: end,
+      },
+    });
+  }
+}
+
+/// Computes the value a level is sought by into `dest`, with the affinity
+/// of its comparison. A NULL value matches no row, and jumps to the end of
+/// the level. The values of an IN list are read in a loop of their own.
+fn compile_key_term(
+  builder: &mut Builder,
+  scope: &Scope,
+  term: &KeyTerm,
+  dest: usize,
+  lists: &mut Lists,
+) -> SqliteResult<()> {
+  match &term.value {
+    Operand::Expr(expr) => compile_expr(builder, scope, expr, false, dest)?,
+    Operand::Position(position) => compile_position(builder, scope, *position, dest),
+    Operand::List(_) | Operand::Select(_) => return lists.open(builder, scope, term, dest),
+  }
+  if let Some(affinity) = term.affinity {
+    builder.emit(Op::Affinity {
+      start: dest,
+      affinities: vec![affinity],
+    });
+  }
+  builder.emit(Op::IsNull {
+    register: dest,
+    target: lists.end,
+  });
+  Ok(())
+}
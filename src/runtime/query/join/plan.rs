@@ -0,0 +1,906 @@
+//! # Query planning
+//!
+//!  Each table of a join is read in the cheapest of the ways its conditions
+//! allow: a full scan of its b-tree, a lookup of one rowid or of a range of
+//! rowids, a seek on the PRIMARY KEY of a WITHOUT ROWID table, or a seek on
+//! an index. A seek finds the entries whose leading key columns equal values
+//! known from the levels before, or each value of an IN list or of an IN
+//! subquery that does not depend on the row in turn, and whose next column
+//! is within the bounds of `<`, `<=`, `>`, `>=` or BETWEEN, when the
+//! comparisons use the collation of the key. An index that holds every
+//! column the query reads from its table is read alone, without looking its
+//! rows up in the table.
+//!
+//!  The cost of a way is the number of b-tree entries it is expected to
+//! read, a row of a table counting as [`ROW_COST`] index entries as wide as
+//! the table, plus the depth of the b-tree for each seek. The number of rows
+//! of a table comes from sqlite_stat1 once ANALYZE has run, and from the
+//! shape of its b-tree otherwise. So do the rows that share the values of
+//! the leading columns of an index, or else the guesses SQLite makes without
+//! statistics: 10 rows for a value of the first column, one fewer for each
+//! further column, a quarter of the rows for each bound of a range, and 25
+//! values for an IN subquery.
+//!
+//!  The rows of the first table come out in the order of the b-tree or index
+//! they are read from, or in the reverse order when read backward. When that
+//! is the order of ORDER BY, the query does not sort them, and the cost of
+//! the ways that need a sort counts it. The rows sought for several values
+//! of an IN list come out in no such order.
+//!
+//! *Reference:* https://www.sqlite.org/optoverview.html
+
+use crate::{
+  btree::BtreeCursor,
+  result::SqliteResult,
+  runtime::{
+    eval::{comparison_collation_name, expr_affinity},
+    schema::{IndexSchema, SortOrder, TableSchema},
+    SqliteRuntime,
+  },
+  sql::ast::{self, BinaryOperator, Expr, NullsOrder, OrderingTerm, Select},
+  value::Affinity,
+};
+
+use super::{super::Names, is_known, list_affinity, Condition, Level, Scope};
+
+/// The cost of reading a row of a table, relative to that of reading an
+/// index entry holding as many values.
+const ROW_COST: f64 = 3.0;
+
+/// SQLite's guess of the rows per value of the first columns of an index
+/// without statistics, by the number of columns.
+const DEFAULT_ROWS_PER_KEY: [f64; 5] = [10.0, 9.0, 8.0, 7.0, 6.0];
+
+/// SQLite's guess of the rows of a subquery on the right of IN.
+const LIST_SUBQUERY_ROWS: usize = 25;
+
+/// A value the rows of a level are looked up by.
+#[derive(Debug, Clone)]
+pub(super) enum Operand {
+  Expr(Expr),
+  Position(usize),
+  /// Each value of an IN list in turn.
+  List(Vec<Expr>),
+  /// Each value of the rows of a subquery on the right of IN in turn.
+  Select(Box<Select>),
+}
+
+/// A comparison between a column of a level's table and a value known from
+/// the levels before it.
+#[derive(Debug, Clone)]
+pub(super) struct KeyTerm {
+  /// The column, or the rowid past the last column.
+  pub(super) column: usize,
+  pub(super) value: Operand,
+  /// The affinity the comparison applies to the value.
+  pub(super) affinity: Option<Affinity>,
+  /// The name of the collation of the comparison, which the column must
+  /// be sorted by to be sought.
+  pub(super) collation: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+  Eq,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Comparison {
+  fn of(operator: BinaryOperator) -> Option<Self> {
+    Some(match operator {
+      BinaryOperator::Eq => Self::Eq,
+      BinaryOperator::Lt => Self::Lt,
+      BinaryOperator::Le => Self::Le,
+      BinaryOperator::Gt => Self::Gt,
+      BinaryOperator::Ge => Self::Ge,
+      _ => return None,
+    })
+  }
+
+  /// The comparison with its operands swapped.
+  fn flip(self) -> Self {
+    match self {
+      Self::Eq => Self::Eq,
+      Self::Lt => Self::Gt,
+      Self::Le => Self::Ge,
+      Self::Gt => Self::Lt,
+      Self::Ge => Self::Le,
+    }
+  }
+}
+
+/// A bound of a range.
+#[derive(Debug, Clone)]
+pub(super) struct Bound {
+  pub(super) term: KeyTerm,
+  pub(super) is_inclusive: bool,
+}
+
+/// The leading columns of a key a seek constrains: to values, then the next
+/// one within bounds.
+#[derive(Debug, Clone, Default)]
+pub(super) struct KeyRange {
+  pub(super) equal: Vec<KeyTerm>,
+  pub(super) lower: Option<Bound>,
+  pub(super) upper: Option<Bound>,
+}
+
+impl KeyRange {
+  fn is_empty(&self) -> bool {
+    self.equal.is_empty() && self.lower.is_none() && self.upper.is_none()
+  }
+
+  /// The number of seeks it takes: one for each combination of the values
+  /// of its IN lists.
+  fn seeks(&self) -> usize {
+    self
+      .equal
+      .iter()
+      .map(|term| match &term.value {
+        Operand::List(list) => list.len(),
+        Operand::Select(_) => LIST_SUBQUERY_ROWS,
+        Operand::Expr(_) | Operand::Position(_) => 1,
+      })
+      .product()
+  }
+
+  /// The share of the rows with the equal values that are within bounds.
+  fn selectivity(&self) -> f64 {
+    match (&self.lower, &self.upper) {
+      (Some(_), Some(_)) => 1.0 / 64.0,
+      (Some(_), None) | (None, Some(_)) => 0.25,
+      (None, None) => 1.0,
+    }
+  }
+}
+
+/// How a level reads the rows of its table.
+#[derive(Debug, Clone)]
+pub(super) enum Access<'a> {
+  /// Every row, in rowid or PRIMARY KEY order.
+  Scan,
+  /// The row with a rowid, or the rows whose rowid is within bounds.
+  Rowid(KeyRange),
+  /// The rows of a WITHOUT ROWID table within a range of its PRIMARY KEY.
+  PrimaryKey(KeyRange),
+  /// The rows an index points to within a range of its keys, or all of
+  /// them for an empty range. Those of a covering index are read from its
+  /// entries alone.
+  Index {
+    index: &'a IndexSchema,
+    range: KeyRange,
+    is_covering: bool,
+  },
+}
+
+impl Access<'_> {
+  /// The step of a query plan reading the table by this name, in SQLite's
+  /// words: `SCAN t`, `SEARCH t USING INDEX i (a=? AND b>?)` and so on.
+  pub(super) fn explain(&self, name: &str, table: &TableSchema) -> String {
+    let (way, range) = match self {
+      Self::Rowid(range) if !range.is_empty() => ("INTEGER PRIMARY KEY".to_string(), range),
+      Self::PrimaryKey(range) if !range.is_empty() => ("PRIMARY KEY".to_string(), range),
+      Self::Index {
+        index,
+        range,
+        is_covering,
+      } => {
+        let covering = if *is_covering { "COVERING " } else { "" };
+        (format!("{covering}INDEX {}", index.name()), range)
+      }
+      _ => return format!("SCAN {name}"),
+    };
+    if range.is_empty() {
+      return format!("SCAN {name} USING {way}");
+    }
+    let column = |term: &KeyTerm| match table.columns().get(term.column) {
+      Some(column) => column.name().to_string(),
+      None => "rowid".to_string(),
+    };
+    let terms: Vec<String> = range
+      .equal
+      .iter()
+      .map(|term| format!("{}=?", column(term)))
+      .chain(
+        range
+          .lower
+          .iter()
+          .map(|bound| format!("{}>?", column(&bound.term))),
+      )
+      .chain(
+        range
+          .upper
+          .iter()
+          .map(|bound| format!("{}<?", column(&bound.term))),
+      )
+      .collect();
+    format!("SEARCH {name} USING {way} ({})", terms.join(" AND "))
+  }
+}
+
+/// A column the rows of a way come out sorted by.
+struct Sorted {
+  column: usize,
+  descending: bool,
+  collation: String,
+}
+
+/// A way of reading a level, and what it costs.
+struct Way<'a> {
+  access: Access<'a>,
+  cost: f64,
+  /// The rows it is expected to return.
+  rows: f64,
+  /// The columns its rows are sorted by, after the `equal` columns.
+  order: Vec<Sorted>,
+  /// Whether it returns a single row at most.
+  is_single: bool,
+}
+
+impl Way<'_> {
+  /// Whether its rows may be read from the last to the first. Those sought
+  /// for each value of an IN list come in the order of the values.
+  fn is_reversible(&self) -> bool {
+    let range = match &self.access {
+      Access::Scan => return !self.is_single,
+      Access::Rowid(range) | Access::PrimaryKey(range) | Access::Index { range, .. } => range,
+    };
+    !self.is_single && range.seeks() == 1
+  }
+}
+
+/// A way picked to read a level, given the levels read before it.
+#[derive(Debug, Clone)]
+pub(super) struct Choice<'a> {
+  pub(super) access: Access<'a>,
+  /// Whether its rows are read from the last to the first.
+  pub(super) is_backward: bool,
+  /// Whether its rows come out in the order of ORDER BY.
+  pub(super) is_ordered: bool,
+  pub(super) cost: f64,
+  /// The rows it is expected to return each time the level starts.
+  pub(super) rows: f64,
+}
+
+impl Choice<'_> {
+  /// A full scan of rows that are not in a b-tree, whose cost is unknown.
+  pub(super) fn scan() -> Self {
+    Self {
+      access: Access::Scan,
+      is_backward: false,
+      is_ordered: false,
+      cost: 1.0,
+      rows: 1.0,
+    }
+  }
+}
+
+impl<'a> Level<'a> {
+  /// Picks the cheapest way to read the rows of the level's table, once the
+  /// levels whose sources are `known` have a row, and when `order_by` is
+  /// given, the cheapest whose rows come out in its order, if that is
+  /// another one. `needed` holds whether the query reads each column of the
+  /// table, and then its rowid, or is `None` if it may read any.
+  pub(super) fn pick_access(
+    &self,
+    runtime: &'a SqliteRuntime,
+    scope: &Scope,
+    known: &[bool],
+    table: &'a TableSchema,
+    needed: Option<&[bool]>,
+    order_by: Option<&[OrderingTerm]>,
+  ) -> SqliteResult<Vec<Choice<'a>>> {
+    let terms = self.key_terms(scope, known, table);
+    let rows = estimate_rows(runtime, table)?;
+    let seek = rows.log2().max(1.0);
+    let rowid = table.columns().len();
+    let column_collation = |column: usize| -> String {
+      table
+        .columns()
+        .get(column)
+        .and_then(|column| column.collation())
+        .unwrap_or("BINARY")
+        .into()
+    };
+    let stats = runtime.sqlite_stat1();
+    let mut ways = vec![];
+
+    let key_order: Vec<Sorted> = match table.is_without_rowid() {
+      true => table
+        .primary_key()
+        .into_iter()
+        .map(|(column, order)| Sorted {
+          column,
+          descending: order == SortOrder::Desc,
+          collation: column_collation(column),
+        })
+        .collect(),
+      false => vec![Sorted {
+        column: rowid,
+        descending: false,
+        collation: "BINARY".into(),
+      }],
+    };
+    ways.push(Way {
+      access: Access::Scan,
+      cost: rows * ROW_COST,
+      rows,
+      order: key_order,
+      is_single: false,
+    });
+
+    if !table.is_without_rowid() {
+      let range = key_range(&terms, &[(rowid, None)]);
+      if !range.is_empty() {
+        let seeks = range.seeks() as f64;
+        let is_single = !range.equal.is_empty() && seeks == 1.0;
+        let out = match range.equal.is_empty() {
+          false => seeks,
+          true => (rows * range.selectivity()).max(1.0),
+        };
+        ways.push(Way {
+          access: Access::Rowid(range),
+          cost: seek * seeks + out * ROW_COST,
+          rows: out,
+          order: vec![Sorted {
+            column: rowid,
+            descending: false,
+            collation: "BINARY".into(),
+          }],
+          is_single,
+        });
+      }
+    } else {
+      let primary_key = table.primary_key();
+      let key: Vec<(usize, Option<String>)> = primary_key
+        .iter()
+        .map(|(column, _)| (*column, Some(column_collation(*column))))
+        .collect();
+      let range = key_range(&terms, &key);
+      if !range.is_empty() {
+        let seeks = range.seeks() as f64;
+        let is_unique = range.equal.len() == primary_key.len();
+        let out = match is_unique {
+          true => seeks,
+          false => {
+            seeks
+              * rows_per_key(
+                rows,
+                range.equal.len(),
+                stats.index(table.name(), table.name()),
+              )
+              * range.selectivity()
+          }
+        };
+        let order = primary_key[range.equal.len()..]
+          .iter()
+          .map(|(column, order)| Sorted {
+            column: *column,
+            descending: *order == SortOrder::Desc,
+            collation: column_collation(*column),
+          })
+          .collect();
+        ways.push(Way {
+          access: Access::PrimaryKey(range),
+          cost: seek * seeks + out.max(1.0) * ROW_COST,
+          rows: out.max(1.0),
+          order,
+          is_single: is_unique && seeks == 1.0,
+        });
+      }
+    }
+
+    let indexes = runtime.schema().index_list(table.name());
+    for index in indexes {
+      // The PRIMARY KEY of a WITHOUT ROWID table is the table itself.
+      if index.is_partial() || index.root_page() == table.root_page() {
+        continue;
+      }
+      let mut key = vec![];
+      for index_key in index.columns() {
+        let Some((column, _)) = index_key.column_name().and_then(|name| table.column(name)) else {
+          break;
+        };
+        let column = match table.rowid_alias() == Some(column) {
+          true => rowid,
+          false => column,
+        };
+        let collation = index_key
+          .collation()
+          .map_or_else(|| column_collation(column), String::from);
+        let descending = index_key.order() == Some(SortOrder::Desc);
+        key.push((column, collation, descending));
+      }
+      // SQLite does not use an index whose collation is not registered.
+      if key
+        .iter()
+        .any(|(_, collation, _)| runtime.collation(collation).is_err())
+      {
+        continue;
+      }
+      let columns = entry_columns(table, index);
+      let is_covering = needed.is_some_and(|needed| {
+        needed.iter().enumerate().all(|(column, &is_needed)| {
+          let is_held = |column| columns.contains(&Some(column));
+          !is_needed || is_held(column) || (table.rowid_alias() == Some(column) && is_held(rowid))
+        })
+      });
+      let range = key_range(
+        &terms,
+        &key
+          .iter()
+          .map(|(column, collation, _)| (*column, Some(collation.clone())))
+          .collect::<Vec<_>>(),
+      );
+      let equal = range.equal.len();
+      let seeks = range.seeks() as f64;
+      let is_unique = index.is_unique() && equal == index.columns().len();
+      let out = match is_unique {
+        true => seeks,
+        false => (seeks
+          * rows_per_key(rows, equal, stats.index(table.name(), index.name()))
+          * range.selectivity())
+        .max(1.0),
+      };
+      let entry_cost = 1.0 + (ROW_COST - 1.0) * columns.len() as f64 / (rowid + 1) as f64;
+      let fetch_cost = match is_covering {
+        true => 0.0,
+        false => ROW_COST,
+      };
+      let mut order: Vec<Sorted> = key
+        .iter()
+        .skip(equal)
+        .map(|(column, collation, descending)| Sorted {
+          column: *column,
+          descending: *descending,
+          collation: collation.clone(),
+        })
+        .collect();
+      if key.len() == index.columns().len() && !table.is_without_rowid() {
+        order.push(Sorted {
+          column: rowid,
+          descending: false,
+          collation: "BINARY".into(),
+        });
+      }
+      ways.push(Way {
+        cost: match range.is_empty() {
+          true => 0.0,
+          false => seek * seeks,
+        } + out * (entry_cost + fetch_cost),
+        access: Access::Index {
+          index,
+          range,
+          is_covering,
+        },
+        rows: out,
+        order,
+        is_single: is_unique && seeks == 1.0,
+      });
+    }
+
+    let mut choices: Vec<Choice> = ways
+      .into_iter()
+      .map(|way| {
+        let is_sorted = |is_backward| {
+          order_by
+            .is_some_and(|order_by| self.is_sorted_by(scope, table, order_by, &way, is_backward))
+        };
+        let is_forward = is_sorted(false);
+        let is_backward = !is_forward && way.is_reversible() && is_sorted(true);
+        Choice {
+          is_backward,
+          is_ordered: is_forward || is_backward,
+          cost: way.cost,
+          rows: way.rows,
+          access: way.access,
+        }
+      })
+      .collect();
+    choices.sort_by(|left, right| left.cost.total_cmp(&right.cost));
+    let mut picked: Vec<Choice> = vec![];
+    for choice in choices {
+      if picked.is_empty() || (choice.is_ordered && !picked.iter().any(|picked| picked.is_ordered))
+      {
+        picked.push(choice);
+      }
+    }
+    Ok(picked)
+  }
+
+  /// Whether the rows of a way come out in the order of ORDER BY terms,
+  /// which must all be columns of the level's table, when read forward or
+  /// `is_backward`. Columns equal to a single value may be skipped, and only
+  /// the default place of NULLs is supported.
+  fn is_sorted_by(
+    &self,
+    scope: &Scope,
+    table: &TableSchema,
+    order_by: &[OrderingTerm],
+    way: &Way,
+    is_backward: bool,
+  ) -> bool {
+    let range = match &way.access {
+      Access::Scan => None,
+      Access::Rowid(range) | Access::PrimaryKey(range) | Access::Index { range, .. } => Some(range),
+    };
+    // The rows sought by each value of an IN list come one list after the
+    // other.
+    if range.is_some_and(|range| range.seeks() > 1) {
+      return false;
+    }
+    let equal: Vec<usize> = range
+      .map(|range| range.equal.iter().map(|term| term.column).collect())
+      .unwrap_or_default();
+    let mut next = way.order.iter();
+    for term in order_by {
+      let Some(column) = self.term_column(scope, table, &term.expr) else {
+        return false;
+      };
+      if way.is_single {
+        continue;
+      }
+      let descending = term.order == Some(ast::SortOrder::Desc);
+      let nulls_first = term
+        .nulls
+        .map_or(!descending, |nulls| nulls == NullsOrder::First);
+      if nulls_first == descending {
+        return false;
+      }
+      if equal.contains(&column) {
+        continue;
+      }
+      let collation = scope.collation_name(&term.expr);
+      let is_sorted = next.next().is_some_and(|sorted| {
+        sorted.column == column
+          && (sorted.descending != is_backward) == descending
+          && sorted
+            .collation
+            .eq_ignore_ascii_case(collation.as_deref().unwrap_or("BINARY"))
+      });
+      if !is_sorted {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// The column of the level's table an expression is, with the rowid past
+  /// the last column, if it is one.
+  fn term_column(&self, scope: &Scope, table: &TableSchema, expr: &Expr) -> Option<usize> {
+    let mut expr = expr;
+    while let Expr::Collate { expr: inner, .. } = expr {
+      expr = inner;
+    }
+    let Expr::Column {
+      table: name,
+      name: column_name,
+      ..
+    } = expr
+    else {
+      return None;
+    };
+    let (position, ..) = scope.find(name.as_deref(), column_name).ok()??;
+    // An unqualified name of a USING column may read another column.
+    let is_coalesced = name.is_none() && scope.coalesced.iter().any(|(left, _)| *left == position);
+    if is_coalesced || scope.source_at(position) != self.source {
+      return None;
+    }
+    let column = position - self.offset;
+    Some(match table.rowid_alias() == Some(column) {
+      true => table.columns().len(),
+      false => column,
+    })
+  }
+
+  /// The comparisons of the level's conditions between a column of its
+  /// table and a value known once the levels of the `known` sources have a
+  /// row, with the rowid alias read as the rowid. `x IN (...)` compares with
+  /// each value of its list, or of its subquery.
+  fn key_terms(
+    &self,
+    scope: &Scope,
+    known: &[bool],
+    table: &TableSchema,
+  ) -> Vec<(KeyTerm, Comparison)> {
+    let is_known_at = |position: usize| known.get(scope.source_at(position)) == Some(&true);
+    let mut terms = vec![];
+    for condition in &self.on {
+      match condition {
+        Condition::Equal(left, right) => {
+          // The comparison has the collation of the left column either way.
+          let (column, value) = match scope.source_at(*right) == self.source {
+            true => (*right, *left),
+            false => (*left, *right),
+          };
+          if scope.source_at(column) != self.source || !is_known_at(value) {
+            continue;
+          }
+          if let Some(affinity) = key_affinity(scope.affinity_at(column), scope.affinity_at(value))
+          {
+            terms.push((
+              KeyTerm {
+                column: column - self.offset,
+                value: Operand::Position(value),
+                affinity,
+                collation: scope.collation_at(*left),
+              },
+              Comparison::Eq,
+            ));
+          }
+        }
+        Condition::Expr(Expr::Binary {
+          left,
+          operator,
+          right,
+        }) => {
+          let Some(comparison) = Comparison::of(*operator) else {
+            continue;
+          };
+          let collation = comparison_collation_name(
+            left,
+            right,
+            &Names {
+              scope,
+              aliases: false,
+            },
+          );
+          let term = self
+            .key_term(scope, known, left, right, &collation)
+            .map(|term| (term, comparison))
+            .or_else(|| {
+              self
+                .key_term(scope, known, right, left, &collation)
+                .map(|term| (term, comparison.flip()))
+            });
+          terms.extend(term);
+        }
+        Condition::Expr(Expr::Between {
+          expr,
+          not: false,
+          low,
+          high,
+        }) => {
+          let env = Names {
+            scope,
+            aliases: false,
+          };
+          for (bound, comparison) in [(low, Comparison::Ge), (high, Comparison::Le)] {
+            let collation = comparison_collation_name(expr, bound, &env);
+            if let Some(term) = self.key_term(scope, known, expr, bound, &collation) {
+              terms.push((term, comparison));
+            }
+          }
+        }
+        Condition::Expr(Expr::InList {
+          expr,
+          not: false,
+          list,
+        }) => {
+          let env = Names {
+            scope,
+            aliases: false,
+          };
+          // Every value is compared with the collation of the first, and
+          // with no affinity of its own.
+          let Some(first) = list.first() else {
+            continue;
+          };
+          let collation = comparison_collation_name(expr, first, &env);
+          let is_usable = list.iter().all(|item| {
+            comparison_collation_name(expr, item, &env) == collation && is_known(scope, item, known)
+          });
+          let Some(position) = self.key_column(scope, expr).filter(|_| is_usable) else {
+            continue;
+          };
+          if let Some(affinity) = key_affinity(scope.affinity_at(position), Affinity::Blob) {
+            terms.push((
+              KeyTerm {
+                column: position - self.offset,
+                value: Operand::List(list.clone()),
+                affinity,
+                collation,
+              },
+              Comparison::Eq,
+            ));
+          }
+        }
+        // The values of a subquery that does not depend on the row are
+        // compared with the collation and affinity of the column.
+        Condition::Expr(Expr::InSelect {
+          expr,
+          not: false,
+          select,
+        }) => {
+          let Some(position) = self.key_column(scope, expr) else {
+            continue;
+          };
+          let Some(value) = list_affinity(scope, select) else {
+            continue;
+          };
+          if let Some(affinity) = key_affinity(scope.affinity_at(position), value) {
+            terms.push((
+              KeyTerm {
+                column: position - self.offset,
+                value: Operand::Select(select.clone()),
+                affinity,
+                collation: scope.collation_at(position),
+              },
+              Comparison::Eq,
+            ));
+          }
+        }
+        Condition::Expr(_) => {}
+      }
+    }
+    let rowid = table.columns().len();
+    for (term, _) in &mut terms {
+      if table.rowid_alias() == Some(term.column) {
+        term.column = rowid;
+      }
+    }
+    terms
+  }
+
+  /// The key term of a comparison, if `column` is a column of the level's
+  /// table and `value` is known once the levels of the `known` sources have
+  /// a row.
+  fn key_term(
+    &self,
+    scope: &Scope,
+    known: &[bool],
+    column: &Expr,
+    value: &Expr,
+    collation: &str,
+  ) -> Option<KeyTerm> {
+    let position = self.key_column(scope, column)?;
+    if !is_known(scope, value, known) {
+      return None;
+    }
+    let env = Names {
+      scope,
+      aliases: false,
+    };
+    let affinity = key_affinity(scope.affinity_at(position), expr_affinity(value, &env))?;
+    Some(KeyTerm {
+      column: position - self.offset,
+      value: Operand::Expr(value.clone()),
+      affinity,
+      collation: collation.into(),
+    })
+  }
+
+  /// The position in the row of an expression that is a column of the
+  /// level's table.
+  fn key_column(&self, scope: &Scope, column: &Expr) -> Option<usize> {
+    let Expr::Column { table, name, .. } = column else {
+      return None;
+    };
+    let (position, ..) = scope.resolve(table.as_deref(), name).ok()?;
+    // An unqualified name of a USING column may read another column.
+    let is_coalesced = table.is_none() && scope.coalesced.iter().any(|(left, _)| *left == position);
+    (!is_coalesced && scope.source_at(position) == self.source).then_some(position)
+  }
+}
+
+/// The terms that constrain the leading columns of a key, given the column
+/// and, unless any will do, the collation of each.
+fn key_range(terms: &[(KeyTerm, Comparison)], key: &[(usize, Option<String>)]) -> KeyRange {
+  let mut range = KeyRange::default();
+  for (column, collation) in key {
+    let usable = |term: &KeyTerm| {
+      term.column == *column
+        && collation.as_deref().map_or(true, |collation| {
+          term.collation.eq_ignore_ascii_case(collation)
+        })
+    };
+    let find = |comparisons: &[Comparison]| {
+      terms
+        .iter()
+        .find(|(term, comparison)| comparisons.contains(comparison) && usable(term))
+    };
+    if let Some((term, _)) = find(&[Comparison::Eq]) {
+      range.equal.push(term.clone());
+      continue;
+    }
+    let bound = |(term, comparison): &(KeyTerm, Comparison)| Bound {
+      term: term.clone(),
+      is_inclusive: matches!(comparison, Comparison::Le | Comparison::Ge),
+    };
+    range.lower = find(&[Comparison::Gt, Comparison::Ge]).map(bound);
+    range.upper = find(&[Comparison::Lt, Comparison::Le]).map(bound);
+    break;
+  }
+  range
+}
+
+/// The column each value of an index entry holds: the key columns, `None`
+/// for an expression, then the rowid past the last column, or the PRIMARY
+/// KEY columns of a WITHOUT ROWID table the key does not already hold.
+pub(crate) fn entry_columns(table: &TableSchema, index: &IndexSchema) -> Vec<Option<usize>> {
+  let collation = |column: usize| {
+    table
+      .columns()
+      .get(column)
+      .and_then(|column| column.collation())
+      .unwrap_or("BINARY")
+  };
+  let keys: Vec<(Option<usize>, &str)> = index
+    .columns()
+    .iter()
+    .map(|key| {
+      let column = key
+        .column_name()
+        .and_then(|name| table.column(name))
+        .map(|(column, _)| column);
+      let key_collation = key
+        .collation()
+        .or_else(|| column.map(collation))
+        .unwrap_or("BINARY");
+      (column, key_collation)
+    })
+    .collect();
+  let mut columns: Vec<Option<usize>> = keys.iter().map(|(column, _)| *column).collect();
+  if !table.is_without_rowid() {
+    columns.push(Some(table.columns().len()));
+    return columns;
+  }
+  for (column, _) in table.primary_key() {
+    let is_held = keys.iter().any(|(key, key_collation)| {
+      *key == Some(column) && key_collation.eq_ignore_ascii_case(collation(column))
+    });
+    if !is_held {
+      columns.push(Some(column));
+    }
+  }
+  columns
+}
+
+/// The number of rows of a table: as counted by ANALYZE, or estimated from
+/// its b-tree.
+fn estimate_rows(runtime: &SqliteRuntime, table: &TableSchema) -> SqliteResult<f64> {
+  let rows = match runtime.sqlite_stat1().table_rows(table.name()) {
+    Some(rows) => rows,
+    None => BtreeCursor::new(runtime.pager(), table.root_page()).estimate_entries()?,
+  };
+  Ok((rows as f64).max(1.0))
+}
+
+/// The rows expected to share the values of the first `columns` columns of
+/// an index, from its statistics if any.
+fn rows_per_key(rows: f64, columns: usize, stats: Option<&[u64]>) -> f64 {
+  if columns == 0 {
+    return rows;
+  }
+  let guess = match stats.and_then(|stats| stats.get(columns)) {
+    Some(&average) => average as f64,
+    None => DEFAULT_ROWS_PER_KEY
+      .get(columns - 1)
+      .copied()
+      .unwrap_or(5.0),
+  };
+  guess.min(rows)
+}
+
+/// The affinity a comparison applies to the value compared with a column,
+/// or `None` when it would apply one to the column instead, so that stored
+/// values could match without being equal to the value.
+fn key_affinity(column: Affinity, value: Affinity) -> Option<Option<Affinity>> {
+  let is_numeric = |affinity| {
+    matches!(
+      affinity,
+      Affinity::Integer | Affinity::Real | Affinity::Numeric
+    )
+  };
+  match (column, value) {
+    (column, value) if is_numeric(column) && !is_numeric(value) => Some(Some(Affinity::Numeric)),
+    (column, _) if is_numeric(column) => Some(None),
+    (_, value) if is_numeric(value) => None,
+    (Affinity::Text, Affinity::Blob) => Some(Some(Affinity::Text)),
+    (Affinity::Blob, Affinity::Text) => None,
+    _ => Some(None),
+  }
+}
@@ -0,0 +1,1732 @@
+//! Tests for query execution
+//!
+//! To run: `cargo test runtime::query`
+
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread,
+  time::Duration,
+};
+
+use crate::{value::Value, SqliteConnection};
+
+const FLIGHTS_DB: &str = "sqlite://./data/flights-populated.db";
+const ADDED_COLUMNS_DB: &str = "sqlite://./data/added-columns.db";
+const JOINS_DB: &str = "sqlite://./data/joins.db";
+const COLLATIONS_DB: &str = "sqlite://./data/collations.db";
+const PLANNER_DB: &str = "sqlite://./data/planner.db";
+
+#[test]
+fn ok_on_select_with_where_and_limit() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+
+  let result = conn
+    .query("SELECT Year, passengers+1 FROM Observation WHERE month_id = 3 LIMIT 2 OFFSET 1")
+    .unwrap();
+  assert_eq!(result.columns(), ["year", "passengers+1"]);
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from(1950), Value::from(136)],
+      [Value::from(1951), Value::from(164)],
+    ]
+  );
+
+  let result = conn
+    .query("SELECT * FROM Observation WHERE passengers > 400 AND year >= 1955")
+    .unwrap();
+  assert_eq!(result.rows().len(), 28);
+
+  let result = conn
+    .query("SELECT rowid, m.* FROM Month AS m WHERE month_id >= 9 LIMIT 1, -1")
+    .unwrap();
+  assert_eq!(result.columns(), ["rowid", "month_id", "month"]);
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from(11), Value::from(10), Value::from("November")],
+      [Value::from(12), Value::from(11), Value::from("December")],
+    ]
+  );
+}
+
+#[test]
+fn ok_on_columns_added_after_rows() {
+  let conn = SqliteConnection::open(ADDED_COLUMNS_DB).unwrap();
+
+  let result = conn
+    .query("SELECT id AS n, NAME, age + 1, -score FROM person WHERE note IS NULL")
+    .unwrap();
+  assert_eq!(result.columns(), ["n", "name", "age + 1", "-score"]);
+  assert_eq!(result.rows().len(), 3);
+  assert_eq!(
+    result.rows()[0],
+    [
+      Value::from(1),
+      Value::from("ana"),
+      Value::from(31),
+      Value::from(1.5)
+    ]
+  );
+
+  let result = conn.query("SELECT * FROM person WHERE id = 4").unwrap();
+  assert_eq!(
+    result.rows(),
+    [[
+      Value::from(4),
+      Value::from("dora"),
+      Value::from(41),
+      Value::from("Porto"),
+      Value::from(9.5),
+      Value::from("new"),
+    ]]
+  );
+}
+
+#[test]
+fn ok_on_generated_columns() {
+  let conn = SqliteConnection::open("sqlite://./data/without-rowid.db").unwrap();
+  let result = conn.query("SELECT * FROM generated").unwrap();
+  assert_eq!(
+    result.rows(),
+    [[
+      Value::from(7),
+      Value::from(5),
+      Value::from(10),
+      Value::from(15),
+      Value::from("w")
+    ]]
+  );
+  // A VIRTUAL column is computed from the row before it is filtered on.
+  let result = conn
+    .query("SELECT id, typeof(y) FROM generated WHERE y = 10")
+    .unwrap();
+  assert_eq!(result.rows(), [[Value::from(7), Value::from("integer")]]);
+}
+
+#[test]
+fn ok_on_schema_table() {
+  let conn = SqliteConnection::open("sqlite://./data/pragmas.db").unwrap();
+  let result = conn
+    .query("SELECT rootpage, name FROM sqlite_master WHERE type = 'index' ORDER BY 1")
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from(3), Value::from("sqlite_autoindex_parent_1")],
+      [Value::from(6), Value::from("pair_d")],
+      [Value::from(7), Value::from("child_expr")],
+    ]
+  );
+  let result = conn
+    .query(
+      "SELECT s.name, count(*) FROM sqlite_schema s, pragma_table_info(s.name) c
+       WHERE s.type = 'table' GROUP BY 1 ORDER BY 1",
+    )
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from("child"), Value::from(4)],
+      [Value::from("pair"), Value::from(3)],
+      [Value::from("parent"), Value::from(3)],
+    ]
+  );
+}
+
+#[test]
+fn ok_on_select_without_from() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+  let result = conn
+    .query("SELECT 1 + 2 * 3, 7 / 2, 7 % 0, NULL OR 1;")
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [[Value::from(7), Value::from(3), Value::Null, Value::from(1)]]
+  );
+}
+
+#[test]
+fn err_on_unknown_names() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(error("SELECT * FROM nowhere"), "no such table: nowhere");
+  assert_eq!(
+    error("SELECT yaer FROM Observation WHERE 0"),
+    "no such column: yaer"
+  );
+  assert_eq!(
+    error("SELECT month FROM Month WHERE o.month_id = 1"),
+    "no such column: o.month_id"
+  );
+  assert_eq!(error("SELECT *"), "no tables specified");
+  assert_eq!(error("SELECT * FROM Month LIMIT 'a'"), "datatype mismatch");
+}
+
+#[test]
+fn ok_on_aggregate_queries() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+
+  // The bare `year` comes from the row holding the maximum.
+  let result = conn
+    .query(
+      "SELECT month_id, max(passengers), year FROM Observation \
+       GROUP BY month_id HAVING max(passengers) > 600",
+    )
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from(6), Value::from(622), Value::from(1960)],
+      [Value::from(7), Value::from(606), Value::from(1960)],
+    ]
+  );
+
+  let result = conn
+    .query(
+      "SELECT year / 5 * 5 AS lustrum, count(*), min(passengers), avg(passengers) \
+       FROM Observation WHERE year < 1960 GROUP BY lustrum",
+    )
+    .unwrap();
+  let rows: Vec<String> = result
+    .rows()
+    .iter()
+    .map(|row| {
+      row
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join("|")
+    })
+    .collect();
+  assert_eq!(
+    rows,
+    [
+      "1945|12|104|126.666666666667",
+      "1950|60|114|194.15",
+      "1955|60|233|358.0"
+    ]
+  );
+
+  let result = conn
+    .query(
+      "SELECT count(DISTINCT year), sum(passengers) FILTER (WHERE month_id = 0), total(year) \
+       FROM Observation",
+    )
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [[Value::from(14), Value::from(2901), Value::from(42668495.0)]]
+  );
+
+  let result = conn
+    .query("SELECT group_concat(month, '|' ORDER BY month_id DESC) FROM Month WHERE month_id < 3")
+    .unwrap();
+  assert_eq!(result.rows(), [[Value::from("March|February|January")]]);
+
+  // Without GROUP BY there is a row even when nothing matches.
+  let result = conn
+    .query("SELECT count(*), sum(passengers), group_concat(year), year FROM Observation WHERE 0")
+    .unwrap();
+  assert_eq!(
+    result.rows(),
+    [[Value::from(0), Value::Null, Value::Null, Value::Null]]
+  );
+  let result = conn
+    .query("SELECT count(*) FROM Observation WHERE 0 GROUP BY year")
+    .unwrap();
+  assert!(result.rows().is_empty());
+}
+
+#[test]
+fn ok_on_sorted_and_distinct_queries() {
+  let mut conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+
+  let result = conn
+    .query("SELECT DISTINCT year FROM Observation WHERE year > 1958 ORDER BY 1 DESC")
+    .unwrap();
+  assert_eq!(
+    result.into_rows(),
+    [
+      [Value::from(1962)],
+      [Value::from(1961)],
+      [Value::from(1960)],
+      [Value::from(1959)]
+    ]
+  );
+
+  let result = conn
+    .query("SELECT DISTINCT month_id % 3 FROM Observation")
+    .unwrap();
+  assert_eq!(
+    result.into_rows(),
+    [[Value::from(0)], [Value::from(1)], [Value::from(2)]]
+  );
+
+  let result = conn
+    .query(
+      "SELECT month_id AS m, max(passengers) FROM Observation GROUP BY m \
+       ORDER BY 2 DESC LIMIT 2 OFFSET 1",
+    )
+    .unwrap();
+  assert_eq!(
+    result.into_rows(),
+    [
+      [Value::from(7), Value::from(606)],
+      [Value::from(5), Value::from(535)]
+    ]
+  );
+
+  // Sorting past a tiny budget spills every few rows to temporary files.
+  let sql = "SELECT passengers, year, month_id FROM Observation \
+             ORDER BY passengers DESC, year, month_id";
+  let in_memory = conn.query(sql).unwrap();
+  conn.set_sort_memory_budget(4096);
+  let spilled = conn.query(sql).unwrap();
+  assert_eq!(spilled, in_memory);
+  assert_eq!(in_memory.rows().len(), 21748);
+  assert!(in_memory
+    .rows()
+    .windows(2)
+    .all(|rows| rows[0][0].compare(&rows[1][0]).is_ge()));
+
+  let conn = SqliteConnection::open(ADDED_COLUMNS_DB).unwrap();
+  let result = conn
+    .query("SELECT name FROM person ORDER BY city COLLATE NOCASE DESC NULLS FIRST, score")
+    .unwrap();
+  assert_eq!(
+    result.into_rows(),
+    [
+      [Value::from("dora")],
+      [Value::from("ana")],
+      [Value::from("bruno")],
+      [Value::from("carla")]
+    ]
+  );
+}
+
+#[test]
+fn ok_on_joins() {
+  let mut conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let names = |conn: &mut SqliteConnection, sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  // Managers are looked up by rowid, and their departments too.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT e.name, m.name, d.name FROM emp e \
+       LEFT JOIN emp m ON m.id = e.manager_id \
+       LEFT JOIN dept d ON d.id = m.dept_id ORDER BY e.id"
+    ),
+    [
+      ["ana", "NULL", "NULL"],
+      ["bruno", "ana", "Engineering"],
+      ["carla", "ana", "Engineering"],
+      ["dora", "carla", "Sales"],
+      ["eva", "carla", "Sales"],
+      ["filipe", "carla", "Sales"],
+    ]
+  );
+
+  // Departments without employees come last, after the loops.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT d.name, e.name FROM emp e FULL JOIN dept d ON e.dept_id = d.id"
+    ),
+    [
+      ["Engineering", "ana"],
+      ["Engineering", "bruno"],
+      ["Sales", "carla"],
+      ["NULL", "dora"],
+      ["NULL", "eva"],
+      ["Sales", "filipe"],
+      ["Legal", "NULL"],
+      ["Support", "NULL"],
+    ]
+  );
+
+  // Assignments are looked up through an index, and projects by their
+  // PRIMARY KEY.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT p.title, e.name, a.hours FROM project p \
+       JOIN assignment a ON a.project_code = p.code \
+       JOIN emp e ON e.id = a.emp_id WHERE a.hours > 3 ORDER BY 1, 2"
+    ),
+    [
+      ["Apollo", "ana", "10.5"],
+      ["Apollo", "bruno", "4.0"],
+      ["Hermes", "carla", "8.0"],
+    ]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT count(*) FROM assignment a JOIN project p ON p.code = a.project_code"
+    ),
+    [["5"]]
+  );
+
+  let result = conn
+    .query("SELECT * FROM emp JOIN dept USING (id, city)")
+    .unwrap();
+  assert_eq!(
+    result.columns(),
+    ["id", "name", "dept_id", "manager_id", "city", "name"]
+  );
+  assert_eq!(result.rows().len(), 2);
+  let result = conn.query("SELECT * FROM emp NATURAL JOIN dept").unwrap();
+  assert_eq!(
+    result.columns(),
+    ["id", "name", "dept_id", "manager_id", "city"]
+  );
+  assert_eq!(result.rows().len(), 0);
+}
+
+#[test]
+fn err_on_bad_joins() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT * FROM emp JOIN dept USING (dept_id)"),
+    "cannot join using column dept_id - column not present in both tables"
+  );
+  assert_eq!(
+    error("SELECT * FROM emp NATURAL JOIN dept ON 1"),
+    "a NATURAL join may not have an ON or USING clause"
+  );
+  assert_eq!(
+    error("SELECT * FROM emp e LEFT JOIN dept d ON d.id = p.lead_id JOIN project p"),
+    "ON clause references tables to its right"
+  );
+  assert_eq!(
+    error("SELECT name FROM emp, dept"),
+    "ambiguous column name: name"
+  );
+}
+
+#[test]
+fn err_on_misused_aggregates() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT year FROM Observation WHERE count(*) > 1"),
+    "misuse of aggregate function count()"
+  );
+  assert_eq!(
+    error("SELECT count(*) AS n FROM Observation WHERE n > 1"),
+    "misuse of aggregate: count()"
+  );
+  assert_eq!(
+    error("SELECT count(max(year)) FROM Observation"),
+    "misuse of aggregate function max()"
+  );
+  assert_eq!(
+    error("SELECT year FROM Observation GROUP BY sum(year)"),
+    "aggregate functions are not allowed in the GROUP BY clause"
+  );
+  assert_eq!(
+    error("SELECT year FROM Observation GROUP BY 2"),
+    "1st GROUP BY term out of range - should be between 1 and 1"
+  );
+  assert_eq!(
+    error("SELECT year FROM Observation HAVING year > 1"),
+    "HAVING clause on a non-aggregate query"
+  );
+  assert_eq!(
+    error("SELECT group_concat(DISTINCT year, ',') FROM Observation"),
+    "DISTINCT aggregates must have exactly one argument"
+  );
+  assert_eq!(
+    error("SELECT year FROM Observation ORDER BY count(*)"),
+    "misuse of aggregate: count()"
+  );
+  assert_eq!(
+    error("SELECT year FROM Observation ORDER BY 2"),
+    "1st ORDER BY term out of range - should be between 1 and 1"
+  );
+}
+
+#[test]
+fn ok_on_subqueries() {
+  let mut conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let names = |conn: &mut SqliteConnection, sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT name, (SELECT name FROM dept WHERE dept.id = emp.dept_id) FROM emp ORDER BY id"
+    ),
+    [
+      ["ana", "Engineering"],
+      ["bruno", "Engineering"],
+      ["carla", "Sales"],
+      ["dora", "NULL"],
+      ["eva", "NULL"],
+      ["filipe", "Sales"],
+    ]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT name FROM emp e WHERE EXISTS (SELECT 1 FROM emp r WHERE r.manager_id = e.id) \
+       ORDER BY 1"
+    ),
+    [["ana"], ["carla"]]
+  );
+  // NULL NOT IN a list without NULL is NULL, so dora is left out.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT name FROM emp WHERE dept_id NOT IN (SELECT id FROM dept WHERE city = 'Lisbon') \
+       ORDER BY 1"
+    ),
+    [["carla"], ["eva"], ["filipe"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT '1' IN (SELECT id FROM emp), 1 IN (SELECT '1'), NULL IN (SELECT 1 WHERE 0)"
+    ),
+    [["1", "0", "0"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT e.name, t.n FROM emp e \
+       JOIN (SELECT manager_id, count(*) AS n FROM emp GROUP BY 1) t ON t.manager_id = e.id \
+       ORDER BY 1"
+    ),
+    [["ana", "2"], ["carla", "3"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT * FROM (VALUES (3, 'c'), (1, 'a')) ORDER BY 1"
+    ),
+    [["1", "a"], ["3", "c"]]
+  );
+}
+
+#[test]
+fn ok_on_truth_and_row_values() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let row = |sql: &str| -> Vec<String> {
+    let result = conn.query(sql).unwrap();
+    result.rows()[0].iter().map(ToString::to_string).collect()
+  };
+
+  // TRUE and FALSE are 1 and 0, and `x IS TRUE` is the truth of `x`.
+  assert_eq!(
+    row("SELECT true, FALSE + 1, 2 IS TRUE, 2 IS 1, NULL IS NOT FALSE, 'abc' IS FALSE"),
+    ["1", "1", "1", "0", "1", "1"]
+  );
+  // Unless a column has the name.
+  assert_eq!(
+    row("WITH t(true) AS (SELECT 5) SELECT true, 3 IS true, (SELECT true) FROM t"),
+    ["5", "0", "5"]
+  );
+  assert_eq!(
+    row(
+      "SELECT (1, 2) = (1, 2), (1, 2) < (1, 3), (1, NULL) = (1, 2), (1, 2) = (NULL, 3), \
+       (1, 2) < (NULL, 3), (1, 2) IS NOT (1, NULL), (1, 2) BETWEEN (0, 9) AND (1, 2)"
+    ),
+    ["1", "1", "NULL", "0", "NULL", "1", "1"]
+  );
+  assert_eq!(
+    row(
+      "SELECT (1, NULL) IN (SELECT 1, 2), (NULL, 1) IN (SELECT 2, 2), \
+       (1, 2) NOT IN (SELECT 1, 3 UNION SELECT NULL, 2), (1, 2) IN ((0, 2), (1, 2))"
+    ),
+    ["NULL", "0", "NULL", "1"]
+  );
+  assert_eq!(
+    row(
+      "SELECT group_concat(name) FROM emp \
+       WHERE (dept_id, id) IN (SELECT dept_id, max(id) FROM emp GROUP BY 1)"
+    ),
+    ["bruno,eva,filipe"]
+  );
+}
+
+#[test]
+fn ok_on_common_table_expressions() {
+  let mut conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let names = |conn: &mut SqliteConnection, sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  assert_eq!(
+    names(
+      &mut conn,
+      "WITH a AS (SELECT * FROM b), b(x) AS MATERIALIZED (SELECT id FROM dept), \
+       c AS NOT MATERIALIZED (SELECT x FROM b WHERE x > 2) \
+       SELECT count(*), (SELECT sum(x) FROM c) FROM a, c"
+    ),
+    [["8", "7"]]
+  );
+
+  // The hierarchy of employees, walked depth first.
+  let result = conn
+    .query(
+      "WITH RECURSIVE chain(id, path, depth) AS (\
+         SELECT id, name, 0 FROM emp WHERE manager_id IS NULL \
+         UNION ALL \
+         SELECT e.id, chain.path || '/' || e.name, depth + 1 FROM emp e \
+         JOIN chain ON e.manager_id = chain.id ORDER BY 3 DESC, 2\
+       ) SELECT path, depth FROM chain",
+    )
+    .unwrap();
+  assert_eq!(result.columns(), ["path", "depth"]);
+  let paths: Vec<String> = result.rows().iter().map(|row| row[0].to_string()).collect();
+  assert_eq!(
+    paths,
+    [
+      "ana",
+      "ana/bruno",
+      "ana/carla",
+      "ana/carla/dora",
+      "ana/carla/eva",
+      "ana/carla/filipe"
+    ]
+  );
+
+  assert_eq!(
+    names(
+      &mut conn,
+      "WITH RECURSIVE cnt(x) AS (VALUES (1) UNION ALL SELECT x + 1 FROM cnt LIMIT 5 OFFSET 1) \
+       SELECT group_concat(x) FROM cnt"
+    ),
+    [["2,3,4,5,6"]]
+  );
+  // The LIMIT of the outer query stops a recursion without an end.
+  assert_eq!(
+    names(
+      &mut conn,
+      "WITH RECURSIVE r(x) AS (SELECT 1 UNION ALL SELECT x+1 FROM r) SELECT * FROM r LIMIT 3"
+    ),
+    [["1"], ["2"], ["3"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "WITH RECURSIVE r(x) AS (SELECT 1 UNION ALL SELECT x+1 FROM r) \
+       SELECT a.x FROM (SELECT 1) JOIN r a WHERE a.x % 10 = 0 LIMIT 2"
+    ),
+    [["10"], ["20"]]
+  );
+  // UNION stops a cycle.
+  assert_eq!(
+    names(
+      &mut conn,
+      "WITH RECURSIVE cycle(x) AS (SELECT 1 UNION SELECT x % 3 + 1 FROM cycle) \
+       SELECT count(*) FROM cycle"
+    ),
+    [["3"]]
+  );
+}
+
+#[test]
+fn ok_on_compound_selects() {
+  let mut conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let names = |conn: &mut SqliteConnection, sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT city FROM emp UNION SELECT city FROM dept"
+    ),
+    [["NULL"], ["Faro"], ["Lisbon"], ["Porto"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT city FROM emp UNION ALL SELECT city FROM dept ORDER BY 1 DESC LIMIT 3"
+    ),
+    [["Porto"], ["Porto"], ["Porto"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT city FROM emp INTERSECT SELECT city FROM dept"
+    ),
+    [["NULL"], ["Lisbon"], ["Porto"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT id, city FROM dept EXCEPT SELECT dept_id, city FROM emp"
+    ),
+    [["3", "Lisbon"], ["4", "NULL"]]
+  );
+  // An alias of the first select names the column for ORDER BY.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT name AS n FROM emp WHERE id < 3 UNION SELECT name FROM dept ORDER BY n DESC"
+    ),
+    [
+      ["bruno"],
+      ["ana"],
+      ["Support"],
+      ["Sales"],
+      ["Legal"],
+      ["Engineering"]
+    ]
+  );
+  // The leftmost collation wins.
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT x FROM (SELECT 'LISBON' COLLATE NOCASE AS x) UNION SELECT city FROM dept"
+    ),
+    [["NULL"], ["Lisbon"], ["Porto"]]
+  );
+  assert_eq!(
+    names(
+      &mut conn,
+      "SELECT city FROM dept UNION SELECT x FROM (SELECT 'LISBON' COLLATE NOCASE AS x)"
+    ),
+    [["NULL"], ["LISBON"], ["Lisbon"], ["Porto"]]
+  );
+
+  // Past a tiny budget, the rows of both sides spill to temporary files.
+  for (operator, count) in [("UNION", 146), ("INTERSECT", 62), ("EXCEPT", 84)] {
+    let sql = format!(
+      "SELECT year, month_id FROM Observation {operator} \
+       SELECT year, month_id FROM Observation WHERE passengers > 300"
+    );
+    let mut conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+    let in_memory = conn.query(&sql).unwrap();
+    conn.set_sort_memory_budget(4096);
+    let spilled = conn.query(&sql).unwrap();
+    assert_eq!(spilled, in_memory, "{operator}");
+    assert_eq!(in_memory.rows().len(), count, "{operator}");
+  }
+}
+
+#[test]
+fn err_on_bad_compound_selects() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT id, name FROM emp EXCEPT SELECT id FROM dept"),
+    "SELECTs to the left and right of EXCEPT do not have the same number of result columns"
+  );
+  assert_eq!(
+    error("SELECT id FROM emp UNION SELECT id FROM dept ORDER BY name"),
+    "1st ORDER BY term does not match any column in the result set"
+  );
+  assert_eq!(
+    error("SELECT id FROM emp UNION SELECT id FROM dept ORDER BY 2"),
+    "1st ORDER BY term out of range - should be between 1 and 1"
+  );
+}
+
+#[test]
+fn err_on_bad_subqueries() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT (SELECT id, name FROM emp)"),
+    "sub-select returns 2 columns - expected 1"
+  );
+  assert_eq!(
+    error("SELECT * FROM emp WHERE id IN (SELECT id, name FROM emp)"),
+    "sub-select returns 2 columns - expected 1"
+  );
+  assert_eq!(
+    error("SELECT (1, 2) IN (SELECT id, name, dept_id FROM emp)"),
+    "sub-select returns 3 columns - expected 2"
+  );
+  assert_eq!(error("SELECT (1, 2) = 1"), "row value misused");
+  assert_eq!(
+    error("SELECT (SELECT missing FROM dept) FROM emp"),
+    "no such column: missing"
+  );
+  assert_eq!(
+    error("WITH emp AS (SELECT * FROM emp) SELECT * FROM emp"),
+    "circular reference: emp"
+  );
+  assert_eq!(
+    error("WITH a AS (SELECT 1), A AS (SELECT 2) SELECT * FROM a"),
+    "duplicate WITH table name: A"
+  );
+  assert_eq!(
+    error("WITH a(x, y) AS (SELECT 1) SELECT * FROM a"),
+    "table a has 1 values for 2 columns"
+  );
+}
+
+#[test]
+fn ok_on_window_functions() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let values = |sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  // Running totals and deltas.
+  assert_eq!(
+    values("SELECT emp_id, sum(hours) OVER (ORDER BY rowid) FROM assignment WHERE emp_id < 100"),
+    [
+      ["1", "10.5"],
+      ["2", "14.5"],
+      ["3", "22.5"],
+      ["1", "24.5"],
+      ["6", "25.5"]
+    ]
+  );
+  assert_eq!(
+    values(
+      "SELECT emp_id, project_code, hours - lag(hours) OVER (PARTITION BY emp_id \
+       ORDER BY project_code) FROM assignment WHERE emp_id < 100"
+    ),
+    [
+      ["1", "apollo", "NULL"],
+      ["1", "hermes", "-8.5"],
+      ["2", "apollo", "NULL"],
+      ["3", "hermes", "NULL"],
+      ["6", "atlas", "NULL"]
+    ]
+  );
+  // Peers share their rank, and the rows come out in the order of the window.
+  assert_eq!(
+    values(
+      "SELECT name, rank() OVER w, dense_rank() OVER w, ntile(2) OVER w FROM emp \
+       WINDOW w AS (ORDER BY dept_id)"
+    ),
+    [
+      ["dora", "1", "1", "1"],
+      ["ana", "2", "2", "1"],
+      ["bruno", "2", "2", "1"],
+      ["carla", "4", "3", "2"],
+      ["filipe", "4", "3", "2"],
+      ["eva", "6", "4", "2"]
+    ]
+  );
+  assert_eq!(
+    values(
+      "SELECT name, first_value(name) OVER w, nth_value(name, 2) OVER w FROM emp \
+       WINDOW w AS (ORDER BY id ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING EXCLUDE CURRENT ROW)"
+    ),
+    [
+      ["ana", "bruno", "NULL"],
+      ["bruno", "ana", "carla"],
+      ["carla", "bruno", "dora"],
+      ["dora", "carla", "eva"],
+      ["eva", "dora", "filipe"],
+      ["filipe", "eva", "NULL"]
+    ]
+  );
+  assert_eq!(
+    values(
+      "SELECT id, avg(id) OVER (ORDER BY id RANGE BETWEEN 1 PRECEDING AND 1 FOLLOWING) \
+       FROM emp WHERE id IN (1, 2, 5, 6)"
+    ),
+    [["1", "1.5"], ["2", "1.5"], ["5", "5.5"], ["6", "5.5"]]
+  );
+  // Windows of an aggregate query run over its groups.
+  assert_eq!(
+    values(
+      "SELECT dept_id, count(*), sum(count(*)) OVER (ORDER BY dept_id) FROM emp \
+       WHERE dept_id IS NOT NULL GROUP BY dept_id"
+    ),
+    [["1", "2", "2"], ["2", "2", "4"], ["9", "1", "5"]]
+  );
+  assert_eq!(
+    values("SELECT name, row_number() OVER (ORDER BY id DESC) AS r FROM emp ORDER BY r LIMIT 2"),
+    [["filipe", "1"], ["eva", "2"]]
+  );
+}
+
+#[test]
+fn err_on_bad_window_functions() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+
+  assert_eq!(
+    error("SELECT row_number() FROM emp"),
+    "misuse of window function row_number()"
+  );
+  assert_eq!(
+    error("SELECT abs(id) OVER () FROM emp"),
+    "abs() may not be used as a window function"
+  );
+  assert_eq!(
+    error("SELECT id FROM emp WHERE rank() OVER () > 1"),
+    "misuse of window function rank()"
+  );
+  assert_eq!(
+    error("SELECT sum(row_number() OVER ()) FROM emp"),
+    "misuse of window function row_number()"
+  );
+  assert_eq!(error("SELECT sum(id) OVER w FROM emp"), "no such window: w");
+  assert_eq!(
+    error("SELECT sum(id) OVER (w ORDER BY id) FROM emp WINDOW w AS (ORDER BY name)"),
+    "cannot override ORDER BY clause of window: w"
+  );
+  assert_eq!(
+    error("SELECT sum(id) OVER (RANGE 1 PRECEDING) FROM emp"),
+    "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression"
+  );
+  assert_eq!(
+    error("SELECT ntile(0) OVER () FROM emp"),
+    "argument of ntile must be a positive integer"
+  );
+}
+
+/// Expected rows are the output of sqlite3 3.51 for the same queries.
+#[test]
+fn ok_on_table_valued_functions() {
+  let conn = SqliteConnection::open(JOINS_DB).unwrap();
+  let values = |sql: &str| -> Vec<Vec<String>> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| row.iter().map(ToString::to_string).collect())
+      .collect()
+  };
+
+  // The hidden columns hold the arguments, and `*` leaves them out.
+  assert_eq!(
+    values(r#"SELECT * FROM json_each('{"a":[2,{"b":null}],"c":"x"}')"#),
+    [
+      [
+        "a",
+        r#"[2,{"b":null}]"#,
+        "array",
+        "NULL",
+        "2",
+        "NULL",
+        "$.a",
+        "$"
+      ],
+      ["c", "x", "text", "x", "11", "NULL", "$.c", "$"],
+    ]
+  );
+  assert_eq!(
+    values(
+      r#"SELECT key, id, parent, fullkey, path, root FROM json_tree('{"a":[2,{"b":null}],"c":"x"}', '$.a')"#
+    ),
+    [
+      ["a", "2", "NULL", "$.a", "$", "$.a"],
+      ["0", "5", "2", "$.a[0]", "$.a", "$.a"],
+      ["1", "7", "2", "$.a[1]", "$.a", "$.a"],
+      ["b", "8", "7", "$.a[1].b", "$.a[1]", "$.a"],
+    ]
+  );
+
+  // The arguments may refer to the tables on the left, and `value` is JSON
+  // for the functions that take JSON.
+  assert_eq!(
+    values(
+      "SELECT d.name, json_group_array(j.value) FROM dept d, \
+       json_each(json_array(d.id, json_array(d.name))) j GROUP BY d.id ORDER BY d.id LIMIT 2"
+    ),
+    [
+      ["Engineering", r#"[1,["Engineering"]]"#],
+      ["Sales", r#"[2,["Sales"]]"#],
+    ]
+  );
+  assert_eq!(
+    values("SELECT count(*) FROM json_each(NULL) RIGHT JOIN json_each('[1,2]', '$.x')"),
+    [["0"]]
+  );
+
+  let error = |sql: &str| conn.query(sql).unwrap_err().to_string();
+  assert_eq!(
+    error("SELECT * FROM json_each('[1]', '$', 1)"),
+    "too many arguments on json_each() - max 2"
+  );
+  assert_eq!(error("SELECT * FROM nope(1)"), "no such table: nope");
+  assert_eq!(
+    error("SELECT * FROM json_tree('[1]', 'x')"),
+    "bad JSON path: 'x'"
+  );
+}
+
+#[test]
+fn ok_on_collations() {
+  let mut conn = SqliteConnection::open(COLLATIONS_DB).unwrap();
+  conn.create_collation("reverse", |a, b| b.cmp(a));
+  let values = |conn: &mut SqliteConnection, sql: &str| -> Vec<String> {
+    let result = conn.query(sql).unwrap();
+    result.rows().iter().map(|row| row[0].to_string()).collect()
+  };
+
+  // Declared collations apply to comparisons, sorts and DISTINCT.
+  assert_eq!(
+    values(&mut conn, "SELECT name FROM word ORDER BY name, id"),
+    ["apple", "APPLE", "Banana", "banana", "cherry"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT DISTINCT name FROM word ORDER BY 1"),
+    ["apple", "Banana", "cherry"]
+  );
+  // DISTINCT arguments keep the first of equal ones, in the order of the
+  // rows.
+  assert_eq!(
+    values(&mut conn, "SELECT group_concat(DISTINCT name) FROM word"),
+    ["apple,Banana,cherry"]
+  );
+  // So do the rows of a recursive UNION.
+  assert_eq!(
+    values(
+      &mut conn,
+      "WITH RECURSIVE r(x) AS (SELECT 'a' COLLATE NOCASE UNION SELECT upper(x) FROM r) \
+       SELECT count(*) FROM r"
+    ),
+    ["1"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT id FROM word WHERE name = 'APPLE'"),
+    ["1", "3"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT id FROM word WHERE tag = 'red'"),
+    ["1", "3"]
+  );
+  // An explicit COLLATE wins, on either side.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM word WHERE name = 'apple' COLLATE BINARY"
+    ),
+    ["1"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT count(*) FROM word WHERE 'RED' = tag COLLATE NOCASE"
+    ),
+    ["2"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT ('abc' = 'ABC' COLLATE NOCASE) || ('abc ' = 'abc' COLLATE RTRIM) \
+       || ('a' < 'B' COLLATE NOCASE) || ('x' IN ('X') COLLATE NOCASE)"
+    ),
+    ["1110"]
+  );
+
+  // The index is only sought with its own collation.
+  assert_eq!(
+    values(&mut conn, "SELECT id FROM word WHERE code = 'b2'"),
+    ["2"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM word WHERE code = 'b2' COLLATE NOCASE"
+    ),
+    ["2", "5"]
+  );
+
+  // Registered collations work the same way.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT name FROM person ORDER BY name COLLATE reverse"
+    ),
+    ["dave", "bob", "Carl", "Ann"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM person WHERE name = 'bob' COLLATE reverse"
+    ),
+    ["2"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT name FROM pet"),
+    ["tom", "rex", "bob"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT kind FROM pet WHERE name = 'rex'"),
+    ["dog"]
+  );
+}
+
+#[test]
+fn err_on_unregistered_collations() {
+  let conn = SqliteConnection::open(COLLATIONS_DB).unwrap();
+
+  assert_eq!(
+    conn.index_cursor("person_name").unwrap_err().to_string(),
+    "no such collation sequence: reverse"
+  );
+  assert_eq!(
+    conn.query("SELECT * FROM pet").unwrap_err().to_string(),
+    "no such collation sequence: reverse"
+  );
+  assert_eq!(
+    conn
+      .query("SELECT name FROM person ORDER BY name COLLATE reverse")
+      .unwrap_err()
+      .to_string(),
+    "no such collation sequence: reverse"
+  );
+  // Without the collation the index is not used.
+  let result = conn
+    .query("SELECT id FROM person WHERE name = 'bob'")
+    .unwrap();
+  assert_eq!(result.rows(), [[Value::from(2)]]);
+}
+
+#[test]
+fn ok_on_planned_access() {
+  let mut conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let values = |conn: &mut SqliteConnection, sql: &str| -> Vec<String> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| {
+        let row: Vec<_> = row.iter().map(|value| value.to_string()).collect();
+        row.join("|")
+      })
+      .collect()
+  };
+
+  // Index equality and range seeks, with ORDER BY delivered by the index.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id, price FROM item WHERE category = 'cat3' ORDER BY price, id LIMIT 4"
+    ),
+    ["43|1.5", "143|1.5", "243|1.5", "343|1.5"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT name FROM item WHERE name BETWEEN 'item1998' AND 'item2' ORDER BY name"
+    ),
+    ["item1998", "item1999", "item2"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT count(*) FROM item WHERE category > 'cat5' AND category < 'cat7'"
+    ),
+    ["100"]
+  );
+
+  // Rowid and PRIMARY KEY ranges.
+  assert_eq!(
+    values(&mut conn, "SELECT id, stock FROM item WHERE id > 1997"),
+    ["1998|9", "1999|10", "2000|11"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM item WHERE 1999 <= id AND id < 2000.5"
+    ),
+    ["1999", "2000"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT * FROM tag WHERE item_id BETWEEN 5 AND 6"),
+    ["5|label5", "6|label6"]
+  );
+
+  // Covering indexes of a WITHOUT ROWID table.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT item_id FROM tag WHERE label = 'extra' ORDER BY item_id LIMIT 3"
+    ),
+    ["10", "20", "30"]
+  );
+  assert_eq!(
+    values(&mut conn, "SELECT label FROM tag ORDER BY label LIMIT 2"),
+    ["extra", "extra"]
+  );
+
+  // Ranges read backward for a descending ORDER BY.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id, price FROM item WHERE category = 'cat3' ORDER BY price DESC, id DESC LIMIT 3"
+    ),
+    ["1983|81.5", "1883|81.5", "1783|81.5"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM item WHERE id >= 1995 AND id < 1998 ORDER BY id DESC"
+    ),
+    ["1997", "1996", "1995"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT name FROM item WHERE name < 'item11' ORDER BY name DESC LIMIT 3"
+    ),
+    ["item1099", "item1098", "item1097"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT item_id, label FROM tag WHERE item_id < 3 ORDER BY item_id DESC, label DESC"
+    ),
+    ["2|label2", "1|label1"]
+  );
+
+  // IN lists seek each distinct value once, in order.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM item WHERE id IN (3, 1, '2', 1, NULL)"
+    ),
+    ["1", "2", "3"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT name FROM item WHERE name IN ('item7', 'item70', 'item7', 'nope')"
+    ),
+    ["item7", "item70"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT count(*) FROM item WHERE category IN ('cat1', 'cat2') AND price > 80"
+    ),
+    ["40"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT * FROM tag WHERE item_id IN (7, 9) AND label > 'a'"
+    ),
+    ["7|label7", "9|label9"]
+  );
+  // So do the rows of a subquery that does not depend on the row.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM item WHERE id IN \
+       (SELECT item_id FROM tag WHERE label = 'label7' AND item_id < 200)"
+    ),
+    ["7", "57", "107", "157"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT id FROM item WHERE id IN \
+       (SELECT '2' UNION ALL SELECT 2.0 UNION ALL SELECT NULL UNION ALL SELECT 5)"
+    ),
+    ["2", "5"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT count(*) FROM tag WHERE item_id IN (SELECT id FROM item WHERE category = 'cat1')"
+    ),
+    ["100"]
+  );
+
+  // Joins read in the order that costs the least.
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT i.name FROM item i, tag t WHERE t.label = 'label7' AND i.id = t.item_id \
+       ORDER BY i.id LIMIT 3"
+    ),
+    ["item7", "item57", "item107"]
+  );
+  assert_eq!(
+    values(
+      &mut conn,
+      "SELECT count(*) FROM item a, tag t, item b \
+       WHERE t.label = 'label7' AND a.id = t.item_id AND b.id = a.id + 1"
+    ),
+    ["40"]
+  );
+}
+
+#[test]
+fn ok_on_explain_query_plan() {
+  let mut conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let plan = |conn: &mut SqliteConnection, sql: &str| conn.query_plan(sql).unwrap().to_string();
+
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT * FROM item WHERE category = 'cat3' ORDER BY price"
+    ),
+    "QUERY PLAN\n`--SEARCH item USING INDEX item_category (category=?)"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT * FROM item WHERE category = 'a' AND price BETWEEN 1 AND 2"
+    ),
+    "QUERY PLAN\n`--SEARCH item USING INDEX item_category (category=? AND price>? AND price<?)"
+  );
+  assert_eq!(
+    plan(&mut conn, "SELECT name FROM item ORDER BY name"),
+    "QUERY PLAN\n`--SCAN item USING COVERING INDEX item_name"
+  );
+  assert_eq!(
+    plan(&mut conn, "SELECT * FROM tag WHERE item_id > 5"),
+    "QUERY PLAN\n`--SEARCH tag USING PRIMARY KEY (item_id>?)"
+  );
+  assert_eq!(
+    plan(&mut conn, "SELECT * FROM item ORDER BY id DESC"),
+    "QUERY PLAN\n`--SCAN item"
+  );
+  assert_eq!(
+    plan(&mut conn, "SELECT * FROM item ORDER BY name DESC"),
+    "QUERY PLAN\n`--SCAN item USING INDEX item_name"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT * FROM item ORDER BY category DESC, price DESC"
+    ),
+    "QUERY PLAN\n`--SCAN item USING INDEX item_category"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT * FROM item WHERE category = 'cat3' ORDER BY price DESC"
+    ),
+    "QUERY PLAN\n`--SEARCH item USING INDEX item_category (category=?)"
+  );
+  assert_eq!(
+    plan(&mut conn, "SELECT * FROM item WHERE name IN ('a', 'b')"),
+    "QUERY PLAN\n`--SEARCH item USING INDEX item_name (name=?)"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT name FROM item WHERE id IN (SELECT item_id FROM tag WHERE label = 'label3')"
+    ),
+    "QUERY PLAN\n\
+     |--SEARCH item USING INTEGER PRIMARY KEY (rowid=?)\n\
+     `--LIST SUBQUERY 1\n   \
+     `--SEARCH tag USING COVERING INDEX tag_label (label=?)"
+  );
+  // A correlated subquery runs for every row, and is no key.
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT name FROM item WHERE id IN (SELECT item_id FROM tag WHERE tag.item_id = item.stock)"
+    ),
+    "QUERY PLAN\n\
+     |--SCAN item\n\
+     `--CORRELATED LIST SUBQUERY 1\n   \
+     `--SEARCH tag USING PRIMARY KEY (item_id=?)"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT * FROM item WHERE category IN ('a', 'b') ORDER BY price"
+    ),
+    "QUERY PLAN\n\
+     |--SEARCH item USING INDEX item_category (category=?)\n\
+     `--USE TEMP B-TREE FOR ORDER BY"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT i.name FROM item i, tag t WHERE t.label = 'x' AND i.id = t.item_id"
+    ),
+    "QUERY PLAN\n\
+     |--SEARCH t USING COVERING INDEX tag_label (label=?)\n\
+     `--SEARCH i USING INTEGER PRIMARY KEY (rowid=?)"
+  );
+  assert_eq!(
+    plan(
+      &mut conn,
+      "SELECT a.stock, count(*) FROM item a JOIN item b ON b.id = a.stock \
+       LEFT JOIN note ON note.rowid = b.id GROUP BY 1 ORDER BY 2"
+    ),
+    "QUERY PLAN\n\
+     |--SCAN a\n\
+     |--SEARCH b USING INTEGER PRIMARY KEY (rowid=?)\n\
+     |--SEARCH note USING INTEGER PRIMARY KEY (rowid=?) LEFT-JOIN\n\
+     |--USE TEMP B-TREE FOR GROUP BY\n\
+     `--USE TEMP B-TREE FOR ORDER BY"
+  );
+  // Subqueries hang from the step that runs them.
+  assert_eq!(
+    plan(
+      &mut conn,
+      "EXPLAIN QUERY PLAN WITH c(x) AS (SELECT 1 UNION ALL SELECT 2) \
+       SELECT (SELECT count(*) FROM note n WHERE n.rowid = c.x) FROM c"
+    ),
+    "QUERY PLAN\n\
+     |--MATERIALIZE c\n\
+     |  `--COMPOUND QUERY\n\
+     |     |--LEFT-MOST SUBQUERY\n\
+     |     |  `--SCAN CONSTANT ROW\n\
+     |     `--UNION ALL\n\
+     |        `--SCAN CONSTANT ROW\n\
+     |--SCAN c\n\
+     `--CORRELATED SCALAR SUBQUERY 1\n   \
+     `--SEARCH n USING INTEGER PRIMARY KEY (rowid=?)"
+  );
+
+  // As a statement, the plan is returned as rows.
+  let result = conn
+    .query("EXPLAIN QUERY PLAN SELECT 1 FROM (SELECT 2)")
+    .unwrap();
+  assert_eq!(result.columns(), ["id", "parent", "notused", "detail"]);
+  assert_eq!(
+    result.rows(),
+    [
+      [
+        1.into(),
+        0.into(),
+        0.into(),
+        "MATERIALIZE (subquery-1)".into()
+      ],
+      [2.into(), 1.into(), 0.into(), "SCAN CONSTANT ROW".into()],
+      [3.into(), 0.into(), 0.into(), "SCAN (subquery-1)".into()],
+    ] as [[Value; 4]; 3]
+  );
+}
+
+#[test]
+fn ok_on_flattened_subqueries() {
+  let mut conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let plan = |conn: &mut SqliteConnection, sql: &str| conn.query_plan(sql).unwrap().to_string();
+  let rows = |conn: &mut SqliteConnection, sql: &str| -> Vec<String> {
+    let result = conn.query(sql).unwrap();
+    result
+      .rows()
+      .iter()
+      .map(|row| {
+        let row: Vec<String> = row.iter().map(ToString::to_string).collect();
+        row.join("|")
+      })
+      .collect()
+  };
+
+  // The conditions of the query search the table of the subquery.
+  let sql = "SELECT * FROM (SELECT * FROM item) WHERE id=3";
+  assert_eq!(
+    plan(&mut conn, sql),
+    "QUERY PLAN\n`--SEARCH item USING INTEGER PRIMARY KEY (rowid=?)"
+  );
+  assert_eq!(rows(&mut conn, sql), ["3|cat3|21.5|item3|3"]);
+
+  let sql = "WITH c AS NOT MATERIALIZED (SELECT id, name AS n FROM item WHERE price > 5) \
+             SELECT c.n, label FROM c JOIN tag ON tag.item_id = c.id WHERE c.id = 3";
+  assert_eq!(
+    plan(&mut conn, sql),
+    "QUERY PLAN\n\
+     |--SEARCH item USING INTEGER PRIMARY KEY (rowid=?)\n\
+     `--SEARCH tag USING PRIMARY KEY (item_id=?)"
+  );
+  assert_eq!(conn.query(sql).unwrap().columns(), ["n", "label"]);
+  assert_eq!(rows(&mut conn, sql), ["item3|label3"]);
+
+  // Plain columns on the right of a LEFT JOIN are null without a match.
+  let sql = "SELECT * FROM (SELECT id FROM item WHERE id < 3) i \
+             LEFT JOIN (SELECT item_id FROM tag) t ON t.item_id = i.id";
+  assert_eq!(
+    plan(&mut conn, sql),
+    "QUERY PLAN\n\
+     |--SEARCH item USING INTEGER PRIMARY KEY (rowid<?)\n\
+     `--SEARCH tag USING PRIMARY KEY (item_id=?) LEFT-JOIN"
+  );
+  assert_eq!(rows(&mut conn, sql), ["1|1", "2|2"]);
+
+  // A MATERIALIZED common table expression fills its table.
+  let sql = "WITH c AS MATERIALIZED (SELECT * FROM item) SELECT name FROM c WHERE id=3";
+  assert_eq!(
+    plan(&mut conn, sql),
+    "QUERY PLAN\n\
+     |--MATERIALIZE c\n\
+     |  `--SCAN item\n\
+     `--SCAN c"
+  );
+  assert_eq!(rows(&mut conn, sql), ["item3"]);
+}
+
+#[test]
+fn ok_on_compiled_queries() {
+  // Each query runs as a program, and returns the rows sqlite3 does.
+  let queries: [(&str, &str, &[&str]); 20] = [
+    (
+      JOINS_DB,
+      "SELECT e.name, m.name, d.name FROM emp e \
+       LEFT JOIN emp m ON m.id = e.manager_id \
+       LEFT JOIN dept d ON d.id = m.dept_id ORDER BY e.id",
+      &[
+        "ana|NULL|NULL",
+        "bruno|ana|Engineering",
+        "carla|ana|Engineering",
+        "dora|carla|Sales",
+        "eva|carla|Sales",
+        "filipe|carla|Sales",
+      ],
+    ),
+    (
+      JOINS_DB,
+      "SELECT p.title, e.name, a.hours FROM project p \
+       JOIN assignment a ON a.project_code = p.code \
+       JOIN emp e ON e.id = a.emp_id WHERE a.hours > 3 ORDER BY 1, 2",
+      &["Apollo|ana|10.5", "Apollo|bruno|4.0", "Hermes|carla|8.0"],
+    ),
+    (
+      JOINS_DB,
+      "SELECT d.name, e.name FROM dept d LEFT JOIN emp e ON e.dept_id = d.id \
+       AND e.name > 'b' WHERE e.id IS NULL OR e.id < 5",
+      &[
+        "Engineering|bruno",
+        "Sales|carla",
+        "Legal|NULL",
+        "Support|NULL",
+      ],
+    ),
+    (
+      JOINS_DB,
+      "SELECT title FROM project WHERE code = 'APL'",
+      &[],
+    ),
+    (
+      JOINS_DB,
+      "SELECT name AS n, upper(name) FROM emp WHERE n LIKE '%a' ORDER BY n DESC",
+      &["eva|EVA", "dora|DORA", "carla|CARLA", "ana|ANA"],
+    ),
+    (
+      JOINS_DB,
+      "SELECT e.*, d.* FROM emp e, dept d WHERE e.dept_id = d.id AND d.city IN ('Lisbon', NULL)",
+      &[
+        "1|ana|1|NULL|Lisbon|1|Engineering|Lisbon",
+        "2|bruno|1|1|Porto|1|Engineering|Lisbon",
+      ],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT id, price FROM item WHERE category = 'cat3' ORDER BY price, id LIMIT 4",
+      &["43|1.5", "143|1.5", "243|1.5", "343|1.5"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT name FROM item WHERE name BETWEEN 'item1998' AND 'item2' ORDER BY name",
+      &["item1998", "item1999", "item2"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT id, stock FROM item WHERE id > 1997",
+      &["1998|9", "1999|10", "2000|11"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT id FROM item WHERE 1999 <= id AND id < 2000.5",
+      &["1999", "2000"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT rowid, id FROM item WHERE id = 7.0 OR id = 8",
+      &["7|7", "8|8"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT * FROM tag WHERE item_id BETWEEN 5 AND 6",
+      &["5|label5", "6|label6"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT item_id FROM tag WHERE label = 'extra' ORDER BY item_id LIMIT 3",
+      &["10", "20", "30"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT label, item_id FROM tag ORDER BY label DESC, item_id DESC LIMIT 2 OFFSET 3",
+      &["label9|1809", "label9|1759"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT price, CAST(price AS TEXT) FROM item WHERE category > 'cat5' \
+       AND category < 'cat7' ORDER BY stock * -1, id LIMIT 5",
+      &[
+        "22.5|22.5",
+        "42.5|42.5",
+        "62.5|62.5",
+        "82.5|82.5",
+        "2.5|2.5",
+      ],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT a.id, b.id FROM item a JOIN item b ON b.id = a.stock WHERE a.id < 4",
+      &["1|1", "2|2", "3|3"],
+    ),
+    (
+      PLANNER_DB,
+      "SELECT CASE WHEN id % 3 = 0 THEN 'fizz' WHEN id % 5 = 0 THEN 'buzz' \
+       ELSE id END, CASE stock WHEN 9 THEN 'nine' END FROM item WHERE id <= 15",
+      &[
+        "1|NULL",
+        "2|NULL",
+        "fizz|NULL",
+        "4|NULL",
+        "buzz|NULL",
+        "fizz|NULL",
+        "7|NULL",
+        "8|NULL",
+        "fizz|nine",
+        "buzz|NULL",
+        "11|NULL",
+        "fizz|NULL",
+        "13|NULL",
+        "14|NULL",
+        "fizz|NULL",
+      ],
+    ),
+    (
+      COLLATIONS_DB,
+      "SELECT name, tag FROM word WHERE name = 'APPLE' OR tag = 'x  ' ORDER BY name, id",
+      &["apple|red", "APPLE|red "],
+    ),
+    (
+      COLLATIONS_DB,
+      "SELECT code FROM word WHERE code > 'b' ORDER BY code COLLATE BINARY",
+      &["b2"],
+    ),
+    (
+      FLIGHTS_DB,
+      "SELECT 1 + 2, 'a' || 'b', NULL IS NULL, 3 NOT BETWEEN 1 AND 2 WHERE 1 LIMIT 1",
+      &["3|ab|1|1"],
+    ),
+  ];
+  for (database, sql, expected) in queries {
+    let conn = SqliteConnection::open(database).unwrap();
+    let rows: Vec<String> = conn
+      .query(sql)
+      .unwrap()
+      .rows()
+      .iter()
+      .map(|row| {
+        let row: Vec<_> = row.iter().map(|value| value.to_string()).collect();
+        row.join("|")
+      })
+      .collect();
+    assert_eq!(rows, expected, "{sql}");
+  }
+}
+
+#[test]
+fn err_on_interrupted_aggregate() {
+  let conn = SqliteConnection::open(FLIGHTS_DB).unwrap();
+  let handle = conn.interrupt_handle();
+  let is_done = Arc::new(AtomicBool::new(false));
+  // Interrupts until the query fails, as the query clears an earlier
+  // interrupt when it starts.
+  let interrupter = {
+    let is_done = Arc::clone(&is_done);
+    thread::spawn(move || {
+      while !is_done.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(10));
+        handle.interrupt();
+      }
+    })
+  };
+  let result = conn.query(
+    "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT count(*) FROM c",
+  );
+  is_done.store(true, Ordering::Relaxed);
+  interrupter.join().unwrap();
+  assert_eq!(result.unwrap_err().to_string(), "interrupted");
+}
+
+#[test]
+fn ok_on_explain() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+
+  let result = conn
+    .query("EXPLAIN SELECT name FROM item WHERE id > 5 ORDER BY stock LIMIT 2")
+    .unwrap();
+  assert_eq!(
+    result.columns(),
+    ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"]
+  );
+  let opcodes: Vec<String> = result.rows().iter().map(|row| row[1].to_string()).collect();
+  assert_eq!(
+    opcodes,
+    [
+      "Init",
+      "IfNot",
+      "SorterOpen",
+      "OpenRead",
+      "Integer",
+      "Affinity",
+      "IsNull",
+      "SeekGT",
+      "Rowid",
+      "Integer",
+      "Gt",
+      "IfNot",
+      "Column",
+      "Column",
+      "SorterInsert",
+      "Next",
+      "SorterSort",
+      "SorterData",
+      "ResultRow",
+      "DecrJumpZero",
+      "SorterNext",
+      "Halt",
+      "Integer",
+      "Goto",
+    ]
+  );
+  assert_eq!(
+    result.rows()[0][..4],
+    [0.into(), "Init".into(), 0.into(), 22.into()]
+  );
+
+  // Aggregates step in aggregate registers, and finish once the scan ends.
+  let result = conn.query("EXPLAIN SELECT count(*) FROM item").unwrap();
+  let opcodes: Vec<String> = result.rows().iter().map(|row| row[1].to_string()).collect();
+  assert!(opcodes.contains(&"AggStep".to_string()), "{opcodes:?}");
+  assert!(opcodes.contains(&"AggFinal".to_string()), "{opcodes:?}");
+}
@@ -0,0 +1,593 @@
+//! # Window calls
+//!
+//!  A call with an OVER clause computes a value for each row of its query
+//! from the rows around it, once the rows of the query are known: after
+//! the WHERE clause, and after grouping and the HAVING clause in an
+//! aggregate query.
+//!
+//!  Each call sorts the rows by the PARTITION BY terms of its window, then
+//! by its ORDER BY terms. Rows with the same PARTITION BY values form a
+//! partition, and rows of a partition with the same ORDER BY values are
+//! peers. The frame of a row is the rows of its partition the call reads:
+//!
+//! - **ROWS** counts offsets in rows.
+//! - **GROUPS** counts offsets in groups of peers.
+//! - **RANGE** measures offsets on the value of the single ORDER BY term,
+//!   and only reaches rows whose value is a number when the row's value is
+//!   one, or its peers otherwise.
+//!
+//!  Without a frame, it spans from the start of the partition to the last
+//! peer of the row. Calls are computed from the last to the first, and the
+//! rows come out in the order of the window of the first call, unless the
+//! query has an ORDER BY.
+//!
+//! *Reference:* https://www.sqlite.org/windowfunctions.html
+
+use core::ops::Range;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  runtime::{
+    eval::{evaluate, is_true, Constant},
+    functions::{resolve_call, Accumulator, FunctionDef, Implementation, WindowFrame},
+    sorter::{compare_keys, KeyOrder},
+    vdbe::Op,
+  },
+  sql::ast::{
+    Expr, Frame, FrameBound, FrameExclude, FrameUnits, FunctionArgs, Over, SortOrder, Window,
+  },
+  value::Value,
+};
+
+use super::{
+  compile::{compile_condition, compile_expr, Builder},
+  group::contains,
+  Scope,
+};
+
+/// A call of a window function, or of an aggregate function with an OVER
+/// clause, in a query.
+pub(crate) struct WindowCall<'a> {
+  expr: &'a Expr,
+  function: &'static FunctionDef,
+  args: &'a FunctionArgs,
+  filter: Option<&'a Expr>,
+  /// The window, with the named windows it refers to resolved.
+  window: Window,
+}
+
+impl WindowCall<'_> {
+  pub(crate) fn expr(&self) -> &Expr {
+    self.expr
+  }
+
+  /// The PARTITION BY and ORDER BY expressions of the window.
+  pub(crate) fn window_exprs(&self) -> impl Iterator<Item = &Expr> {
+    self
+      .window
+      .partition_by
+      .iter()
+      .chain(self.window.order_by.iter().map(|term| &term.expr))
+  }
+
+  /// The number of values a row has for the call: those of its PARTITION
+  /// BY terms, then of its ORDER BY terms, then its arguments, then whether
+  /// the row passes its FILTER.
+  pub(super) fn width(&self) -> usize {
+    self.window.partition_by.len() + self.window.order_by.len() + self.arg_count() + 1
+  }
+
+  fn arg_count(&self) -> usize {
+    match self.args {
+      FunctionArgs::Star => 0,
+      FunctionArgs::List(args) => args.len(),
+    }
+  }
+
+  /// How a program computes the call, with its values at `offset` in a row.
+  pub(super) fn spec(&self, scope: &Scope, offset: usize) -> SqliteResult<WindowSpec> {
+    let window = &self.window;
+    let default_frame = Frame {
+      units: FrameUnits::Range,
+      start: FrameBound::UnboundedPreceding,
+      end: FrameBound::CurrentRow,
+      exclude: FrameExclude::NoOthers,
+    };
+    let frame = window.frame.as_ref().unwrap_or(&default_frame);
+    Ok(WindowSpec {
+      function: self.function,
+      offset,
+      partition_orders: window
+        .partition_by
+        .iter()
+        .map(|expr| scope.collation(expr).map(KeyOrder::ascending))
+        .collect::<SqliteResult<_>>()?,
+      key_orders: window
+        .order_by
+        .iter()
+        .map(|term| scope.term_order(term))
+        .collect::<SqliteResult<_>>()?,
+      arg_count: self.arg_count(),
+      units: frame.units,
+      start: Bound::new(&frame.start, frame.units, "starting")?,
+      end: Bound::new(&frame.end, frame.units, "ending")?,
+      exclude: frame.exclude,
+      is_descending: window
+        .order_by
+        .first()
+        .is_some_and(|term| term.order == Some(SortOrder::Desc)),
+    })
+  }
+
+  /// Compiles the values of the current row for the call into the
+  /// registers from `first`.
+  pub(super) fn compile_values(
+    &self,
+    builder: &mut Builder,
+    scope: &Scope,
+    first: usize,
+  ) -> SqliteResult<()> {
+    let args: &[Expr] = match self.args {
+      FunctionArgs::Star => &[],
+      FunctionArgs::List(args) => args,
+    };
+    let exprs = self.window_exprs().chain(args);
+    let mut register = first;
+    for expr in exprs {
+      compile_expr(builder, scope, expr, true, register)?;
+      register += 1;
+    }
+    builder.emit(Op::Integer {
+      value: 1,
+      dest: register,
+    });
+    if let Some(filter) = self.filter {
+      let passes = builder.label();
+      let fails = builder.label();
+      compile_condition(builder, scope, filter, fails)?;
+      builder.emit(Op::Goto { target: passes });
+      builder.place(fails);
+      builder.emit(Op::Integer {
+        value: 0,
+        dest: register,
+      });
+      builder.place(passes);
+    }
+    Ok(())
+  }
+}
+
+/// A window call as a program computes it, over rows that have its values
+/// from `offset`, as [`WindowCall::compile_values`] gives them.
+#[derive(Debug, Clone)]
+pub(crate) struct WindowSpec {
+  function: &'static FunctionDef,
+  offset: usize,
+  partition_orders: Vec<KeyOrder>,
+  key_orders: Vec<KeyOrder>,
+  arg_count: usize,
+  units: FrameUnits,
+  start: Bound,
+  end: Bound,
+  exclude: FrameExclude,
+  is_descending: bool,
+}
+
+/// The window calls of an expression, outermost first, with `windows` the
+/// named windows of the query. A window call inside the arguments or the
+/// window of another call, or inside an aggregate call, is an error.
+pub(crate) fn window_calls<'a>(
+  expr: &'a Expr,
+  windows: &[(String, Window)],
+) -> SqliteResult<Vec<WindowCall<'a>>> {
+  let mut calls: Vec<WindowCall> = vec![];
+  let mut aggregates: Vec<&Expr> = vec![];
+  let mut result = Ok(());
+  expr.walk(&mut |expr| {
+    if result.is_err() {
+      return;
+    }
+    let Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    } = expr
+    else {
+      return;
+    };
+    let Some(over) = over else {
+      if let Ok(function) = resolve_call(name, *distinct, args, order_by, filter.as_deref(), false)
+      {
+        if matches!(function.implementation, Implementation::Aggregate(_)) {
+          aggregates.push(expr);
+        }
+      }
+      return;
+    };
+    let is_nested = calls
+      .iter()
+      .map(|call| call.expr)
+      .chain(aggregates.iter().copied())
+      .any(|outer| contains(outer, expr));
+    if is_nested {
+      result = Err(SqliteError::Custom(format!(
+        "misuse of window function {name}()"
+      )));
+      return;
+    }
+    result =
+      resolve_call(name, *distinct, args, order_by, filter.as_deref(), true).and_then(|function| {
+        calls.push(WindowCall {
+          expr,
+          function,
+          args,
+          filter: filter.as_deref(),
+          window: resolve_window(over, windows)?,
+        });
+        Ok(())
+      });
+  });
+  result.map(|_| calls)
+}
+
+/// Whether an expression has a window call.
+pub(crate) fn has_window_call(expr: &Expr) -> bool {
+  let mut found = false;
+  expr.walk(&mut |expr| {
+    found |= matches!(expr, Expr::Function { over: Some(_), .. });
+  });
+  found
+}
+
+/// The window of an OVER clause.
+fn resolve_window(over: &Over, windows: &[(String, Window)]) -> SqliteResult<Window> {
+  match over {
+    Over::Name(name) => named_window(name, windows),
+    Over::Window(window) => extend_window(window, windows),
+  }
+}
+
+/// A window merged with the named window it extends.
+fn extend_window(window: &Window, windows: &[(String, Window)]) -> SqliteResult<Window> {
+  let Some(name) = &window.base else {
+    return check_frame(window.clone());
+  };
+  let base = named_window(name, windows)?;
+  let overridden = if !window.partition_by.is_empty() {
+    Some("PARTITION clause")
+  } else if !window.order_by.is_empty() && !base.order_by.is_empty() {
+    Some("ORDER BY clause")
+  } else if base.frame.is_some() {
+    Some("frame specification")
+  } else {
+    None
+  };
+  if let Some(overridden) = overridden {
+    return Err(SqliteError::Custom(format!(
+      "cannot override {overridden} of window: {name}"
+    )));
+  }
+  check_frame(Window {
+    base: None,
+    partition_by: base.partition_by,
+    order_by: match window.order_by.is_empty() {
+      true => base.order_by,
+      false => window.order_by.clone(),
+    },
+    frame: window.frame.clone(),
+  })
+}
+
+/// A window of the WINDOW clause, which may extend one defined before it.
+/// The last of windows with the same name is used.
+fn named_window(name: &str, windows: &[(String, Window)]) -> SqliteResult<Window> {
+  let index = windows
+    .iter()
+    .rposition(|(other, _)| other.eq_ignore_ascii_case(name))
+    .ok_or_else(|| SqliteError::Custom(format!("no such window: {name}")))?;
+  extend_window(&windows[index].1, &windows[..index])
+}
+
+fn check_frame(window: Window) -> SqliteResult<Window> {
+  let has_offset = window.frame.as_ref().is_some_and(|frame| {
+    frame.units == FrameUnits::Range
+      && [&frame.start, &frame.end]
+        .iter()
+        .any(|bound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_)))
+  });
+  if has_offset && window.order_by.len() != 1 {
+    return Err(SqliteError::Custom(
+      "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression".into(),
+    ));
+  }
+  Ok(window)
+}
+
+/// A frame bound, with its offset evaluated.
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+  Unbounded,
+  /// An offset from the current row, negative for PRECEDING.
+  Offset(f64),
+  Current,
+}
+
+impl Bound {
+  fn new(bound: &FrameBound, units: FrameUnits, edge: &str) -> SqliteResult<Self> {
+    let (offset, sign) = match bound {
+      FrameBound::UnboundedPreceding | FrameBound::UnboundedFollowing => {
+        return Ok(Self::Unbounded)
+      }
+      FrameBound::CurrentRow => return Ok(Self::Current),
+      FrameBound::Preceding(offset) => (offset, -1.0),
+      FrameBound::Following(offset) => (offset, 1.0),
+    };
+    let offset = match evaluate(offset, &Constant) {
+      Ok(Value::Integer(offset)) if offset >= 0 => Some(offset as f64),
+      Ok(Value::Real(offset))
+        if offset >= 0.0 && (units == FrameUnits::Range || offset.fract() == 0.0) =>
+      {
+        Some(offset)
+      }
+      _ => None,
+    };
+    match offset {
+      Some(offset) => Ok(Self::Offset(sign * offset)),
+      None => Err(SqliteError::Custom(format!(
+        "frame {edge} offset must be a non-negative {}",
+        match units {
+          FrameUnits::Range => "number",
+          FrameUnits::Rows | FrameUnits::Groups => "integer",
+        }
+      ))),
+    }
+  }
+}
+
+/// The rows of one partition, in the order of the window.
+struct Partition<'a> {
+  /// The ORDER BY values of each row.
+  keys: Vec<&'a [Value]>,
+  /// The groups of peers.
+  groups: Vec<Range<usize>>,
+  /// The group of each row.
+  group_of: Vec<usize>,
+  /// The value of the ORDER BY term of each group when it is a number, for
+  /// RANGE, negated when the term is descending so that values grow along
+  /// the partition.
+  numbers: Vec<Option<f64>>,
+}
+
+impl Partition<'_> {
+  fn len(&self) -> usize {
+    self.keys.len()
+  }
+
+  /// The first row of a group, or the end of the partition.
+  fn group_start(&self, group: usize) -> usize {
+    self
+      .groups
+      .get(group)
+      .map_or(self.len(), |range| range.start)
+  }
+
+  /// Where a frame starts or ends, exclusive, for a row.
+  fn position(&self, bound: Bound, units: FrameUnits, row: usize, is_start: bool) -> usize {
+    let group = self.group_of[row];
+    let peers = &self.groups[group];
+    let offset = match bound {
+      Bound::Unbounded if is_start => return 0,
+      Bound::Unbounded => return self.len(),
+      Bound::Current => {
+        return match (units, is_start) {
+          (FrameUnits::Rows, true) => row,
+          (FrameUnits::Rows, false) => row + 1,
+          (_, true) => peers.start,
+          (_, false) => peers.end,
+        }
+      }
+      Bound::Offset(offset) => offset,
+    };
+    match units {
+      FrameUnits::Rows => {
+        let target = row as f64 + offset + if is_start { 0.0 } else { 1.0 };
+        target.clamp(0.0, self.len() as f64) as usize
+      }
+      FrameUnits::Groups => {
+        let target = group as f64 + offset;
+        if target < 0.0 {
+          0
+        } else if target >= self.groups.len() as f64 {
+          self.len()
+        } else {
+          let target = &self.groups[target as usize];
+          match is_start {
+            true => target.start,
+            false => target.end,
+          }
+        }
+      }
+      FrameUnits::Range => {
+        let Some(number) = self.numbers[group] else {
+          return match is_start {
+            true => peers.start,
+            false => peers.end,
+          };
+        };
+        let target = number + offset;
+        // The groups whose value is a number are consecutive.
+        let first = self
+          .numbers
+          .iter()
+          .position(Option::is_some)
+          .unwrap_or_default();
+        let last = first
+          + self.numbers[first..]
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.numbers.len() - first);
+        let numbers = &self.numbers[first..last];
+        let index = match is_start {
+          true => numbers.partition_point(|number| number.is_some_and(|number| number < target)),
+          false => numbers.partition_point(|number| number.is_some_and(|number| number <= target)),
+        };
+        self.group_start(first + index)
+      }
+    }
+  }
+}
+
+/// Computes window calls over the rows of a query. Returns the rows in the
+/// order they come out in, each with the results of the calls appended.
+pub(crate) fn compute_windows(
+  mut rows: Vec<Vec<Value>>,
+  specs: &[WindowSpec],
+) -> SqliteResult<Vec<Vec<Value>>> {
+  let mut order: Vec<usize> = (0..rows.len()).collect();
+  let mut results = vec![vec![Value::Null; specs.len()]; rows.len()];
+  for (index, spec) in specs.iter().enumerate().rev() {
+    let keys_at = spec.offset + spec.partition_orders.len();
+    let args_at = keys_at + spec.key_orders.len();
+    let passes_at = args_at + spec.arg_count;
+    let partition_keys = |row: usize| &rows[row][spec.offset..keys_at];
+    let keys = |row: usize| &rows[row][keys_at..args_at];
+    order.sort_by(|a, b| {
+      compare_keys(
+        partition_keys(*a),
+        partition_keys(*b),
+        &spec.partition_orders,
+      )
+      .then_with(|| compare_keys(keys(*a), keys(*b), &spec.key_orders))
+    });
+
+    let mut first = 0;
+    while first < order.len() {
+      let size = order[first..]
+        .iter()
+        .position(|row| {
+          compare_keys(
+            partition_keys(*row),
+            partition_keys(order[first]),
+            &spec.partition_orders,
+          )
+          .is_ne()
+        })
+        .unwrap_or(order.len() - first);
+      let partition_rows = &order[first..first + size];
+      first += size;
+
+      let mut partition = Partition {
+        keys: partition_rows.iter().map(|row| keys(*row)).collect(),
+        groups: vec![],
+        group_of: vec![],
+        numbers: vec![],
+      };
+      for position in 0..partition_rows.len() {
+        let is_peer = position > 0
+          && compare_keys(
+            partition.keys[position - 1],
+            partition.keys[position],
+            &spec.key_orders,
+          )
+          .is_eq();
+        match partition.groups.last_mut() {
+          Some(group) if is_peer => group.end += 1,
+          _ => {
+            partition.groups.push(position..position + 1);
+            let number = match partition.keys[position].first() {
+              Some(Value::Integer(integer)) => Some(*integer as f64),
+              Some(Value::Real(real)) => Some(*real),
+              _ => None,
+            };
+            partition
+              .numbers
+              .push(number.map(|number| match spec.is_descending {
+                true => -number,
+                false => number,
+              }));
+          }
+        }
+        partition.group_of.push(partition.groups.len() - 1);
+      }
+
+      let partition_args: Vec<Vec<Value>> = partition_rows
+        .iter()
+        .map(|row| rows[*row][args_at..passes_at].to_vec())
+        .collect();
+      let partition_passes: Vec<bool> = partition_rows
+        .iter()
+        .map(|row| is_true(&rows[*row][passes_at]) == Some(true))
+        .collect();
+      let mut running: Option<(Range<usize>, Box<dyn Accumulator>)> = None;
+      for (position, row) in partition_rows.iter().enumerate() {
+        let peers = partition.groups[partition.group_of[position]].clone();
+        let frame_start = partition.position(spec.start, spec.units, position, true);
+        let frame_end = partition.position(spec.end, spec.units, position, false);
+        let excluded = match spec.exclude {
+          FrameExclude::NoOthers => [0..0, 0..0],
+          FrameExclude::CurrentRow => [position..position + 1, 0..0],
+          FrameExclude::Group => [peers.clone(), 0..0],
+          FrameExclude::Ties => [peers.start..position, position + 1..peers.end],
+        };
+        let window_frame = WindowFrame {
+          args: &partition_args,
+          row: position,
+          peers,
+          group: partition.group_of[position],
+          frame: frame_start..frame_end.max(frame_start),
+          excluded,
+        };
+        results[*row][index] = match spec.function.implementation {
+          Implementation::Window(function) => function(&window_frame)?,
+          Implementation::Aggregate(start) => {
+            let frame = &window_frame.frame;
+            match window_frame.excluded.iter().all(Range::is_empty) {
+              true => {
+                // A frame that only grows at its end adds its new rows to
+                // the running accumulator, and any other starts a new one.
+                if !running
+                  .as_ref()
+                  .is_some_and(|(range, _)| range.start == frame.start && range.end <= frame.end)
+                {
+                  running = None;
+                }
+                let (range, accumulator) =
+                  running.get_or_insert_with(|| (frame.start..frame.start, start()));
+                for row in range.end..frame.end {
+                  if partition_passes[row] {
+                    accumulator.step(&partition_args[row])?;
+                  }
+                }
+                range.end = frame.end;
+                accumulator.value()?
+              }
+              false => {
+                let mut accumulator = start();
+                for row in window_frame.rows() {
+                  if partition_passes[row] {
+                    accumulator.step(&partition_args[row])?;
+                  }
+                }
+                accumulator.value()?
+              }
+            }
+          }
+          Implementation::Scalar(_) => Value::Null,
+        };
+      }
+    }
+  }
+  Ok(
+    order
+      .into_iter()
+      .map(|row| {
+        let mut values = core::mem::take(&mut rows[row]);
+        values.append(&mut results[row]);
+        values
+      })
+      .collect(),
+  )
+}
@@ -1,2 +1,210 @@
+//! # Database schema
+//!
+//!  The schema of a database is read from the sqlite_schema table, whose
+//! b-tree is rooted at page 1. Each table row keeps the CREATE statement that
+//! defined it, which is parsed to learn the columns, their declared types and
+//! affinities and the constraints of every table. Index rows are linked to
+//! their table, including the sqlite_autoindex entries that SQLite creates
+//! for UNIQUE and PRIMARY KEY constraints, which have no SQL of their own.
+//! The sqlite_schema table itself is found by name like any other, and is
+//! read the same way.
+//!
+//! *Reference:* https://www.sqlite.org/schematab.html
+
+mod ddl;
+mod index;
+mod table;
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::OnceLock;
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  pager::SqlitePager,
+  result::{SqliteError, SqliteResult},
+};
+
+use super::internal_tables::sqlite_master::{
+  SchemaObject, SchemaObjectType, SqliteMaster, SQLITE_SCHEMA_ROOT_PAGE,
+};
+
+pub use self::index::{IndexKey, IndexKeyKind, IndexOrigin, IndexSchema};
+pub use self::table::{
+  ColumnDef, ConflictClause, DefaultValue, Deferrable, ForeignKeyAction, ForeignKeyClause,
+  GeneratedColumn, GeneratedColumnKind, IndexedColumn, PrimaryKeyClause, SortOrder,
+  TableConstraint, TableConstraintKind, TableSchema,
+};
+
+/// The table the schema is stored in, which is not in the schema itself.
+const SCHEMA_TABLE: &str =
+  "CREATE TABLE sqlite_schema(type text, name text, tbl_name text, rootpage int, sql text)";
+
+/// The names the schema table goes by.
+const SCHEMA_TABLE_NAMES: [&str; 2] = ["sqlite_schema", "sqlite_master"];
+
+/// The schema table, whose b-tree is rooted on page 1.
+fn schema_table() -> Option<&'static TableSchema> {
+  static TABLE: OnceLock<Option<TableSchema>> = OnceLock::new();
+  TABLE
+    .get_or_init(|| {
+      let mut table = TableSchema::parse(SCHEMA_TABLE).ok()?;
+      table.root_page = SQLITE_SCHEMA_ROOT_PAGE;
+      Some(table)
+    })
+    .as_ref()
+}
+
 #[derive(Debug, Default)]
-pub struct SqliteSchema(());
+pub struct SqliteSchema {
+  objects: Vec<SchemaObject>,
+  tables: Vec<TableSchema>,
+  indexes: Vec<IndexSchema>,
+}
+
+impl SqliteSchema {
+  /// Reads and parses the sqlite_schema table.
+  pub(crate) fn load(pager: &SqlitePager, encoding: &DatabaseTextEncoding) -> SqliteResult<Self> {
+    let objects = SqliteMaster::read(pager, encoding)?.into_objects();
+
+    let mut tables = vec![];
+    let mut indexes = vec![];
+    for object in objects.iter() {
+      match object.object_type() {
+        SchemaObjectType::Table => {
+          let Some(sql) = object.sql() else {
+            continue;
+          };
+          match TableSchema::parse(sql) {
+            Ok(mut table) => {
+              table.root_page = object.root_page();
+              if table.is_without_rowid() {
+                // The table b-tree is itself the primary key index.
+                indexes.extend(
+                  IndexSchema::implicit_indexes(&table)
+                    .into_iter()
+                    .filter(|index| index.origin() == IndexOrigin::PrimaryKey)
+                    .map(|mut index| {
+                      index.root_page = table.root_page;
+                      index
+                    }),
+                );
+              }
+              tables.push(table);
+            }
+            Err(err) => {
+              // Virtual tables and statements we cannot parse yet must not
+              // make the whole database unreadable.
+              warn!("Skipping table [{}]: {err}", object.name());
+            }
+          }
+        }
+        SchemaObjectType::Index => {
+          let index = match object.sql() {
+            Some(sql) => IndexSchema::parse(sql),
+            None => Self::find_implicit_index(&tables, object),
+          };
+          match index {
+            Ok(mut index) => {
+              index.root_page = object.root_page();
+              indexes.push(index);
+            }
+            Err(err) => {
+              warn!("Skipping index [{}]: {err}", object.name());
+            }
+          }
+        }
+        SchemaObjectType::View | SchemaObjectType::Trigger => (),
+      }
+    }
+    trace!(
+      "[{}] tables and [{}] indexes parsed.",
+      tables.len(),
+      indexes.len()
+    );
+
+    Ok(Self {
+      objects,
+      tables,
+      indexes,
+    })
+  }
+
+  /// Maps a `sqlite_autoindex_TABLE_N` row back to the constraint of its
+  /// table that created it.
+  fn find_implicit_index(
+    tables: &[TableSchema],
+    object: &SchemaObject,
+  ) -> SqliteResult<IndexSchema> {
+    let table = tables
+      .iter()
+      .find(|table| table.name().eq_ignore_ascii_case(object.tbl_name()))
+      .ok_or_else(|| {
+        SqliteError::Custom(format!(
+          "Table [{}] of the index was not found",
+          object.tbl_name()
+        ))
+      })?;
+    IndexSchema::implicit_indexes(table)
+      .into_iter()
+      .find(|index| index.name().eq_ignore_ascii_case(object.name()))
+      .ok_or_else(|| {
+        SqliteError::Custom(format!(
+          "No UNIQUE or PRIMARY KEY constraint of [{}] matches the index",
+          table.name()
+        ))
+      })
+  }
+
+  /// Every row of the sqlite_schema table.
+  pub fn objects(&self) -> &[SchemaObject] {
+    &self.objects
+  }
+
+  pub fn tables(&self) -> &[TableSchema] {
+    &self.tables
+  }
+
+  /// Finds a table by name, ignoring ASCII case like SQLite does. The
+  /// table the schema is stored in is found as `sqlite_schema` or
+  /// `sqlite_master`.
+  pub fn table(&self, name: &str) -> Option<&TableSchema> {
+    self
+      .tables
+      .iter()
+      .find(|table| table.name().eq_ignore_ascii_case(name))
+      .or_else(|| {
+        match SCHEMA_TABLE_NAMES
+          .iter()
+          .any(|schema| schema.eq_ignore_ascii_case(name))
+        {
+          true => schema_table(),
+          false => None,
+        }
+      })
+  }
+
+  pub fn indexes(&self) -> &[IndexSchema] {
+    &self.indexes
+  }
+
+  /// Finds an index by name, ignoring ASCII case.
+  pub fn index(&self, name: &str) -> Option<&IndexSchema> {
+    self
+      .indexes
+      .iter()
+      .find(|index| index.name().eq_ignore_ascii_case(name))
+  }
+
+  /// The indexes of a table, most recently created first, which is the order
+  /// `PRAGMA index_list` reports them in.
+  pub fn index_list(&self, table_name: &str) -> Vec<&IndexSchema> {
+    self
+      .indexes
+      .iter()
+      .rev()
+      .filter(|index| index.table_name().eq_ignore_ascii_case(table_name))
+      .collect()
+  }
+}
@@ -0,0 +1,690 @@
+//! # Schema DDL parsing
+//!
+//!  The `sql` column of sqlite_schema holds a copy of the original CREATE
+//! statement text that created each object, except normalized as follows:
+//!
+//! - The CREATE, TABLE, VIEW, TRIGGER, and INDEX keywords at the beginning of
+//!   the statement are converted to all upper case letters.
+//! - The TEMP or TEMPORARY keyword is removed if it occurs after the initial
+//!   CREATE keyword.
+//! - Any database name qualifier that occurs prior to the name of the object
+//!   being created is removed.
+//! - Leading spaces are removed.
+//! - All spaces following the first two keywords are converted into a single
+//!   space.
+//!
+//!  Everything else is kept verbatim, so the statements still have to be
+//! parsed with the full CREATE grammar.
+//!
+//! *Reference:* https://www.sqlite.org/schematab.html#interpretation_of_the_schema_table
+
+use super::index::{IndexKey, IndexKeyKind, IndexSchema};
+use super::table::{
+  ColumnDef, ConflictClause, DefaultValue, Deferrable, ForeignKeyAction, ForeignKeyClause,
+  GeneratedColumn, GeneratedColumnKind, IndexedColumn, PrimaryKeyClause, SortOrder,
+  TableConstraint, TableConstraintKind, TableSchema,
+};
+use crate::{
+  result::{SqlParsingError, SqliteResult},
+  sql::{tokenize, Token, TokenKind},
+  value::Affinity,
+};
+
+/// Token predicates used by the DDL grammar, which matches keywords by
+/// spelling since words like ROWID and STRICT are not SQL keywords.
+trait DdlToken {
+  fn is_punct(&self, punct: &str) -> bool;
+  fn is_name(&self) -> bool;
+}
+
+impl DdlToken for Token {
+  fn is_punct(&self, punct: &str) -> bool {
+    matches!(self.kind(), TokenKind::Operator(_)) && self.value() == punct
+  }
+
+  fn is_name(&self) -> bool {
+    matches!(
+      self.kind(),
+      TokenKind::Keyword(_)
+        | TokenKind::Identifier
+        | TokenKind::QuotedIdentifier
+        | TokenKind::String
+    )
+  }
+}
+
+/// The types of the columns of STRICT tables, stored in upper case.
+const STANDARD_TYPES: [&str; 6] = ["ANY", "BLOB", "INT", "INTEGER", "REAL", "TEXT"];
+
+/// Keywords that end a column's type name.
+const COLUMN_CONSTRAINT_KEYWORDS: [&str; 11] = [
+  "CONSTRAINT",
+  "PRIMARY",
+  "NOT",
+  "NULL",
+  "UNIQUE",
+  "CHECK",
+  "DEFAULT",
+  "COLLATE",
+  "REFERENCES",
+  "GENERATED",
+  "AS",
+];
+
+/// Keywords that start a table constraint.
+const TABLE_CONSTRAINT_KEYWORDS: [&str; 5] =
+  ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"];
+
+pub(super) struct DdlParser<'a> {
+  sql: &'a str,
+  tokens: Vec<Token>,
+  position: usize,
+}
+
+impl<'a> DdlParser<'a> {
+  pub(super) fn new(sql: &'a str) -> SqliteResult<Self> {
+    Ok(Self {
+      sql,
+      tokens: tokenize(sql)?,
+      position: 0,
+    })
+  }
+
+  /// `CREATE [TEMP|TEMPORARY] TABLE [IF NOT EXISTS] [schema.]name (...)
+  /// [table-options]`
+  pub(super) fn parse_create_table(mut self) -> SqliteResult<TableSchema> {
+    let mut table = TableSchema::default();
+
+    self.expect_keyword("CREATE")?;
+    table.is_temporary = self.eat_keyword("TEMP") || self.eat_keyword("TEMPORARY");
+    self.expect_keyword("TABLE")?;
+    table.if_not_exists = self.parse_if_not_exists()?;
+    let (schema_name, name) = self.parse_qualified_name()?;
+    table.schema_name = schema_name;
+    table.name = name;
+
+    if self.peek_keyword("AS") {
+      return Err(self.error("CREATE TABLE ... AS SELECT is not supported"));
+    }
+    self.expect_punct("(")?;
+    loop {
+      if self.peek().is_some_and(|token| {
+        TABLE_CONSTRAINT_KEYWORDS
+          .iter()
+          .any(|keyword| token.is_word(keyword))
+      }) {
+        break;
+      }
+      table.columns.push(self.parse_column_def(&table)?);
+      if !self.eat_punct(",") {
+        break;
+      }
+    }
+    while !self.peek_punct(")") {
+      table.constraints.push(self.parse_table_constraint()?);
+      // SQLite tolerates table constraints that are not comma separated.
+      self.eat_punct(",");
+    }
+    self.expect_punct(")")?;
+
+    loop {
+      if self.eat_keyword("WITHOUT") {
+        self.expect_keyword("ROWID")?;
+        table.without_rowid = true;
+      } else if self.eat_keyword("STRICT") {
+        table.strict = true;
+      } else {
+        break;
+      }
+      if !self.eat_punct(",") {
+        break;
+      }
+    }
+    self.eat_punct(";");
+    self.expect_end()?;
+
+    if table.strict {
+      for column in table.columns.iter_mut() {
+        if column
+          .declared_type
+          .as_deref()
+          .is_some_and(|declared_type| declared_type.eq_ignore_ascii_case("ANY"))
+        {
+          column.affinity = Affinity::Blob;
+        }
+      }
+    }
+    if table.without_rowid && table.primary_key().is_empty() {
+      return Err(self.error("PRIMARY KEY missing on table"));
+    }
+
+    Ok(table)
+  }
+
+  /// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] [schema.]name ON table
+  /// (indexed-column, ...) [WHERE expr]`
+  pub(super) fn parse_create_index(mut self) -> SqliteResult<IndexSchema> {
+    let mut index = IndexSchema::default();
+
+    self.expect_keyword("CREATE")?;
+    index.unique = self.eat_keyword("UNIQUE");
+    self.expect_keyword("INDEX")?;
+    index.if_not_exists = self.parse_if_not_exists()?;
+    let (schema_name, name) = self.parse_qualified_name()?;
+    index.schema_name = schema_name;
+    index.name = name;
+    self.expect_keyword("ON")?;
+    index.table_name = self.parse_name()?;
+
+    self.expect_punct("(")?;
+    loop {
+      index.columns.push(self.parse_index_key()?);
+      if !self.eat_punct(",") {
+        break;
+      }
+    }
+    self.expect_punct(")")?;
+
+    if self.eat_keyword("WHERE") {
+      let start = match self.peek() {
+        Some(token) => token.span().start,
+        None => return Err(self.error("Expected an expression")),
+      };
+      while self.peek().is_some_and(|token| !token.is_punct(";")) {
+        self.position += 1;
+      }
+      let end = self.tokens[self.position - 1].span().end;
+      index.where_clause = Some(self.sql[start..end].to_string());
+    }
+    self.eat_punct(";");
+    self.expect_end()?;
+
+    Ok(index)
+  }
+
+  /// `(column-name | expr) [COLLATE name] [ASC|DESC]`
+  fn parse_index_key(&mut self) -> SqliteResult<IndexKey> {
+    let first = self.position;
+    let mut depth = 0usize;
+    while let Some(token) = self.peek() {
+      if depth == 0
+        && (token.is_punct(",")
+          || token.is_punct(")")
+          || token.is_word("COLLATE")
+          || token.is_word("ASC")
+          || token.is_word("DESC"))
+      {
+        break;
+      }
+      if token.is_punct("(") {
+        depth += 1;
+      } else if token.is_punct(")") {
+        depth -= 1;
+      }
+      self.position += 1;
+    }
+    let kind = match &self.tokens[first..self.position] {
+      [] => return Err(self.error("Expected an indexed column")),
+      [token] if token.is_name() => IndexKeyKind::Column(token.value().to_string()),
+      tokens => {
+        let (start, end) = (tokens[0].span().start, tokens[tokens.len() - 1].span().end);
+        IndexKeyKind::Expression(self.sql[start..end].to_string())
+      }
+    };
+    let collation = if self.eat_keyword("COLLATE") {
+      Some(self.parse_name()?)
+    } else {
+      None
+    };
+    let order = self.parse_sort_order();
+    Ok(IndexKey {
+      kind,
+      collation,
+      order,
+    })
+  }
+
+  fn parse_column_def(&mut self, table: &TableSchema) -> SqliteResult<ColumnDef> {
+    let name = self.parse_name()?;
+    if table.column(&name).is_some() {
+      return Err(self.error(format!("duplicate column name: {name}")));
+    }
+    let declared_type = self.parse_type_name()?;
+    let mut column = ColumnDef {
+      name,
+      affinity: Affinity::from_declared_type(declared_type.as_deref()),
+      declared_type,
+      ..Default::default()
+    };
+
+    loop {
+      if self.eat_keyword("CONSTRAINT") {
+        self.parse_name()?;
+      }
+      if self.eat_keyword("PRIMARY") {
+        self.expect_keyword("KEY")?;
+        let order = self.parse_sort_order();
+        let conflict = self.parse_conflict_clause()?;
+        let autoincrement = self.eat_keyword("AUTOINCREMENT");
+        column.primary_key = Some(PrimaryKeyClause {
+          order,
+          conflict,
+          autoincrement,
+        });
+      } else if self.eat_keyword("NOT") {
+        self.expect_keyword("NULL")?;
+        column.not_null = Some(self.parse_conflict_clause()?);
+      } else if self.eat_keyword("NULL") {
+        self.parse_conflict_clause()?;
+      } else if self.eat_keyword("UNIQUE") {
+        column.unique = Some(self.parse_conflict_clause()?);
+      } else if self.eat_keyword("CHECK") {
+        column.checks.push(self.parse_parenthesized()?);
+      } else if self.eat_keyword("DEFAULT") {
+        column.default = Some(self.parse_default_value()?);
+      } else if self.eat_keyword("COLLATE") {
+        column.collation = Some(self.parse_name()?);
+      } else if self.eat_keyword("REFERENCES") {
+        column.references = Some(self.parse_foreign_key_clause()?);
+      } else if self.peek_keyword("GENERATED") || self.peek_keyword("AS") {
+        if self.eat_keyword("GENERATED") {
+          self.expect_keyword("ALWAYS")?;
+        }
+        self.expect_keyword("AS")?;
+        let expression = self.parse_parenthesized()?;
+        let kind = if self.eat_keyword("STORED") {
+          GeneratedColumnKind::Stored
+        } else {
+          self.eat_keyword("VIRTUAL");
+          GeneratedColumnKind::Virtual
+        };
+        column.generated = Some(GeneratedColumn { expression, kind });
+      } else {
+        break;
+      }
+    }
+
+    Ok(column)
+  }
+
+  /// `name [name ...] [( signed-number [, signed-number] )]`, returned as the
+  /// text it spans.
+  ///
+  ///  As SQLite stores it, a type that is a single quoted name is dequoted,
+  /// and one of the types of STRICT tables (ANY, BLOB, INT, INTEGER, REAL and
+  /// TEXT) is stored in upper case: `"integer"` and `[INTEGER]` are both the
+  /// type `INTEGER`, and so alias the rowid.
+  ///
+  /// *Reference:* https://www.sqlite.org/stricttables.html
+  fn parse_type_name(&mut self) -> SqliteResult<Option<String>> {
+    let start = match self.peek() {
+      Some(token)
+        if token.is_name()
+          && !COLUMN_CONSTRAINT_KEYWORDS
+            .iter()
+            .any(|keyword| token.is_word(keyword)) =>
+      {
+        token.span().start
+      }
+      _ => return Ok(None),
+    };
+    let mut end = start;
+    while let Some(token) = self.peek() {
+      if !token.is_name()
+        || COLUMN_CONSTRAINT_KEYWORDS
+          .iter()
+          .any(|keyword| token.is_word(keyword))
+      {
+        break;
+      }
+      end = token.span().end;
+      self.position += 1;
+    }
+    let declared_type = if self.peek_punct("(") {
+      self.skip_parenthesized()?;
+      end = self.tokens[self.position - 1].span().end;
+      self.sql[start..end].to_string()
+    } else {
+      match self.tokens.get(self.position - 1) {
+        Some(token) if token.span().start == start => token.value().to_string(),
+        _ => self.sql[start..end].to_string(),
+      }
+    };
+    Ok(Some(
+      STANDARD_TYPES
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(&declared_type))
+        .map_or(declared_type, |name| name.to_string()),
+    ))
+  }
+
+  fn parse_default_value(&mut self) -> SqliteResult<DefaultValue> {
+    if self.peek_punct("(") {
+      return Ok(DefaultValue {
+        sql: self.parse_parenthesized()?,
+        is_expression: true,
+      });
+    }
+    let start = self
+      .peek()
+      .map(|token| token.span().start)
+      .unwrap_or(self.sql.len());
+    if self.eat_punct("+") || self.eat_punct("-") {
+      match self.next() {
+        Some(token) if matches!(token.kind(), TokenKind::Integer | TokenKind::Float) => (),
+        _ => return Err(self.error("Expected a number after the sign")),
+      }
+    } else {
+      match self.next() {
+        Some(token) if !matches!(token.kind(), TokenKind::Operator(_)) => (),
+        _ => return Err(self.error("Expected a default value")),
+      }
+    }
+    let end = self.tokens[self.position - 1].span().end;
+    Ok(DefaultValue {
+      sql: self.sql[start..end].to_string(),
+      is_expression: false,
+    })
+  }
+
+  fn parse_table_constraint(&mut self) -> SqliteResult<TableConstraint> {
+    let name = if self.eat_keyword("CONSTRAINT") {
+      Some(self.parse_name()?)
+    } else {
+      None
+    };
+    let kind = if self.eat_keyword("PRIMARY") {
+      self.expect_keyword("KEY")?;
+      self.expect_punct("(")?;
+      let columns = self.parse_indexed_columns()?;
+      let autoincrement = self.eat_keyword("AUTOINCREMENT");
+      self.expect_punct(")")?;
+      TableConstraintKind::PrimaryKey {
+        columns,
+        conflict: self.parse_conflict_clause()?,
+        autoincrement,
+      }
+    } else if self.eat_keyword("UNIQUE") {
+      self.expect_punct("(")?;
+      let columns = self.parse_indexed_columns()?;
+      self.expect_punct(")")?;
+      TableConstraintKind::Unique {
+        columns,
+        conflict: self.parse_conflict_clause()?,
+      }
+    } else if self.eat_keyword("CHECK") {
+      TableConstraintKind::Check(self.parse_parenthesized()?)
+    } else if self.eat_keyword("FOREIGN") {
+      self.expect_keyword("KEY")?;
+      let columns = self.parse_name_list()?;
+      self.expect_keyword("REFERENCES")?;
+      TableConstraintKind::ForeignKey {
+        columns,
+        clause: self.parse_foreign_key_clause()?,
+      }
+    } else {
+      return Err(self.error("Expected a table constraint"));
+    };
+    Ok(TableConstraint { name, kind })
+  }
+
+  fn parse_indexed_columns(&mut self) -> SqliteResult<Vec<IndexedColumn>> {
+    let mut columns = vec![];
+    loop {
+      let name = self.parse_name()?;
+      let collation = if self.eat_keyword("COLLATE") {
+        Some(self.parse_name()?)
+      } else {
+        None
+      };
+      let order = self.parse_sort_order();
+      columns.push(IndexedColumn {
+        name,
+        collation,
+        order,
+      });
+      if !self.eat_punct(",") {
+        break;
+      }
+    }
+    Ok(columns)
+  }
+
+  /// `REFERENCES` has already been consumed.
+  fn parse_foreign_key_clause(&mut self) -> SqliteResult<ForeignKeyClause> {
+    let mut clause = ForeignKeyClause {
+      foreign_table: self.parse_name()?,
+      ..Default::default()
+    };
+    if self.peek_punct("(") {
+      clause.columns = self.parse_name_list()?;
+    }
+    loop {
+      if self.eat_keyword("ON") {
+        let is_delete = if self.eat_keyword("DELETE") {
+          true
+        } else {
+          self.expect_keyword("UPDATE")?;
+          false
+        };
+        let action = if self.eat_keyword("SET") {
+          if self.eat_keyword("NULL") {
+            ForeignKeyAction::SetNull
+          } else {
+            self.expect_keyword("DEFAULT")?;
+            ForeignKeyAction::SetDefault
+          }
+        } else if self.eat_keyword("CASCADE") {
+          ForeignKeyAction::Cascade
+        } else if self.eat_keyword("RESTRICT") {
+          ForeignKeyAction::Restrict
+        } else {
+          self.expect_keyword("NO")?;
+          self.expect_keyword("ACTION")?;
+          ForeignKeyAction::NoAction
+        };
+        if is_delete {
+          clause.on_delete = Some(action);
+        } else {
+          clause.on_update = Some(action);
+        }
+      } else if self.eat_keyword("MATCH") {
+        clause.match_name = Some(self.parse_name()?);
+      } else {
+        break;
+      }
+    }
+    let is_not = self.peek_keyword("NOT") && self.peek_nth_keyword(1, "DEFERRABLE");
+    if is_not {
+      self.position += 1;
+    }
+    if self.eat_keyword("DEFERRABLE") {
+      let deferrable = if self.eat_keyword("INITIALLY") {
+        if self.eat_keyword("DEFERRED") {
+          Deferrable::InitiallyDeferred
+        } else {
+          self.expect_keyword("IMMEDIATE")?;
+          Deferrable::InitiallyImmediate
+        }
+      } else {
+        Deferrable::Deferrable
+      };
+      clause.deferrable = Some(if is_not {
+        Deferrable::NotDeferrable
+      } else {
+        deferrable
+      });
+    }
+    Ok(clause)
+  }
+
+  fn parse_conflict_clause(&mut self) -> SqliteResult<ConflictClause> {
+    if !(self.peek_keyword("ON") && self.peek_nth_keyword(1, "CONFLICT")) {
+      return Ok(ConflictClause::default());
+    }
+    self.position += 2;
+    let conflict = if self.eat_keyword("ROLLBACK") {
+      ConflictClause::Rollback
+    } else if self.eat_keyword("ABORT") {
+      ConflictClause::Abort
+    } else if self.eat_keyword("FAIL") {
+      ConflictClause::Fail
+    } else if self.eat_keyword("IGNORE") {
+      ConflictClause::Ignore
+    } else if self.eat_keyword("REPLACE") {
+      ConflictClause::Replace
+    } else {
+      return Err(self.error("Expected a conflict resolution algorithm"));
+    };
+    Ok(conflict)
+  }
+
+  fn parse_sort_order(&mut self) -> Option<SortOrder> {
+    if self.eat_keyword("ASC") {
+      Some(SortOrder::Asc)
+    } else if self.eat_keyword("DESC") {
+      Some(SortOrder::Desc)
+    } else {
+      None
+    }
+  }
+
+  fn parse_if_not_exists(&mut self) -> SqliteResult<bool> {
+    if self.eat_keyword("IF") {
+      self.expect_keyword("NOT")?;
+      self.expect_keyword("EXISTS")?;
+      Ok(true)
+    } else {
+      Ok(false)
+    }
+  }
+
+  /// `[schema.]name`
+  fn parse_qualified_name(&mut self) -> SqliteResult<(Option<String>, String)> {
+    let name = self.parse_name()?;
+    if self.eat_punct(".") {
+      Ok((Some(name), self.parse_name()?))
+    } else {
+      Ok((None, name))
+    }
+  }
+
+  /// `( name, ... )`
+  fn parse_name_list(&mut self) -> SqliteResult<Vec<String>> {
+    self.expect_punct("(")?;
+    let mut names = vec![self.parse_name()?];
+    while self.eat_punct(",") {
+      names.push(self.parse_name()?);
+    }
+    self.expect_punct(")")?;
+    Ok(names)
+  }
+
+  fn parse_name(&mut self) -> SqliteResult<String> {
+    match self.peek() {
+      Some(token) if token.is_name() => {
+        let value = token.value().to_string();
+        self.position += 1;
+        Ok(value)
+      }
+      _ => Err(self.error("Expected a name")),
+    }
+  }
+
+  /// Consumes a parenthesized expression and returns its inner SQL text.
+  fn parse_parenthesized(&mut self) -> SqliteResult<String> {
+    let open = self.position;
+    self.skip_parenthesized()?;
+    let start = self.tokens[open].span().end;
+    let end = self.tokens[self.position - 1].span().start;
+    Ok(self.sql[start..end].trim().to_string())
+  }
+
+  fn skip_parenthesized(&mut self) -> SqliteResult<()> {
+    self.expect_punct("(")?;
+    let mut depth = 1;
+    while depth > 0 {
+      match self.next() {
+        Some(token) if token.is_punct("(") => depth += 1,
+        Some(token) if token.is_punct(")") => depth -= 1,
+        Some(_) => (),
+        None => return Err(self.error("Unbalanced parentheses")),
+      }
+    }
+    Ok(())
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn next(&mut self) -> Option<&Token> {
+    let token = self.tokens.get(self.position);
+    if token.is_some() {
+      self.position += 1;
+    }
+    token
+  }
+
+  fn peek_keyword(&self, keyword: &str) -> bool {
+    self.peek_nth_keyword(0, keyword)
+  }
+
+  fn peek_nth_keyword(&self, n: usize, keyword: &str) -> bool {
+    self
+      .tokens
+      .get(self.position + n)
+      .is_some_and(|token| token.is_word(keyword))
+  }
+
+  fn peek_punct(&self, punct: &str) -> bool {
+    self.peek().is_some_and(|token| token.is_punct(punct))
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    let is_match = self.peek_keyword(keyword);
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn eat_punct(&mut self, punct: &str) -> bool {
+    let is_match = self.peek_punct(punct);
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn expect_keyword(&mut self, keyword: &str) -> SqliteResult<()> {
+    if self.eat_keyword(keyword) {
+      Ok(())
+    } else {
+      Err(self.error(format!("Expected `{keyword}`")))
+    }
+  }
+
+  fn expect_punct(&mut self, punct: &str) -> SqliteResult<()> {
+    if self.eat_punct(punct) {
+      Ok(())
+    } else {
+      Err(self.error(format!("Expected `{punct}`")))
+    }
+  }
+
+  fn expect_end(&self) -> SqliteResult<()> {
+    match self.peek() {
+      None => Ok(()),
+      Some(token) => Err(self.error(format!("Unexpected `{}`", token.value()))),
+    }
+  }
+
+  fn error(&self, message: impl Into<String>) -> crate::result::SqliteError {
+    let offset = self
+      .peek()
+      .map(|token| token.span().start)
+      .unwrap_or(self.sql.len());
+    SqlParsingError::new(message, self.sql, offset).into()
+  }
+}
@@ -0,0 +1,230 @@
+use super::{ddl::DdlParser, table::TableSchema, SortOrder, TableConstraintKind};
+use crate::result::SqliteResult;
+
+/// Prefix of the names SQLite gives to the indexes it creates on its own for
+/// UNIQUE and PRIMARY KEY constraints.
+pub(super) const AUTOINDEX_PREFIX: &str = "sqlite_autoindex_";
+
+/// The parsed definition of an index, either from a CREATE INDEX statement or
+/// implied by a UNIQUE or PRIMARY KEY constraint of its table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexSchema {
+  pub(super) name: String,
+  pub(super) schema_name: Option<String>,
+  pub(super) table_name: String,
+  pub(super) root_page: u32,
+  pub(super) unique: bool,
+  pub(super) if_not_exists: bool,
+  pub(super) columns: Vec<IndexKey>,
+  pub(super) where_clause: Option<String>,
+  pub(super) origin: IndexOrigin,
+}
+
+impl IndexSchema {
+  /// Parses a `CREATE [UNIQUE] INDEX` statement.
+  pub fn parse(sql: &str) -> SqliteResult<Self> {
+    DdlParser::new(sql)?.parse_create_index()
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The schema qualifier of the CREATE statement, if any.
+  pub fn schema_name(&self) -> Option<&str> {
+    self.schema_name.as_deref()
+  }
+
+  pub fn table_name(&self) -> &str {
+    &self.table_name
+  }
+
+  pub fn root_page(&self) -> u32 {
+    self.root_page
+  }
+
+  pub fn is_unique(&self) -> bool {
+    self.unique
+  }
+
+  pub fn if_not_exists(&self) -> bool {
+    self.if_not_exists
+  }
+
+  pub fn columns(&self) -> &[IndexKey] {
+    &self.columns
+  }
+
+  /// The WHERE expression of a partial index, as SQL text.
+  pub fn where_clause(&self) -> Option<&str> {
+    self.where_clause.as_deref()
+  }
+
+  pub fn is_partial(&self) -> bool {
+    self.where_clause.is_some()
+  }
+
+  pub fn origin(&self) -> IndexOrigin {
+    self.origin
+  }
+
+  /// The indexes SQLite creates on its own for the UNIQUE and PRIMARY KEY
+  /// constraints of `table`, named `sqlite_autoindex_TABLE_N` in order of
+  /// appearance.
+  ///
+  ///  A constraint over the same columns and collations as an earlier one
+  /// does not get an index of its own; when it is the PRIMARY KEY, the
+  /// earlier index becomes the primary key index. An INTEGER PRIMARY KEY that
+  /// aliases the rowid needs no index at all.
+  ///
+  /// *Reference:* https://www.sqlite.org/fileformat2.html#representation_of_sql_indices
+  pub(super) fn implicit_indexes(table: &TableSchema) -> Vec<Self> {
+    let mut indexes: Vec<Self> = vec![];
+    let mut add = |columns: Vec<IndexKey>, origin: IndexOrigin| {
+      let collation = |key: &IndexKey| {
+        key
+          .collation()
+          .or_else(|| {
+            key
+              .column_name()
+              .and_then(|name| table.column(name))
+              .and_then(|(_, column)| column.collation())
+          })
+          .unwrap_or("BINARY")
+          .to_ascii_uppercase()
+      };
+      let duplicate = indexes.iter_mut().find(|index| {
+        index.columns.len() == columns.len()
+          && index.columns.iter().zip(&columns).all(|(a, b)| {
+            a.column_name()
+              .zip(b.column_name())
+              .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+              && collation(a) == collation(b)
+          })
+      });
+      match duplicate {
+        Some(index) => {
+          if origin == IndexOrigin::PrimaryKey {
+            index.origin = origin;
+          }
+        }
+        None => {
+          let number = indexes.len() + 1;
+          indexes.push(Self {
+            name: format!("{AUTOINDEX_PREFIX}{}_{number}", table.name()),
+            schema_name: table.schema_name().map(String::from),
+            table_name: table.name().to_string(),
+            unique: true,
+            columns,
+            origin,
+            ..Default::default()
+          });
+        }
+      }
+    };
+
+    let rowid_alias = table.rowid_alias();
+    for (index, column) in table.columns().iter().enumerate() {
+      let key = || {
+        vec![IndexKey {
+          kind: IndexKeyKind::Column(column.name().to_string()),
+          ..Default::default()
+        }]
+      };
+      if column.is_unique() {
+        add(key(), IndexOrigin::Unique);
+      }
+      if let Some(pk) = column.primary_key() {
+        if rowid_alias != Some(index) {
+          let mut key = key();
+          key[0].order = pk.order();
+          add(key, IndexOrigin::PrimaryKey);
+        }
+      }
+    }
+    for constraint in table.constraints() {
+      let (columns, origin) = match constraint.kind() {
+        TableConstraintKind::PrimaryKey { .. } if rowid_alias.is_some() => continue,
+        TableConstraintKind::PrimaryKey { columns, .. } => (columns, IndexOrigin::PrimaryKey),
+        TableConstraintKind::Unique { columns, .. } => (columns, IndexOrigin::Unique),
+        _ => continue,
+      };
+      let keys = columns
+        .iter()
+        .map(|column| IndexKey {
+          kind: IndexKeyKind::Column(column.name().to_string()),
+          collation: column.collation().map(String::from),
+          order: column.order(),
+        })
+        .collect();
+      add(keys, origin);
+    }
+    indexes
+  }
+}
+
+/// How an index came to exist, as reported by `PRAGMA index_list`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexOrigin {
+  /// A CREATE INDEX statement.
+  #[default]
+  CreateIndex,
+  /// A UNIQUE constraint.
+  Unique,
+  /// A PRIMARY KEY constraint.
+  PrimaryKey,
+}
+
+impl std::fmt::Display for IndexOrigin {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let origin = match self {
+      Self::CreateIndex => "c",
+      Self::Unique => "u",
+      Self::PrimaryKey => "pk",
+    };
+    write!(f, "{origin}")
+  }
+}
+
+/// A key term of an index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexKey {
+  pub(super) kind: IndexKeyKind,
+  pub(super) collation: Option<String>,
+  pub(super) order: Option<SortOrder>,
+}
+
+impl IndexKey {
+  pub fn kind(&self) -> &IndexKeyKind {
+    &self.kind
+  }
+
+  /// The indexed column, unless the key is an expression.
+  pub fn column_name(&self) -> Option<&str> {
+    match &self.kind {
+      IndexKeyKind::Column(name) => Some(name),
+      IndexKeyKind::Expression(_) => None,
+    }
+  }
+
+  pub fn collation(&self) -> Option<&str> {
+    self.collation.as_deref()
+  }
+
+  pub fn order(&self) -> Option<SortOrder> {
+    self.order
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexKeyKind {
+  Column(String),
+  /// An expression key, as SQL text.
+  Expression(String),
+}
+
+impl Default for IndexKeyKind {
+  fn default() -> Self {
+    Self::Column(String::new())
+  }
+}
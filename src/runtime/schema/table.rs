@@ -0,0 +1,479 @@
+//! # CREATE TABLE
+//!
+//!  The "CREATE TABLE" command is used to create a new table in an SQLite
+//! database. A CREATE TABLE command specifies the following attributes of the
+//! new table:
+//!
+//! - The name of the new table.
+//! - The database in which the new table is created.
+//! - The name of each column in the table.
+//! - The declared type of each column in the table.
+//! - A default value or expression for each column in the table.
+//! - A default collation sequence to use with each column.
+//! - Optionally, a PRIMARY KEY for the table.
+//! - A set of SQL constraints for each table. SQLite supports UNIQUE, NOT NULL,
+//!   CHECK and FOREIGN KEY constraints.
+//! - Optionally, a generated column constraint.
+//! - Whether the table is a WITHOUT ROWID table.
+//! - Whether the table is subject to strict type checking.
+//!
+//! *Reference:* https://www.sqlite.org/lang_createtable.html
+
+use super::ddl::DdlParser;
+use crate::{result::SqliteResult, value::Affinity};
+
+/// The parsed form of a `CREATE TABLE` statement.
+#[derive(Debug, Clone, Default)]
+pub struct TableSchema {
+  pub(super) name: String,
+  pub(super) schema_name: Option<String>,
+  pub(super) root_page: u32,
+  pub(super) is_temporary: bool,
+  pub(super) if_not_exists: bool,
+  pub(super) columns: Vec<ColumnDef>,
+  pub(super) constraints: Vec<TableConstraint>,
+  pub(super) without_rowid: bool,
+  pub(super) strict: bool,
+}
+
+impl TableSchema {
+  /// Parses a `CREATE TABLE` statement, as stored in sqlite_schema.
+  pub fn parse(sql: &str) -> SqliteResult<Self> {
+    DdlParser::new(sql)?.parse_create_table()
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The schema qualifier written before the table name, if any.
+  pub fn schema_name(&self) -> Option<&str> {
+    self.schema_name.as_deref()
+  }
+
+  /// Page number of the root b-tree page, as recorded in sqlite_schema.
+  pub fn root_page(&self) -> u32 {
+    self.root_page
+  }
+
+  pub fn is_temporary(&self) -> bool {
+    self.is_temporary
+  }
+
+  pub fn if_not_exists(&self) -> bool {
+    self.if_not_exists
+  }
+
+  pub fn columns(&self) -> &[ColumnDef] {
+    &self.columns
+  }
+
+  pub fn constraints(&self) -> &[TableConstraint] {
+    &self.constraints
+  }
+
+  pub fn is_without_rowid(&self) -> bool {
+    self.without_rowid
+  }
+
+  pub fn is_strict(&self) -> bool {
+    self.strict
+  }
+
+  /// Finds a column by name, ignoring ASCII case like SQLite does.
+  pub fn column(&self, name: &str) -> Option<(usize, &ColumnDef)> {
+    self
+      .columns
+      .iter()
+      .enumerate()
+      .find(|(_, column)| column.name.eq_ignore_ascii_case(name))
+  }
+
+  /// Indexes of the PRIMARY KEY columns, in key order, with their sort
+  /// order. Empty when the table has no explicit PRIMARY KEY.
+  pub fn primary_key(&self) -> Vec<(usize, SortOrder)> {
+    if let Some(index) = self
+      .columns
+      .iter()
+      .position(|column| column.primary_key.is_some())
+    {
+      let order = self.columns[index]
+        .primary_key
+        .as_ref()
+        .and_then(|pk| pk.order)
+        .unwrap_or_default();
+      return vec![(index, order)];
+    }
+    self
+      .constraints
+      .iter()
+      .find_map(|constraint| match &constraint.kind {
+        TableConstraintKind::PrimaryKey { columns, .. } => Some(
+          columns
+            .iter()
+            .filter_map(|indexed| {
+              self
+                .column(&indexed.name)
+                .map(|(index, _)| (index, indexed.order.unwrap_or_default()))
+            })
+            .collect(),
+        ),
+        _ => None,
+      })
+      .unwrap_or_default()
+  }
+
+  /// Index of the column that is an alias for the rowid, if any.
+  ///
+  ///  In a rowid table, a column with the declared type "INTEGER" (exactly,
+  /// in any case) that is the only PRIMARY KEY column becomes an alias for
+  /// the rowid. The value of that column is not stored in the record: it is
+  /// the rowid of the row. The quirky exception is
+  /// `INTEGER PRIMARY KEY DESC` as a column constraint, which does not create
+  /// an alias.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_createtable.html#rowid
+  pub fn rowid_alias(&self) -> Option<usize> {
+    if self.without_rowid {
+      return None;
+    }
+    let is_integer = |index: usize| {
+      self.columns[index]
+        .declared_type
+        .as_deref()
+        .is_some_and(|declared_type| declared_type.eq_ignore_ascii_case("INTEGER"))
+    };
+
+    if let Some(index) = self
+      .columns
+      .iter()
+      .position(|column| column.primary_key.is_some())
+    {
+      let is_desc = self.columns[index]
+        .primary_key
+        .as_ref()
+        .is_some_and(|pk| pk.order == Some(SortOrder::Desc));
+      return (is_integer(index) && !is_desc).then_some(index);
+    }
+
+    match self.primary_key().as_slice() {
+      [(index, _)] if is_integer(*index) => Some(*index),
+      _ => None,
+    }
+  }
+
+  /// Column indexes in the order their values are stored in the records of
+  /// the table b-tree.
+  ///
+  ///  VIRTUAL generated columns are computed when read and never stored. The
+  /// records of a WITHOUT ROWID table start with the PRIMARY KEY columns, in
+  /// key order, followed by the remaining columns in declaration order.
+  ///
+  /// *Reference:* https://www.sqlite.org/withoutrowid.html
+  pub fn storage_order(&self) -> Vec<usize> {
+    let is_stored = |index: &usize| {
+      !matches!(
+        self.columns[*index].generated,
+        Some(GeneratedColumn {
+          kind: GeneratedColumnKind::Virtual,
+          ..
+        })
+      )
+    };
+    let mut order: Vec<usize> = vec![];
+    if self.without_rowid {
+      for (index, _) in self.primary_key() {
+        if !order.contains(&index) {
+          order.push(index);
+        }
+      }
+    }
+    for index in (0..self.columns.len()).filter(is_stored) {
+      if !order.contains(&index) {
+        order.push(index);
+      }
+    }
+    order
+  }
+
+  /// Whether the PRIMARY KEY was declared with AUTOINCREMENT.
+  pub fn is_autoincrement(&self) -> bool {
+    self
+      .columns
+      .iter()
+      .filter_map(|column| column.primary_key.as_ref())
+      .any(|pk| pk.autoincrement)
+      || self.constraints.iter().any(|constraint| {
+        matches!(
+          constraint.kind,
+          TableConstraintKind::PrimaryKey {
+            autoincrement: true,
+            ..
+          }
+        )
+      })
+  }
+}
+
+/// A column definition of a `CREATE TABLE` statement.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnDef {
+  pub(super) name: String,
+  pub(super) declared_type: Option<String>,
+  pub(super) affinity: Affinity,
+  pub(super) primary_key: Option<PrimaryKeyClause>,
+  pub(super) not_null: Option<ConflictClause>,
+  pub(super) unique: Option<ConflictClause>,
+  pub(super) default: Option<DefaultValue>,
+  pub(super) collation: Option<String>,
+  pub(super) checks: Vec<String>,
+  pub(super) references: Option<ForeignKeyClause>,
+  pub(super) generated: Option<GeneratedColumn>,
+}
+
+impl ColumnDef {
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The declared type, exactly as written in the statement.
+  pub fn declared_type(&self) -> Option<&str> {
+    self.declared_type.as_deref()
+  }
+
+  pub fn affinity(&self) -> Affinity {
+    self.affinity
+  }
+
+  pub fn primary_key(&self) -> Option<&PrimaryKeyClause> {
+    self.primary_key.as_ref()
+  }
+
+  pub fn is_not_null(&self) -> bool {
+    self.not_null.is_some()
+  }
+
+  pub fn is_unique(&self) -> bool {
+    self.unique.is_some()
+  }
+
+  pub fn default(&self) -> Option<&DefaultValue> {
+    self.default.as_ref()
+  }
+
+  pub fn collation(&self) -> Option<&str> {
+    self.collation.as_deref()
+  }
+
+  /// CHECK expressions, as SQL text.
+  pub fn checks(&self) -> &[String] {
+    &self.checks
+  }
+
+  pub fn references(&self) -> Option<&ForeignKeyClause> {
+    self.references.as_ref()
+  }
+
+  pub fn generated(&self) -> Option<&GeneratedColumn> {
+    self.generated.as_ref()
+  }
+}
+
+/// `ASC` or `DESC`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+  #[default]
+  Asc,
+  Desc,
+}
+
+/// The `ON CONFLICT` algorithm of a constraint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictClause {
+  Rollback,
+  #[default]
+  Abort,
+  Fail,
+  Ignore,
+  Replace,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrimaryKeyClause {
+  pub(super) order: Option<SortOrder>,
+  pub(super) conflict: ConflictClause,
+  pub(super) autoincrement: bool,
+}
+
+impl PrimaryKeyClause {
+  pub fn order(&self) -> Option<SortOrder> {
+    self.order
+  }
+
+  pub fn conflict(&self) -> ConflictClause {
+    self.conflict
+  }
+
+  pub fn is_autoincrement(&self) -> bool {
+    self.autoincrement
+  }
+}
+
+/// The `DEFAULT` clause of a column, kept as SQL text.
+///
+///  The text is the literal as written (`'x'`, `-5`, `x'ab'`, `NULL`,
+/// `CURRENT_TIMESTAMP`, ...), or the inner text of a parenthesized
+/// expression, matching the `dflt_value` column of `PRAGMA table_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultValue {
+  pub(super) sql: String,
+  pub(super) is_expression: bool,
+}
+
+impl DefaultValue {
+  pub fn sql(&self) -> &str {
+    &self.sql
+  }
+
+  /// `true` for `DEFAULT (expr)`, `false` for literals and identifiers.
+  pub fn is_expression(&self) -> bool {
+    self.is_expression
+  }
+}
+
+/// `GENERATED ALWAYS AS (expr) [VIRTUAL|STORED]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedColumn {
+  pub(super) expression: String,
+  pub(super) kind: GeneratedColumnKind,
+}
+
+impl GeneratedColumn {
+  pub fn expression(&self) -> &str {
+    &self.expression
+  }
+
+  pub fn kind(&self) -> GeneratedColumnKind {
+    self.kind
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeneratedColumnKind {
+  #[default]
+  Virtual,
+  Stored,
+}
+
+/// A `REFERENCES` clause.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForeignKeyClause {
+  pub(super) foreign_table: String,
+  pub(super) columns: Vec<String>,
+  pub(super) on_delete: Option<ForeignKeyAction>,
+  pub(super) on_update: Option<ForeignKeyAction>,
+  pub(super) match_name: Option<String>,
+  pub(super) deferrable: Option<Deferrable>,
+}
+
+impl ForeignKeyClause {
+  pub fn foreign_table(&self) -> &str {
+    &self.foreign_table
+  }
+
+  /// The referenced columns. Empty means the parent's PRIMARY KEY.
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  pub fn on_delete(&self) -> Option<ForeignKeyAction> {
+    self.on_delete
+  }
+
+  pub fn on_update(&self) -> Option<ForeignKeyAction> {
+    self.on_update
+  }
+
+  pub fn match_name(&self) -> Option<&str> {
+    self.match_name.as_deref()
+  }
+
+  pub fn deferrable(&self) -> Option<Deferrable> {
+    self.deferrable
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignKeyAction {
+  SetNull,
+  SetDefault,
+  Cascade,
+  Restrict,
+  NoAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deferrable {
+  NotDeferrable,
+  Deferrable,
+  InitiallyDeferred,
+  InitiallyImmediate,
+}
+
+/// A column of a PRIMARY KEY or UNIQUE table constraint.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexedColumn {
+  pub(super) name: String,
+  pub(super) collation: Option<String>,
+  pub(super) order: Option<SortOrder>,
+}
+
+impl IndexedColumn {
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn collation(&self) -> Option<&str> {
+    self.collation.as_deref()
+  }
+
+  pub fn order(&self) -> Option<SortOrder> {
+    self.order
+  }
+}
+
+/// A table constraint, optionally named with `CONSTRAINT name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableConstraint {
+  pub(super) name: Option<String>,
+  pub(super) kind: TableConstraintKind,
+}
+
+impl TableConstraint {
+  pub fn name(&self) -> Option<&str> {
+    self.name.as_deref()
+  }
+
+  pub fn kind(&self) -> &TableConstraintKind {
+    &self.kind
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableConstraintKind {
+  PrimaryKey {
+    columns: Vec<IndexedColumn>,
+    conflict: ConflictClause,
+    autoincrement: bool,
+  },
+  Unique {
+    columns: Vec<IndexedColumn>,
+    conflict: ConflictClause,
+  },
+  /// The CHECK expression, as SQL text.
+  Check(String),
+  ForeignKey {
+    columns: Vec<String>,
+    clause: ForeignKeyClause,
+  },
+}
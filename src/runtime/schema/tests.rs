@@ -0,0 +1,369 @@
+//! Tests for the schema parser
+//!
+//! To run: `cargo test runtime::schema`
+
+use super::{
+  ConflictClause, Deferrable, ForeignKeyAction, GeneratedColumnKind, IndexKeyKind, IndexOrigin,
+  IndexSchema, SortOrder, TableConstraintKind, TableSchema,
+};
+use crate::{runtime::SchemaObjectType, value::Affinity, SqliteConnection};
+
+#[test]
+fn ok_on_parse_columns_and_affinities() {
+  let table = TableSchema::parse(
+    "CREATE TABLE t(a INT, b VARCHAR(10), c CLOB, d BLOB, e, f DOUBLE PRECISION, \
+     g DECIMAL(10, 5), h CHARINT, i FLOATING POINT, j unsigned   big int)",
+  )
+  .unwrap();
+
+  let affinities: Vec<_> = table.columns().iter().map(|c| c.affinity()).collect();
+  assert_eq!(
+    affinities,
+    [
+      Affinity::Integer,
+      Affinity::Text,
+      Affinity::Text,
+      Affinity::Blob,
+      Affinity::Blob,
+      Affinity::Real,
+      Affinity::Numeric,
+      Affinity::Integer,
+      Affinity::Integer,
+      Affinity::Integer,
+    ]
+  );
+  assert_eq!(table.columns()[1].declared_type(), Some("VARCHAR(10)"));
+  assert_eq!(table.columns()[4].declared_type(), None);
+  assert_eq!(table.columns()[6].declared_type(), Some("DECIMAL(10, 5)"));
+  assert_eq!(
+    table.columns()[9].declared_type(),
+    Some("unsigned   big int")
+  );
+}
+
+#[test]
+fn ok_on_normalize_declared_types() {
+  let table = TableSchema::parse(
+    r#"CREATE TABLE t(a int, b "INTEGER", c [text], d `Real`, e "my type", f Blob(4), g any)"#,
+  )
+  .unwrap();
+  let declared_types: Vec<_> = table.columns().iter().map(|c| c.declared_type()).collect();
+  assert_eq!(
+    declared_types,
+    [
+      Some("INT"),
+      Some("INTEGER"),
+      Some("TEXT"),
+      Some("REAL"),
+      Some("my type"),
+      Some("Blob(4)"),
+      Some("ANY"),
+    ]
+  );
+}
+
+#[test]
+fn ok_on_parse_column_constraints() {
+  let table = TableSchema::parse(
+    r#"CREATE TABLE IF NOT EXISTS "my ""table""" (
+      [id] INTEGER NOT NULL CONSTRAINT pk PRIMARY KEY ASC ON CONFLICT REPLACE AUTOINCREMENT,
+      `name` TEXT DEFAULT 'it''s' COLLATE NOCASE UNIQUE,
+      qty INTEGER DEFAULT -5 CHECK (qty > 0) CHECK(qty < 100),
+      created TEXT DEFAULT CURRENT_TIMESTAMP,
+      total REAL DEFAULT (qty * 1.5),
+      owner_id INTEGER REFERENCES owner(id) ON DELETE CASCADE ON UPDATE SET NULL
+        DEFERRABLE INITIALLY DEFERRED,
+      doubled INTEGER GENERATED ALWAYS AS (qty * 2) STORED,
+      tripled AS (qty * 3)
+    )"#,
+  )
+  .unwrap();
+
+  assert_eq!(table.name(), r#"my "table""#);
+  assert!(table.if_not_exists());
+
+  let id = &table.columns()[0];
+  assert_eq!(id.name(), "id");
+  assert!(id.is_not_null());
+  let pk = id.primary_key().unwrap();
+  assert_eq!(pk.order(), Some(SortOrder::Asc));
+  assert_eq!(pk.conflict(), ConflictClause::Replace);
+  assert!(pk.is_autoincrement());
+  assert!(table.is_autoincrement());
+
+  let name = &table.columns()[1];
+  assert_eq!(name.default().unwrap().sql(), "'it''s'");
+  assert_eq!(name.collation(), Some("NOCASE"));
+  assert!(name.is_unique());
+
+  let qty = &table.columns()[2];
+  assert_eq!(qty.default().unwrap().sql(), "-5");
+  assert_eq!(qty.checks(), ["qty > 0", "qty < 100"]);
+
+  assert_eq!(
+    table.columns()[3].default().unwrap().sql(),
+    "CURRENT_TIMESTAMP"
+  );
+  let total = table.columns()[4].default().unwrap();
+  assert_eq!(total.sql(), "qty * 1.5");
+  assert!(total.is_expression());
+
+  let references = table.columns()[5].references().unwrap();
+  assert_eq!(references.foreign_table(), "owner");
+  assert_eq!(references.columns(), ["id"]);
+  assert_eq!(references.on_delete(), Some(ForeignKeyAction::Cascade));
+  assert_eq!(references.on_update(), Some(ForeignKeyAction::SetNull));
+  assert_eq!(references.deferrable(), Some(Deferrable::InitiallyDeferred));
+
+  let doubled = table.columns()[6].generated().unwrap();
+  assert_eq!(doubled.expression(), "qty * 2");
+  assert_eq!(doubled.kind(), GeneratedColumnKind::Stored);
+  let tripled = table.columns()[7].generated().unwrap();
+  assert_eq!(tripled.kind(), GeneratedColumnKind::Virtual);
+  assert_eq!(table.columns()[7].declared_type(), None);
+}
+
+#[test]
+fn ok_on_parse_table_constraints_and_options() {
+  let table = TableSchema::parse(
+    "CREATE TABLE t(a TEXT, b INT, c ANY, \
+     CONSTRAINT pk PRIMARY KEY (a COLLATE NOCASE DESC, b), \
+     UNIQUE (b) ON CONFLICT IGNORE \
+     CHECK (b <> 0), \
+     FOREIGN KEY (b) REFERENCES other NOT DEFERRABLE) WITHOUT ROWID, STRICT",
+  )
+  .unwrap();
+
+  assert!(table.is_without_rowid());
+  assert!(table.is_strict());
+  assert_eq!(table.columns()[2].affinity(), Affinity::Blob);
+  assert_eq!(
+    table.primary_key(),
+    [(0, SortOrder::Desc), (1, SortOrder::Asc)]
+  );
+  assert_eq!(table.rowid_alias(), None);
+
+  let constraints = table.constraints();
+  assert_eq!(constraints.len(), 4);
+  assert_eq!(constraints[0].name(), Some("pk"));
+  match constraints[0].kind() {
+    TableConstraintKind::PrimaryKey { columns, .. } => {
+      assert_eq!(columns[0].collation(), Some("NOCASE"));
+      assert_eq!(columns[0].order(), Some(SortOrder::Desc));
+    }
+    kind => panic!("Unexpected constraint {kind:?}"),
+  }
+  match constraints[1].kind() {
+    TableConstraintKind::Unique { conflict, .. } => {
+      assert_eq!(*conflict, ConflictClause::Ignore)
+    }
+    kind => panic!("Unexpected constraint {kind:?}"),
+  }
+  assert_eq!(
+    *constraints[2].kind(),
+    TableConstraintKind::Check("b <> 0".into())
+  );
+  match constraints[3].kind() {
+    TableConstraintKind::ForeignKey { columns, clause } => {
+      assert_eq!(columns, &["b"]);
+      assert_eq!(clause.foreign_table(), "other");
+      assert_eq!(clause.deferrable(), Some(Deferrable::NotDeferrable));
+    }
+    kind => panic!("Unexpected constraint {kind:?}"),
+  }
+}
+
+#[test]
+fn ok_on_rowid_alias_rules() {
+  let cases = [
+    ("CREATE TABLE t(id INTEGER PRIMARY KEY, v)", Some(0)),
+    ("CREATE TABLE t(v, id integer primary key asc)", Some(1)),
+    ("CREATE TABLE t(id INTEGER PRIMARY KEY DESC, v)", None),
+    ("CREATE TABLE t(id INT PRIMARY KEY, v)", None),
+    (
+      "CREATE TABLE t(id INTEGER, v, PRIMARY KEY(id DESC))",
+      Some(0),
+    ),
+    (
+      r#"CREATE TABLE t("id" INTEGER, PRIMARY KEY("id" AUTOINCREMENT))"#,
+      Some(0),
+    ),
+    ("CREATE TABLE t(id INTEGER, v, PRIMARY KEY(id, v))", None),
+    (
+      "CREATE TABLE t(id INTEGER PRIMARY KEY, v) WITHOUT ROWID",
+      None,
+    ),
+    ("CREATE TABLE t(id, v)", None),
+    (r#"CREATE TABLE t(id "INTEGER" PRIMARY KEY, v)"#, Some(0)),
+    ("CREATE TABLE t(id [integer] PRIMARY KEY, v)", Some(0)),
+    ("CREATE TABLE t(id `Integer` PRIMARY KEY, v)", Some(0)),
+  ];
+  for (sql, expected) in cases {
+    let table = TableSchema::parse(sql).unwrap();
+    assert_eq!(table.rowid_alias(), expected, "{sql}");
+  }
+}
+
+#[test]
+fn err_on_invalid_create_table() {
+  let cases = [
+    "CREATE TABLE t",
+    "CREATE TABLE t(a, a)",
+    "CREATE TABLE t(a) WITHOUT ROWID",
+    "CREATE TABLE t AS SELECT 1",
+    "CREATE VIEW v AS SELECT 1",
+    "CREATE TABLE t(a CHECK (a > 0)",
+  ];
+  for sql in cases {
+    assert!(TableSchema::parse(sql).is_err(), "{sql}");
+  }
+}
+
+#[test]
+fn ok_on_load_schema_from_database() {
+  let conn = SqliteConnection::open("sqlite://./data/mydatabase.db").unwrap();
+  let schema = conn.schema();
+
+  assert_eq!(schema.objects().len(), 5);
+  assert_eq!(
+    schema
+      .objects()
+      .iter()
+      .filter(|object| object.object_type() == SchemaObjectType::Index)
+      .count(),
+    1
+  );
+
+  let names: Vec<_> = schema.tables().iter().map(|t| t.name()).collect();
+  assert_eq!(names, ["Observation", "Month", "users", "sqlite_sequence"]);
+
+  let users = schema.table("USERS").unwrap();
+  assert_eq!(users.root_page(), 4);
+  assert_eq!(users.rowid_alias(), Some(0));
+  assert!(users.is_autoincrement());
+  assert!(users.columns()[0].is_unique());
+
+  let observation = schema.table("observation").unwrap();
+  assert_eq!(observation.columns().len(), 3);
+  assert_eq!(observation.columns()[0].declared_type(), Some("BIGINT"));
+  assert_eq!(observation.columns()[0].affinity(), Affinity::Integer);
+  assert_eq!(observation.rowid_alias(), None);
+}
+
+#[test]
+fn ok_on_parse_create_index() {
+  let index = IndexSchema::parse(
+    "CREATE UNIQUE INDEX IF NOT EXISTS main.idx ON \"t\" \
+     (a, \"b c\" COLLATE NOCASE DESC, lower(d) ASC, e + (f * 2)) WHERE a > 0 AND e IS NOT NULL;",
+  )
+  .unwrap();
+
+  assert_eq!(index.name(), "idx");
+  assert_eq!(index.schema_name(), Some("main"));
+  assert_eq!(index.table_name(), "t");
+  assert!(index.is_unique());
+  assert!(index.if_not_exists());
+  assert_eq!(index.origin(), IndexOrigin::CreateIndex);
+  assert_eq!(index.where_clause(), Some("a > 0 AND e IS NOT NULL"));
+
+  let columns = index.columns();
+  assert_eq!(columns.len(), 4);
+  assert_eq!(columns[0].column_name(), Some("a"));
+  assert_eq!(columns[0].order(), None);
+  assert_eq!(columns[1].column_name(), Some("b c"));
+  assert_eq!(columns[1].collation(), Some("NOCASE"));
+  assert_eq!(columns[1].order(), Some(SortOrder::Desc));
+  assert_eq!(
+    *columns[2].kind(),
+    IndexKeyKind::Expression("lower(d)".into())
+  );
+  assert_eq!(columns[2].order(), Some(SortOrder::Asc));
+  assert_eq!(
+    *columns[3].kind(),
+    IndexKeyKind::Expression("e + (f * 2)".into())
+  );
+
+  assert!(IndexSchema::parse("CREATE INDEX i ON t").is_err());
+  assert!(IndexSchema::parse("CREATE INDEX i ON t()").is_err());
+}
+
+#[test]
+fn ok_on_implicit_indexes() {
+  let table = TableSchema::parse(
+    "CREATE TABLE a(x UNIQUE PRIMARY KEY, y COLLATE NOCASE UNIQUE, \
+     UNIQUE(y COLLATE BINARY), UNIQUE(y), UNIQUE(x DESC))",
+  )
+  .unwrap();
+  let indexes = IndexSchema::implicit_indexes(&table);
+  let names: Vec<_> = indexes
+    .iter()
+    .map(|index| (index.name(), index.origin()))
+    .collect();
+  assert_eq!(
+    names,
+    [
+      ("sqlite_autoindex_a_1", IndexOrigin::PrimaryKey),
+      ("sqlite_autoindex_a_2", IndexOrigin::Unique),
+      ("sqlite_autoindex_a_3", IndexOrigin::Unique),
+    ]
+  );
+
+  let table = TableSchema::parse("CREATE TABLE c(id INTEGER PRIMARY KEY, u UNIQUE)").unwrap();
+  let indexes = IndexSchema::implicit_indexes(&table);
+  assert_eq!(indexes.len(), 1);
+  assert_eq!(indexes[0].columns()[0].column_name(), Some("u"));
+}
+
+#[test]
+fn ok_on_load_indexes_from_database() {
+  let conn = SqliteConnection::open("sqlite://./data/indexes.db").unwrap();
+  let schema = conn.schema();
+
+  let index_list: Vec<_> = schema
+    .index_list("a")
+    .iter()
+    .map(|index| {
+      (
+        index.name(),
+        index.is_unique(),
+        index.origin(),
+        index.is_partial(),
+      )
+    })
+    .collect();
+  assert_eq!(
+    index_list,
+    [
+      ("ib", true, IndexOrigin::CreateIndex, false),
+      ("ia", false, IndexOrigin::CreateIndex, true),
+      ("sqlite_autoindex_a_3", true, IndexOrigin::Unique, false),
+      ("sqlite_autoindex_a_2", true, IndexOrigin::PrimaryKey, false),
+      ("sqlite_autoindex_a_1", true, IndexOrigin::Unique, false),
+    ]
+  );
+  let autoindex = schema.index("sqlite_autoindex_a_3").unwrap();
+  assert_eq!(autoindex.root_page(), 5);
+  assert_eq!(autoindex.columns()[0].column_name(), Some("z"));
+
+  // The primary key index of a WITHOUT ROWID table is the table itself.
+  let b = schema.table("b").unwrap();
+  let index_list: Vec<_> = schema
+    .index_list("b")
+    .iter()
+    .map(|index| (index.name(), index.origin(), index.root_page()))
+    .collect();
+  assert_eq!(
+    index_list,
+    [
+      ("sqlite_autoindex_b_2", IndexOrigin::Unique, 7),
+      (
+        "sqlite_autoindex_b_1",
+        IndexOrigin::PrimaryKey,
+        b.root_page()
+      ),
+    ]
+  );
+
+  let index_list: Vec<_> = schema.index_list("c").iter().map(|i| i.name()).collect();
+  assert_eq!(index_list, ["sqlite_autoindex_c_1"]);
+}
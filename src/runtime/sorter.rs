@@ -0,0 +1,788 @@
+//! # Sorter
+//!
+//!  Sorts records, each a list of key values and a payload, by their keys.
+//! Records are kept in memory until they take more than a memory budget;
+//! the sorter then sorts them and writes them as a run to a temporary file.
+//! When every record is in, the runs are merged, at most
+//! [`MERGE_WIDTH`] at a time.
+//!
+//!  Records whose keys compare equal come out in the order they went in.
+//! When only the first records are wanted, a sorter given that number keeps
+//! just those in a heap, as long as they fit in the budget.
+//!
+//!  The sorter serves ORDER BY, DISTINCT and GROUP BY, the ORDER BY and
+//! DISTINCT of aggregate calls, UNION, INTERSECT and EXCEPT, and CREATE
+//! INDEX, which sorts the entries of an index built from the rows of its
+//! table. Its runs also hold the keys of a [`KeySet`], for the duplicates a
+//! recursive UNION leaves out as its rows come.
+
+#[cfg(test)]
+mod tests;
+
+use core::cmp::{Ordering, Reverse};
+use std::{
+  collections::BinaryHeap,
+  fs::{self, File},
+  io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+  path::PathBuf,
+  rc::Rc,
+  sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+  vec,
+};
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  sql::ast::{NullsOrder, OrderingTerm, SortOrder},
+  value::Value,
+};
+
+use super::{collation::Collation, query::entry_columns, schema::IndexKeyKind, SqliteRuntime};
+
+/// The memory a sorter may use before it spills to temporary files.
+pub const DEFAULT_SORT_MEMORY_BUDGET: usize = 16 * 1024 * 1024;
+
+/// The most runs merged at once.
+const MERGE_WIDTH: usize = 16;
+
+/// How one key of a record is ordered.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeyOrder {
+  pub(crate) descending: bool,
+  pub(crate) nulls_first: bool,
+  pub(crate) collation: Collation,
+}
+
+impl KeyOrder {
+  /// Ascending, NULLs first, with the given collation.
+  pub(crate) fn ascending(collation: Collation) -> Self {
+    Self {
+      descending: false,
+      nulls_first: true,
+      collation,
+    }
+  }
+
+  /// The order of an ORDER BY term, with the collation of its expression.
+  /// NULLs come first in ascending order and last in descending order
+  /// unless the term says otherwise.
+  pub(crate) fn of_term(term: &OrderingTerm, collation: Collation) -> Self {
+    let descending = term.order == Some(SortOrder::Desc);
+    Self {
+      descending,
+      nulls_first: term
+        .nulls
+        .map_or(!descending, |nulls| nulls == NullsOrder::First),
+      collation,
+    }
+  }
+
+  fn compare(&self, a: &Value, b: &Value) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+      (true, true) => Ordering::Equal,
+      (true, false) if self.nulls_first => Ordering::Less,
+      (true, false) => Ordering::Greater,
+      (false, true) if self.nulls_first => Ordering::Greater,
+      (false, true) => Ordering::Less,
+      (false, false) if self.descending => self.collation.compare(a, b).reverse(),
+      (false, false) => self.collation.compare(a, b),
+    }
+  }
+}
+
+/// Compares two lists of keys.
+pub(crate) fn compare_keys(a: &[Value], b: &[Value], order: &[KeyOrder]) -> Ordering {
+  a.iter()
+    .zip(b)
+    .zip(order)
+    .map(|((a, b), order)| order.compare(a, b))
+    .find(|ordering| ordering.is_ne())
+    .unwrap_or(Ordering::Equal)
+}
+
+#[derive(Debug)]
+struct Record {
+  key: Vec<Value>,
+  /// The position of the record in the input, which orders equal keys.
+  sequence: u64,
+  payload: Vec<Value>,
+}
+
+impl Record {
+  /// An estimate of the memory the record takes.
+  fn size(&self) -> usize {
+    core::mem::size_of::<Self>() + values_size(&self.key) + values_size(&self.payload)
+  }
+
+  fn compare(&self, other: &Self, order: &[KeyOrder]) -> Ordering {
+    compare_keys(&self.key, &other.key, order).then(self.sequence.cmp(&other.sequence))
+  }
+}
+
+/// An estimate of the memory a list of values takes.
+fn values_size(values: &[Value]) -> usize {
+  values
+    .iter()
+    .map(|value| {
+      core::mem::size_of::<Value>()
+        + match value {
+          Value::Text(text) => text.len(),
+          Value::Blob(blob) => blob.len(),
+          _ => 0,
+        }
+    })
+    .sum()
+}
+
+/// A record in a heap, ordered by the keys of its sorter.
+struct Ranked {
+  record: Record,
+  order: Rc<[KeyOrder]>,
+  /// Where the record comes from, when merging.
+  source: usize,
+}
+
+impl PartialEq for Ranked {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other).is_eq()
+  }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Ranked {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.record.compare(&other.record, &self.order)
+  }
+}
+
+pub(crate) struct Sorter {
+  order: Rc<[KeyOrder]>,
+  budget: usize,
+  /// The records in memory, unless only the first ones are kept.
+  buffer: Vec<Record>,
+  /// The first records, as a max-heap, when only those are wanted.
+  top: Option<(BinaryHeap<Ranked>, usize)>,
+  /// The memory taken by the records in memory.
+  size: usize,
+  runs: Vec<Run>,
+  sequence: u64,
+}
+
+impl Sorter {
+  pub(crate) fn new(order: Vec<KeyOrder>, budget: usize) -> Self {
+    Self {
+      order: order.into(),
+      budget,
+      buffer: vec![],
+      top: None,
+      size: 0,
+      runs: vec![],
+      sequence: 0,
+    }
+  }
+
+  /// Keeps only the first `count` records.
+  pub(crate) fn with_limit(mut self, count: usize) -> Self {
+    self.top = Some((BinaryHeap::with_capacity(count.min(1024) + 1), count));
+    self
+  }
+
+  /// The number of values of a sort key.
+  pub(crate) fn key_count(&self) -> usize {
+    self.order.len()
+  }
+
+  /// How sort keys are compared.
+  pub(crate) fn key_orders(&self) -> &[KeyOrder] {
+    &self.order
+  }
+
+  pub(crate) fn push(&mut self, key: Vec<Value>, payload: Vec<Value>) -> SqliteResult<()> {
+    let record = Record {
+      key,
+      sequence: self.sequence,
+      payload,
+    };
+    self.sequence += 1;
+
+    if let Some((heap, count)) = &mut self.top {
+      if *count == 0 {
+        return Ok(());
+      }
+      let is_first = heap.len() < *count
+        || heap
+          .peek()
+          .is_some_and(|last| record.compare(&last.record, &self.order).is_lt());
+      if !is_first {
+        return Ok(());
+      }
+      self.size += record.size();
+      heap.push(Ranked {
+        record,
+        order: self.order.clone(),
+        source: 0,
+      });
+      if heap.len() > *count {
+        if let Some(last) = heap.pop() {
+          self.size -= last.record.size();
+        }
+      }
+      if self.size > self.budget {
+        // Too large to keep in memory: sort them all after all.
+        self.buffer = heap.drain().map(|ranked| ranked.record).collect();
+        self.top = None;
+        self.spill()?;
+      }
+      return Ok(());
+    }
+
+    self.size += record.size();
+    self.buffer.push(record);
+    if self.size > self.budget {
+      self.spill()?;
+    }
+    Ok(())
+  }
+
+  /// Writes the records in memory as a sorted run.
+  fn spill(&mut self) -> SqliteResult<()> {
+    let order = self.order.clone();
+    self.buffer.sort_unstable_by(|a, b| a.compare(b, &order));
+    let run = Run::write(self.buffer.drain(..))?;
+    self.runs.push(run);
+    self.size = 0;
+    Ok(())
+  }
+
+  /// Whether any record was written to a temporary file.
+  #[cfg(test)]
+  pub(crate) fn has_spilled(&self) -> bool {
+    !self.runs.is_empty()
+  }
+
+  /// The records, sorted by their keys.
+  pub(crate) fn finish(mut self) -> SqliteResult<Sorted> {
+    if let Some((heap, _)) = self.top.take() {
+      let records: Vec<Record> = heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|ranked| ranked.record)
+        .collect();
+      return Ok(Sorted(Records::Memory(records.into_iter())));
+    }
+    let order = self.order.clone();
+    self.buffer.sort_unstable_by(|a, b| a.compare(b, &order));
+    if self.runs.is_empty() {
+      return Ok(Sorted(Records::Memory(
+        core::mem::take(&mut self.buffer).into_iter(),
+      )));
+    }
+    if !self.buffer.is_empty() {
+      let run = Run::write(self.buffer.drain(..))?;
+      self.runs.push(run);
+    }
+
+    let mut runs = core::mem::take(&mut self.runs);
+    while runs.len() > MERGE_WIDTH {
+      let mut merged = vec![];
+      let mut runs_left = runs.into_iter();
+      loop {
+        let group: Vec<Run> = runs_left.by_ref().take(MERGE_WIDTH).collect();
+        if group.is_empty() {
+          break;
+        }
+        let mut merge = Merge::new(group, self.order.clone())?;
+        merged.push(Run::write_fallible(&mut merge)?);
+      }
+      runs = merged;
+    }
+    Ok(Sorted(Records::Merge(Merge::new(
+      runs,
+      self.order.clone(),
+    )?)))
+  }
+}
+
+/// The sorted records of a [`Sorter`], as their keys and payloads.
+pub(crate) struct Sorted(Records);
+
+enum Records {
+  Memory(vec::IntoIter<Record>),
+  Merge(Merge),
+}
+
+impl Iterator for Sorted {
+  type Item = SqliteResult<(Vec<Value>, Vec<Value>)>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let record = match &mut self.0 {
+      Records::Memory(records) => Ok(records.next()?),
+      Records::Merge(merge) => merge.next()?,
+    };
+    Some(record.map(|record| (record.key, record.payload)))
+  }
+}
+
+/// Merges sorted runs.
+struct Merge {
+  readers: Vec<RunReader>,
+  heads: BinaryHeap<Reverse<Ranked>>,
+  order: Rc<[KeyOrder]>,
+}
+
+impl Merge {
+  fn new(runs: Vec<Run>, order: Rc<[KeyOrder]>) -> SqliteResult<Self> {
+    let mut merge = Self {
+      readers: runs
+        .into_iter()
+        .map(Run::read)
+        .collect::<SqliteResult<_>>()?,
+      heads: BinaryHeap::new(),
+      order,
+    };
+    for source in 0..merge.readers.len() {
+      merge.advance(source)?;
+    }
+    Ok(merge)
+  }
+
+  /// Reads the next record of a run into the heads.
+  fn advance(&mut self, source: usize) -> SqliteResult<()> {
+    if let Some(record) = self.readers[source].next()? {
+      self.heads.push(Reverse(Ranked {
+        record,
+        order: self.order.clone(),
+        source,
+      }));
+    }
+    Ok(())
+  }
+}
+
+impl Iterator for Merge {
+  type Item = SqliteResult<Record>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let Reverse(head) = self.heads.pop()?;
+    Some(self.advance(head.source).map(|_| head.record))
+  }
+}
+
+/// A set of keys, which tells whether a key is new as it is added: for the
+/// duplicates that must be left out before every key is known, as those of
+/// a recursive UNION. Like a [`Sorter`], it keeps keys in memory up to its
+/// budget, then writes them as a sorted run. A run keeps in memory every
+/// [`INDEX_STRIDE`]th key and where its record starts, from which a lookup
+/// reads. Past [`MERGE_WIDTH`] runs, they are merged into one.
+pub(crate) struct KeySet {
+  order: Rc<[KeyOrder]>,
+  budget: usize,
+  /// The keys in memory, sorted.
+  keys: Vec<Vec<Value>>,
+  /// The memory taken by the keys in memory.
+  size: usize,
+  runs: Vec<IndexedRun>,
+}
+
+/// The number of records of a run between two keys of its index.
+const INDEX_STRIDE: usize = 64;
+
+/// A run of a [`KeySet`], open for lookups.
+struct IndexedRun {
+  run: Run,
+  reader: BufReader<File>,
+  /// Every [`INDEX_STRIDE`]th key, with the offset of its record.
+  index: Vec<(Vec<Value>, u64)>,
+}
+
+impl KeySet {
+  pub(crate) fn new(order: Vec<KeyOrder>, budget: usize) -> Self {
+    Self {
+      order: order.into(),
+      budget,
+      keys: vec![],
+      size: 0,
+      runs: vec![],
+    }
+  }
+
+  /// Adds a key, returning whether the set did not have it.
+  pub(crate) fn insert(&mut self, key: Vec<Value>) -> SqliteResult<bool> {
+    let position = match self
+      .keys
+      .binary_search_by(|other| compare_keys(other, &key, &self.order))
+    {
+      Ok(_) => return Ok(false),
+      Err(position) => position,
+    };
+    for run in &mut self.runs {
+      if run.contains(&key, &self.order)? {
+        return Ok(false);
+      }
+    }
+    self.size += core::mem::size_of::<Vec<Value>>() + values_size(&key);
+    self.keys.insert(position, key);
+    if self.size > self.budget {
+      self.spill()?;
+    }
+    Ok(true)
+  }
+
+  /// Writes the keys in memory as a run, merged with the others when there
+  /// are too many.
+  fn spill(&mut self) -> SqliteResult<()> {
+    self.size = 0;
+    let keys = core::mem::take(&mut self.keys);
+    if self.runs.len() < MERGE_WIDTH {
+      let run = IndexedRun::write(&mut keys.into_iter().map(Ok))?;
+      self.runs.push(run);
+      return Ok(());
+    }
+    let mut runs: Vec<Run> = self.runs.drain(..).map(|run| run.run).collect();
+    runs.push(Run::write(keys.into_iter().map(|key| Record {
+      key,
+      sequence: 0,
+      payload: vec![],
+    }))?);
+    let mut merge = Merge::new(runs, self.order.clone())?;
+    let run = IndexedRun::write(&mut merge.by_ref().map(|record| record.map(|record| record.key)))?;
+    self.runs.push(run);
+    Ok(())
+  }
+}
+
+impl IndexedRun {
+  /// Writes sorted keys as a run, and indexes it.
+  fn write(keys: &mut dyn Iterator<Item = SqliteResult<Vec<Value>>>) -> SqliteResult<Self> {
+    let (run, mut writer) = Run::create()?;
+    let mut index = vec![];
+    let mut offset = 0;
+    let mut bytes = vec![];
+    for (position, key) in keys.enumerate() {
+      let record = Record {
+        key: key?,
+        sequence: 0,
+        payload: vec![],
+      };
+      bytes.clear();
+      write_record(&mut bytes, &record).map_err(io_error)?;
+      writer.write_all(&bytes).map_err(io_error)?;
+      if position % INDEX_STRIDE == 0 {
+        index.push((record.key, offset));
+      }
+      offset += bytes.len() as u64;
+    }
+    writer.flush().map_err(io_error)?;
+    let reader = BufReader::new(File::open(&run.path).map_err(io_error)?);
+    Ok(Self { run, reader, index })
+  }
+
+  /// Whether the run has a key, read from the last key of the index not
+  /// past it.
+  fn contains(&mut self, key: &[Value], order: &[KeyOrder]) -> SqliteResult<bool> {
+    let block = self
+      .index
+      .partition_point(|(first, _)| compare_keys(first, key, order).is_le());
+    let Some((_, offset)) = block.checked_sub(1).map(|block| &self.index[block]) else {
+      return Ok(false);
+    };
+    self
+      .reader
+      .seek(SeekFrom::Start(*offset))
+      .map_err(io_error)?;
+    for _ in 0..INDEX_STRIDE {
+      let Some(record) = read_record(&mut self.reader)? else {
+        break;
+      };
+      match compare_keys(&record.key, key, order) {
+        Ordering::Less => {}
+        Ordering::Equal => return Ok(true),
+        Ordering::Greater => break,
+      }
+    }
+    Ok(false)
+  }
+}
+
+impl SqliteRuntime {
+  /// The entries of an index, built from the rows of its table the way
+  /// CREATE INDEX builds them: each holds the keys of a row, then its rowid
+  /// or the PRIMARY KEY columns the keys do not hold, and they come out
+  /// sorted in index order. A partial index only has the rows its WHERE
+  /// clause accepts.
+  pub fn index_entries(&self, index_name: &str) -> SqliteResult<IndexEntries> {
+    let index = self
+      .schema()
+      .index(index_name)
+      .ok_or_else(|| SqliteError::Custom(format!("no such index: {index_name}")))?;
+    let table = self
+      .schema()
+      .table(index.table_name())
+      .ok_or_else(|| SqliteError::Custom(format!("no such table: {}", index.table_name())))?;
+    // The PRIMARY KEY of a WITHOUT ROWID table is the table itself.
+    if index.root_page() == table.root_page() {
+      return Err(SqliteError::Custom(format!(
+        "{index_name} is the b-tree of table {}",
+        table.name()
+      )));
+    }
+    let mut values = vec![];
+    let mut order = vec![];
+    for (key, collation) in index.columns().iter().zip(self.key_collations(index)?) {
+      values.push(match key.kind() {
+        IndexKeyKind::Column(name) => identifier(name),
+        IndexKeyKind::Expression(expr) => format!("({expr})"),
+      });
+      let descending = key.order() == Some(SortOrder::Desc);
+      order.push(KeyOrder {
+        descending,
+        nulls_first: !descending,
+        collation,
+      });
+    }
+    let primary_key = table.primary_key();
+    let held = entry_columns(table, index);
+    for column in held.iter().skip(index.columns().len()).flatten() {
+      match table.columns().get(*column) {
+        Some(definition) if table.is_without_rowid() => {
+          values.push(identifier(definition.name()));
+          let descending = primary_key
+            .iter()
+            .any(|(key, order)| key == column && *order == SortOrder::Desc);
+          order.push(KeyOrder {
+            descending,
+            nulls_first: !descending,
+            collation: self.collation(definition.collation().unwrap_or("BINARY"))?,
+          });
+        }
+        _ => {
+          values.push("rowid".into());
+          order.push(KeyOrder::ascending(Collation::binary()));
+        }
+      }
+    }
+    let mut sql = format!(
+      "SELECT {} FROM {}",
+      values.join(", "),
+      identifier(table.name())
+    );
+    if let Some(where_clause) = index.where_clause() {
+      sql.push_str(&format!(" WHERE {where_clause}"));
+    }
+
+    let mut statement = self.prepare(&sql)?;
+    let mut sorter = Sorter::new(order.clone(), self.sort_memory_budget);
+    while let Some(entry) = statement.step()? {
+      sorter.push(entry, vec![])?;
+    }
+    let unique = index.is_unique().then(|| {
+      let names: Option<Vec<String>> = index
+        .columns()
+        .iter()
+        .map(|key| Some(format!("{}.{}", table.name(), key.column_name()?)))
+        .collect();
+      let constraint = match names {
+        Some(names) => names.join(", "),
+        None => format!("index '{}'", index.name()),
+      };
+      let keys = order[..index.columns().len()].to_vec();
+      (keys, constraint)
+    });
+    Ok(IndexEntries {
+      sorted: sorter.finish()?,
+      unique,
+      previous: None,
+    })
+  }
+}
+
+/// The entries of an index being built, in index order.
+pub struct IndexEntries {
+  sorted: Sorted,
+  /// The order of the keys of a UNIQUE index, which no two entries may
+  /// share, and the name of its constraint.
+  unique: Option<(Vec<KeyOrder>, String)>,
+  /// The keys of the previous entry of a UNIQUE index.
+  previous: Option<Vec<Value>>,
+}
+
+impl Iterator for IndexEntries {
+  type Item = SqliteResult<Vec<Value>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry = match self.sorted.next()? {
+      Ok((entry, _)) => entry,
+      Err(err) => return Some(Err(err)),
+    };
+    if let Some((order, constraint)) = &self.unique {
+      let keys: Vec<Value> = entry.iter().take(order.len()).cloned().collect();
+      // NULLs are distinct from each other, and from any value.
+      let is_duplicate = !keys.iter().any(Value::is_null)
+        && self
+          .previous
+          .as_ref()
+          .is_some_and(|previous| compare_keys(previous, &keys, order).is_eq());
+      if is_duplicate {
+        return Some(Err(SqliteError::Custom(format!(
+          "UNIQUE constraint failed: {constraint}"
+        ))));
+      }
+      self.previous = Some(keys);
+    }
+    Some(Ok(entry))
+  }
+}
+
+/// A name quoted as an identifier.
+fn identifier(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A temporary file of sorted records, removed when dropped.
+struct Run {
+  path: PathBuf,
+}
+
+impl Drop for Run {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+fn io_error(err: std::io::Error) -> SqliteError {
+  SqliteError::Custom(format!("unable to use the sorter's temporary file: {err}"))
+}
+
+impl Run {
+  fn create() -> SqliteResult<(Self, BufWriter<File>)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let path = std::env::temp_dir().join(format!(
+      "sqlite-rs-{}-{}.sort",
+      std::process::id(),
+      COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+    let file = File::create(&path).map_err(io_error)?;
+    Ok((Self { path }, BufWriter::new(file)))
+  }
+
+  fn write(records: impl Iterator<Item = Record>) -> SqliteResult<Self> {
+    Self::write_fallible(&mut records.map(Ok))
+  }
+
+  fn write_fallible(records: &mut dyn Iterator<Item = SqliteResult<Record>>) -> SqliteResult<Self> {
+    let (run, mut writer) = Self::create()?;
+    for record in records {
+      write_record(&mut writer, &record?).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(run)
+  }
+
+  fn read(self) -> SqliteResult<RunReader> {
+    let file = File::open(&self.path).map_err(io_error)?;
+    Ok(RunReader {
+      reader: BufReader::new(file),
+      _run: self,
+    })
+  }
+}
+
+struct RunReader {
+  reader: BufReader<File>,
+  _run: Run,
+}
+
+impl RunReader {
+  fn next(&mut self) -> SqliteResult<Option<Record>> {
+    read_record(&mut self.reader)
+  }
+}
+
+/// Reads the next record of a run, if there is one.
+fn read_record(reader: &mut impl Read) -> SqliteResult<Option<Record>> {
+  let mut sequence = [0; 8];
+  match reader.read_exact(&mut sequence) {
+    Ok(()) => {}
+    Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+    Err(err) => return Err(io_error(err)),
+  }
+  let key = read_values(reader).map_err(io_error)?;
+  let payload = read_values(reader).map_err(io_error)?;
+  Ok(Some(Record {
+    key,
+    sequence: u64::from_le_bytes(sequence),
+    payload,
+  }))
+}
+
+fn write_record(writer: &mut impl Write, record: &Record) -> std::io::Result<()> {
+  writer.write_all(&record.sequence.to_le_bytes())?;
+  write_values(writer, &record.key)?;
+  write_values(writer, &record.payload)
+}
+
+/// Writes a count of values, then each as a tag and its data.
+fn write_values(writer: &mut impl Write, values: &[Value]) -> std::io::Result<()> {
+  writer.write_all(&(values.len() as u32).to_le_bytes())?;
+  for value in values {
+    match value {
+      Value::Null => writer.write_all(&[0])?,
+      Value::Integer(integer) => {
+        writer.write_all(&[1])?;
+        writer.write_all(&integer.to_le_bytes())?;
+      }
+      Value::Real(real) => {
+        writer.write_all(&[2])?;
+        writer.write_all(&real.to_bits().to_le_bytes())?;
+      }
+      Value::Text(text) => {
+        writer.write_all(&[3])?;
+        writer.write_all(&(text.len() as u32).to_le_bytes())?;
+        writer.write_all(text.as_bytes())?;
+      }
+      Value::Blob(blob) => {
+        writer.write_all(&[4])?;
+        writer.write_all(&(blob.len() as u32).to_le_bytes())?;
+        writer.write_all(blob)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+fn read_values(reader: &mut impl Read) -> std::io::Result<Vec<Value>> {
+  fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+  }
+  fn read_bytes(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let length = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+  }
+
+  let count = u32::from_le_bytes(read_array(reader)?) as usize;
+  let mut values = Vec::with_capacity(count);
+  for _ in 0..count {
+    let [tag] = read_array(reader)?;
+    values.push(match tag {
+      0 => Value::Null,
+      1 => Value::Integer(i64::from_le_bytes(read_array(reader)?)),
+      2 => Value::Real(f64::from_bits(u64::from_le_bytes(read_array(reader)?))),
+      3 => Value::Text(
+        String::from_utf8(read_bytes(reader)?)
+          .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?,
+      ),
+      _ => Value::Blob(read_bytes(reader)?),
+    });
+  }
+  Ok(values)
+}
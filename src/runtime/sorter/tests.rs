@@ -0,0 +1,194 @@
+//! Tests for the sorter
+//!
+//! To run: `cargo test runtime::sorter`
+
+use super::{KeyOrder, KeySet, Sorter};
+use crate::{runtime::collation::Collation, value::Value, SqliteConnection};
+
+fn sorted(sorter: Sorter) -> Vec<(Vec<Value>, Vec<Value>)> {
+  sorter.finish().unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn ok_on_spilled_runs() {
+  // A budget of a few records makes runs of a few records, merged in more
+  // than one pass.
+  let mut sorter = Sorter::new(vec![KeyOrder::default()], 512);
+  for index in 0..1000_i64 {
+    let key = (index * 7919) % 1000;
+    sorter
+      .push(vec![Value::from(key % 100)], vec![Value::from(index)])
+      .unwrap();
+  }
+  assert!(sorter.has_spilled());
+  let records = sorted(sorter);
+  assert_eq!(records.len(), 1000);
+  // Equal keys keep the order they came in.
+  for pair in records.windows(2) {
+    let ((a_key, a_index), (b_key, b_index)) = (&pair[0], &pair[1]);
+    let ordering = a_key[0].compare(&b_key[0]);
+    assert!(ordering.is_lt() || (ordering.is_eq() && a_index[0].compare(&b_index[0]).is_lt()));
+  }
+}
+
+#[test]
+fn ok_on_key_set() {
+  // A budget of a few keys writes many runs, merged once there are too
+  // many, and each key is still found wherever it went.
+  let mut set = KeySet::new(vec![KeyOrder::default()], 512);
+  let mut added = 0;
+  for index in 0..3000_i64 {
+    let key = (index * 7919) % 1000;
+    let is_new = set.insert(vec![Value::from(key)]).unwrap();
+    assert_eq!(is_new, index < 1000, "{index}");
+    added += usize::from(is_new);
+  }
+  assert_eq!(added, 1000);
+
+  let mut set = KeySet::new(vec![KeyOrder::ascending(Collation::nocase())], usize::MAX);
+  assert!(set.insert(vec![Value::from("a")]).unwrap());
+  assert!(!set.insert(vec![Value::from("A")]).unwrap());
+  assert!(set.insert(vec![Value::from("b")]).unwrap());
+}
+
+#[test]
+fn ok_on_key_orders() {
+  let keys = [
+    Value::from("b"),
+    Value::Null,
+    Value::from("A"),
+    Value::from(1),
+    Value::from("a "),
+  ];
+  let order = |descending, nulls_first, collation| {
+    let mut sorter = Sorter::new(
+      vec![KeyOrder {
+        descending,
+        nulls_first,
+        collation,
+      }],
+      usize::MAX,
+    );
+    for key in &keys {
+      sorter.push(vec![key.clone()], vec![]).unwrap();
+    }
+    sorted(sorter)
+      .into_iter()
+      .map(|(key, _)| key[0].clone())
+      .collect::<Vec<_>>()
+  };
+
+  assert_eq!(
+    order(false, true, Collation::binary()),
+    [
+      Value::Null,
+      Value::from(1),
+      Value::from("A"),
+      Value::from("a "),
+      Value::from("b")
+    ]
+  );
+  assert_eq!(
+    order(true, false, Collation::binary()),
+    [
+      Value::from("b"),
+      Value::from("a "),
+      Value::from("A"),
+      Value::from(1),
+      Value::Null
+    ]
+  );
+  assert_eq!(
+    order(true, true, Collation::nocase()),
+    [
+      Value::Null,
+      Value::from("b"),
+      Value::from("a "),
+      Value::from("A"),
+      Value::from(1)
+    ]
+  );
+  assert_eq!(
+    order(false, false, Collation::rtrim()),
+    [
+      Value::from(1),
+      Value::from("A"),
+      Value::from("a "),
+      Value::from("b"),
+      Value::Null
+    ]
+  );
+}
+
+#[test]
+fn ok_on_top_records() {
+  let mut sorter = Sorter::new(vec![KeyOrder::default()], usize::MAX).with_limit(3);
+  for index in (0..100_i64).rev() {
+    sorter
+      .push(vec![Value::from(index / 2)], vec![Value::from(index)])
+      .unwrap();
+  }
+  let payloads: Vec<Value> = sorted(sorter)
+    .into_iter()
+    .map(|(_, payload)| payload[0].clone())
+    .collect();
+  assert_eq!(payloads, [Value::from(1), Value::from(0), Value::from(3)]);
+
+  // First records that outgrow the budget are sorted all the same.
+  let mut sorter = Sorter::new(vec![KeyOrder::default()], 256).with_limit(50);
+  for index in (0..100_i64).rev() {
+    sorter.push(vec![Value::from(index)], vec![]).unwrap();
+  }
+  assert!(sorter.has_spilled());
+  let keys: Vec<Value> = sorted(sorter)
+    .into_iter()
+    .take(3)
+    .map(|(key, _)| key[0].clone())
+    .collect();
+  assert_eq!(keys, [Value::from(0), Value::from(1), Value::from(2)]);
+}
+
+#[test]
+fn ok_on_index_entries() {
+  // Each index, built again from its table in a budget that spills, holds
+  // the entries of its b-tree in the same order: column and expression
+  // keys, DESC and NOCASE ones, partial indexes and those of WITHOUT ROWID
+  // tables.
+  for path in ["joins.db", "planner.db", "pragmas.db", "indexes.db"] {
+    let mut conn = SqliteConnection::open(format!("sqlite://./data/{path}")).unwrap();
+    conn.set_sort_memory_budget(4096);
+    let schema = conn.schema();
+    // That of the PRIMARY KEY of a WITHOUT ROWID table is the table.
+    let names: Vec<String> = schema
+      .indexes()
+      .iter()
+      .filter(|index| {
+        schema
+          .table(index.table_name())
+          .is_some_and(|table| table.root_page() != index.root_page())
+      })
+      .map(|index| index.name().to_string())
+      .collect();
+    for name in names {
+      let built: Vec<Vec<Value>> = conn
+        .index_entries(&name)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+      let mut cursor = conn.index_cursor(&name).unwrap();
+      let mut stored = vec![];
+      let mut has_entry = cursor.rewind().unwrap();
+      while has_entry {
+        stored.push(cursor.entry().unwrap());
+        has_entry = cursor.advance().unwrap();
+      }
+      assert_eq!(built.len(), stored.len(), "{path}: {name}");
+      for (built, stored) in built.iter().zip(&stored) {
+        assert_eq!(built.len(), stored.len(), "{path}: {name}");
+        for (a, b) in built.iter().zip(stored) {
+          assert!(a.compare(b).is_eq(), "{path}: {name}: {built:?} {stored:?}");
+        }
+      }
+    }
+  }
+}
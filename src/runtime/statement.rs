@@ -0,0 +1,255 @@
+//! # Prepared statements
+//!
+//!  A statement is parsed and compiled once, and then run as many times as
+//! needed, each time with other values bound to its parameters. It is
+//! stepped through one row at a time, and reset to run it again.
+//!
+//!  Parameters are numbered from 1 the way SQLite does: `?` takes the
+//! number after the largest so far, `?NNN` the number NNN, and `:name`,
+//! `@name` and `$name` the number they had the first time they appeared,
+//! or the next one. A parameter is bound by its number or its name, and is
+//! NULL until it is. Bindings are kept when the statement is reset.
+//!
+//!  A statement is compiled for the schema of the connection. Another
+//! connection may change the schema in the meantime, which the first step
+//! after preparing or resetting the statement checks the schema cookie
+//! for: a statement for an old schema is compiled again from its SQL text,
+//! keeping its bindings, as `sqlite3_prepare_v2` makes it.
+//!
+//!  Every statement runs as a program, which returns its rows as it finds
+//! them. That of `EXPLAIN` returns the instructions of the program of its
+//! query, one at a time.
+//!
+//! *Reference:* https://www.sqlite.org/c3ref/prepare.html
+
+#[cfg(test)]
+mod tests;
+
+use std::rc::Rc;
+
+use crate::{
+  result::{SqliteError, SqliteResult},
+  sql::Parser,
+  value::Value,
+};
+
+use super::{
+  query::CompiledStatement,
+  vdbe::{Program, Vdbe},
+  SqliteRuntime,
+};
+
+/// The values bound to the parameters of a statement.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bindings {
+  /// The name of each parameter, by its number from 1. `None` for one only
+  /// written `?`.
+  names: Vec<Option<String>>,
+  values: Vec<Value>,
+}
+
+impl Bindings {
+  fn new(names: Vec<Option<String>>) -> Self {
+    Self {
+      values: vec![Value::Null; names.len()],
+      names,
+    }
+  }
+
+  /// The number of the parameter an expression reads by this name: `?N` is
+  /// parameter N.
+  fn index(&self, name: &str) -> Option<usize> {
+    match name.strip_prefix('?') {
+      Some(digits) => digits.parse().ok(),
+      None => Some(
+        self
+          .names
+          .iter()
+          .position(|parameter| parameter.as_deref() == Some(name))?
+          + 1,
+      ),
+    }
+  }
+
+  /// The value bound to a parameter, NULL if none is.
+  pub(crate) fn value(&self, name: &str) -> Value {
+    self
+      .index(name)
+      .and_then(|index| self.values.get(index.checked_sub(1)?))
+      .cloned()
+      .unwrap_or(Value::Null)
+  }
+}
+
+enum State<'a> {
+  /// Not stepped since it was prepared or reset.
+  Ready,
+  Running(Vdbe<'a>),
+  /// Failed before its program started.
+  Done,
+}
+
+/// A statement compiled once to run many times.
+pub struct Statement<'a> {
+  runtime: &'a SqliteRuntime,
+  sql: String,
+  /// The schema cookie of the schema the program was compiled for.
+  schema_cookie: u32,
+  program: Rc<Program<'a>>,
+  /// Whether its program is listed rather than run, for `EXPLAIN`.
+  is_listing: bool,
+  columns: Vec<String>,
+  declared_types: Vec<Option<String>>,
+  bindings: Rc<Bindings>,
+  state: State<'a>,
+}
+
+impl SqliteRuntime {
+  /// Parses and compiles a statement, to bind its parameters and step
+  /// through its rows.
+  pub fn prepare(&self, sql: &str) -> SqliteResult<Statement<'_>> {
+    let (names, compiled) = self.compile_sql(sql)?;
+    Ok(Statement {
+      runtime: self,
+      sql: sql.to_string(),
+      schema_cookie: **self.file_header().schema_cookie(),
+      program: Rc::new(compiled.program),
+      is_listing: compiled.is_listing,
+      columns: compiled.columns,
+      declared_types: compiled.declared_types,
+      bindings: Rc::new(Bindings::new(names)),
+      state: State::Ready,
+    })
+  }
+
+  /// Parses and compiles the text of one statement, with the names of its
+  /// parameters.
+  fn compile_sql(&self, sql: &str) -> SqliteResult<(Vec<Option<String>>, CompiledStatement<'_>)> {
+    let mut parser = Parser::new(sql)?;
+    let statement = parser.parse_statement()?;
+    parser.expect_end()?;
+    let compiled = self.compile_statement(&statement)?;
+    Ok((parser.parameters().to_vec(), compiled))
+  }
+}
+
+impl Statement<'_> {
+  /// Runs the statement until its next row, or `None` once it is done. A
+  /// statement that has failed is done too, until it is reset.
+  pub fn step(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    if let State::Ready = self.state {
+      // The program reads the b-trees of the schema it was compiled for.
+      if let Err(err) = self.recompile_if_stale() {
+        self.state = State::Done;
+        return Err(err);
+      }
+      let vdbe =
+        Vdbe::new(self.runtime, Rc::clone(&self.program)).with_bindings(Rc::clone(&self.bindings));
+      self.state = State::Running(match self.is_listing {
+        true => vdbe.listing(),
+        false => vdbe,
+      });
+    }
+    match &mut self.state {
+      State::Running(vdbe) => vdbe.step(),
+      State::Ready | State::Done => Ok(None),
+    }
+  }
+
+  /// Compiles the statement again when the schema has changed since it was
+  /// compiled, by this connection or another one.
+  fn recompile_if_stale(&mut self) -> SqliteResult<()> {
+    self.runtime.begin_read()?;
+    let schema_cookie = **self.runtime.file_header().schema_cookie();
+    if schema_cookie == self.schema_cookie {
+      return Ok(());
+    }
+    let (_, compiled) = self.runtime.compile_sql(&self.sql)?;
+    self.schema_cookie = schema_cookie;
+    self.program = Rc::new(compiled.program);
+    self.is_listing = compiled.is_listing;
+    self.columns = compiled.columns;
+    self.declared_types = compiled.declared_types;
+    Ok(())
+  }
+
+  /// Makes the statement run again from the start on the next step, with
+  /// the same bindings.
+  pub fn reset(&mut self) {
+    self.state = State::Ready;
+  }
+
+  /// Binds a value to a parameter, by its number from 1. The statement must
+  /// not have been stepped since it was prepared or reset.
+  pub fn bind(&mut self, index: usize, value: impl Into<Value>) -> SqliteResult<()> {
+    if !matches!(self.state, State::Ready) {
+      return Err(SqliteError::Custom(
+        "bad parameter or other API misuse".into(),
+      ));
+    }
+    let bindings = Rc::make_mut(&mut self.bindings);
+    let slot = index
+      .checked_sub(1)
+      .and_then(|index| bindings.values.get_mut(index))
+      .ok_or_else(|| SqliteError::Custom("column index out of range".into()))?;
+    *slot = value.into();
+    Ok(())
+  }
+
+  /// Binds a value to a parameter, by its name with its prefix, such as
+  /// `:id`.
+  pub fn bind_name(&mut self, name: &str, value: impl Into<Value>) -> SqliteResult<()> {
+    let index = self
+      .parameter_index(name)
+      .ok_or_else(|| SqliteError::Custom("column index out of range".into()))?;
+    self.bind(index, value)
+  }
+
+  /// Makes every parameter NULL.
+  pub fn clear_bindings(&mut self) {
+    for value in &mut Rc::make_mut(&mut self.bindings).values {
+      *value = Value::Null;
+    }
+  }
+
+  /// The largest parameter number.
+  pub fn parameter_count(&self) -> usize {
+    self.bindings.names.len()
+  }
+
+  /// The name of a parameter, by its number from 1, with its prefix. `None`
+  /// for one only written `?`.
+  pub fn parameter_name(&self, index: usize) -> Option<&str> {
+    self.bindings.names.get(index.checked_sub(1)?)?.as_deref()
+  }
+
+  /// The number of the parameter with this name, prefix included.
+  pub fn parameter_index(&self, name: &str) -> Option<usize> {
+    self
+      .bindings
+      .names
+      .iter()
+      .position(|parameter| parameter.as_deref() == Some(name))
+      .map(|index| index + 1)
+  }
+
+  /// The names of the result columns.
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  pub fn column_count(&self) -> usize {
+    self.columns.len()
+  }
+
+  /// The name of a result column, by its index from 0.
+  pub fn column_name(&self, index: usize) -> Option<&str> {
+    self.columns.get(index).map(String::as_str)
+  }
+
+  /// The declared type of a result column, by its index from 0: that of the
+  /// table column it reads, when it is only a column.
+  pub fn column_decltype(&self, index: usize) -> Option<&str> {
+    self.declared_types.get(index)?.as_deref()
+  }
+}
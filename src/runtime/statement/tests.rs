@@ -0,0 +1,243 @@
+//! Tests for prepared statements
+//!
+//! To run: `cargo test runtime::statement`
+
+use crate::{value::Value, SqliteConnection};
+
+const PLANNER_DB: &str = "sqlite://./data/planner.db";
+
+fn rows(statement: &mut super::Statement) -> Vec<Vec<Value>> {
+  let mut rows = vec![];
+  while let Some(row) = statement.step().unwrap() {
+    rows.push(row);
+  }
+  rows
+}
+
+#[test]
+fn ok_on_bound_parameters() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let mut statement = conn
+    .prepare("SELECT name, price * 2 AS double FROM item WHERE id = ?")
+    .unwrap();
+  assert_eq!(statement.parameter_count(), 1);
+  assert_eq!(statement.parameter_name(1), None);
+  assert_eq!(statement.columns(), ["name", "double"]);
+  assert_eq!(statement.column_decltype(0), Some("TEXT"));
+  assert_eq!(statement.column_decltype(1), None);
+
+  for id in [3, 1, 2000] {
+    statement.bind(1, id).unwrap();
+    assert_eq!(
+      statement.step().unwrap().unwrap()[0],
+      Value::from(format!("item{id}"))
+    );
+    assert_eq!(statement.step().unwrap(), None);
+    assert_eq!(
+      statement.bind(1, 1).unwrap_err().to_string(),
+      "bad parameter or other API misuse"
+    );
+    statement.reset();
+  }
+  // Bindings are kept on reset.
+  assert_eq!(rows(&mut statement).len(), 1);
+  statement.reset();
+  statement.clear_bindings();
+  assert_eq!(rows(&mut statement), Vec::<Vec<Value>>::new());
+  statement.reset();
+  assert_eq!(
+    statement.bind(2, 1).unwrap_err().to_string(),
+    "column index out of range"
+  );
+}
+
+#[test]
+fn ok_on_named_parameters() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let mut statement = conn.prepare("SELECT :a, @b, $c, ?5, :a + ?1, ?").unwrap();
+  assert_eq!(statement.parameter_count(), 6);
+  let names: Vec<_> = (1..=6)
+    .map(|index| statement.parameter_name(index))
+    .collect();
+  assert_eq!(
+    names,
+    [Some(":a"), Some("@b"), Some("$c"), None, Some("?5"), None]
+  );
+  assert_eq!(statement.parameter_index("$c"), Some(3));
+  assert_eq!(statement.parameter_index(":z"), None);
+
+  statement.bind_name(":a", 10).unwrap();
+  statement.bind_name("@b", "b").unwrap();
+  statement.bind(5, 2.5).unwrap();
+  statement.bind(6, vec![1u8]).unwrap();
+  assert_eq!(
+    rows(&mut statement),
+    [[
+      Value::from(10),
+      Value::from("b"),
+      Value::Null,
+      Value::from(2.5),
+      Value::from(20),
+      Value::from(vec![1u8]),
+    ]]
+  );
+  assert!(statement.bind_name(":z", 1).is_err());
+}
+
+#[test]
+fn ok_on_parameters_in_limit_and_groups() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  // LIMIT reads its parameters when the statement runs.
+  let mut statement = conn
+    .prepare("SELECT id FROM item WHERE id > :low ORDER BY id LIMIT :count")
+    .unwrap();
+  assert_eq!(statement.column_decltype(0), Some("INTEGER"));
+  for (low, count) in [(10, 2), (1990, 20)] {
+    statement.reset();
+    statement.bind_name(":low", low).unwrap();
+    statement.bind_name(":count", count).unwrap();
+    let ids: Vec<Value> = rows(&mut statement).into_iter().flatten().collect();
+    let expected: Vec<Value> = (low + 1..=(low + count).min(2000))
+      .map(Value::from)
+      .collect();
+    assert_eq!(ids, expected);
+  }
+
+  let mut statement = conn
+    .prepare("SELECT category, count(*) FROM item WHERE stock < ?1 GROUP BY 1")
+    .unwrap();
+  assert_eq!(statement.columns(), ["category", "count(*)"]);
+  statement.bind(1, 4).unwrap();
+  let groups = rows(&mut statement);
+  assert_eq!(groups.len(), 20);
+  statement.reset();
+  assert_eq!(rows(&mut statement), groups);
+
+  // An unbound LIMIT is NULL.
+  let mut statement = conn.prepare("SELECT 1 LIMIT ?").unwrap();
+  assert_eq!(
+    statement.step().unwrap_err().to_string(),
+    "datatype mismatch"
+  );
+  assert_eq!(statement.step().unwrap(), None);
+}
+
+#[test]
+fn err_on_bad_parameter_number() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  for sql in ["SELECT ?0", "SELECT ?32767"] {
+    assert!(conn
+      .prepare(sql)
+      .err()
+      .unwrap()
+      .to_string()
+      .contains("variable number must be between ?1 and ?32766"));
+  }
+}
+
+#[test]
+fn ok_on_changed_schema() {
+  let path = std::env::temp_dir().join(format!(
+    "sqlite-rs-statement-schema-{}.sqlite3",
+    std::process::id()
+  ));
+  std::fs::copy("./data/small.sqlite3", &path).unwrap();
+  let conn = SqliteConnection::open(format!("sqlite://{}", path.display())).unwrap();
+  let mut statement = conn.prepare("SELECT count(*) FROM sqlite_schema").unwrap();
+  let mut tables = conn
+    .prepare("SELECT name FROM sqlite_schema WHERE name = ?")
+    .unwrap();
+  tables.bind(1, "trade").unwrap();
+  assert!(rows(&mut tables).is_empty());
+  let count = rows(&mut statement);
+  statement.reset();
+  assert_eq!(rows(&mut statement), count);
+
+  // Another process changes the schema, and the statements are reset to
+  // run again: they are compiled again for the new schema, with the same
+  // bindings.
+  std::fs::write(
+    &path,
+    std::fs::read("./data/small-altered.sqlite3").unwrap(),
+  )
+  .unwrap();
+  statement.reset();
+  let altered = rows(&mut statement);
+  assert_ne!(altered, count);
+  tables.reset();
+  assert_eq!(rows(&mut tables), [["trade".into()]]);
+  assert_eq!(
+    conn
+      .query("SELECT count(*) FROM sqlite_schema")
+      .unwrap()
+      .rows(),
+    altered
+  );
+  drop((statement, tables));
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn ok_on_statements_alongside_queries() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let mut names = conn.prepare("SELECT name FROM item WHERE id = ?").unwrap();
+  let mut prices = conn.prepare("SELECT price FROM item WHERE id = ?").unwrap();
+  let interrupt = conn.interrupt_handle();
+  names.bind(1, 3).unwrap();
+  prices.bind(1, 3).unwrap();
+  assert_eq!(names.step().unwrap().unwrap()[0], "item3".into());
+  let price = conn.query("SELECT price FROM item WHERE id = 3").unwrap();
+  assert_eq!(prices.step().unwrap().unwrap(), price.rows()[0]);
+  assert!(conn.schema().table("item").is_some());
+  interrupt.interrupt();
+  assert!(names.step().is_err());
+}
+
+#[test]
+fn ok_on_stepped_pragmas_and_explain() {
+  let conn = SqliteConnection::open(PLANNER_DB).unwrap();
+  let mut statement = conn.prepare("PRAGMA index_info('item_category')").unwrap();
+  assert_eq!(statement.columns(), ["seqno", "cid", "name"]);
+  assert_eq!(
+    statement.step().unwrap(),
+    Some(vec![0.into(), 1.into(), "category".into()])
+  );
+  statement.reset();
+  assert_eq!(
+    rows(&mut statement),
+    [
+      [0.into(), 1.into(), "category".into()],
+      [1.into(), 2.into(), "price".into()],
+    ] as [[Value; 3]; 2]
+  );
+
+  // EXPLAIN lists one instruction at each step.
+  let mut statement = conn
+    .prepare("EXPLAIN SELECT name FROM item WHERE id = ?")
+    .unwrap();
+  assert_eq!(statement.columns()[1], "opcode");
+  let first = statement.step().unwrap().unwrap();
+  assert_eq!(first[1], "Init".into());
+  let count = rows(&mut statement).len() + 1;
+  statement.reset();
+  assert_eq!(rows(&mut statement).len(), count);
+
+  // A pragma that sets a field writes it when it runs.
+  let path = std::env::temp_dir().join(format!(
+    "sqlite-rs-statement-pragma-{}.sqlite3",
+    std::process::id()
+  ));
+  std::fs::copy("./data/small.sqlite3", &path).unwrap();
+  let mut conn = SqliteConnection::open(format!("sqlite://{}", path.display())).unwrap();
+  let version =
+    |conn: &mut SqliteConnection| rows(&mut conn.prepare("PRAGMA user_version").unwrap());
+  drop(conn.prepare("PRAGMA user_version = 9").unwrap());
+  assert_eq!(version(&mut conn), [[Value::from(0)]]);
+  let mut statement = conn.prepare("PRAGMA user_version = 9").unwrap();
+  assert_eq!(statement.columns().len(), 0);
+  assert_eq!(statement.step().unwrap(), None);
+  drop(statement);
+  assert_eq!(version(&mut conn), [[Value::from(9)]]);
+  drop(conn);
+  std::fs::remove_file(&path).unwrap();
+}
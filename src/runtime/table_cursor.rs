@@ -0,0 +1,394 @@
+//! # Table cursors
+//!
+//!  A table cursor reads the rows of a single table, whatever its storage.
+//! Rowid tables live in table b-trees keyed by rowid. WITHOUT ROWID tables
+//! live in index b-trees keyed by their PRIMARY KEY, with the other columns
+//! stored after the key. Either way, rows are returned with their values in
+//! declaration order.
+//!
+//! *Reference:* https://www.sqlite.org/withoutrowid.html
+
+#[cfg(test)]
+mod tests;
+
+use std::cmp::Ordering;
+
+use crate::{
+  btree::BtreeCursor,
+  file_header::DatabaseTextEncoding,
+  pager::SqlitePager,
+  record::Record,
+  result::{SqliteError, SqliteResult},
+  sql::{ast::Expr, parse_expr},
+  value::{Affinity, Value},
+};
+
+use super::{
+  collation::Collation,
+  eval::{evaluate, no_such_column, Constant, Environment},
+  schema::{ColumnDef, GeneratedColumnKind, SortOrder, TableSchema},
+};
+
+#[derive(Debug)]
+pub struct TableCursor<'a> {
+  cursor: BtreeCursor<'a>,
+  table: &'a TableSchema,
+  encoding: DatabaseTextEncoding,
+  /// Column index of each value of a stored record.
+  storage_order: Vec<usize>,
+  /// The DEFAULT value of each column, for records written before the
+  /// column was added.
+  defaults: Vec<Value>,
+  /// The order and collation of each PRIMARY KEY column of a WITHOUT ROWID
+  /// table, which its b-tree is sorted by.
+  key_orders: Vec<(SortOrder, Collation)>,
+  /// The expression of each VIRTUAL generated column, in an order where
+  /// each comes after those it reads.
+  generated: Vec<(usize, Expr)>,
+}
+
+impl<'a> TableCursor<'a> {
+  /// Opens a cursor over a table, given the collations of the PRIMARY KEY
+  /// of a WITHOUT ROWID table, in key order. Fails when a DEFAULT value
+  /// cannot be evaluated.
+  pub fn new(
+    pager: &'a SqlitePager,
+    table: &'a TableSchema,
+    encoding: DatabaseTextEncoding,
+    collations: Vec<Collation>,
+  ) -> SqliteResult<Self> {
+    Ok(Self {
+      cursor: BtreeCursor::new(pager, table.root_page()),
+      table,
+      encoding,
+      storage_order: table.storage_order(),
+      defaults: table
+        .columns()
+        .iter()
+        .map(default_value)
+        .collect::<SqliteResult<_>>()?,
+      key_orders: table
+        .primary_key()
+        .iter()
+        .map(|(_, order)| *order)
+        .zip(collations)
+        .collect(),
+      generated: generation_order(table),
+    })
+  }
+
+  pub fn table(&self) -> &TableSchema {
+    self.table
+  }
+
+  /// Returns `true` while the cursor points to a row.
+  pub fn is_valid(&self) -> bool {
+    self.cursor.is_valid()
+  }
+
+  /// Moves to the first row, returning `false` if the table is empty.
+  pub fn rewind(&mut self) -> SqliteResult<bool> {
+    self.cursor.rewind()
+  }
+
+  /// Moves to the next row, returning `false` past the last one.
+  pub fn advance(&mut self) -> SqliteResult<bool> {
+    self.cursor.advance()
+  }
+
+  /// Moves to the last row, returning `false` if the table is empty.
+  pub fn last(&mut self) -> SqliteResult<bool> {
+    self.cursor.last()
+  }
+
+  /// Moves to the previous row, returning `false` before the first one.
+  pub fn retreat(&mut self) -> SqliteResult<bool> {
+    self.cursor.retreat()
+  }
+
+  /// The rowid of the current row. WITHOUT ROWID tables have none.
+  pub fn rowid(&self) -> SqliteResult<Option<i64>> {
+    if self.table.is_without_rowid() {
+      return Ok(None);
+    }
+    self.cursor.rowid()
+  }
+
+  /// The values of the current row, in declaration order.
+  ///
+  ///  The rowid alias column reads as the rowid. VIRTUAL generated columns
+  /// are not stored, and are computed from the other columns. A record
+  /// written before `ALTER TABLE ... ADD COLUMN` is shorter than the table,
+  /// and the added columns read as their DEFAULT.
+  pub fn row(&self) -> SqliteResult<Vec<Value>> {
+    let record = Record::decode(&self.cursor.payload()?, &self.encoding)?;
+    let values = record.into_values();
+    let mut row = vec![Value::Null; self.table.columns().len()];
+    for column in self.storage_order.iter().skip(values.len()) {
+      row[*column] = self.defaults[*column].clone();
+    }
+    for (value, column) in values.into_iter().zip(&self.storage_order) {
+      row[*column] = stored_value(&self.table.columns()[*column], value);
+    }
+    if let Some(alias) = self.table.rowid_alias() {
+      row[alias] = self.rowid()?.into();
+    }
+    for (column, expr) in &self.generated {
+      let value = evaluate(
+        expr,
+        &Generating {
+          table: self.table,
+          row: &row,
+          encoding: self.encoding,
+        },
+      )?;
+      row[*column] = value.apply_affinity(self.table.columns()[*column].affinity());
+    }
+    Ok(row)
+  }
+
+  /// Positions the cursor on the row with the given `rowid`, returning
+  /// whether it exists.
+  pub fn seek_rowid(&mut self, rowid: i64) -> SqliteResult<bool> {
+    if self.table.is_without_rowid() {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] is a WITHOUT ROWID table",
+        self.table.name()
+      )));
+    }
+    self.cursor.seek_rowid(rowid)
+  }
+
+  /// Positions the cursor on the first row whose rowid is greater than or
+  /// equal to `rowid`, returning whether there is one.
+  pub fn seek_rowid_ge(&mut self, rowid: i64) -> SqliteResult<bool> {
+    if self.table.is_without_rowid() {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] is a WITHOUT ROWID table",
+        self.table.name()
+      )));
+    }
+    self.cursor.seek_rowid_ge(rowid)
+  }
+
+  /// Positions the cursor on the last row whose rowid is less than or equal
+  /// to `rowid`, returning whether there is one.
+  pub fn seek_rowid_le(&mut self, rowid: i64) -> SqliteResult<bool> {
+    if self.table.is_without_rowid() {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] is a WITHOUT ROWID table",
+        self.table.name()
+      )));
+    }
+    self.cursor.seek_rowid_le(rowid)
+  }
+
+  /// Positions the cursor on the first row of a WITHOUT ROWID table whose
+  /// leading PRIMARY KEY values are greater than or equal to `key`, in key
+  /// order, returning whether there is one.
+  pub fn seek_key_ge(&mut self, key: &[Value]) -> SqliteResult<bool> {
+    if !self.table.is_without_rowid() {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] is not a WITHOUT ROWID table",
+        self.table.name()
+      )));
+    }
+    let orders = &self.key_orders;
+    let encoding = self.encoding;
+    self.cursor.seek_index_ge(|payload| {
+      let record = Record::decode(payload, &encoding)?;
+      Ok(compare_key(record.values(), key, orders))
+    })
+  }
+
+  /// Positions the cursor on the last row of a WITHOUT ROWID table whose
+  /// leading PRIMARY KEY values are less than `key`, or equal to it when
+  /// `is_inclusive`, in key order, returning whether there is one.
+  pub fn seek_key_le(&mut self, key: &[Value], is_inclusive: bool) -> SqliteResult<bool> {
+    if !self.table.is_without_rowid() {
+      return Err(SqliteError::Custom(format!(
+        "Table [{}] is not a WITHOUT ROWID table",
+        self.table.name()
+      )));
+    }
+    let orders = &self.key_orders;
+    let encoding = self.encoding;
+    let compare = |payload: &[u8]| {
+      let record = Record::decode(payload, &encoding)?;
+      Ok(compare_key(record.values(), key, orders))
+    };
+    match is_inclusive {
+      true => self.cursor.seek_index_le(compare),
+      false => self.cursor.seek_index_lt(compare),
+    }
+  }
+
+  /// Orders the leading PRIMARY KEY values of the current row of a WITHOUT
+  /// ROWID table against `key`, in key order.
+  pub fn compare_key(&self, key: &[Value]) -> SqliteResult<Ordering> {
+    let record = Record::decode(&self.cursor.payload()?, &self.encoding)?;
+    Ok(compare_key(record.values(), key, &self.key_orders))
+  }
+
+  /// Positions the cursor on the row whose PRIMARY KEY equals `key`, given
+  /// in key order, returning whether it exists.
+  ///
+  ///  WITHOUT ROWID tables are searched by key. Rowid tables are searched by
+  /// rowid when the key is the rowid alias, and scanned otherwise.
+  pub fn seek_primary_key(&mut self, key: &[Value]) -> SqliteResult<bool> {
+    let primary_key = self.table.primary_key();
+    if primary_key.is_empty() || primary_key.len() != key.len() {
+      return Err(SqliteError::Custom(format!(
+        "Expected a key of [{}] values for table [{}]",
+        primary_key.len(),
+        self.table.name()
+      )));
+    }
+
+    if self.table.is_without_rowid() {
+      return Ok(self.seek_key_ge(key)? && self.compare_key(key)? == Ordering::Equal);
+    }
+
+    if self.table.rowid_alias().is_some() {
+      return match key[0] {
+        Value::Integer(rowid) => self.seek_rowid(rowid),
+        Value::Real(real) if real.fract() == 0.0 && real.abs() < 9.2e18 => {
+          self.seek_rowid(real as i64)
+        }
+        _ => Ok(false),
+      };
+    }
+
+    let mut has_row = self.rewind()?;
+    while has_row {
+      let row = self.row()?;
+      let is_match = primary_key
+        .iter()
+        .zip(key)
+        .all(|((column, _), value)| row[*column].compare(value) == Ordering::Equal);
+      if is_match {
+        return Ok(true);
+      }
+      has_row = self.advance()?;
+    }
+    Ok(false)
+  }
+}
+
+/// `ALTER TABLE ... ADD COLUMN` only accepts constant defaults, so a default
+/// that cannot be evaluated without a row is never needed here. One that
+/// fails to evaluate is an error rather than NULL.
+fn default_value(column: &ColumnDef) -> SqliteResult<Value> {
+  let value = match column.default() {
+    Some(default) => evaluate(&parse_expr(default.sql())?, &Constant)?,
+    None => Value::Null,
+  };
+  Ok(value.apply_affinity(column.affinity()))
+}
+
+/// The VIRTUAL generated columns of a table with their expressions, each
+/// after the others it reads. SQLite rejects a table whose generated
+/// columns read each other in a loop, so there is always such an order.
+fn generation_order(table: &TableSchema) -> Vec<(usize, Expr)> {
+  let mut pending: Vec<(usize, Expr)> = table
+    .columns()
+    .iter()
+    .enumerate()
+    .filter_map(|(column, definition)| {
+      let generated = definition.generated()?;
+      match generated.kind() {
+        GeneratedColumnKind::Virtual => Some((column, parse_expr(generated.expression()).ok()?)),
+        GeneratedColumnKind::Stored => None,
+      }
+    })
+    .collect();
+  let mut ordered = vec![];
+  while !pending.is_empty() {
+    let is_pending = |name: &str, pending: &[(usize, Expr)]| {
+      table
+        .column(name)
+        .is_some_and(|(column, _)| pending.iter().any(|(other, _)| *other == column))
+    };
+    let ready = pending.iter().position(|(_, expr)| {
+      let mut is_ready = true;
+      expr.walk(&mut |expr| {
+        if let Expr::Column { name, .. } = expr {
+          is_ready &= !is_pending(name, &pending);
+        }
+      });
+      is_ready
+    });
+    // A loop is read as it stands.
+    ordered.push(pending.remove(ready.unwrap_or(0)));
+  }
+  ordered
+}
+
+/// A row whose generated columns are being computed, for their expressions
+/// to read its other columns.
+struct Generating<'r> {
+  table: &'r TableSchema,
+  row: &'r [Value],
+  encoding: DatabaseTextEncoding,
+}
+
+impl Generating<'_> {
+  fn find(&self, table: Option<&str>, name: &str) -> Option<(usize, &ColumnDef)> {
+    match table {
+      Some(table) if !table.eq_ignore_ascii_case(self.table.name()) => None,
+      _ => self.table.column(name),
+    }
+  }
+}
+
+impl Environment for Generating<'_> {
+  fn column(&self, _: Option<&str>, table: Option<&str>, name: &str) -> SqliteResult<Value> {
+    let (column, _) = self
+      .find(table, name)
+      .ok_or_else(|| no_such_column(table, name))?;
+    Ok(self.row.get(column).cloned().unwrap_or_default())
+  }
+
+  fn column_affinity(&self, _: Option<&str>, table: Option<&str>, name: &str) -> Affinity {
+    self
+      .find(table, name)
+      .map_or(Affinity::Blob, |(_, column)| column.affinity())
+  }
+
+  fn column_collation(&self, _: Option<&str>, table: Option<&str>, name: &str) -> Option<String> {
+    let (_, column) = self.find(table, name)?;
+    column.collation().map(str::to_string)
+  }
+
+  fn encoding(&self) -> DatabaseTextEncoding {
+    self.encoding
+  }
+}
+
+/// A value read from a record for a column. SQLite stores a REAL with no
+/// fractional part as an integer, to be read back as a REAL.
+pub(super) fn stored_value(column: &ColumnDef, value: Value) -> Value {
+  match value {
+    Value::Integer(integer) if column.affinity() == Affinity::Real => Value::Real(integer as f64),
+    value => value,
+  }
+}
+
+/// Orders the leading values of a stored key against a search key, honoring
+/// DESC key columns and the collation of each.
+pub(super) fn compare_key(
+  stored: &[Value],
+  key: &[Value],
+  orders: &[(SortOrder, Collation)],
+) -> Ordering {
+  for ((stored, key), (order, collation)) in stored.iter().zip(key).zip(orders) {
+    let ordering = match order {
+      SortOrder::Asc => collation.compare(stored, key),
+      SortOrder::Desc => collation.compare(key, stored),
+    };
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
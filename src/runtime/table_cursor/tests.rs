@@ -0,0 +1,129 @@
+//! Tests for table cursors
+//!
+//! To run: `cargo test runtime::table_cursor`
+
+use crate::{runtime::schema::TableSchema, value::Value, SqliteConnection};
+
+const WITHOUT_ROWID_DB: &str = "sqlite://./data/without-rowid.db";
+
+#[test]
+fn ok_on_without_rowid_table_scan() {
+  let conn = SqliteConnection::open(WITHOUT_ROWID_DB).unwrap();
+  let mut cursor = conn.table_cursor("lookup").unwrap();
+
+  let mut rows = vec![];
+  let mut has_row = cursor.rewind().unwrap();
+  while has_row {
+    assert_eq!(cursor.rowid().unwrap(), None);
+    rows.push(cursor.row().unwrap());
+    has_row = cursor.advance().unwrap();
+  }
+  assert_eq!(rows.len(), 3000);
+
+  // Ordered by code, then by version descending.
+  assert_eq!(
+    rows[0],
+    [
+      Value::from("C0000"),
+      Value::from(2),
+      Value::from("label 2"),
+      Value::Null
+    ]
+  );
+  assert_eq!(rows[1][2], Value::from("label 1"));
+  for pair in rows.windows(2) {
+    let (a, b) = (&pair[0], &pair[1]);
+    assert!(a[0].compare(&b[0]).then(b[1].compare(&a[1])).is_lt());
+  }
+
+  // Rows whose note spills onto overflow pages.
+  let blobs: Vec<_> = rows.iter().filter_map(|row| row[3].as_blob()).collect();
+  assert_eq!(blobs.len(), 6);
+  assert!(blobs.iter().all(|blob| *blob == [0; 5000]));
+}
+
+#[test]
+fn ok_on_without_rowid_primary_key_lookup() {
+  let conn = SqliteConnection::open(WITHOUT_ROWID_DB).unwrap();
+  let mut cursor = conn.table_cursor("lookup").unwrap();
+
+  for i in [1, 2, 3, 1500, 1501, 2999, 3000] {
+    let key = [Value::from(format!("C{:04}", i / 3)), Value::from(i % 3)];
+    assert!(cursor.seek_primary_key(&key).unwrap(), "{key:?}");
+    assert_eq!(cursor.row().unwrap()[2], Value::from(format!("label {i}")));
+  }
+
+  assert!(!cursor
+    .seek_primary_key(&["C0000".into(), 0.into()])
+    .unwrap());
+  assert!(!cursor
+    .seek_primary_key(&["C9999".into(), 0.into()])
+    .unwrap());
+  assert!(!cursor
+    .seek_primary_key(&["C0001".into(), 5.into()])
+    .unwrap());
+  assert!(cursor.seek_primary_key(&["C0001".into()]).is_err());
+  assert!(cursor.seek_rowid(1).is_err());
+}
+
+#[test]
+fn ok_on_restore_declaration_order() {
+  let conn = SqliteConnection::open(WITHOUT_ROWID_DB).unwrap();
+  let mut cursor = conn.table_cursor("reordered").unwrap();
+
+  assert!(cursor.rewind().unwrap());
+  assert_eq!(
+    cursor.row().unwrap(),
+    [Value::from(2), Value::from("two"), Value::from("y")]
+  );
+  assert!(cursor.seek_primary_key(&["z".into(), 1.into()]).unwrap());
+  assert_eq!(cursor.row().unwrap()[1], Value::from("one"));
+}
+
+#[test]
+fn ok_on_rowid_table_lookups() {
+  let conn = SqliteConnection::open(WITHOUT_ROWID_DB).unwrap();
+  let mut cursor = conn.table_cursor("generated").unwrap();
+
+  assert!(cursor.seek_primary_key(&[7.into()]).unwrap());
+  assert_eq!(cursor.rowid().unwrap(), Some(7));
+  assert_eq!(
+    cursor.row().unwrap(),
+    [
+      Value::from(7),
+      Value::from(5),
+      Value::from(10),
+      Value::from(15),
+      Value::from("w")
+    ]
+  );
+  assert!(!cursor.seek_primary_key(&[8.into()]).unwrap());
+
+  let conn = SqliteConnection::open("sqlite://./data/small.sqlite3").unwrap();
+  assert!(conn.table_cursor("missing").is_err());
+}
+
+#[test]
+fn ok_on_defaults_of_added_columns() {
+  let conn = SqliteConnection::open("sqlite://./data/added-columns.db").unwrap();
+  let result = conn.query("SELECT * FROM flag").unwrap();
+  assert_eq!(
+    result.rows(),
+    [
+      [Value::from(1), Value::from(1), Value::from(0)],
+      [Value::from(2), Value::from(1), Value::from(0)],
+      [Value::from(3), Value::from(0), Value::from(1)],
+    ]
+  );
+}
+
+#[test]
+fn err_on_bad_default() {
+  let table = TableSchema::parse("CREATE TABLE t(a DEFAULT (no_such_function()))").unwrap();
+  assert_eq!(
+    super::default_value(&table.columns()[0])
+      .unwrap_err()
+      .to_string(),
+    "no such function: no_such_function"
+  );
+}
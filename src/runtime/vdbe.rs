@@ -0,0 +1,1097 @@
+//! # Virtual database engine
+//!
+//!  Queries run as programs for a register machine, modelled on SQLite's
+//! VDBE. A program is a list of instructions, each an opcode with its
+//! operands (see [`op`]). Registers hold values, and cursors read the
+//! b-trees of tables and indexes, or the rows of a sorter. A program runs
+//! from its first instruction until it halts, and stops each time it has a
+//! result row, so a statement is stepped through one row at a time.
+//!
+//!  A connection may be interrupted from another thread: a running program
+//! checks for it before each instruction, and fails. The request is
+//! forgotten when the next program starts.
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+#[cfg(test)]
+mod tests;
+
+mod op;
+
+use std::{
+  cell::RefCell,
+  rc::Rc,
+  sync::{
+    atomic::{self, AtomicBool},
+    Arc,
+  },
+};
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  result::{SqliteError, SqliteResult},
+  sql::ast::CompoundOperator,
+  value::Value,
+};
+
+pub(crate) use self::op::{JsonCheck, Op, Tree};
+
+use super::{
+  collation::Collation,
+  eval::{
+    binary, boolean, compare_values, comparison, is_true, match_pattern, negate, no_such_column,
+    to_integer, unix_time_millis, Environment,
+  },
+  functions::{to_jsonb, Implementation},
+  index_cursor::IndexCursor,
+  query::{compute_windows, Aggregate, Distinct, Queue},
+  sorter::{compare_keys, KeySet, Sorter},
+  statement::Bindings,
+  table_cursor::TableCursor,
+  SqliteRuntime,
+};
+
+/// The columns of `EXPLAIN`, which lists the instructions of a program.
+pub(crate) const EXPLAIN_COLUMNS: [&str; 8] =
+  ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"];
+
+/// Interrupts the programs running on a connection, from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+  is_interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+  /// Makes the running program fail with `interrupted`.
+  pub fn interrupt(&self) {
+    self.is_interrupted.store(true, atomic::Ordering::Relaxed);
+  }
+
+  fn is_interrupted(&self) -> bool {
+    self.is_interrupted.load(atomic::Ordering::Relaxed)
+  }
+
+  fn clear(&self) {
+    self.is_interrupted.store(false, atomic::Ordering::Relaxed);
+  }
+}
+
+/// The instructions of a program, and the registers and cursors it uses.
+#[derive(Debug, Default)]
+pub(crate) struct Program<'a> {
+  ops: Vec<Op<'a>>,
+  /// The number of registers, which are numbered from 1.
+  registers: usize,
+  cursors: usize,
+}
+
+impl<'a> Program<'a> {
+  pub(crate) fn new(ops: Vec<Op<'a>>, registers: usize, cursors: usize) -> Self {
+    Self {
+      ops,
+      registers,
+      cursors,
+    }
+  }
+}
+
+/// What a cursor reads.
+enum Cursor<'a> {
+  Table(TableCursor<'a>, Current),
+  Index(IndexCursor<'a>, Current),
+  Sorter(SorterState),
+  Ephemeral(Ephemeral),
+  Set(KeySet),
+  Queue(Queue),
+}
+
+/// The row a b-tree cursor is on.
+#[derive(Default)]
+struct Current {
+  /// Its values, once read.
+  values: Option<Vec<Value>>,
+  /// Whether it is a row of NULLs.
+  is_null_row: bool,
+}
+
+impl Current {
+  /// Forgets the row, once the cursor has moved.
+  fn reset(&mut self) {
+    *self = Self::default();
+  }
+}
+
+enum SorterState {
+  /// Rows are added, and combined by a compound operator when given.
+  Filling(Sorter, Option<CompoundOperator>),
+  /// Rows are read, with the current one.
+  Sorted(SortedRows, Option<Vec<Value>>),
+}
+
+type SortedRows = Box<dyn Iterator<Item = SqliteResult<Vec<Value>>>>;
+
+/// A cursor on the rows of an ephemeral table, which other cursors may
+/// share. Its rows have no rowid.
+struct Ephemeral {
+  rows: Rc<RefCell<Vec<Vec<Value>>>>,
+  /// The index of the current row.
+  position: usize,
+  is_null_row: bool,
+}
+
+impl Ephemeral {
+  fn new(rows: Rc<RefCell<Vec<Vec<Value>>>>) -> Self {
+    Self {
+      rows,
+      position: 0,
+      is_null_row: false,
+    }
+  }
+
+  /// Moves to the row at `position`, returning whether there is one.
+  fn seek(&mut self, position: usize) -> bool {
+    self.position = position;
+    self.is_null_row = false;
+    position < self.rows.borrow().len()
+  }
+
+  fn column(&self, column: usize) -> Value {
+    match self.is_null_row {
+      true => Value::Null,
+      false => self
+        .rows
+        .borrow()
+        .get(self.position)
+        .and_then(|row| row.get(column).cloned())
+        .unwrap_or_default(),
+    }
+  }
+}
+
+/// What the functions a program calls see of it.
+struct Env<'a> {
+  runtime: &'a SqliteRuntime,
+  statement_time: i64,
+  bindings: Option<&'a Bindings>,
+}
+
+impl Environment for Env<'_> {
+  fn column(&self, _: Option<&str>, table: Option<&str>, name: &str) -> SqliteResult<Value> {
+    Err(no_such_column(table, name))
+  }
+
+  fn collation(&self, name: &str) -> SqliteResult<Collation> {
+    self.runtime.collation(name)
+  }
+
+  fn encoding(&self) -> DatabaseTextEncoding {
+    *self.runtime.file_header().database_text_encoding()
+  }
+
+  fn variable(&self, name: &str) -> SqliteResult<Value> {
+    Ok(
+      self
+        .bindings
+        .map_or(Value::Null, |bindings| bindings.value(name)),
+    )
+  }
+
+  fn statement_time(&self) -> i64 {
+    self.statement_time
+  }
+}
+
+/// A running program.
+pub(crate) struct Vdbe<'a> {
+  runtime: &'a SqliteRuntime,
+  program: Rc<Program<'a>>,
+  /// The address of the next instruction.
+  pc: usize,
+  registers: Vec<Value>,
+  cursors: Vec<Option<Cursor<'a>>>,
+  /// The time the program started, in milliseconds since the Unix epoch.
+  statement_time: i64,
+  /// The values bound to the parameters of its statement, if it has any.
+  bindings: Option<Rc<Bindings>>,
+  /// The aggregates of the program, by slot.
+  aggregates: Vec<Option<Aggregate>>,
+  /// Whether each `Once` instruction has run, by address.
+  has_run: Vec<bool>,
+  is_halted: bool,
+  /// Whether each step returns the listing of the next instruction rather
+  /// than run it, for `EXPLAIN`.
+  is_listing: bool,
+}
+
+impl<'a> Vdbe<'a> {
+  pub(crate) fn new(runtime: &'a SqliteRuntime, program: Rc<Program<'a>>) -> Self {
+    runtime.interrupt.clear();
+    Self {
+      runtime,
+      registers: vec![Value::Null; program.registers + 1],
+      cursors: (0..program.cursors).map(|_| None).collect(),
+      aggregates: vec![],
+      has_run: vec![false; program.ops.len()],
+      program,
+      pc: 0,
+      statement_time: unix_time_millis(),
+      bindings: None,
+      is_halted: false,
+      is_listing: false,
+    }
+  }
+
+  /// Makes the program read its parameters from `bindings`. They are NULL
+  /// otherwise.
+  pub(crate) fn with_bindings(self, bindings: Rc<Bindings>) -> Self {
+    Self {
+      bindings: Some(bindings),
+      ..self
+    }
+  }
+
+  /// Makes each step return the listing of the next instruction, as a row
+  /// of `EXPLAIN`, rather than run the program.
+  pub(crate) fn listing(self) -> Self {
+    Self {
+      is_listing: true,
+      ..self
+    }
+  }
+
+  /// Runs the program until its next result row, or `None` once it has
+  /// halted. It halts on an error too.
+  pub(crate) fn step(&mut self) -> SqliteResult<Option<Vec<Value>>> {
+    let program = Rc::clone(&self.program);
+    if self.is_listing {
+      let row = program.ops.get(self.pc).map(|op| op.explain(self.pc));
+      self.pc += 1;
+      return Ok(row);
+    }
+    while !self.is_halted {
+      if self.runtime.interrupt.is_interrupted() {
+        self.is_halted = true;
+        return Err(SqliteError::Custom("interrupted".into()));
+      }
+      let Some(op) = program.ops.get(self.pc) else {
+        self.is_halted = true;
+        break;
+      };
+      self.pc += 1;
+      match self.execute(op) {
+        Ok(Some(row)) => return Ok(Some(row)),
+        Ok(None) => {}
+        Err(err) => {
+          self.is_halted = true;
+          return Err(err);
+        }
+      }
+    }
+    Ok(None)
+  }
+
+  /// Runs an instruction, returning the row of `ResultRow`.
+  fn execute(&mut self, op: &Op<'a>) -> SqliteResult<Option<Vec<Value>>> {
+    match op {
+      Op::Init { target } | Op::Goto { target } => self.pc = *target,
+      Op::Halt => self.is_halted = true,
+      Op::Integer { value, dest } => self.registers[*dest] = Value::Integer(*value),
+      Op::Real { value, dest } => self.registers[*dest] = Value::Real(*value),
+      Op::String8 { value, dest } => self.registers[*dest] = Value::Text(value.clone()),
+      Op::Blob { value, dest } => self.registers[*dest] = Value::Blob(value.clone()),
+      Op::Null { dest } => self.registers[*dest] = Value::Null,
+      Op::Variable { name, dest } => self.registers[*dest] = self.env().variable(name)?,
+      Op::Copy { source, dest } => self.registers[*dest] = self.registers[*source].clone(),
+      Op::OpenRead { cursor, tree } => {
+        self.cursors[*cursor] = Some(match tree {
+          Tree::Table(table) => {
+            Cursor::Table(self.runtime.table_cursor(table.name())?, Current::default())
+          }
+          Tree::Index(index) => {
+            Cursor::Index(self.runtime.index_cursor(index.name())?, Current::default())
+          }
+        });
+      }
+      Op::Rewind { cursor, target } => {
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) => {
+            current.reset();
+            cursor.rewind()?
+          }
+          Cursor::Index(cursor, current) => {
+            current.reset();
+            cursor.rewind()?
+          }
+          Cursor::Ephemeral(ephemeral) => ephemeral.seek(0),
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_unless(has_row, *target);
+      }
+      Op::Next { cursor, target } => {
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) => {
+            let is_on_row = !current.is_null_row && cursor.is_valid();
+            current.reset();
+            is_on_row && cursor.advance()?
+          }
+          Cursor::Index(cursor, current) => {
+            let is_on_row = !current.is_null_row && cursor.is_valid();
+            current.reset();
+            is_on_row && cursor.advance()?
+          }
+          Cursor::Ephemeral(ephemeral) => {
+            !ephemeral.is_null_row && ephemeral.seek(ephemeral.position + 1)
+          }
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_if(has_row, *target);
+      }
+      Op::Last { cursor, target } => {
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) => {
+            current.reset();
+            cursor.last()?
+          }
+          Cursor::Index(cursor, current) => {
+            current.reset();
+            cursor.last()?
+          }
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_unless(has_row, *target);
+      }
+      Op::Prev { cursor, target } => {
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) => {
+            let is_on_row = !current.is_null_row && cursor.is_valid();
+            current.reset();
+            is_on_row && cursor.retreat()?
+          }
+          Cursor::Index(cursor, current) => {
+            let is_on_row = !current.is_null_row && cursor.is_valid();
+            current.reset();
+            is_on_row && cursor.retreat()?
+          }
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_if(has_row, *target);
+      }
+      Op::Column {
+        cursor,
+        column,
+        dest,
+      } => {
+        let value = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) => read(current, column, || cursor.row())?,
+          Cursor::Index(cursor, current) => read(current, column, || cursor.entry())?,
+          Cursor::Ephemeral(ephemeral) => ephemeral.column(*column),
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.registers[*dest] = value;
+      }
+      Op::Rowid { cursor, dest } | Op::IdxRowid { cursor, dest } => {
+        let rowid = match self.cursor(*cursor)? {
+          Cursor::Table(_, current) | Cursor::Index(_, current) if current.is_null_row => None,
+          Cursor::Table(cursor, _) => cursor.rowid()?,
+          Cursor::Index(cursor, _) => cursor.rowid()?,
+          Cursor::Ephemeral(_) => None,
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.registers[*dest] = rowid.into();
+      }
+      Op::SeekRowid {
+        cursor,
+        key,
+        target,
+      } => {
+        let rowid = match self.registers[*key] {
+          Value::Integer(rowid) => Some(rowid),
+          Value::Real(real) if real.fract() == 0.0 && real.abs() < 9.2e18 => Some(real as i64),
+          _ => None,
+        };
+        let (cursor, current) = self.table_cursor(*cursor)?;
+        current.reset();
+        let is_found = match rowid {
+          Some(rowid) => cursor.seek_rowid(rowid)?,
+          None => false,
+        };
+        self.jump_unless(is_found, *target);
+      }
+      Op::SeekGE {
+        cursor,
+        key,
+        count,
+        target,
+      }
+      | Op::SeekGT {
+        cursor,
+        key,
+        count,
+        target,
+      } => {
+        let is_greater = matches!(op, Op::SeekGT { .. });
+        let key = self.registers[*key..*key + *count].to_vec();
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) if !cursor.table().is_without_rowid() => {
+            current.reset();
+            // Integers sort before texts and blobs, which no rowid reaches.
+            match key.first() {
+              Some(Value::Integer(rowid)) if is_greater => {
+                cursor.seek_rowid_ge(rowid.saturating_add(1))?
+              }
+              Some(Value::Integer(rowid)) => cursor.seek_rowid_ge(*rowid)?,
+              Some(Value::Real(real)) if *real < -9.2e18 => cursor.rewind()?,
+              Some(Value::Real(real)) if *real < 9.2e18 => {
+                cursor.seek_rowid_ge(real.floor() as i64)?
+              }
+              _ => false,
+            }
+          }
+          Cursor::Table(cursor, current) => {
+            current.reset();
+            cursor.seek_key_ge(&key)?
+          }
+          Cursor::Index(cursor, current) => {
+            current.reset();
+            cursor.seek_ge(&key)?
+          }
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_unless(has_row, *target);
+      }
+      Op::SeekLE {
+        cursor,
+        key,
+        count,
+        target,
+      }
+      | Op::SeekLT {
+        cursor,
+        key,
+        count,
+        target,
+      } => {
+        let is_less = matches!(op, Op::SeekLT { .. });
+        let key = self.registers[*key..*key + *count].to_vec();
+        let has_row = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, current) if !cursor.table().is_without_rowid() => {
+            current.reset();
+            // Every rowid is less than a text or a blob.
+            match key.first() {
+              Some(Value::Integer(rowid)) if is_less => match rowid.checked_sub(1) {
+                Some(rowid) => cursor.seek_rowid_le(rowid)?,
+                None => false,
+              },
+              Some(Value::Integer(rowid)) => cursor.seek_rowid_le(*rowid)?,
+              Some(Value::Real(real)) if *real >= 9.2e18 => cursor.last()?,
+              Some(Value::Real(real)) if *real >= -9.2e18 => match is_less {
+                true => cursor.seek_rowid_le(real.ceil() as i64 - 1)?,
+                false => cursor.seek_rowid_le(real.floor() as i64)?,
+              },
+              Some(Value::Text(_) | Value::Blob(_)) => cursor.last()?,
+              _ => false,
+            }
+          }
+          Cursor::Table(cursor, current) => {
+            current.reset();
+            cursor.seek_key_le(&key, !is_less)?
+          }
+          Cursor::Index(cursor, current) => {
+            current.reset();
+            cursor.seek_le(&key, !is_less)?
+          }
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        self.jump_unless(has_row, *target);
+      }
+      Op::IdxGT {
+        cursor,
+        key,
+        count,
+        target,
+      }
+      | Op::IdxLT {
+        cursor,
+        key,
+        count,
+        target,
+      } => {
+        let key = self.registers[*key..*key + *count].to_vec();
+        let ordering = match self.cursor(*cursor)? {
+          Cursor::Table(cursor, _) => cursor.compare_key(&key)?,
+          Cursor::Index(cursor, _) => cursor.compare_entry(&key)?,
+          _ => return Err(not_a_btree(*cursor)),
+        };
+        let is_past = match op {
+          Op::IdxLT { .. } => ordering.is_lt(),
+          _ => ordering.is_gt(),
+        };
+        self.jump_if(is_past, *target);
+      }
+      Op::NotFound {
+        cursor,
+        key,
+        count,
+        target,
+      } => {
+        let key = self.registers[*key..*key + *count].to_vec();
+        let (cursor, current) = self.table_cursor(*cursor)?;
+        current.reset();
+        let is_found = cursor.seek_primary_key(&key)?;
+        self.jump_unless(is_found, *target);
+      }
+      Op::NullRow { cursor } => match self.cursor(*cursor)? {
+        Cursor::Table(_, current) | Cursor::Index(_, current) => {
+          current.reset();
+          current.is_null_row = true;
+        }
+        Cursor::Ephemeral(ephemeral) => ephemeral.is_null_row = true,
+        _ => return Err(not_a_btree(*cursor)),
+      },
+      Op::Affinity { start, affinities } => {
+        for (register, affinity) in self.registers[*start..].iter_mut().zip(affinities) {
+          *register = core::mem::take(register).apply_affinity(*affinity);
+        }
+      }
+      Op::RealAffinity { register } => {
+        if let Value::Integer(integer) = self.registers[*register] {
+          self.registers[*register] = Value::Real(integer as f64);
+        }
+      }
+      Op::Cast { register, affinity } => {
+        let encoding = *self.runtime.file_header().database_text_encoding();
+        self.registers[*register] = self.registers[*register].cast_in(*affinity, encoding);
+      }
+      Op::IsNull { register, target } => {
+        let is_null = self.registers[*register].is_null();
+        self.jump_if(is_null, *target);
+      }
+      Op::NotNull { register, target } => {
+        let is_null = self.registers[*register].is_null();
+        self.jump_unless(is_null, *target);
+      }
+      Op::If {
+        register,
+        target,
+        if_null,
+      } => {
+        let is_true = is_true(&self.registers[*register]).unwrap_or(*if_null);
+        self.jump_if(is_true, *target);
+      }
+      Op::IfNot {
+        register,
+        target,
+        if_null,
+      } => {
+        let is_false = is_true(&self.registers[*register]).map_or(*if_null, |is_true| !is_true);
+        self.jump_if(is_false, *target);
+      }
+      Op::IfPos {
+        register,
+        target,
+        decrement,
+      } => {
+        if let Value::Integer(integer) = self.registers[*register] {
+          if integer > 0 {
+            self.registers[*register] = Value::Integer(integer - decrement);
+            self.pc = *target;
+          }
+        }
+      }
+      Op::DecrJumpZero { register, target } => {
+        if let Value::Integer(integer) = self.registers[*register] {
+          let integer = integer.saturating_sub(1);
+          self.registers[*register] = Value::Integer(integer);
+          self.jump_if(integer == 0, *target);
+        }
+      }
+      Op::Compare {
+        operator,
+        left,
+        right,
+        dest,
+        affinities: [left_affinity, right_affinity],
+        collation,
+      } => {
+        let is_match = comparison(*operator)
+          .ok_or_else(|| SqliteError::Custom(format!("unsupported comparison: {operator:?}")))?;
+        let ordering = compare_values(
+          self.registers[*left].clone(),
+          *left_affinity,
+          self.registers[*right].clone(),
+          *right_affinity,
+          collation,
+          *operator,
+        );
+        self.registers[*dest] = boolean(ordering.map(is_match));
+      }
+      Op::Arithmetic {
+        operator,
+        left,
+        right,
+        dest,
+      } => {
+        self.registers[*dest] = binary(*operator, &self.registers[*left], &self.registers[*right])?;
+      }
+      Op::And { left, right, dest } => {
+        let left = is_true(&self.registers[*left]);
+        let right = is_true(&self.registers[*right]);
+        // FALSE AND NULL is FALSE, TRUE AND NULL is NULL.
+        self.registers[*dest] = match (left, right) {
+          (Some(false), _) | (_, Some(false)) => boolean(Some(false)),
+          (Some(true), Some(true)) => boolean(Some(true)),
+          _ => Value::Null,
+        };
+      }
+      Op::Or { left, right, dest } => {
+        let left = is_true(&self.registers[*left]);
+        let right = is_true(&self.registers[*right]);
+        // TRUE OR NULL is TRUE, FALSE OR NULL is NULL.
+        self.registers[*dest] = match (left, right) {
+          (Some(true), _) | (_, Some(true)) => boolean(Some(true)),
+          (Some(false), Some(false)) => boolean(Some(false)),
+          _ => Value::Null,
+        };
+      }
+      Op::Not { source, dest } => {
+        self.registers[*dest] = boolean(is_true(&self.registers[*source]).map(|is_true| !is_true));
+      }
+      Op::BitNot { source, dest } => {
+        self.registers[*dest] = match to_integer(&self.registers[*source]) {
+          Some(integer) => Value::Integer(!integer),
+          None => Value::Null,
+        };
+      }
+      Op::Negate { source, dest } => self.registers[*dest] = negate(&self.registers[*source]),
+      Op::Function {
+        function,
+        args,
+        count,
+        dest,
+      } => {
+        let Implementation::Scalar(implementation) = function.implementation else {
+          return Err(SqliteError::Custom(format!(
+            "misuse of aggregate function {}()",
+            function.name()
+          )));
+        };
+        let value = implementation(&self.env(), &self.registers[*args..*args + *count])?;
+        self.registers[*dest] = value;
+      }
+      Op::Like {
+        operator,
+        args,
+        count,
+        dest,
+      } => {
+        let args = &self.registers[*args..*args + *count];
+        let is_match = match_pattern(*operator, &args[0], &args[1], args.get(2))?;
+        self.registers[*dest] = boolean(is_match);
+      }
+      Op::ResultRow { start, count } => {
+        return Ok(Some(self.registers[*start..*start + *count].to_vec()))
+      }
+      Op::SorterOpen {
+        cursor,
+        order,
+        limit,
+        combine,
+      } => {
+        let sorter = Sorter::new(order.clone(), self.runtime.sort_memory_budget());
+        let sorter = match limit {
+          Some(limit) => sorter.with_limit(*limit),
+          None => sorter,
+        };
+        self.cursors[*cursor] = Some(Cursor::Sorter(SorterState::Filling(sorter, *combine)));
+      }
+      Op::SorterInsert {
+        cursor,
+        key,
+        values,
+        count,
+      } => {
+        let values = self.registers[*values..*values + *count].to_vec();
+        let registers = &self.registers;
+        let Some(Cursor::Sorter(SorterState::Filling(sorter, _))) = self.cursors[*cursor].as_mut()
+        else {
+          return Err(not_a_sorter(*cursor));
+        };
+        let key = registers[*key..*key + sorter.key_count()].to_vec();
+        sorter.push(key, values)?;
+      }
+      Op::SorterSort { cursor, target } => {
+        let Some(Cursor::Sorter(SorterState::Filling(sorter, combine))) =
+          self.cursors[*cursor].take()
+        else {
+          return Err(not_a_sorter(*cursor));
+        };
+        let key_orders = sorter.key_orders().to_vec();
+        let sorted = sorter.finish()?;
+        let mut sorted: SortedRows = match combine {
+          Some(operator) => Box::new(Distinct::new(sorted, key_orders, operator)),
+          None => Box::new(sorted.map(|record| record.map(|(_, values)| values))),
+        };
+        let current = sorted.next().transpose()?;
+        let has_row = current.is_some();
+        self.cursors[*cursor] = Some(Cursor::Sorter(SorterState::Sorted(sorted, current)));
+        self.jump_unless(has_row, *target);
+      }
+      Op::SorterData {
+        cursor,
+        dest,
+        count,
+      } => {
+        let Some(Cursor::Sorter(SorterState::Sorted(_, Some(values)))) = &self.cursors[*cursor]
+        else {
+          return Err(not_a_sorter(*cursor));
+        };
+        let values = values.clone();
+        for (register, value) in self.registers[*dest..*dest + *count].iter_mut().zip(values) {
+          *register = value;
+        }
+      }
+      Op::SorterNext { cursor, target } => {
+        let Some(Cursor::Sorter(SorterState::Sorted(sorted, current))) = &mut self.cursors[*cursor]
+        else {
+          return Err(not_a_sorter(*cursor));
+        };
+        *current = sorted.next().transpose()?;
+        let has_row = current.is_some();
+        self.jump_if(has_row, *target);
+      }
+      Op::OpenEphemeral { cursor, .. } => {
+        self.cursors[*cursor] = Some(Cursor::Ephemeral(Ephemeral::new(Rc::default())));
+      }
+      Op::OpenDup { cursor, source } => {
+        let rows = Rc::clone(&self.ephemeral(*source)?.rows);
+        self.cursors[*cursor] = Some(Cursor::Ephemeral(Ephemeral::new(rows)));
+      }
+      Op::Insert {
+        cursor,
+        start,
+        count,
+      } => {
+        let row = self.registers[*start..*start + *count].to_vec();
+        self.ephemeral(*cursor)?.rows.borrow_mut().push(row);
+      }
+      Op::SeekPosition {
+        cursor,
+        register,
+        target,
+      } => {
+        let position = match self.registers[*register] {
+          Value::Integer(position) => usize::try_from(position).unwrap_or(usize::MAX),
+          _ => usize::MAX,
+        };
+        let has_row = self.ephemeral(*cursor)?.seek(position);
+        self.jump_unless(has_row, *target);
+      }
+      Op::AddImm { register, value } => {
+        let integer = to_integer(&self.registers[*register]).unwrap_or_default();
+        self.registers[*register] = Value::Integer(integer.wrapping_add(*value));
+      }
+      Op::MustBeInt { register } => {
+        let integer = match &self.registers[*register] {
+          Value::Integer(integer) => Some(*integer),
+          Value::Text(text) => text.trim().parse().ok(),
+          _ => None,
+        };
+        let integer = integer.ok_or_else(|| SqliteError::Custom("datatype mismatch".into()))?;
+        self.registers[*register] = Value::Integer(integer);
+      }
+      Op::SetOpen { cursor, order } => {
+        let set = KeySet::new(order.clone(), self.runtime.sort_memory_budget());
+        self.cursors[*cursor] = Some(Cursor::Set(set));
+      }
+      Op::SetInsert {
+        cursor,
+        key,
+        count,
+        target,
+      } => {
+        let key = self.registers[*key..*key + *count].to_vec();
+        let Cursor::Set(set) = self.cursor(*cursor)? else {
+          return Err(not_a_set(*cursor));
+        };
+        let is_new = set.insert(key)?;
+        self.jump_unless(is_new, *target);
+      }
+      Op::AggReset {
+        slot,
+        function,
+        distinct,
+        order,
+      } => {
+        let aggregate = Aggregate::new(
+          function,
+          distinct.clone(),
+          order.clone(),
+          self.runtime.sort_memory_budget(),
+        )?;
+        if self.aggregates.len() <= *slot {
+          self.aggregates.resize_with(*slot + 1, || None);
+        }
+        self.aggregates[*slot] = Some(aggregate);
+      }
+      Op::AggStep {
+        slot,
+        args,
+        count,
+        replaced,
+      } => {
+        let Some(Some(aggregate)) = self.aggregates.get_mut(*slot) else {
+          return Err(no_aggregate(*slot));
+        };
+        let keys = *args + *count;
+        let is_replaced = aggregate.step(
+          self.registers[*args..keys].to_vec(),
+          self.registers[keys..keys + aggregate.key_count()].to_vec(),
+        )?;
+        if let Some(replaced) = replaced {
+          self.registers[*replaced] = Value::Integer(is_replaced.into());
+        }
+      }
+      Op::AggFinal { slot, dest } => {
+        let aggregate = self
+          .aggregates
+          .get_mut(*slot)
+          .and_then(Option::take)
+          .ok_or_else(|| no_aggregate(*slot))?;
+        self.registers[*dest] = aggregate.finish()?;
+      }
+      Op::CompareKeys {
+        left,
+        right,
+        count,
+        order,
+        target,
+      } => {
+        let is_equal = compare_keys(
+          &self.registers[*left..*left + *count],
+          &self.registers[*right..*right + *count],
+          order,
+        )
+        .is_eq();
+        self.jump_if(is_equal, *target);
+      }
+      Op::Gosub { register, target } => {
+        self.registers[*register] = Value::Integer(self.pc as i64);
+        self.pc = *target;
+      }
+      Op::Return { register } => self.pc = self.address(*register)?,
+      Op::Once { target } => {
+        let has_run = core::mem::replace(&mut self.has_run[self.pc - 1], true);
+        self.jump_if(has_run, *target);
+      }
+      Op::Noop => {}
+      Op::InitCoroutine { register, target } => {
+        self.registers[*register] = Value::Integer(self.pc as i64);
+        self.pc = *target;
+      }
+      Op::Yield { register } => {
+        let resume = self.address(*register)?;
+        self.registers[*register] = Value::Integer(self.pc as i64);
+        self.pc = resume;
+      }
+      Op::QueueOpen {
+        cursor,
+        order,
+        distinct,
+      } => {
+        let queue = Queue::new(
+          order.clone(),
+          distinct.clone(),
+          self.runtime.sort_memory_budget(),
+        );
+        self.cursors[*cursor] = Some(Cursor::Queue(queue));
+      }
+      Op::QueuePush {
+        cursor,
+        key,
+        values,
+        count,
+      } => {
+        let row = self.registers[*values..*values + *count].to_vec();
+        let registers = &self.registers;
+        let Some(Cursor::Queue(queue)) = self.cursors[*cursor].as_mut() else {
+          return Err(not_a_queue(*cursor));
+        };
+        let key = registers[*key..*key + queue.key_count()].to_vec();
+        queue.push(key, row)?;
+      }
+      Op::QueuePop {
+        cursor,
+        dest,
+        count,
+        target,
+      } => {
+        let Cursor::Queue(queue) = self.cursor(*cursor)? else {
+          return Err(not_a_queue(*cursor));
+        };
+        match queue.pop() {
+          Some(row) => {
+            for (register, value) in self.registers[*dest..*dest + *count].iter_mut().zip(row) {
+              *register = value;
+            }
+          }
+          None => self.pc = *target,
+        }
+      }
+      Op::Window { cursor, specs } => {
+        let ephemeral = self.ephemeral(*cursor)?;
+        let rows = core::mem::take(&mut *ephemeral.rows.borrow_mut());
+        *ephemeral.rows.borrow_mut() = compute_windows(rows, specs)?;
+      }
+      Op::FunctionRows {
+        cursor,
+        function,
+        args,
+        count,
+      } => {
+        let args = &self.registers[*args..*args + *count];
+        let mut rows = (function.implementation)(self.runtime, args)?;
+        // Rows without the hidden columns get the values of the arguments.
+        let visible = function.columns.len() - function.arguments;
+        for row in &mut rows {
+          if row.len() == visible {
+            row.extend(
+              (0..function.arguments).map(|index| args.get(index).cloned().unwrap_or_default()),
+            );
+          }
+        }
+        let rows = Rc::new(RefCell::new(rows));
+        self.cursors[*cursor] = Some(Cursor::Ephemeral(Ephemeral::new(rows)));
+      }
+      Op::SetCookie { offset, value } => {
+        self.runtime.write_header_field(*offset, *value as u32)?;
+      }
+      Op::ToJson { register, check } => {
+        let is_json = match check {
+          JsonCheck::Always => true,
+          JsonCheck::Container => matches!(
+            &self.registers[*register],
+            Value::Text(text) if text.starts_with(['[', '{'])
+          ),
+          JsonCheck::Column {
+            cursor,
+            offset,
+            column,
+            is_json,
+          } => {
+            let ephemeral = self.ephemeral(*cursor)?;
+            let rows = ephemeral.rows.borrow();
+            !ephemeral.is_null_row
+              && rows
+                .get(ephemeral.position)
+                .and_then(|row| row.get(*offset..))
+                .is_some_and(|row| is_json(*column, row))
+          }
+          JsonCheck::Registers {
+            start,
+            column,
+            is_json,
+          } => self
+            .registers
+            .get(*start..)
+            .is_some_and(|row| is_json(*column, row)),
+        };
+        if is_json {
+          let value = core::mem::take(&mut self.registers[*register]);
+          self.registers[*register] = to_jsonb(value);
+        }
+      }
+    }
+    Ok(None)
+  }
+
+  /// The address a coroutine or subroutine keeps in a register.
+  fn address(&self, register: usize) -> SqliteResult<usize> {
+    match self.registers[register] {
+      Value::Integer(address) => Ok(address as usize),
+      _ => Err(SqliteError::Custom(format!(
+        "register {register} holds no address"
+      ))),
+    }
+  }
+
+  fn ephemeral(&mut self, cursor: usize) -> SqliteResult<&mut Ephemeral> {
+    match self.cursor(cursor)? {
+      Cursor::Ephemeral(ephemeral) => Ok(ephemeral),
+      _ => Err(SqliteError::Custom(format!(
+        "cursor {cursor} is not an ephemeral table"
+      ))),
+    }
+  }
+
+  fn env(&self) -> Env<'_> {
+    Env {
+      runtime: self.runtime,
+      statement_time: self.statement_time,
+      bindings: self.bindings.as_deref(),
+    }
+  }
+
+  fn jump_if(&mut self, condition: bool, target: usize) {
+    if condition {
+      self.pc = target;
+    }
+  }
+
+  fn jump_unless(&mut self, condition: bool, target: usize) {
+    self.jump_if(!condition, target);
+  }
+
+  fn cursor(&mut self, cursor: usize) -> SqliteResult<&mut Cursor<'a>> {
+    self
+      .cursors
+      .get_mut(cursor)
+      .and_then(Option::as_mut)
+      .ok_or_else(|| SqliteError::Custom(format!("cursor {cursor} is not open")))
+  }
+
+  fn table_cursor(&mut self, cursor: usize) -> SqliteResult<(&mut TableCursor<'a>, &mut Current)> {
+    match self.cursor(cursor)? {
+      Cursor::Table(cursor, current) => Ok((cursor, current)),
+      _ => Err(SqliteError::Custom(format!(
+        "cursor {cursor} is not a table cursor"
+      ))),
+    }
+  }
+}
+
+/// A value of the current row of a b-tree cursor, reading the row first if
+/// needed. A row of NULLs reads as NULL.
+fn read(
+  current: &mut Current,
+  column: &usize,
+  row: impl FnOnce() -> SqliteResult<Vec<Value>>,
+) -> SqliteResult<Value> {
+  if current.is_null_row {
+    return Ok(Value::Null);
+  }
+  let values = match &mut current.values {
+    Some(values) => values,
+    values => values.insert(row()?),
+  };
+  Ok(values.get(*column).cloned().unwrap_or_default())
+}
+
+fn not_a_btree(cursor: usize) -> SqliteError {
+  SqliteError::Custom(format!("cursor {cursor} is not a b-tree cursor"))
+}
+
+fn not_a_sorter(cursor: usize) -> SqliteError {
+  SqliteError::Custom(format!("cursor {cursor} is not a sorter"))
+}
+
+fn not_a_set(cursor: usize) -> SqliteError {
+  SqliteError::Custom(format!("cursor {cursor} is not a set"))
+}
+
+fn not_a_queue(cursor: usize) -> SqliteError {
+  SqliteError::Custom(format!("cursor {cursor} is not a queue"))
+}
+
+fn no_aggregate(slot: usize) -> SqliteError {
+  SqliteError::Custom(format!("aggregate {slot} is not started"))
+}
@@ -0,0 +1,1046 @@
+//! # Instructions
+//!
+//!  Each instruction of a program is an opcode with its operands. `EXPLAIN`
+//! lists them in SQLite's layout: three integers P1 to P3, a P4 of any kind
+//! and a P5 of flags, followed by a comment. Registers are numbered from 1
+//! and cursors from 0, and jumps go to the address of an instruction.
+//!
+//! *Reference:* https://www.sqlite.org/opcode.html
+
+use crate::{
+  runtime::{
+    collation::Collation,
+    functions::{FunctionDef, TableFunctionDef},
+    query::WindowSpec,
+    schema::{IndexSchema, SortOrder, TableSchema},
+    sorter::KeyOrder,
+  },
+  sql::ast::{BinaryOperator, CompoundOperator, LikeOperator},
+  value::{Affinity, Value},
+};
+
+/// The b-tree a cursor reads.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Tree<'a> {
+  Table(&'a TableSchema),
+  Index(&'a IndexSchema),
+}
+
+/// How `ToJson` tells that a text holds JSON.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum JsonCheck {
+  /// The text comes from a function that returns JSON.
+  Always,
+  /// The text holds JSON when it is an array or an object, as the result of
+  /// `json_extract()`.
+  Container,
+  /// The text is a column of the rows of a table-valued function, which
+  /// tells whether it holds JSON from its row, read by `cursor` from its
+  /// column `offset` on.
+  Column {
+    cursor: usize,
+    offset: usize,
+    column: usize,
+    is_json: fn(usize, &[Value]) -> bool,
+  },
+  /// Like `Column`, for a row held in the registers from `start`.
+  Registers {
+    start: usize,
+    column: usize,
+    is_json: fn(usize, &[Value]) -> bool,
+  },
+}
+
+/// An instruction of a program. Jumps go to `target`.
+#[derive(Debug, Clone)]
+pub(crate) enum Op<'a> {
+  /// Jumps to the instructions that set the program up, which then go to
+  /// address 1.
+  Init {
+    target: usize,
+  },
+  Goto {
+    target: usize,
+  },
+  /// Ends the program.
+  Halt,
+  Integer {
+    value: i64,
+    dest: usize,
+  },
+  Real {
+    value: f64,
+    dest: usize,
+  },
+  String8 {
+    value: String,
+    dest: usize,
+  },
+  Blob {
+    value: Vec<u8>,
+    dest: usize,
+  },
+  Null {
+    dest: usize,
+  },
+  /// Loads the value bound to a parameter.
+  Variable {
+    name: String,
+    dest: usize,
+  },
+  Copy {
+    source: usize,
+    dest: usize,
+  },
+  /// Opens a cursor over a table or an index.
+  OpenRead {
+    cursor: usize,
+    tree: Tree<'a>,
+  },
+  /// Moves a cursor to its first row, or jumps when there is none.
+  Rewind {
+    cursor: usize,
+    target: usize,
+  },
+  /// Moves a cursor to its next row, and jumps back to the loop unless it
+  /// was the last one. A cursor on a row of NULLs has no next row.
+  Next {
+    cursor: usize,
+    target: usize,
+  },
+  /// Moves a cursor on a table or an index to its last row, or jumps when
+  /// there is none.
+  Last {
+    cursor: usize,
+    target: usize,
+  },
+  /// Like `Next`, for the previous row.
+  Prev {
+    cursor: usize,
+    target: usize,
+  },
+  /// Loads a column of the current row of a table, or a value of the
+  /// current entry of an index.
+  Column {
+    cursor: usize,
+    column: usize,
+    dest: usize,
+  },
+  Rowid {
+    cursor: usize,
+    dest: usize,
+  },
+  /// Loads the rowid the current entry of an index points to.
+  IdxRowid {
+    cursor: usize,
+    dest: usize,
+  },
+  /// Moves a table cursor to the row whose rowid is in `key`, or jumps when
+  /// there is none or the key is not an integer.
+  SeekRowid {
+    cursor: usize,
+    key: usize,
+    target: usize,
+  },
+  /// Moves a cursor to the first row whose key is at least the `count`
+  /// values from `key`, or jumps when there is none. A rowid table is
+  /// sought by rowid, which is never a text or a blob.
+  SeekGE {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Like `SeekGE`, for the first rowid greater than `key`.
+  SeekGT {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Moves a cursor to the last row whose key is at most the `count` values
+  /// from `key`, or jumps when there is none.
+  SeekLE {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Like `SeekLE`, for the last rowid less than `key`.
+  SeekLT {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Jumps when the leading values of the key of the current row are
+  /// greater than the `count` values from `key`, in key order.
+  IdxGT {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Like `IdxGT`, when they are less than the values from `key`.
+  IdxLT {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Moves the cursor of a WITHOUT ROWID table to the row whose PRIMARY KEY
+  /// is the `count` values from `key`, or jumps when there is none.
+  NotFound {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Moves a cursor to a row of NULLs, for a LEFT join without a match.
+  NullRow {
+    cursor: usize,
+  },
+  /// Applies an affinity to each register from `start`.
+  Affinity {
+    start: usize,
+    affinities: Vec<Affinity>,
+  },
+  /// Makes an integer read from a REAL column a real.
+  RealAffinity {
+    register: usize,
+  },
+  Cast {
+    register: usize,
+    affinity: Affinity,
+  },
+  IsNull {
+    register: usize,
+    target: usize,
+  },
+  NotNull {
+    register: usize,
+    target: usize,
+  },
+  /// Jumps when a register is true, or NULL with `if_null`.
+  If {
+    register: usize,
+    target: usize,
+    if_null: bool,
+  },
+  /// Jumps when a register is false, or NULL with `if_null`.
+  IfNot {
+    register: usize,
+    target: usize,
+    if_null: bool,
+  },
+  /// Jumps when a register is positive, after subtracting `decrement`.
+  IfPos {
+    register: usize,
+    target: usize,
+    decrement: i64,
+  },
+  /// Decrements a register, and jumps when it reaches zero.
+  DecrJumpZero {
+    register: usize,
+    target: usize,
+  },
+  /// Stores whether a comparison holds between two registers, compared
+  /// with their affinities and a collation.
+  Compare {
+    operator: BinaryOperator,
+    left: usize,
+    right: usize,
+    dest: usize,
+    affinities: [Affinity; 2],
+    collation: Collation,
+  },
+  /// Stores the result of an arithmetic, bitwise or `||` operator.
+  Arithmetic {
+    operator: BinaryOperator,
+    left: usize,
+    right: usize,
+    dest: usize,
+  },
+  And {
+    left: usize,
+    right: usize,
+    dest: usize,
+  },
+  Or {
+    left: usize,
+    right: usize,
+    dest: usize,
+  },
+  Not {
+    source: usize,
+    dest: usize,
+  },
+  BitNot {
+    source: usize,
+    dest: usize,
+  },
+  Negate {
+    source: usize,
+    dest: usize,
+  },
+  /// Calls a scalar function with the `count` arguments from `args`.
+  Function {
+    function: &'static FunctionDef,
+    args: usize,
+    count: usize,
+    dest: usize,
+  },
+  /// Matches the value in `args` against the pattern after it, and the
+  /// escape character after that when `count` is 3.
+  Like {
+    operator: LikeOperator,
+    args: usize,
+    count: usize,
+    dest: usize,
+  },
+  /// Returns the `count` registers from `start` as a row.
+  ResultRow {
+    start: usize,
+    count: usize,
+  },
+  /// Opens a sorter, which keeps the first `limit` rows when given. With
+  /// `combine`, its key is a whole row and its only value the side of a
+  /// compound operator the row comes from, 0 for the left and 1 for the
+  /// right, and it returns the rows the operator keeps.
+  SorterOpen {
+    cursor: usize,
+    order: Vec<KeyOrder>,
+    limit: Option<usize>,
+    combine: Option<CompoundOperator>,
+  },
+  /// Adds a row to a sorter: its sort key from `key`, and the `count`
+  /// values from `values`.
+  SorterInsert {
+    cursor: usize,
+    key: usize,
+    values: usize,
+    count: usize,
+  },
+  /// Sorts the rows of a sorter and moves to the first, or jumps when there
+  /// is none.
+  SorterSort {
+    cursor: usize,
+    target: usize,
+  },
+  /// Loads the `count` values of the current row of a sorter.
+  SorterData {
+    cursor: usize,
+    dest: usize,
+    count: usize,
+  },
+  SorterNext {
+    cursor: usize,
+    target: usize,
+  },
+  /// Opens an empty ephemeral table, which holds rows in memory.
+  OpenEphemeral {
+    cursor: usize,
+    columns: usize,
+  },
+  /// Opens a cursor on the rows of an ephemeral table, with a position of
+  /// its own.
+  OpenDup {
+    cursor: usize,
+    source: usize,
+  },
+  /// Appends the `count` registers from `start` as a row of an ephemeral
+  /// table.
+  Insert {
+    cursor: usize,
+    start: usize,
+    count: usize,
+  },
+  /// Moves an ephemeral cursor to the row whose index is in `register`, or
+  /// jumps when there are not that many rows.
+  SeekPosition {
+    cursor: usize,
+    register: usize,
+    target: usize,
+  },
+  /// Adds a constant to an integer register.
+  AddImm {
+    register: usize,
+    value: i64,
+  },
+  /// Makes a register an integer, from an integer or a text that reads as
+  /// one, and fails otherwise.
+  MustBeInt {
+    register: usize,
+  },
+  /// Opens an empty set of keys, compared in the given order.
+  SetOpen {
+    cursor: usize,
+    order: Vec<KeyOrder>,
+  },
+  /// Adds the key of the `count` registers from `key` to a set, or jumps
+  /// when the set has it already.
+  SetInsert {
+    cursor: usize,
+    key: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Starts an aggregate with no rows. `distinct` compares the arguments
+  /// of a DISTINCT call, and `order` sorts the rows of a call with an ORDER
+  /// BY or DISTINCT.
+  AggReset {
+    slot: usize,
+    function: &'static FunctionDef,
+    distinct: Option<Vec<KeyOrder>>,
+    order: Option<Vec<KeyOrder>>,
+  },
+  /// Adds the `count` arguments from `args` to an aggregate, followed by
+  /// the values of its ORDER BY terms. Stores in `replaced` whether the row
+  /// is now that of a `min()` or `max()`.
+  AggStep {
+    slot: usize,
+    args: usize,
+    count: usize,
+    replaced: Option<usize>,
+  },
+  /// Stores the result of an aggregate over its rows.
+  AggFinal {
+    slot: usize,
+    dest: usize,
+  },
+  /// Jumps when the `count` registers from `left` equal those from
+  /// `right`, in the given order.
+  CompareKeys {
+    left: usize,
+    right: usize,
+    count: usize,
+    order: Vec<KeyOrder>,
+    target: usize,
+  },
+  /// Stores the address of the next instruction in `register` and jumps
+  /// to a subroutine.
+  Gosub {
+    register: usize,
+    target: usize,
+  },
+  /// Returns from a subroutine to the address in `register`.
+  Return {
+    register: usize,
+  },
+  /// Falls through the first time it runs, and jumps every time after.
+  Once {
+    target: usize,
+  },
+  Noop,
+  /// Stores in `register` the address of a coroutine that starts at the
+  /// next instruction, and jumps past it.
+  InitCoroutine {
+    register: usize,
+    target: usize,
+  },
+  /// Swaps the address of the next instruction with the one in `register`,
+  /// going back and forth between a coroutine and its caller.
+  Yield {
+    register: usize,
+  },
+  /// Opens an empty queue of rows, taken out lowest key first and in the
+  /// order they came in for equal keys. With `distinct`, a row already
+  /// queued once is left out.
+  QueueOpen {
+    cursor: usize,
+    order: Vec<KeyOrder>,
+    distinct: Option<Vec<KeyOrder>>,
+  },
+  /// Adds a row to a queue: its key from `key`, and the `count` values
+  /// from `values`.
+  QueuePush {
+    cursor: usize,
+    key: usize,
+    values: usize,
+    count: usize,
+  },
+  /// Takes the first row out of a queue into the `count` registers from
+  /// `dest`, or jumps when it is empty.
+  QueuePop {
+    cursor: usize,
+    dest: usize,
+    count: usize,
+    target: usize,
+  },
+  /// Computes window calls over the rows of an ephemeral table, appending
+  /// their results to each row, and puts the rows in the order of the
+  /// window of the first call.
+  Window {
+    cursor: usize,
+    specs: Vec<WindowSpec>,
+  },
+  /// Fills an ephemeral table with the rows of a table-valued function,
+  /// called with the `count` arguments from `args`.
+  FunctionRows {
+    cursor: usize,
+    function: &'static TableFunctionDef,
+    args: usize,
+    count: usize,
+  },
+  /// Makes a text that holds JSON a JSONB blob, which the JSON functions
+  /// read as JSON rather than as a string.
+  ToJson {
+    register: usize,
+    check: JsonCheck,
+  },
+  /// Writes `value` to the 4-byte field of the database header at
+  /// `offset`, for a pragma that sets one.
+  SetCookie {
+    offset: usize,
+    value: i32,
+  },
+}
+
+/// An instruction as `EXPLAIN` lists it.
+struct Listing {
+  opcode: &'static str,
+  p1: i64,
+  p2: i64,
+  p3: i64,
+  p4: Option<String>,
+  p5: i64,
+  comment: Option<String>,
+}
+
+impl Listing {
+  fn new(opcode: &'static str, p1: usize, p2: usize, p3: usize) -> Self {
+    Self {
+      opcode,
+      p1: p1 as i64,
+      p2: p2 as i64,
+      p3: p3 as i64,
+      p4: None,
+      p5: 0,
+      comment: None,
+    }
+  }
+
+  fn p4(self, p4: impl Into<String>) -> Self {
+    Self {
+      p4: Some(p4.into()),
+      ..self
+    }
+  }
+
+  fn p5(self, p5: i64) -> Self {
+    Self { p5, ..self }
+  }
+
+  fn comment(self, comment: impl Into<String>) -> Self {
+    Self {
+      comment: Some(comment.into()),
+      ..self
+    }
+  }
+}
+
+/// SQLite's flag for comparisons where NULL equals NULL, as `IS` does.
+const NULL_EQUAL: i64 = 0x80;
+
+impl Op<'_> {
+  /// The target of a jump, which holds a label until the program is built.
+  pub(crate) fn target_mut(&mut self) -> Option<&mut usize> {
+    match self {
+      Self::Init { target }
+      | Self::Goto { target }
+      | Self::Rewind { target, .. }
+      | Self::Next { target, .. }
+      | Self::Last { target, .. }
+      | Self::Prev { target, .. }
+      | Self::SeekRowid { target, .. }
+      | Self::SeekGE { target, .. }
+      | Self::SeekGT { target, .. }
+      | Self::SeekLE { target, .. }
+      | Self::SeekLT { target, .. }
+      | Self::IdxGT { target, .. }
+      | Self::IdxLT { target, .. }
+      | Self::NotFound { target, .. }
+      | Self::IsNull { target, .. }
+      | Self::NotNull { target, .. }
+      | Self::If { target, .. }
+      | Self::IfNot { target, .. }
+      | Self::IfPos { target, .. }
+      | Self::DecrJumpZero { target, .. }
+      | Self::SorterSort { target, .. }
+      | Self::SorterNext { target, .. }
+      | Self::SeekPosition { target, .. }
+      | Self::SetInsert { target, .. }
+      | Self::CompareKeys { target, .. }
+      | Self::Gosub { target, .. }
+      | Self::Once { target }
+      | Self::InitCoroutine { target, .. }
+      | Self::QueuePop { target, .. } => Some(target),
+      _ => None,
+    }
+  }
+
+  /// The row of `EXPLAIN` for the instruction at `address`: the address,
+  /// the opcode, P1 to P5 and a comment.
+  pub(crate) fn explain(&self, address: usize) -> Vec<Value> {
+    let listing = self.listing();
+    vec![
+      Value::Integer(address as i64),
+      Value::Text(listing.opcode.into()),
+      Value::Integer(listing.p1),
+      Value::Integer(listing.p2),
+      Value::Integer(listing.p3),
+      listing.p4.into(),
+      Value::Integer(listing.p5),
+      listing.comment.into(),
+    ]
+  }
+
+  fn listing(&self) -> Listing {
+    match self {
+      Self::Init { target } => {
+        Listing::new("Init", 0, *target, 0).comment(format!("Start at {target}"))
+      }
+      Self::Goto { target } => Listing::new("Goto", 0, *target, 0),
+      Self::Halt => Listing::new("Halt", 0, 0, 0),
+      Self::Integer { value, dest } => Listing {
+        p1: *value,
+        ..Listing::new("Integer", 0, *dest, 0).comment(format!("r[{dest}]={value}"))
+      },
+      Self::Real { value, dest } => Listing::new("Real", 0, *dest, 0)
+        .p4(Value::Real(*value).to_string())
+        .comment(format!("r[{dest}]={}", Value::Real(*value))),
+      Self::String8 { value, dest } => Listing::new("String8", 0, *dest, 0)
+        .p4(value.clone())
+        .comment(format!("r[{dest}]='{value}'")),
+      Self::Blob { value, dest } => {
+        let hex: String = value.iter().map(|byte| format!("{byte:02X}")).collect();
+        Listing::new("Blob", value.len(), *dest, 0)
+          .p4(format!("x'{hex}'"))
+          .comment(format!("r[{dest}]=x'{hex}'"))
+      }
+      Self::Null { dest } => Listing::new("Null", 0, *dest, 0).comment(format!("r[{dest}]=NULL")),
+      Self::Variable { name, dest } => Listing::new("Variable", 0, *dest, 0)
+        .p4(name.clone())
+        .comment(format!("r[{dest}]=parameter({name})")),
+      Self::Copy { source, dest } => {
+        Listing::new("Copy", *source, *dest, 0).comment(format!("r[{dest}]=r[{source}]"))
+      }
+      Self::OpenRead { cursor, tree } => match tree {
+        Tree::Table(table) => Listing::new("OpenRead", *cursor, table.root_page() as usize, 0)
+          .p4(table.columns().len().to_string())
+          .comment(format!(
+            "root={} iDb=0; {}",
+            table.root_page(),
+            table.name()
+          )),
+        Tree::Index(index) => {
+          let keys = index.columns().iter().map(|key| {
+            let order = match key.order() {
+              Some(SortOrder::Desc) => "-",
+              _ => "",
+            };
+            let collation = key
+              .collation()
+              .filter(|collation| !collation.eq_ignore_ascii_case("BINARY"))
+              .unwrap_or_default();
+            format!("{order}{collation}")
+          });
+          Listing::new("OpenRead", *cursor, index.root_page() as usize, 0)
+            .p4(key_info(
+              index.columns().len() + 1,
+              keys.chain([String::new()]),
+            ))
+            .comment(format!(
+              "root={} iDb=0; {}",
+              index.root_page(),
+              index.name()
+            ))
+        }
+      },
+      Self::Rewind { cursor, target } => Listing::new("Rewind", *cursor, *target, 0),
+      Self::Next { cursor, target } => Listing::new("Next", *cursor, *target, 0),
+      Self::Last { cursor, target } => Listing::new("Last", *cursor, *target, 0),
+      Self::Prev { cursor, target } => Listing::new("Prev", *cursor, *target, 0),
+      Self::Column {
+        cursor,
+        column,
+        dest,
+      } => Listing::new("Column", *cursor, *column, *dest)
+        .comment(format!("r[{dest}]=cursor {cursor} column {column}")),
+      Self::Rowid { cursor, dest } => {
+        Listing::new("Rowid", *cursor, *dest, 0).comment(format!("r[{dest}]=rowid"))
+      }
+      Self::IdxRowid { cursor, dest } => {
+        Listing::new("IdxRowid", *cursor, *dest, 0).comment(format!("r[{dest}]=rowid"))
+      }
+      Self::SeekRowid {
+        cursor,
+        key,
+        target,
+      } => Listing::new("SeekRowid", *cursor, *target, *key).comment(format!("intkey=r[{key}]")),
+      Self::SeekGE {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("SeekGE", *cursor, *target, *key, *count),
+      Self::SeekGT {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("SeekGT", *cursor, *target, *key, *count),
+      Self::SeekLE {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("SeekLE", *cursor, *target, *key, *count),
+      Self::SeekLT {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("SeekLT", *cursor, *target, *key, *count),
+      Self::IdxGT {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("IdxGT", *cursor, *target, *key, *count),
+      Self::IdxLT {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("IdxLT", *cursor, *target, *key, *count),
+      Self::NotFound {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("NotFound", *cursor, *target, *key, *count),
+      Self::NullRow { cursor } => Listing::new("NullRow", *cursor, 0, 0),
+      Self::Affinity { start, affinities } => {
+        let codes: String = affinities
+          .iter()
+          .map(|affinity| affinity_code(*affinity))
+          .collect();
+        Listing::new("Affinity", *start, affinities.len(), 0)
+          .p4(codes)
+          .comment(format!(
+            "affinity(r[{}])",
+            registers(*start, affinities.len())
+          ))
+      }
+      Self::RealAffinity { register } => Listing::new("RealAffinity", *register, 0, 0),
+      Self::Cast { register, affinity } => Listing::new(
+        "Cast",
+        *register,
+        u32::from(affinity_code(*affinity)) as usize,
+        0,
+      )
+      .comment(format!("affinity(r[{register}])")),
+      Self::IsNull { register, target } => Listing::new("IsNull", *register, *target, 0)
+        .comment(format!("if r[{register}]==NULL goto {target}")),
+      Self::NotNull { register, target } => Listing::new("NotNull", *register, *target, 0)
+        .comment(format!("if r[{register}]!=NULL goto {target}")),
+      Self::If {
+        register,
+        target,
+        if_null,
+      } => Listing::new("If", *register, *target, usize::from(*if_null)),
+      Self::IfNot {
+        register,
+        target,
+        if_null,
+      } => Listing::new("IfNot", *register, *target, usize::from(*if_null)),
+      Self::IfPos {
+        register,
+        target,
+        decrement,
+      } => Listing {
+        p3: *decrement,
+        ..Listing::new("IfPos", *register, *target, 0).comment(format!(
+          "if r[{register}]>0 then r[{register}]-={decrement}, goto {target}"
+        ))
+      },
+      Self::DecrJumpZero { register, target } => {
+        Listing::new("DecrJumpZero", *register, *target, 0)
+          .comment(format!("if (--r[{register}])==0 goto {target}"))
+      }
+      Self::Compare {
+        operator,
+        left,
+        right,
+        dest,
+        collation,
+        ..
+      } => {
+        let (opcode, symbol) = match operator {
+          BinaryOperator::Eq | BinaryOperator::Is => ("Eq", "=="),
+          BinaryOperator::Ne | BinaryOperator::IsNot => ("Ne", "!="),
+          BinaryOperator::Lt => ("Lt", "<"),
+          BinaryOperator::Le => ("Le", "<="),
+          BinaryOperator::Gt => ("Gt", ">"),
+          _ => ("Ge", ">="),
+        };
+        let is_null_equal = matches!(operator, BinaryOperator::Is | BinaryOperator::IsNot);
+        Listing::new(opcode, *left, *dest, *right)
+          .p4(format!("({})", collation.name()))
+          .p5(if is_null_equal { NULL_EQUAL } else { 0 })
+          .comment(format!("r[{dest}]=(r[{left}]{symbol}r[{right}])"))
+      }
+      Self::Arithmetic {
+        operator,
+        left,
+        right,
+        dest,
+      } => {
+        let (opcode, symbol) = match operator {
+          BinaryOperator::Add => ("Add", "+"),
+          BinaryOperator::Subtract => ("Subtract", "-"),
+          BinaryOperator::Multiply => ("Multiply", "*"),
+          BinaryOperator::Divide => ("Divide", "/"),
+          BinaryOperator::Remainder => ("Remainder", "%"),
+          BinaryOperator::Concat => ("Concat", "||"),
+          BinaryOperator::BitAnd => ("BitAnd", "&"),
+          BinaryOperator::BitOr => ("BitOr", "|"),
+          BinaryOperator::ShiftLeft => ("ShiftLeft", "<<"),
+          BinaryOperator::Extract => ("Extract", "->"),
+          BinaryOperator::ExtractText => ("Extract", "->>"),
+          _ => ("ShiftRight", ">>"),
+        };
+        Listing::new(opcode, *right, *left, *dest)
+          .comment(format!("r[{dest}]=r[{left}]{symbol}r[{right}]"))
+      }
+      Self::And { left, right, dest } => Listing::new("And", *left, *right, *dest)
+        .comment(format!("r[{dest}]=(r[{left}] && r[{right}])")),
+      Self::Or { left, right, dest } => Listing::new("Or", *left, *right, *dest)
+        .comment(format!("r[{dest}]=(r[{left}] || r[{right}])")),
+      Self::Not { source, dest } => {
+        Listing::new("Not", *source, *dest, 0).comment(format!("r[{dest}]=!r[{source}]"))
+      }
+      Self::BitNot { source, dest } => {
+        Listing::new("BitNot", *source, *dest, 0).comment(format!("r[{dest}]=~r[{source}]"))
+      }
+      Self::Negate { source, dest } => {
+        Listing::new("Negate", *source, *dest, 0).comment(format!("r[{dest}]=-r[{source}]"))
+      }
+      Self::Function {
+        function,
+        args,
+        count,
+        dest,
+      } => Listing::new("Function", 0, *args, *dest)
+        .p4(format!("{}({count})", function.name()))
+        .p5(*count as i64)
+        .comment(format!("r[{dest}]=func(r[{}])", registers(*args, *count))),
+      Self::Like {
+        operator,
+        args,
+        count,
+        dest,
+      } => {
+        let name = match operator {
+          LikeOperator::Glob => "glob",
+          _ => "like",
+        };
+        Listing::new("Function", 0, *args, *dest)
+          .p4(format!("{name}({count})"))
+          .p5(*count as i64)
+          .comment(format!("r[{dest}]=func(r[{}])", registers(*args, *count)))
+      }
+      Self::ResultRow { start, count } => Listing::new("ResultRow", *start, *count, 0)
+        .comment(format!("output=r[{}]", registers(*start, *count))),
+      Self::SorterOpen {
+        cursor,
+        order,
+        limit,
+        combine,
+      } => {
+        let listing = Listing::new("SorterOpen", *cursor, order.len(), 0).p4(key_orders(order));
+        match (limit, combine) {
+          (Some(limit), _) => listing.comment(format!("keep {limit} rows")),
+          (None, Some(operator)) => listing.comment(format!("{operator:?}")),
+          (None, None) => listing,
+        }
+      }
+      Self::SorterInsert {
+        cursor,
+        key,
+        values,
+        count,
+      } => Listing::new("SorterInsert", *cursor, *key, *values)
+        .p5(*count as i64)
+        .comment(format!(
+          "key=r[{key}..]; values=r[{}]",
+          registers(*values, *count)
+        )),
+      Self::SorterSort { cursor, target } => Listing::new("SorterSort", *cursor, *target, 0),
+      Self::SorterData {
+        cursor,
+        dest,
+        count,
+      } => Listing::new("SorterData", *cursor, *dest, *count)
+        .comment(format!("r[{}]=data", registers(*dest, *count))),
+      Self::SorterNext { cursor, target } => Listing::new("SorterNext", *cursor, *target, 0),
+      Self::OpenEphemeral { cursor, columns } => {
+        Listing::new("OpenEphemeral", *cursor, *columns, 0)
+      }
+      Self::OpenDup { cursor, source } => Listing::new("OpenDup", *cursor, *source, 0),
+      Self::Insert {
+        cursor,
+        start,
+        count,
+      } => Listing::new("Insert", *cursor, *start, *count)
+        .comment(format!("row=r[{}]", registers(*start, *count))),
+      Self::SeekPosition {
+        cursor,
+        register,
+        target,
+      } => Listing::new("SeekPosition", *cursor, *target, *register)
+        .comment(format!("position=r[{register}]")),
+      Self::AddImm { register, value } => Listing {
+        p2: *value,
+        ..Listing::new("AddImm", *register, 0, 0)
+          .comment(format!("r[{register}]=r[{register}]+{value}"))
+      },
+      Self::MustBeInt { register } => Listing::new("MustBeInt", *register, 0, 0),
+      Self::SetOpen { cursor, order } => {
+        Listing::new("OpenEphemeral", *cursor, order.len(), 0).p4(key_orders(order))
+      }
+      Self::SetInsert {
+        cursor,
+        key,
+        count,
+        target,
+      } => seek("Found", *cursor, *target, *key, *count).comment(format!(
+        "key=r[{}]; insert if not found",
+        registers(*key, *count)
+      )),
+      Self::AggReset { slot, function, .. } => {
+        Listing::new("AggReset", *slot, 0, 0).p4(format!("{}()", function.name()))
+      }
+      Self::AggStep {
+        slot,
+        args,
+        count,
+        replaced,
+      } => Listing::new("AggStep", *slot, *args, replaced.unwrap_or_default())
+        .p5(*count as i64)
+        .comment(format!(
+          "accum=agg[{slot}] step(r[{}])",
+          registers(*args, *count)
+        )),
+      Self::AggFinal { slot, dest } => {
+        Listing::new("AggFinal", *slot, *dest, 0).comment(format!("r[{dest}]=agg[{slot}]"))
+      }
+      Self::CompareKeys {
+        left,
+        right,
+        count,
+        order,
+        target,
+      } => Listing::new("Compare", *left, *right, *count)
+        .p4(key_orders(order))
+        .comment(format!(
+          "r[{}]==r[{}] goto {target}",
+          registers(*left, *count),
+          registers(*right, *count)
+        )),
+      Self::Gosub { register, target } => Listing::new("Gosub", *register, *target, 0),
+      Self::Return { register } => Listing::new("Return", *register, 0, 0),
+      Self::Once { target } => Listing::new("Once", 0, *target, 0),
+      Self::Noop => Listing::new("Noop", 0, 0, 0),
+      Self::InitCoroutine { register, target } => {
+        Listing::new("InitCoroutine", *register, *target, 0)
+      }
+      Self::Yield { register } => Listing::new("Yield", *register, 0, 0),
+      Self::QueueOpen { cursor, order, .. } => {
+        Listing::new("OpenEphemeral", *cursor, order.len(), 0)
+          .p4(key_orders(order))
+          .comment("queue")
+      }
+      Self::QueuePush {
+        cursor,
+        key,
+        values,
+        count,
+      } => Listing::new("IdxInsert", *cursor, *key, *values)
+        .p5(*count as i64)
+        .comment(format!(
+          "key=r[{key}..]; values=r[{}]",
+          registers(*values, *count)
+        )),
+      Self::QueuePop {
+        cursor,
+        dest,
+        count,
+        target,
+      } => Listing::new("QueuePop", *cursor, *target, *dest)
+        .p4(count.to_string())
+        .comment(format!("r[{}]=first row", registers(*dest, *count))),
+      Self::Window { cursor, specs } => Listing::new("Window", *cursor, specs.len(), 0),
+      Self::FunctionRows {
+        cursor,
+        function,
+        args,
+        count,
+      } => Listing::new("VFilter", *cursor, *args, *count)
+        .p4(function.name)
+        .comment(format!("args=r[{}]", registers(*args, *count))),
+      Self::ToJson { register, .. } => Listing::new("ToJson", *register, 0, 0),
+      Self::SetCookie { offset, value } => Listing {
+        p3: i64::from(*value),
+        ..Listing::new("SetCookie", 0, *offset, 0)
+      },
+    }
+  }
+}
+
+/// The key info of a list of key orders.
+fn key_orders(order: &[KeyOrder]) -> String {
+  let keys = order.iter().map(|key| {
+    let order = if key.descending { "-" } else { "" };
+    let collation = match key.collation.name() {
+      name if name.eq_ignore_ascii_case("BINARY") => "",
+      name => name,
+    };
+    format!("{order}{collation}")
+  });
+  key_info(order.len(), keys)
+}
+
+/// A seek by the `count` registers from `key`.
+fn seek(opcode: &'static str, cursor: usize, target: usize, key: usize, count: usize) -> Listing {
+  Listing::new(opcode, cursor, target, key)
+    .p4(count.to_string())
+    .comment(format!("key=r[{}]", registers(key, count)))
+}
+
+/// `k(n,...)`: the number of values of a key, and the order and collation
+/// of each, `-` for descending and nothing for BINARY.
+fn key_info(count: usize, keys: impl Iterator<Item = String>) -> String {
+  let keys: Vec<String> = keys.collect();
+  format!("k({count},{})", keys.join(","))
+}
+
+/// `r[n]`, or `r[n..m]` for several registers.
+fn registers(start: usize, count: usize) -> String {
+  match count {
+    0 | 1 => start.to_string(),
+    _ => format!("{start}..{}", start + count - 1),
+  }
+}
+
+/// The letter SQLite stands for an affinity with.
+fn affinity_code(affinity: Affinity) -> char {
+  match affinity {
+    Affinity::Blob => 'A',
+    Affinity::Text => 'B',
+    Affinity::Numeric => 'C',
+    Affinity::Integer => 'D',
+    Affinity::Real => 'E',
+  }
+}
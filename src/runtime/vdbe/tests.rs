@@ -0,0 +1,95 @@
+//! Tests for the virtual database engine
+//!
+//! To run: `cargo test runtime::vdbe`
+
+use std::rc::Rc;
+
+use super::{Op, Program, Vdbe};
+use crate::{sql::ast::BinaryOperator, value::Value, SqliteConnection, IN_MEMORY_URI};
+
+#[test]
+fn ok_on_stepped_rows() {
+  let conn = SqliteConnection::open(IN_MEMORY_URI).unwrap();
+  // Counts from 10 by 5, three times.
+  let program = Program::new(
+    vec![
+      Op::Init { target: 6 },
+      Op::ResultRow { start: 1, count: 1 },
+      Op::Arithmetic {
+        operator: BinaryOperator::Add,
+        left: 1,
+        right: 2,
+        dest: 1,
+      },
+      Op::DecrJumpZero {
+        register: 3,
+        target: 5,
+      },
+      Op::Goto { target: 1 },
+      Op::Halt,
+      Op::Integer { value: 10, dest: 1 },
+      Op::Integer { value: 5, dest: 2 },
+      Op::Integer { value: 3, dest: 3 },
+      Op::Goto { target: 1 },
+    ],
+    3,
+    0,
+  );
+  let program = Rc::new(program);
+  // Listed, each step returns an instruction.
+  let mut listing = Vdbe::new(&conn.runtime, Rc::clone(&program)).listing();
+  let rows: Vec<Vec<Value>> = core::iter::from_fn(|| listing.step().unwrap()).collect();
+  assert_eq!(rows.len(), 10);
+  assert_eq!(
+    rows[0],
+    [
+      0.into(),
+      "Init".into(),
+      0.into(),
+      6.into(),
+      0.into(),
+      Value::Null,
+      0.into(),
+      "Start at 6".into(),
+    ]
+  );
+  assert_eq!(rows[2][1], "Add".into());
+
+  let mut vdbe = Vdbe::new(&conn.runtime, program);
+  for expected in [10, 15, 20] {
+    assert_eq!(vdbe.step().unwrap(), Some(vec![Value::from(expected)]));
+  }
+  assert_eq!(vdbe.step().unwrap(), None);
+  assert_eq!(vdbe.step().unwrap(), None);
+}
+
+#[test]
+fn err_on_interrupt() {
+  let conn = SqliteConnection::open(IN_MEMORY_URI).unwrap();
+  let handle = conn.interrupt_handle();
+  // Returns the same row forever.
+  let program = Program::new(
+    vec![
+      Op::Init { target: 3 },
+      Op::ResultRow { start: 1, count: 1 },
+      Op::Goto { target: 1 },
+      Op::Integer { value: 1, dest: 1 },
+      Op::Goto { target: 1 },
+    ],
+    1,
+    0,
+  );
+  let program = Rc::new(program);
+
+  let mut vdbe = Vdbe::new(&conn.runtime, Rc::clone(&program));
+  assert_eq!(vdbe.step().unwrap(), Some(vec![Value::from(1)]));
+  std::thread::spawn(move || handle.interrupt())
+    .join()
+    .unwrap();
+  assert_eq!(vdbe.step().unwrap_err().to_string(), "interrupted");
+  assert_eq!(vdbe.step().unwrap(), None);
+
+  // The next program runs.
+  let mut vdbe = Vdbe::new(&conn.runtime, program);
+  assert_eq!(vdbe.step().unwrap(), Some(vec![Value::from(1)]));
+}
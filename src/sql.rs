@@ -0,0 +1,17 @@
+//! # SQL front end
+//!
+//!  The SQL text is broken up into tokens by the tokenizer, and the parser
+//! assembles those tokens into an abstract syntax tree.
+//!
+//! *Reference:* https://www.sqlite.org/arch.html
+
+pub mod ast;
+pub mod keyword;
+pub mod parser;
+pub mod tokenizer;
+
+pub use self::{
+  keyword::Keyword,
+  parser::{parse_expr, parse_select, parse_statement, Parser},
+  tokenizer::{tokenize, Operator, Span, Token, TokenKind, Tokenizer},
+};
@@ -0,0 +1,590 @@
+//! # Abstract syntax tree
+//!
+//!  The parsed form of SQL statements. Names are kept dequoted, and
+//! expressions keep the shape of the source text: nothing is resolved against
+//! the schema here.
+//!
+//! *Reference:* https://www.sqlite.org/lang_select.html
+
+pub use crate::runtime::schema::SortOrder;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+  Select(Box<Select>),
+  /// `EXPLAIN select`
+  Explain(Box<Select>),
+  /// `EXPLAIN QUERY PLAN select`
+  ExplainQueryPlan(Box<Select>),
+  Pragma(Pragma),
+}
+
+/// `PRAGMA [schema.]name [= value]`, or `PRAGMA [schema.]name(value)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pragma {
+  pub name: QualifiedName,
+  /// The value, as the text of a signed number, a name or a string.
+  pub value: Option<String>,
+}
+
+/// A `SELECT` statement. In a compound select, the `ORDER BY` and `LIMIT`
+/// of the first select apply to the whole compound, and the selects that
+/// follow have none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Select {
+  pub with: Option<With>,
+  pub distinct: bool,
+  pub columns: Vec<ResultColumn>,
+  pub from: Option<JoinClause>,
+  pub where_clause: Option<Expr>,
+  pub group_by: Vec<Expr>,
+  pub having: Option<Expr>,
+  pub order_by: Vec<OrderingTerm>,
+  pub limit: Option<Limit>,
+  /// The rows of a `VALUES` clause, which stands for the result columns and
+  /// every clause up to `ORDER BY`.
+  pub values: Vec<Vec<Expr>>,
+  /// The named windows of a `WINDOW` clause.
+  pub windows: Vec<(String, Window)>,
+  pub compound: Vec<(CompoundOperator, Select)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompoundOperator {
+  Union,
+  UnionAll,
+  Intersect,
+  Except,
+}
+
+/// `WITH [RECURSIVE] name AS (select), ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct With {
+  pub recursive: bool,
+  pub ctes: Vec<CommonTableExpr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonTableExpr {
+  pub name: String,
+  pub columns: Vec<String>,
+  /// `AS MATERIALIZED` or `AS NOT MATERIALIZED`.
+  pub materialized: Option<bool>,
+  pub select: Box<Select>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultColumn {
+  /// `*`
+  Star,
+  /// `table.*`
+  TableStar(String),
+  Expr {
+    expr: Expr,
+    alias: Option<String>,
+    /// The expression as written, which names the column when there is no
+    /// alias.
+    text: String,
+  },
+}
+
+/// A `FROM` clause: a table or subquery followed by any number of joins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinClause {
+  pub first: TableOrSubquery,
+  pub joins: Vec<Join>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+  pub operator: JoinOperator,
+  pub table: TableOrSubquery,
+  pub constraint: Option<JoinConstraint>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JoinOperator {
+  pub natural: bool,
+  pub kind: JoinKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinKind {
+  /// `,`
+  Comma,
+  /// `JOIN` or `INNER JOIN`
+  #[default]
+  Inner,
+  Cross,
+  /// `LEFT [OUTER] JOIN`
+  Left,
+  /// `RIGHT [OUTER] JOIN`
+  Right,
+  /// `FULL [OUTER] JOIN`
+  Full,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+  On(Expr),
+  Using(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableOrSubquery {
+  Table {
+    name: QualifiedName,
+    alias: Option<String>,
+    indexed: Option<IndexedBy>,
+  },
+  /// A table-valued function, like `json_each(...)`.
+  TableFunction {
+    name: QualifiedName,
+    args: Vec<Expr>,
+    alias: Option<String>,
+  },
+  Subquery {
+    select: Box<Select>,
+    alias: Option<String>,
+  },
+  /// A parenthesized join clause.
+  Join(Box<JoinClause>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexedBy {
+  /// `INDEXED BY name`
+  Index(String),
+  /// `NOT INDEXED`
+  NotIndexed,
+}
+
+/// `[schema.]name`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QualifiedName {
+  pub schema: Option<String>,
+  pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+  pub expr: Expr,
+  pub order: Option<SortOrder>,
+  pub nulls: Option<NullsOrder>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullsOrder {
+  First,
+  Last,
+}
+
+/// The window of a window function call: `OVER name` or `OVER (window)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Over {
+  Name(String),
+  Window(Box<Window>),
+}
+
+/// `[base] [PARTITION BY expr, ...] [ORDER BY term, ...] [frame]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Window {
+  /// The name of the window this one extends.
+  pub base: Option<String>,
+  pub partition_by: Vec<Expr>,
+  pub order_by: Vec<OrderingTerm>,
+  pub frame: Option<Frame>,
+}
+
+/// `{ROWS | RANGE | GROUPS} BETWEEN start AND end [EXCLUDE ...]`. A frame
+/// with a start only ends at the current row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+  pub units: FrameUnits,
+  pub start: FrameBound,
+  pub end: FrameBound,
+  pub exclude: FrameExclude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnits {
+  Rows,
+  Range,
+  Groups,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBound {
+  UnboundedPreceding,
+  Preceding(Expr),
+  CurrentRow,
+  Following(Expr),
+  UnboundedFollowing,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameExclude {
+  #[default]
+  NoOthers,
+  CurrentRow,
+  Group,
+  Ties,
+}
+
+/// `LIMIT limit [OFFSET offset]`, also written `LIMIT offset, limit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+  pub limit: Expr,
+  pub offset: Option<Expr>,
+}
+
+/// # SQL Language Expressions
+///
+/// *Reference:* https://www.sqlite.org/lang_expr.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+  Literal(Literal),
+  /// A parameter, with its prefix: `?`, `?1`, `:name`, `@name` or `$name`.
+  Variable(String),
+  /// `[[schema.]table.]column`
+  Column {
+    schema: Option<String>,
+    table: Option<String>,
+    name: String,
+  },
+  Unary {
+    operator: UnaryOperator,
+    expr: Box<Expr>,
+  },
+  Binary {
+    left: Box<Expr>,
+    operator: BinaryOperator,
+    right: Box<Expr>,
+  },
+  Collate {
+    expr: Box<Expr>,
+    collation: String,
+  },
+  Cast {
+    expr: Box<Expr>,
+    type_name: String,
+  },
+  Function {
+    name: String,
+    distinct: bool,
+    args: FunctionArgs,
+    /// `ORDER BY` inside the call of an aggregate.
+    order_by: Vec<OrderingTerm>,
+    filter: Option<Box<Expr>>,
+    /// The window of a window function call.
+    over: Option<Over>,
+  },
+  Case {
+    operand: Option<Box<Expr>>,
+    when_then: Vec<(Expr, Expr)>,
+    else_expr: Option<Box<Expr>>,
+  },
+  Between {
+    expr: Box<Expr>,
+    not: bool,
+    low: Box<Expr>,
+    high: Box<Expr>,
+  },
+  Like {
+    expr: Box<Expr>,
+    not: bool,
+    operator: LikeOperator,
+    pattern: Box<Expr>,
+    escape: Option<Box<Expr>>,
+  },
+  InList {
+    expr: Box<Expr>,
+    not: bool,
+    list: Vec<Expr>,
+  },
+  InSelect {
+    expr: Box<Expr>,
+    not: bool,
+    select: Box<Select>,
+  },
+  /// `expr IN [schema.]table`, or a table-valued function call.
+  InTable {
+    expr: Box<Expr>,
+    not: bool,
+    table: QualifiedName,
+    args: Vec<Expr>,
+  },
+  /// `ISNULL`, `NOTNULL` and `NOT NULL`.
+  IsNull {
+    expr: Box<Expr>,
+    not: bool,
+  },
+  Exists {
+    not: bool,
+    select: Box<Select>,
+  },
+  /// A scalar subquery.
+  Subquery(Box<Select>),
+  /// A row value, like `(a, b)`.
+  Row(Vec<Expr>),
+}
+
+impl Expr {
+  /// Calls `visit` on this expression and on every expression nested in it,
+  /// parents first. Subqueries are not entered, since they have a scope of
+  /// their own.
+  pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Expr)) {
+    visit(self);
+    match self {
+      Self::Literal(_)
+      | Self::Variable(_)
+      | Self::Column { .. }
+      | Self::Exists { .. }
+      | Self::Subquery(_) => {}
+      Self::Unary { expr, .. }
+      | Self::Collate { expr, .. }
+      | Self::Cast { expr, .. }
+      | Self::IsNull { expr, .. }
+      | Self::InSelect { expr, .. } => expr.walk(visit),
+      Self::Binary { left, right, .. } => {
+        left.walk(visit);
+        right.walk(visit);
+      }
+      Self::Function {
+        args,
+        order_by,
+        filter,
+        over,
+        ..
+      } => {
+        if let FunctionArgs::List(args) = args {
+          args.iter().for_each(|arg| arg.walk(visit));
+        }
+        order_by.iter().for_each(|term| term.expr.walk(visit));
+        if let Some(filter) = filter {
+          filter.walk(visit);
+        }
+        if let Some(Over::Window(window)) = over {
+          window.partition_by.iter().for_each(|expr| expr.walk(visit));
+          window
+            .order_by
+            .iter()
+            .for_each(|term| term.expr.walk(visit));
+        }
+      }
+      Self::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        if let Some(operand) = operand {
+          operand.walk(visit);
+        }
+        for (when, then) in when_then {
+          when.walk(visit);
+          then.walk(visit);
+        }
+        if let Some(else_expr) = else_expr {
+          else_expr.walk(visit);
+        }
+      }
+      Self::Between {
+        expr, low, high, ..
+      } => {
+        expr.walk(visit);
+        low.walk(visit);
+        high.walk(visit);
+      }
+      Self::Like {
+        expr,
+        pattern,
+        escape,
+        ..
+      } => {
+        expr.walk(visit);
+        pattern.walk(visit);
+        if let Some(escape) = escape {
+          escape.walk(visit);
+        }
+      }
+      Self::InList { expr, list, .. } => {
+        expr.walk(visit);
+        list.iter().for_each(|item| item.walk(visit));
+      }
+      Self::InTable { expr, args, .. } => {
+        expr.walk(visit);
+        args.iter().for_each(|arg| arg.walk(visit));
+      }
+      Self::Row(exprs) => exprs.iter().for_each(|expr| expr.walk(visit)),
+    }
+  }
+
+  /// Calls `visit` on every expression nested in this one and then on this
+  /// one, children first, so that an expression `visit` replaces is not
+  /// visited again. Subqueries are not entered, as with [`Self::walk`].
+  pub fn walk_mut(&mut self, visit: &mut impl FnMut(&mut Expr)) {
+    match self {
+      Self::Literal(_)
+      | Self::Variable(_)
+      | Self::Column { .. }
+      | Self::Exists { .. }
+      | Self::Subquery(_) => {}
+      Self::Unary { expr, .. }
+      | Self::Collate { expr, .. }
+      | Self::Cast { expr, .. }
+      | Self::IsNull { expr, .. }
+      | Self::InSelect { expr, .. } => expr.walk_mut(visit),
+      Self::Binary { left, right, .. } => {
+        left.walk_mut(visit);
+        right.walk_mut(visit);
+      }
+      Self::Function {
+        args,
+        order_by,
+        filter,
+        over,
+        ..
+      } => {
+        if let FunctionArgs::List(args) = args {
+          args.iter_mut().for_each(|arg| arg.walk_mut(visit));
+        }
+        order_by
+          .iter_mut()
+          .for_each(|term| term.expr.walk_mut(visit));
+        if let Some(filter) = filter {
+          filter.walk_mut(visit);
+        }
+        if let Some(Over::Window(window)) = over {
+          window.walk_mut(visit);
+        }
+      }
+      Self::Case {
+        operand,
+        when_then,
+        else_expr,
+      } => {
+        if let Some(operand) = operand {
+          operand.walk_mut(visit);
+        }
+        for (when, then) in when_then {
+          when.walk_mut(visit);
+          then.walk_mut(visit);
+        }
+        if let Some(else_expr) = else_expr {
+          else_expr.walk_mut(visit);
+        }
+      }
+      Self::Between {
+        expr, low, high, ..
+      } => {
+        expr.walk_mut(visit);
+        low.walk_mut(visit);
+        high.walk_mut(visit);
+      }
+      Self::Like {
+        expr,
+        pattern,
+        escape,
+        ..
+      } => {
+        expr.walk_mut(visit);
+        pattern.walk_mut(visit);
+        if let Some(escape) = escape {
+          escape.walk_mut(visit);
+        }
+      }
+      Self::InList { expr, list, .. } => {
+        expr.walk_mut(visit);
+        list.iter_mut().for_each(|item| item.walk_mut(visit));
+      }
+      Self::InTable { expr, args, .. } => {
+        expr.walk_mut(visit);
+        args.iter_mut().for_each(|arg| arg.walk_mut(visit));
+      }
+      Self::Row(exprs) => exprs.iter_mut().for_each(|expr| expr.walk_mut(visit)),
+    }
+    visit(self);
+  }
+}
+
+impl Window {
+  /// Calls [`Expr::walk_mut`] on the PARTITION BY and ORDER BY terms.
+  pub fn walk_mut(&mut self, visit: &mut impl FnMut(&mut Expr)) {
+    self
+      .partition_by
+      .iter_mut()
+      .for_each(|expr| expr.walk_mut(visit));
+    self
+      .order_by
+      .iter_mut()
+      .for_each(|term| term.expr.walk_mut(visit));
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+  Null,
+  Integer(i64),
+  Float(f64),
+  String(String),
+  Blob(Vec<u8>),
+  CurrentTime,
+  CurrentDate,
+  CurrentTimestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionArgs {
+  /// `count(*)`
+  Star,
+  List(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+  /// `-`
+  Negate,
+  /// `+`
+  Plus,
+  /// `~`
+  BitNot,
+  Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+  Or,
+  And,
+  Eq,
+  Ne,
+  /// `IS` and `IS NOT DISTINCT FROM`
+  Is,
+  /// `IS NOT` and `IS DISTINCT FROM`
+  IsNot,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  BitAnd,
+  BitOr,
+  ShiftLeft,
+  ShiftRight,
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Remainder,
+  /// `||`
+  Concat,
+  /// `->`
+  Extract,
+  /// `->>`
+  ExtractText,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LikeOperator {
+  Like,
+  Glob,
+  Regexp,
+  Match,
+}
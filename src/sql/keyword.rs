@@ -0,0 +1,197 @@
+use core::fmt::Display;
+
+macro_rules! keywords {
+  ($($variant:ident => $text:literal,)*) => {
+    /// # SQL Keywords
+    ///
+    ///  The words reserved by SQLite's grammar. Many of them can still be used
+    /// as identifiers where the grammar is not ambiguous, so the parser decides
+    /// what a keyword means in context.
+    ///
+    /// *Reference:* https://www.sqlite.org/lang_keywords.html
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum Keyword {
+      $($variant,)*
+    }
+
+    impl Keyword {
+      pub const ALL: &'static [Keyword] = &[$(Self::$variant,)*];
+
+      /// Looks a bare word up, ignoring ASCII case.
+      pub fn from_word(word: &str) -> Option<Self> {
+        if word.len() > MAX_KEYWORD_LENGTH {
+          return None;
+        }
+        match word.to_ascii_uppercase().as_str() {
+          $($text => Some(Self::$variant),)*
+          _ => None,
+        }
+      }
+
+      pub fn as_str(&self) -> &'static str {
+        match self {
+          $(Self::$variant => $text,)*
+        }
+      }
+    }
+  };
+}
+
+/// Length of `CURRENT_TIMESTAMP`, the longest keyword.
+const MAX_KEYWORD_LENGTH: usize = 17;
+
+keywords! {
+  Abort => "ABORT",
+  Action => "ACTION",
+  Add => "ADD",
+  After => "AFTER",
+  All => "ALL",
+  Alter => "ALTER",
+  Always => "ALWAYS",
+  Analyze => "ANALYZE",
+  And => "AND",
+  As => "AS",
+  Asc => "ASC",
+  Attach => "ATTACH",
+  Autoincrement => "AUTOINCREMENT",
+  Before => "BEFORE",
+  Begin => "BEGIN",
+  Between => "BETWEEN",
+  By => "BY",
+  Cascade => "CASCADE",
+  Case => "CASE",
+  Cast => "CAST",
+  Check => "CHECK",
+  Collate => "COLLATE",
+  Column => "COLUMN",
+  Commit => "COMMIT",
+  Conflict => "CONFLICT",
+  Constraint => "CONSTRAINT",
+  Create => "CREATE",
+  Cross => "CROSS",
+  Current => "CURRENT",
+  CurrentDate => "CURRENT_DATE",
+  CurrentTime => "CURRENT_TIME",
+  CurrentTimestamp => "CURRENT_TIMESTAMP",
+  Database => "DATABASE",
+  Default => "DEFAULT",
+  Deferrable => "DEFERRABLE",
+  Deferred => "DEFERRED",
+  Delete => "DELETE",
+  Desc => "DESC",
+  Detach => "DETACH",
+  Distinct => "DISTINCT",
+  Do => "DO",
+  Drop => "DROP",
+  Each => "EACH",
+  Else => "ELSE",
+  End => "END",
+  Escape => "ESCAPE",
+  Except => "EXCEPT",
+  Exclude => "EXCLUDE",
+  Exclusive => "EXCLUSIVE",
+  Exists => "EXISTS",
+  Explain => "EXPLAIN",
+  Fail => "FAIL",
+  Filter => "FILTER",
+  First => "FIRST",
+  Following => "FOLLOWING",
+  For => "FOR",
+  Foreign => "FOREIGN",
+  From => "FROM",
+  Full => "FULL",
+  Generated => "GENERATED",
+  Glob => "GLOB",
+  Group => "GROUP",
+  Groups => "GROUPS",
+  Having => "HAVING",
+  If => "IF",
+  Ignore => "IGNORE",
+  Immediate => "IMMEDIATE",
+  In => "IN",
+  Index => "INDEX",
+  Indexed => "INDEXED",
+  Initially => "INITIALLY",
+  Inner => "INNER",
+  Insert => "INSERT",
+  Instead => "INSTEAD",
+  Intersect => "INTERSECT",
+  Into => "INTO",
+  Is => "IS",
+  Isnull => "ISNULL",
+  Join => "JOIN",
+  Key => "KEY",
+  Last => "LAST",
+  Left => "LEFT",
+  Like => "LIKE",
+  Limit => "LIMIT",
+  Match => "MATCH",
+  Materialized => "MATERIALIZED",
+  Natural => "NATURAL",
+  No => "NO",
+  Not => "NOT",
+  Nothing => "NOTHING",
+  Notnull => "NOTNULL",
+  Null => "NULL",
+  Nulls => "NULLS",
+  Of => "OF",
+  Offset => "OFFSET",
+  On => "ON",
+  Or => "OR",
+  Order => "ORDER",
+  Others => "OTHERS",
+  Outer => "OUTER",
+  Over => "OVER",
+  Partition => "PARTITION",
+  Plan => "PLAN",
+  Pragma => "PRAGMA",
+  Preceding => "PRECEDING",
+  Primary => "PRIMARY",
+  Query => "QUERY",
+  Raise => "RAISE",
+  Range => "RANGE",
+  Recursive => "RECURSIVE",
+  References => "REFERENCES",
+  Regexp => "REGEXP",
+  Reindex => "REINDEX",
+  Release => "RELEASE",
+  Rename => "RENAME",
+  Replace => "REPLACE",
+  Restrict => "RESTRICT",
+  Returning => "RETURNING",
+  Right => "RIGHT",
+  Rollback => "ROLLBACK",
+  Row => "ROW",
+  Rows => "ROWS",
+  Savepoint => "SAVEPOINT",
+  Select => "SELECT",
+  Set => "SET",
+  Table => "TABLE",
+  Temp => "TEMP",
+  Temporary => "TEMPORARY",
+  Then => "THEN",
+  Ties => "TIES",
+  To => "TO",
+  Transaction => "TRANSACTION",
+  Trigger => "TRIGGER",
+  Unbounded => "UNBOUNDED",
+  Union => "UNION",
+  Unique => "UNIQUE",
+  Update => "UPDATE",
+  Using => "USING",
+  Vacuum => "VACUUM",
+  Values => "VALUES",
+  View => "VIEW",
+  Virtual => "VIRTUAL",
+  When => "WHEN",
+  Where => "WHERE",
+  Window => "WINDOW",
+  With => "WITH",
+  Without => "WITHOUT",
+}
+
+impl Display for Keyword {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
@@ -0,0 +1,1459 @@
+//! # Parser
+//!
+//!  The parser takes the tokens of an SQL statement and assigns them meaning
+//! based on their context, producing an [abstract syntax tree](super::ast).
+//! Expressions are parsed by precedence climbing with SQLite's operator
+//! precedence, from highest to lowest:
+//!
+//! ```text
+//! ~ [expr]    + [expr]    - [expr]
+//! [expr] COLLATE (collation-name)
+//! ||   ->   ->>
+//! *   /   %
+//! +   -
+//! &   |   <<   >>
+//! [expr] ESCAPE [escape-character-expr]
+//! <   >   <=   >=
+//! =   ==   <>   !=   IS   IS NOT   IS DISTINCT FROM   IS NOT DISTINCT FROM
+//!   [expr] BETWEEN [expr] AND [expr]   IN   MATCH   LIKE   REGEXP   GLOB
+//!   [expr] ISNULL   [expr] NOTNULL   [expr] NOT NULL
+//! NOT [expr]
+//! AND
+//! OR
+//! ```
+//!
+//! *Reference:* https://www.sqlite.org/lang_expr.html#operators_and_parse_affecting_attributes
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+  ast::{
+    BinaryOperator, CommonTableExpr, CompoundOperator, Expr, Frame, FrameBound, FrameExclude,
+    FrameUnits, FunctionArgs, IndexedBy, Join, JoinClause, JoinConstraint, JoinKind, JoinOperator,
+    LikeOperator, Limit, Literal, NullsOrder, OrderingTerm, Over, Pragma, QualifiedName,
+    ResultColumn, Select, SortOrder, Statement, TableOrSubquery, UnaryOperator, Window, With,
+  },
+  tokenize, Keyword, Operator, Token, TokenKind,
+};
+use crate::result::{SqlParsingError, SqliteError, SqliteResult};
+
+/// Parses a single SQL statement, optionally followed by a semicolon.
+pub fn parse_statement(sql: &str) -> SqliteResult<Statement> {
+  let mut parser = Parser::new(sql)?;
+  let statement = parser.parse_statement()?;
+  parser.expect_end()?;
+  Ok(statement)
+}
+
+/// Parses a single `SELECT` statement.
+pub fn parse_select(sql: &str) -> SqliteResult<Select> {
+  let mut parser = Parser::new(sql)?;
+  if !parser.peek_select() {
+    return Err(parser.error("a SELECT statement"));
+  }
+  let select = parser.parse_select()?;
+  parser.eat_operator(Operator::Semicolon);
+  parser.expect_end()?;
+  Ok(select)
+}
+
+/// Parses a standalone expression.
+pub fn parse_expr(sql: &str) -> SqliteResult<Expr> {
+  let mut parser = Parser::new(sql)?;
+  let expr = parser.parse_expr()?;
+  parser.expect_end()?;
+  Ok(expr)
+}
+
+/// The largest index of a parameter, as SQLite's default
+/// `SQLITE_MAX_VARIABLE_NUMBER`.
+pub(crate) const MAX_VARIABLE_NUMBER: usize = 32766;
+
+/// Binding powers, from loosest to tightest.
+mod precedence {
+  pub(super) const OR: u8 = 1;
+  pub(super) const AND: u8 = 2;
+  pub(super) const NOT: u8 = 3;
+  pub(super) const EQUALITY: u8 = 4;
+  pub(super) const COMPARISON: u8 = 5;
+  pub(super) const ESCAPE: u8 = 6;
+  pub(super) const BITWISE: u8 = 7;
+  pub(super) const ADDITIVE: u8 = 8;
+  pub(super) const MULTIPLICATIVE: u8 = 9;
+  pub(super) const CONCAT: u8 = 10;
+  pub(super) const COLLATE: u8 = 11;
+  pub(super) const UNARY: u8 = 12;
+}
+
+/// Keywords that can also be used as identifiers, as long as the grammar is
+/// not ambiguous.
+const FALLBACK_KEYWORDS: [Keyword; 75] = [
+  Keyword::Abort,
+  Keyword::Action,
+  Keyword::After,
+  Keyword::Analyze,
+  Keyword::Asc,
+  Keyword::Attach,
+  Keyword::Before,
+  Keyword::Begin,
+  Keyword::By,
+  Keyword::Cascade,
+  Keyword::Cast,
+  Keyword::Column,
+  Keyword::Conflict,
+  Keyword::Database,
+  Keyword::Deferred,
+  Keyword::Desc,
+  Keyword::Detach,
+  Keyword::Do,
+  Keyword::Each,
+  Keyword::End,
+  Keyword::Exclusive,
+  Keyword::Explain,
+  Keyword::Fail,
+  Keyword::For,
+  Keyword::Ignore,
+  Keyword::Immediate,
+  Keyword::Initially,
+  Keyword::Instead,
+  Keyword::Like,
+  Keyword::Glob,
+  Keyword::Regexp,
+  Keyword::Match,
+  Keyword::No,
+  Keyword::Plan,
+  Keyword::Query,
+  Keyword::Key,
+  Keyword::Of,
+  Keyword::Offset,
+  Keyword::Pragma,
+  Keyword::Raise,
+  Keyword::Recursive,
+  Keyword::Release,
+  Keyword::Replace,
+  Keyword::Restrict,
+  Keyword::Row,
+  Keyword::Rows,
+  Keyword::Rollback,
+  Keyword::Savepoint,
+  Keyword::Temp,
+  Keyword::Temporary,
+  Keyword::Trigger,
+  Keyword::Vacuum,
+  Keyword::View,
+  Keyword::Virtual,
+  Keyword::With,
+  Keyword::Without,
+  Keyword::Nulls,
+  Keyword::First,
+  Keyword::Last,
+  Keyword::Current,
+  Keyword::Following,
+  Keyword::Partition,
+  Keyword::Preceding,
+  Keyword::Range,
+  Keyword::Unbounded,
+  Keyword::Exclude,
+  Keyword::Groups,
+  Keyword::Others,
+  Keyword::Ties,
+  Keyword::Generated,
+  Keyword::Always,
+  Keyword::Materialized,
+  Keyword::Reindex,
+  Keyword::Rename,
+  Keyword::If,
+];
+
+/// Keywords that can name tables and columns, but are not read as a bare
+/// alias since they may follow an expression or a table.
+const NAME_ONLY_KEYWORDS: [Keyword; 10] = [
+  Keyword::Cross,
+  Keyword::Full,
+  Keyword::Inner,
+  Keyword::Left,
+  Keyword::Natural,
+  Keyword::Outer,
+  Keyword::Right,
+  Keyword::Filter,
+  Keyword::Over,
+  Keyword::Window,
+];
+
+/// A recursive descent parser over the tokens of an SQL input.
+#[derive(Debug)]
+pub struct Parser<'a> {
+  sql: &'a str,
+  tokens: Vec<Token>,
+  position: usize,
+  /// The name of every parameter so far, by its index from 1. `None` for
+  /// one only written `?`.
+  parameters: Vec<Option<String>>,
+}
+
+impl<'a> Parser<'a> {
+  pub fn new(sql: &'a str) -> SqliteResult<Self> {
+    Ok(Self {
+      sql,
+      tokens: tokenize(sql)?,
+      position: 0,
+      parameters: vec![],
+    })
+  }
+
+  /// The names of the parameters of what has been parsed, by their index
+  /// from 1, which is how they are bound.
+  pub fn parameters(&self) -> &[Option<String>] {
+    &self.parameters
+  }
+
+  /// Whether every token has been consumed.
+  pub fn is_at_end(&self) -> bool {
+    self.position >= self.tokens.len()
+  }
+
+  /// Parses one statement and its optional terminating semicolon.
+  pub fn parse_statement(&mut self) -> SqliteResult<Statement> {
+    let statement = if self.peek_select() {
+      Statement::Select(Box::new(self.parse_select()?))
+    } else if self.eat_keyword(Keyword::Explain) {
+      let is_query_plan = self.eat_keyword(Keyword::Query);
+      if is_query_plan {
+        self.expect_keyword(Keyword::Plan)?;
+      }
+      if !self.peek_select() {
+        return Err(self.error("a SELECT statement"));
+      }
+      let select = Box::new(self.parse_select()?);
+      match is_query_plan {
+        true => Statement::ExplainQueryPlan(select),
+        false => Statement::Explain(select),
+      }
+    } else if self.eat_keyword(Keyword::Pragma) {
+      Statement::Pragma(self.parse_pragma()?)
+    } else {
+      return Err(self.error("a statement"));
+    };
+    self.eat_operator(Operator::Semicolon);
+    Ok(statement)
+  }
+
+  /// Fails unless every token has been consumed.
+  pub fn expect_end(&self) -> SqliteResult<()> {
+    if self.is_at_end() {
+      Ok(())
+    } else {
+      Err(self.error("the end of the statement"))
+    }
+  }
+
+  pub fn parse_select(&mut self) -> SqliteResult<Select> {
+    let with = if self.eat_keyword(Keyword::With) {
+      Some(self.parse_with()?)
+    } else {
+      None
+    };
+    let mut select = self.parse_select_core()?;
+    select.with = with;
+    while let Some(operator) = self.parse_compound_operator() {
+      select.compound.push((operator, self.parse_select_core()?));
+    }
+
+    if self.eat_keyword(Keyword::Order) {
+      select.order_by = self.parse_order_by()?;
+    }
+    if self.eat_keyword(Keyword::Limit) {
+      let first = self.parse_expr()?;
+      select.limit = Some(if self.eat_keyword(Keyword::Offset) {
+        Limit {
+          limit: first,
+          offset: Some(self.parse_expr()?),
+        }
+      } else if self.eat_operator(Operator::Comma) {
+        // `LIMIT offset, limit`
+        Limit {
+          limit: self.parse_expr()?,
+          offset: Some(first),
+        }
+      } else {
+        Limit {
+          limit: first,
+          offset: None,
+        }
+      });
+    }
+    if let Some(operator) = self.peek_compound_operator() {
+      let clause = if select.limit.is_some() {
+        "LIMIT"
+      } else {
+        "ORDER BY"
+      };
+      return Err(self.error_message(format!(
+        "{clause} clause should come after {operator} not before"
+      )));
+    }
+    Ok(select)
+  }
+
+  /// Whether a select, possibly with a `WITH` clause or made of `VALUES`,
+  /// starts at the current token.
+  fn peek_select(&self) -> bool {
+    self.peek_keyword(Keyword::Select)
+      || self.peek_keyword(Keyword::Values)
+      // `WITH` may also name a column.
+      || (self.peek_keyword(Keyword::With)
+        && self
+          .tokens
+          .get(self.position + 1)
+          .is_some_and(|token| !matches!(token.kind(), TokenKind::Operator(_))))
+  }
+
+  /// `WITH` has already been consumed.
+  fn parse_with(&mut self) -> SqliteResult<With> {
+    let recursive = self.eat_keyword(Keyword::Recursive);
+    let mut ctes = vec![];
+    loop {
+      let name = self.parse_name()?;
+      let columns = if self.peek_operator(Operator::LeftParen) {
+        self.parse_name_list()?
+      } else {
+        vec![]
+      };
+      self.expect_keyword(Keyword::As)?;
+      let materialized = if self.eat_keyword(Keyword::Materialized) {
+        Some(true)
+      } else if self.eat_keyword(Keyword::Not) {
+        self.expect_keyword(Keyword::Materialized)?;
+        Some(false)
+      } else {
+        None
+      };
+      self.expect_operator(Operator::LeftParen)?;
+      let select = self.parse_select()?;
+      self.expect_operator(Operator::RightParen)?;
+      ctes.push(CommonTableExpr {
+        name,
+        columns,
+        materialized,
+        select: Box::new(select),
+      });
+      if !self.eat_operator(Operator::Comma) {
+        break;
+      }
+    }
+    Ok(With { recursive, ctes })
+  }
+
+  fn peek_compound_operator(&self) -> Option<&'static str> {
+    if self.peek_keyword(Keyword::Union) {
+      Some(if self.peek_nth_keyword(1, Keyword::All) {
+        "UNION ALL"
+      } else {
+        "UNION"
+      })
+    } else if self.peek_keyword(Keyword::Intersect) {
+      Some("INTERSECT")
+    } else if self.peek_keyword(Keyword::Except) {
+      Some("EXCEPT")
+    } else {
+      None
+    }
+  }
+
+  fn parse_compound_operator(&mut self) -> Option<CompoundOperator> {
+    let operator = if self.eat_keyword(Keyword::Union) {
+      if self.eat_keyword(Keyword::All) {
+        CompoundOperator::UnionAll
+      } else {
+        CompoundOperator::Union
+      }
+    } else if self.eat_keyword(Keyword::Intersect) {
+      CompoundOperator::Intersect
+    } else if self.eat_keyword(Keyword::Except) {
+      CompoundOperator::Except
+    } else {
+      return None;
+    };
+    Some(operator)
+  }
+
+  /// A select without `WITH`, `ORDER BY` and `LIMIT`, or a `VALUES` clause.
+  fn parse_select_core(&mut self) -> SqliteResult<Select> {
+    if self.eat_keyword(Keyword::Values) {
+      let mut values = vec![];
+      loop {
+        self.expect_operator(Operator::LeftParen)?;
+        let row = self.parse_expr_list()?;
+        self.expect_operator(Operator::RightParen)?;
+        if values
+          .first()
+          .is_some_and(|first: &Vec<Expr>| first.len() != row.len())
+        {
+          return Err(self.error_message("all VALUES must have the same number of terms"));
+        }
+        values.push(row);
+        if !self.eat_operator(Operator::Comma) {
+          break;
+        }
+      }
+      return Ok(Select {
+        values,
+        ..Default::default()
+      });
+    }
+
+    self.expect_keyword(Keyword::Select)?;
+    let mut select = Select {
+      distinct: self.eat_keyword(Keyword::Distinct),
+      ..Default::default()
+    };
+    if !select.distinct {
+      self.eat_keyword(Keyword::All);
+    }
+
+    loop {
+      select.columns.push(self.parse_result_column()?);
+      if !self.eat_operator(Operator::Comma) {
+        break;
+      }
+    }
+    if self.eat_keyword(Keyword::From) {
+      select.from = Some(self.parse_join_clause()?);
+    }
+    if self.eat_keyword(Keyword::Where) {
+      select.where_clause = Some(self.parse_expr()?);
+    }
+    if self.eat_keyword(Keyword::Group) {
+      self.expect_keyword(Keyword::By)?;
+      select.group_by = self.parse_expr_list()?;
+    }
+    if self.eat_keyword(Keyword::Having) {
+      select.having = Some(self.parse_expr()?);
+    }
+    if self.eat_keyword(Keyword::Window) {
+      loop {
+        let name = self.parse_name()?;
+        self.expect_keyword(Keyword::As)?;
+        self.expect_operator(Operator::LeftParen)?;
+        select.windows.push((name, self.parse_window()?));
+        if !self.eat_operator(Operator::Comma) {
+          break;
+        }
+      }
+    }
+    Ok(select)
+  }
+
+  /// The rest of a `PRAGMA` statement, after the keyword.
+  fn parse_pragma(&mut self) -> SqliteResult<Pragma> {
+    let name = self.parse_qualified_name()?;
+    let value = if self.eat_operator(Operator::Eq) {
+      Some(self.parse_pragma_value()?)
+    } else if self.eat_operator(Operator::LeftParen) {
+      let value = self.parse_pragma_value()?;
+      self.expect_operator(Operator::RightParen)?;
+      Some(value)
+    } else {
+      None
+    };
+    Ok(Pragma { name, value })
+  }
+
+  /// A signed number, a name or a string. Any keyword is a name here, as in
+  /// `PRAGMA journal_mode = DELETE`.
+  fn parse_pragma_value(&mut self) -> SqliteResult<String> {
+    let is_negative = self.eat_operator(Operator::Minus);
+    let is_signed = is_negative || self.eat_operator(Operator::Plus);
+    let is_value = self.peek().is_some_and(|token| match token.kind() {
+      TokenKind::Integer | TokenKind::Float => true,
+      TokenKind::Identifier
+      | TokenKind::QuotedIdentifier
+      | TokenKind::String
+      | TokenKind::Keyword(_) => !is_signed,
+      _ => false,
+    });
+    if !is_value {
+      return Err(self.error("a pragma value"));
+    }
+    let sign = if is_negative { "-" } else { "" };
+    let value = format!("{sign}{}", self.tokens[self.position].value());
+    self.position += 1;
+    Ok(value)
+  }
+
+  /// A window definition, after its opening parenthesis, up to and
+  /// including its closing one.
+  fn parse_window(&mut self) -> SqliteResult<Window> {
+    let mut window = Window::default();
+    let is_clause = [
+      Keyword::Partition,
+      Keyword::Range,
+      Keyword::Rows,
+      Keyword::Groups,
+    ]
+    .into_iter()
+    .any(|keyword| self.peek_keyword(keyword));
+    if self.peek_name() && !is_clause {
+      window.base = Some(self.parse_name()?);
+    }
+    if self.eat_keyword(Keyword::Partition) {
+      self.expect_keyword(Keyword::By)?;
+      window.partition_by = self.parse_expr_list()?;
+    }
+    if self.eat_keyword(Keyword::Order) {
+      window.order_by = self.parse_order_by()?;
+    }
+    let units = if self.eat_keyword(Keyword::Rows) {
+      Some(FrameUnits::Rows)
+    } else if self.eat_keyword(Keyword::Range) {
+      Some(FrameUnits::Range)
+    } else if self.eat_keyword(Keyword::Groups) {
+      Some(FrameUnits::Groups)
+    } else {
+      None
+    };
+    if let Some(units) = units {
+      window.frame = Some(self.parse_frame(units)?);
+    }
+    self.expect_operator(Operator::RightParen)?;
+    Ok(window)
+  }
+
+  /// The bounds and exclusion of a frame, after its units.
+  fn parse_frame(&mut self, units: FrameUnits) -> SqliteResult<Frame> {
+    let (start, end) = if self.eat_keyword(Keyword::Between) {
+      let start = self.parse_frame_bound(true)?;
+      self.expect_keyword(Keyword::And)?;
+      (start, self.parse_frame_bound(false)?)
+    } else {
+      (self.parse_frame_bound(true)?, FrameBound::CurrentRow)
+    };
+    // The frame may not end before it starts.
+    let is_backwards = matches!(
+      (&start, &end),
+      (
+        FrameBound::Following(_),
+        FrameBound::Preceding(_) | FrameBound::CurrentRow
+      ) | (FrameBound::CurrentRow, FrameBound::Preceding(_))
+    );
+    if is_backwards {
+      return Err(self.error_message("unsupported frame specification"));
+    }
+    let exclude = if self.eat_keyword(Keyword::Exclude) {
+      if self.eat_keyword(Keyword::No) {
+        self.expect_keyword(Keyword::Others)?;
+        FrameExclude::NoOthers
+      } else if self.eat_keyword(Keyword::Current) {
+        self.expect_keyword(Keyword::Row)?;
+        FrameExclude::CurrentRow
+      } else if self.eat_keyword(Keyword::Group) {
+        FrameExclude::Group
+      } else {
+        self.expect_keyword(Keyword::Ties)?;
+        FrameExclude::Ties
+      }
+    } else {
+      FrameExclude::NoOthers
+    };
+    Ok(Frame {
+      units,
+      start,
+      end,
+      exclude,
+    })
+  }
+
+  /// A frame bound. The start of a frame may not be `UNBOUNDED FOLLOWING`
+  /// and its end may not be `UNBOUNDED PRECEDING`.
+  fn parse_frame_bound(&mut self, is_start: bool) -> SqliteResult<FrameBound> {
+    if self.eat_keyword(Keyword::Unbounded) {
+      return match is_start {
+        true => {
+          self.expect_keyword(Keyword::Preceding)?;
+          Ok(FrameBound::UnboundedPreceding)
+        }
+        false => {
+          self.expect_keyword(Keyword::Following)?;
+          Ok(FrameBound::UnboundedFollowing)
+        }
+      };
+    }
+    if self.peek_keyword(Keyword::Current) && self.peek_nth_keyword(1, Keyword::Row) {
+      self.position += 2;
+      return Ok(FrameBound::CurrentRow);
+    }
+    let offset = self.parse_expr()?;
+    if self.eat_keyword(Keyword::Preceding) {
+      Ok(FrameBound::Preceding(offset))
+    } else if self.eat_keyword(Keyword::Following) {
+      Ok(FrameBound::Following(offset))
+    } else {
+      Err(self.error("PRECEDING or FOLLOWING"))
+    }
+  }
+
+  fn parse_result_column(&mut self) -> SqliteResult<ResultColumn> {
+    if self.eat_operator(Operator::Star) {
+      return Ok(ResultColumn::Star);
+    }
+    if self.peek_name()
+      && self.peek_nth_operator(1, Operator::Dot)
+      && self.peek_nth_operator(2, Operator::Star)
+    {
+      let table = self.parse_name()?;
+      self.position += 2;
+      return Ok(ResultColumn::TableStar(table));
+    }
+    let start = self.tokens[self.position.min(self.tokens.len() - 1)]
+      .span()
+      .start;
+    let expr = self.parse_expr()?;
+    let end = self.tokens[self.position - 1].span().end;
+    let alias = self.parse_alias()?;
+    Ok(ResultColumn::Expr {
+      expr,
+      alias,
+      text: self.sql[start..end].to_string(),
+    })
+  }
+
+  /// `[AS] alias`
+  fn parse_alias(&mut self) -> SqliteResult<Option<String>> {
+    if self.eat_keyword(Keyword::As) {
+      return self.parse_name().map(Some);
+    }
+    let is_alias = self.peek().is_some_and(|token| match token.kind() {
+      TokenKind::Identifier | TokenKind::QuotedIdentifier | TokenKind::String => true,
+      TokenKind::Keyword(keyword) => FALLBACK_KEYWORDS.contains(&keyword),
+      _ => false,
+    });
+    if is_alias {
+      self.parse_name().map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn parse_join_clause(&mut self) -> SqliteResult<JoinClause> {
+    let first = self.parse_table_or_subquery()?;
+    let mut joins = vec![];
+    while let Some(operator) = self.parse_join_operator()? {
+      let table = self.parse_table_or_subquery()?;
+      let constraint = if self.eat_keyword(Keyword::On) {
+        Some(JoinConstraint::On(self.parse_expr()?))
+      } else if self.eat_keyword(Keyword::Using) {
+        Some(JoinConstraint::Using(self.parse_name_list()?))
+      } else {
+        None
+      };
+      joins.push(Join {
+        operator,
+        table,
+        constraint,
+      });
+    }
+    Ok(JoinClause { first, joins })
+  }
+
+  fn parse_join_operator(&mut self) -> SqliteResult<Option<JoinOperator>> {
+    if self.eat_operator(Operator::Comma) {
+      return Ok(Some(JoinOperator {
+        natural: false,
+        kind: JoinKind::Comma,
+      }));
+    }
+    let start = self.position;
+    let natural = self.eat_keyword(Keyword::Natural);
+    let kind = if self.eat_keyword(Keyword::Left) {
+      self.eat_keyword(Keyword::Outer);
+      JoinKind::Left
+    } else if self.eat_keyword(Keyword::Right) {
+      self.eat_keyword(Keyword::Outer);
+      JoinKind::Right
+    } else if self.eat_keyword(Keyword::Full) {
+      self.eat_keyword(Keyword::Outer);
+      JoinKind::Full
+    } else if self.eat_keyword(Keyword::Inner) {
+      JoinKind::Inner
+    } else if self.eat_keyword(Keyword::Cross) {
+      JoinKind::Cross
+    } else {
+      JoinKind::Inner
+    };
+    if self.eat_keyword(Keyword::Join) {
+      Ok(Some(JoinOperator { natural, kind }))
+    } else if self.position == start {
+      Ok(None)
+    } else {
+      Err(self.error("JOIN"))
+    }
+  }
+
+  fn parse_table_or_subquery(&mut self) -> SqliteResult<TableOrSubquery> {
+    if self.eat_operator(Operator::LeftParen) {
+      if self.peek_select() {
+        let select = self.parse_select()?;
+        self.expect_operator(Operator::RightParen)?;
+        return Ok(TableOrSubquery::Subquery {
+          select: Box::new(select),
+          alias: self.parse_alias()?,
+        });
+      }
+      let join_clause = self.parse_join_clause()?;
+      self.expect_operator(Operator::RightParen)?;
+      return Ok(if join_clause.joins.is_empty() {
+        join_clause.first
+      } else {
+        TableOrSubquery::Join(Box::new(join_clause))
+      });
+    }
+
+    let name = self.parse_qualified_name()?;
+    if self.eat_operator(Operator::LeftParen) {
+      let args = if self.peek_operator(Operator::RightParen) {
+        vec![]
+      } else {
+        self.parse_expr_list()?
+      };
+      self.expect_operator(Operator::RightParen)?;
+      return Ok(TableOrSubquery::TableFunction {
+        name,
+        args,
+        alias: self.parse_alias()?,
+      });
+    }
+    let alias = self.parse_alias()?;
+    let indexed = if self.eat_keyword(Keyword::Indexed) {
+      self.expect_keyword(Keyword::By)?;
+      Some(IndexedBy::Index(self.parse_name()?))
+    } else if self.peek_keyword(Keyword::Not) && self.peek_nth_keyword(1, Keyword::Indexed) {
+      self.position += 2;
+      Some(IndexedBy::NotIndexed)
+    } else {
+      None
+    };
+    Ok(TableOrSubquery::Table {
+      name,
+      alias,
+      indexed,
+    })
+  }
+
+  /// The terms of an `ORDER BY`, after `ORDER`.
+  fn parse_order_by(&mut self) -> SqliteResult<Vec<OrderingTerm>> {
+    self.expect_keyword(Keyword::By)?;
+    let mut terms = vec![self.parse_ordering_term()?];
+    while self.eat_operator(Operator::Comma) {
+      terms.push(self.parse_ordering_term()?);
+    }
+    Ok(terms)
+  }
+
+  fn parse_ordering_term(&mut self) -> SqliteResult<OrderingTerm> {
+    let expr = self.parse_expr()?;
+    let order = if self.eat_keyword(Keyword::Asc) {
+      Some(SortOrder::Asc)
+    } else if self.eat_keyword(Keyword::Desc) {
+      Some(SortOrder::Desc)
+    } else {
+      None
+    };
+    let nulls = if self.eat_keyword(Keyword::Nulls) {
+      if self.eat_keyword(Keyword::First) {
+        Some(NullsOrder::First)
+      } else {
+        self.expect_keyword(Keyword::Last)?;
+        Some(NullsOrder::Last)
+      }
+    } else {
+      None
+    };
+    Ok(OrderingTerm { expr, order, nulls })
+  }
+
+  pub fn parse_expr(&mut self) -> SqliteResult<Expr> {
+    self.parse_expr_bp(0)
+  }
+
+  fn parse_expr_list(&mut self) -> SqliteResult<Vec<Expr>> {
+    let mut exprs = vec![self.parse_expr()?];
+    while self.eat_operator(Operator::Comma) {
+      exprs.push(self.parse_expr()?);
+    }
+    Ok(exprs)
+  }
+
+  /// Parses an expression whose operators bind at least as tightly as
+  /// `min_bp`.
+  fn parse_expr_bp(&mut self, min_bp: u8) -> SqliteResult<Expr> {
+    let mut left = self.parse_prefix()?;
+    while let Some(token) = self.peek() {
+      let kind = token.kind();
+
+      if let Some((operator, bp)) = binary_operator(kind) {
+        if bp < min_bp {
+          break;
+        }
+        self.position += 1;
+        let right = self.parse_expr_bp(bp + 1)?;
+        left = binary(left, operator, right);
+        continue;
+      }
+
+      match kind {
+        TokenKind::Keyword(Keyword::Collate) => {
+          if precedence::COLLATE < min_bp {
+            break;
+          }
+          self.position += 1;
+          let collation = self.parse_name()?;
+          left = Expr::Collate {
+            expr: Box::new(left),
+            collation,
+          };
+        }
+        TokenKind::Keyword(Keyword::Is) => {
+          if precedence::EQUALITY < min_bp {
+            break;
+          }
+          self.position += 1;
+          let not = self.eat_keyword(Keyword::Not);
+          let distinct_from = self.eat_keyword(Keyword::Distinct);
+          if distinct_from {
+            self.expect_keyword(Keyword::From)?;
+          }
+          let operator = if not != distinct_from {
+            BinaryOperator::IsNot
+          } else {
+            BinaryOperator::Is
+          };
+          let right = self.parse_expr_bp(precedence::EQUALITY + 1)?;
+          left = binary(left, operator, right);
+        }
+        TokenKind::Keyword(Keyword::Isnull | Keyword::Notnull) => {
+          if precedence::EQUALITY < min_bp {
+            break;
+          }
+          self.position += 1;
+          left = Expr::IsNull {
+            expr: Box::new(left),
+            not: kind == TokenKind::Keyword(Keyword::Notnull),
+          };
+        }
+        TokenKind::Keyword(
+          Keyword::Not
+          | Keyword::Between
+          | Keyword::In
+          | Keyword::Like
+          | Keyword::Glob
+          | Keyword::Regexp
+          | Keyword::Match,
+        ) => {
+          if precedence::EQUALITY < min_bp {
+            break;
+          }
+          let not = self.eat_keyword(Keyword::Not);
+          if not && self.eat_keyword(Keyword::Null) {
+            left = Expr::IsNull {
+              expr: Box::new(left),
+              not: true,
+            };
+            continue;
+          }
+          left = self.parse_equality_operator(left, not)?;
+        }
+        _ => break,
+      }
+    }
+    Ok(left)
+  }
+
+  /// `[NOT] BETWEEN`, `[NOT] IN` and `[NOT] LIKE|GLOB|REGEXP|MATCH`, after
+  /// the optional `NOT`.
+  fn parse_equality_operator(&mut self, left: Expr, not: bool) -> SqliteResult<Expr> {
+    let expr = Box::new(left);
+    if self.eat_keyword(Keyword::Between) {
+      let low = self.parse_expr_bp(precedence::EQUALITY + 1)?;
+      self.expect_keyword(Keyword::And)?;
+      let high = self.parse_expr_bp(precedence::EQUALITY + 1)?;
+      return Ok(Expr::Between {
+        expr,
+        not,
+        low: Box::new(low),
+        high: Box::new(high),
+      });
+    }
+    if self.eat_keyword(Keyword::In) {
+      return self.parse_in(expr, not);
+    }
+    let operator = if self.eat_keyword(Keyword::Like) {
+      LikeOperator::Like
+    } else if self.eat_keyword(Keyword::Glob) {
+      LikeOperator::Glob
+    } else if self.eat_keyword(Keyword::Regexp) {
+      LikeOperator::Regexp
+    } else if self.eat_keyword(Keyword::Match) {
+      LikeOperator::Match
+    } else {
+      return Err(self.error("BETWEEN, IN, LIKE, GLOB, REGEXP, MATCH or NULL"));
+    };
+    let pattern = self.parse_expr_bp(precedence::EQUALITY + 1)?;
+    let escape = if self.eat_keyword(Keyword::Escape) {
+      Some(Box::new(self.parse_expr_bp(precedence::ESCAPE)?))
+    } else {
+      None
+    };
+    Ok(Expr::Like {
+      expr,
+      not,
+      operator,
+      pattern: Box::new(pattern),
+      escape,
+    })
+  }
+
+  /// The right-hand side of `IN`.
+  fn parse_in(&mut self, expr: Box<Expr>, not: bool) -> SqliteResult<Expr> {
+    if self.eat_operator(Operator::LeftParen) {
+      if self.peek_select() {
+        let select = self.parse_select()?;
+        self.expect_operator(Operator::RightParen)?;
+        return Ok(Expr::InSelect {
+          expr,
+          not,
+          select: Box::new(select),
+        });
+      }
+      let list = if self.peek_operator(Operator::RightParen) {
+        vec![]
+      } else {
+        self.parse_expr_list()?
+      };
+      self.expect_operator(Operator::RightParen)?;
+      return Ok(Expr::InList { expr, not, list });
+    }
+
+    let table = self.parse_qualified_name()?;
+    let args = if self.eat_operator(Operator::LeftParen) {
+      let args = if self.peek_operator(Operator::RightParen) {
+        vec![]
+      } else {
+        self.parse_expr_list()?
+      };
+      self.expect_operator(Operator::RightParen)?;
+      args
+    } else {
+      vec![]
+    };
+    Ok(Expr::InTable {
+      expr,
+      not,
+      table,
+      args,
+    })
+  }
+
+  fn parse_prefix(&mut self) -> SqliteResult<Expr> {
+    let Some(token) = self.peek() else {
+      return Err(self.error("an expression"));
+    };
+    let operator = match token.kind() {
+      TokenKind::Operator(Operator::Minus) => UnaryOperator::Negate,
+      TokenKind::Operator(Operator::Plus) => UnaryOperator::Plus,
+      TokenKind::Operator(Operator::BitNot) => UnaryOperator::BitNot,
+      TokenKind::Keyword(Keyword::Not) => {
+        self.position += 1;
+        if self.eat_keyword(Keyword::Exists) {
+          return self.parse_exists(true);
+        }
+        let expr = self.parse_expr_bp(precedence::NOT)?;
+        return Ok(Expr::Unary {
+          operator: UnaryOperator::Not,
+          expr: Box::new(expr),
+        });
+      }
+      _ => return self.parse_primary(),
+    };
+    self.position += 1;
+
+    // -9223372036854775808 is the only integer literal whose magnitude does
+    // not fit in an i64.
+    if operator == UnaryOperator::Negate
+      && self.peek().is_some_and(|token| {
+        token.kind() == TokenKind::Integer
+          && token.value().replace('_', "") == "9223372036854775808"
+      })
+    {
+      self.position += 1;
+      return Ok(Expr::Literal(Literal::Integer(i64::MIN)));
+    }
+
+    let expr = self.parse_expr_bp(precedence::UNARY)?;
+    Ok(Expr::Unary {
+      operator,
+      expr: Box::new(expr),
+    })
+  }
+
+  fn parse_primary(&mut self) -> SqliteResult<Expr> {
+    let Some(token) = self.peek().cloned() else {
+      return Err(self.error("an expression"));
+    };
+    let literal = match token.kind() {
+      TokenKind::Integer => Some(self.integer_literal(&token)?),
+      TokenKind::Float => Some(Literal::Float(self.float_literal(&token)?)),
+      TokenKind::String => Some(Literal::String(token.value().to_string())),
+      TokenKind::Blob => Some(Literal::Blob(decode_hex(token.value()))),
+      TokenKind::Keyword(Keyword::Null) => Some(Literal::Null),
+      TokenKind::Keyword(Keyword::CurrentTime) => Some(Literal::CurrentTime),
+      TokenKind::Keyword(Keyword::CurrentDate) => Some(Literal::CurrentDate),
+      TokenKind::Keyword(Keyword::CurrentTimestamp) => Some(Literal::CurrentTimestamp),
+      _ => None,
+    };
+    if let Some(literal) = literal {
+      self.position += 1;
+      return Ok(Expr::Literal(literal));
+    }
+
+    match token.kind() {
+      TokenKind::Variable => {
+        let name = self.number_parameter(token.value())?;
+        self.position += 1;
+        Ok(Expr::Variable(name))
+      }
+      TokenKind::Operator(Operator::LeftParen) => {
+        self.position += 1;
+        if self.peek_select() {
+          let select = self.parse_select()?;
+          self.expect_operator(Operator::RightParen)?;
+          return Ok(Expr::Subquery(Box::new(select)));
+        }
+        let mut exprs = self.parse_expr_list()?;
+        self.expect_operator(Operator::RightParen)?;
+        Ok(if exprs.len() == 1 {
+          exprs.remove(0)
+        } else {
+          Expr::Row(exprs)
+        })
+      }
+      TokenKind::Keyword(Keyword::Cast) if self.peek_nth_operator(1, Operator::LeftParen) => {
+        self.position += 2;
+        let expr = self.parse_expr()?;
+        self.expect_keyword(Keyword::As)?;
+        let type_name = self.parse_type_name()?;
+        self.expect_operator(Operator::RightParen)?;
+        Ok(Expr::Cast {
+          expr: Box::new(expr),
+          type_name,
+        })
+      }
+      TokenKind::Keyword(Keyword::Case) => {
+        self.position += 1;
+        self.parse_case()
+      }
+      TokenKind::Keyword(Keyword::Exists) => {
+        self.position += 1;
+        self.parse_exists(false)
+      }
+      TokenKind::Keyword(Keyword::Raise) if self.peek_nth_operator(1, Operator::LeftParen) => {
+        Err(self.error_message("RAISE() may only be used within a trigger-program"))
+      }
+      _ if self.peek_name() => self.parse_column_or_function(),
+      _ => Err(self.error("an expression")),
+    }
+  }
+
+  /// `EXISTS` has already been consumed.
+  fn parse_exists(&mut self, not: bool) -> SqliteResult<Expr> {
+    self.expect_operator(Operator::LeftParen)?;
+    let select = self.parse_select()?;
+    self.expect_operator(Operator::RightParen)?;
+    Ok(Expr::Exists {
+      not,
+      select: Box::new(select),
+    })
+  }
+
+  /// `CASE` has already been consumed.
+  fn parse_case(&mut self) -> SqliteResult<Expr> {
+    let operand = if self.peek_keyword(Keyword::When) {
+      None
+    } else {
+      Some(Box::new(self.parse_expr()?))
+    };
+    let mut when_then = vec![];
+    while self.eat_keyword(Keyword::When) {
+      let when = self.parse_expr()?;
+      self.expect_keyword(Keyword::Then)?;
+      when_then.push((when, self.parse_expr()?));
+    }
+    if when_then.is_empty() {
+      return Err(self.error("WHEN"));
+    }
+    let else_expr = if self.eat_keyword(Keyword::Else) {
+      Some(Box::new(self.parse_expr()?))
+    } else {
+      None
+    };
+    self.expect_keyword(Keyword::End)?;
+    Ok(Expr::Case {
+      operand,
+      when_then,
+      else_expr,
+    })
+  }
+
+  fn parse_column_or_function(&mut self) -> SqliteResult<Expr> {
+    let name = self.parse_name()?;
+    if self.eat_operator(Operator::LeftParen) {
+      return self.parse_function(name);
+    }
+    if !self.eat_operator(Operator::Dot) {
+      return Ok(Expr::Column {
+        schema: None,
+        table: None,
+        name,
+      });
+    }
+    let second = self.parse_name()?;
+    if !self.eat_operator(Operator::Dot) {
+      return Ok(Expr::Column {
+        schema: None,
+        table: Some(name),
+        name: second,
+      });
+    }
+    Ok(Expr::Column {
+      schema: Some(name),
+      table: Some(second),
+      name: self.parse_name()?,
+    })
+  }
+
+  /// The opening parenthesis has already been consumed.
+  fn parse_function(&mut self, name: String) -> SqliteResult<Expr> {
+    let mut distinct = false;
+    let args = if self.eat_operator(Operator::Star) {
+      FunctionArgs::Star
+    } else if self.peek_operator(Operator::RightParen) {
+      FunctionArgs::List(vec![])
+    } else {
+      distinct = self.eat_keyword(Keyword::Distinct);
+      if !distinct {
+        self.eat_keyword(Keyword::All);
+      }
+      FunctionArgs::List(self.parse_expr_list()?)
+    };
+    let order_by = match &args {
+      FunctionArgs::List(args) if !args.is_empty() && self.eat_keyword(Keyword::Order) => {
+        self.parse_order_by()?
+      }
+      _ => vec![],
+    };
+    self.expect_operator(Operator::RightParen)?;
+
+    let filter = if self.peek_word("FILTER") && self.peek_nth_operator(1, Operator::LeftParen) {
+      self.position += 2;
+      self.expect_keyword(Keyword::Where)?;
+      let filter = self.parse_expr()?;
+      self.expect_operator(Operator::RightParen)?;
+      Some(Box::new(filter))
+    } else {
+      None
+    };
+    let over = if self.eat_keyword(Keyword::Over) {
+      Some(if self.eat_operator(Operator::LeftParen) {
+        Over::Window(Box::new(self.parse_window()?))
+      } else {
+        Over::Name(self.parse_name()?)
+      })
+    } else {
+      None
+    };
+    Ok(Expr::Function {
+      name,
+      distinct,
+      args,
+      order_by,
+      filter,
+      over,
+    })
+  }
+
+  /// The type name of a CAST, as its verbatim source text.
+  fn parse_type_name(&mut self) -> SqliteResult<String> {
+    let start = match self.peek() {
+      Some(token) if self.peek_name() || token.kind() == TokenKind::String => token.span().start,
+      _ => return Err(self.error("a type name")),
+    };
+    let mut end = start;
+    let mut depth = 0usize;
+    while let Some(token) = self.peek() {
+      if token.is_operator(Operator::LeftParen) {
+        depth += 1;
+      } else if token.is_operator(Operator::RightParen) {
+        if depth == 0 {
+          break;
+        }
+        depth -= 1;
+      }
+      end = token.span().end;
+      self.position += 1;
+    }
+    Ok(self.sql[start..end].to_string())
+  }
+
+  fn integer_literal(&self, token: &Token) -> SqliteResult<Literal> {
+    let digits = token.value().replace('_', "");
+    if let Some(hex) = digits
+      .strip_prefix("0x")
+      .or_else(|| digits.strip_prefix("0X"))
+    {
+      // Hexadecimal literals are 64-bit two's-complement integers.
+      return u64::from_str_radix(hex, 16)
+        .map(|value| Literal::Integer(value as i64))
+        .map_err(|_| self.error_message(format!("hex literal too big: {}", token.value())));
+    }
+    match digits.parse::<i64>() {
+      Ok(value) => Ok(Literal::Integer(value)),
+      // Too large for an integer: SQLite reads it as a real.
+      Err(_) => Ok(Literal::Float(self.float_literal(token)?)),
+    }
+  }
+
+  fn float_literal(&self, token: &Token) -> SqliteResult<f64> {
+    token
+      .value()
+      .replace('_', "")
+      .parse()
+      .map_err(|_| self.error_message(format!("malformed number: {}", token.value())))
+  }
+
+  /// `( name, ... )`
+  fn parse_name_list(&mut self) -> SqliteResult<Vec<String>> {
+    self.expect_operator(Operator::LeftParen)?;
+    let mut names = vec![self.parse_name()?];
+    while self.eat_operator(Operator::Comma) {
+      names.push(self.parse_name()?);
+    }
+    self.expect_operator(Operator::RightParen)?;
+    Ok(names)
+  }
+
+  /// `[schema.]name`
+  fn parse_qualified_name(&mut self) -> SqliteResult<QualifiedName> {
+    let name = self.parse_name()?;
+    if self.eat_operator(Operator::Dot) {
+      Ok(QualifiedName {
+        schema: Some(name),
+        name: self.parse_name()?,
+      })
+    } else {
+      Ok(QualifiedName { schema: None, name })
+    }
+  }
+
+  fn peek_name(&self) -> bool {
+    self.peek().is_some_and(|token| match token.kind() {
+      TokenKind::Identifier | TokenKind::QuotedIdentifier => true,
+      TokenKind::Keyword(keyword) => {
+        FALLBACK_KEYWORDS.contains(&keyword) || NAME_ONLY_KEYWORDS.contains(&keyword)
+      }
+      _ => false,
+    })
+  }
+
+  fn parse_name(&mut self) -> SqliteResult<String> {
+    let is_name = self.peek_name()
+      || self
+        .peek()
+        .is_some_and(|token| token.kind() == TokenKind::String);
+    if !is_name {
+      return Err(self.error("a name"));
+    }
+    let name = self.tokens[self.position].value().to_string();
+    self.position += 1;
+    Ok(name)
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn peek_keyword(&self, keyword: Keyword) -> bool {
+    self.peek_nth_keyword(0, keyword)
+  }
+
+  fn peek_nth_keyword(&self, n: usize, keyword: Keyword) -> bool {
+    self
+      .tokens
+      .get(self.position + n)
+      .is_some_and(|token| token.is_keyword(keyword))
+  }
+
+  fn peek_word(&self, word: &str) -> bool {
+    self.peek().is_some_and(|token| token.is_word(word))
+  }
+
+  fn peek_operator(&self, operator: Operator) -> bool {
+    self.peek_nth_operator(0, operator)
+  }
+
+  fn peek_nth_operator(&self, n: usize, operator: Operator) -> bool {
+    self
+      .tokens
+      .get(self.position + n)
+      .is_some_and(|token| token.is_operator(operator))
+  }
+
+  fn eat_keyword(&mut self, keyword: Keyword) -> bool {
+    let is_match = self.peek_keyword(keyword);
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn eat_operator(&mut self, operator: Operator) -> bool {
+    let is_match = self.peek_operator(operator);
+    if is_match {
+      self.position += 1;
+    }
+    is_match
+  }
+
+  fn expect_keyword(&mut self, keyword: Keyword) -> SqliteResult<()> {
+    if self.eat_keyword(keyword) {
+      Ok(())
+    } else {
+      Err(self.error(keyword.as_str()))
+    }
+  }
+
+  fn expect_operator(&mut self, operator: Operator) -> SqliteResult<()> {
+    if self.eat_operator(operator) {
+      Ok(())
+    } else {
+      Err(self.error(format!("\"{operator}\"")))
+    }
+  }
+
+  /// A syntax error at the current token.
+  fn error(&self, expected: impl Into<String>) -> SqliteError {
+    let (message, offset) = match self.peek() {
+      Some(token) => {
+        let span = token.span();
+        (
+          format!("near \"{}\": syntax error", &self.sql[span.start..span.end]),
+          span.start,
+        )
+      }
+      None => ("incomplete input".to_string(), self.sql.len()),
+    };
+    SqlParsingError::new(message, self.sql, offset)
+      .with_expected(expected)
+      .into()
+  }
+
+  /// An error about the previous token.
+  /// Gives a parameter its index, the way SQLite does, and returns the name
+  /// it is read by. `?` takes the index after the largest so far, and reads
+  /// as `?N`. `?N` takes index N, and a named parameter the index it had
+  /// the first time it was seen, or the next one.
+  fn number_parameter(&mut self, name: &str) -> SqliteResult<String> {
+    let (index, name) = match name.strip_prefix('?') {
+      Some("") => (self.parameters.len() + 1, None),
+      Some(digits) => {
+        let index = digits
+          .parse::<usize>()
+          .ok()
+          .filter(|index| (1..=MAX_VARIABLE_NUMBER).contains(index))
+          .ok_or_else(|| {
+            self.error_message(format!(
+              "variable number must be between ?1 and ?{MAX_VARIABLE_NUMBER}"
+            ))
+          })?;
+        (index, Some(format!("?{index}")))
+      }
+      None => {
+        let index = self
+          .parameters
+          .iter()
+          .position(|parameter| parameter.as_deref() == Some(name))
+          .unwrap_or(self.parameters.len())
+          + 1;
+        (index, Some(name.to_string()))
+      }
+    };
+    if self.parameters.len() < index {
+      self.parameters.resize(index, None);
+    }
+    let parameter = &mut self.parameters[index - 1];
+    if parameter.is_none() {
+      parameter.clone_from(&name);
+    }
+    Ok(name.unwrap_or_else(|| format!("?{index}")))
+  }
+
+  fn error_message(&self, message: impl Into<String>) -> SqliteError {
+    let offset = self
+      .peek()
+      .map(|token| token.span().start)
+      .unwrap_or(self.sql.len());
+    SqlParsingError::new(message, self.sql, offset).into()
+  }
+}
+
+fn binary_operator(kind: TokenKind) -> Option<(BinaryOperator, u8)> {
+  use precedence::*;
+  let operator = match kind {
+    TokenKind::Keyword(Keyword::Or) => (BinaryOperator::Or, OR),
+    TokenKind::Keyword(Keyword::And) => (BinaryOperator::And, AND),
+    TokenKind::Operator(operator) => match operator {
+      Operator::Eq => (BinaryOperator::Eq, EQUALITY),
+      Operator::Ne => (BinaryOperator::Ne, EQUALITY),
+      Operator::Lt => (BinaryOperator::Lt, COMPARISON),
+      Operator::Le => (BinaryOperator::Le, COMPARISON),
+      Operator::Gt => (BinaryOperator::Gt, COMPARISON),
+      Operator::Ge => (BinaryOperator::Ge, COMPARISON),
+      Operator::BitAnd => (BinaryOperator::BitAnd, BITWISE),
+      Operator::BitOr => (BinaryOperator::BitOr, BITWISE),
+      Operator::ShiftLeft => (BinaryOperator::ShiftLeft, BITWISE),
+      Operator::ShiftRight => (BinaryOperator::ShiftRight, BITWISE),
+      Operator::Plus => (BinaryOperator::Add, ADDITIVE),
+      Operator::Minus => (BinaryOperator::Subtract, ADDITIVE),
+      Operator::Star => (BinaryOperator::Multiply, MULTIPLICATIVE),
+      Operator::Slash => (BinaryOperator::Divide, MULTIPLICATIVE),
+      Operator::Percent => (BinaryOperator::Remainder, MULTIPLICATIVE),
+      Operator::Concat => (BinaryOperator::Concat, CONCAT),
+      Operator::Arrow => (BinaryOperator::Extract, CONCAT),
+      Operator::LongArrow => (BinaryOperator::ExtractText, CONCAT),
+      _ => return None,
+    },
+    _ => return None,
+  };
+  Some(operator)
+}
+
+fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+  Expr::Binary {
+    left: Box::new(left),
+    operator,
+    right: Box::new(right),
+  }
+}
+
+/// The tokenizer has already checked that blobs hold an even number of hex
+/// digits.
+fn decode_hex(hex: &str) -> Vec<u8> {
+  hex
+    .as_bytes()
+    .chunks(2)
+    .filter_map(|pair| {
+      std::str::from_utf8(pair)
+        .ok()
+        .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+    })
+    .collect()
+}
@@ -0,0 +1,525 @@
+//! Tests for the SQL parser
+//!
+//! To run: `cargo test sql::parser`
+
+use super::{parse_expr, parse_select, parse_statement, Parser};
+use crate::{
+  result::SqliteError,
+  sql::ast::{
+    BinaryOperator, CompoundOperator, Expr, Frame, FrameBound, FrameExclude, FrameUnits,
+    FunctionArgs, IndexedBy, JoinConstraint, JoinKind, LikeOperator, Literal, NullsOrder,
+    OrderingTerm, Over, ResultColumn, SortOrder, Statement, TableOrSubquery, UnaryOperator, Window,
+  },
+};
+
+fn column(name: &str) -> Expr {
+  Expr::Column {
+    schema: None,
+    table: None,
+    name: name.into(),
+  }
+}
+
+fn integer(value: i64) -> Expr {
+  Expr::Literal(Literal::Integer(value))
+}
+
+fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+  Expr::Binary {
+    left: Box::new(left),
+    operator,
+    right: Box::new(right),
+  }
+}
+
+fn error_message(sql: &str) -> String {
+  match parse_select(sql) {
+    Err(SqliteError::SqlParsing(err)) => err.to_string(),
+    other => panic!("Expected a parsing error, got {other:?}"),
+  }
+}
+
+#[test]
+fn ok_on_operator_precedence() {
+  assert_eq!(
+    parse_expr("1 + 2 * 3 - 4").unwrap(),
+    binary(
+      binary(
+        integer(1),
+        BinaryOperator::Add,
+        binary(integer(2), BinaryOperator::Multiply, integer(3))
+      ),
+      BinaryOperator::Subtract,
+      integer(4)
+    )
+  );
+  assert_eq!(
+    parse_expr("NOT a = 1 OR b AND c").unwrap(),
+    binary(
+      Expr::Unary {
+        operator: UnaryOperator::Not,
+        expr: Box::new(binary(column("a"), BinaryOperator::Eq, integer(1))),
+      },
+      BinaryOperator::Or,
+      binary(column("b"), BinaryOperator::And, column("c"))
+    )
+  );
+  assert_eq!(
+    parse_expr("-a || 'x' COLLATE nocase").unwrap(),
+    binary(
+      Expr::Unary {
+        operator: UnaryOperator::Negate,
+        expr: Box::new(column("a")),
+      },
+      BinaryOperator::Concat,
+      Expr::Collate {
+        expr: Box::new(Expr::Literal(Literal::String("x".into()))),
+        collation: "nocase".into(),
+      }
+    )
+  );
+  assert_eq!(
+    parse_expr("a < b = c IS NOT DISTINCT FROM d").unwrap(),
+    binary(
+      binary(
+        binary(column("a"), BinaryOperator::Lt, column("b")),
+        BinaryOperator::Eq,
+        column("c")
+      ),
+      BinaryOperator::Is,
+      column("d")
+    )
+  );
+}
+
+#[test]
+fn ok_on_literals() {
+  assert_eq!(parse_expr("0x7fffffffffffffff").unwrap(), integer(i64::MAX));
+  assert_eq!(parse_expr("0xffffffffffffffff").unwrap(), integer(-1));
+  assert_eq!(
+    parse_expr("-9223372036854775808").unwrap(),
+    integer(i64::MIN)
+  );
+  assert_eq!(
+    parse_expr("9223372036854775808").unwrap(),
+    Expr::Literal(Literal::Float(9223372036854775808.0))
+  );
+  assert_eq!(parse_expr("1_000").unwrap(), integer(1000));
+  assert_eq!(
+    parse_expr("x'0aFf'").unwrap(),
+    Expr::Literal(Literal::Blob(vec![0x0a, 0xff]))
+  );
+  assert_eq!(
+    parse_expr("current_date").unwrap(),
+    Expr::Literal(Literal::CurrentDate)
+  );
+  assert!(parse_expr("0x10000000000000000").is_err());
+}
+
+#[test]
+fn ok_on_special_expressions() {
+  let expr = parse_expr(
+    "x NOT BETWEEN 1 AND 2 AND y NOT LIKE 'a%' ESCAPE '\\' AND z IN (SELECT 1) AND w NOTNULL",
+  )
+  .unwrap();
+  let Expr::Binary { left, right, .. } = expr else {
+    panic!("Expected AND");
+  };
+  assert_eq!(
+    *right,
+    Expr::IsNull {
+      expr: Box::new(column("w")),
+      not: true,
+    }
+  );
+  let Expr::Binary { left, right, .. } = *left else {
+    panic!("Expected AND");
+  };
+  assert!(matches!(*right, Expr::InSelect { not: false, .. }));
+  let Expr::Binary { left, right, .. } = *left else {
+    panic!("Expected AND");
+  };
+  assert!(matches!(
+    *right,
+    Expr::Like {
+      not: true,
+      operator: LikeOperator::Like,
+      escape: Some(_),
+      ..
+    }
+  ));
+  assert_eq!(
+    *left,
+    Expr::Between {
+      expr: Box::new(column("x")),
+      not: true,
+      low: Box::new(integer(1)),
+      high: Box::new(integer(2)),
+    }
+  );
+
+  assert_eq!(
+    parse_expr("CAST(x AS VARCHAR(10))").unwrap(),
+    Expr::Cast {
+      expr: Box::new(column("x")),
+      type_name: "VARCHAR(10)".into(),
+    }
+  );
+  assert!(matches!(
+    parse_expr("CASE WHEN a THEN 1 WHEN b THEN 2 ELSE 3 END").unwrap(),
+    Expr::Case { operand: None, ref when_then, else_expr: Some(_) } if when_then.len() == 2
+  ));
+  assert_eq!(
+    parse_expr("count(*) FILTER (WHERE x > 0)").unwrap(),
+    Expr::Function {
+      name: "count".into(),
+      distinct: false,
+      args: FunctionArgs::Star,
+      order_by: vec![],
+      filter: Some(Box::new(binary(
+        column("x"),
+        BinaryOperator::Gt,
+        integer(0)
+      ))),
+      over: None,
+    }
+  );
+  assert_eq!(
+    parse_expr("(a, b) IN ()").unwrap(),
+    Expr::InList {
+      expr: Box::new(Expr::Row(vec![column("a"), column("b")])),
+      not: false,
+      list: vec![],
+    }
+  );
+  assert!(matches!(
+    parse_expr("NOT EXISTS (SELECT 1)").unwrap(),
+    Expr::Exists { not: true, .. }
+  ));
+  assert_eq!(
+    parse_expr("main.t.\"key\"").unwrap(),
+    Expr::Column {
+      schema: Some("main".into()),
+      table: Some("t".into()),
+      name: "key".into(),
+    }
+  );
+}
+
+#[test]
+fn ok_on_select_clauses() {
+  let select = parse_select(
+    "SELECT DISTINCT t.*, a AS x, b y, count(*) FROM main.t AS t INDEXED BY i \
+     WHERE a > ?1 GROUP BY a, b HAVING count(*) > 1 \
+     ORDER BY 2 DESC NULLS FIRST, a LIMIT 10, 20;",
+  )
+  .unwrap();
+  assert!(select.distinct);
+  assert_eq!(select.columns.len(), 4);
+  assert_eq!(select.columns[0], ResultColumn::TableStar("t".into()));
+  assert_eq!(
+    select.columns[2],
+    ResultColumn::Expr {
+      expr: column("b"),
+      alias: Some("y".into()),
+      text: "b".into(),
+    }
+  );
+  let from = select.from.unwrap();
+  assert!(from.joins.is_empty());
+  let TableOrSubquery::Table {
+    name,
+    alias,
+    indexed,
+  } = from.first
+  else {
+    panic!("Expected a table");
+  };
+  assert_eq!(name.schema.as_deref(), Some("main"));
+  assert_eq!(alias.as_deref(), Some("t"));
+  assert_eq!(indexed, Some(IndexedBy::Index("i".into())));
+  assert_eq!(
+    select.where_clause,
+    Some(binary(
+      column("a"),
+      BinaryOperator::Gt,
+      Expr::Variable("?1".into())
+    ))
+  );
+  assert_eq!(select.group_by, [column("a"), column("b")]);
+  assert!(select.having.is_some());
+  assert_eq!(select.order_by[0].order, Some(SortOrder::Desc));
+  assert_eq!(select.order_by[0].nulls, Some(NullsOrder::First));
+  assert_eq!(select.order_by[1].order, None);
+  let limit = select.limit.unwrap();
+  assert_eq!(limit.limit, integer(20));
+  assert_eq!(limit.offset, Some(integer(10)));
+}
+
+#[test]
+fn ok_on_joins_and_subqueries() {
+  let select = parse_select(
+    "SELECT * FROM a NATURAL LEFT OUTER JOIN b, (SELECT 1) s \
+     CROSS JOIN json_each('[]') j JOIN c USING (id) INNER JOIN d ON d.id = c.id",
+  )
+  .unwrap();
+  let from = select.from.unwrap();
+  let kinds: Vec<_> = from
+    .joins
+    .iter()
+    .map(|join| (join.operator.natural, join.operator.kind))
+    .collect();
+  assert_eq!(
+    kinds,
+    [
+      (true, JoinKind::Left),
+      (false, JoinKind::Comma),
+      (false, JoinKind::Cross),
+      (false, JoinKind::Inner),
+      (false, JoinKind::Inner),
+    ]
+  );
+  assert!(matches!(
+    &from.joins[1].table,
+    TableOrSubquery::Subquery { alias: Some(alias), .. } if alias == "s"
+  ));
+  assert!(matches!(
+    &from.joins[2].table,
+    TableOrSubquery::TableFunction { args, .. } if args.len() == 1
+  ));
+  assert_eq!(
+    from.joins[3].constraint,
+    Some(JoinConstraint::Using(vec!["id".into()]))
+  );
+  assert!(matches!(
+    from.joins[4].constraint,
+    Some(JoinConstraint::On(_))
+  ));
+
+  // Keywords that SQLite lets fall back to identifiers.
+  let select = parse_select("SELECT key, replace(a, 'b', 'c') FROM temp.view").unwrap();
+  assert_eq!(
+    select.columns[0],
+    ResultColumn::Expr {
+      expr: column("key"),
+      alias: None,
+      text: "key".into(),
+    }
+  );
+}
+
+#[test]
+fn ok_on_ctes_and_compound_selects() {
+  let select = parse_select(
+    "WITH RECURSIVE t(n) AS MATERIALIZED (VALUES (1) UNION ALL SELECT n + 1 FROM t), \
+     u AS NOT MATERIALIZED (SELECT 2) \
+     SELECT n FROM t UNION SELECT * FROM u EXCEPT VALUES (3), (4) ORDER BY 1 LIMIT 5",
+  )
+  .unwrap();
+  let with = select.with.as_ref().unwrap();
+  assert!(with.recursive);
+  assert_eq!(with.ctes[0].name, "t");
+  assert_eq!(with.ctes[0].columns, ["n"]);
+  assert_eq!(with.ctes[0].materialized, Some(true));
+  assert_eq!(with.ctes[0].select.values, [vec![integer(1)]]);
+  assert_eq!(
+    with.ctes[0].select.compound[0].0,
+    CompoundOperator::UnionAll
+  );
+  assert_eq!(with.ctes[1].materialized, Some(false));
+
+  let operators: Vec<_> = select
+    .compound
+    .iter()
+    .map(|(operator, _)| *operator)
+    .collect();
+  assert_eq!(
+    operators,
+    [CompoundOperator::Union, CompoundOperator::Except]
+  );
+  assert_eq!(
+    select.compound[1].1.values,
+    [vec![integer(3)], vec![integer(4)]]
+  );
+  assert_eq!(select.order_by.len(), 1);
+  assert!(select.limit.is_some());
+
+  // Subqueries may have a WITH clause of their own, and `with` still names
+  // a column.
+  let select = parse_select("SELECT (WITH x AS (SELECT 1) SELECT * FROM x), (with)").unwrap();
+  assert!(matches!(
+    &select.columns[0],
+    ResultColumn::Expr { expr: Expr::Subquery(subquery), .. } if subquery.with.is_some()
+  ));
+  assert!(matches!(
+    &select.columns[1],
+    ResultColumn::Expr { expr, .. } if *expr == column("with")
+  ));
+}
+
+#[test]
+fn ok_on_window_definitions() {
+  let select = parse_select(
+    "SELECT sum(x) OVER (w ORDER BY y ROWS BETWEEN 2 PRECEDING AND CURRENT ROW EXCLUDE TIES), \
+     rank() OVER w FROM t WINDOW w AS (PARTITION BY g), v AS (w GROUPS 1 PRECEDING)",
+  )
+  .unwrap();
+  let over = |index: usize| match &select.columns[index] {
+    ResultColumn::Expr {
+      expr: Expr::Function { over, .. },
+      ..
+    } => over.clone(),
+    _ => None,
+  };
+  assert_eq!(
+    over(0),
+    Some(Over::Window(Box::new(Window {
+      base: Some("w".into()),
+      partition_by: vec![],
+      order_by: vec![OrderingTerm {
+        expr: column("y"),
+        order: None,
+        nulls: None,
+      }],
+      frame: Some(Frame {
+        units: FrameUnits::Rows,
+        start: FrameBound::Preceding(integer(2)),
+        end: FrameBound::CurrentRow,
+        exclude: FrameExclude::Ties,
+      }),
+    })))
+  );
+  assert_eq!(over(1), Some(Over::Name("w".into())));
+  assert_eq!(select.windows[0].0, "w");
+  assert_eq!(select.windows[0].1.partition_by, [column("g")]);
+  // A frame with only a start ends at the current row.
+  assert_eq!(
+    select.windows[1].1.frame,
+    Some(Frame {
+      units: FrameUnits::Groups,
+      start: FrameBound::Preceding(integer(1)),
+      end: FrameBound::CurrentRow,
+      exclude: FrameExclude::NoOthers,
+    })
+  );
+}
+
+#[test]
+fn ok_on_explain_query_plan() {
+  let select = parse_select("SELECT a FROM t").unwrap();
+  assert_eq!(
+    parse_statement("EXPLAIN QUERY PLAN SELECT a FROM t;").unwrap(),
+    Statement::ExplainQueryPlan(Box::new(select))
+  );
+  assert!(error_message("EXPLAIN QUERY PLAN SELECT 1").ends_with("expected a SELECT statement"));
+}
+
+#[test]
+fn ok_on_explain() {
+  let select = parse_select("SELECT a FROM t").unwrap();
+  assert_eq!(
+    parse_statement("EXPLAIN SELECT a FROM t").unwrap(),
+    Statement::Explain(Box::new(select))
+  );
+  assert!(matches!(
+    parse_statement("EXPLAIN 1"),
+    Err(SqliteError::SqlParsing(err)) if err.to_string().ends_with("expected a SELECT statement")
+  ));
+}
+
+#[test]
+fn ok_on_parameter_numbers() {
+  let mut parser = Parser::new("SELECT ?, :a, ?5, :a, ?, $b").unwrap();
+  let Statement::Select(select) = parser.parse_statement().unwrap() else {
+    panic!("Expected a select");
+  };
+  let names: Vec<_> = select
+    .columns
+    .iter()
+    .map(|column| match column {
+      ResultColumn::Expr {
+        expr: Expr::Variable(name),
+        ..
+      } => name.as_str(),
+      _ => panic!("Expected a variable"),
+    })
+    .collect();
+  assert_eq!(names, ["?1", ":a", "?5", ":a", "?6", "$b"]);
+  assert_eq!(
+    parser.parameters(),
+    [
+      None,
+      Some(":a".into()),
+      None,
+      None,
+      Some("?5".into()),
+      None,
+      Some("$b".into())
+    ]
+  );
+  assert!(error_message("SELECT ?0").starts_with("variable number must be between ?1 and ?32766"));
+}
+
+#[test]
+fn ok_on_pragmas() {
+  for (sql, schema, name, value) in [
+    ("PRAGMA user_version", None, "user_version", None),
+    (
+      "PRAGMA main.page_size = 4096",
+      Some("main"),
+      "page_size",
+      Some("4096"),
+    ),
+    (
+      "PRAGMA application_id = -2",
+      None,
+      "application_id",
+      Some("-2"),
+    ),
+    (
+      "PRAGMA journal_mode = DELETE",
+      None,
+      "journal_mode",
+      Some("DELETE"),
+    ),
+    (
+      "PRAGMA table_info('item')",
+      None,
+      "table_info",
+      Some("item"),
+    ),
+  ] {
+    let Statement::Pragma(pragma) = parse_statement(sql).unwrap() else {
+      panic!("Expected a pragma");
+    };
+    assert_eq!(pragma.name.schema.as_deref(), schema);
+    assert_eq!(pragma.name.name, name);
+    assert_eq!(pragma.value.as_deref(), value);
+  }
+  assert!(parse_statement("PRAGMA page_size = -x").is_err());
+}
+
+#[test]
+fn err_on_syntax_errors() {
+  assert_eq!(
+    error_message("SELECT 1 +"),
+    "incomplete input at line 1, column 11, expected an expression"
+  );
+  assert_eq!(
+    error_message("SELECT a FROM t WHERE"),
+    "incomplete input at line 1, column 22, expected an expression"
+  );
+  assert_eq!(
+    error_message("SELECT a b c"),
+    "near \"c\": syntax error at line 1, column 12, expected the end of the statement"
+  );
+  assert_eq!(
+    error_message("SELECT (1\nFROM t"),
+    "near \"FROM\": syntax error at line 2, column 1, expected \")\""
+  );
+  assert!(error_message("SELECT CASE x END").contains("near \"END\": syntax error"));
+  assert!(error_message("VALUES (1, 2), (3)")
+    .starts_with("all VALUES must have the same number of terms"));
+  assert!(error_message("SELECT 1 ORDER BY 1 UNION ALL SELECT 2")
+    .starts_with("ORDER BY clause should come after UNION ALL not before"));
+}
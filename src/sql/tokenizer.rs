@@ -0,0 +1,463 @@
+//! # Tokenizer
+//!
+//!  When a string containing SQL statements is to be executed, the interface
+//! passes that string to the tokenizer. The job of the tokenizer is to break
+//! the original string up into tokens and pass those tokens one by one to the
+//! parser.
+//!
+//!  Whitespace and comments are skipped. Every token keeps the byte span of
+//! the text it was read from, so errors can point at the offending input.
+//!
+//! *Reference:* https://www.sqlite.org/arch.html#tokenizer
+
+#[cfg(test)]
+mod tests;
+
+use core::fmt::Display;
+
+use super::keyword::Keyword;
+use crate::result::{SqlParsingError, SqliteResult};
+
+/// A byte range of the SQL input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Self {
+    Self { start, end }
+  }
+
+  /// The smallest span covering both spans.
+  pub fn to(self, other: Span) -> Span {
+    Span::new(self.start.min(other.start), self.end.max(other.end))
+  }
+
+  pub fn len(&self) -> usize {
+    self.end - self.start
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.start == self.end
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+  Keyword(Keyword),
+  /// A bare identifier that is not a keyword.
+  Identifier,
+  /// An identifier quoted with `""`, `[]` or ``` `` ```.
+  QuotedIdentifier,
+  /// A `'...'` string literal.
+  String,
+  /// A `X'...'` blob literal.
+  Blob,
+  Integer,
+  Float,
+  /// A parameter: `?`, `?NNN`, `:AAAA`, `@AAAA` or `$AAAA`.
+  Variable,
+  Operator(Operator),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+  LeftParen,
+  RightParen,
+  Comma,
+  Semicolon,
+  Dot,
+  Plus,
+  Minus,
+  Star,
+  Slash,
+  Percent,
+  /// `||`
+  Concat,
+  /// `->`
+  Arrow,
+  /// `->>`
+  LongArrow,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  /// `=` or `==`
+  Eq,
+  /// `!=` or `<>`
+  Ne,
+  /// `<<`
+  ShiftLeft,
+  /// `>>`
+  ShiftRight,
+  /// `&`
+  BitAnd,
+  /// `|`
+  BitOr,
+  /// `~`
+  BitNot,
+}
+
+impl Operator {
+  /// Multi-character operators come first, so the longest match wins.
+  const TABLE: [(&'static str, Self); 26] = [
+    ("->>", Self::LongArrow),
+    ("->", Self::Arrow),
+    ("||", Self::Concat),
+    ("<=", Self::Le),
+    (">=", Self::Ge),
+    ("==", Self::Eq),
+    ("!=", Self::Ne),
+    ("<>", Self::Ne),
+    ("<<", Self::ShiftLeft),
+    (">>", Self::ShiftRight),
+    ("(", Self::LeftParen),
+    (")", Self::RightParen),
+    (",", Self::Comma),
+    (";", Self::Semicolon),
+    (".", Self::Dot),
+    ("+", Self::Plus),
+    ("-", Self::Minus),
+    ("*", Self::Star),
+    ("/", Self::Slash),
+    ("%", Self::Percent),
+    ("<", Self::Lt),
+    (">", Self::Gt),
+    ("=", Self::Eq),
+    ("&", Self::BitAnd),
+    ("|", Self::BitOr),
+    ("~", Self::BitNot),
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    Self::TABLE
+      .iter()
+      .find(|(_, operator)| operator == self)
+      .map(|(text, _)| *text)
+      .unwrap_or_default()
+  }
+}
+
+impl Display for Operator {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+  kind: TokenKind,
+  span: Span,
+  value: String,
+}
+
+impl Token {
+  pub fn kind(&self) -> TokenKind {
+    self.kind
+  }
+
+  pub fn span(&self) -> Span {
+    self.span
+  }
+
+  /// The dequoted name of identifiers, the unescaped content of strings, the
+  /// hex digits of blobs and the source text of every other token.
+  pub fn value(&self) -> &str {
+    &self.value
+  }
+
+  pub fn is_keyword(&self, keyword: Keyword) -> bool {
+    self.kind == TokenKind::Keyword(keyword)
+  }
+
+  pub fn is_operator(&self, operator: Operator) -> bool {
+    self.kind == TokenKind::Operator(operator)
+  }
+
+  /// Whether this is an unquoted keyword or identifier spelled `word`,
+  /// ignoring ASCII case.
+  pub fn is_word(&self, word: &str) -> bool {
+    matches!(self.kind, TokenKind::Keyword(_) | TokenKind::Identifier)
+      && self.value.eq_ignore_ascii_case(word)
+  }
+}
+
+/// Splits `sql` into tokens.
+pub fn tokenize(sql: &str) -> SqliteResult<Vec<Token>> {
+  Tokenizer::new(sql).collect()
+}
+
+/// An iterator over the tokens of an SQL input.
+#[derive(Debug)]
+pub struct Tokenizer<'a> {
+  sql: &'a str,
+  position: usize,
+  is_failed: bool,
+}
+
+impl<'a> Tokenizer<'a> {
+  pub fn new(sql: &'a str) -> Self {
+    Self {
+      sql,
+      position: 0,
+      is_failed: false,
+    }
+  }
+
+  fn bytes(&self) -> &'a [u8] {
+    self.sql.as_bytes()
+  }
+
+  fn peek_byte(&self, n: usize) -> Option<u8> {
+    self.bytes().get(self.position + n).copied()
+  }
+
+  fn unrecognized(&self, start: usize) -> SqlParsingError {
+    let end = self.position.max(start + 1).min(self.sql.len());
+    let text = self.sql.get(start..end).unwrap_or_default();
+    SqlParsingError::new(format!("unrecognized token: \"{text}\""), self.sql, start)
+  }
+
+  /// Skips whitespace and comments.
+  fn skip_trivia(&mut self) {
+    while let Some(byte) = self.peek_byte(0) {
+      match byte {
+        b' ' | b'\t' | b'\n' | b'\r' | b'\x0c' => self.position += 1,
+        b'-' if self.peek_byte(1) == Some(b'-') => {
+          while self.peek_byte(0).is_some_and(|byte| byte != b'\n') {
+            self.position += 1;
+          }
+        }
+        b'/' if self.peek_byte(1) == Some(b'*') => {
+          // An unterminated comment runs to the end of the input.
+          self.position = match self.sql[self.position + 2..].find("*/") {
+            Some(end) => self.position + 2 + end + 2,
+            None => self.sql.len(),
+          };
+        }
+        _ => break,
+      }
+    }
+  }
+
+  fn read_token(&mut self) -> Result<Token, SqlParsingError> {
+    let start = self.position;
+    let byte = self.bytes()[start];
+    let (kind, value) = match byte {
+      b'\'' => (TokenKind::String, self.read_quoted(b'\'')?),
+      b'"' | b'`' => (TokenKind::QuotedIdentifier, self.read_quoted(byte)?),
+      b'[' => (TokenKind::QuotedIdentifier, self.read_quoted(b']')?),
+      b'x' | b'X' if self.peek_byte(1) == Some(b'\'') => (TokenKind::Blob, self.read_blob()?),
+      b'0'..=b'9' => self.read_number()?,
+      b'.' if self.peek_byte(1).is_some_and(|byte| byte.is_ascii_digit()) => self.read_number()?,
+      b'?' => {
+        self.position += 1;
+        while self.peek_byte(0).is_some_and(|byte| byte.is_ascii_digit()) {
+          self.position += 1;
+        }
+        (
+          TokenKind::Variable,
+          self.sql[start..self.position].to_string(),
+        )
+      }
+      b':' | b'@' | b'$' => (TokenKind::Variable, self.read_named_variable(byte)?),
+      byte if is_identifier_start(byte) => {
+        while self.peek_byte(0).is_some_and(is_identifier_char) {
+          self.position += 1;
+        }
+        let word = &self.sql[start..self.position];
+        let kind = match Keyword::from_word(word) {
+          Some(keyword) => TokenKind::Keyword(keyword),
+          None => TokenKind::Identifier,
+        };
+        (kind, word.to_string())
+      }
+      _ => {
+        let rest = &self.sql[start..];
+        let Some((text, operator)) = Operator::TABLE
+          .iter()
+          .find(|(text, _)| rest.starts_with(text))
+        else {
+          self.position += rest.chars().next().map(char::len_utf8).unwrap_or(1);
+          return Err(self.unrecognized(start));
+        };
+        self.position += text.len();
+        (TokenKind::Operator(*operator), text.to_string())
+      }
+    };
+    Ok(Token {
+      kind,
+      span: Span::new(start, self.position),
+      value,
+    })
+  }
+
+  /// Reads text up to the closing quote. A doubled quote character stands
+  /// for itself, except inside `[]`.
+  fn read_quoted(&mut self, close: u8) -> Result<String, SqlParsingError> {
+    let start = self.position;
+    self.position += 1;
+    let mut value = String::new();
+    let mut segment_start = self.position;
+    loop {
+      match self.peek_byte(0) {
+        None => {
+          return Err(SqlParsingError::new(
+            format!("unrecognized token: \"{}\"", &self.sql[start..]),
+            self.sql,
+            start,
+          ))
+        }
+        Some(byte) if byte == close => {
+          value.push_str(&self.sql[segment_start..self.position]);
+          if close != b']' && self.peek_byte(1) == Some(close) {
+            value.push(close as char);
+            self.position += 2;
+            segment_start = self.position;
+          } else {
+            self.position += 1;
+            return Ok(value);
+          }
+        }
+        Some(_) => self.position += 1,
+      }
+    }
+  }
+
+  /// `X'...'` with an even number of hex digits.
+  fn read_blob(&mut self) -> Result<String, SqlParsingError> {
+    let start = self.position;
+    self.position += 2;
+    let digits_start = self.position;
+    while self
+      .peek_byte(0)
+      .is_some_and(|byte| byte.is_ascii_hexdigit())
+    {
+      self.position += 1;
+    }
+    let digits = &self.sql[digits_start..self.position];
+    if self.peek_byte(0) != Some(b'\'') || digits.len() % 2 != 0 {
+      while self.peek_byte(0).is_some_and(|byte| byte != b'\'') {
+        self.position += 1;
+      }
+      if self.peek_byte(0).is_some() {
+        self.position += 1;
+      }
+      return Err(self.unrecognized(start));
+    }
+    self.position += 1;
+    Ok(digits.to_string())
+  }
+
+  /// Decimal and hexadecimal literals. An underscore may separate two digits.
+  fn read_number(&mut self) -> Result<(TokenKind, String), SqlParsingError> {
+    let start = self.position;
+    let mut kind = TokenKind::Integer;
+
+    let is_hex = self.peek_byte(0) == Some(b'0')
+      && matches!(self.peek_byte(1), Some(b'x' | b'X'))
+      && self
+        .peek_byte(2)
+        .is_some_and(|byte| byte.is_ascii_hexdigit());
+    if is_hex {
+      self.position += 2;
+      self.read_digits(u8::is_ascii_hexdigit);
+    } else {
+      self.read_digits(u8::is_ascii_digit);
+      if self.peek_byte(0) == Some(b'.') {
+        kind = TokenKind::Float;
+        self.position += 1;
+        self.read_digits(u8::is_ascii_digit);
+      }
+      if matches!(self.peek_byte(0), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(self.peek_byte(1), Some(b'+' | b'-')));
+        if self
+          .peek_byte(1 + sign)
+          .is_some_and(|byte| byte.is_ascii_digit())
+        {
+          kind = TokenKind::Float;
+          self.position += 1 + sign;
+          self.read_digits(u8::is_ascii_digit);
+        }
+      }
+    }
+
+    // A number running into an identifier, like `12abc` or `1_`, is invalid.
+    if self.peek_byte(0).is_some_and(is_identifier_char) {
+      while self.peek_byte(0).is_some_and(is_identifier_char) {
+        self.position += 1;
+      }
+      return Err(self.unrecognized(start));
+    }
+    Ok((kind, self.sql[start..self.position].to_string()))
+  }
+
+  fn read_digits(&mut self, is_digit: fn(&u8) -> bool) {
+    while let Some(byte) = self.peek_byte(0) {
+      let is_separator = byte == b'_'
+        && self.position > 0
+        && is_digit(&self.bytes()[self.position - 1])
+        && self.peek_byte(1).is_some_and(|next| is_digit(&next));
+      if is_digit(&byte) || is_separator {
+        self.position += 1;
+      } else {
+        break;
+      }
+    }
+  }
+
+  /// `:AAAA`, `@AAAA` and `$AAAA`. TCL-style `$` names may also contain
+  /// `::` separators and end with a `(...)` suffix.
+  fn read_named_variable(&mut self, prefix: u8) -> Result<String, SqlParsingError> {
+    let start = self.position;
+    self.position += 1;
+    loop {
+      match self.peek_byte(0) {
+        Some(byte) if is_identifier_char(byte) => self.position += 1,
+        Some(b':') if prefix == b'$' && self.peek_byte(1) == Some(b':') => self.position += 2,
+        Some(b'(') if prefix == b'$' && self.position > start + 1 => {
+          match self.sql[self.position..].find(')') {
+            Some(end) => self.position += end + 1,
+            None => {
+              self.position = self.sql.len();
+              return Err(self.unrecognized(start));
+            }
+          }
+          break;
+        }
+        _ => break,
+      }
+    }
+    if self.position == start + 1 {
+      return Err(self.unrecognized(start));
+    }
+    Ok(self.sql[start..self.position].to_string())
+  }
+}
+
+impl Iterator for Tokenizer<'_> {
+  type Item = SqliteResult<Token>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.is_failed {
+      return None;
+    }
+    self.skip_trivia();
+    if self.position >= self.sql.len() {
+      return None;
+    }
+    let token = self.read_token();
+    self.is_failed = token.is_err();
+    Some(token.map_err(Into::into))
+  }
+}
+
+fn is_identifier_start(byte: u8) -> bool {
+  byte.is_ascii_alphabetic() || byte == b'_' || byte >= 0x80
+}
+
+fn is_identifier_char(byte: u8) -> bool {
+  is_identifier_start(byte) || byte.is_ascii_digit() || byte == b'$'
+}
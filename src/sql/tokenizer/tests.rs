@@ -0,0 +1,142 @@
+//! Tests for the SQL tokenizer
+//!
+//! To run: `cargo test sql::tokenizer`
+
+use super::{tokenize, Operator, Span, TokenKind};
+use crate::{result::SqliteError, sql::Keyword};
+
+fn kinds_and_values(sql: &str) -> Vec<(TokenKind, String)> {
+  tokenize(sql)
+    .unwrap()
+    .into_iter()
+    .map(|token| (token.kind(), token.value().to_string()))
+    .collect()
+}
+
+fn error_message(sql: &str) -> String {
+  match tokenize(sql) {
+    Err(SqliteError::SqlParsing(err)) => err.to_string(),
+    other => panic!("Expected a parsing error, got {other:?}"),
+  }
+}
+
+#[test]
+fn ok_on_keywords_and_identifiers() {
+  assert_eq!(
+    kinds_and_values(r#"select "a ""b""", [c d], `e``f`, _g$1, rowid FROM ñandú"#),
+    [
+      (TokenKind::Keyword(Keyword::Select), "select".into()),
+      (TokenKind::QuotedIdentifier, r#"a "b""#.into()),
+      (TokenKind::Operator(Operator::Comma), ",".into()),
+      (TokenKind::QuotedIdentifier, "c d".into()),
+      (TokenKind::Operator(Operator::Comma), ",".into()),
+      (TokenKind::QuotedIdentifier, "e`f".into()),
+      (TokenKind::Operator(Operator::Comma), ",".into()),
+      (TokenKind::Identifier, "_g$1".into()),
+      (TokenKind::Operator(Operator::Comma), ",".into()),
+      (TokenKind::Identifier, "rowid".into()),
+      (TokenKind::Keyword(Keyword::From), "FROM".into()),
+      (TokenKind::Identifier, "ñandú".into()),
+    ]
+  );
+  assert_eq!(Keyword::ALL.len(), 147);
+  assert_eq!(
+    Keyword::from_word("current_timestamp"),
+    Some(Keyword::CurrentTimestamp)
+  );
+  assert_eq!(Keyword::from_word("strict"), None);
+}
+
+#[test]
+fn ok_on_literals() {
+  assert_eq!(
+    kinds_and_values("'it''s' x'0aFf' X'' 42 1_000_000 0x1F 0Xdead_beef 3.14 .5 1. 1e10 2.5E-3"),
+    [
+      (TokenKind::String, "it's".into()),
+      (TokenKind::Blob, "0aFf".into()),
+      (TokenKind::Blob, "".into()),
+      (TokenKind::Integer, "42".into()),
+      (TokenKind::Integer, "1_000_000".into()),
+      (TokenKind::Integer, "0x1F".into()),
+      (TokenKind::Integer, "0Xdead_beef".into()),
+      (TokenKind::Float, "3.14".into()),
+      (TokenKind::Float, ".5".into()),
+      (TokenKind::Float, "1.".into()),
+      (TokenKind::Float, "1e10".into()),
+      (TokenKind::Float, "2.5E-3".into()),
+    ]
+  );
+}
+
+#[test]
+fn ok_on_variables() {
+  let tokens = tokenize("? ?12 :name @at $tcl::var(x y) $a").unwrap();
+  let values: Vec<_> = tokens.iter().map(|token| token.value()).collect();
+  assert_eq!(values, ["?", "?12", ":name", "@at", "$tcl::var(x y)", "$a"]);
+  assert!(tokens
+    .iter()
+    .all(|token| token.kind() == TokenKind::Variable));
+}
+
+#[test]
+fn ok_on_operators_and_comments() {
+  let tokens = tokenize(
+    "a->>'$.x' -- comment\n || b->c /* multi\nline */ <= >= == != <> << >> < > = & | ~ + - * / % ; . ( )",
+  )
+  .unwrap();
+  let operators: Vec<_> = tokens
+    .iter()
+    .filter_map(|token| match token.kind() {
+      TokenKind::Operator(operator) => Some(operator),
+      _ => None,
+    })
+    .collect();
+  use Operator::*;
+  assert_eq!(
+    operators,
+    [
+      LongArrow, Concat, Arrow, Le, Ge, Eq, Ne, Ne, ShiftLeft, ShiftRight, Lt, Gt, Eq, BitAnd,
+      BitOr, BitNot, Plus, Minus, Star, Slash, Percent, Semicolon, Dot, LeftParen, RightParen,
+    ]
+  );
+  assert!(tokenize("SELECT 1 /* unterminated").is_ok());
+}
+
+#[test]
+fn ok_on_spans() {
+  let sql = "SELECT\n  \"col\" FROM t";
+  let tokens = tokenize(sql).unwrap();
+  assert_eq!(tokens[1].span(), Span::new(9, 14));
+  assert_eq!(
+    &sql[tokens[1].span().start..tokens[1].span().end],
+    "\"col\""
+  );
+  assert_eq!(
+    tokens[0].span().to(tokens[3].span()),
+    Span::new(0, sql.len())
+  );
+  assert!(tokens[2].is_keyword(Keyword::From));
+  assert!(tokens[2].is_word("from"));
+}
+
+#[test]
+fn err_on_unrecognized_tokens() {
+  assert_eq!(
+    error_message("SELECT 12abc"),
+    "unrecognized token: \"12abc\" at line 1, column 8"
+  );
+  assert_eq!(
+    error_message("SELECT\n  1 ! 2"),
+    "unrecognized token: \"!\" at line 2, column 5"
+  );
+  assert!(tokenize("SELECT 'open").is_err());
+  assert!(tokenize("SELECT [open").is_err());
+  assert!(tokenize("SELECT x'abc'").is_err());
+  assert!(tokenize("SELECT x'zz'").is_err());
+  assert!(tokenize("SELECT 1__0").is_err());
+  assert!(tokenize("SELECT 10_").is_err());
+  assert!(tokenize("SELECT 0x").is_err());
+  assert!(tokenize("SELECT 7e").is_err());
+  assert!(tokenize("SELECT :").is_err());
+  assert!(tokenize("SELECT #").is_err());
+}
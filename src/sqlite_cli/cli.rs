@@ -7,7 +7,7 @@ use self::help::CliHelp;
 use self::traits::ArgName;
 use super::result::SqliteCliError;
 use std::collections::HashMap;
-use std::{env::Args, ops::Not};
+use std::env::Args;
 
 #[derive(Debug, Default)]
 pub(crate) struct Cli {
@@ -9,12 +9,11 @@ use super::{
   cli::Cli,
   result::{SqliteCliError, SqliteCliResult},
 };
-use sqlite_rs::{error, io::SqliteIoMode, runtime::SqliteRuntime, SqliteConnection, IN_MEMORY_URI};
+use sqlite_rs::{io::SqliteIoMode, SqliteConnection, IN_MEMORY_URI};
 
 #[derive(Debug)]
 pub(crate) struct SqliteCliRepl {
   is_tty: bool,
-  cli: Cli,
   conn: SqliteConnection,
 }
 
@@ -40,7 +39,7 @@ impl SqliteCliRepl {
     dbg!(conn.io_mode());
     let is_tty = stdin().is_terminal();
 
-    Ok(Self { cli, is_tty, conn })
+    Ok(Self { is_tty, conn })
   }
   fn run_from_pipe(&mut self) -> SqliteCliResult<()> {
     use std::io;
@@ -54,8 +53,8 @@ impl SqliteCliRepl {
         ".q" | ".quit" => (),
         s => self.internal_command(s)?,
       };
-    } else {
-      self::sql::run(normalized_input)?;
+    } else if let Err(err) = self::sql::run(&mut self.conn, normalized_input) {
+      println!("Error: {err}");
     }
     Ok(())
   }
@@ -94,8 +93,8 @@ impl SqliteCliRepl {
             }
           }
         };
-      } else {
-        self::sql::run(normalized_input)?;
+      } else if let Err(err) = self::sql::run(&mut self.conn, normalized_input) {
+        println!("Error: {err}");
       }
     }
     Ok(())
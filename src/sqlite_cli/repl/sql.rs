@@ -1,8 +1,81 @@
 use crate::sqlite_cli::result::SqliteCliResult;
+use sqlite_rs::{
+  sql::{ast::Statement, parse_statement},
+  value::Value,
+  SqliteConnection,
+};
 
-pub(super) fn run(normalized_input: impl AsRef<str>) -> SqliteCliResult<()> {
-  if normalized_input.as_ref().len() > 0 {
-    println!(r#"SQL queries is not implemented. Enter ".help" for help"#);
+/// Runs a query and prints its rows like the `list` mode of sqlite3: values
+/// separated by `|`, with NULL as an empty string. The plan of `EXPLAIN
+/// QUERY PLAN` is printed as a tree instead, and the program of `EXPLAIN` as
+/// a table.
+pub(super) fn run(
+  conn: &mut SqliteConnection,
+  normalized_input: impl AsRef<str>,
+) -> SqliteCliResult<()> {
+  let sql = normalized_input.as_ref();
+  if sql.is_empty() {
+    return Ok(());
+  }
+  let statement = parse_statement(sql)?;
+  if let Statement::ExplainQueryPlan(_) = statement {
+    println!("{}", conn.query_plan(sql)?);
+    return Ok(());
+  }
+  let result = conn.query(sql)?;
+  if let Statement::Explain(_) = statement {
+    print_program(result.rows());
+    return Ok(());
+  }
+  for row in result.rows() {
+    let line: Vec<String> = row
+      .iter()
+      .map(|value| match value {
+        Value::Null => String::new(),
+        value => value.to_string(),
+      })
+      .collect();
+    println!("{}", line.join("|"));
   }
   Ok(())
 }
+
+/// Prints the instructions of a program like sqlite3 does, with the body of
+/// each loop indented.
+fn print_program(rows: &[Vec<Value>]) {
+  const WIDTHS: [usize; 8] = [4, 13, 4, 4, 4, 13, 2, 13];
+  let header = ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"];
+  let print_line = |cells: &[String]| {
+    let line: Vec<String> = cells
+      .iter()
+      .zip(WIDTHS)
+      .map(|(cell, width)| format!("{cell:width$}"))
+      .collect();
+    println!("{}", line.join("  ").trim_end());
+  };
+  print_line(&header.map(String::from));
+  print_line(&WIDTHS.map(|width| "-".repeat(width)));
+  // A loop goes from the target of its `Next` to the `Next` itself.
+  let mut depths = vec![0; rows.len()];
+  for (address, row) in rows.iter().enumerate() {
+    let is_loop =
+      matches!(&row[1], Value::Text(opcode) if opcode == "Next" || opcode == "SorterNext");
+    if let (true, Value::Integer(target)) = (is_loop, &row[3]) {
+      let target = usize::try_from(*target).unwrap_or(address);
+      for depth in depths.iter_mut().take(address).skip(target) {
+        *depth += 1;
+      }
+    }
+  }
+  for (row, depth) in rows.iter().zip(depths) {
+    let mut cells: Vec<String> = row
+      .iter()
+      .map(|value| match value {
+        Value::Null => String::new(),
+        value => value.to_string(),
+      })
+      .collect();
+    cells[1] = format!("{}{}", "  ".repeat(depth), cells[1]);
+    print_line(&cells);
+  }
+}
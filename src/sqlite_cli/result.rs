@@ -18,7 +18,15 @@ pub(crate) enum SqliteCliError {
 
 impl Display for SqliteCliError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{:?}", self)
+    match self {
+      Self::Infallible(_) => write!(f, "Infallible"),
+      Self::SqliteRsLib(err) => write!(f, "{err}"),
+      Self::Custom(msg) => write!(f, "{msg}"),
+      Self::StdIo(err) => write!(f, "{err}"),
+      Self::InvalidCLiArgs(arg) => write!(f, "Invalid argument: {arg}"),
+      Self::AddrParseError(err) => write!(f, "{err}"),
+      Self::ParseIntError(err) => write!(f, "{err}"),
+    }
   }
 }
 impl From<Infallible> for SqliteCliError {
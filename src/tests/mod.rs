@@ -1,8 +1,4 @@
-use crate::{
-  debug,
-  file_header::{self, MagicHeaderString},
-  io::SqliteIoMode,
-};
+use crate::io::SqliteIoMode;
 
 #[test]
 fn ok_on_get_conn_methods() {
@@ -60,10 +56,10 @@ fn ok_check_database_consistence_size() {
     let file_metadata = conn.file_metadata();
 
     assert_eq!(*conn.io_mode(), SqliteIoMode::File);
-    file_metadata.map(|metadata| {
+    if let Some(metadata) = file_metadata {
       let expected_size = u32::from(page_size) * **pages;
       assert_eq!(metadata.len(), expected_size.into());
-    });
+    }
 
     assert_eq!(*conn.io_mode(), SqliteIoMode::File);
   });
@@ -113,3 +109,36 @@ fn ok_check_database_consistence_size() {
 //   // trace!("{data:?}");
 //   // trace!("{page_kind:?}");
 // }
+
+#[test]
+fn ok_on_reload_schema_when_cookie_changes() {
+  use crate::SqliteConnection;
+  let path = std::env::temp_dir().join(format!(
+    "sqlite-rs-schema-cookie-{}.sqlite3",
+    std::process::id()
+  ));
+  std::fs::copy("./data/small.sqlite3", &path).unwrap();
+
+  let conn = SqliteConnection::open(format!("sqlite://{}", path.display())).unwrap();
+  assert!(!conn.refresh_schema().unwrap());
+  assert!(conn.schema().table("trade").is_none());
+  assert_eq!(conn.sqlite_sequence().get("trade"), None);
+
+  // Another process adds a table to the database.
+  std::fs::write(
+    &path,
+    std::fs::read("./data/small-altered.sqlite3").unwrap(),
+  )
+  .unwrap();
+
+  let mut cursor = conn.table_cursor("trade").unwrap();
+  assert!(cursor.rewind().unwrap());
+  assert!(cursor.advance().unwrap());
+  assert!(!cursor.advance().unwrap());
+  assert_eq!(**conn.file_header().schema_cookie(), 8);
+  assert!(conn.schema().table("trade").is_some());
+  assert_eq!(conn.sqlite_sequence().get("trade"), Some(2));
+  assert!(!conn.refresh_schema().unwrap());
+
+  std::fs::remove_file(&path).unwrap();
+}
@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 use std::io::Seek;
+use std::io::Write;
 
 pub trait Name {
   const NAME: &'static str;
@@ -39,7 +40,6 @@ where
   fn validate_parsed(&self) -> SqliteResult<()>;
 }
 
-// pub(crate) trait SqliteRawIo: Read + Send + Sync + Write {}
-pub(crate) trait SqliteRawIo: Read + Send + Sync + Seek {}
+pub(crate) trait SqliteRawIo: Read + Send + Sync + Seek + Write {}
 impl SqliteRawIo for Cursor<Vec<u8>> {}
 impl SqliteRawIo for File {}
@@ -0,0 +1,508 @@
+//! # Datatypes In SQLite
+//!
+//!  Each value stored in an SQLite database (or manipulated by the database
+//! engine) has one of the following storage classes:
+//!
+//! - **NULL**. The value is a NULL value.
+//! - **INTEGER**. The value is a signed integer, stored in 0, 1, 2, 3, 4, 6,
+//!   or 8 bytes depending on the magnitude of the value.
+//! - **REAL**. The value is a floating point value, stored as an 8-byte IEEE
+//!   floating point number.
+//! - **TEXT**. The value is a text string, stored using the database encoding
+//!   (UTF-8, UTF-16BE or UTF-16LE).
+//! - **BLOB**. The value is a blob of data, stored exactly as it was input.
+//!
+//! *Reference:* https://www.sqlite.org/datatype3.html
+
+#[cfg(test)]
+mod tests;
+
+use core::{cmp::Ordering, fmt::Display};
+
+use crate::{
+  file_header::DatabaseTextEncoding,
+  record::{decode_text, encode_text},
+};
+
+/// A single SQL value, tagged with its storage class.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+  #[default]
+  Null,
+  Integer(i64),
+  Real(f64),
+  Text(String),
+  Blob(Vec<u8>),
+}
+
+impl Value {
+  pub fn is_null(&self) -> bool {
+    matches!(self, Self::Null)
+  }
+
+  /// The storage class name, as returned by the `typeof()` SQL function.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Self::Null => "null",
+      Self::Integer(_) => "integer",
+      Self::Real(_) => "real",
+      Self::Text(_) => "text",
+      Self::Blob(_) => "blob",
+    }
+  }
+
+  pub fn as_integer(&self) -> Option<i64> {
+    match self {
+      Self::Integer(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_real(&self) -> Option<f64> {
+    match self {
+      Self::Real(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_text(&self) -> Option<&str> {
+    match self {
+      Self::Text(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_blob(&self) -> Option<&[u8]> {
+    match self {
+      Self::Blob(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  /// Compares two values in the order SQLite sorts them: NULLs first, then
+  /// INTEGER and REAL values compared numerically, then TEXT, then BLOBs.
+  /// Text is compared with the BINARY collation, byte by byte.
+  ///
+  /// *Reference:* https://www.sqlite.org/datatype3.html#sort_order
+  pub fn compare(&self, other: &Self) -> Ordering {
+    match (self, other) {
+      (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+      (Self::Real(a), Self::Real(b)) => a.total_cmp(b),
+      (Self::Integer(a), Self::Real(b)) => compare_integer_to_real(*a, *b),
+      (Self::Real(a), Self::Integer(b)) => compare_integer_to_real(*b, *a).reverse(),
+      (Self::Text(a), Self::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+      (Self::Blob(a), Self::Blob(b)) => a.cmp(b),
+      (a, b) => a.sort_class().cmp(&b.sort_class()),
+    }
+  }
+
+  /// Converts the value as `CAST(value AS type)` does, for a type with the
+  /// given affinity, in a UTF-8 database.
+  pub fn cast(&self, affinity: Affinity) -> Self {
+    self.cast_in(affinity, DatabaseTextEncoding::Utf8)
+  }
+
+  /// Converts the value as `CAST(value AS type)` does, for a type with the
+  /// given affinity, in a database whose text is in `encoding`.
+  ///
+  ///  Text is read as the longest prefix that looks like a number, so
+  /// `CAST('12abc' AS INTEGER)` is 12 and text without such a prefix is 0.
+  /// Casting to INTEGER only reads digits and saturates on overflow, as does
+  /// a REAL out of range. NUMERIC keeps a REAL unless text reads as one that
+  /// is a small enough whole number. Blobs are read as text in the database
+  /// encoding, and text becomes a blob in it. NULL stays NULL.
+  ///
+  /// *Reference:* https://www.sqlite.org/lang_expr.html#castexpr
+  pub fn cast_in(&self, affinity: Affinity, encoding: DatabaseTextEncoding) -> Self {
+    let text = |value: &Self| match value {
+      Self::Blob(blob) => decode_text(blob, &encoding),
+      value => value.to_string(),
+    };
+    match (affinity, self) {
+      (_, Self::Null) => Self::Null,
+      (Affinity::Text, Self::Text(_)) | (Affinity::Blob, Self::Blob(_)) => self.clone(),
+      (Affinity::Text, value) => Self::Text(text(value)),
+      (Affinity::Blob, value) => Self::Blob(encode_text(&value.to_string(), &encoding)),
+      (Affinity::Integer, Self::Integer(_)) => self.clone(),
+      // `as` saturates, which is what SQLite does too.
+      (Affinity::Integer, Self::Real(real)) => Self::Integer(*real as i64),
+      (Affinity::Integer, value) => Self::Integer(integer_prefix(&text(value))),
+      (Affinity::Real, Self::Integer(integer)) => Self::Real(*integer as f64),
+      (Affinity::Real, Self::Real(_)) => self.clone(),
+      (Affinity::Real, value) => match numeric_prefix(&text(value)).value {
+        Self::Integer(integer) => Self::Real(integer as f64),
+        other => other,
+      },
+      (Affinity::Numeric, Self::Integer(_) | Self::Real(_)) => self.clone(),
+      (Affinity::Numeric, value) => match numeric_prefix(&text(value)).value {
+        // Whole numbers up to 2^51 convert exactly both ways.
+        Self::Real(real)
+          if real.fract() == 0.0
+            && (-2_251_799_813_685_248.0..2_251_799_813_685_248.0).contains(&real) =>
+        {
+          Self::Integer(real as i64)
+        }
+        value => value,
+      },
+    }
+  }
+
+  /// Converts the value to the affinity of a column it is stored in or
+  /// compared with. Unlike CAST, only conversions that lose nothing are
+  /// made: text becomes a number only when all of it is a well-formed
+  /// number.
+  ///
+  /// *Reference:* https://www.sqlite.org/datatype3.html#type_affinity
+  pub fn apply_affinity(self, affinity: Affinity) -> Self {
+    match (affinity, self) {
+      (Affinity::Text, value @ (Self::Integer(_) | Self::Real(_))) => Self::Text(value.to_string()),
+      (Affinity::Integer | Affinity::Numeric, Self::Real(real)) => {
+        Self::Real(real).with_integer_real()
+      }
+      (Affinity::Real, Self::Integer(integer)) => Self::Real(integer as f64),
+      (Affinity::Integer | Affinity::Numeric | Affinity::Real, Self::Text(text)) => {
+        let prefix = numeric_prefix(&text);
+        if !prefix.is_number || !prefix.is_whole {
+          return Self::Text(text);
+        }
+        match (affinity, prefix.value) {
+          (Affinity::Real, Self::Integer(integer)) => Self::Real(integer as f64),
+          (Affinity::Real, value) => value,
+          (_, value) => value.with_integer_real(),
+        }
+      }
+      (_, value) => value,
+    }
+  }
+
+  /// Converts the value to INTEGER or REAL for arithmetic, reading text and
+  /// blobs as their longest numeric prefix. NULL stays NULL.
+  pub fn to_numeric(&self) -> Self {
+    match self {
+      Self::Null | Self::Integer(_) | Self::Real(_) => self.clone(),
+      Self::Text(text) => numeric_prefix(text).value,
+      Self::Blob(blob) => numeric_prefix(&String::from_utf8_lossy(blob)).value,
+    }
+  }
+
+  /// A REAL with no fractional part that fits in an INTEGER becomes one.
+  fn with_integer_real(self) -> Self {
+    match self {
+      Self::Real(real)
+        if real.fract() == 0.0 && real > i64::MIN as f64 && real < i64::MAX as f64 =>
+      {
+        Self::Integer(real as i64)
+      }
+      value => value,
+    }
+  }
+
+  fn sort_class(&self) -> u8 {
+    match self {
+      Self::Null => 0,
+      Self::Integer(_) | Self::Real(_) => 1,
+      Self::Text(_) => 2,
+      Self::Blob(_) => 3,
+    }
+  }
+}
+
+/// Compares without the precision loss of converting large integers to
+/// floating point.
+fn compare_integer_to_real(integer: i64, real: f64) -> Ordering {
+  const I64_BOUND: f64 = 9_223_372_036_854_775_808.0;
+  if real.is_nan() {
+    return Ordering::Greater;
+  }
+  if real < -I64_BOUND {
+    return Ordering::Greater;
+  }
+  if real >= I64_BOUND {
+    return Ordering::Less;
+  }
+  let truncated = real.trunc();
+  match integer.cmp(&(truncated as i64)) {
+    Ordering::Equal => 0.0
+      .partial_cmp(&(real - truncated))
+      .unwrap_or(Ordering::Equal),
+    ordering => ordering,
+  }
+}
+
+/// The longest prefix of a text that reads as a number.
+struct NumericPrefix {
+  /// INTEGER, or REAL if the prefix has a fraction or an exponent or does
+  /// not fit in an INTEGER. 0 when there is no prefix.
+  value: Value,
+  /// Whether there were any digits.
+  is_number: bool,
+  /// Whether nothing but whitespace follows the prefix.
+  is_whole: bool,
+}
+
+fn is_space(byte: u8) -> bool {
+  matches!(byte, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+fn numeric_prefix(text: &str) -> NumericPrefix {
+  let bytes = text.as_bytes();
+  let count_digits = |from: usize| {
+    bytes[from..]
+      .iter()
+      .take_while(|byte| byte.is_ascii_digit())
+      .count()
+  };
+
+  let start = bytes.iter().take_while(|byte| is_space(**byte)).count();
+  let mut end = start;
+  if matches!(bytes.get(end), Some(b'+' | b'-')) {
+    end += 1;
+  }
+  let integer_digits = count_digits(end);
+  end += integer_digits;
+  let mut is_real = false;
+  if bytes.get(end) == Some(&b'.') {
+    let fraction_digits = count_digits(end + 1);
+    if integer_digits > 0 || fraction_digits > 0 {
+      end += 1 + fraction_digits;
+      is_real = true;
+    }
+  }
+  if integer_digits == 0 && !is_real {
+    return NumericPrefix {
+      value: Value::Integer(0),
+      is_number: false,
+      is_whole: false,
+    };
+  }
+  if matches!(bytes.get(end), Some(b'e' | b'E')) {
+    let mut exponent = end + 1;
+    if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+      exponent += 1;
+    }
+    let exponent_digits = count_digits(exponent);
+    if exponent_digits > 0 {
+      end = exponent + exponent_digits;
+      is_real = true;
+    }
+  }
+
+  let number = &text[start..end];
+  let as_real = || Value::Real(number.parse().unwrap_or_default());
+  let value = if is_real {
+    as_real()
+  } else {
+    number
+      .parse()
+      .map(Value::Integer)
+      .unwrap_or_else(|_| as_real())
+  };
+  NumericPrefix {
+    value,
+    is_number: true,
+    is_whole: bytes[end..].iter().all(|byte| is_space(*byte)),
+  }
+}
+
+/// Reads a text that is a number and nothing else but whitespace around it.
+pub(crate) fn parse_number(text: &str) -> Option<f64> {
+  let prefix = numeric_prefix(text);
+  match prefix.value {
+    _ if !prefix.is_number || !prefix.is_whole => None,
+    Value::Integer(integer) => Some(integer as f64),
+    Value::Real(real) => Some(real),
+    _ => None,
+  }
+}
+
+/// Reads an optional sign and digits after any whitespace, saturating at the
+/// bounds of an INTEGER.
+fn integer_prefix(text: &str) -> i64 {
+  let mut bytes = text.bytes().skip_while(|byte| is_space(*byte)).peekable();
+  let is_negative = match bytes.peek() {
+    Some(b'-') => {
+      bytes.next();
+      true
+    }
+    Some(b'+') => {
+      bytes.next();
+      false
+    }
+    _ => false,
+  };
+  let mut value: i64 = 0;
+  for digit in bytes.take_while(u8::is_ascii_digit) {
+    let digit = i64::from(digit - b'0');
+    value = match value.checked_mul(10).and_then(|value| match is_negative {
+      true => value.checked_sub(digit),
+      false => value.checked_add(digit),
+    }) {
+      Some(value) => value,
+      None if is_negative => return i64::MIN,
+      None => return i64::MAX,
+    };
+  }
+  value
+}
+
+/// Renders REAL values the way SQLite converts them to text: like the C
+/// format `%!.15g`, with 15 significant digits and always a decimal point.
+pub fn format_real(value: f64) -> String {
+  if value.is_infinite() {
+    return if value > 0.0 { "Inf" } else { "-Inf" }.into();
+  }
+  if value == 0.0 {
+    return "0.0".into();
+  }
+
+  let scientific = format!("{value:.14e}");
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+  let exponent: i32 = exponent.parse().unwrap_or_default();
+  if !(-4..15).contains(&exponent) {
+    let mantissa = trim_fraction(mantissa.to_string());
+    let sign = if exponent < 0 { '-' } else { '+' };
+    return format!("{mantissa}e{sign}{:02}", exponent.abs());
+  }
+  let precision = (14 - exponent) as usize;
+  trim_fraction(format!("{value:.precision$}"))
+}
+
+/// Drops trailing zeros of a fraction, keeping at least one digit after the
+/// decimal point.
+fn trim_fraction(mut number: String) -> String {
+  if !number.contains('.') {
+    number.push_str(".0");
+    return number;
+  }
+  while number.ends_with('0') {
+    number.pop();
+  }
+  if number.ends_with('.') {
+    number.push('0');
+  }
+  number
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      Self::Null => write!(f, "NULL"),
+      Self::Integer(value) => write!(f, "{value}"),
+      Self::Real(value) => write!(f, "{}", format_real(*value)),
+      Self::Text(value) => write!(f, "{value}"),
+      Self::Blob(value) => write!(f, "{}", String::from_utf8_lossy(value)),
+    }
+  }
+}
+
+impl From<i64> for Value {
+  fn from(value: i64) -> Self {
+    Self::Integer(value)
+  }
+}
+
+impl From<f64> for Value {
+  fn from(value: f64) -> Self {
+    Self::Real(value)
+  }
+}
+
+impl From<&str> for Value {
+  fn from(value: &str) -> Self {
+    Self::Text(value.into())
+  }
+}
+
+impl From<String> for Value {
+  fn from(value: String) -> Self {
+    Self::Text(value)
+  }
+}
+
+impl From<Vec<u8>> for Value {
+  fn from(value: Vec<u8>) -> Self {
+    Self::Blob(value)
+  }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+  fn from(value: Option<T>) -> Self {
+    value.map(Into::into).unwrap_or(Self::Null)
+  }
+}
+
+/// # Type Affinity
+///
+///  Each column in an SQLite 3 database is assigned one of the following type
+/// affinities: TEXT, NUMERIC, INTEGER, REAL and BLOB.
+///
+///  The affinity of a column is determined by the declared type of the column,
+/// according to the following rules in the order shown:
+///
+/// 1. If the declared type contains the string "INT" then it is assigned
+///    INTEGER affinity.
+/// 2. If the declared type of the column contains any of the strings "CHAR",
+///    "CLOB", or "TEXT" then that column has TEXT affinity. Notice that the
+///    type VARCHAR contains the string "CHAR" and is thus assigned TEXT
+///    affinity.
+/// 3. If the declared type for a column contains the string "BLOB" or if no
+///    type is specified then the column has affinity BLOB.
+/// 4. If the declared type for a column contains any of the strings "REAL",
+///    "FLOA", or "DOUB" then the column has REAL affinity.
+/// 5. Otherwise, the affinity is NUMERIC.
+///
+///  Note that the order of the rules for determining column affinity is
+/// important. A column whose declared type is "CHARINT" will match both rules
+/// 1 and 2 but the first rule takes precedence and so the column affinity will
+/// be INTEGER.
+///
+/// *Reference:* https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Affinity {
+  Integer,
+  Text,
+  #[default]
+  Blob,
+  Real,
+  Numeric,
+}
+
+impl Affinity {
+  /// Applies SQLite's substring rules to a declared column type.
+  pub fn from_declared_type(declared_type: Option<&str>) -> Self {
+    let declared_type = match declared_type {
+      Some(declared_type) => declared_type.to_ascii_uppercase(),
+      None => return Self::Blob,
+    };
+    if declared_type.contains("INT") {
+      Self::Integer
+    } else if ["CHAR", "CLOB", "TEXT"]
+      .iter()
+      .any(|s| declared_type.contains(s))
+    {
+      Self::Text
+    } else if declared_type.contains("BLOB") || declared_type.trim().is_empty() {
+      Self::Blob
+    } else if ["REAL", "FLOA", "DOUB"]
+      .iter()
+      .any(|s| declared_type.contains(s))
+    {
+      Self::Real
+    } else {
+      Self::Numeric
+    }
+  }
+}
+
+impl Display for Affinity {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let name = match self {
+      Self::Integer => "INTEGER",
+      Self::Text => "TEXT",
+      Self::Blob => "BLOB",
+      Self::Real => "REAL",
+      Self::Numeric => "NUMERIC",
+    };
+    write!(f, "{name}")
+  }
+}
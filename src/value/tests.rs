@@ -0,0 +1,1049 @@
+//! Tests for values and their conversions
+//!
+//! To run: `cargo test value`
+
+use crate::file_header::DatabaseTextEncoding;
+
+use super::{Affinity, Value};
+
+/// The affinities of the expected values of each case, in order.
+const AFFINITIES: [Affinity; 5] = [
+  Affinity::Integer,
+  Affinity::Real,
+  Affinity::Numeric,
+  Affinity::Text,
+  Affinity::Blob,
+];
+
+/// Whether two values are the same, telling INTEGER from REAL.
+fn assert_same(actual: &Value, expected: &Value, case: &str) {
+  assert_eq!(actual.type_name(), expected.type_name(), "{case}");
+  assert_eq!(actual, expected, "{case}");
+}
+
+/// `CAST(value AS type)` for INTEGER, REAL, NUMERIC, TEXT and BLOB, as
+/// `quote()` reports it in SQLite 3.51.
+#[test]
+fn ok_on_cast_golden_table() {
+  for (value, expected) in [
+    (
+      Value::from("0x10"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("0x10"),
+        Value::from(b"0x10".to_vec()),
+      ],
+    ),
+    (
+      Value::from(" 12abc"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from(" 12abc"),
+        Value::from(b" 12abc".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1e400"),
+      [
+        Value::from(1),
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from("1e400"),
+        Value::from(b"1e400".to_vec()),
+      ],
+    ),
+    (
+      Value::from("-0.0"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("-0.0"),
+        Value::from(b"-0.0".to_vec()),
+      ],
+    ),
+    (
+      Value::from("12"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12"),
+        Value::from(b"12".to_vec()),
+      ],
+    ),
+    (
+      Value::from(" 12 "),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from(" 12 "),
+        Value::from(b" 12 ".to_vec()),
+      ],
+    ),
+    (
+      Value::from("\t12\n"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("\t12\n"),
+        Value::from(b"\t12\n".to_vec()),
+      ],
+    ),
+    (
+      Value::from("12.0"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12.0"),
+        Value::from(b"12.0".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1.5e3"),
+      [
+        Value::from(1),
+        Value::from(1500.0),
+        Value::from(1500),
+        Value::from("1.5e3"),
+        Value::from(b"1.5e3".to_vec()),
+      ],
+    ),
+    (
+      Value::from("4.0e0"),
+      [
+        Value::from(4),
+        Value::from(4.0),
+        Value::from(4),
+        Value::from("4.0e0"),
+        Value::from(b"4.0e0".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1.5"),
+      [
+        Value::from(1),
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from("1.5"),
+        Value::from(b"1.5".to_vec()),
+      ],
+    ),
+    (
+      Value::from("-1.5"),
+      [
+        Value::from(-1),
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from("-1.5"),
+        Value::from(b"-1.5".to_vec()),
+      ],
+    ),
+    (
+      Value::from("12.5abc"),
+      [
+        Value::from(12),
+        Value::from(12.5),
+        Value::from(12.5),
+        Value::from("12.5abc"),
+        Value::from(b"12.5abc".to_vec()),
+      ],
+    ),
+    (
+      Value::from(".5"),
+      [
+        Value::from(0),
+        Value::from(0.5),
+        Value::from(0.5),
+        Value::from(".5"),
+        Value::from(b".5".to_vec()),
+      ],
+    ),
+    (
+      Value::from("5."),
+      [
+        Value::from(5),
+        Value::from(5.0),
+        Value::from(5),
+        Value::from("5."),
+        Value::from(b"5.".to_vec()),
+      ],
+    ),
+    (
+      Value::from("+7"),
+      [
+        Value::from(7),
+        Value::from(7.0),
+        Value::from(7),
+        Value::from("+7"),
+        Value::from(b"+7".to_vec()),
+      ],
+    ),
+    (
+      Value::from("00012"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("00012"),
+        Value::from(b"00012".to_vec()),
+      ],
+    ),
+    (
+      Value::from("-"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("-"),
+        Value::from(b"-".to_vec()),
+      ],
+    ),
+    (
+      Value::from("- 5"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("- 5"),
+        Value::from(b"- 5".to_vec()),
+      ],
+    ),
+    (
+      Value::from(""),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from(""),
+        Value::from(vec![]),
+      ],
+    ),
+    (
+      Value::from("abc"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("abc"),
+        Value::from(b"abc".to_vec()),
+      ],
+    ),
+    (
+      Value::from("Infinity"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("Infinity"),
+        Value::from(b"Infinity".to_vec()),
+      ],
+    ),
+    (
+      Value::from("12e"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12e"),
+        Value::from(b"12e".to_vec()),
+      ],
+    ),
+    (
+      Value::from("12e+"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12e+"),
+        Value::from(b"12e+".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1,5"),
+      [
+        Value::from(1),
+        Value::from(1.0),
+        Value::from(1),
+        Value::from("1,5"),
+        Value::from(b"1,5".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1e-400"),
+      [
+        Value::from(1),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("1e-400"),
+        Value::from(b"1e-400".to_vec()),
+      ],
+    ),
+    (
+      Value::from("1e18"),
+      [
+        Value::from(1),
+        Value::from(1e+18),
+        Value::from(1e+18),
+        Value::from("1e18"),
+        Value::from(b"1e18".to_vec()),
+      ],
+    ),
+    (
+      Value::from("9223372036854775807"),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(i64::MAX),
+        Value::from("9223372036854775807"),
+        Value::from(b"9223372036854775807".to_vec()),
+      ],
+    ),
+    (
+      Value::from("9223372036854775808"),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9223372036854775808"),
+        Value::from(b"9223372036854775808".to_vec()),
+      ],
+    ),
+    (
+      Value::from("-9223372036854775808"),
+      [
+        Value::from(i64::MIN),
+        Value::from(-9.223372036854776e+18),
+        Value::from(i64::MIN),
+        Value::from("-9223372036854775808"),
+        Value::from(b"-9223372036854775808".to_vec()),
+      ],
+    ),
+    (
+      Value::from("-9223372036854775809"),
+      [
+        Value::from(i64::MIN),
+        Value::from(-9.223372036854776e+18),
+        Value::from(-9.223372036854776e+18),
+        Value::from("-9223372036854775809"),
+        Value::from(b"-9223372036854775809".to_vec()),
+      ],
+    ),
+    (
+      Value::from("123456789012345678901234"),
+      [
+        Value::from(i64::MAX),
+        Value::from(1.2345678901234569e+23),
+        Value::from(1.2345678901234569e+23),
+        Value::from("123456789012345678901234"),
+        Value::from(b"123456789012345678901234".to_vec()),
+      ],
+    ),
+    (
+      Value::from("9223372036854775807.5"),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9223372036854775807.5"),
+        Value::from(b"9223372036854775807.5".to_vec()),
+      ],
+    ),
+    (
+      Value::from(12.0),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12.0),
+        Value::from("12.0"),
+        Value::from(b"12.0".to_vec()),
+      ],
+    ),
+    (
+      Value::from(1.5),
+      [
+        Value::from(1),
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from("1.5"),
+        Value::from(b"1.5".to_vec()),
+      ],
+    ),
+    (
+      Value::from(-1.5),
+      [
+        Value::from(-1),
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from("-1.5"),
+        Value::from(b"-1.5".to_vec()),
+      ],
+    ),
+    (
+      Value::from(-0.0),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0.0),
+        Value::from("0.0"),
+        Value::from(b"0.0".to_vec()),
+      ],
+    ),
+    (
+      Value::from(1e19),
+      [
+        Value::from(i64::MAX),
+        Value::from(1e+19),
+        Value::from(1e+19),
+        Value::from("1.0e+19"),
+        Value::from(b"1.0e+19".to_vec()),
+      ],
+    ),
+    (
+      Value::from(-1e19),
+      [
+        Value::from(i64::MIN),
+        Value::from(-1e+19),
+        Value::from(-1e+19),
+        Value::from("-1.0e+19"),
+        Value::from(b"-1.0e+19".to_vec()),
+      ],
+    ),
+    (
+      Value::from(9223372036854775807.0),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9.22337203685478e+18"),
+        Value::from(b"9.22337203685478e+18".to_vec()),
+      ],
+    ),
+    (
+      Value::from(f64::INFINITY),
+      [
+        Value::from(i64::MAX),
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from("Inf"),
+        Value::from(b"Inf".to_vec()),
+      ],
+    ),
+    (
+      Value::from(f64::NEG_INFINITY),
+      [
+        Value::from(i64::MIN),
+        Value::from(f64::NEG_INFINITY),
+        Value::from(f64::NEG_INFINITY),
+        Value::from("-Inf"),
+        Value::from(b"-Inf".to_vec()),
+      ],
+    ),
+    (
+      Value::from(12),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12"),
+        Value::from(b"12".to_vec()),
+      ],
+    ),
+    (
+      Value::from(i64::MAX),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(i64::MAX),
+        Value::from("9223372036854775807"),
+        Value::from(b"9223372036854775807".to_vec()),
+      ],
+    ),
+    (
+      Value::from(i64::MIN),
+      [
+        Value::from(i64::MIN),
+        Value::from(-9.223372036854776e+18),
+        Value::from(i64::MIN),
+        Value::from("-9223372036854775808"),
+        Value::from(b"-9223372036854775808".to_vec()),
+      ],
+    ),
+    (
+      Value::from(b"12".to_vec()),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12"),
+        Value::from(b"12".to_vec()),
+      ],
+    ),
+    (
+      Value::from(b"1.5e".to_vec()),
+      [
+        Value::from(1),
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from("1.5e"),
+        Value::from(b"1.5e".to_vec()),
+      ],
+    ),
+    (
+      Value::from(vec![]),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from(""),
+        Value::from(vec![]),
+      ],
+    ),
+    (
+      Value::Null,
+      [
+        Value::Null,
+        Value::Null,
+        Value::Null,
+        Value::Null,
+        Value::Null,
+      ],
+    ),
+  ] {
+    for (affinity, expected) in AFFINITIES.into_iter().zip(expected) {
+      let case = format!("CAST({value:?} AS {affinity:?})");
+      assert_same(&value.cast(affinity), &expected, &case);
+    }
+  }
+}
+
+/// The value a column of each affinity stores, as `quote()` reports it in
+/// SQLite 3.51 after inserting it in a table with a column of each.
+#[test]
+fn ok_on_affinity_golden_table() {
+  for (value, expected) in [
+    (
+      Value::from("0x10"),
+      [
+        Value::from("0x10"),
+        Value::from("0x10"),
+        Value::from("0x10"),
+        Value::from("0x10"),
+        Value::from("0x10"),
+      ],
+    ),
+    (
+      Value::from(" 12abc"),
+      [
+        Value::from(" 12abc"),
+        Value::from(" 12abc"),
+        Value::from(" 12abc"),
+        Value::from(" 12abc"),
+        Value::from(" 12abc"),
+      ],
+    ),
+    (
+      Value::from("1e400"),
+      [
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from("1e400"),
+        Value::from("1e400"),
+      ],
+    ),
+    (
+      Value::from("-0.0"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("-0.0"),
+        Value::from("-0.0"),
+      ],
+    ),
+    (
+      Value::from("12"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12"),
+        Value::from("12"),
+      ],
+    ),
+    (
+      Value::from(" 12 "),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from(" 12 "),
+        Value::from(" 12 "),
+      ],
+    ),
+    (
+      Value::from("\t12\n"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("\t12\n"),
+        Value::from("\t12\n"),
+      ],
+    ),
+    (
+      Value::from("12.0"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12.0"),
+        Value::from("12.0"),
+      ],
+    ),
+    (
+      Value::from("1.5e3"),
+      [
+        Value::from(1500),
+        Value::from(1500.0),
+        Value::from(1500),
+        Value::from("1.5e3"),
+        Value::from("1.5e3"),
+      ],
+    ),
+    (
+      Value::from("4.0e0"),
+      [
+        Value::from(4),
+        Value::from(4.0),
+        Value::from(4),
+        Value::from("4.0e0"),
+        Value::from("4.0e0"),
+      ],
+    ),
+    (
+      Value::from("1.5"),
+      [
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from("1.5"),
+        Value::from("1.5"),
+      ],
+    ),
+    (
+      Value::from("-1.5"),
+      [
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from("-1.5"),
+        Value::from("-1.5"),
+      ],
+    ),
+    (
+      Value::from("12.5abc"),
+      [
+        Value::from("12.5abc"),
+        Value::from("12.5abc"),
+        Value::from("12.5abc"),
+        Value::from("12.5abc"),
+        Value::from("12.5abc"),
+      ],
+    ),
+    (
+      Value::from(".5"),
+      [
+        Value::from(0.5),
+        Value::from(0.5),
+        Value::from(0.5),
+        Value::from(".5"),
+        Value::from(".5"),
+      ],
+    ),
+    (
+      Value::from("5."),
+      [
+        Value::from(5),
+        Value::from(5.0),
+        Value::from(5),
+        Value::from("5."),
+        Value::from("5."),
+      ],
+    ),
+    (
+      Value::from("+7"),
+      [
+        Value::from(7),
+        Value::from(7.0),
+        Value::from(7),
+        Value::from("+7"),
+        Value::from("+7"),
+      ],
+    ),
+    (
+      Value::from("00012"),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("00012"),
+        Value::from("00012"),
+      ],
+    ),
+    (
+      Value::from("-"),
+      [
+        Value::from("-"),
+        Value::from("-"),
+        Value::from("-"),
+        Value::from("-"),
+        Value::from("-"),
+      ],
+    ),
+    (
+      Value::from("- 5"),
+      [
+        Value::from("- 5"),
+        Value::from("- 5"),
+        Value::from("- 5"),
+        Value::from("- 5"),
+        Value::from("- 5"),
+      ],
+    ),
+    (
+      Value::from(""),
+      [
+        Value::from(""),
+        Value::from(""),
+        Value::from(""),
+        Value::from(""),
+        Value::from(""),
+      ],
+    ),
+    (
+      Value::from("abc"),
+      [
+        Value::from("abc"),
+        Value::from("abc"),
+        Value::from("abc"),
+        Value::from("abc"),
+        Value::from("abc"),
+      ],
+    ),
+    (
+      Value::from("Infinity"),
+      [
+        Value::from("Infinity"),
+        Value::from("Infinity"),
+        Value::from("Infinity"),
+        Value::from("Infinity"),
+        Value::from("Infinity"),
+      ],
+    ),
+    (
+      Value::from("12e"),
+      [
+        Value::from("12e"),
+        Value::from("12e"),
+        Value::from("12e"),
+        Value::from("12e"),
+        Value::from("12e"),
+      ],
+    ),
+    (
+      Value::from("12e+"),
+      [
+        Value::from("12e+"),
+        Value::from("12e+"),
+        Value::from("12e+"),
+        Value::from("12e+"),
+        Value::from("12e+"),
+      ],
+    ),
+    (
+      Value::from("1,5"),
+      [
+        Value::from("1,5"),
+        Value::from("1,5"),
+        Value::from("1,5"),
+        Value::from("1,5"),
+        Value::from("1,5"),
+      ],
+    ),
+    (
+      Value::from("1e-400"),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("1e-400"),
+        Value::from("1e-400"),
+      ],
+    ),
+    (
+      Value::from("1e18"),
+      [
+        Value::from(1000000000000000000),
+        Value::from(1e+18),
+        Value::from(1000000000000000000),
+        Value::from("1e18"),
+        Value::from("1e18"),
+      ],
+    ),
+    (
+      Value::from("9223372036854775807"),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(i64::MAX),
+        Value::from("9223372036854775807"),
+        Value::from("9223372036854775807"),
+      ],
+    ),
+    (
+      Value::from("9223372036854775808"),
+      [
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9223372036854775808"),
+        Value::from("9223372036854775808"),
+      ],
+    ),
+    (
+      Value::from("-9223372036854775808"),
+      [
+        Value::from(i64::MIN),
+        Value::from(-9.223372036854776e+18),
+        Value::from(i64::MIN),
+        Value::from("-9223372036854775808"),
+        Value::from("-9223372036854775808"),
+      ],
+    ),
+    (
+      Value::from("-9223372036854775809"),
+      [
+        Value::from(-9.223372036854776e+18),
+        Value::from(-9.223372036854776e+18),
+        Value::from(-9.223372036854776e+18),
+        Value::from("-9223372036854775809"),
+        Value::from("-9223372036854775809"),
+      ],
+    ),
+    (
+      Value::from("123456789012345678901234"),
+      [
+        Value::from(1.2345678901234569e+23),
+        Value::from(1.2345678901234569e+23),
+        Value::from(1.2345678901234569e+23),
+        Value::from("123456789012345678901234"),
+        Value::from("123456789012345678901234"),
+      ],
+    ),
+    (
+      Value::from("9223372036854775807.5"),
+      [
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9223372036854775807.5"),
+        Value::from("9223372036854775807.5"),
+      ],
+    ),
+    (
+      Value::from(12.0),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12.0"),
+        Value::from(12.0),
+      ],
+    ),
+    (
+      Value::from(1.5),
+      [
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from(1.5),
+        Value::from("1.5"),
+        Value::from(1.5),
+      ],
+    ),
+    (
+      Value::from(-1.5),
+      [
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from(-1.5),
+        Value::from("-1.5"),
+        Value::from(-1.5),
+      ],
+    ),
+    (
+      Value::from(-0.0),
+      [
+        Value::from(0),
+        Value::from(0.0),
+        Value::from(0),
+        Value::from("0.0"),
+        Value::from(0.0),
+      ],
+    ),
+    (
+      Value::from(1e19),
+      [
+        Value::from(1e+19),
+        Value::from(1e+19),
+        Value::from(1e+19),
+        Value::from("1.0e+19"),
+        Value::from(1e+19),
+      ],
+    ),
+    (
+      Value::from(-1e19),
+      [
+        Value::from(-1e+19),
+        Value::from(-1e+19),
+        Value::from(-1e+19),
+        Value::from("-1.0e+19"),
+        Value::from(-1e+19),
+      ],
+    ),
+    (
+      Value::from(9223372036854775807.0),
+      [
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from(9.223372036854776e+18),
+        Value::from("9.22337203685478e+18"),
+        Value::from(9.223372036854776e+18),
+      ],
+    ),
+    (
+      Value::from(f64::INFINITY),
+      [
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from(f64::INFINITY),
+        Value::from("Inf"),
+        Value::from(f64::INFINITY),
+      ],
+    ),
+    (
+      Value::from(f64::NEG_INFINITY),
+      [
+        Value::from(f64::NEG_INFINITY),
+        Value::from(f64::NEG_INFINITY),
+        Value::from(f64::NEG_INFINITY),
+        Value::from("-Inf"),
+        Value::from(f64::NEG_INFINITY),
+      ],
+    ),
+    (
+      Value::from(12),
+      [
+        Value::from(12),
+        Value::from(12.0),
+        Value::from(12),
+        Value::from("12"),
+        Value::from(12),
+      ],
+    ),
+    (
+      Value::from(i64::MAX),
+      [
+        Value::from(i64::MAX),
+        Value::from(9.223372036854776e+18),
+        Value::from(i64::MAX),
+        Value::from("9223372036854775807"),
+        Value::from(i64::MAX),
+      ],
+    ),
+    (
+      Value::from(i64::MIN),
+      [
+        Value::from(i64::MIN),
+        Value::from(-9.223372036854776e+18),
+        Value::from(i64::MIN),
+        Value::from("-9223372036854775808"),
+        Value::from(i64::MIN),
+      ],
+    ),
+    (
+      Value::from(b"12".to_vec()),
+      [
+        Value::from(b"12".to_vec()),
+        Value::from(b"12".to_vec()),
+        Value::from(b"12".to_vec()),
+        Value::from(b"12".to_vec()),
+        Value::from(b"12".to_vec()),
+      ],
+    ),
+    (
+      Value::from(b"1.5e".to_vec()),
+      [
+        Value::from(b"1.5e".to_vec()),
+        Value::from(b"1.5e".to_vec()),
+        Value::from(b"1.5e".to_vec()),
+        Value::from(b"1.5e".to_vec()),
+        Value::from(b"1.5e".to_vec()),
+      ],
+    ),
+    (
+      Value::from(vec![]),
+      [
+        Value::from(vec![]),
+        Value::from(vec![]),
+        Value::from(vec![]),
+        Value::from(vec![]),
+        Value::from(vec![]),
+      ],
+    ),
+    (
+      Value::Null,
+      [
+        Value::Null,
+        Value::Null,
+        Value::Null,
+        Value::Null,
+        Value::Null,
+      ],
+    ),
+  ] {
+    for (affinity, expected) in AFFINITIES.into_iter().zip(expected) {
+      let case = format!("{value:?} in a column of {affinity:?} affinity");
+      assert_same(&value.clone().apply_affinity(affinity), &expected, &case);
+    }
+  }
+}
+
+#[test]
+fn ok_on_cast_in_database_encoding() {
+  let utf16 = Value::from(vec![b'1', 0, b'2', 0, b'x', 0]);
+  for (affinity, expected) in [
+    (Affinity::Text, Value::from("12x")),
+    (Affinity::Integer, Value::from(12)),
+    (Affinity::Numeric, Value::from(12)),
+  ] {
+    assert_same(
+      &utf16.cast_in(affinity, DatabaseTextEncoding::Utf16Le),
+      &expected,
+      &format!("{affinity:?}"),
+    );
+  }
+  assert_same(
+    &Value::from("12").cast_in(Affinity::Blob, DatabaseTextEncoding::Utf16Be),
+    &Value::from(vec![0, b'1', 0, b'2']),
+    "BLOB",
+  );
+}
@@ -0,0 +1,65 @@
+//! # Variable-length integers
+//!
+//!  A variable-length integer or "varint" is a static Huffman encoding of
+//! 64-bit twos-complement integers that uses less space for small positive
+//! values. A varint is between 1 and 9 bytes in length. The varint consists of
+//! either zero or more bytes which have the high-order bit set followed by a
+//! single byte with the high-order bit clear, or nine bytes, whichever is
+//! shorter. The lower seven bits of each of the first eight bytes and all 8
+//! bits of the ninth byte are used to reconstruct the 64-bit twos-complement
+//! integer. Varints are big-endian: bits taken from the earlier byte of the
+//! varint are more significant than bits taken from the later bytes.
+//!
+//! *Reference:* https://www.sqlite.org/fileformat2.html#varint
+
+#[cfg(test)]
+mod tests;
+
+use crate::result::{SqliteError, SqliteResult};
+
+/// The largest number of bytes a varint can take.
+pub(crate) const MAX_VARINT_LENGTH: usize = 9;
+
+/// Decodes the varint at the start of `bytes`, returning its value and the
+/// number of bytes it occupies.
+pub(crate) fn read_varint(bytes: &[u8]) -> SqliteResult<(i64, usize)> {
+  let mut value: u64 = 0;
+  for (idx, byte) in bytes.iter().take(MAX_VARINT_LENGTH).enumerate() {
+    if idx == MAX_VARINT_LENGTH - 1 {
+      value = (value << 8) | u64::from(*byte);
+      return Ok((value as i64, MAX_VARINT_LENGTH));
+    }
+    value = (value << 7) | u64::from(byte & 0x7f);
+    if byte & 0x80 == 0 {
+      return Ok((value as i64, idx + 1));
+    }
+  }
+  Err(SqliteError::Custom("Truncated varint".into()))
+}
+
+/// Encodes `value` as a varint.
+pub(crate) fn write_varint(value: i64) -> Vec<u8> {
+  let value = value as u64;
+  if value & (0xff00_0000_u64 << 32) != 0 {
+    let mut buf = vec![0u8; MAX_VARINT_LENGTH];
+    buf[8] = value as u8;
+    let mut rest = value >> 8;
+    for byte in buf.iter_mut().take(8).rev() {
+      *byte = (rest as u8 & 0x7f) | 0x80;
+      rest >>= 7;
+    }
+    return buf;
+  }
+  let mut buf = Vec::with_capacity(MAX_VARINT_LENGTH);
+  let mut rest = value;
+  loop {
+    buf.push((rest as u8 & 0x7f) | 0x80);
+    rest >>= 7;
+    if rest == 0 {
+      break;
+    }
+  }
+  buf[0] &= 0x7f;
+  buf.reverse();
+  buf
+}
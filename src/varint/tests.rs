@@ -0,0 +1,37 @@
+//! Tests for varint
+//!
+//! To run: `cargo test varint`
+
+use super::{read_varint, write_varint};
+
+#[test]
+fn ok_on_varint_roundtrip() {
+  let samples = [
+    0,
+    1,
+    127,
+    128,
+    240,
+    2287,
+    16383,
+    16384,
+    i64::from(u32::MAX),
+    0x00ff_ffff_ffff_ffff,
+    0x0100_0000_0000_0000,
+    i64::MAX,
+    -1,
+    i64::MIN,
+  ];
+  for value in samples {
+    let bytes = write_varint(value);
+    assert_eq!(read_varint(&bytes).unwrap(), (value, bytes.len()));
+  }
+  assert_eq!(write_varint(127), [0x7f]);
+  assert_eq!(write_varint(128), [0x81, 0x00]);
+  assert_eq!(write_varint(-1).len(), 9);
+}
+
+#[test]
+fn err_on_truncated_varint() {
+  assert!(read_varint(&[0x81, 0x82]).is_err());
+}